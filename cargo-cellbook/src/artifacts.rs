@@ -0,0 +1,101 @@
+//! Files produced by cells, such as images written by `open_image_bytes`.
+//!
+//! Temporary artifacts are deleted when the runner exits instead of
+//! accumulating in the system temp directory.
+
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use parking_lot::Mutex;
+
+use crate::store;
+
+/// A file produced by a cell.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Artifact {
+    pub path: PathBuf,
+    pub mime: String,
+    /// Cell that was running when the artifact was produced.
+    pub cell: Option<String>,
+    /// Whether the file was created by cellbook and is owned by the runner.
+    pub temporary: bool,
+}
+
+static ARTIFACTS: LazyLock<Mutex<Vec<Artifact>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+
+static CURRENT_CELL: LazyLock<Mutex<Option<String>>> = LazyLock::new(|| Mutex::new(None));
+
+/// Set the cell that newly registered artifacts are attributed to,
+/// unless they are registered from a tracked cell run, see [`store::tracked`].
+pub fn set_current_cell(name: Option<&str>) {
+    *CURRENT_CELL.lock() = name.map(str::to_string);
+}
+
+/// Record an artifact, replacing an earlier entry for the same path.
+pub fn register(path: &Path, mime: &str, temporary: bool) {
+    let artifact = Artifact {
+        path: path.to_path_buf(),
+        mime: mime.to_string(),
+        cell: store::current_cell().or_else(|| CURRENT_CELL.lock().clone()),
+        temporary,
+    };
+    let mut artifacts = ARTIFACTS.lock();
+    artifacts.retain(|a| a.path != artifact.path);
+    artifacts.push(artifact);
+}
+
+/// All artifacts in the order they were produced.
+pub fn list() -> Vec<Artifact> {
+    ARTIFACTS.lock().clone()
+}
+
+/// Delete an artifact file and forget it.
+pub fn delete(path: &Path) -> std::io::Result<()> {
+    ARTIFACTS.lock().retain(|a| a.path != path);
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Delete all temporary artifact files and clear the list.
+/// Returns the number of files removed.
+pub fn cleanup() -> usize {
+    let artifacts = std::mem::take(&mut *ARTIFACTS.lock());
+    artifacts
+        .iter()
+        .filter(|a| a.temporary)
+        .filter(|a| std::fs::remove_file(&a.path).is_ok())
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_attributes_current_cell_and_cleans_up() {
+        let dir = std::env::temp_dir().join(format!("cellbook-artifacts-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let temp = dir.join("plot.svg");
+        let user = dir.join("user.png");
+        std::fs::write(&temp, "<svg/>").unwrap();
+        std::fs::write(&user, "png").unwrap();
+
+        set_current_cell(Some("plot"));
+        register(&temp, "image/svg+xml", true);
+        register(&temp, "image/svg+xml", true);
+        set_current_cell(None);
+        register(&user, "image/png", false);
+
+        let artifacts = list();
+        let temp_entry: Vec<_> = artifacts.iter().filter(|a| a.path == temp).collect();
+        assert_eq!(temp_entry.len(), 1);
+        assert_eq!(temp_entry[0].cell.as_deref(), Some("plot"));
+
+        cleanup();
+        assert!(!temp.exists());
+        assert!(user.exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
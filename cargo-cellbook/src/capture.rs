@@ -0,0 +1,148 @@
+//! Stdout capture for running cells.
+//!
+//! Stdout can only be redirected once per process, so cells running at the
+//! same time share one redirect. Each gets what was written while it ran,
+//! which includes the output of the cells that overlapped with it.
+
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use gag::BufferRedirect;
+use parking_lot::Mutex;
+
+/// The active redirect, everything read from it so far and the number of captures using it.
+struct Shared {
+    redirect: BufferRedirect,
+    output: String,
+    captures: usize,
+}
+
+impl Shared {
+    /// Append what was written since the last read.
+    fn read(&mut self) {
+        let _ = std::io::stdout().flush();
+        let _ = self.redirect.read_to_string(&mut self.output);
+    }
+}
+
+static SHARED: Mutex<Option<Shared>> = Mutex::new(None);
+
+/// A capture in progress. Dropping it, also when the cell is aborted,
+/// stops the redirect once no other capture uses it.
+struct Capture {
+    /// Offset in the shared output where this capture started.
+    start: usize,
+}
+
+impl Capture {
+    fn start() -> Option<Self> {
+        let mut shared = SHARED.lock();
+        let shared = match &mut *shared {
+            Some(shared) => shared,
+            None => shared.insert(Shared {
+                redirect: BufferRedirect::stdout().ok()?,
+                output: String::new(),
+                captures: 0,
+            }),
+        };
+        shared.read();
+        shared.captures += 1;
+        Some(Self {
+            start: shared.output.len(),
+        })
+    }
+
+    fn output(&self) -> String {
+        let mut shared = SHARED.lock();
+        let Some(shared) = shared.as_mut() else {
+            return String::new();
+        };
+        shared.read();
+        shared.output.get(self.start..).unwrap_or_default().to_string()
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        let mut shared = SHARED.lock();
+        if let Some(active) = shared.as_mut() {
+            active.captures -= 1;
+            if active.captures == 0 {
+                *shared = None;
+            }
+        }
+    }
+}
+
+/// Capture stdout during execution of an async closure.
+pub async fn capture_stdout<F, Fut, T>(f: F) -> (String, T)
+where
+    F: FnOnce() -> Fut,
+    Fut: std::future::Future<Output = T>,
+{
+    let Some(capture) = Capture::start() else {
+        return (String::new(), f().await);
+    };
+
+    let result = f().await;
+    (capture.output(), result)
+}
+
+/// Capture stdout like [`capture_stdout`], also passing what is written to
+/// `on_output` as the closure runs, read every `interval`.
+pub async fn capture_stdout_live<F, Fut, T>(
+    f: F,
+    interval: Duration,
+    mut on_output: impl FnMut(&str),
+) -> (String, T)
+where
+    F: FnOnce() -> Fut,
+    Fut: std::future::Future<Output = T>,
+{
+    let Some(capture) = Capture::start() else {
+        return (String::new(), f().await);
+    };
+
+    let future = f();
+    tokio::pin!(future);
+    let mut ticker = tokio::time::interval(interval);
+    let mut sent = 0;
+    let result = loop {
+        tokio::select! {
+            result = &mut future => break result,
+            _ = ticker.tick() => {
+                let output = capture.output();
+                on_output(&output[sent..]);
+                sent = output.len();
+            }
+        }
+    };
+    let output = capture.output();
+    on_output(&output[sent..]);
+    (output, result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(text: &str) {
+        // `print!` goes to the test harness, not the redirected file descriptor.
+        let _ = std::io::stdout().write_all(text.as_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_overlapping_captures_share_the_redirect() {
+        let (outer, inner) = capture_stdout(|| async {
+            write("outer start\n");
+            let (inner, ()) = capture_stdout(|| async { write("inner\n") }).await;
+            write("outer end\n");
+            inner
+        })
+        .await;
+
+        assert!(inner.contains("inner\n"));
+        assert!(!inner.contains("outer"));
+        assert!(outer.contains("outer start\ninner\nouter end\n"));
+    }
+}
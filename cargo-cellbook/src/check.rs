@@ -0,0 +1,256 @@
+//! `cargo cellbook check`: type-check the notebook without running it.
+//!
+//! Runs `cargo check` with the same arguments as the watcher's builds and
+//! groups the compiler's diagnostics by the cell function they point into.
+
+use std::fs;
+use std::io::IsTerminal;
+use std::path::Path;
+use std::process::Stdio;
+
+use serde::Deserialize;
+use tokio::process::Command;
+
+use crate::errors::{Error, Result};
+use crate::source::{self, CellSpan};
+use crate::tui::config;
+use crate::{watcher, workspace};
+
+/// Source file of the notebook library.
+const NOTEBOOK: &str = "cellbook.rs";
+
+/// A line of `cargo check --message-format json` output.
+#[derive(Debug, Deserialize)]
+struct CargoMessage {
+    reason: String,
+    message: Option<Diagnostic>,
+}
+
+/// A diagnostic emitted by rustc.
+#[derive(Debug, Deserialize)]
+struct Diagnostic {
+    message: String,
+    level: String,
+    spans: Vec<Span>,
+    rendered: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Span {
+    file_name: String,
+    line_start: u32,
+    is_primary: bool,
+}
+
+impl Diagnostic {
+    fn is_error(&self) -> bool {
+        self.level.starts_with("error")
+    }
+
+    /// Closing counts such as "aborting due to 2 previous errors", which repeat the diagnostics above,
+    /// and notes pointing to `rustc --explain`.
+    fn is_summary(&self) -> bool {
+        self.spans.is_empty()
+            && (self.level == "failure-note"
+                || self.message.starts_with("aborting due to")
+                || self.message.ends_with("warning emitted")
+                || self.message.ends_with("warnings emitted"))
+    }
+
+    /// Line in the notebook source the diagnostic points at. Span paths are
+    /// relative to the workspace `root`, and `notebook` is the canonical path
+    /// of the notebook source, so other crates' `cellbook.rs` don't match.
+    fn notebook_line(&self, notebook: &Path, root: &Path) -> Option<u32> {
+        let span = self.spans.iter().find(|span| span.is_primary)?;
+        let path = root.join(&span.file_name);
+        let path = path.canonicalize().unwrap_or(path);
+        (path == notebook).then_some(span.line_start)
+    }
+}
+
+/// Diagnostics of one cell, or of the code outside cells when `cell` is `None`.
+struct Group<'a> {
+    cell: Option<&'a CellSpan>,
+    diagnostics: Vec<&'a Diagnostic>,
+}
+
+/// Compiler diagnostics in the JSON lines printed by cargo.
+fn parse(stdout: &str) -> Vec<Diagnostic> {
+    stdout
+        .lines()
+        .filter_map(|line| serde_json::from_str::<CargoMessage>(line).ok())
+        .filter(|message| message.reason == "compiler-message")
+        .filter_map(|message| message.message)
+        .filter(|diagnostic| !diagnostic.is_summary())
+        .collect()
+}
+
+/// Group diagnostics by the cell they fall inside, in notebook order, followed by the rest.
+/// See [`Diagnostic::notebook_line`] for `notebook` and `root`.
+fn group<'a>(
+    diagnostics: &'a [Diagnostic],
+    cells: &'a [CellSpan],
+    notebook: &Path,
+    root: &Path,
+) -> Vec<Group<'a>> {
+    let mut groups: Vec<Group> = cells
+        .iter()
+        .map(|cell| Group {
+            cell: Some(cell),
+            diagnostics: Vec::new(),
+        })
+        .chain(std::iter::once(Group {
+            cell: None,
+            diagnostics: Vec::new(),
+        }))
+        .collect();
+    let outside = groups.len() - 1;
+
+    for diagnostic in diagnostics {
+        let index = diagnostic
+            .notebook_line(notebook, root)
+            .and_then(|line| cells.iter().position(|cell| cell.lines.contains(&line)))
+            .unwrap_or(outside);
+        groups[index].diagnostics.push(diagnostic);
+    }
+    groups.retain(|group| !group.diagnostics.is_empty());
+    groups
+}
+
+fn counts(diagnostics: &[&Diagnostic]) -> (usize, usize) {
+    let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+    (errors, diagnostics.len() - errors)
+}
+
+/// Check the notebook in the current directory and print its diagnostics per cell.
+/// Fails if there were errors.
+pub async fn check() -> Result<()> {
+    if !Path::new("Cargo.toml").exists() {
+        return Err(Error::NoCargoToml);
+    }
+
+    let build = config::load_build();
+    let format = if std::io::stdout().is_terminal() {
+        "--message-format=json-diagnostic-rendered-ansi"
+    } else {
+        "--message-format=json"
+    };
+    let output = Command::new("cargo")
+        .args(watcher::cargo_args("check", &build))
+        .arg(format)
+        .envs(&build.env)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .output()
+        .await?;
+
+    let diagnostics = parse(&String::from_utf8_lossy(&output.stdout));
+    // Without diagnostics a failure is in cargo itself, such as an invalid manifest.
+    if diagnostics.is_empty() && !output.status.success() {
+        return Err(Error::Build(String::from_utf8_lossy(&output.stderr).to_string()));
+    }
+
+    let source = fs::read_to_string(NOTEBOOK).unwrap_or_default();
+    let cells = source::cell_spans(&source);
+    let current = std::env::current_dir()?;
+    let root = workspace::metadata(&build.env).map_or(current.clone(), |metadata| metadata.workspace_root);
+    let notebook = current.join(NOTEBOOK);
+    let notebook = notebook.canonicalize().unwrap_or(notebook);
+    let groups = group(&diagnostics, &cells, &notebook, &root);
+    for group in &groups {
+        let (errors, warnings) = counts(&group.diagnostics);
+        match group.cell {
+            Some(cell) => println!(
+                "== fn {} ({}:{}-{}): {} error(s), {} warning(s)\n",
+                cell.name,
+                NOTEBOOK,
+                cell.lines.start(),
+                cell.lines.end(),
+                errors,
+                warnings
+            ),
+            None => println!("== outside cells: {} error(s), {} warning(s)\n", errors, warnings),
+        }
+        for diagnostic in &group.diagnostics {
+            match &diagnostic.rendered {
+                Some(rendered) => println!("{}", rendered.trim_end()),
+                None => println!("{}: {}", diagnostic.level, diagnostic.message),
+            }
+            println!();
+        }
+    }
+
+    let all: Vec<&Diagnostic> = diagnostics.iter().collect();
+    let (errors, warnings) = counts(&all);
+    let cells_affected = groups.iter().filter(|group| group.cell.is_some()).count();
+    if errors == 0 && warnings == 0 {
+        println!("No problems found in {} cell(s)", cells.len());
+    } else {
+        println!(
+            "{} error(s) and {} warning(s), {} cell(s) affected",
+            errors, warnings, cells_affected
+        );
+    }
+
+    if errors > 0 {
+        return Err(Error::Check(errors));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(level: &str, file: &str, line: u32) -> String {
+        serde_json::json!({
+            "reason": "compiler-message",
+            "message": {
+                "message": format!("{} at {}", level, line),
+                "level": level,
+                "spans": [{ "file_name": file, "line_start": line, "is_primary": true }],
+                "rendered": format!("{}: at {}:{}", level, file, line),
+            }
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn test_diagnostics_are_grouped_by_cell() {
+        let cells = vec![
+            CellSpan {
+                name: "load".to_string(),
+                lines: 3..=8,
+            },
+            CellSpan {
+                name: "report".to_string(),
+                lines: 10..=20,
+            },
+        ];
+        let stdout = [
+            r#"{"reason":"compiler-artifact"}"#.to_string(),
+            message("error", "notebooks/prices/cellbook.rs", 12),
+            message("warning", "notebooks/demo/cellbook.rs", 4),
+            message("warning", "cellbook.rs", 5),
+            message("error", "/ws/notebooks/prices/cellbook.rs", 1),
+            message("error", "src/other.rs", 5),
+            r#"{"reason":"compiler-message","message":{"message":"aborting due to 3 previous errors","level":"error","spans":[],"rendered":null}}"#.to_string(),
+        ]
+        .join("\n");
+
+        let diagnostics = parse(&stdout);
+        assert_eq!(diagnostics.len(), 5);
+        let groups = group(
+            &diagnostics,
+            &cells,
+            Path::new("/ws/notebooks/prices/cellbook.rs"),
+            Path::new("/ws"),
+        );
+        let summary: Vec<(Option<&str>, (usize, usize))> = groups
+            .iter()
+            .map(|g| (g.cell.map(|c| c.name.as_str()), counts(&g.diagnostics)))
+            .collect();
+        assert_eq!(summary, vec![(Some("report"), (1, 0)), (None, (2, 2))]);
+    }
+}
@@ -0,0 +1,196 @@
+//! `cargo cellbook clean`: remove files the runner leaves behind.
+//!
+//! Reload copies, temporary images and store values kept on disk are normally
+//! deleted when the runner exits, but stay around if it is killed first.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::errors::{Error, Result};
+use crate::{loader, store};
+
+/// Image extensions written by `open_image_bytes`.
+const IMAGE_EXTENSIONS: &[&str] = &["svg", "png", "jpg", "jpeg", "gif", "bmp", "webp"];
+
+/// Remove reload copies of the notebook library, temporary images, store values
+/// kept on disk and store exports, then run `cargo clean` if `cargo` is set.
+pub fn clean(cargo: bool) -> Result<()> {
+    let lib_path = loader::find_dylib_path()?;
+    let lib_file = lib_path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let lib_dir = lib_path.parent().unwrap_or(Path::new("."));
+    let temp_dir = std::env::temp_dir();
+
+    let copies = [
+        matching(lib_dir, |name| is_reload_copy(name, &lib_file)),
+        matching(&temp_dir, |name| is_temp_copy(name, &lib_file)),
+    ]
+    .concat();
+    let images = matching(&temp_dir, is_temp_image);
+    let spill_dirs = process_dirs(store::SPILL_DIR);
+    let mmap_dirs = process_dirs(store::MMAP_DIR);
+    let spilled: Vec<PathBuf> = spill_dirs
+        .iter()
+        .flat_map(|dir| matching(dir, |_| true))
+        .collect();
+    let mapped: Vec<PathBuf> = mmap_dirs.iter().flat_map(|dir| matching(dir, |_| true)).collect();
+    let exports = matching(Path::new("."), is_store_export);
+
+    let mut freed = 0;
+    for (label, paths) in [
+        ("reload copies", &copies),
+        ("temporary images", &images),
+        ("spilled store values", &spilled),
+        ("mapped store values", &mapped),
+        ("store exports", &exports),
+    ] {
+        let mut removed = 0;
+        for path in paths {
+            let size = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+            // Copies still loaded by a running runner can't be removed on Windows.
+            if fs::remove_file(path).is_ok() {
+                removed += 1;
+                freed += size;
+            }
+        }
+        println!("Removed {} {}", removed, label);
+    }
+    for dir in spill_dirs.iter().chain(&mmap_dirs) {
+        let _ = fs::remove_dir(dir);
+    }
+    println!("Freed {}", store::format_bytes(freed));
+
+    if cargo {
+        let status = Command::new("cargo").arg("clean").status()?;
+        if !status.success() {
+            return Err(Error::CargoClean(status));
+        }
+    }
+    Ok(())
+}
+
+/// Directories under `base` of runner processes that have exited, named by their pid.
+/// Those of running runners hold values still in use.
+fn process_dirs(base: impl AsRef<Path>) -> Vec<PathBuf> {
+    fs::read_dir(base)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let pid = path.file_name().and_then(|name| name.to_str()?.parse().ok());
+            !pid.is_some_and(is_running)
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // Signal 0 only checks that the process exists. EPERM means it belongs to another user.
+    let result = unsafe { libc::kill(pid, 0) };
+    result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn is_running(pid: u32) -> bool {
+    // `tasklist` lists the process only while it is running.
+    Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {}", pid), "/NH"])
+        .output()
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).contains(&pid.to_string()))
+}
+
+/// Files in `dir` whose name matches `filter`.
+fn matching(dir: &Path, filter: impl Fn(&str) -> bool) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_file()))
+        .filter(|entry| filter(&entry.file_name().to_string_lossy()))
+        .map(|entry| entry.path())
+        .collect()
+}
+
+/// `libnotebook.so.reload.3`, a copy next to the built library.
+fn is_reload_copy(name: &str, lib_file: &str) -> bool {
+    name.strip_prefix(lib_file)
+        .and_then(|rest| rest.strip_prefix(".reload."))
+        .is_some_and(is_number)
+}
+
+/// `cellbook-1234-3-notebook.dll`, a copy in the temp directory on Windows.
+fn is_temp_copy(name: &str, lib_file: &str) -> bool {
+    let Some(rest) = name.strip_prefix("cellbook-") else {
+        return false;
+    };
+    let Some(ids) = rest.strip_suffix(lib_file).and_then(|ids| ids.strip_suffix('-')) else {
+        return false;
+    };
+    ids.split_once('-')
+        .is_some_and(|(pid, counter)| is_number(pid) && is_number(counter))
+}
+
+/// `cellbook_18f3a2c4.png`, written by `open_image_bytes`.
+fn is_temp_image(name: &str) -> bool {
+    let Some((id, extension)) = name
+        .strip_prefix("cellbook_")
+        .and_then(|rest| rest.split_once('.'))
+    else {
+        return false;
+    };
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_hexdigit()) && IMAGE_EXTENSIONS.contains(&extension)
+}
+
+/// `cellbook-export-1718000000.postcard`, written by the TUI's store export.
+fn is_store_export(name: &str) -> bool {
+    name.strip_prefix("cellbook-export-")
+        .and_then(|rest| rest.strip_suffix(".postcard"))
+        .is_some_and(is_number)
+}
+
+fn is_number(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_leftover_files() {
+        assert!(is_reload_copy("libnb.so.reload.3", "libnb.so"));
+        assert!(!is_reload_copy("libnb.so", "libnb.so"));
+        assert!(!is_reload_copy("libother.so.reload.3", "libnb.so"));
+
+        assert!(is_temp_copy("cellbook-1234-3-nb.dll", "nb.dll"));
+        assert!(!is_temp_copy("cellbook-docs-1234", "nb.dll"));
+
+        assert!(is_temp_image("cellbook_18f3a2c4.png"));
+        assert!(is_temp_image("cellbook_ff.svg"));
+        assert!(!is_temp_image("cellbook_notes.png"));
+        assert!(!is_temp_image("cellbook_ff.postcard"));
+
+        assert!(is_store_export("cellbook-export-1718000000.postcard"));
+        assert!(!is_store_export("cellbook-report.html"));
+    }
+
+    #[test]
+    fn test_skips_dirs_of_running_runners() {
+        let base = std::env::temp_dir().join(format!("cellbook-clean-{}", std::process::id()));
+        let running = base.join(std::process::id().to_string());
+        let exited = base.join(u32::MAX.to_string());
+        fs::create_dir_all(&running).unwrap();
+        fs::create_dir_all(&exited).unwrap();
+
+        assert_eq!(process_dirs(&base), vec![exited]);
+        let _ = fs::remove_dir_all(&base);
+    }
+}
@@ -0,0 +1,108 @@
+//! System clipboard access through platform clipboard commands, or through the
+//! terminal with OSC 52 escape sequences where no command can reach the clipboard
+//! the user sees, such as over SSH. See [`terminal`] for how they get through tmux.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{Error, Result};
+use crate::terminal;
+use crate::util::base64;
+
+/// Clipboard commands tried in order, with their arguments.
+const COMMANDS: &[(&str, &[&str])] = &[
+    ("pbcopy", &[]),
+    ("wl-copy", &[]),
+    ("xclip", &["-selection", "clipboard"]),
+    ("xsel", &["--clipboard", "--input"]),
+    ("clip.exe", &[]),
+];
+
+/// Largest text copied with OSC 52, which many terminals cap at around 100 KB.
+const MAX_OSC52_BYTES: usize = 74 * 1024;
+
+/// How text is copied, set by the `clipboard` setting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClipboardMode {
+    /// OSC 52 in SSH sessions or when no clipboard command is installed, a command otherwise.
+    #[default]
+    Auto,
+    /// Only the platform clipboard commands.
+    Command,
+    /// Only OSC 52, handled by the terminal, including over SSH.
+    Osc52,
+}
+
+/// Copy text to the clipboard the way `mode` says.
+pub fn copy(text: &str, mode: ClipboardMode) -> Result<()> {
+    match mode {
+        ClipboardMode::Command => copy_with_command(text),
+        ClipboardMode::Osc52 => copy_with_osc52(text),
+        ClipboardMode::Auto if in_ssh_session() => copy_with_osc52(text),
+        ClipboardMode::Auto => match copy_with_command(text) {
+            Err(Error::Clipboard(e)) if e == NO_COMMAND => copy_with_osc52(text),
+            result => result,
+        },
+    }
+}
+
+const NO_COMMAND: &str = "no clipboard command found";
+
+/// Copy text to the system clipboard using the first available command.
+fn copy_with_command(text: &str) -> Result<()> {
+    for (program, args) in COMMANDS {
+        let Ok(mut child) = Command::new(program)
+            .args(*args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        else {
+            continue;
+        };
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes())?;
+        }
+        let status = child.wait()?;
+        if status.success() {
+            return Ok(());
+        }
+        return Err(Error::Clipboard(format!("{} exited with {}", program, status)));
+    }
+
+    Err(Error::Clipboard(NO_COMMAND.to_string()))
+}
+
+/// Ask the terminal to set its clipboard, through tmux or screen if the runner is in one.
+fn copy_with_osc52(text: &str) -> Result<()> {
+    if text.len() > MAX_OSC52_BYTES {
+        return Err(Error::Clipboard(format!(
+            "{} bytes is too much for OSC 52, set clipboard = \"command\" to copy it",
+            text.len()
+        )));
+    }
+    terminal::write_sequence(&osc52(text))?;
+    Ok(())
+}
+
+fn osc52(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", base64(text.as_bytes()))
+}
+
+fn in_ssh_session() -> bool {
+    std::env::var_os("SSH_TTY").is_some() || std::env::var_os("SSH_CONNECTION").is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_osc52_sequence() {
+        assert_eq!(osc52("hello"), "\x1b]52;c;aGVsbG8=\x07");
+    }
+}
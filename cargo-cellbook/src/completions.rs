@@ -0,0 +1,138 @@
+//! Shell completion scripts for `cargo cellbook`, generated by `clap_complete`
+//! from the clap definition of the command line.
+//!
+//! The scripts hook into cargo's own completion: words after `cargo cellbook`
+//! are completed here and everything else is passed on to cargo's.
+
+use clap::{Command, ValueEnum};
+use clap_complete::Generator;
+use clap_complete::shells::{Bash, Fish};
+
+/// Shell to print completions for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    /// Zsh, through its bash completion compatibility
+    Zsh,
+    Fish,
+}
+
+const BIN_NAME: &str = "cargo-cellbook";
+
+/// Script of `generator` for the `cellbook` subcommand of `cli`, completed as `bin_name`.
+fn generate(generator: impl Generator, cli: &Command, bin_name: &'static str) -> String {
+    let mut command = Command::new(bin_name);
+    if let Some(cellbook) = cli.find_subcommand("cellbook") {
+        // The generators complete hidden subcommands too, so they are left out here.
+        let subcommands = cellbook.get_subcommands().filter(|sub| !sub.is_hide_set());
+        command = command
+            .args(cellbook.get_arguments().cloned())
+            .subcommands(subcommands.cloned());
+    }
+    let mut script = Vec::new();
+    clap_complete::generate(generator, &mut command, bin_name, &mut script);
+    String::from_utf8_lossy(&script).into_owned()
+}
+
+fn bash(cli: &Command) -> String {
+    // The bash generator names its cases inconsistently for names with a `-`,
+    // so it generates `_cargo_cellbook` for `cargo_cellbook`, registered here for
+    // `cargo-cellbook`.
+    let script = generate(Bash, cli, "cargo_cellbook").replace(
+        "-o default cargo_cellbook\n",
+        &format!("-o default {}\n", BIN_NAME),
+    );
+    format!(
+        r#"# bash completion for cargo cellbook
+{script}
+_cargo_cellbook_or_cargo() {{
+    if [[ ${{COMP_WORDS[1]}} == cellbook && $COMP_CWORD -gt 1 ]]; then
+        # Complete `cargo cellbook ...` as `cargo-cellbook ...`.
+        COMP_WORDS=({BIN_NAME} "${{COMP_WORDS[@]:2}}")
+        COMP_CWORD=$((COMP_CWORD - 1))
+        _cargo_cellbook {BIN_NAME} "$2" "$3"
+    elif declare -F _cargo >/dev/null; then
+        _cargo "$@"
+    fi
+}}
+
+# Load cargo's completion first so that it doesn't replace this one later.
+declare -F _cargo >/dev/null || {{ declare -F _completion_loader >/dev/null && _completion_loader cargo; }}
+complete -o default -F _cargo_cellbook_or_cargo cargo
+"#
+    )
+}
+
+fn zsh(cli: &Command) -> String {
+    format!(
+        "# zsh completion for cargo cellbook\nautoload -U +X bashcompinit && bashcompinit\n{}",
+        bash(cli)
+    )
+}
+
+fn fish(cli: &Command) -> String {
+    // The generated script completes `cargo-cellbook`. Its conditions skip the
+    // command name, so they also skip `cellbook` after `cargo`, and don't match
+    // without it.
+    let script = generate(Fish, cli, BIN_NAME)
+        .replace(
+            "    set -e cmd[1]\n",
+            "    set -e cmd[1]\n    test \"$cmd[1]\" = cellbook; or return 1\n    set -e cmd[1]\n",
+        )
+        .replace(&format!("complete -c {} ", BIN_NAME), "complete -c cargo ");
+    format!("# fish completion for cargo cellbook\n{}", script)
+}
+
+/// Completion script for `shell`, generated from the command line definition `cli`.
+pub fn script(shell: Shell, cli: Command) -> String {
+    match shell {
+        Shell::Bash => bash(&cli),
+        Shell::Zsh => zsh(&cli),
+        Shell::Fish => fish(&cli),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{Arg, ArgAction};
+
+    use super::*;
+
+    fn cli() -> Command {
+        Command::new("cargo").subcommand(
+            Command::new("cellbook")
+                .subcommand(
+                    Command::new("run")
+                        .about("Run it")
+                        .arg(Arg::new("ui").long("ui").value_parser(["tui", "plain"])),
+                )
+                .subcommand(Command::new("runs").subcommand(
+                    Command::new("list").arg(Arg::new("json").long("json").action(ArgAction::SetTrue)),
+                ))
+                .subcommand(Command::new("worker").hide(true)),
+        )
+    }
+
+    #[test]
+    fn test_bash_completes_subcommands_flags_and_values() {
+        let script = script(Shell::Bash, cli());
+        assert!(script.contains("_cargo_cellbook() {"));
+        assert!(script.contains("_cargo_cellbook cargo-cellbook \"$2\" \"$3\""));
+        assert!(script.contains("-o bashdefault -o default cargo-cellbook\n"));
+        assert!(!script.contains("default cargo_cellbook"));
+        assert!(script.contains("cargo_cellbook__subcmd__runs__subcmd__list)"));
+        assert!(script.contains("complete -o default -F _cargo_cellbook_or_cargo cargo"));
+        assert!(script.contains("--json"));
+        assert!(script.contains("tui plain"));
+        assert!(!script.contains("worker"));
+    }
+
+    #[test]
+    fn test_fish_completes_after_cargo_cellbook() {
+        let script = script(Shell::Fish, cli());
+        assert!(script.contains("test \"$cmd[1]\" = cellbook; or return 1"));
+        assert!(script.contains("complete -c cargo -n \"__fish_cargo_cellbook_needs_command\""));
+        assert!(script.contains("-a \"run\" -d 'Run it'"));
+        assert!(!script.contains("complete -c cargo-cellbook"));
+    }
+}
@@ -0,0 +1,146 @@
+//! Documentation lookup for symbols used in a cell.
+//!
+//! Prefers local `cargo doc` output, which covers the notebook's exact
+//! dependency versions, and falls back to docs.rs.
+
+use std::path::Path;
+
+use crate::source::{TokenKind, tokenize};
+
+/// Names too common to be worth looking up.
+const PRELUDE: &[&str] = &[
+    "Box", "Err", "None", "Ok", "Option", "Result", "Self", "Some", "String", "Vec",
+];
+
+/// Paths and type-like names referenced in `source`, in order of first use.
+///
+/// `a::b::C` is kept as a whole path. Lone identifiers are kept if they
+/// start with an uppercase letter.
+pub fn symbols(source: &str) -> Vec<String> {
+    let tokens: Vec<_> = tokenize(source)
+        .into_iter()
+        .filter(|t| !matches!(t.kind, TokenKind::Whitespace | TokenKind::Comment))
+        .collect();
+
+    let mut symbols: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        if tokens[i].kind != TokenKind::Ident {
+            i += 1;
+            continue;
+        }
+
+        let mut segments = vec![tokens[i].text];
+        i += 1;
+        while tokens.get(i).map(|t| t.text) == Some(":")
+            && tokens.get(i + 1).map(|t| t.text) == Some(":")
+            && tokens.get(i + 2).is_some_and(|t| t.kind == TokenKind::Ident)
+        {
+            segments.push(tokens[i + 2].text);
+            i += 3;
+        }
+
+        let is_path = segments.len() > 1;
+        let is_type = segments[0].starts_with(|c: char| c.is_ascii_uppercase());
+        let symbol = segments.join("::");
+        if (is_path || is_type) && !PRELUDE.contains(&segments[0]) && !symbols.contains(&symbol) {
+            symbols.push(symbol);
+        }
+    }
+    symbols
+}
+
+/// Documentation URL for `symbol`.
+///
+/// `doc_dir` is the `target/doc` directory and `notebook_crate` the crate
+/// name of the notebook, whose local docs also search its dependencies.
+pub fn url(symbol: &str, doc_dir: &Path, notebook_crate: &str) -> String {
+    let segments: Vec<&str> = symbol.split("::").collect();
+    // A lowercase first segment of a path names a crate (or a module in scope).
+    let krate = segments
+        .first()
+        .filter(|s| segments.len() > 1 && s.starts_with(|c: char| c.is_ascii_lowercase()))
+        .copied();
+    // Search from the first type, so `prelude::DataFrame` finds re-exports and
+    // `CsvReadOptions::default` finds the method.
+    let start = segments
+        .iter()
+        .position(|s| s.starts_with(|c: char| c.is_ascii_uppercase()))
+        .unwrap_or(segments.len() - 1);
+    let query = segments[start..].join("::");
+
+    let local = [krate, Some(notebook_crate)]
+        .into_iter()
+        .flatten()
+        .map(|krate| doc_dir.join(krate).join("index.html"))
+        .find(|index| index.exists());
+    if let Some(index) = local {
+        return format!("file://{}?search={}", index.display(), query);
+    }
+
+    match krate {
+        Some(krate @ ("std" | "core" | "alloc")) => {
+            format!("https://doc.rust-lang.org/{krate}/?search={query}")
+        }
+        Some(krate) => format!("https://docs.rs/{krate}/latest/{krate}/?search={query}"),
+        None => format!("https://docs.rs/releases/search?query={}", segments[start]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symbols_keeps_paths_and_types() {
+        let source = r#"
+#[cell]
+async fn load() -> Result<()> {
+    // DataFrame in a comment is ignored
+    let opts = CsvReadOptions::default().with_has_header(true);
+    let df: polars::prelude::DataFrame = read(opts, "data.csv")?;
+    let v: Vec<f64> = Vec::new();
+    store!(df)?;
+    Ok(())
+}
+"#;
+        assert_eq!(
+            symbols(source),
+            vec!["CsvReadOptions::default", "polars::prelude::DataFrame"]
+        );
+    }
+
+    #[test]
+    fn test_url_falls_back_to_docs_rs() {
+        let doc_dir = Path::new("/nonexistent/target/doc");
+        assert_eq!(
+            url("polars::prelude::DataFrame", doc_dir, "notebook"),
+            "https://docs.rs/polars/latest/polars/?search=DataFrame"
+        );
+        assert_eq!(
+            url("std::collections::HashMap", doc_dir, "notebook"),
+            "https://doc.rust-lang.org/std/?search=HashMap"
+        );
+        assert_eq!(
+            url("std::fs::read_to_string", doc_dir, "notebook"),
+            "https://doc.rust-lang.org/std/?search=read_to_string"
+        );
+        assert_eq!(
+            url("CsvReadOptions::default", doc_dir, "notebook"),
+            "https://docs.rs/releases/search?query=CsvReadOptions"
+        );
+    }
+
+    #[test]
+    fn test_url_prefers_local_docs() {
+        let doc_dir = std::env::temp_dir().join(format!("cellbook-docs-{}", std::process::id()));
+        std::fs::create_dir_all(doc_dir.join("notebook")).unwrap();
+        std::fs::write(doc_dir.join("notebook/index.html"), "").unwrap();
+
+        let url = url("CsvReadOptions", &doc_dir, "notebook");
+        let _ = std::fs::remove_dir_all(&doc_dir);
+
+        assert!(url.starts_with("file://"));
+        assert!(url.ends_with("notebook/index.html?search=CsvReadOptions"));
+    }
+}
@@ -8,8 +8,88 @@ pub enum Error {
     Build(String),
     #[error("Library load error: {0}")]
     LibLoad(String),
+    #[error("Notebook library does not export `{0}`, is cellbook.rs missing an #[init] function?")]
+    SymbolMissing(&'static str),
+    #[error("Cell '{0}' not found in the loaded notebook")]
+    CellNotFound(String),
+    #[error(
+        "{library} has {} but cargo-cellbook {} expects v{expected}; \
+         build it against cellbook {}",
+        abi_found(*found),
+        env!("CARGO_PKG_VERSION"),
+        env!("CARGO_PKG_VERSION")
+    )]
+    AbiMismatch {
+        library: String,
+        expected: u32,
+        found: Option<u32>,
+    },
+    #[error(
+        "{library} uses cellbook {cellbook} but cargo-cellbook is {runner}; \
+         run `cargo install cargo-cellbook --version {cellbook}` or depend on cellbook {runner}"
+    )]
+    VersionSkew {
+        library: String,
+        runner: String,
+        cellbook: String,
+    },
     #[error("No Cargo.toml found in current directory")]
     NoCargoToml,
+    #[error("Cargo.toml is not a package of its workspace")]
+    NotAPackage,
+    #[error("Package '{0}' has no library target")]
+    NoLibTarget(String),
+    #[error("Invalid #[init] settings: {0}")]
+    NotebookConfig(String),
     #[error("Watch error: {0}")]
     Watch(String),
+    #[error("Store error: {0}")]
+    Store(String),
+    #[error("Clipboard error: {0}")]
+    Clipboard(String),
+    #[error("{0} cell(s) failed")]
+    CellsFailed(usize),
+    #[error("cargo check found {0} error(s)")]
+    Check(usize),
+    #[error("lint found {0} problem(s)")]
+    Lint(usize),
+    #[error("RPC error: {0}")]
+    Rpc(String),
+    #[error("Kernel error: {0}")]
+    Kernel(String),
+    #[error("Run log error: {0}")]
+    Runs(String),
+    #[error("Store history error: {0}")]
+    History(String),
+    #[error("Unknown example '{0}', see `cargo cellbook examples list`")]
+    UnknownExample(String),
+    #[error("Web server error: {0}")]
+    Web(String),
+    #[error("Workspace error: {0}")]
+    Workspace(String),
+    #[error("cargo metadata failed: {0}")]
+    Metadata(String),
+    #[error("cargo clean failed with {0}")]
+    CargoClean(std::process::ExitStatus),
+    #[error("{var} '{program}' {}", launch_failure(source))]
+    Launch {
+        var: &'static str,
+        program: String,
+        source: std::io::Error,
+    },
+}
+
+fn abi_found(found: Option<u32>) -> String {
+    match found {
+        Some(version) => format!("ABI v{}", version),
+        None => "no ABI version (built against an older cellbook)".to_string(),
+    }
+}
+
+fn launch_failure(source: &std::io::Error) -> String {
+    if source.kind() == std::io::ErrorKind::NotFound {
+        "not found".to_string()
+    } else {
+        format!("failed to start: {}", source)
+    }
 }
@@ -0,0 +1,147 @@
+//! `cargo cellbook examples`: start a project from one of the repository's examples.
+//!
+//! The examples are embedded in the binary, so new users can run a working
+//! notebook without cloning the repository.
+
+use std::fs;
+use std::path::Path;
+
+use crate::errors::{Error, Result};
+
+struct Example {
+    name: &'static str,
+    description: &'static str,
+    /// Files relative to the project directory, with their contents.
+    files: &'static [(&'static str, &'static str)],
+}
+
+macro_rules! example_file {
+    ($example:literal, $path:literal) => {
+        (
+            $path,
+            include_str!(concat!("../../examples/", $example, "/", $path)),
+        )
+    };
+}
+
+const EXAMPLES: &[Example] = &[
+    Example {
+        name: "context-store",
+        description: "Pass values between cells through the store, with init and teardown",
+        files: &[
+            example_file!("context-store", "Cargo.toml"),
+            example_file!("context-store", "cellbook.rs"),
+        ],
+    },
+    Example {
+        name: "plot-timeseries",
+        description: "Load stock prices with polars, plot them and show tables",
+        files: &[
+            example_file!("plot-timeseries", "Cargo.toml"),
+            example_file!("plot-timeseries", "Cellbook.toml"),
+            example_file!("plot-timeseries", "cellbook.rs"),
+            example_file!("plot-timeseries", "data/stock_prices.csv"),
+        ],
+    },
+    Example {
+        name: "unit-test",
+        description: "Test cells with `cargo test` using a `TestContext`",
+        files: &[
+            example_file!("unit-test", "Cargo.toml"),
+            example_file!("unit-test", "cellbook.rs"),
+        ],
+    },
+    Example {
+        name: "versioned-types",
+        description: "Store versioned types and handle schema mismatches",
+        files: &[
+            example_file!("versioned-types", "Cargo.toml"),
+            example_file!("versioned-types", "cellbook.rs"),
+        ],
+    },
+];
+
+/// Print the embedded examples.
+pub fn list() {
+    let width = EXAMPLES.iter().map(|e| e.name.len()).max().unwrap_or(0);
+    for example in EXAMPLES {
+        println!("{:<width$}  {}", example.name, example.description);
+    }
+    println!("\nStart a project from one with `cargo cellbook examples new <name>`");
+}
+
+/// Create a project called `name` (defaults to the example's name) from the example `example`.
+pub fn create(example: &str, name: Option<&str>) -> Result<()> {
+    let Some(example) = EXAMPLES.iter().find(|e| e.name == example) else {
+        return Err(Error::UnknownExample(example.to_string()));
+    };
+    let name = name.unwrap_or(example.name);
+    crate::check_new_project(name)?;
+
+    let project_path = Path::new(name);
+    for (path, contents) in example.files {
+        let path = project_path.join(path);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        if path.ends_with("Cargo.toml") {
+            fs::write(&path, manifest(contents, name))?;
+        } else {
+            fs::write(&path, contents)?;
+        }
+    }
+
+    println!(
+        "Created cellbook project {} from the {} example",
+        name, example.name
+    );
+    println!("Run it with `cd {} && cargo cellbook run`", name);
+    Ok(())
+}
+
+/// Rewrite an example's manifest for a standalone project: rename the package and
+/// take `cellbook` from crates.io instead of the repository.
+fn manifest(source: &str, name: &str) -> String {
+    let mut out = String::with_capacity(source.len());
+    for line in source.lines() {
+        if line.starts_with("name = ") {
+            out.push_str(&format!("name = \"{}\"", name));
+        } else if let Some(rest) = line.strip_prefix("cellbook = { path = \"../../cellbook\"") {
+            match rest.trim_start_matches(',').trim() {
+                "}" => out.push_str("cellbook = \"0\""),
+                rest => out.push_str(&format!("cellbook = {{ version = \"0\", {}", rest)),
+            }
+        } else {
+            out.push_str(line);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifests_point_at_crates_io() {
+        for example in EXAMPLES {
+            let (_, source) = example
+                .files
+                .iter()
+                .find(|(path, _)| *path == "Cargo.toml")
+                .unwrap();
+            let manifest = manifest(source, "my-notebook");
+            assert!(manifest.contains("name = \"my-notebook\"\n"), "{}", example.name);
+            assert!(!manifest.contains("path = \"../"), "{}", example.name);
+        }
+
+        let source = "[package]\nname = \"plot\"\n\n[dependencies]\n\
+                      cellbook = { path = \"../../cellbook\", features = [\"data\"] }\n";
+        assert_eq!(
+            manifest(source, "nb"),
+            "[package]\nname = \"nb\"\n\n[dependencies]\ncellbook = { version = \"0\", features = [\"data\"] }\n"
+        );
+        assert!(manifest("cellbook = { path = \"../../cellbook\" }\n", "nb").contains("cellbook = \"0\"\n"));
+    }
+}
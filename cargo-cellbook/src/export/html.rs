@@ -0,0 +1,176 @@
+//! Standalone HTML report rendering.
+
+use std::fmt::Write;
+
+use super::{CellReport, Image, Outcome};
+use crate::run_info::RunInfo;
+use crate::source::{self, TokenKind};
+use crate::util::base64;
+
+const STYLE: &str = r#"
+body { font-family: -apple-system, "Segoe UI", Helvetica, Arial, sans-serif; margin: 2rem auto; max-width: 960px; padding: 0 1rem; color: #1f2328; }
+h1 { font-size: 1.6rem; margin-bottom: 0.25rem; }
+.summary { color: #59636e; margin-top: 0; }
+section { border: 1px solid #d1d9e0; border-radius: 6px; margin: 1.5rem 0; overflow: hidden; }
+section > header { display: flex; justify-content: space-between; align-items: baseline; padding: 0.5rem 1rem; background: #f6f8fa; border-bottom: 1px solid #d1d9e0; }
+section > header h2 { font-size: 1rem; margin: 0; font-family: ui-monospace, Menlo, Consolas, monospace; }
+.status { font-size: 0.85rem; }
+.success .status { color: #1a7f37; }
+.error .status { color: #d1242f; }
+.skipped .status { color: #59636e; }
+pre { margin: 0; padding: 0.75rem 1rem; overflow-x: auto; font: 0.85rem/1.45 ui-monospace, Menlo, Consolas, monospace; }
+pre.stdout { border-top: 1px solid #d1d9e0; background: #fbfbfb; }
+pre.failure { border-top: 1px solid #d1d9e0; background: #fff5f5; color: #d1242f; }
+figure { margin: 0; padding: 0.75rem 1rem; border-top: 1px solid #d1d9e0; }
+figure img { max-width: 100%; }
+.kw { color: #cf222e; }
+.str { color: #0a3069; }
+.num, .lt { color: #0550ae; }
+.com { color: #6e7781; font-style: italic; }
+.mac { color: #8250df; }
+"#;
+
+/// Render the report as a self-contained HTML document.
+pub fn render(title: &str, run_info: &RunInfo, reports: &[CellReport]) -> String {
+    let total: std::time::Duration = reports.iter().map(|r| r.duration).sum();
+    let failed = reports
+        .iter()
+        .filter(|r| matches!(r.outcome, Outcome::Error(_)))
+        .count();
+
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{title}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n<h1>{title}</h1>\n\
+         <p class=\"summary\">{count} cells, {failed} failed, {total:.2?} total</p>\n\
+         <p class=\"summary\">{run}</p>\n",
+        title = escape(title),
+        count = reports.len(),
+        run = escape(&run_info.describe()),
+    );
+
+    for report in reports {
+        render_cell(&mut html, report);
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+fn render_cell(html: &mut String, report: &CellReport) {
+    let (class, status) = match &report.outcome {
+        Outcome::Success => ("success", format!("✓ {:.2?}", report.duration)),
+        Outcome::Error(_) => ("error", format!("✗ {:.2?}", report.duration)),
+        Outcome::Skipped => ("skipped", "skipped".to_string()),
+    };
+
+    let _ = write!(
+        html,
+        "<section class=\"{class}\">\n<header><h2>{}</h2><span class=\"status\">{status}</span></header>\n",
+        escape(&report.name),
+    );
+    if !report.source.is_empty() {
+        let _ = writeln!(html, "<pre><code>{}</code></pre>", highlight(&report.source));
+    }
+    if !report.stdout.is_empty() {
+        let _ = writeln!(html, "<pre class=\"stdout\">{}</pre>", escape(&report.stdout));
+    }
+    if let Outcome::Error(e) = &report.outcome {
+        let _ = writeln!(html, "<pre class=\"failure\">{}</pre>", escape(e));
+    }
+    for image in &report.images {
+        let _ = writeln!(html, "<figure><img src=\"{}\"></figure>", data_uri(image));
+    }
+    html.push_str("</section>\n");
+}
+
+/// Escape text for use in HTML content and attribute values.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Highlight Rust source as escaped HTML with CSS classes.
+fn highlight(code: &str) -> String {
+    let mut out = String::with_capacity(code.len() * 2);
+    for token in source::tokenize(code) {
+        let class = match token.kind {
+            TokenKind::Keyword => "kw",
+            TokenKind::String | TokenKind::Char => "str",
+            TokenKind::Number => "num",
+            TokenKind::Lifetime => "lt",
+            TokenKind::Comment => "com",
+            TokenKind::Macro => "mac",
+            TokenKind::Whitespace | TokenKind::Ident | TokenKind::Punct => {
+                out.push_str(&escape(token.text));
+                continue;
+            }
+        };
+        let _ = write!(out, "<span class=\"{class}\">{}</span>", escape(token.text));
+    }
+    out
+}
+
+fn data_uri(image: &Image) -> String {
+    let mime = match image.extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        _ => "application/octet-stream",
+    };
+    format!("data:{};base64,{}", mime, base64(&image.data))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_highlight_escapes_source() {
+        let html = highlight("let v: Vec<u8> = vec![1]; // <done>");
+        assert!(html.contains("<span class=\"kw\">let</span>"));
+        assert!(html.contains("Vec&lt;u8&gt;"));
+        assert!(html.contains("<span class=\"mac\">vec!</span>"));
+        assert!(html.contains("<span class=\"com\">// &lt;done&gt;</span>"));
+    }
+
+    #[test]
+    fn test_render_includes_output_and_images() {
+        let reports = vec![CellReport {
+            name: "plot".to_string(),
+            doc: String::new(),
+            source: "fn plot() {}".to_string(),
+            stdout: "<b>hi</b>\n".to_string(),
+            duration: Duration::from_millis(5),
+            outcome: Outcome::Error("boom".to_string()),
+            images: vec![Image {
+                extension: "svg".to_string(),
+                data: b"<svg/>".to_vec(),
+            }],
+        }];
+
+        let html = render("demo & co", &RunInfo::new(Some(42), Some(0)), &reports);
+        assert!(html.contains("<title>demo &amp; co</title>"));
+        assert!(html.contains("&lt;b&gt;hi&lt;/b&gt;"));
+        assert!(html.contains("<pre class=\"failure\">boom</pre>"));
+        assert!(html.contains("data:image/svg+xml;base64,PHN2Zy8+"));
+        assert!(html.contains("1 cells, 1 failed"));
+        assert!(html.contains("seed 42, run at 1970-01-01 00:00:00 UTC"));
+    }
+}
@@ -0,0 +1,116 @@
+//! Markdown report rendering.
+//!
+//! Images can't be embedded in Markdown, so they are written to a directory
+//! next to the report and linked by relative path.
+
+use std::fmt::Write;
+
+use super::{CellReport, Outcome};
+use crate::run_info::RunInfo;
+
+/// Render the report as Markdown, linking images under `assets`.
+pub fn render(title: &str, run_info: &RunInfo, reports: &[CellReport], assets: &str) -> String {
+    let total: std::time::Duration = reports.iter().map(|r| r.duration).sum();
+    let failed = reports
+        .iter()
+        .filter(|r| matches!(r.outcome, Outcome::Error(_)))
+        .count();
+
+    let mut md = format!(
+        "# {}\n\n{} cells, {} failed, {:.2?} total\n\n{}\n",
+        title,
+        reports.len(),
+        failed,
+        total,
+        run_info.describe()
+    );
+    for report in reports {
+        render_cell(&mut md, report, assets);
+    }
+    md
+}
+
+/// File name of a cell's `index`th image in the assets directory.
+pub fn image_name(cell: &str, index: usize, extension: &str) -> String {
+    format!("{}-{}.{}", cell, index + 1, extension)
+}
+
+fn render_cell(md: &mut String, report: &CellReport, assets: &str) {
+    let status = match &report.outcome {
+        Outcome::Success => format!("✓ {:.2?}", report.duration),
+        Outcome::Error(_) => format!("✗ {:.2?}", report.duration),
+        Outcome::Skipped => "skipped".to_string(),
+    };
+    let _ = write!(md, "\n## {}\n\n{}\n", report.name, status);
+
+    if !report.doc.is_empty() {
+        let _ = write!(md, "\n{}\n", report.doc);
+    }
+    if !report.source.is_empty() {
+        let _ = write!(md, "\n{}", fence(&report.source, "rust"));
+    }
+    if !report.stdout.is_empty() {
+        let _ = write!(md, "\nOutput:\n\n{}", fence(&report.stdout, "text"));
+    }
+    if let Outcome::Error(e) = &report.outcome {
+        let _ = write!(md, "\nError:\n\n{}", fence(e, "text"));
+    }
+    for (i, image) in report.images.iter().enumerate() {
+        let name = image_name(&report.name, i, &image.extension);
+        let _ = write!(md, "\n![{}]({}/{})\n", report.name, assets, name);
+    }
+}
+
+/// Fenced code block, with a fence longer than any backtick run in `content`.
+fn fence(content: &str, lang: &str) -> String {
+    let mut longest = 0;
+    let mut run = 0;
+    for c in content.chars() {
+        run = if c == '`' { run + 1 } else { 0 };
+        longest = longest.max(run);
+    }
+    let fence = "`".repeat(longest.max(2) + 1);
+    format!("{fence}{lang}\n{}\n{fence}\n", content.trim_end_matches('\n'))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::super::Image;
+    use super::*;
+
+    #[test]
+    fn test_render_cells() {
+        let reports = vec![CellReport {
+            name: "plot".to_string(),
+            doc: "Plot closing prices.".to_string(),
+            source: "/// Plot closing prices.\nasync fn plot() {}".to_string(),
+            stdout: "done\n".to_string(),
+            duration: Duration::from_millis(5),
+            outcome: Outcome::Success,
+            images: vec![Image {
+                extension: "svg".to_string(),
+                data: Vec::new(),
+            }],
+        }];
+
+        let md = render(
+            "stocks",
+            &RunInfo::new(Some(42), Some(0)),
+            &reports,
+            "report_files",
+        );
+        assert!(md.starts_with("# stocks\n\n1 cells, 0 failed"));
+        assert!(md.contains("\n\nseed 42, run at 1970-01-01 00:00:00 UTC (--seed 42 --timestamp 0)\n"));
+        assert!(md.contains("\n## plot\n\n✓ 5.00ms\n\nPlot closing prices.\n"));
+        assert!(md.contains("```rust\n/// Plot closing prices.\nasync fn plot() {}\n```\n"));
+        assert!(md.contains("Output:\n\n```text\ndone\n```\n"));
+        assert!(md.contains("![plot](report_files/plot-1.svg)"));
+    }
+
+    #[test]
+    fn test_fence_outgrows_backticks() {
+        assert_eq!(fence("a ``` b", "text"), "````text\na ``` b\n````\n");
+    }
+}
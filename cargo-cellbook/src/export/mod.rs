@@ -0,0 +1,262 @@
+//! Headless notebook execution for `cargo cellbook export`.
+
+mod html;
+mod markdown;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+use futures::future::BoxFuture;
+
+use crate::capture::capture_stdout;
+use crate::errors::Result;
+use crate::failure::CellFailure;
+use crate::loader::{self, CellResult, LoadedLibrary};
+use crate::run_info::RunInfo;
+use crate::{history, isolation, retry, runs, source, store, watcher};
+
+/// Report output format.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum ExportFormat {
+    /// Standalone HTML page with highlighted source, output and embedded images
+    Html,
+    /// Markdown document with fenced source and output, and images in a directory beside it
+    #[value(name = "md", alias = "markdown")]
+    Markdown,
+}
+
+impl ExportFormat {
+    fn default_output(self) -> PathBuf {
+        match self {
+            ExportFormat::Html => PathBuf::from("cellbook-report.html"),
+            ExportFormat::Markdown => PathBuf::from("cellbook-report.md"),
+        }
+    }
+}
+
+/// Outcome of a cell in the report.
+pub enum Outcome {
+    Success,
+    Error(String),
+    /// Not run because the init cell failed.
+    Skipped,
+}
+
+/// Image recorded by a cell through `open_image` or `open_image_bytes`.
+pub struct Image {
+    pub extension: String,
+    pub data: Vec<u8>,
+}
+
+/// Everything captured from a single cell run.
+pub struct CellReport {
+    pub name: String,
+    /// Doc comment of the cell, or its `desc` if it has none.
+    pub doc: String,
+    pub source: String,
+    pub stdout: String,
+    pub duration: Duration,
+    pub outcome: Outcome,
+    pub images: Vec<Image>,
+}
+
+/// Build the notebook, run the init cell and every cell in order, and write a report.
+pub async fn export(format: ExportFormat, output: Option<PathBuf>, run_info: RunInfo) -> Result<()> {
+    let title = loader::package()?.name;
+    loader::set_project_root()?;
+    run_info.share();
+    runs::start(&run_info);
+    history::start();
+    let lib_path = loader::find_dylib_path()?;
+
+    watcher::initial_build().await?;
+
+    // Cells write images here instead of spawning a viewer.
+    let artifact_dir = std::env::temp_dir().join(format!("cellbook-export-{}", std::process::id()));
+    fs::create_dir_all(&artifact_dir)?;
+    loader::set_notebook_var("CELLBOOK_EXPORT_DIR", artifact_dir.to_string_lossy());
+
+    let lib = LoadedLibrary::load(&lib_path)?;
+    let source = fs::read_to_string("cellbook.rs").unwrap_or_default();
+    let reports = run_cells(&lib, &source, &artifact_dir).await;
+
+    // Background tasks and resources reference library code.
+    lib.release().await.report(&mut std::io::stderr());
+    drop(lib);
+    let _ = fs::remove_dir_all(&artifact_dir);
+
+    let output = output.unwrap_or_else(|| format.default_output());
+    let content = match format {
+        ExportFormat::Html => html::render(&title, &run_info, &reports),
+        ExportFormat::Markdown => {
+            let assets = write_assets(&reports, &output)?;
+            markdown::render(&title, &run_info, &reports, &assets)
+        }
+    };
+    fs::write(&output, content)?;
+
+    println!("Exported {} cells to {}", reports.len(), output.display());
+
+    Ok(())
+}
+
+async fn run_cells(lib: &LoadedLibrary, source: &str, artifact_dir: &Path) -> Vec<CellReport> {
+    let mut reports = Vec::with_capacity(lib.cells().len() + 1);
+
+    let mut init = run_cell(
+        &lib.init().name,
+        cell_source(source, lib.init().line),
+        in_process(lib.init_future()),
+        artifact_dir,
+    )
+    .await;
+    init.doc = cell_doc(&init.source, lib.init().description.as_deref());
+    let init_failed = matches!(init.outcome, Outcome::Error(_));
+    reports.push(init);
+
+    for cell in lib.cells() {
+        let source = cell_source(source, cell.line);
+        // Services run until stopped, so they have no output to report.
+        if init_failed || cell.service {
+            let mut report = CellReport::unrun(&cell.name, source, Outcome::Skipped);
+            report.doc = cell_doc(&report.source, cell.description.as_deref());
+            reports.push(report);
+            continue;
+        }
+
+        let mut report = if cell.isolated {
+            let attempt = || isolation::run(lib.lib_path().to_path_buf(), cell.name.clone());
+            let run = retry::run(cell.retries, cell.backoff_ms, attempt, |_, _| {});
+            run_cell(&cell.name, source, run, artifact_dir).await
+        } else {
+            match lib.cell_attempts(cell) {
+                Ok(attempts) => {
+                    let attempt = || async {
+                        match attempts() {
+                            Ok(future) => in_process(future).await,
+                            Err(e) => (String::new(), Err(CellFailure::from_message(e.to_string()))),
+                        }
+                    };
+                    let run = retry::run(cell.retries, cell.backoff_ms, attempt, |_, _| {});
+                    run_cell(&cell.name, source, run, artifact_dir).await
+                }
+                Err(e) => CellReport::unrun(&cell.name, source, Outcome::Error(e.to_string())),
+            }
+        };
+        report.doc = cell_doc(&report.source, cell.description.as_deref());
+        reports.push(report);
+    }
+
+    reports
+}
+
+/// Run a cell future in this process, capturing its stdout.
+async fn in_process(
+    future: BoxFuture<'static, CellResult>,
+) -> (String, std::result::Result<(), CellFailure>) {
+    capture_stdout(|| async { future.await.map_err(|e| CellFailure::from_error(&*e)) }).await
+}
+
+/// Time a cell run producing stdout and a result.
+async fn run_cell(
+    name: &str,
+    source: String,
+    run: impl Future<Output = (String, std::result::Result<(), CellFailure>)>,
+    artifact_dir: &Path,
+) -> CellReport {
+    let start = Instant::now();
+    let (stdout, result) = store::tracked(name, run).await;
+    let duration = start.elapsed();
+    let error = result.as_ref().err().map(|e| e.to_string());
+    runs::record(name, duration, error.as_deref(), &store::take_access(name));
+
+    CellReport {
+        name: name.to_string(),
+        doc: String::new(),
+        source,
+        stdout,
+        duration,
+        outcome: match error {
+            None => Outcome::Success,
+            Some(e) => Outcome::Error(e),
+        },
+        images: take_images(artifact_dir),
+    }
+}
+
+impl CellReport {
+    fn unrun(name: &str, source: String, outcome: Outcome) -> Self {
+        Self {
+            name: name.to_string(),
+            doc: String::new(),
+            source,
+            stdout: String::new(),
+            duration: Duration::ZERO,
+            outcome,
+            images: Vec::new(),
+        }
+    }
+}
+
+fn cell_source(source: &str, line: u32) -> String {
+    source::extract_item(source, line).unwrap_or_default().to_string()
+}
+
+fn cell_doc(item: &str, description: Option<&str>) -> String {
+    match source::doc_comment(item) {
+        Some(doc) => doc,
+        None => description.unwrap_or_default().to_string(),
+    }
+}
+
+/// Write the reports' images to a directory named after `output`, returning
+/// its name for linking from the report.
+fn write_assets(reports: &[CellReport], output: &Path) -> Result<String> {
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let assets = format!("{}_files", stem);
+    let dir = output.with_file_name(&assets);
+    for report in reports {
+        for (i, image) in report.images.iter().enumerate() {
+            fs::create_dir_all(&dir)?;
+            fs::write(
+                dir.join(markdown::image_name(&report.name, i, &image.extension)),
+                &image.data,
+            )?;
+        }
+    }
+    Ok(assets)
+}
+
+/// Read and remove images written to the artifact directory, oldest first.
+fn take_images(artifact_dir: &Path) -> Vec<Image> {
+    let Ok(entries) = fs::read_dir(artifact_dir) else {
+        return Vec::new();
+    };
+
+    let mut paths: Vec<_> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let modified = e.metadata().and_then(|m| m.modified()).ok()?;
+            Some((modified, e.path()))
+        })
+        .collect();
+    paths.sort();
+
+    paths
+        .into_iter()
+        .filter_map(|(_, path)| {
+            let data = fs::read(&path).ok()?;
+            let _ = fs::remove_file(&path);
+            Some(Image {
+                extension: path
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .unwrap_or_default()
+                    .to_lowercase(),
+                data,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,146 @@
+//! Errors returned by cells, with the cause chain and backtrace kept apart
+//! from the message so they can be shown as a structured report.
+
+use std::error::Error as StdError;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// Marker anyhow puts before a captured backtrace in its `Debug` output.
+const BACKTRACE_MARKER: &str = "\nStack backtrace:\n";
+
+/// A failed cell run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CellFailure {
+    pub message: String,
+    /// Sources of the error, outermost first.
+    pub causes: Vec<String>,
+    /// Backtrace captured where the error was created, if any.
+    pub backtrace: Option<String>,
+}
+
+impl CellFailure {
+    /// Capture the message, cause chain and backtrace of an error returned by a cell.
+    ///
+    /// Backtraces are only captured by errors that support it, such as
+    /// `anyhow::Error` with `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE` set.
+    pub fn from_error(error: &(dyn StdError + 'static)) -> Self {
+        if let Some(failure) = error.downcast_ref::<CellFailure>() {
+            return failure.clone();
+        }
+        let mut causes = Vec::new();
+        let mut source = error.source();
+        while let Some(cause) = source {
+            causes.push(cause.to_string());
+            source = cause.source();
+        }
+        Self {
+            message: error.to_string(),
+            causes,
+            backtrace: backtrace(&format!("{:?}", error)),
+        }
+    }
+
+    /// A failure reported by notebook code as JSON, see `cellbook::ffi::FfiFuture::cell`.
+    /// Anything else, such as the message of a panic, is taken as the message.
+    pub fn from_report(report: &str) -> Self {
+        #[derive(Deserialize)]
+        struct Report {
+            message: String,
+            causes: Vec<String>,
+            debug: String,
+        }
+
+        match serde_json::from_str::<Report>(report) {
+            Ok(report) => Self {
+                message: report.message,
+                causes: report.causes,
+                backtrace: backtrace(&report.debug),
+            },
+            Err(_) => Self::from_message(report),
+        }
+    }
+
+    /// A failure with only a message, for errors from the runner itself.
+    pub fn from_message(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            causes: Vec::new(),
+            backtrace: None,
+        }
+    }
+
+    /// Report for the error view, headed by the cell's location in the notebook.
+    pub fn render(&self, cell: &str, line: u32) -> String {
+        let mut out = format!("cellbook.rs:{} {}\n\nError: {}\n", line, cell, self.message);
+        if !self.causes.is_empty() {
+            out.push_str("\nCaused by:\n");
+            for (i, cause) in self.causes.iter().enumerate() {
+                out.push_str(&format!("  {}. {}\n", i + 1, cause));
+            }
+        }
+        if let Some(backtrace) = &self.backtrace {
+            out.push_str("\nBacktrace:\n");
+            out.push_str(backtrace);
+            out.push('\n');
+        }
+        out
+    }
+}
+
+impl fmt::Display for CellFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl StdError for CellFailure {}
+
+/// The backtrace section of an error's `Debug` output.
+fn backtrace(debug: &str) -> Option<String> {
+    let (_, backtrace) = debug.split_once(BACKTRACE_MARKER)?;
+    let backtrace = backtrace.trim_end();
+    (!backtrace.is_empty()).then(|| backtrace.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("failed to load prices")]
+    struct LoadError(#[source] std::io::Error);
+
+    #[test]
+    fn test_from_error_collects_causes() {
+        let io = std::io::Error::new(std::io::ErrorKind::NotFound, "prices.csv missing");
+        let failure = CellFailure::from_error(&LoadError(io));
+        assert_eq!(failure.message, "failed to load prices");
+        assert_eq!(failure.causes, vec!["prices.csv missing"]);
+        assert_eq!(failure.backtrace, None);
+
+        let report = failure.render("load", 12);
+        assert!(report.starts_with("cellbook.rs:12 load\n\nError: failed to load prices\n"));
+        assert!(report.contains("Caused by:\n  1. prices.csv missing\n"));
+    }
+
+    #[test]
+    fn test_from_report_keeps_causes_and_backtrace() {
+        let report = r#"{"message":"outer","causes":["inner"],"debug":"outer\n\nStack backtrace:\n   0: notebook::load\n"}"#;
+        let failure = CellFailure::from_report(report);
+        assert_eq!(failure.message, "outer");
+        assert_eq!(failure.causes, vec!["inner"]);
+        assert_eq!(failure.backtrace.as_deref(), Some("   0: notebook::load"));
+        assert_eq!(CellFailure::from_error(&failure), failure);
+
+        let panicked = CellFailure::from_report("panicked: bad cell");
+        assert_eq!(panicked, CellFailure::from_message("panicked: bad cell"));
+    }
+
+    #[test]
+    fn test_backtrace_from_debug_output() {
+        let debug = "outer\n\nCaused by:\n    inner\n\nStack backtrace:\n   0: notebook::load\n";
+        assert_eq!(backtrace(debug).as_deref(), Some("   0: notebook::load"));
+        assert_eq!(backtrace("outer"), None);
+    }
+}
@@ -0,0 +1,181 @@
+//! `fast_build`: build the notebook with a cargo profile tuned for
+//! save-to-reload latency rather than for the code it produces.
+//!
+//! The profile is generated on the command line with `--config`, so the
+//! notebook's `Cargo.toml` is left alone. It builds into its own directory
+//! under the target directory, so builds from an editor or a plain
+//! `cargo build` keep their own artifacts and neither evicts the other's
+//! dependencies from the cache.
+//!
+//! Nothing is kept warm between builds: cargo has no daemon mode, so each
+//! rebuild starts `cargo build` afresh, and dependencies are only compiled
+//! ahead of time by the startup build. `-Zthreads` is passed as
+//! `build.rustflags`, which cargo ignores when `RUSTFLAGS` is set, also in
+//! `[build] env`.
+
+use std::path::Path;
+use std::process::Command;
+use std::sync::OnceLock;
+
+use crate::tui::config::BuildConfig;
+
+/// Cargo profile of fast builds, also the directory it builds into.
+pub const PROFILE: &str = "cellbook-fast";
+
+/// Most frontend threads passed to `-Zthreads`, beyond which rustc rarely gets faster.
+const MAX_THREADS: usize = 8;
+
+/// What the toolchain building the notebook supports beyond stable.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Toolchain {
+    nightly: bool,
+    /// The cranelift codegen backend is installed, with
+    /// `rustup component add rustc-codegen-cranelift-preview`.
+    cranelift: bool,
+}
+
+/// Arguments added to `cargo build` and `cargo check` in fast mode.
+pub fn cargo_args(build: &BuildConfig) -> Vec<String> {
+    let threads = std::thread::available_parallelism()
+        .map(|n| n.get().min(MAX_THREADS))
+        .unwrap_or(1);
+    profile_args(toolchain(build), threads)
+}
+
+fn profile_args(toolchain: Toolchain, threads: usize) -> Vec<String> {
+    let mut settings = vec![
+        "inherits=\"dev\"".to_string(),
+        // Dependencies rarely need stepping through, and their debug info
+        // dominates link times.
+        "package.\"*\".debug=0".to_string(),
+    ];
+    let mut args = vec!["--profile".to_string(), PROFILE.to_string()];
+    if toolchain.nightly {
+        args.push("--config".to_string());
+        args.push(format!("build.rustflags=[\"-Zthreads={}\"]", threads));
+        if toolchain.cranelift {
+            args.push("-Zcodegen-backend".to_string());
+            settings.push("codegen-backend=\"cranelift\"".to_string());
+        }
+    }
+    for setting in settings {
+        args.push("--config".to_string());
+        args.push(format!("profile.{}.{}", PROFILE, setting));
+    }
+    args
+}
+
+/// Detected once per session, with the build's environment so that
+/// `RUSTUP_TOOLCHAIN` set in `[build]` is taken into account.
+fn toolchain(build: &BuildConfig) -> Toolchain {
+    static TOOLCHAIN: OnceLock<Toolchain> = OnceLock::new();
+    *TOOLCHAIN.get_or_init(|| {
+        let rustc = |args: &[&str]| {
+            let output = Command::new("rustc").args(args).envs(&build.env).output().ok()?;
+            output
+                .status
+                .success()
+                .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        };
+        let Some((nightly, host)) = rustc(&["-vV"]).as_deref().map(parse_version) else {
+            return Toolchain::default();
+        };
+        let cranelift = nightly
+            && rustc(&["--print", "sysroot"]).is_some_and(|sysroot| {
+                has_cranelift(
+                    &Path::new(&sysroot)
+                        .join("lib/rustlib")
+                        .join(host.unwrap_or_default()),
+                )
+            });
+        Toolchain { nightly, cranelift }
+    })
+}
+
+/// Whether `rustc -vV` describes a nightly compiler, and its host triple.
+fn parse_version(output: &str) -> (bool, Option<String>) {
+    let field = |name: &str| {
+        output
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+            .map(str::trim)
+    };
+    let nightly = field("release").is_some_and(|release| release.contains("nightly"));
+    (nightly, field("host").map(String::from))
+}
+
+/// Whether the host's directory in the sysroot has the cranelift backend.
+fn has_cranelift(rustlib_host: &Path) -> bool {
+    std::fs::read_dir(rustlib_host.join("codegen-backends"))
+        .map(|entries| {
+            entries.flatten().any(|entry| {
+                entry
+                    .file_name()
+                    .to_string_lossy()
+                    .contains("rustc_codegen_cranelift")
+            })
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_args_follow_the_toolchain() {
+        let stable = profile_args(Toolchain::default(), 8);
+        assert_eq!(
+            stable,
+            [
+                "--profile",
+                "cellbook-fast",
+                "--config",
+                "profile.cellbook-fast.inherits=\"dev\"",
+                "--config",
+                "profile.cellbook-fast.package.\"*\".debug=0",
+            ]
+        );
+
+        let nightly = profile_args(
+            Toolchain {
+                nightly: true,
+                cranelift: false,
+            },
+            4,
+        );
+        assert!(nightly.contains(&"build.rustflags=[\"-Zthreads=4\"]".to_string()));
+        assert!(!nightly.contains(&"-Zcodegen-backend".to_string()));
+
+        let cranelift = profile_args(
+            Toolchain {
+                nightly: true,
+                cranelift: true,
+            },
+            4,
+        );
+        assert!(cranelift.contains(&"-Zcodegen-backend".to_string()));
+        assert!(cranelift.contains(&"profile.cellbook-fast.codegen-backend=\"cranelift\"".to_string()));
+    }
+
+    #[test]
+    fn test_parse_version() {
+        let output = "rustc 1.92.0-nightly (abc 2025-10-01)\nbinary: rustc\nhost: x86_64-unknown-linux-gnu\nrelease: 1.92.0-nightly\n";
+        assert_eq!(
+            parse_version(output),
+            (true, Some("x86_64-unknown-linux-gnu".to_string()))
+        );
+        assert!(!parse_version("release: 1.90.0\nhost: aarch64-apple-darwin").0);
+    }
+
+    #[test]
+    fn test_has_cranelift() {
+        let dir = std::env::temp_dir().join(format!("cellbook-cranelift-{}", std::process::id()));
+        assert!(!has_cranelift(&dir));
+        std::fs::create_dir_all(dir.join("codegen-backends")).unwrap();
+        assert!(!has_cranelift(&dir));
+        std::fs::write(dir.join("codegen-backends/librustc_codegen_cranelift-abc.so"), "").unwrap();
+        assert!(has_cranelift(&dir));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,423 @@
+//! Data-flow graph of cells and the store keys passed between them.
+//!
+//! A cell's edges are the keys it loaded and stored when it last ran. Cells
+//! that haven't run fall back to the keys named by store and load calls in
+//! their source.
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::fs;
+
+use crate::errors::Result;
+use crate::loader::{self, LoadedLibrary};
+use crate::source::{self, Token, TokenKind};
+use crate::store::Access;
+use crate::watcher;
+
+/// A cell and the keys it loads and stores.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Node {
+    pub cell: String,
+    pub access: Access,
+}
+
+/// Cells in notebook order, linked by the keys one stores and another loads.
+#[derive(Clone, Debug, Default)]
+pub struct Graph {
+    nodes: Vec<Node>,
+}
+
+impl Graph {
+    pub fn new(nodes: Vec<Node>) -> Self {
+        Self { nodes }
+    }
+
+    /// Cells that load `key`, other than `cell` itself.
+    fn readers(&self, cell: &str, key: &str) -> Vec<&str> {
+        self.nodes
+            .iter()
+            .filter(|node| node.cell != cell && node.access.reads.contains(key))
+            .map(|node| node.cell.as_str())
+            .collect()
+    }
+
+    /// Depth of each cell below the earlier cells whose keys it loads.
+    fn layers(&self) -> Vec<usize> {
+        let mut layers: Vec<usize> = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let layer = self
+                .nodes
+                .iter()
+                .zip(&layers)
+                .filter(|(earlier, _)| !earlier.access.writes.is_disjoint(&node.access.reads))
+                .map(|(_, layer)| layer + 1)
+                .max()
+                .unwrap_or(0);
+            layers.push(layer);
+        }
+        layers
+    }
+
+    /// Boxes for the cells, one row per layer, each followed by arrows for
+    /// the keys its cells pass on.
+    pub fn to_text(&self) -> String {
+        let layers = self.layers();
+        let mut text = String::new();
+        for layer in 0..=layers.iter().copied().max().unwrap_or(0) {
+            let nodes: Vec<&Node> = self
+                .nodes
+                .iter()
+                .zip(&layers)
+                .filter(|(_, l)| **l == layer)
+                .map(|(node, _)| node)
+                .collect();
+            if nodes.is_empty() {
+                continue;
+            }
+
+            let mut rows = [String::new(), String::new(), String::new()];
+            for node in &nodes {
+                let line = "─".repeat(node.cell.chars().count() + 2);
+                let _ = write!(rows[0], "┌{}┐ ", line);
+                let _ = write!(rows[1], "│ {} │ ", node.cell);
+                let _ = write!(rows[2], "└{}┘ ", line);
+            }
+            for row in rows {
+                let _ = writeln!(text, "{}", row.trim_end());
+            }
+
+            for node in &nodes {
+                for key in &node.access.writes {
+                    let readers = self.readers(&node.cell, key);
+                    if !readers.is_empty() {
+                        let _ = writeln!(text, "  {} ─ {} ─▶ {}", node.cell, key, readers.join(", "));
+                    }
+                }
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Graphviz DOT with a box per cell and an ellipse per key.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph cellbook {\n    rankdir=LR;\n    node [shape=box];\n");
+        let keys: BTreeSet<&String> = self
+            .nodes
+            .iter()
+            .flat_map(|node| node.access.reads.iter().chain(&node.access.writes))
+            .collect();
+        for node in &self.nodes {
+            let _ = writeln!(
+                dot,
+                "    {} [label={}];",
+                quote("cell", &node.cell),
+                quote("", &node.cell)
+            );
+        }
+        for key in keys {
+            let _ = writeln!(
+                dot,
+                "    {} [label={}, shape=ellipse];",
+                quote("key", key),
+                quote("", key)
+            );
+        }
+        for node in &self.nodes {
+            for key in &node.access.writes {
+                let _ = writeln!(dot, "    {} -> {};", quote("cell", &node.cell), quote("key", key));
+            }
+            for key in &node.access.reads {
+                let _ = writeln!(dot, "    {} -> {};", quote("key", key), quote("cell", &node.cell));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// DOT identifier for a name, prefixed so cells and keys with the same name differ.
+fn quote(prefix: &str, name: &str) -> String {
+    let name = name.replace('\\', "\\\\").replace('"', "\\\"");
+    if prefix.is_empty() {
+        format!("\"{}\"", name)
+    } else {
+        format!("\"{}:{}\"", prefix, name)
+    }
+}
+
+/// A store or load call naming a key in a cell's source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyUse {
+    pub key: String,
+    /// Macro or method called, such as `load` or `store_as`.
+    pub call: String,
+    /// Type named at the call, as in `load!(prices as Vec<f64>)` or
+    /// `let prices: Vec<f64> = load!(prices)?`, without whitespace. A stored
+    /// variable takes the type of its `let` in the same cell.
+    pub ty: Option<String>,
+    /// Byte offset of the call in the cell's source.
+    pub offset: usize,
+}
+
+impl KeyUse {
+    pub fn reads(&self) -> bool {
+        self.call.starts_with("load") || self.call.starts_with("consume")
+    }
+
+    pub fn writes(&self) -> bool {
+        self.stores() || self.call.starts_with("consume") || self.call == "remove"
+    }
+
+    pub fn stores(&self) -> bool {
+        self.call.starts_with("store")
+    }
+}
+
+/// Keys a cell's source passes to the store and load macros and context methods.
+pub fn declared_access(item: &str) -> Access {
+    let mut access = Access::default();
+    for key_use in key_uses(item) {
+        if key_use.reads() {
+            access.reads.insert(key_use.key.clone());
+        }
+        if key_use.writes() {
+            access.writes.insert(key_use.key);
+        }
+    }
+    access
+}
+
+/// Store and load calls in a cell's source, in order.
+pub fn key_uses(item: &str) -> Vec<KeyUse> {
+    let mut offset = 0;
+    let tokens: Vec<(usize, Token)> = source::tokenize(item)
+        .into_iter()
+        .map(|token| {
+            offset += token.text.len();
+            (offset - token.text.len(), token)
+        })
+        .filter(|(_, t)| !matches!(t.kind, TokenKind::Whitespace | TokenKind::Comment))
+        .collect();
+    let texts: Vec<&str> = tokens.iter().map(|(_, t)| t.text).collect();
+    let mut uses = Vec::new();
+
+    for (i, (offset, token)) in tokens.iter().enumerate() {
+        let (call, key, ty) = match token.kind {
+            // `store!(prices)`, `load!(prices as Vec<f64>)`
+            TokenKind::Macro => {
+                let key = match (tokens.get(i + 1), tokens.get(i + 2)) {
+                    (Some((_, open)), Some((_, key))) if open.text == "(" && key.kind == TokenKind::Ident => {
+                        key.text
+                    }
+                    _ => continue,
+                };
+                let call = token.text.trim_end_matches('!');
+                let ty = if texts.get(i + 3) == Some(&"as") {
+                    type_at(&texts[i + 4..])
+                } else if call.starts_with("store") && texts.get(i + 3) != Some(&"=") {
+                    let_type(&texts[..i], key)
+                } else {
+                    annotated_type(&texts[..i])
+                };
+                (call, key.to_string(), ty)
+            }
+            // `ctx.store("prices", &prices)`, `ctx.load::<Vec<f64>>("prices")`
+            TokenKind::Ident if i > 0 && texts[i - 1] == "." => {
+                let Some(open) = texts[i + 1..]
+                    .iter()
+                    .take(16)
+                    .position(|&t| t == "(" || t == ";")
+                    .map(|n| i + 1 + n)
+                    .filter(|&n| texts[n] == "(")
+                else {
+                    continue;
+                };
+                let key = match tokens.get(open + 1) {
+                    Some((_, key)) if key.kind == TokenKind::String && key.text.starts_with('"') => {
+                        key.text.trim_matches('"')
+                    }
+                    _ => continue,
+                };
+                let ty = if texts[i + 1..open].starts_with(&[":", ":", "<"]) {
+                    type_at(&texts[i + 4..open])
+                } else {
+                    annotated_type(&texts[..i.saturating_sub(2)])
+                };
+                (token.text, key.to_string(), ty)
+            }
+            _ => continue,
+        };
+        uses.push(KeyUse {
+            key,
+            call: call.to_string(),
+            ty,
+            offset: *offset,
+        });
+    }
+    uses
+}
+
+/// The type at the start of `tokens`, up to a `)`, `,` or `>` closing the
+/// surrounding brackets.
+fn type_at(tokens: &[&str]) -> Option<String> {
+    let mut depth = 0usize;
+    let mut ty = String::new();
+    for &token in tokens {
+        match token {
+            "<" | "(" | "[" => depth += 1,
+            ">" | ")" | "]" if depth == 0 => break,
+            ">" | ")" | "]" => depth -= 1,
+            "," | "=" | ";" | "{" if depth == 0 => break,
+            _ => {}
+        }
+        ty.push_str(token);
+    }
+    (!ty.is_empty()).then_some(ty)
+}
+
+/// Type of a `let name: Type =` that `tokens` end with, as in
+/// `let prices: Vec<f64> = load!(prices)`.
+fn annotated_type(tokens: &[&str]) -> Option<String> {
+    let eq = tokens.len().checked_sub(1).filter(|&n| tokens[n] == "=")?;
+    let statement = tokens[..eq].iter().rposition(|&t| t == "let")?;
+    let pattern = &tokens[statement + 1..eq];
+    let pattern = pattern.strip_prefix(&["mut"]).unwrap_or(pattern);
+    match pattern {
+        [_, ":", ty @ ..] if !ty.contains(&";") => type_at(ty),
+        _ => None,
+    }
+}
+
+/// Type of the last `let name: Type` in `tokens`.
+fn let_type(tokens: &[&str], name: &str) -> Option<String> {
+    (0..tokens.len().saturating_sub(2))
+        .rev()
+        .find_map(|i| match tokens[i..i + 3] {
+            ["let" | "mut", n, ":"] if n == name => type_at(&tokens[i + 3..]),
+            _ => None,
+        })
+}
+
+/// Build the notebook and print the graph of keys declared in its source.
+pub async fn print(dot: bool) -> Result<()> {
+    loader::set_project_root()?;
+    let lib_path = loader::find_dylib_path()?;
+    // Quietly, so the output can be piped to Graphviz.
+    watcher::rebuild().await?;
+
+    let lib = LoadedLibrary::load(&lib_path)?;
+    let source = fs::read_to_string("cellbook.rs").unwrap_or_default();
+    let nodes = lib
+        .visible_cells()
+        .iter()
+        .map(|cell| Node {
+            cell: cell.name.clone(),
+            access: source::extract_item(&source, cell.line)
+                .map(declared_access)
+                .unwrap_or_default(),
+        })
+        .collect();
+    let graph = Graph::new(nodes);
+
+    if dot {
+        print!("{}", graph.to_dot());
+    } else {
+        print!("{}", graph.to_text());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(cell: &str, reads: &[&str], writes: &[&str]) -> Node {
+        Node {
+            cell: cell.to_string(),
+            access: Access {
+                reads: reads.iter().map(|k| k.to_string()).collect(),
+                writes: writes.iter().map(|k| k.to_string()).collect(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_declared_access_from_macros_and_methods() {
+        let item = r#"#[cell]
+async fn summary() -> Result<()> {
+    let prices = load!(prices as Vec<f64>)?;
+    let config: Config = ctx.load::<Config>("config")?;
+    let stats = consume!(scratch)?;
+    // store!(commented)
+    store!(mean = prices.iter().sum::<f64>());
+    ctx.store("report", &stats)?;
+    ctx.remove_resource("pool");
+    Ok(())
+}"#;
+        let access = declared_access(item);
+        assert_eq!(
+            access.reads,
+            ["config", "prices", "scratch"].map(String::from).into()
+        );
+        assert_eq!(
+            access.writes,
+            ["mean", "report", "scratch"].map(String::from).into()
+        );
+    }
+
+    #[test]
+    fn test_key_uses_carry_the_named_type() {
+        let item = r#"async fn summary() -> Result<()> {
+    let prices = load!(prices as Vec<f64>)?;
+    let config: Config = ctx.load::<Config>("config")?;
+    let limits: HashMap<String, u32> = load!(limits)?;
+    let total: f64 = prices.iter().sum();
+    store!(total)?;
+    store!(report = build_report())?;
+    Ok(())
+}"#;
+        let types: Vec<(String, Option<String>)> = key_uses(item)
+            .into_iter()
+            .map(|key_use| (key_use.key, key_use.ty))
+            .collect();
+        assert_eq!(
+            types,
+            [
+                ("prices", Some("Vec<f64>")),
+                ("config", Some("Config")),
+                ("limits", Some("HashMap<String,u32>")),
+                ("total", Some("f64")),
+                ("report", None),
+            ]
+            .map(|(key, ty)| (key.to_string(), ty.map(String::from)))
+        );
+    }
+
+    #[test]
+    fn test_text_layers_cells_below_their_inputs() {
+        let graph = Graph::new(vec![
+            node("init", &[], &["config"]),
+            node("load", &["config"], &["prices"]),
+            node("plot", &["prices"], &[]),
+            node("notes", &[], &[]),
+        ]);
+        assert_eq!(graph.layers(), [0, 1, 2, 0]);
+        let text = graph.to_text();
+        assert!(text.starts_with("┌──────┐ ┌───────┐\n│ init │ │ notes │\n"));
+        assert!(text.contains("  init ─ config ─▶ load\n"));
+        assert!(text.contains("  load ─ prices ─▶ plot\n"));
+    }
+
+    #[test]
+    fn test_dot_links_cells_through_keys() {
+        let graph = Graph::new(vec![
+            node("load", &[], &["prices"]),
+            node("plot", &["prices"], &[]),
+        ]);
+        let dot = graph.to_dot();
+        assert!(dot.contains("\"key:prices\" [label=\"prices\", shape=ellipse];"));
+        assert!(dot.contains("\"cell:load\" -> \"key:prices\";"));
+        assert!(dot.contains("\"key:prices\" -> \"cell:plot\";"));
+    }
+}
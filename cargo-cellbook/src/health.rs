@@ -0,0 +1,143 @@
+//! Startup health check: run the init cell before the TUI starts.
+//!
+//! Enabled by the `check_init` setting. A failing init is reported on the
+//! plain terminal with options to retry, edit the notebook, continue into
+//! the TUI anyway or quit.
+
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::terminal::{disable_raw_mode, enable_raw_mode};
+
+use crate::capture::capture_stdout;
+use crate::errors::Result;
+use crate::failure::CellFailure;
+use crate::loader::LoadedLibrary;
+use crate::store::{self, Access, Provenance, StoreDiff};
+use crate::{runs, tui, watcher};
+
+/// A successful init run, shown in the TUI as if it had run there.
+pub struct InitRun {
+    pub stdout: String,
+    pub duration: Duration,
+    pub diff: StoreDiff,
+    pub access: Access,
+}
+
+pub enum HealthCheck {
+    Passed(InitRun),
+    /// Init failed and the user chose to open the TUI anyway.
+    Continue,
+    Quit,
+}
+
+enum Choice {
+    Retry,
+    Edit,
+    Continue,
+    Quit,
+}
+
+/// Run the init cell until it succeeds or the user continues or quits.
+pub async fn check_init(lib: &mut LoadedLibrary) -> Result<HealthCheck> {
+    loop {
+        println!("Running {}...", lib.init().name);
+        let before = store::snapshot();
+        let future = lib.init_future();
+        let start = Instant::now();
+        // Counted as the init cell's first run once the TUI starts.
+        store::set_producer(Some(Provenance::new(&lib.init().name, Some(1))));
+        let name = lib.init().name.clone();
+        let (stdout, result) = store::tracked(
+            &name,
+            capture_stdout(|| async { future.await.map_err(|e| CellFailure::from_error(&*e)) }),
+        )
+        .await;
+        store::set_producer(None);
+        let duration = start.elapsed();
+        let access = store::take_access(&name);
+        let error = result.as_ref().err().map(|e| e.message.as_str());
+        runs::record(&name, duration, error, &access);
+
+        let error = match result {
+            Ok(()) => {
+                let diff = StoreDiff::between(&before, &store::snapshot());
+                return Ok(HealthCheck::Passed(InitRun {
+                    stdout,
+                    duration,
+                    diff,
+                    access,
+                }));
+            }
+            Err(e) => e,
+        };
+
+        println!("\n{} failed:\n", lib.init().name);
+        if !stdout.is_empty() {
+            println!("{}", stdout.trim_end());
+            println!();
+        }
+        println!("{}", error.render(&lib.init().name, lib.init().line));
+
+        loop {
+            match prompt()? {
+                Choice::Retry => {}
+                Choice::Edit => tui::edit_cellbook(Some(lib.init().line))?,
+                Choice::Continue => return Ok(HealthCheck::Continue),
+                Choice::Quit => return Ok(HealthCheck::Quit),
+            }
+            if rebuild(lib).await {
+                break;
+            }
+        }
+    }
+}
+
+/// Rebuild and reload the notebook. Returns `false` if the build failed.
+async fn rebuild(lib: &mut LoadedLibrary) -> bool {
+    if let Err(e) = watcher::initial_build().await {
+        println!("{}\n", e);
+        return false;
+    }
+    // Tasks and resources from the failed run reference the old library.
+    lib.release().await.report(&mut std::io::stdout());
+    match lib.reload() {
+        Ok(()) => true,
+        Err(e) => {
+            println!("Error: {}\n", e);
+            false
+        }
+    }
+}
+
+fn prompt() -> Result<Choice> {
+    print!("[r] Retry  [e] Edit  [c] Continue to TUI  [q] Quit ");
+    std::io::stdout().flush()?;
+
+    enable_raw_mode()?;
+    let choice = read_choice();
+    let _ = disable_raw_mode();
+    println!();
+    choice
+}
+
+fn read_choice() -> Result<Choice> {
+    loop {
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        let choice = match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => Choice::Quit,
+            KeyCode::Char('r') | KeyCode::Enter => Choice::Retry,
+            KeyCode::Char('e') => Choice::Edit,
+            KeyCode::Char('c') => Choice::Continue,
+            KeyCode::Char('q') | KeyCode::Esc => Choice::Quit,
+            _ => continue,
+        };
+        return Ok(choice);
+    }
+}
@@ -0,0 +1,162 @@
+//! Hex dumps of serialized store values.
+//!
+//! The host only sees postcard bytes, so this is the closest it can get
+//! to showing a value when debugging serialization issues.
+
+use std::fmt::Write;
+
+/// Number of leading bytes included in a dump.
+pub const MAX_DUMP_BYTES: usize = 4096;
+
+const BYTES_PER_LINE: usize = 16;
+
+/// Describe a stored value of `len` bytes: origin, length, entropy hint, the type's
+/// registered schema and a hex dump of its first bytes. `bytes` may hold only the
+/// first bytes of values kept on disk.
+pub fn describe(
+    key: &str,
+    type_name: &str,
+    origin: Option<&str>,
+    schema: Option<&str>,
+    bytes: &[u8],
+    len: u64,
+) -> String {
+    let mut out = String::new();
+    let entropy = entropy(bytes);
+    let _ = writeln!(out, "Key:     {}", key);
+    let _ = writeln!(out, "Type:    {}", type_name);
+    let _ = writeln!(
+        out,
+        "Origin:  {}",
+        origin.unwrap_or("unknown (stored outside a cell run)")
+    );
+    let _ = writeln!(out, "Length:  {} bytes", len);
+    let _ = writeln!(
+        out,
+        "Entropy: {:.2} bits/byte ({})",
+        entropy,
+        entropy_hint(entropy, bytes.len())
+    );
+    if let Some(schema) = schema {
+        out.push_str("Schema:\n");
+        for line in schema.lines() {
+            let _ = writeln!(out, "  {}", line);
+        }
+    }
+    out.push('\n');
+
+    let shown = &bytes[..bytes.len().min(MAX_DUMP_BYTES)];
+    out.push_str(&dump(shown));
+    if (shown.len() as u64) < len {
+        let _ = writeln!(out, "... {} more bytes", len - shown.len() as u64);
+    }
+    out
+}
+
+/// Format bytes as offset, hex and ASCII columns.
+pub fn dump(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for (i, chunk) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+        let _ = write!(out, "{:08x}  ", i * BYTES_PER_LINE);
+        for j in 0..BYTES_PER_LINE {
+            match chunk.get(j) {
+                Some(b) => {
+                    let _ = write!(out, "{:02x} ", b);
+                }
+                None => out.push_str("   "),
+            }
+            if j == BYTES_PER_LINE / 2 - 1 {
+                out.push(' ');
+            }
+        }
+        out.push(' ');
+        out.extend(chunk.iter().map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            }
+        }));
+        out.push('\n');
+    }
+    out
+}
+
+/// Shannon entropy in bits per byte.
+fn entropy(bytes: &[u8]) -> f64 {
+    if bytes.is_empty() {
+        return 0.0;
+    }
+    let mut counts = [0usize; 256];
+    for &b in bytes {
+        counts[b as usize] += 1;
+    }
+    let len = bytes.len() as f64;
+    counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+fn entropy_hint(entropy: f64, len: usize) -> &'static str {
+    match entropy {
+        _ if len < 16 => "too short to judge",
+        e if e < 1.0 => "mostly repeated bytes",
+        e if e < 5.0 => "structured data or text",
+        e if e < 7.5 => "mixed binary data",
+        _ => "looks compressed or random",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dump_formats_offsets_hex_and_ascii() {
+        let dump = dump(b"hello, cellbook!\x00\x01");
+        let lines: Vec<_> = dump.lines().collect();
+        assert_eq!(
+            lines[0],
+            "00000000  68 65 6c 6c 6f 2c 20 63  65 6c 6c 62 6f 6f 6b 21  hello, cellbook!"
+        );
+        assert!(lines[1].starts_with("00000010  00 01 "));
+        assert!(lines[1].ends_with(" .."));
+    }
+
+    #[test]
+    fn test_entropy() {
+        assert_eq!(entropy(&[]), 0.0);
+        assert_eq!(entropy(&[7; 64]), 0.0);
+        let all: Vec<u8> = (0..=255).collect();
+        assert!((entropy(&all) - 8.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_describe_truncates_long_values() {
+        let bytes = vec![0u8; MAX_DUMP_BYTES + 10];
+        let text = describe(
+            "data",
+            "Vec<u8>",
+            Some("load, run 2"),
+            None,
+            &bytes,
+            bytes.len() as u64,
+        );
+        assert!(text.contains("Origin:  load, run 2"));
+        assert!(text.contains("Length:  4106 bytes"));
+        assert!(text.contains("mostly repeated bytes"));
+        assert!(text.ends_with("... 10 more bytes\n"));
+    }
+
+    #[test]
+    fn test_describe_indents_schema() {
+        let schema = "struct Stats {\n    mean: f64,\n}";
+        let text = describe("stats", "app::Stats#v1", None, Some(schema), &[0; 8], 8);
+        assert!(text.contains("Schema:\n  struct Stats {\n      mean: f64,\n  }\n\n00000000"));
+    }
+}
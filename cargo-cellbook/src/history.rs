@@ -0,0 +1,376 @@
+//! History of store changes, kept as JSON lines in `.cellbook/history.jsonl`.
+//!
+//! Every store and removal is appended with its time and the cell run that made
+//! it, across sessions, so `cargo cellbook store history` can tell who last
+//! overwrote a key and `cargo cellbook store list` which keys the last session
+//! left stored. Changes made from the interface have no cell.
+
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{Error, Result};
+use crate::run_info;
+use crate::store::{self, Provenance};
+
+pub const HISTORY_FILE: &str = ".cellbook/history.jsonl";
+
+/// Size at which the history file is moved aside to `history.jsonl.1`, replacing
+/// the one moved aside before, so at most twice this is kept.
+const MAX_FILE_BYTES: u64 = 8 * 1024 * 1024;
+
+/// Changes shown in the TUI's history popup.
+const POPUP_ENTRIES: usize = 50;
+
+/// How a key changed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Store,
+    Remove,
+    /// Removed by the store quota.
+    Evict,
+    /// Removed once its lifetime passed.
+    Expire,
+}
+
+impl Change {
+    fn label(self) -> &'static str {
+        match self {
+            Change::Store => "store",
+            Change::Remove => "remove",
+            Change::Evict => "evict",
+            Change::Expire => "expire",
+        }
+    }
+}
+
+/// One change of a key.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub key: String,
+    pub change: Change,
+    /// Seconds since the Unix epoch.
+    pub time: u64,
+    /// Cell whose run made the change, if a cell made it.
+    pub cell: Option<String>,
+    pub run: Option<u32>,
+    /// Type of a stored value.
+    pub type_name: Option<String>,
+    /// Serialized size of a stored value.
+    pub bytes: Option<u64>,
+}
+
+/// Absolute path of the history file, set while recording.
+static PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Start recording store changes into the project in the current directory.
+pub fn start() {
+    *PATH.lock() = std::env::current_dir().ok().map(|dir| dir.join(HISTORY_FILE));
+}
+
+/// Append a change of `key` made by `by`, if recording. `stored` is the type and
+/// size of a stored value.
+pub fn record(key: &str, change: Change, by: Option<&Provenance>, stored: Option<(&str, u64)>) {
+    let Some(path) = PATH.lock().clone() else {
+        return;
+    };
+    let entry = Entry {
+        key: key.to_string(),
+        change,
+        time: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        cell: by.map(|p| p.cell.clone()),
+        run: by.and_then(|p| p.run),
+        type_name: stored.map(|(type_name, _)| type_name.to_string()),
+        bytes: stored.map(|(_, bytes)| bytes),
+    };
+    // History is best effort and never fails a store.
+    let _ = append(&path, &entry, MAX_FILE_BYTES);
+}
+
+/// Where the history file is moved once it reaches `max_bytes`.
+fn rotated(path: &Path) -> PathBuf {
+    path.with_extension("jsonl.1")
+}
+
+fn append(path: &Path, entry: &Entry, max_bytes: u64) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    if fs::metadata(path).is_ok_and(|m| m.len() >= max_bytes) {
+        fs::rename(path, rotated(path))?;
+    }
+    let mut line = serde_json::to_string(entry).map_err(|e| Error::History(e.to_string()))?;
+    line.push('\n');
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// Recorded changes, oldest first, including those moved aside. Lines that don't
+/// parse are skipped.
+fn read(path: &Path) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for path in [rotated(path), path.to_path_buf()] {
+        match fs::read_to_string(&path) {
+            Ok(content) => entries.extend(parse(&content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(entries)
+}
+
+fn parse(content: &str) -> Vec<Entry> {
+    content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// Whether `key` matches `pattern`, where `*` matches any run of characters.
+fn matches(pattern: &str, key: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = key.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// Who made a change: `load (run 3)`, or `interface` for changes made outside cells.
+fn describe_author(entry: &Entry) -> String {
+    match (&entry.cell, entry.run) {
+        (Some(cell), Some(run)) => format!("{} (run {})", cell, run),
+        (Some(cell), None) => cell.clone(),
+        (None, _) => "interface".to_string(),
+    }
+}
+
+fn format_entry(entry: &Entry) -> String {
+    let mut line = format!(
+        "{}  {:<6}  {}  by {}",
+        run_info::format_utc(entry.time),
+        entry.change.label(),
+        entry.key,
+        describe_author(entry)
+    );
+    if let (Some(type_name), Some(bytes)) = (&entry.type_name, entry.bytes) {
+        line.push_str(&format!("  {}, {}", type_name, store::format_bytes(bytes)));
+    }
+    line
+}
+
+/// The last change of `key` followed by its recent changes, newest first.
+fn format_key(key: &str, entries: &[Entry]) -> String {
+    let changes: Vec<&Entry> = entries.iter().rev().filter(|e| e.key == key).collect();
+    let Some(last) = changes.first() else {
+        return format!("No changes of '{}' recorded in {}\n", key, HISTORY_FILE);
+    };
+    let mut out = format!(
+        "Last changed {} by {} ({})\n\n",
+        run_info::format_utc(last.time),
+        describe_author(last),
+        last.change.label()
+    );
+    for entry in changes.iter().take(POPUP_ENTRIES) {
+        out.push_str(&format_entry(entry));
+        out.push('\n');
+    }
+    if changes.len() > POPUP_ENTRIES {
+        out.push_str(&format!(
+            "... {} earlier changes, see `cargo cellbook store history {}`\n",
+            changes.len() - POPUP_ENTRIES,
+            key
+        ));
+    }
+    out
+}
+
+/// History of `key` for the TUI's popup.
+pub fn describe_key(key: &str) -> String {
+    let path = PATH.lock().clone().unwrap_or_else(|| PathBuf::from(HISTORY_FILE));
+    match read(&path) {
+        Ok(entries) => format_key(key, &entries),
+        Err(e) => format!("Can't read {}: {}\n", HISTORY_FILE, e),
+    }
+}
+
+/// Print recorded changes, oldest first, of keys matching `key` and made by `cell`,
+/// as a JSON array if `json` is set.
+pub fn show(key: Option<&str>, cell: Option<&str>, json: bool) -> Result<()> {
+    let entries: Vec<Entry> = read(Path::new(HISTORY_FILE))?
+        .into_iter()
+        .filter(|e| key.is_none_or(|pattern| matches(pattern, &e.key)))
+        .filter(|e| cell.is_none_or(|cell| e.cell.as_deref() == Some(cell)))
+        .collect();
+    if json {
+        let json = serde_json::to_string_pretty(&entries).map_err(|e| Error::History(e.to_string()))?;
+        println!("{}", json);
+        return Ok(());
+    }
+    if entries.is_empty() {
+        println!("No matching changes recorded in {}", HISTORY_FILE);
+        return Ok(());
+    }
+    for entry in &entries {
+        println!("{}", format_entry(entry));
+    }
+    Ok(())
+}
+
+/// The last change of each key whose last change stored it, by key.
+fn stored(entries: &[Entry]) -> Vec<&Entry> {
+    let mut last: HashMap<&str, &Entry> = HashMap::new();
+    for entry in entries {
+        last.insert(&entry.key, entry);
+    }
+    let mut stored: Vec<&Entry> = last.into_values().filter(|e| e.change == Change::Store).collect();
+    stored.sort_by(|a, b| a.key.cmp(&b.key));
+    stored
+}
+
+/// Print the keys stored as of the last recorded change, with the type, size and
+/// cell run of their last store, as a JSON array if `json` is set.
+pub fn list(json: bool) -> Result<()> {
+    let entries = read(Path::new(HISTORY_FILE))?;
+    let stored = stored(&entries);
+    if json {
+        let json = serde_json::to_string_pretty(&stored).map_err(|e| Error::History(e.to_string()))?;
+        println!("{}", json);
+        return Ok(());
+    }
+    if stored.is_empty() {
+        println!("No stored keys recorded in {}", HISTORY_FILE);
+        return Ok(());
+    }
+    for entry in stored {
+        println!(
+            "{}: {} ({})  by {}, {}",
+            entry.key,
+            entry.type_name.as_deref().unwrap_or("?"),
+            store::format_bytes(entry.bytes.unwrap_or(0)),
+            describe_author(entry),
+            run_info::format_utc(entry.time)
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(key: &str, change: Change, cell: Option<&str>, time: u64) -> Entry {
+        Entry {
+            key: key.to_string(),
+            change,
+            time,
+            cell: cell.map(String::from),
+            run: cell.map(|_| 2),
+            type_name: (change == Change::Store).then(|| "Vec<f64>".to_string()),
+            bytes: (change == Change::Store).then_some(2048),
+        }
+    }
+
+    #[test]
+    fn test_key_patterns() {
+        assert!(matches("prices", "prices"));
+        assert!(!matches("prices", "prices_2024"));
+        assert!(matches("prices*", "prices_2024"));
+        assert!(matches("*_2024", "prices_2024"));
+        assert!(matches("p*_*4", "prices_2024"));
+        assert!(!matches("p*_*5", "prices_2024"));
+        assert!(matches("*", ""));
+        assert!(!matches("a*a", "a"));
+    }
+
+    #[test]
+    fn test_format_key_shows_last_change_first() {
+        let entries = vec![
+            entry("prices", Change::Store, Some("load"), 1_700_000_000),
+            entry("other", Change::Store, Some("load"), 1_700_000_001),
+            entry("prices", Change::Store, Some("clean"), 1_700_000_002),
+            entry("prices", Change::Remove, None, 1_700_000_003),
+        ];
+        let content: String = entries
+            .iter()
+            .map(|e| serde_json::to_string(e).unwrap() + "\n")
+            .collect();
+        assert_eq!(parse(&(content + "not json\n")), entries);
+
+        let out = format_key("prices", &entries);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(
+            lines[0],
+            "Last changed 2023-11-14 22:13:23 UTC by interface (remove)"
+        );
+        assert_eq!(lines[2], "2023-11-14 22:13:23 UTC  remove  prices  by interface");
+        assert_eq!(
+            lines[3],
+            "2023-11-14 22:13:22 UTC  store   prices  by clean (run 2)  Vec<f64>, 2.0 KB"
+        );
+        assert_eq!(lines.len(), 5);
+        assert!(format_key("missing", &entries).starts_with("No changes of 'missing'"));
+    }
+
+    #[test]
+    fn test_full_history_file_is_moved_aside() {
+        let dir = std::env::temp_dir().join(format!("cellbook-history-{}", std::process::id()));
+        let path = dir.join("history.jsonl");
+        let line_bytes = serde_json::to_string(&entry("a", Change::Store, None, 1))
+            .unwrap()
+            .len() as u64
+            + 1;
+        for (time, key) in ["a", "b", "c", "d", "e"].into_iter().enumerate() {
+            append(
+                &path,
+                &entry(key, Change::Store, None, time as u64),
+                2 * line_bytes,
+            )
+            .unwrap();
+        }
+
+        // The file holding a and b was replaced by the one holding c and d.
+        let keys: Vec<String> = read(&path).unwrap().into_iter().map(|e| e.key).collect();
+        assert_eq!(keys, ["c", "d", "e"]);
+        assert!(rotated(&path).ends_with("history.jsonl.1"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_stored_keys_are_those_last_stored() {
+        let entries = vec![
+            entry("prices", Change::Store, Some("load"), 1),
+            entry("temp", Change::Store, Some("load"), 2),
+            entry("prices", Change::Store, Some("clean"), 3),
+            entry("temp", Change::Expire, None, 4),
+            entry("model", Change::Store, Some("fit"), 5),
+        ];
+        let stored = stored(&entries);
+        let keys: Vec<&str> = stored.iter().map(|e| e.key.as_str()).collect();
+        assert_eq!(keys, ["model", "prices"]);
+        assert_eq!(stored[1].cell.as_deref(), Some("clean"));
+    }
+}
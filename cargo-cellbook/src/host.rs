@@ -0,0 +1,332 @@
+//! Host callbacks passed to notebook code with each cell.
+//!
+//! Every callback is an `extern "C"` function over [`ffi`](crate::ffi) values,
+//! so notebooks built by another compiler than the runner can call them.
+//! They forward to the store, tasks, resources, artifacts and prompts of the
+//! runner, or to the runner's connection in an isolation worker.
+
+use std::path::Path;
+use std::time::Duration;
+
+use crate::ffi::{FfiFuture, FfiHandle, FfiReader, FfiResource, FfiSlice, FfiStream, FfiString, FfiValue};
+use crate::store::SharedBytes;
+use crate::{artifacts, isolation, loader, prompt, resources, store, tasks};
+
+/// Host callbacks passed to each cell.
+///
+/// Mirrors `cellbook::context::HostFns`; field order and types must match.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct HostFns {
+    store_fn: unsafe extern "C" fn(FfiSlice, FfiSlice, FfiSlice) -> FfiString,
+    load_fn: unsafe extern "C" fn(FfiSlice) -> FfiValue,
+    remove_fn: unsafe extern "C" fn(FfiSlice) -> bool,
+    list_fn: unsafe extern "C" fn() -> FfiString,
+    spawn_fn: unsafe extern "C" fn(FfiSlice, FfiFuture),
+    get_resource_fn: unsafe extern "C" fn(FfiSlice) -> FfiResource,
+    put_resource_fn: unsafe extern "C" fn(FfiSlice, FfiHandle, FfiSlice),
+    remove_resource_fn: unsafe extern "C" fn(FfiSlice) -> bool,
+    artifact_fn: unsafe extern "C" fn(FfiSlice, FfiSlice, bool),
+    expire_fn: unsafe extern "C" fn(FfiSlice, u64),
+    get_shared_fn: unsafe extern "C" fn(FfiSlice) -> FfiResource,
+    put_shared_fn: unsafe extern "C" fn(FfiSlice, FfiHandle, FfiSlice),
+    schema_fn: unsafe extern "C" fn(FfiSlice, FfiSlice),
+    prompt_fn: unsafe extern "C" fn(FfiSlice) -> FfiFuture,
+    store_stream_fn: Option<unsafe extern "C" fn(FfiSlice, *mut FfiReader, FfiSlice, *mut u64) -> FfiString>,
+    load_stream_fn: Option<unsafe extern "C" fn(FfiSlice) -> FfiStream>,
+    load_bytes_fn: Option<unsafe extern "C" fn(FfiSlice) -> FfiValue>,
+    working_dir: FfiSlice,
+    var_fn: Option<unsafe extern "C" fn(FfiSlice) -> FfiString>,
+}
+
+// Layout of ABI version 9, asserted the same way in `cellbook::context`.
+crate::ffi::assert_layout!(
+    HostFns,
+    20,
+    {
+        store_fn: 0,
+        load_fn: 1,
+        remove_fn: 2,
+        list_fn: 3,
+        spawn_fn: 4,
+        get_resource_fn: 5,
+        put_resource_fn: 6,
+        remove_resource_fn: 7,
+        artifact_fn: 8,
+        expire_fn: 9,
+        get_shared_fn: 10,
+        put_shared_fn: 11,
+        schema_fn: 12,
+        prompt_fn: 13,
+        store_stream_fn: 14,
+        load_stream_fn: 15,
+        load_bytes_fn: 16,
+        working_dir: 17,
+        var_fn: 19,
+    }
+);
+
+impl HostFns {
+    pub fn new() -> Self {
+        if isolation::is_worker() {
+            return Self::worker();
+        }
+        Self {
+            store_fn: store_value,
+            load_fn: load_value,
+            remove_fn: remove_value,
+            list_fn: list,
+            spawn_fn: spawn,
+            get_resource_fn: get_resource,
+            put_resource_fn: put_resource,
+            remove_resource_fn: remove_resource,
+            artifact_fn: register_artifact,
+            expire_fn: expire,
+            get_shared_fn: get_shared,
+            put_shared_fn: put_shared,
+            schema_fn: register_schema,
+            prompt_fn: request_line,
+            store_stream_fn: Some(store_stream),
+            load_stream_fn: Some(load_stream),
+            load_bytes_fn: Some(load_bytes),
+            working_dir: FfiSlice::NONE,
+            var_fn: Some(notebook_var),
+        }
+    }
+
+    /// Callbacks for a cell running in an isolation worker. Store access,
+    /// artifacts and prompts go to the runner; tasks and resources stay in the worker.
+    fn worker() -> Self {
+        Self {
+            store_fn: worker::store_value,
+            load_fn: worker::load_value,
+            remove_fn: worker::remove_value,
+            list_fn: worker::list,
+            spawn_fn: spawn,
+            get_resource_fn: get_resource,
+            put_resource_fn: put_resource,
+            remove_resource_fn: remove_resource,
+            artifact_fn: worker::register_artifact,
+            expire_fn: worker::expire,
+            // The worker has no store versions, so every shared load deserializes.
+            get_shared_fn: get_shared,
+            put_shared_fn: put_shared,
+            schema_fn: worker::register_schema,
+            prompt_fn: worker::request_line,
+            store_stream_fn: Some(worker::store_stream),
+            // Streams and mapped values are loaded whole through `load_fn`.
+            load_stream_fn: None,
+            load_bytes_fn: None,
+            working_dir: FfiSlice::NONE,
+            var_fn: Some(notebook_var),
+        }
+    }
+
+    /// The callbacks with `dir` as the cell's absolute working directory.
+    pub fn in_dir(self, dir: Option<&'static str>) -> Self {
+        Self {
+            working_dir: dir.into(),
+            ..self
+        }
+    }
+}
+
+/// Bytes lent to notebook code by [`store::load_bytes`].
+struct Shared(SharedBytes);
+
+impl AsRef<[u8]> for Shared {
+    fn as_ref(&self) -> &[u8] {
+        (*self.0).as_ref()
+    }
+}
+
+fn stored(result: std::result::Result<(), String>) -> FfiString {
+    result.err().into()
+}
+
+fn streamed(result: std::result::Result<u64, String>, len: *mut u64) -> FfiString {
+    match result {
+        Ok(read) => {
+            unsafe { *len = read };
+            FfiString::none()
+        }
+        Err(e) => FfiString::new(e),
+    }
+}
+
+fn keys(keys: Vec<(String, String)>) -> FfiString {
+    FfiString::new(serde_json::to_string(&keys).unwrap_or_default())
+}
+
+unsafe extern "C" fn store_value(key: FfiSlice, bytes: FfiSlice, type_name: FfiSlice) -> FfiString {
+    let (key, bytes, type_name) = unsafe { (key.to_str(), bytes.as_bytes(), type_name.to_str()) };
+    stored(store::store_value(&key, bytes.to_vec(), &type_name))
+}
+
+unsafe extern "C" fn load_value(key: FfiSlice) -> FfiValue {
+    FfiValue::new(store::load_value(&unsafe { key.to_str() }))
+}
+
+unsafe extern "C" fn remove_value(key: FfiSlice) -> bool {
+    store::remove_value(&unsafe { key.to_str() }).is_some()
+}
+
+unsafe extern "C" fn list() -> FfiString {
+    keys(store::list())
+}
+
+unsafe extern "C" fn spawn(name: FfiSlice, future: FfiFuture) {
+    tasks::spawn(
+        &unsafe { name.to_str() },
+        Box::pin(async move { future.await.map(drop) }),
+    );
+}
+
+unsafe extern "C" fn get_resource(key: FfiSlice) -> FfiResource {
+    FfiResource::new(resources::get(&unsafe { key.to_str() }))
+}
+
+unsafe extern "C" fn put_resource(key: FfiSlice, value: FfiHandle, type_name: FfiSlice) {
+    resources::put(&unsafe { key.to_str() }, value, &unsafe { type_name.to_str() });
+}
+
+unsafe extern "C" fn remove_resource(key: FfiSlice) -> bool {
+    resources::remove(&unsafe { key.to_str() }).is_some()
+}
+
+unsafe extern "C" fn register_artifact(path: FfiSlice, mime: FfiSlice, temporary: bool) {
+    let (path, mime) = unsafe { (path.to_str(), mime.to_str()) };
+    artifacts::register(Path::new(&*path), &mime, temporary);
+}
+
+unsafe extern "C" fn expire(key: FfiSlice, ttl_ms: u64) {
+    store::set_expiry(&unsafe { key.to_str() }, Duration::from_millis(ttl_ms));
+}
+
+unsafe extern "C" fn get_shared(key: FfiSlice) -> FfiResource {
+    FfiResource::new(resources::get_shared(&unsafe { key.to_str() }))
+}
+
+unsafe extern "C" fn put_shared(key: FfiSlice, value: FfiHandle, type_name: FfiSlice) {
+    resources::put_shared(&unsafe { key.to_str() }, value, &unsafe { type_name.to_str() });
+}
+
+unsafe extern "C" fn register_schema(type_name: FfiSlice, schema: FfiSlice) {
+    store::register_schema(&unsafe { type_name.to_str() }, &unsafe { schema.to_str() });
+}
+
+unsafe extern "C" fn request_line(message: FfiSlice) -> FfiFuture {
+    FfiFuture::new(prompt::request(&unsafe { message.to_str() }))
+}
+
+unsafe extern "C" fn store_stream(
+    key: FfiSlice,
+    reader: *mut FfiReader,
+    type_name: FfiSlice,
+    len: *mut u64,
+) -> FfiString {
+    let (key, type_name) = unsafe { (key.to_str(), type_name.to_str()) };
+    streamed(store::store_stream(&key, unsafe { &mut *reader }, &type_name), len)
+}
+
+unsafe extern "C" fn load_stream(key: FfiSlice) -> FfiStream {
+    FfiStream::new(store::load_stream(&unsafe { key.to_str() }))
+}
+
+unsafe extern "C" fn load_bytes(key: FfiSlice) -> FfiValue {
+    let value = store::load_bytes(&unsafe { key.to_str() });
+    FfiValue::new(value.map(|(bytes, type_name)| (Shared(bytes), type_name)))
+}
+
+unsafe extern "C" fn notebook_var(name: FfiSlice) -> FfiString {
+    loader::notebook_var(&unsafe { name.to_str() }).into()
+}
+
+/// Callbacks of an isolation worker that go to the runner.
+mod worker {
+    use super::*;
+
+    pub unsafe extern "C" fn store_value(key: FfiSlice, bytes: FfiSlice, type_name: FfiSlice) -> FfiString {
+        let (key, bytes, type_name) = unsafe { (key.to_str(), bytes.as_bytes(), type_name.to_str()) };
+        stored(isolation::store_value(&key, bytes.to_vec(), &type_name))
+    }
+
+    pub unsafe extern "C" fn load_value(key: FfiSlice) -> FfiValue {
+        FfiValue::new(isolation::load_value(&unsafe { key.to_str() }))
+    }
+
+    pub unsafe extern "C" fn remove_value(key: FfiSlice) -> bool {
+        isolation::remove_value(&unsafe { key.to_str() }).is_some()
+    }
+
+    pub unsafe extern "C" fn list() -> FfiString {
+        keys(isolation::list())
+    }
+
+    pub unsafe extern "C" fn register_artifact(path: FfiSlice, mime: FfiSlice, temporary: bool) {
+        let (path, mime) = unsafe { (path.to_str(), mime.to_str()) };
+        isolation::register_artifact(Path::new(&*path), &mime, temporary);
+    }
+
+    pub unsafe extern "C" fn expire(key: FfiSlice, ttl_ms: u64) {
+        isolation::expire(&unsafe { key.to_str() }, Duration::from_millis(ttl_ms));
+    }
+
+    pub unsafe extern "C" fn register_schema(type_name: FfiSlice, schema: FfiSlice) {
+        isolation::register_schema(&unsafe { type_name.to_str() }, &unsafe { schema.to_str() });
+    }
+
+    pub unsafe extern "C" fn request_line(message: FfiSlice) -> FfiFuture {
+        FfiFuture::new(isolation::prompt(&unsafe { message.to_str() }))
+    }
+
+    pub unsafe extern "C" fn store_stream(
+        key: FfiSlice,
+        reader: *mut FfiReader,
+        type_name: FfiSlice,
+        len: *mut u64,
+    ) -> FfiString {
+        let (key, type_name) = unsafe { (key.to_str(), type_name.to_str()) };
+        streamed(isolation::store_stream(&key, unsafe { &mut *reader }, &type_name), len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    #[test]
+    fn test_store_callbacks_round_trip() {
+        let key = FfiSlice::from("host_fns_test");
+        let error = unsafe { store_value(key, FfiSlice::from("abc"), FfiSlice::from("bytes")) };
+        assert_eq!(error.into_string(), None);
+
+        let value = unsafe { load_value(key) };
+        assert_eq!(value.type_name.into_string().as_deref(), Some("bytes"));
+        let listed = unsafe { list() }.into_string().unwrap();
+        assert!(listed.contains(r#"["host_fns_test","bytes"]"#), "{}", listed);
+
+        assert!(unsafe { remove_value(key) });
+        assert!(!unsafe { remove_value(key) });
+        assert_eq!(unsafe { load_value(key) }.type_name.into_string(), None);
+    }
+
+    #[test]
+    fn test_streams_round_trip() {
+        let key = FfiSlice::from("host_fns_stream_test");
+        let mut reader = FfiReader::new(Box::new(&b"streamed"[..]));
+        let mut len = 0;
+        let error = unsafe { store_stream(key, &mut reader, FfiSlice::from("stream"), &mut len) };
+        assert_eq!(error.into_string(), None);
+        assert_eq!(len, 8);
+
+        let mut stream = unsafe { load_stream(key) };
+        let mut read = String::new();
+        stream.reader.read_to_string(&mut read).unwrap();
+        assert_eq!(read, "streamed");
+        assert_eq!(stream.type_name.into_string().as_deref(), Some("stream"));
+        store::remove_value("host_fns_stream_test");
+    }
+}
@@ -0,0 +1,543 @@
+//! Running cells in a worker process, so a segfault or abort in user code
+//! (FFI, native dependencies) fails the cell instead of killing the runner.
+//!
+//! The runner starts `cargo cellbook worker` with the library path and cell
+//! name. The worker loads the library itself and reaches the runner's store
+//! over a loopback TCP connection carrying length-prefixed postcard frames.
+//! The connection opens with a token the runner passes in the worker's
+//! environment, so other local processes can't reach the store through it.
+//! Prompts for input are answered by the runner's interface. Streamed values
+//! cross the connection in chunks, so neither process holds a whole one in memory.
+//! Resources and background tasks are local to the worker and end with it.
+
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::process::Command;
+use tokio::sync::mpsc;
+
+use crate::errors::{Error, Result};
+use crate::failure::CellFailure;
+use crate::loader::{self, LoadedLibrary};
+use crate::{artifacts, prompt, store, util};
+
+/// Worker to runner.
+#[derive(Serialize, Deserialize)]
+enum Request {
+    Store {
+        key: String,
+        bytes: Vec<u8>,
+        type_name: String,
+    },
+    /// Start of a value from `CellContext::store_stream`, spilled by the runner
+    /// if large. Its bytes follow in `StreamChunk`s up to a `StreamEnd`.
+    StoreStream {
+        key: String,
+        type_name: String,
+    },
+    /// Part of the streamed value. Not answered.
+    StreamChunk(Vec<u8>),
+    /// End of the streamed value, with the error if reading it failed.
+    StreamEnd(Option<String>),
+    Load(String),
+    Remove(String),
+    List,
+    Expire {
+        key: String,
+        ttl: Duration,
+    },
+    Artifact {
+        path: PathBuf,
+        mime: String,
+        temporary: bool,
+    },
+    Schema {
+        type_name: String,
+        schema: String,
+    },
+    Prompt(String),
+    /// The cell finished. Not answered.
+    Finished(std::result::Result<(), CellFailure>),
+}
+
+/// Runner to worker.
+#[derive(Serialize, Deserialize)]
+enum Response {
+    Stored(std::result::Result<(), String>),
+    Streamed(std::result::Result<u64, String>),
+    Value(Option<(Vec<u8>, String)>),
+    Keys(Vec<(String, String)>),
+    Line(std::result::Result<String, String>),
+    Done,
+}
+
+/// Environment variable passing the connection token to the worker.
+const TOKEN_VAR: &str = "CELLBOOK_WORKER_TOKEN";
+
+/// How long a connection may take to send the token before it is dropped.
+const TOKEN_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn encode<T: Serialize>(message: &T) -> Vec<u8> {
+    let body = postcard::to_stdvec(message).expect("IPC messages are serializable");
+    let mut frame = (body.len() as u32).to_le_bytes().to_vec();
+    frame.extend(body);
+    frame
+}
+
+/// Run `cell` from the library at `lib_path` in a worker process.
+/// Returns the worker's stdout and the cell result.
+pub async fn run(lib_path: PathBuf, cell: String) -> (String, std::result::Result<(), CellFailure>) {
+    match run_worker(&lib_path, &cell).await {
+        Ok(output) => output,
+        Err(e) => (
+            String::new(),
+            Err(CellFailure::from_message(format!(
+                "Failed to start worker: {}",
+                e
+            ))),
+        ),
+    }
+}
+
+async fn run_worker(lib_path: &Path, cell: &str) -> Result<(String, std::result::Result<(), CellFailure>)> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+    let port = listener.local_addr()?.port();
+    let token = util::random_token()?;
+
+    let mut child = Command::new(std::env::current_exe()?)
+        .args(["cellbook", "worker", "--port"])
+        .arg(port.to_string())
+        .arg("--cell")
+        .arg(cell)
+        .args(loader::sandbox_cells().then_some("--sandbox"))
+        .arg(lib_path)
+        .envs(loader::notebook_vars())
+        .env(TOKEN_VAR, &token)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // Aborting the cell (reload, quit) kills the worker.
+        .kill_on_drop(true)
+        .spawn()?;
+    let stdout = tokio::spawn(read_all(child.stdout.take()));
+    let stderr = tokio::spawn(read_all(child.stderr.take()));
+
+    let finished = tokio::select! {
+        accepted = accept_worker(&listener, &token) => serve(accepted?).await,
+        // Exited before connecting.
+        _ = child.wait() => None,
+    };
+    let status = child.wait().await?;
+    let stdout = stdout.await.unwrap_or_default();
+    let stderr = stderr.await.unwrap_or_default();
+
+    let result = finished.unwrap_or_else(|| Err(CellFailure::from_message(crash_message(status, &stderr))));
+    Ok((stdout, result))
+}
+
+/// Accept the first connection that opens with `token`, dropping any other.
+async fn accept_worker(listener: &TcpListener, token: &str) -> Result<tokio::net::TcpStream> {
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        let mut received = vec![0; token.len()];
+        let read = tokio::time::timeout(TOKEN_TIMEOUT, stream.read_exact(&mut received)).await;
+        if matches!(read, Ok(Ok(_))) && received == token.as_bytes() {
+            return Ok(stream);
+        }
+    }
+}
+
+async fn read_all(pipe: Option<impl AsyncRead + Unpin>) -> String {
+    let mut bytes = Vec::new();
+    if let Some(mut pipe) = pipe {
+        let _ = pipe.read_to_end(&mut bytes).await;
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Size of the chunks a streamed value is sent in.
+const CHUNK_BYTES: usize = 64 * 1024;
+
+async fn read_request(stream: &mut tokio::net::TcpStream) -> Option<Request> {
+    let len = stream.read_u32_le().await.ok()?;
+    let mut body = vec![0; len as usize];
+    stream.read_exact(&mut body).await.ok()?;
+    postcard::from_bytes(&body).ok()
+}
+
+/// Answer store requests until the worker reports the cell result.
+/// Returns `None` if the connection closed first.
+async fn serve(mut stream: tokio::net::TcpStream) -> Option<std::result::Result<(), CellFailure>> {
+    loop {
+        let response = match read_request(&mut stream).await? {
+            Request::Store {
+                key,
+                bytes,
+                type_name,
+            } => Response::Stored(store::store_value(&key, bytes, &type_name)),
+            Request::StoreStream { key, type_name } => {
+                Response::Streamed(receive_stream(&mut stream, key, type_name).await?)
+            }
+            // Only sent after a `StoreStream`.
+            Request::StreamChunk(_) | Request::StreamEnd(_) => return None,
+            Request::Load(key) => Response::Value(store::load_value(&key)),
+            Request::Remove(key) => Response::Value(store::remove_value(&key)),
+            Request::List => Response::Keys(store::list()),
+            Request::Expire { key, ttl } => {
+                store::set_expiry(&key, ttl);
+                Response::Done
+            }
+            Request::Artifact {
+                path,
+                mime,
+                temporary,
+            } => {
+                artifacts::register(&path, &mime, temporary);
+                Response::Done
+            }
+            Request::Schema { type_name, schema } => {
+                store::register_schema(&type_name, &schema);
+                Response::Done
+            }
+            Request::Prompt(message) => Response::Line(prompt::request(&message).await),
+            Request::Finished(result) => return Some(result),
+        };
+        stream.write_all(&encode(&response)).await.ok()?;
+    }
+}
+
+/// Store the chunks following a `StoreStream` request as they arrive.
+async fn receive_stream(
+    stream: &mut tokio::net::TcpStream,
+    key: String,
+    type_name: String,
+) -> Option<std::result::Result<u64, String>> {
+    let (tx, rx) = mpsc::channel(4);
+    let stored = tokio::task::spawn_blocking(move || {
+        let mut reader = ChunkReader {
+            rx,
+            chunk: Vec::new(),
+            pos: 0,
+        };
+        store::store_stream(&key, &mut reader, &type_name)
+    });
+    loop {
+        // Sending fails once the store stopped reading, the rest is then skipped.
+        match read_request(stream).await? {
+            Request::StreamChunk(chunk) => {
+                let _ = tx.send(Ok(chunk)).await;
+            }
+            Request::StreamEnd(error) => {
+                if let Some(error) = error {
+                    let _ = tx.send(Err(error)).await;
+                }
+                break;
+            }
+            _ => return None,
+        }
+    }
+    drop(tx);
+    Some(stored.await.unwrap_or_else(|e| Err(e.to_string())))
+}
+
+/// Reads a streamed value from the chunks received for it, on a blocking thread.
+struct ChunkReader {
+    rx: mpsc::Receiver<std::result::Result<Vec<u8>, String>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            match self.rx.blocking_recv() {
+                Some(Ok(chunk)) => (self.chunk, self.pos) = (chunk, 0),
+                Some(Err(e)) => return Err(std::io::Error::other(e)),
+                None => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.chunk.len() - self.pos);
+        buf[..len].copy_from_slice(&self.chunk[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+fn crash_message(status: ExitStatus, stderr: &str) -> String {
+    let reason = match (status.code(), signal(&status)) {
+        (Some(code), _) => format!("exited with code {}", code),
+        (None, Some(signal)) => format!("killed by signal {}", signal),
+        (None, None) => "terminated".to_string(),
+    };
+    let stderr = stderr.trim_end();
+    if stderr.is_empty() {
+        format!("Isolated worker {}", reason)
+    } else {
+        format!("Isolated worker {}:\n{}", reason, stderr)
+    }
+}
+
+#[cfg(unix)]
+fn signal(status: &ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal()
+}
+
+#[cfg(not(unix))]
+fn signal(_status: &ExitStatus) -> Option<i32> {
+    None
+}
+
+/// Connection to the runner, set in worker processes only.
+static CONNECTION: Mutex<Option<TcpStream>> = Mutex::new(None);
+
+pub fn is_worker() -> bool {
+    CONNECTION.lock().is_some()
+}
+
+/// Entry point of the worker process: run one cell against the runner's store.
+pub async fn worker(lib_path: &Path, cell: &str, port: u16, sandbox: bool) -> Result<()> {
+    loader::set_sandbox_cells(sandbox);
+    let token = std::env::var(TOKEN_VAR).map_err(|_| {
+        std::io::Error::other(format!(
+            "{} is not set, workers are started by the runner",
+            TOKEN_VAR
+        ))
+    })?;
+    let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port))?;
+    stream.set_nodelay(true)?;
+    stream.write_all(token.as_bytes())?;
+    *CONNECTION.lock() = Some(stream);
+
+    let lib = LoadedLibrary::load(lib_path)?;
+    let cell = lib
+        .cells()
+        .iter()
+        .find(|c| c.name == cell)
+        .ok_or_else(|| Error::CellNotFound(cell.to_string()))?;
+    let dir = cell.working_dir().map(String::from);
+    // Resolves and creates the cell's directory relative to the project root.
+    let future = lib.cell_future(cell)?;
+    // The worker runs this one cell, so unlike the runner it can switch its
+    // process directory, which relative paths in any code of the cell then use.
+    if let Some(dir) = dir {
+        std::env::set_current_dir(dir)?;
+    }
+    let result = future.await.map_err(|e| CellFailure::from_error(&*e));
+
+    let mut connection = CONNECTION.lock();
+    if let Some(stream) = connection.as_mut() {
+        stream.write_all(&encode(&Request::Finished(result)))?;
+    }
+    Ok(())
+}
+
+/// Send a request to the runner and wait for the response.
+/// Exits the worker if the runner went away, since the cell can't continue.
+fn call(request: Request) -> Response {
+    exchange(|stream| stream.write_all(&encode(&request)))
+}
+
+/// Write requests with `send` and wait for the response to the last one.
+fn exchange(send: impl FnOnce(&mut TcpStream) -> std::io::Result<()>) -> Response {
+    let mut connection = CONNECTION.lock();
+    let response = connection.as_mut().and_then(|stream| {
+        send(stream).ok()?;
+        let mut len = [0; 4];
+        stream.read_exact(&mut len).ok()?;
+        let mut body = vec![0; u32::from_le_bytes(len) as usize];
+        stream.read_exact(&mut body).ok()?;
+        postcard::from_bytes(&body).ok()
+    });
+    match response {
+        Some(response) => response,
+        None => {
+            eprintln!("Lost connection to the runner");
+            std::process::exit(1);
+        }
+    }
+}
+
+pub fn store_value(key: &str, bytes: Vec<u8>, type_name: &str) -> std::result::Result<(), String> {
+    let request = Request::Store {
+        key: key.to_string(),
+        bytes,
+        type_name: type_name.to_string(),
+    };
+    match call(request) {
+        Response::Stored(result) => result,
+        _ => Err("unexpected response from the runner".to_string()),
+    }
+}
+
+pub fn store_stream(key: &str, reader: &mut dyn Read, type_name: &str) -> std::result::Result<u64, String> {
+    let start = Request::StoreStream {
+        key: key.to_string(),
+        type_name: type_name.to_string(),
+    };
+    let response = exchange(|stream| {
+        stream.write_all(&encode(&start))?;
+        let mut chunk = vec![0; CHUNK_BYTES];
+        // A read error still ends the stream, so the runner drops what it has.
+        let error = loop {
+            match reader.read(&mut chunk) {
+                Ok(0) => break None,
+                Ok(n) => stream.write_all(&encode(&Request::StreamChunk(chunk[..n].to_vec())))?,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => break Some(e.to_string()),
+            }
+        };
+        stream.write_all(&encode(&Request::StreamEnd(error)))
+    });
+    match response {
+        Response::Streamed(result) => result,
+        _ => Err("unexpected response from the runner".to_string()),
+    }
+}
+
+pub fn load_value(key: &str) -> Option<(Vec<u8>, String)> {
+    match call(Request::Load(key.to_string())) {
+        Response::Value(value) => value,
+        _ => None,
+    }
+}
+
+pub fn remove_value(key: &str) -> Option<(Vec<u8>, String)> {
+    match call(Request::Remove(key.to_string())) {
+        Response::Value(value) => value,
+        _ => None,
+    }
+}
+
+pub fn list() -> Vec<(String, String)> {
+    match call(Request::List) {
+        Response::Keys(keys) => keys,
+        _ => Vec::new(),
+    }
+}
+
+pub fn expire(key: &str, ttl: Duration) {
+    call(Request::Expire {
+        key: key.to_string(),
+        ttl,
+    });
+}
+
+pub fn register_artifact(path: &Path, mime: &str, temporary: bool) {
+    let request = Request::Artifact {
+        path: path.to_path_buf(),
+        mime: mime.to_string(),
+        temporary,
+    };
+    call(request);
+}
+
+pub fn register_schema(type_name: &str, schema: &str) {
+    call(Request::Schema {
+        type_name: type_name.to_string(),
+        schema: schema.to_string(),
+    });
+}
+
+/// Ask the runner for a line of input. The request blocks the connection until
+/// answered, so it waits on a blocking thread.
+pub fn prompt(message: &str) -> BoxFuture<'static, std::result::Result<String, String>> {
+    let request = Request::Prompt(message.to_string());
+    Box::pin(async move {
+        match tokio::task::spawn_blocking(move || call(request)).await {
+            Ok(Response::Line(line)) => line,
+            _ => Err("unexpected response from the runner".to_string()),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_prefixes_length() {
+        let frame = encode(&Request::Load("data".to_string()));
+        let len = u32::from_le_bytes(frame[..4].try_into().unwrap()) as usize;
+        assert_eq!(len, frame.len() - 4);
+        let Request::Load(key) = postcard::from_bytes(&frame[4..]).unwrap() else {
+            panic!("expected a load request");
+        };
+        assert_eq!(key, "data");
+    }
+
+    #[tokio::test]
+    async fn test_accept_worker_drops_connections_without_the_token() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let clients = tokio::spawn(async move {
+            let mut stranger = tokio::net::TcpStream::connect(addr).await.unwrap();
+            stranger
+                .write_all(b"not the token, just as long as i")
+                .await
+                .unwrap();
+            let mut worker = tokio::net::TcpStream::connect(addr).await.unwrap();
+            worker
+                .write_all(b"0123456789abcdef0123456789abcdef!")
+                .await
+                .unwrap();
+            (stranger, worker)
+        });
+
+        let mut accepted = accept_worker(&listener, "0123456789abcdef0123456789abcdef")
+            .await
+            .unwrap();
+        let mut next = [0; 1];
+        accepted.read_exact(&mut next).await.unwrap();
+        assert_eq!(&next, b"!");
+        drop(clients.await.unwrap());
+    }
+
+    #[test]
+    fn test_chunk_reader_joins_chunks() {
+        let (tx, rx) = mpsc::channel(4);
+        tx.blocking_send(Ok(b"hello ".to_vec())).unwrap();
+        tx.blocking_send(Ok(Vec::new())).unwrap();
+        tx.blocking_send(Ok(b"world".to_vec())).unwrap();
+        drop(tx);
+        let mut reader = ChunkReader {
+            rx,
+            chunk: Vec::new(),
+            pos: 0,
+        };
+        let mut read = String::new();
+        reader.read_to_string(&mut read).unwrap();
+        assert_eq!(read, "hello world");
+
+        let (tx, rx) = mpsc::channel(4);
+        tx.blocking_send(Ok(b"part".to_vec())).unwrap();
+        tx.blocking_send(Err("disk gone".to_string())).unwrap();
+        let mut reader = ChunkReader {
+            rx,
+            chunk: Vec::new(),
+            pos: 0,
+        };
+        let error = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(error.to_string(), "disk gone");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_crash_message() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let aborted = crash_message(ExitStatus::from_raw(6), "");
+        assert_eq!(aborted, "Isolated worker killed by signal 6");
+
+        let failed = crash_message(ExitStatus::from_raw(1 << 8), "Error: boom\n");
+        assert_eq!(failed, "Isolated worker exited with code 1:\nError: boom");
+    }
+}
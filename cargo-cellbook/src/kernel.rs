@@ -0,0 +1,817 @@
+//! `cargo cellbook kernel`: a Jupyter kernel for a cellbook project.
+//!
+//! Each code cell is compiled as a scratch cell against the notebook's
+//! dependencies and run with the live store, so values pass between Jupyter
+//! cells with `store!` and `load!` as they do between notebook cells. `%run <cell>`
+//! runs a cell of the notebook itself. Output streams to the front end as it is
+//! written and images cells open are shown inline.
+//!
+//! The kernel speaks version 5.3 of the Jupyter messaging protocol over ZeroMQ,
+//! with the sockets and signing key from the connection file Jupyter passes. Only
+//! built with the `jupyter` feature.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use futures::future::BoxFuture;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use sha2::Sha256;
+use tokio::sync::mpsc;
+use zeromq::{PubSocket, RepSocket, RouterSocket, Socket, SocketRecv, SocketSend, ZmqMessage};
+
+use crate::errors::{Error, Result};
+use crate::failure::CellFailure;
+use crate::loader::LoadedLibrary;
+use crate::runner::{self, OUTPUT_INTERVAL, Runs, Start};
+use crate::store::{self, Provenance};
+use crate::tui::TuiEvent;
+use crate::tui::config::GeneralConfig;
+use crate::util::base64;
+use crate::{artifacts, prompt, resources, run_info, scratch, tasks};
+
+const PROTOCOL_VERSION: &str = "5.3";
+
+/// Separates the routing identities of a message from its parts.
+const DELIMITER: &[u8] = b"<IDS|MSG>";
+
+/// Sockets and key written to the connection file by Jupyter.
+#[derive(Debug, Deserialize)]
+struct ConnectionInfo {
+    transport: String,
+    ip: String,
+    shell_port: u16,
+    iopub_port: u16,
+    stdin_port: u16,
+    control_port: u16,
+    hb_port: u16,
+    key: String,
+    signature_scheme: String,
+}
+
+impl ConnectionInfo {
+    fn endpoint(&self, port: u16) -> String {
+        format!("{}://{}:{}", self.transport, self.ip, port)
+    }
+}
+
+/// Signs messages with the connection's key. An empty key turns signing off.
+struct Signer {
+    key: Vec<u8>,
+}
+
+impl Signer {
+    fn mac(&self, parts: &[&[u8]]) -> Option<Hmac<Sha256>> {
+        if self.key.is_empty() {
+            return None;
+        }
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC takes keys of any length");
+        for part in parts {
+            mac.update(part);
+        }
+        Some(mac)
+    }
+
+    /// Hex signature of the header, parent header, metadata and content.
+    fn sign(&self, parts: &[&[u8]]) -> String {
+        let Some(mac) = self.mac(parts) else {
+            return String::new();
+        };
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    fn verify(&self, signature: &[u8], parts: &[&[u8]]) -> bool {
+        let Some(mac) = self.mac(parts) else {
+            return true;
+        };
+        let Some(signature) = decode_hex(signature) else {
+            return false;
+        };
+        mac.verify_slice(&signature).is_ok()
+    }
+}
+
+fn decode_hex(hex: &[u8]) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    hex.chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+/// A message received on the shell, control or stdin socket.
+#[derive(Debug)]
+struct Message {
+    /// Routing identities to send replies back through.
+    identities: Vec<Vec<u8>>,
+    header: Value,
+    content: Value,
+}
+
+impl Message {
+    fn parse(frames: Vec<Vec<u8>>, signer: &Signer) -> std::result::Result<Self, String> {
+        let delimiter = frames
+            .iter()
+            .position(|frame| frame == DELIMITER)
+            .ok_or("no <IDS|MSG> delimiter")?;
+        let parts = &frames[delimiter + 1..];
+        if parts.len() < 5 {
+            return Err(format!("{} parts after the delimiter, expected 5", parts.len()));
+        }
+        let signed: Vec<&[u8]> = parts[1..5].iter().map(Vec::as_slice).collect();
+        if !signer.verify(&parts[0], &signed) {
+            return Err("bad signature".to_string());
+        }
+        let json = |part: &[u8]| serde_json::from_slice(part).map_err(|e| e.to_string());
+        Ok(Self {
+            identities: frames[..delimiter].to_vec(),
+            header: json(&parts[1])?,
+            content: json(&parts[4])?,
+        })
+    }
+
+    fn msg_type(&self) -> &str {
+        self.header["msg_type"].as_str().unwrap_or_default()
+    }
+}
+
+/// Socket a reply goes out on.
+#[derive(Clone, Copy)]
+enum Channel {
+    Shell,
+    Control,
+    Stdin,
+}
+
+/// What the kernel does after a control message.
+#[derive(PartialEq, Eq)]
+enum Control {
+    Continue,
+    Interrupt,
+    Shutdown,
+}
+
+/// An error shown in the front end.
+struct ExecuteError {
+    ename: String,
+    evalue: String,
+    traceback: Vec<String>,
+}
+
+impl ExecuteError {
+    fn from_failure(failure: &CellFailure, cell: &str, line: u32) -> Self {
+        Self {
+            ename: "CellError".to_string(),
+            evalue: failure.message.clone(),
+            traceback: failure.render(cell, line).lines().map(String::from).collect(),
+        }
+    }
+}
+
+struct Kernel {
+    signer: Signer,
+    /// Session id for the headers of messages the kernel sends.
+    session: String,
+    next_id: u64,
+    shell: RouterSocket,
+    control: RouterSocket,
+    stdin: RouterSocket,
+    iopub: PubSocket,
+    execution_count: u32,
+    config: GeneralConfig,
+    runs: Runs,
+    /// Scratch libraries stay loaded while tasks or resources they created may
+    /// still reference their code, see [`Kernel::unload_scratch_libs`].
+    scratch_libs: Vec<LoadedLibrary>,
+    /// Id of the last prompt sent to the front end.
+    prompted: Option<u64>,
+}
+
+/// Register the kernel with Jupyter, for the current user.
+pub fn install() -> Result<()> {
+    let dir = kernels_dir()
+        .ok_or_else(|| Error::Kernel("no Jupyter data directory for this platform".to_string()))?
+        .join("cellbook");
+    std::fs::create_dir_all(&dir)?;
+    let exe = std::env::current_exe()?;
+    let spec = json!({
+        "argv": [exe, "cellbook", "kernel", "--connection-file", "{connection_file}"],
+        "display_name": "Rust (cellbook)",
+        "language": "rust",
+        "interrupt_mode": "message",
+    });
+    let spec = serde_json::to_string_pretty(&spec).map_err(|e| Error::Kernel(e.to_string()))?;
+    std::fs::write(dir.join("kernel.json"), spec)?;
+    println!("Installed the cellbook kernel in {}", dir.display());
+    println!("Start notebooks from a cellbook project directory to run them against it");
+    Ok(())
+}
+
+/// Where Jupyter looks for kernels of the current user.
+fn kernels_dir() -> Option<PathBuf> {
+    let data_dir = match std::env::var_os("JUPYTER_DATA_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None if cfg!(target_os = "macos") => dirs::home_dir()?.join("Library/Jupyter"),
+        None => dirs::data_dir()?.join("jupyter"),
+    };
+    Some(data_dir.join("kernels"))
+}
+
+/// Serve Jupyter front ends until asked to shut down. Reloads on the watcher's
+/// events like the TUI.
+pub async fn serve(
+    lib: &mut LoadedLibrary,
+    mut event_rx: mpsc::Receiver<TuiEvent>,
+    config: &GeneralConfig,
+    connection_file: &Path,
+) -> Result<()> {
+    prompt::set_input(prompt::Input::Queue);
+
+    let info: ConnectionInfo = serde_json::from_str(&std::fs::read_to_string(connection_file)?)
+        .map_err(|e| Error::Kernel(format!("invalid connection file: {}", e)))?;
+    if !info.key.is_empty() && info.signature_scheme != "hmac-sha256" {
+        return Err(Error::Kernel(format!(
+            "unsupported signature scheme {}",
+            info.signature_scheme
+        )));
+    }
+
+    let bind = |socket_name: &str, e: zeromq::ZmqError| {
+        Error::Kernel(format!("can't bind the {} socket: {}", socket_name, e))
+    };
+    let mut shell = RouterSocket::new();
+    shell
+        .bind(&info.endpoint(info.shell_port))
+        .await
+        .map_err(|e| bind("shell", e))?;
+    let mut control = RouterSocket::new();
+    control
+        .bind(&info.endpoint(info.control_port))
+        .await
+        .map_err(|e| bind("control", e))?;
+    let mut stdin = RouterSocket::new();
+    stdin
+        .bind(&info.endpoint(info.stdin_port))
+        .await
+        .map_err(|e| bind("stdin", e))?;
+    let mut iopub = PubSocket::new();
+    iopub
+        .bind(&info.endpoint(info.iopub_port))
+        .await
+        .map_err(|e| bind("iopub", e))?;
+    let mut heartbeat = RepSocket::new();
+    heartbeat
+        .bind(&info.endpoint(info.hb_port))
+        .await
+        .map_err(|e| bind("heartbeat", e))?;
+    // The front end checks the kernel is alive by having it echo messages.
+    let heartbeat = tokio::spawn(async move {
+        while let Ok(message) = heartbeat.recv().await {
+            if heartbeat.send(message).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut kernel = Kernel {
+        signer: Signer {
+            key: info.key.into_bytes(),
+        },
+        session: session_id(),
+        next_id: 0,
+        shell,
+        control,
+        stdin,
+        iopub,
+        execution_count: 0,
+        config: config.clone(),
+        runs: Runs::default(),
+        scratch_libs: Vec::new(),
+        prompted: None,
+    };
+    kernel
+        .publish(&Value::Null, "status", json!({ "execution_state": "starting" }))
+        .await;
+
+    loop {
+        tokio::select! {
+            frames = kernel.shell.recv() => {
+                let Some(message) = kernel.parse(frames) else { continue };
+                if kernel.handle_shell(lib, message).await == Control::Shutdown {
+                    break;
+                }
+            }
+            frames = kernel.control.recv() => {
+                let Some(message) = kernel.parse(frames) else { continue };
+                if kernel.handle_control(&message).await == Control::Shutdown {
+                    break;
+                }
+            }
+            Some(event) = event_rx.recv() => match event {
+                TuiEvent::BuildCompleted(Some(e)) => eprintln!("{}", e),
+                TuiEvent::Reloaded => {
+                    release(lib).await;
+                    kernel.unload_scratch_libs();
+                    match lib.reload() {
+                        Ok(()) => {
+                            eprintln!("Reloaded {} cells", lib.cells().len());
+                            if let Err(e) = kernel.config.reload(lib.config()) {
+                                eprintln!("Error: {}", e);
+                            }
+                        }
+                        Err(e) => eprintln!("Error: {}", e),
+                    }
+                }
+                _ => {}
+            },
+        }
+    }
+
+    heartbeat.abort();
+    release(lib).await;
+    drop(std::mem::take(&mut kernel.scratch_libs));
+    artifacts::cleanup();
+    store::remove_disk_files();
+    Ok(())
+}
+
+/// Id unique to this kernel process, for the session field of its messages.
+fn session_id() -> String {
+    let started = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    format!("{:x}-{:x}", std::process::id(), started.as_nanos())
+}
+
+/// Run teardown, then drop background tasks and resources.
+async fn release(lib: &LoadedLibrary) {
+    lib.release().await.report(&mut std::io::stderr());
+}
+
+impl Kernel {
+    /// Unload scratch libraries no code runs from anymore. Tasks and resources don't
+    /// record which library they came from, so none is unloaded while any exist.
+    fn unload_scratch_libs(&mut self) {
+        if tasks::any_running() || !resources::is_empty() {
+            return;
+        }
+        self.scratch_libs.retain(|lib| lib.in_use() > 0);
+    }
+
+    fn parse(&self, frames: zeromq::ZmqResult<ZmqMessage>) -> Option<Message> {
+        let frames: Vec<Vec<u8>> = frames.ok()?.into_vec().into_iter().map(|f| f.to_vec()).collect();
+        match Message::parse(frames, &self.signer) {
+            Ok(message) => Some(message),
+            Err(e) => {
+                eprintln!("Dropped a message: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Frames of a message with `identities` in front, replying to `parent`.
+    fn frames(
+        &mut self,
+        identities: Vec<Vec<u8>>,
+        parent: &Value,
+        msg_type: &str,
+        content: Value,
+    ) -> ZmqMessage {
+        self.next_id += 1;
+        let header = json!({
+            "msg_id": format!("{}-{}", self.session, self.next_id),
+            "session": self.session,
+            "username": "cellbook",
+            "date": run_info::format_iso8601(SystemTime::now()),
+            "msg_type": msg_type,
+            "version": PROTOCOL_VERSION,
+        });
+        let parts: Vec<Vec<u8>> = [&header, parent, &json!({}), &content]
+            .iter()
+            .map(|part| part.to_string().into_bytes())
+            .collect();
+        let signed: Vec<&[u8]> = parts.iter().map(Vec::as_slice).collect();
+        let signature = self.signer.sign(&signed);
+
+        let mut message = ZmqMessage::from(DELIMITER.to_vec());
+        for identity in identities.into_iter().rev() {
+            message.push_front(identity.into());
+        }
+        message.push_back(signature.into_bytes().into());
+        for part in parts {
+            message.push_back(part.into());
+        }
+        message
+    }
+
+    async fn reply(&mut self, channel: Channel, request: &Message, msg_type: &str, content: Value) {
+        let message = self.frames(request.identities.clone(), &request.header, msg_type, content);
+        let socket = match channel {
+            Channel::Shell => &mut self.shell,
+            Channel::Control => &mut self.control,
+            Channel::Stdin => &mut self.stdin,
+        };
+        if let Err(e) = socket.send(message).await {
+            eprintln!("Can't send {}: {}", msg_type, e);
+        }
+    }
+
+    /// Broadcast on the iopub socket, where front ends follow what the kernel does.
+    async fn publish(&mut self, parent: &Value, msg_type: &str, content: Value) {
+        let message = self.frames(vec![msg_type.as_bytes().to_vec()], parent, msg_type, content);
+        let _ = self.iopub.send(message).await;
+    }
+
+    async fn handle_shell(&mut self, lib: &mut LoadedLibrary, request: Message) -> Control {
+        self.publish(&request.header, "status", json!({ "execution_state": "busy" }))
+            .await;
+        let mut control = Control::Continue;
+        match request.msg_type() {
+            "kernel_info_request" => {
+                self.reply(Channel::Shell, &request, "kernel_info_reply", kernel_info())
+                    .await
+            }
+            "execute_request" => control = self.execute(lib, &request).await,
+            "is_complete_request" => {
+                // Rust snippets can't be checked without compiling them.
+                self.reply(
+                    Channel::Shell,
+                    &request,
+                    "is_complete_reply",
+                    json!({ "status": "unknown" }),
+                )
+                .await
+            }
+            "comm_info_request" => {
+                let content = json!({ "status": "ok", "comms": {} });
+                self.reply(Channel::Shell, &request, "comm_info_reply", content)
+                    .await
+            }
+            "history_request" => {
+                let content = json!({ "status": "ok", "history": [] });
+                self.reply(Channel::Shell, &request, "history_reply", content)
+                    .await
+            }
+            "shutdown_request" => control = self.shutdown(Channel::Shell, &request).await,
+            other => eprintln!("Ignored a {}", other),
+        }
+        self.publish(&request.header, "status", json!({ "execution_state": "idle" }))
+            .await;
+        control
+    }
+
+    async fn handle_control(&mut self, request: &Message) -> Control {
+        match request.msg_type() {
+            "kernel_info_request" => {
+                self.reply(Channel::Control, request, "kernel_info_reply", kernel_info())
+                    .await;
+                Control::Continue
+            }
+            "interrupt_request" => {
+                self.reply(
+                    Channel::Control,
+                    request,
+                    "interrupt_reply",
+                    json!({ "status": "ok" }),
+                )
+                .await;
+                Control::Interrupt
+            }
+            "shutdown_request" => self.shutdown(Channel::Control, request).await,
+            other => {
+                eprintln!("Ignored a {}", other);
+                Control::Continue
+            }
+        }
+    }
+
+    async fn shutdown(&mut self, channel: Channel, request: &Message) -> Control {
+        let restart = request.content["restart"].as_bool().unwrap_or(false);
+        let content = json!({ "status": "ok", "restart": restart });
+        self.reply(channel, request, "shutdown_reply", content).await;
+        Control::Shutdown
+    }
+
+    async fn execute(&mut self, lib: &mut LoadedLibrary, request: &Message) -> Control {
+        let code = request.content["code"].as_str().unwrap_or_default().to_string();
+        let silent = request.content["silent"].as_bool().unwrap_or(false);
+        if !silent {
+            self.execution_count += 1;
+            let content = json!({ "code": code, "execution_count": self.execution_count });
+            self.publish(&request.header, "execute_input", content).await;
+        }
+
+        let (result, control) = match code.trim() {
+            "" => (Ok(()), Control::Continue),
+            code => match code.strip_prefix("%run ") {
+                Some(name) => self.run_cell(lib, request, name.trim()).await,
+                None => self.run_snippet(lib, request, code).await,
+            },
+        };
+
+        let count = self.execution_count;
+        match result {
+            Ok(()) => {
+                let content = json!({
+                    "status": "ok",
+                    "execution_count": count,
+                    "payload": [],
+                    "user_expressions": {},
+                });
+                self.reply(Channel::Shell, request, "execute_reply", content)
+                    .await;
+            }
+            Err(e) => {
+                let error = json!({ "ename": e.ename, "evalue": e.evalue, "traceback": e.traceback });
+                self.publish(&request.header, "error", error.clone()).await;
+                let mut content = json!({ "status": "error", "execution_count": count });
+                content
+                    .as_object_mut()
+                    .unwrap()
+                    .extend(error.as_object().unwrap().clone());
+                self.reply(Channel::Shell, request, "execute_reply", content)
+                    .await;
+            }
+        }
+        control
+    }
+
+    /// Compile `code` as a scratch cell and run it.
+    async fn run_snippet(
+        &mut self,
+        lib: &LoadedLibrary,
+        request: &Message,
+        code: &str,
+    ) -> (std::result::Result<(), ExecuteError>, Control) {
+        let compiled = async {
+            let path = scratch::build(code, lib.lib_path()).await?;
+            let scratch_lib = LoadedLibrary::load_copy(&path)?;
+            let cell = scratch_lib
+                .cells()
+                .iter()
+                .find(|c| c.name == scratch::CELL_NAME)
+                .ok_or_else(|| Error::CellNotFound(scratch::CELL_NAME.to_string()))?;
+            let future = scratch_lib.cell_future(cell)?;
+            Ok::<_, Error>((scratch_lib, future))
+        };
+        let future = match compiled.await {
+            Ok((scratch_lib, future)) => {
+                self.unload_scratch_libs();
+                self.scratch_libs.push(scratch_lib);
+                future
+            }
+            Err(Error::Build(output)) => return (Err(compile_error(&output)), Control::Continue),
+            Err(e) => {
+                let error = ExecuteError {
+                    ename: "LoadError".to_string(),
+                    evalue: e.to_string(),
+                    traceback: vec![e.to_string()],
+                };
+                return (Err(error), Control::Continue);
+            }
+        };
+        let run = Box::pin(async move { future.await.map_err(|e| CellFailure::from_error(&*e)) });
+        artifacts::set_current_cell(Some(scratch::CELL_NAME));
+        store::set_producer(Some(Provenance::new(scratch::CELL_NAME, None)));
+        let (result, _, control) = self.run(request, scratch::CELL_NAME, run).await;
+        store::set_producer(None);
+        artifacts::set_current_cell(None);
+        (
+            result.map_err(|e| ExecuteError::from_failure(&e, scratch::CELL_NAME, 0)),
+            control,
+        )
+    }
+
+    /// Run the notebook cell `name`, for `%run <name>`.
+    async fn run_cell(
+        &mut self,
+        lib: &LoadedLibrary,
+        request: &Message,
+        name: &str,
+    ) -> (std::result::Result<(), ExecuteError>, Control) {
+        let Some(idx) = lib.visible_cells().iter().position(|c| c.name == name) else {
+            let error = ExecuteError {
+                ename: "CellNotFound".to_string(),
+                evalue: format!("no cell named '{}'", name),
+                traceback: Vec::new(),
+            };
+            return (Err(error), Control::Continue);
+        };
+        let cell = lib.visible_cells()[idx].clone();
+        // Retries are announced in the cell's output, which streams to the front end.
+        let (name, retries) = (cell.name.clone(), cell.retries);
+        let on_retry = move |retry, e: &CellFailure| {
+            println!("[{}] retry {}/{} after: {}", name, retry, retries, e.message);
+        };
+        let run = match self.runs.start(lib, &self.config, idx, on_retry) {
+            Start::Run { future, .. } => future,
+            Start::Service => {
+                let text = format!("{} is running as a service until reload or exit\n", cell.name);
+                self.publish(
+                    &request.header,
+                    "stream",
+                    json!({ "name": "stdout", "text": text }),
+                )
+                .await;
+                return (Ok(()), Control::Continue);
+            }
+            Start::Refused(reason) => {
+                let error = ExecuteError {
+                    ename: "NotRun".to_string(),
+                    evalue: format!("{} {}", cell.name, reason),
+                    traceback: Vec::new(),
+                };
+                return (Err(error), Control::Continue);
+            }
+        };
+        let (result, duration, control) = self.run(request, &cell.name, run).await;
+        self.runs.finish(&cell, idx, duration, &result, &self.config);
+        (
+            result.map_err(|e| ExecuteError::from_failure(&e, &cell.name, cell.line)),
+            control,
+        )
+    }
+
+    /// Run a cell's future, streaming its output to the front end and answering
+    /// its prompts from there, then show the images it opened.
+    async fn run(
+        &mut self,
+        request: &Message,
+        name: &str,
+        run: BoxFuture<'static, std::result::Result<(), CellFailure>>,
+    ) -> (std::result::Result<(), CellFailure>, Duration, Control) {
+        let allow_stdin = request.content["allow_stdin"].as_bool().unwrap_or(false);
+        let artifacts_before = artifacts::list().len();
+
+        let (output_tx, mut output_rx) = mpsc::unbounded_channel::<String>();
+        let mut handle = runner::spawn(name, run, move |text| {
+            let _ = output_tx.send(text.to_string());
+        });
+
+        let mut ticker = tokio::time::interval(OUTPUT_INTERVAL);
+        let mut control = Control::Continue;
+        let (_, result, duration) = loop {
+            tokio::select! {
+                joined = &mut handle => break runner::finished(joined),
+                Some(text) = output_rx.recv() => {
+                    let content = json!({ "name": "stdout", "text": text });
+                    self.publish(&request.header, "stream", content).await;
+                }
+                _ = ticker.tick() => self.forward_prompt(request, allow_stdin).await,
+                frames = self.stdin.recv() => {
+                    if let Some(reply) = self.parse(frames)
+                        && reply.msg_type() == "input_reply"
+                    {
+                        prompt::answer(reply.content["value"].as_str().map(String::from));
+                    }
+                }
+                frames = self.control.recv() => {
+                    let Some(message) = self.parse(frames) else { continue };
+                    match self.handle_control(&message).await {
+                        Control::Continue => {}
+                        // Aborting the task makes it finish as interrupted.
+                        stop => {
+                            handle.abort();
+                            control = stop;
+                        }
+                    }
+                }
+            }
+        };
+        while let Ok(text) = output_rx.try_recv() {
+            let content = json!({ "name": "stdout", "text": text });
+            self.publish(&request.header, "stream", content).await;
+        }
+
+        for artifact in artifacts::list().into_iter().skip(artifacts_before) {
+            if !artifact.mime.starts_with("image/") || artifact.cell.as_deref() != Some(name) {
+                continue;
+            }
+            let Ok(bytes) = std::fs::read(&artifact.path) else {
+                continue;
+            };
+            let content = json!({
+                "data": { artifact.mime: base64(&bytes) },
+                "metadata": {},
+                "transient": {},
+            });
+            self.publish(&request.header, "display_data", content).await;
+        }
+        let control = if control == Control::Interrupt {
+            Control::Continue
+        } else {
+            control
+        };
+        (result, duration, control)
+    }
+
+    /// Ask the front end to answer the prompt a running cell waits on, or cancel
+    /// it if the front end can't take input.
+    async fn forward_prompt(&mut self, request: &Message, allow_stdin: bool) {
+        let Some((id, message)) = prompt::pending() else {
+            return;
+        };
+        if self.prompted == Some(id) {
+            return;
+        }
+        self.prompted = Some(id);
+        if !allow_stdin {
+            prompt::answer(None);
+            return;
+        }
+        let content = json!({ "prompt": format!("{} ", message), "password": false });
+        self.reply(Channel::Stdin, request, "input_request", content)
+            .await;
+    }
+}
+
+fn kernel_info() -> Value {
+    json!({
+        "status": "ok",
+        "protocol_version": PROTOCOL_VERSION,
+        "implementation": "cellbook",
+        "implementation_version": env!("CARGO_PKG_VERSION"),
+        "language_info": {
+            "name": "rust",
+            "version": "",
+            "mimetype": "text/rust",
+            "file_extension": ".rs",
+            "pygments_lexer": "rust",
+            "codemirror_mode": "rust",
+        },
+        "banner": "cellbook: code cells run as scratch cells against the notebook's store",
+        "help_links": [],
+    })
+}
+
+/// The first compiler error as the message, with the whole build output as the traceback.
+fn compile_error(output: &str) -> ExecuteError {
+    let evalue = output
+        .lines()
+        .find(|line| line.starts_with("error"))
+        .unwrap_or("build failed")
+        .to_string();
+    ExecuteError {
+        ename: "CompileError".to_string(),
+        evalue,
+        traceback: output.lines().map(String::from).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signed_frames(signer: &Signer, header: &Value, content: &Value) -> Vec<Vec<u8>> {
+        let parts: Vec<Vec<u8>> = [header, &json!({}), &json!({}), content]
+            .iter()
+            .map(|part| part.to_string().into_bytes())
+            .collect();
+        let signed: Vec<&[u8]> = parts.iter().map(Vec::as_slice).collect();
+        let mut frames = vec![
+            b"peer".to_vec(),
+            DELIMITER.to_vec(),
+            signer.sign(&signed).into_bytes(),
+        ];
+        frames.extend(parts);
+        frames
+    }
+
+    #[test]
+    fn test_parses_and_verifies_signed_messages() {
+        let signer = Signer {
+            key: b"secret".to_vec(),
+        };
+        let header = json!({ "msg_type": "execute_request", "msg_id": "1" });
+        let content = json!({ "code": "1 + 1" });
+        let frames = signed_frames(&signer, &header, &content);
+
+        let message = Message::parse(frames.clone(), &signer).unwrap();
+        assert_eq!(message.identities, vec![b"peer".to_vec()]);
+        assert_eq!(message.msg_type(), "execute_request");
+        assert_eq!(message.content["code"], "1 + 1");
+
+        let mut tampered = frames;
+        tampered[6] = json!({ "code": "panic!()" }).to_string().into_bytes();
+        assert!(Message::parse(tampered, &signer).is_err());
+
+        let unsigned = Signer { key: Vec::new() };
+        assert_eq!(unsigned.sign(&[b"{}"]), "");
+        assert!(Message::parse(signed_frames(&unsigned, &header, &content), &signer).is_err());
+    }
+
+    #[test]
+    fn test_compile_error_leads_with_the_first_error() {
+        let output =
+            "   Compiling cellbook-scratch\nerror[E0425]: cannot find value `x`\n  --> scratch.rs:12:1\n";
+        let error = compile_error(output);
+        assert_eq!(error.evalue, "error[E0425]: cannot find value `x`");
+        assert_eq!(error.traceback.len(), 3);
+    }
+}
@@ -0,0 +1,195 @@
+//! Line editing for the plain runner's prompt, with rustyline: history kept in
+//! `.cellbook/plain_history`, and tab completion of commands and cell names.
+
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::{CmdKind, Highlighter};
+use rustyline::hint::Hinter;
+use rustyline::history::FileHistory;
+use rustyline::validate::Validator;
+use rustyline::{CompletionType, Config, Context, Editor, Helper};
+
+pub use rustyline::error::ReadlineError;
+
+const HISTORY_FILE: &str = ".cellbook/plain_history";
+
+/// Lines of history kept.
+const MAX_HISTORY: usize = 1000;
+
+/// The line being edited as the editor last drew it, to draw it again after
+/// other output was printed over it.
+#[derive(Debug, Default)]
+struct Shown {
+    /// Prompt of the line being read, `None` between lines.
+    prompt: Option<String>,
+    line: String,
+    /// Whether the line was drawn again, so the editor draws all of it on the next key
+    /// instead of only what changed.
+    redrawn: bool,
+}
+
+/// Completes the word before the cursor from words the runner keeps up to date,
+/// and follows the line as it is edited.
+struct Words {
+    words: Arc<Mutex<Vec<String>>>,
+    shown: Arc<Mutex<Shown>>,
+}
+
+impl Completer for Words {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = line[..pos].rfind(' ').map_or(0, |i| i + 1);
+        let word = &line[start..pos];
+        let mut matches: Vec<String> = self
+            .words
+            .lock()
+            .iter()
+            .filter(|w| w.starts_with(word))
+            .cloned()
+            .collect();
+        matches.sort();
+        matches.dedup();
+        let pairs = matches
+            .into_iter()
+            .map(|w| Pair {
+                replacement: format!("{} ", w),
+                display: w,
+            })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for Words {
+    type Hint = String;
+}
+
+impl Highlighter for Words {
+    // Called with the line whenever it changes or the cursor moves, before it is drawn.
+    fn highlight_char(&self, line: &str, _: usize, _: CmdKind) -> bool {
+        let mut shown = self.shown.lock();
+        shown.line.replace_range(.., line);
+        std::mem::take(&mut shown.redrawn)
+    }
+}
+
+impl Validator for Words {}
+
+impl Helper for Words {}
+
+/// Draws the line being edited again after other output.
+#[derive(Clone)]
+pub struct Redraw(Arc<Mutex<Shown>>);
+
+impl Redraw {
+    /// Print the prompt and line being edited, if a line is, below what was
+    /// printed over them. The editor puts the cursor back on the next key.
+    pub fn redraw(&self) {
+        let mut shown = self.0.lock();
+        if let Some(prompt) = &shown.prompt {
+            print!("{}{}", prompt, shown.line);
+            let _ = std::io::stdout().flush();
+            shown.redrawn = true;
+        }
+    }
+}
+
+/// Reads lines from the terminal with editing, history and completion.
+pub struct LineEditor {
+    editor: Editor<Words, FileHistory>,
+    shown: Arc<Mutex<Shown>>,
+    /// Where lines are appended, until saving one fails.
+    history: Option<&'static Path>,
+}
+
+impl LineEditor {
+    /// An editor completing `words`, which the runner updates as cells change.
+    pub fn new(words: Arc<Mutex<Vec<String>>>) -> rustyline::Result<Self> {
+        let config = Config::builder()
+            .max_history_size(MAX_HISTORY)?
+            .history_ignore_dups(true)?
+            .completion_type(CompletionType::List)
+            .build();
+        let mut editor = Editor::with_config(config)?;
+        let shown = Arc::new(Mutex::new(Shown::default()));
+        editor.set_helper(Some(Words {
+            words,
+            shown: shown.clone(),
+        }));
+        let history = Path::new(HISTORY_FILE);
+        // There is no history yet on the first run.
+        let _ = editor.load_history(history);
+        Ok(Self {
+            editor,
+            shown,
+            history: Some(history),
+        })
+    }
+
+    /// Read a line after `prompt`. Fails with [`ReadlineError::Interrupted`] on
+    /// Ctrl+C and [`ReadlineError::Eof`] at the end of input.
+    pub fn read_line(&mut self, prompt: &str) -> rustyline::Result<String> {
+        *self.shown.lock() = Shown {
+            prompt: Some(prompt.to_string()),
+            ..Shown::default()
+        };
+        let line = self.editor.readline(prompt);
+        self.shown.lock().prompt = None;
+        let line = line?;
+        if !line.trim().is_empty() && self.editor.add_history_entry(line.as_str())? {
+            self.save_history();
+        }
+        Ok(line)
+    }
+
+    pub fn redraw(&self) -> Redraw {
+        Redraw(self.shown.clone())
+    }
+
+    fn save_history(&mut self) {
+        let Some(path) = self.history else {
+            return;
+        };
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        if let Err(e) = self.editor.append_history(path) {
+            eprintln!("Can't save the prompt history to {}: {}", path.display(), e);
+            self.history = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustyline::history::DefaultHistory;
+
+    fn complete(line: &str, words: &[&str]) -> (usize, Vec<String>) {
+        let words = Words {
+            words: Arc::new(Mutex::new(words.iter().map(|w| w.to_string()).collect())),
+            shown: Arc::default(),
+        };
+        let history = DefaultHistory::new();
+        let (start, pairs) = words
+            .complete(line, line.len(), &Context::new(&history))
+            .unwrap();
+        (start, pairs.into_iter().map(|p| p.replacement).collect())
+    }
+
+    #[test]
+    fn test_complete_the_word_before_the_cursor() {
+        let words = ["load", "load_prices", "plot", "watch", "load"];
+        assert_eq!(complete("w p", &words), (2, vec!["plot ".to_string()]));
+        assert_eq!(
+            complete("lo", &words),
+            (0, vec!["load ".to_string(), "load_prices ".to_string()])
+        );
+        assert_eq!(complete("x", &words), (0, Vec::new()));
+    }
+}
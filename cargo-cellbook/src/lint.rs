@@ -0,0 +1,279 @@
+//! `cargo cellbook lint`: common notebook mistakes found in `cellbook.rs`
+//! without building it.
+//!
+//! The checks read the source with the same lexer as the data-flow graph, so
+//! keys and types named through variables or helper functions are not seen.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+
+use crate::errors::{Error, Result};
+use crate::graph::{self, KeyUse};
+use crate::source::{self, TokenKind};
+
+/// Source file of the notebook library.
+const NOTEBOOK: &str = "cellbook.rs";
+
+/// A mistake at a line of the notebook.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Finding {
+    /// 1-based line in `cellbook.rs`.
+    pub line: u32,
+    /// Function the line belongs to, if any.
+    pub cell: Option<String>,
+    pub message: String,
+}
+
+impl Finding {
+    /// `cellbook.rs:12: fn load: ...`, as printed by `cargo cellbook lint`.
+    pub fn describe(&self) -> String {
+        match &self.cell {
+            Some(cell) => format!("{}:{}: fn {}: {}", NOTEBOOK, self.line, cell, self.message),
+            None => format!("{}:{}: {}", NOTEBOOK, self.line, self.message),
+        }
+    }
+}
+
+/// A cell function with its store and load calls.
+struct Cell<'a> {
+    name: String,
+    /// Name from `#[cell(name = "...")]`.
+    title: Option<String>,
+    first_line: u32,
+    item: &'a str,
+    uses: Vec<KeyUse>,
+}
+
+impl Cell<'_> {
+    fn line_of(&self, key_use: &KeyUse) -> u32 {
+        self.first_line + self.item[..key_use.offset].matches('\n').count() as u32
+    }
+}
+
+/// Lint the notebook in the current directory. No findings without a `cellbook.rs`.
+pub fn lint_notebook() -> Vec<Finding> {
+    fs::read_to_string(NOTEBOOK)
+        .map(|source| lint_source(&source))
+        .unwrap_or_default()
+}
+
+/// Findings in notebook source, ordered by line.
+pub fn lint_source(source: &str) -> Vec<Finding> {
+    let cells: Vec<Cell> = source::cell_spans(source)
+        .into_iter()
+        .filter_map(|span| {
+            let item = source::extract_item(source, *span.lines.start())?;
+            Some(Cell {
+                title: cell_title(item),
+                first_line: *span.lines.start(),
+                uses: graph::key_uses(item),
+                name: span.name,
+                item,
+            })
+        })
+        .collect();
+
+    let mut findings = Vec::new();
+    if !has_init(source) {
+        findings.push(Finding {
+            line: 1,
+            cell: None,
+            message: "no #[init] function, the notebook won't load without one".to_string(),
+        });
+    }
+    duplicate_names(&cells, &mut findings);
+    unstored_keys(&cells, &mut findings);
+    type_mismatches(&cells, &mut findings);
+    findings.sort_by_key(|finding| finding.line);
+    findings
+}
+
+fn has_init(source: &str) -> bool {
+    source.lines().map(str::trim_start).any(|line| {
+        line.strip_prefix("#[init")
+            .is_some_and(|rest| rest.starts_with(']') || rest.starts_with('('))
+    })
+}
+
+/// `name` from a `#[cell(name = "...")]` attribute of the item.
+fn cell_title(item: &str) -> Option<String> {
+    let attrs = item
+        .lines()
+        .map(str::trim_start)
+        .take_while(|line| line.starts_with("#[") || line.starts_with("///"))
+        .filter(|line| line.starts_with("#[cell("));
+    for attr in attrs {
+        let tokens: Vec<_> = source::tokenize(attr)
+            .into_iter()
+            .filter(|t| t.kind != TokenKind::Whitespace)
+            .collect();
+        for window in tokens.windows(3) {
+            if window[0].text == "name" && window[1].text == "=" && window[2].kind == TokenKind::String {
+                return Some(window[2].text.trim_matches('"').to_string());
+            }
+        }
+    }
+    None
+}
+
+/// Cells listed under the same name, which only the first of can be run by name.
+fn duplicate_names(cells: &[Cell], findings: &mut Vec<Finding>) {
+    let mut seen: HashMap<&str, &Cell> = HashMap::new();
+    for cell in cells {
+        let name = cell.title.as_deref().unwrap_or(&cell.name);
+        match seen.get(name) {
+            Some(first) => findings.push(Finding {
+                line: cell.first_line,
+                cell: Some(cell.name.clone()),
+                message: format!(
+                    "cell name `{}` is also used by fn {} on line {}",
+                    name, first.name, first.first_line
+                ),
+            }),
+            None => {
+                seen.insert(name, cell);
+            }
+        }
+    }
+}
+
+/// Loads of keys no cell stores, which fail unless the store was filled elsewhere.
+fn unstored_keys(cells: &[Cell], findings: &mut Vec<Finding>) {
+    let stored: Vec<&str> = cells
+        .iter()
+        .flat_map(|cell| &cell.uses)
+        .filter(|key_use| key_use.stores())
+        .map(|key_use| key_use.key.as_str())
+        .collect();
+    for cell in cells {
+        let mut reported = Vec::new();
+        for key_use in cell.uses.iter().filter(|key_use| key_use.reads()) {
+            if stored.contains(&key_use.key.as_str()) || reported.contains(&&key_use.key) {
+                continue;
+            }
+            reported.push(&key_use.key);
+            findings.push(Finding {
+                line: cell.line_of(key_use),
+                cell: Some(cell.name.clone()),
+                message: format!("loads `{}`, which no cell stores", key_use.key),
+            });
+        }
+    }
+}
+
+/// Keys stored or loaded as different types, reported where a type first
+/// differs from the one the key was first used as.
+fn type_mismatches(cells: &[Cell], findings: &mut Vec<Finding>) {
+    // Key to the first typed use: type, cell and line.
+    let mut first: BTreeMap<&str, (&str, &str, u32)> = BTreeMap::new();
+    for cell in cells {
+        for key_use in &cell.uses {
+            let Some(ty) = key_use.ty.as_deref() else {
+                continue;
+            };
+            let line = cell.line_of(key_use);
+            match first.get(key_use.key.as_str()) {
+                None => {
+                    first.insert(&key_use.key, (ty, &cell.name, line));
+                }
+                Some(&(first_ty, first_cell, first_line)) if first_ty != ty => {
+                    findings.push(Finding {
+                        line,
+                        cell: Some(cell.name.clone()),
+                        message: format!(
+                            "{} `{}` as `{}`, but fn {} uses it as `{}` on line {}",
+                            if key_use.stores() { "stores" } else { "loads" },
+                            key_use.key,
+                            ty,
+                            first_cell,
+                            first_ty,
+                            first_line
+                        ),
+                    });
+                }
+                Some(_) => {}
+            }
+        }
+    }
+}
+
+/// Print the findings in the notebook in the current directory.
+/// Fails if there were any.
+pub fn lint() -> Result<()> {
+    let source = fs::read_to_string(NOTEBOOK)?;
+    let findings = lint_source(&source);
+    for finding in &findings {
+        println!("{}", finding.describe());
+    }
+    if findings.is_empty() {
+        println!(
+            "No problems found in {} cell(s)",
+            source::cell_spans(&source).len()
+        );
+        return Ok(());
+    }
+    Err(Error::Lint(findings.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTEBOOK_SOURCE: &str = r#"use cellbook::*;
+
+#[cell]
+async fn load_data() -> Result<()> {
+    let prices: Vec<f64> = vec![1.0];
+    store!(prices)?;
+    Ok(())
+}
+
+#[cell(name = "Load data")]
+async fn reload() -> Result<()> {
+    let prices = load!(prices as Vec<i32>)?;
+    let config: Config = load!(config)?;
+    Ok(())
+}
+
+/// Also named after its function.
+#[cell(name = "load_data")]
+async fn summary() -> Result<()> {
+    let prices: Vec<f64> = load!(prices)?;
+    Ok(())
+}
+"#;
+
+    #[test]
+    fn test_lint_finds_notebook_mistakes() {
+        let findings: Vec<String> = lint_source(NOTEBOOK_SOURCE)
+            .iter()
+            .map(Finding::describe)
+            .collect();
+        assert_eq!(
+            findings,
+            [
+                "cellbook.rs:1: no #[init] function, the notebook won't load without one",
+                "cellbook.rs:12: fn reload: loads `prices` as `Vec<i32>`, but fn load_data uses it as `Vec<f64>` on line 6",
+                "cellbook.rs:13: fn reload: loads `config`, which no cell stores",
+                "cellbook.rs:17: fn summary: cell name `load_data` is also used by fn load_data on line 3",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_clean_notebook_has_no_findings() {
+        let source = r#"#[init]
+async fn setup() -> Result<()> {
+    store!(config = Config::default())?;
+    Ok(())
+}
+
+#[cell]
+async fn report() -> Result<()> {
+    let config: Config = load!(config)?;
+    Ok(())
+}
+"#;
+        assert_eq!(lint_source(source), []);
+    }
+}
@@ -1,178 +1,803 @@
 //! Dynamic library loading for cellbook.
 
+use std::collections::HashMap;
+use std::ffi::{CStr, CString, c_char, c_void};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime};
 
 use futures::future::BoxFuture;
 use libloading::{Library, Symbol};
+use parking_lot::Mutex;
+use serde::Deserialize;
 
+use crate::capture::capture_stdout;
 use crate::errors::{Error, Result};
-use crate::store;
+use crate::failure::CellFailure;
+use crate::ffi::FfiFuture;
+use crate::host::HostFns;
+use crate::tui::config::{self, BuildConfig};
+use crate::{fast_build, resources, runs, store, tasks, workspace};
+
+/// Interface version this runner calls notebooks with.
+/// Must match `cellbook::registry::ABI_VERSION`.
+pub const ABI_VERSION: u32 = 9;
 
 static RELOAD_COUNTER: AtomicU64 = AtomicU64::new(0);
+static BLOCKING_CELLS: AtomicBool = AtomicBool::new(false);
+static SANDBOX_CELLS: AtomicBool = AtomicBool::new(false);
+
+/// Working directory of sandboxed cells, relative to the project root.
+pub const SANDBOX_DIR: &str = ".cellbook/scratch";
+
+/// Poll every cell on a blocking thread, as if marked `#[cell(blocking)]`.
+pub fn set_blocking_cells(enabled: bool) {
+    BLOCKING_CELLS.store(enabled, Ordering::Relaxed);
+}
+
+/// Give every cell without a `cwd` [`SANDBOX_DIR`] as its working directory, as if marked `#[cell(sandbox)]`.
+pub fn set_sandbox_cells(enabled: bool) {
+    SANDBOX_CELLS.store(enabled, Ordering::Relaxed);
+}
+
+pub fn sandbox_cells() -> bool {
+    SANDBOX_CELLS.load(Ordering::Relaxed)
+}
+
+/// How long a `#[teardown]` function may run before exit or reload goes ahead without it.
+const TEARDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Stable cell identifier derived from the notebook and cell name.
+///
+/// Unlike list positions, IDs keep referring to the same cell when a reload
+/// inserts or removes cells above it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CellId(u64);
+
+impl CellId {
+    pub fn new(notebook: &str, name: &str) -> Self {
+        let mut hasher = DefaultHasher::new();
+        notebook.hash(&mut hasher);
+        name.hash(&mut hasher);
+        Self(hasher.finish())
+    }
+}
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct CellInfo {
+    pub id: CellId,
     pub name: String,
     pub line: u32,
+    /// Run in a worker process, see [`isolation`](crate::isolation).
+    pub isolated: bool,
+    /// Left out of the TUI cell list unless hidden cells are shown.
+    pub hidden: bool,
+    /// A plain `fn` or `#[cell(blocking)]` cell, polled on a blocking thread.
+    /// See [`set_blocking_cells`] to run every cell this way.
+    pub blocking: bool,
+    /// Working directory from `#[cell(cwd = "...")]`, relative to the project root.
+    pub cwd: Option<String>,
+    /// Work in [`SANDBOX_DIR`], see [`set_sandbox_cells`] for every cell to do so.
+    pub sandbox: bool,
+    /// A `#[cell(service)]` that runs until stopped, as a background task beside other cells.
+    pub service: bool,
+    /// Display name from `#[cell(name = "...")]`.
+    pub title: Option<String>,
+    /// Description from `#[cell(desc = "...")]`.
+    pub description: Option<String>,
+    /// The function's `///` doc comment.
+    pub doc: Option<String>,
+    /// Times a failed run is retried, from `#[cell(retries = 3)]`.
+    pub retries: u32,
+    /// Wait before the first retry from `#[cell(backoff_ms = 1000)]`, doubled
+    /// before each later one.
+    pub backoff_ms: u64,
 }
 
-type CellFn = fn(
-    store::StoreFn,
-    store::LoadFn,
-    store::RemoveFn,
-    store::ListFn,
-) -> BoxFuture<'static, std::result::Result<(), Box<dyn std::error::Error + Send + Sync>>>;
-type InitFn = fn() -> BoxFuture<'static, std::result::Result<(), Box<dyn std::error::Error + Send + Sync>>>;
+impl CellInfo {
+    pub fn new(notebook: &str, name: impl Into<String>, line: u32) -> Self {
+        let name = name.into();
+        Self {
+            id: CellId::new(notebook, &name),
+            name,
+            line,
+            isolated: false,
+            hidden: false,
+            blocking: false,
+            cwd: None,
+            sandbox: false,
+            service: false,
+            title: None,
+            description: None,
+            doc: None,
+            retries: 0,
+            backoff_ms: 0,
+        }
+    }
 
-type GetCellsFn = unsafe extern "Rust" fn() -> Vec<(String, u32, CellFn)>;
-type GetInitFn = unsafe extern "Rust" fn() -> (String, u32, InitFn);
+    /// Working directory of the cell relative to the project root, if not the root itself.
+    pub fn working_dir(&self) -> Option<&str> {
+        match &self.cwd {
+            Some(cwd) => Some(cwd),
+            None => (self.sandbox || sandbox_cells()).then_some(SANDBOX_DIR),
+        }
+    }
 
-type CellResult = std::result::Result<(), Box<dyn std::error::Error + Send + Sync>>;
+    /// The display name if the cell has one, otherwise the function name.
+    pub fn display_name(&self) -> &str {
+        self.title.as_deref().unwrap_or(&self.name)
+    }
+}
 
-type LoadedSymbols = (Vec<CellInfo>, Vec<CellFn>, String, u32, InitFn);
+/// Mirrors `cellbook::registry::CellFn`.
+type CellFn = extern "C" fn(HostFns) -> FfiFuture;
+
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
+type VersionFn = unsafe extern "C" fn() -> *const c_char;
+type ManifestFn = unsafe extern "C" fn() -> *const c_char;
+type CellFnLookup = unsafe extern "C" fn(*const c_char) -> *const c_void;
+
+pub type CellResult = std::result::Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+/// Cells of a notebook as described by `__cellbook_manifest`.
+///
+/// Mirrors `cellbook::registry::Manifest`, which the notebook serializes to JSON.
+#[derive(Deserialize)]
+struct Manifest {
+    init: ManifestCell,
+    teardown: Option<ManifestCell>,
+    cells: Vec<ManifestCell>,
+    /// Runner settings declared with `#[init(...)]` as `(key, TOML value)` pairs.
+    #[serde(default)]
+    config: Vec<(String, String)>,
+}
+
+#[derive(Deserialize)]
+struct ManifestCell {
+    name: String,
+    line: u32,
+    #[serde(default)]
+    isolated: bool,
+    #[serde(default)]
+    hidden: bool,
+    #[serde(default)]
+    blocking: bool,
+    cwd: Option<String>,
+    #[serde(default)]
+    sandbox: bool,
+    #[serde(default)]
+    service: bool,
+    title: Option<String>,
+    description: Option<String>,
+    doc: Option<String>,
+    #[serde(default)]
+    retries: u32,
+    #[serde(default)]
+    backoff_ms: u64,
+}
 
-/// SAFETY: The caller must ensure the library exports valid `__cellbook_get_cells`
-/// and `__cellbook_get_init` symbols with the expected signatures.
-unsafe fn load_symbols(library: &Library) -> Result<LoadedSymbols> {
-    let get_cells: Symbol<GetCellsFn> = unsafe {
+/// Cells and functions read from a notebook library.
+struct LoadedSymbols {
+    /// Visible cells, init first.
+    visible: Vec<CellInfo>,
+    /// Cell functions in the order of `visible`, minus init.
+    cell_fns: Vec<CellFn>,
+    init_fn: CellFn,
+    teardown: Option<(String, CellFn)>,
+    config: Vec<(String, String)>,
+}
+
+/// Read the notebook's manifest and look up each function it lists.
+///
+/// Only C strings and pointers cross the boundary here, so a runner built by
+/// another compiler than the notebook can still read which cells it has.
+///
+/// SAFETY: The caller must ensure the library exports valid `__cellbook_manifest`
+/// and `__cellbook_cell_fn` symbols, which the ABI check establishes.
+unsafe fn load_symbols(library: &Library, notebook: &str) -> Result<LoadedSymbols> {
+    unsafe { check_abi(library, "Notebook library") }?;
+    let manifest: Symbol<ManifestFn> = unsafe {
         library
-            .get(b"__cellbook_get_cells")
-            .map_err(|e| Error::LibLoad(format!("Symbol not found: {}", e)))?
+            .get(b"__cellbook_manifest")
+            .map_err(|_| Error::SymbolMissing("__cellbook_manifest"))?
     };
-    let get_init: Symbol<GetInitFn> = unsafe {
+    let lookup: Symbol<CellFnLookup> = unsafe {
         library
-            .get(b"__cellbook_get_init")
-            .map_err(|e| Error::LibLoad(format!("Symbol not found: {}", e)))?
+            .get(b"__cellbook_cell_fn")
+            .map_err(|_| Error::SymbolMissing("__cellbook_cell_fn"))?
+    };
+
+    let json = unsafe { CStr::from_ptr(manifest()) }.to_string_lossy();
+    let manifest: Manifest =
+        serde_json::from_str(&json).map_err(|e| Error::LibLoad(format!("Invalid cell manifest: {}", e)))?;
+
+    let cell_fn = |name: &str| -> Result<CellFn> {
+        let c_name = CString::new(name).map_err(|_| Error::CellNotFound(name.to_string()))?;
+        let func = unsafe { lookup(c_name.as_ptr()) };
+        if func.is_null() {
+            return Err(Error::CellNotFound(name.to_string()));
+        }
+        // SAFETY: Non-null results of `__cellbook_cell_fn` are cell functions.
+        Ok(unsafe { std::mem::transmute::<*const c_void, CellFn>(func) })
     };
 
-    let raw_cells = unsafe { get_cells() };
-    let mut cells = Vec::new();
-    let mut cell_fns = Vec::new();
+    let init_fn = cell_fn(&manifest.init.name)?;
+    let teardown = match manifest.teardown {
+        Some(teardown) => Some((teardown.name.clone(), cell_fn(&teardown.name)?)),
+        None => None,
+    };
 
-    for (name, line, func) in raw_cells {
-        cells.push(CellInfo { name, line });
-        cell_fns.push(func);
+    let mut cells = manifest.cells;
+    cells.sort_by_key(|c| c.line);
+    let mut visible = Vec::with_capacity(cells.len() + 1);
+    visible.push(CellInfo::new(notebook, manifest.init.name, manifest.init.line));
+    let mut cell_fns = Vec::with_capacity(cells.len());
+    for cell in cells {
+        cell_fns.push(cell_fn(&cell.name)?);
+        let mut info = CellInfo::new(notebook, cell.name, cell.line);
+        info.isolated = cell.isolated;
+        info.hidden = cell.hidden;
+        info.blocking = cell.blocking;
+        info.cwd = cell.cwd;
+        info.sandbox = cell.sandbox;
+        info.service = cell.service;
+        info.title = cell.title;
+        info.description = cell.description;
+        info.doc = cell.doc;
+        info.retries = cell.retries;
+        info.backoff_ms = cell.backoff_ms;
+        visible.push(info);
     }
 
-    let mut indices: Vec<usize> = (0..cells.len()).collect();
-    indices.sort_by_key(|&i| cells[i].line);
+    check_unique_names(&visible)?;
 
-    let sorted_cells: Vec<_> = indices.iter().map(|&i| cells[i].clone()).collect();
-    let sorted_fns: Vec<_> = indices.iter().map(|&i| cell_fns[i]).collect();
+    Ok(LoadedSymbols {
+        visible,
+        cell_fns,
+        init_fn,
+        teardown,
+        config: manifest.config,
+    })
+}
+
+/// Refuse a notebook or plugin built against a cellbook whose symbols and types
+/// differ from what the runner expects, before calling any other symbol.
+/// `name` describes the library in errors.
+///
+/// The symbols are emitted by `#[init]` and `export_plugin!`. Everything else
+/// crossing the boundary is C-ABI, so the compiler that built the library
+/// doesn't need to match the runner's.
+///
+/// SAFETY: `__cellbook_abi_version` and `__cellbook_version`, if exported, must
+/// have the expected `extern "C"` signatures, which every cellbook version keeps.
+pub unsafe fn check_abi(library: &Library, name: &str) -> Result<()> {
+    let found = unsafe { library.get::<AbiVersionFn>(b"__cellbook_abi_version") }
+        .ok()
+        .map(|abi_version| unsafe { abi_version() });
+    let cellbook = unsafe { library.get::<VersionFn>(b"__cellbook_version") }
+        .ok()
+        .map(|version| {
+            unsafe { CStr::from_ptr(version()) }
+                .to_string_lossy()
+                .into_owned()
+        });
+    check_handshake(name, found, cellbook)
+}
 
-    let (init_name, init_line, init_fn) = unsafe { get_init() };
-    Ok((sorted_cells, sorted_fns, init_name, init_line, init_fn))
+fn check_handshake(name: &str, found: Option<u32>, cellbook: Option<String>) -> Result<()> {
+    if found == Some(ABI_VERSION) {
+        return Ok(());
+    }
+    let runner = env!("CARGO_PKG_VERSION");
+    match cellbook {
+        Some(cellbook) if cellbook != runner => Err(Error::VersionSkew {
+            library: name.to_string(),
+            runner: runner.to_string(),
+            cellbook,
+        }),
+        _ => Err(Error::AbiMismatch {
+            library: name.to_string(),
+            expected: ABI_VERSION,
+            found,
+        }),
+    }
+}
+
+/// Absolute working directory of a cell, as handed to it in [`HostFns`].
+///
+/// The process working directory is shared by every cell and the runner, so
+/// it is never switched; cells resolve their paths against this instead.
+/// Only the sandbox is created, a mistyped `cwd` should fail.
+fn cell_dir(dir: &str, create: bool) -> Result<&'static str> {
+    /// Directories handed out so far, leaked once each since `HostFns` is `Copy`.
+    static DIRS: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+    let dir = std::env::current_dir()?.join(dir);
+    if create {
+        std::fs::create_dir_all(&dir)?;
+    } else if !dir.is_dir() {
+        let message = format!("working directory {} doesn't exist", dir.display());
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, message).into());
+    }
+    let dir = dir.to_string_lossy();
+    let mut dirs = DIRS.lock();
+    if let Some(known) = dirs.iter().find(|known| **known == dir) {
+        return Ok(known);
+    }
+    let leaked: &'static str = Box::leak(dir.into_owned().into_boxed_str());
+    dirs.push(leaked);
+    Ok(leaked)
+}
+
+/// Refuse notebooks where two cells (or a cell and init) share a name,
+/// since outputs, counts and diffs are keyed by cell name.
+fn check_unique_names(cells: &[CellInfo]) -> Result<()> {
+    let mut lines: HashMap<&str, Vec<u32>> = HashMap::new();
+    for cell in cells {
+        lines.entry(&cell.name).or_default().push(cell.line);
+    }
+
+    let mut duplicates: Vec<String> = cells
+        .iter()
+        .filter_map(|cell| {
+            let lines = lines.remove(cell.name.as_str())?;
+            (lines.len() > 1).then(|| {
+                let lines: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+                format!("'{}' at lines {}", cell.name, lines.join(", "))
+            })
+        })
+        .collect();
+    duplicates.sort();
+
+    if duplicates.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::LibLoad(format!(
+            "Duplicate cell names: {}",
+            duplicates.join("; ")
+        )))
+    }
+}
+
+/// Copy the library to a unique path to bypass dlopen caching and load it.
+fn load_unique_copy(lib_path: &Path) -> Result<(Library, PathBuf)> {
+    let counter = RELOAD_COUNTER.fetch_add(1, Ordering::SeqCst);
+    let unique_path = unique_copy_path(lib_path, counter);
+
+    std::fs::copy(lib_path, &unique_path)
+        .map_err(|e| Error::LibLoad(format!("Failed to copy library for reload: {}", e)))?;
+
+    // SAFETY: We trust the user's cellbook code to be safe (dylib).
+    let library = unsafe { Library::new(&unique_path) }.map_err(|e| {
+        let _ = std::fs::remove_file(&unique_path);
+        Error::LibLoad(format!("Failed to load {}: {}", unique_path.display(), e))
+    })?;
+
+    Ok((library, unique_path))
+}
+
+/// Path for the `counter`th copy of the library.
+///
+/// Windows locks loaded DLLs, so copies go to the temp directory where they
+/// can't block cargo from replacing files in `target`. Elsewhere they stay next
+/// to the original, since the temp directory may be mounted `noexec`.
+fn unique_copy_path(lib_path: &Path, counter: u64) -> PathBuf {
+    if cfg!(target_os = "windows") {
+        let file_name = lib_path.file_name().unwrap_or_default().to_string_lossy();
+        std::env::temp_dir().join(format!(
+            "cellbook-{}-{}-{}",
+            std::process::id(),
+            counter,
+            file_name
+        ))
+    } else {
+        PathBuf::from(format!("{}.reload.{}", lib_path.display(), counter))
+    }
+}
+
+/// Key identifying the notebook in cell IDs; stable across reload copies.
+fn notebook_key(lib_path: &Path) -> String {
+    lib_path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Modification time and size of a library file, which change with every build.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct LibraryVersion {
+    modified: SystemTime,
+    len: u64,
+}
+
+impl LibraryVersion {
+    fn read(path: &Path) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        Some(Self {
+            modified: metadata.modified().ok()?,
+            len: metadata.len(),
+        })
+    }
 }
 
 pub struct LoadedLibrary {
-    _library: Library,
-    cells: Vec<CellInfo>,
+    /// Only `None` while dropping, so the library is unloaded before its copies are deleted.
+    library: Option<Library>,
+    /// Init followed by cells in source order, cached per load.
+    visible: Vec<CellInfo>,
     cell_fns: Vec<CellFn>,
-    init_name: String,
-    init_line: u32,
-    init_fn: InitFn,
+    init_fn: CellFn,
+    teardown: Option<(String, CellFn)>,
+    config: Vec<(String, String)>,
     lib_path: PathBuf,
+    /// Version of the file at `lib_path` when it was loaded, read before
+    /// loading so a build finishing meanwhile shows up as stale.
+    version: Option<LibraryVersion>,
+    /// Held by every cell future and blocking thread running the library's
+    /// code, see [`in_use`](Self::in_use).
+    references: Arc<()>,
     temp_paths: Vec<PathBuf>,
 }
 
 impl Drop for LoadedLibrary {
     fn drop(&mut self) {
+        self.unload();
         for path in &self.temp_paths {
             let _ = std::fs::remove_file(path);
         }
     }
 }
 
+/// Call a cell, init or teardown function, failing with the [`CellFailure`] it reports.
+fn call(func: CellFn, host: HostFns) -> BoxFuture<'static, CellResult> {
+    let future = func(host);
+    Box::pin(async move {
+        future
+            .await
+            .map(drop)
+            .map_err(|report| CellFailure::from_report(&report).into())
+    })
+}
+
+/// `future`, counted in `references` until it completes or is dropped, or for a
+/// `blocking` cell until its thread returns, which aborting the task doesn't stop.
+fn holding(
+    references: &Arc<()>,
+    future: BoxFuture<'static, CellResult>,
+    blocking: bool,
+) -> BoxFuture<'static, CellResult> {
+    let reference = Arc::clone(references);
+    if !blocking {
+        return Box::pin(async move {
+            let _reference = reference;
+            future.await
+        });
+    }
+    // Long polls on a runtime thread would stall drawing and events.
+    Box::pin(async move {
+        store::run_blocking(move || {
+            let _reference = reference;
+            tokio::runtime::Handle::current().block_on(future)
+        })
+        .await
+        .unwrap_or_else(|e| Err(format!("cell panicked: {}", e).into()))
+    })
+}
+
 impl LoadedLibrary {
     pub fn load(lib_path: &Path) -> Result<Self> {
+        let started = Instant::now();
+        let version = LibraryVersion::read(lib_path);
+        // A loaded DLL can't be overwritten, so rebuilds would fail.
+        if cfg!(target_os = "windows") {
+            let loaded = Self::load_copy(lib_path)?;
+            runs::loaded(started.elapsed());
+            return Ok(loaded);
+        }
+
         // SAFETY: We trust the user's cellbook code to be safe (dylib).
         let library = unsafe { Library::new(lib_path) }
             .map_err(|e| Error::LibLoad(format!("Failed to load {}: {}", lib_path.display(), e)))?;
 
-        let (cells, cell_fns, init_name, init_line, init_fn) = unsafe { load_symbols(&library) }?;
+        let LoadedSymbols {
+            visible,
+            cell_fns,
+            init_fn,
+            teardown,
+            config,
+        } = unsafe { load_symbols(&library, &notebook_key(lib_path)) }?;
+        runs::loaded(started.elapsed());
 
         Ok(LoadedLibrary {
-            _library: library,
-            cells,
+            library: Some(library),
+            visible,
             cell_fns,
-            init_name,
-            init_line,
             init_fn,
+            teardown,
+            config,
             lib_path: lib_path.to_path_buf(),
+            version,
+            references: Arc::new(()),
             temp_paths: Vec::new(),
         })
     }
 
-    pub fn reload(&mut self) -> Result<()> {
-        // Copy to a unique path to bypass dlopen caching.
-        let counter = RELOAD_COUNTER.fetch_add(1, Ordering::SeqCst);
-        let unique_path = PathBuf::from(format!("{}.reload.{}", self.lib_path.display(), counter));
-
-        std::fs::copy(&self.lib_path, &unique_path)
-            .map_err(|e| Error::LibLoad(format!("Failed to copy library for reload: {}", e)))?;
+    /// Load a copy of the library, so a later build at the same path isn't
+    /// served from the dlopen cache. The copy is deleted on drop.
+    pub fn load_copy(lib_path: &Path) -> Result<Self> {
+        let version = LibraryVersion::read(lib_path);
+        let (library, unique_path) = load_unique_copy(lib_path)?;
+        let LoadedSymbols {
+            visible,
+            cell_fns,
+            init_fn,
+            teardown,
+            config,
+        } = unsafe { load_symbols(&library, &notebook_key(lib_path)) }?;
 
-        let library = unsafe { Library::new(&unique_path) }.map_err(|e| {
-            let _ = std::fs::remove_file(&unique_path);
-            Error::LibLoad(format!("Failed to load {}: {}", unique_path.display(), e))
-        })?;
+        Ok(LoadedLibrary {
+            library: Some(library),
+            visible,
+            cell_fns,
+            init_fn,
+            teardown,
+            config,
+            lib_path: lib_path.to_path_buf(),
+            version,
+            references: Arc::new(()),
+            temp_paths: vec![unique_path],
+        })
+    }
 
-        let (cells, cell_fns, init_name, init_line, init_fn) = unsafe { load_symbols(&library) }?;
+    pub fn reload(&mut self) -> Result<()> {
+        let started = Instant::now();
+        let version = LibraryVersion::read(&self.lib_path);
+        let (library, unique_path) = load_unique_copy(&self.lib_path)?;
 
+        let LoadedSymbols {
+            visible,
+            cell_fns,
+            init_fn,
+            teardown,
+            config,
+        } = unsafe { load_symbols(&library, &notebook_key(&self.lib_path)) }?;
+
+        // Unloading the previous copy first, which Windows only allows
+        // deleting after that.
+        self.unload();
+        self.library = Some(library);
+        self.references = Arc::new(());
+        for path in self.temp_paths.drain(..) {
+            let _ = std::fs::remove_file(path);
+        }
         self.temp_paths.push(unique_path);
-        self._library = library;
-        self.cells = cells;
+        self.visible = visible;
         self.cell_fns = cell_fns;
-        self.init_name = init_name;
-        self.init_line = init_line;
         self.init_fn = init_fn;
+        self.teardown = teardown;
+        self.config = config;
+        self.version = version;
+        runs::loaded(started.elapsed());
+        // The new build may come from another commit or feature set.
+        runs::refresh();
 
         Ok(())
     }
 
+    /// Number of cell futures and blocking threads that still run code from
+    /// the library, which has to be zero before it is unmapped.
+    pub fn in_use(&self) -> usize {
+        Arc::strong_count(&self.references) - 1
+    }
+
+    /// Unmap the library, unless code from it may still run. A blocking
+    /// cell keeps running on its thread after its task is aborted, so such a
+    /// library is kept mapped for the rest of the process instead.
+    fn unload(&mut self) {
+        let Some(library) = self.library.take() else {
+            return;
+        };
+        if self.in_use() > 0 {
+            std::mem::forget(library);
+        } else {
+            drop(library);
+        }
+    }
+
+    /// Whether a newer build is on disk than the one loaded, such as one
+    /// that finished without the reload that should have followed.
+    pub fn is_stale(&self) -> bool {
+        LibraryVersion::read(&self.lib_path).is_some_and(|current| Some(current) != self.version)
+    }
+
+    pub fn lib_path(&self) -> &Path {
+        &self.lib_path
+    }
+
+    /// Runner settings declared by the notebook as `(key, TOML value)` pairs.
+    pub fn config(&self) -> &[(String, String)] {
+        &self.config
+    }
+
+    /// Init followed by all cells in source order.
+    pub fn visible_cells(&self) -> &[CellInfo] {
+        &self.visible
+    }
+
     pub fn cells(&self) -> &[CellInfo] {
-        &self.cells
+        &self.visible[1..]
     }
 
     /// Create a future for running a cell without awaiting it.
-    pub fn cell_future(&self, name: &str) -> Result<BoxFuture<'static, CellResult>> {
+    pub fn cell_future(&self, cell: &CellInfo) -> Result<BoxFuture<'static, CellResult>> {
+        self.cell_attempts(cell)?()
+    }
+
+    /// Create a function that makes a new future for running a cell each time it
+    /// is called, for retrying the cell without borrowing the library.
+    /// `cell` may come from an earlier library, and is looked up by its id.
+    pub fn cell_attempts(
+        &self,
+        cell: &CellInfo,
+    ) -> Result<impl Fn() -> Result<BoxFuture<'static, CellResult>> + Send + 'static> {
         let idx = self
-            .cells
+            .cells()
             .iter()
-            .position(|c| c.name == name)
-            .ok_or_else(|| Error::LibLoad(format!("Cell '{}' not found", name)))?;
-
-        let cell_fn = self.cell_fns[idx];
-        Ok(cell_fn(
-            store::get_store_fn(),
-            store::get_load_fn(),
-            store::get_remove_fn(),
-            store::get_list_fn(),
-        ))
+            .position(|c| c.id == cell.id)
+            .ok_or_else(|| Error::CellNotFound(cell.name.clone()))?;
+
+        let func = self.cell_fns[idx];
+        let cell = &self.cells()[idx];
+        let dir = cell
+            .working_dir()
+            .map(|dir| cell_dir(dir, cell.cwd.is_none()))
+            .transpose()?;
+        let blocking = cell.blocking || BLOCKING_CELLS.load(Ordering::Relaxed);
+        let references = Arc::clone(&self.references);
+        Ok(move || {
+            let future = call(func, HostFns::new().in_dir(dir));
+            Ok(holding(&references, future, blocking))
+        })
     }
 
     /// Create a future for running the init function without awaiting it.
     pub fn init_future(&self) -> BoxFuture<'static, CellResult> {
-        (self.init_fn)()
+        holding(&self.references, call(self.init_fn, HostFns::new()), false)
     }
 
-    pub fn init_name(&self) -> &str {
-        &self.init_name
+    pub fn init(&self) -> &CellInfo {
+        &self.visible[0]
     }
 
-    pub fn init_line(&self) -> u32 {
-        self.init_line
+    /// Run the `#[teardown]` function, if the notebook has one.
+    /// Returns its stdout and result.
+    pub async fn run_teardown(&self) -> Option<(String, std::result::Result<(), String>)> {
+        let (name, func) = self.teardown.as_ref()?;
+        let future = call(*func, HostFns::new());
+        let (stdout, result) = capture_stdout(|| tokio::time::timeout(TEARDOWN_TIMEOUT, future)).await;
+        let result = match result {
+            Ok(result) => result.map_err(|e| format!("{} failed: {}", name, e)),
+            Err(_) => Err(format!(
+                "{} timed out after {}s",
+                name,
+                TEARDOWN_TIMEOUT.as_secs()
+            )),
+        };
+        Some((stdout, result))
+    }
+
+    /// Run the `#[teardown]` function, then abort background tasks and drop
+    /// shared resources, which reference code from the library.
+    /// Call before reloading or unloading it.
+    pub async fn release(&self) -> Released {
+        let (teardown_stdout, result) = self.run_teardown().await.unwrap_or((String::new(), Ok(())));
+        tasks::abort_all().await;
+        Released {
+            teardown_stdout,
+            teardown_error: result.err(),
+            dropped: resources::clear(),
+        }
     }
 }
 
-pub fn find_dylib_path() -> Result<PathBuf> {
-    let cargo_toml = Path::new("Cargo.toml");
-    if !cargo_toml.exists() {
+/// What [`LoadedLibrary::release`] ran and dropped.
+#[derive(Debug, Default)]
+pub struct Released {
+    /// What the `#[teardown]` function printed.
+    pub teardown_stdout: String,
+    pub teardown_error: Option<String>,
+    /// Number of shared resources dropped.
+    pub dropped: usize,
+}
+
+impl Released {
+    /// Write the teardown output, its error and the dropped resources to `out`.
+    pub fn report(&self, out: &mut impl Write) {
+        let _ = write!(out, "{}", self.teardown_stdout);
+        if let Some(e) = &self.teardown_error {
+            let _ = writeln!(out, "Teardown: {}", e);
+        }
+        if self.dropped > 0 {
+            let _ = writeln!(out, "Dropped {} resource(s)", self.dropped);
+        }
+    }
+}
+
+/// The package in the current directory, as `cargo metadata` resolves it.
+pub fn package() -> Result<workspace::Package> {
+    package_of(&metadata(&config::load_build())?).cloned()
+}
+
+/// `cargo metadata` for the notebook in the current directory.
+fn metadata(build: &BuildConfig) -> Result<workspace::Metadata> {
+    if !Path::new("Cargo.toml").exists() {
         return Err(Error::NoCargoToml);
     }
+    workspace::metadata(&build.env)
+}
+
+fn package_of(metadata: &workspace::Metadata) -> Result<&workspace::Package> {
+    metadata
+        .package(Path::new("Cargo.toml"))
+        .ok_or(Error::NotAPackage)
+}
+
+/// Crate name of the notebook library in the current directory.
+pub fn lib_name() -> Result<String> {
+    lib_name_of(&package()?)
+}
 
-    let content = std::fs::read_to_string(cargo_toml)?;
-    let name = extract_package_name(&content)?;
+fn lib_name_of(package: &workspace::Package) -> Result<String> {
+    package
+        .lib_name()
+        .map(String::from)
+        .ok_or_else(|| Error::NoLibTarget(package.name.clone()))
+}
+
+/// Values passed to notebook code, by the name of the environment variable
+/// that carries them outside the runner.
+static NOTEBOOK_VARS: Mutex<Vec<(&'static str, String)>> = Mutex::new(Vec::new());
+
+/// Pass a value to notebook code through [`HostFns`], replacing an earlier one.
+pub fn set_notebook_var(name: &'static str, value: impl Into<String>) {
+    let value = value.into();
+    let mut vars = NOTEBOOK_VARS.lock();
+    match vars.iter_mut().find(|(n, _)| *n == name) {
+        Some((_, v)) => *v = value,
+        None => vars.push((name, value)),
+    }
+}
+
+/// A value passed to notebook code. Isolation workers get the runner's values
+/// in their environment, so unset names fall back to it.
+pub fn notebook_var(name: &str) -> Option<String> {
+    let set = NOTEBOOK_VARS
+        .lock()
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, v)| v.clone());
+    set.or_else(|| std::env::var(name).ok())
+}
+
+/// Every value passed to notebook code, for the environment of an isolation worker.
+pub fn notebook_vars() -> Vec<(&'static str, String)> {
+    NOTEBOOK_VARS.lock().clone()
+}
+
+/// Tell notebook code where the project root is, for `cellbook::path!`.
+/// Must be called before the library is loaded and any cell runs.
+pub fn set_project_root() -> Result<()> {
+    let root = std::env::current_dir()?;
+    set_notebook_var("CELLBOOK_PROJECT_ROOT", root.to_string_lossy());
+    Ok(())
+}
+
+/// File name of the dynamic library built for a library, named by its crate name
+/// or by a package name, whose hyphens cargo replaces with underscores.
+pub fn dylib_file_name(name: &str) -> String {
     let lib_name = name.replace('-', "_");
 
     let ext = if cfg!(target_os = "macos") {
@@ -183,49 +808,39 @@ pub fn find_dylib_path() -> Result<PathBuf> {
         "so"
     };
 
-    let lib_filename = if cfg!(target_os = "windows") {
+    if cfg!(target_os = "windows") {
         format!("{}.{}", lib_name, ext)
     } else {
         format!("lib{}.{}", lib_name, ext)
-    };
-
-    let local_path = Path::new("target/debug").join(&lib_filename);
-    if local_path.exists() {
-        return Ok(local_path);
     }
+}
 
-    // Check for workspace root.
-    let mut current = std::env::current_dir()?;
-    loop {
-        let parent = current.parent();
-        if parent.is_none() {
-            break;
-        }
-        let parent = parent.unwrap();
-        let parent_cargo = parent.join("Cargo.toml");
-        if parent_cargo.exists()
-            && let Ok(content) = std::fs::read_to_string(&parent_cargo)
-            && content.contains("[workspace]")
-        {
-            let workspace_path = parent.join("target/debug").join(&lib_filename);
-            return Ok(workspace_path);
-        }
-        current = parent.to_path_buf();
+/// Directory under the target directory the notebook's profile builds into:
+/// the dev profile's `debug`, or that of the `fast_build` profile.
+fn profile_dir(build: &BuildConfig) -> &'static str {
+    if build.fast_build {
+        fast_build::PROFILE
+    } else {
+        "debug"
     }
-
-    Ok(local_path)
 }
 
-fn extract_package_name(cargo_toml: &str) -> Result<String> {
-    let parsed: toml::Value =
-        toml::from_str(cargo_toml).map_err(|e| Error::LibLoad(format!("Invalid Cargo.toml: {}", e)))?;
+/// Directory cargo builds the notebook into: `target_dir` from `[build]`, else
+/// the one `cargo metadata` reports.
+fn target_dir(build: &BuildConfig, metadata: &workspace::Metadata) -> PathBuf {
+    build
+        .target_dir
+        .clone()
+        .unwrap_or_else(|| metadata.target_directory.clone())
+}
 
-    parsed
-        .get("package")
-        .and_then(|p| p.get("name"))
-        .and_then(|n| n.as_str())
-        .map(String::from)
-        .ok_or_else(|| Error::LibLoad("Could not find package name in Cargo.toml".to_string()))
+pub fn find_dylib_path() -> Result<PathBuf> {
+    let build = config::load_build();
+    let metadata = metadata(&build)?;
+    let lib_filename = dylib_file_name(&lib_name_of(package_of(&metadata)?)?);
+    Ok(target_dir(&build, &metadata)
+        .join(profile_dir(&build))
+        .join(lib_filename))
 }
 
 #[cfg(test)]
@@ -233,12 +848,153 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_extract_package_name() {
-        let toml = r#"
-[package]
-name = "my-project"
-version = "0.1.0"
-"#;
-        assert_eq!(extract_package_name(toml).unwrap(), "my-project");
+    fn test_manifest_fields_default() {
+        let json = r#"{
+            "init": {"name": "setup", "line": 3},
+            "teardown": null,
+            "cells": [{"name": "load", "line": 8, "hidden": true, "title": "Load data"}]
+        }"#;
+        let manifest: Manifest = serde_json::from_str(json).unwrap();
+        assert_eq!(manifest.init.name, "setup");
+        assert!(manifest.config.is_empty());
+        let cell = &manifest.cells[0];
+        assert!(cell.hidden && !cell.isolated && !cell.blocking);
+        assert_eq!(cell.title.as_deref(), Some("Load data"));
+        assert_eq!(cell.description, None);
+    }
+
+    #[test]
+    fn test_working_dir_prefers_cwd() {
+        let mut cell = CellInfo::new("nb", "load", 1);
+        assert_eq!(cell.working_dir(), None);
+        cell.sandbox = true;
+        assert_eq!(cell.working_dir(), Some(SANDBOX_DIR));
+        cell.cwd = Some("data".to_string());
+        assert_eq!(cell.working_dir(), Some("data"));
+    }
+
+    #[test]
+    fn test_cell_dir_creates_only_the_sandbox() {
+        let dir = std::env::temp_dir().join(format!("cellbook-cell-dir-{}", std::process::id()));
+        let dir_str = dir.to_str().unwrap();
+        assert!(cell_dir(dir_str, false).is_err());
+
+        let created = cell_dir(dir_str, true).unwrap();
+        assert!(dir.is_dir());
+        assert_eq!(created, dir_str);
+        assert!(std::ptr::eq(created, cell_dir(dir_str, false).unwrap()));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_check_handshake() {
+        let runner = env!("CARGO_PKG_VERSION").to_string();
+        let name = "Notebook library";
+        assert!(check_handshake(name, Some(ABI_VERSION), Some("0.0.1".to_string())).is_ok());
+        assert!(check_handshake(name, Some(ABI_VERSION), None).is_ok());
+        assert!(matches!(
+            check_handshake(name, None, None),
+            Err(Error::AbiMismatch { found: None, .. })
+        ));
+        assert!(matches!(
+            check_handshake(name, Some(ABI_VERSION + 1), Some(runner)),
+            Err(Error::AbiMismatch { found: Some(_), .. })
+        ));
+        let err = check_handshake("Plugin notify.so", Some(ABI_VERSION + 1), Some("0.0.1".to_string())).unwrap_err();
+        assert!(matches!(err, Error::VersionSkew { .. }));
+        assert!(
+            err.to_string()
+                .starts_with("Plugin notify.so uses cellbook 0.0.1")
+        );
+        assert!(
+            err.to_string()
+                .contains("cargo install cargo-cellbook --version 0.0.1")
+        );
+    }
+
+    #[test]
+    fn test_check_unique_names_lists_all_lines() {
+        let cells = |names: &[(&str, u32)]| -> Vec<CellInfo> {
+            names
+                .iter()
+                .map(|&(name, line)| CellInfo::new("nb", name, line))
+                .collect()
+        };
+
+        assert!(check_unique_names(&cells(&[("setup", 1), ("a", 5), ("b", 9)])).is_ok());
+
+        let err = check_unique_names(&cells(&[("setup", 1), ("a", 5), ("setup", 9), ("a", 12)]))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("'a' at lines 5, 12"), "{}", err);
+        assert!(err.contains("'setup' at lines 1, 9"), "{}", err);
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_unique_copy_path_is_next_to_library() {
+        let path = unique_copy_path(Path::new("target/debug/libnb.so"), 3);
+        assert_eq!(path, Path::new("target/debug/libnb.so.reload.3"));
+    }
+
+    #[test]
+    fn test_library_version_changes_with_a_new_build() {
+        let path = std::env::temp_dir().join(format!("cellbook-version-test-{}.so", std::process::id()));
+        assert_eq!(LibraryVersion::read(&path), None);
+        std::fs::write(&path, "old build").unwrap();
+        let loaded = LibraryVersion::read(&path);
+        assert!(loaded.is_some());
+        assert_eq!(LibraryVersion::read(&path), loaded);
+        std::fs::write(&path, "newer build").unwrap();
+        assert_ne!(LibraryVersion::read(&path), loaded);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_cell_futures_keep_the_library_in_use() {
+        let references = Arc::new(());
+        let in_use = |references: &Arc<()>| Arc::strong_count(references) - 1;
+
+        let completed = holding(&references, Box::pin(async { Ok(()) }), false);
+        assert_eq!(in_use(&references), 1);
+        completed.await.unwrap();
+        assert_eq!(in_use(&references), 0);
+
+        let aborted = tokio::spawn(holding(&references, Box::pin(futures::future::pending()), false));
+        assert_eq!(in_use(&references), 1);
+        aborted.abort();
+        let _ = aborted.await;
+        assert_eq!(in_use(&references), 0);
+
+        // A blocking cell holds the library until its thread returns, even after an abort.
+        let (started_tx, started) = tokio::sync::oneshot::channel();
+        let (release, released) = tokio::sync::oneshot::channel::<()>();
+        let cell = Box::pin(async move {
+            let _ = started_tx.send(());
+            let _ = released.await;
+            Ok(())
+        });
+        let blocking = tokio::spawn(holding(&references, cell, true));
+        started.await.unwrap();
+        blocking.abort();
+        let _ = blocking.await;
+        assert_eq!(in_use(&references), 1);
+        release.send(()).unwrap();
+        for _ in 0..100 {
+            if in_use(&references) == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(in_use(&references), 0);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_unique_copy_path_is_in_temp_dir() {
+        let path = unique_copy_path(Path::new(r"target\debug\nb.dll"), 3);
+        assert!(path.starts_with(std::env::temp_dir()));
+        let file_name = path.file_name().unwrap().to_string_lossy();
+        assert_eq!(file_name, format!("cellbook-{}-3-nb.dll", std::process::id()));
     }
 }
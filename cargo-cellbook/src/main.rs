@@ -1,15 +1,54 @@
+mod artifacts;
+mod capture;
+mod check;
+mod clean;
+mod clipboard;
+mod completions;
+mod docs;
 mod errors;
+mod examples;
+mod export;
+mod failure;
+mod fast_build;
+mod ffi;
+mod graph;
+mod health;
+mod hexdump;
+mod history;
+mod host;
+mod isolation;
+#[cfg(feature = "jupyter")]
+mod kernel;
+mod line_editor;
+mod lint;
 mod loader;
+mod notification;
+mod plugins;
+mod prompt;
+mod resources;
+mod retry;
+mod rpc;
+mod run_info;
+mod runner;
+mod runs;
+mod scratch;
+mod source;
 mod store;
+mod tasks;
+mod terminal;
 mod tui;
+mod util;
 mod watcher;
+mod web;
+mod workspace;
 
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, CommandFactory, Parser, Subcommand};
 use errors::Result;
 use tokio::sync::mpsc;
+use tui::config::UiMode;
 
 #[derive(Parser)]
 #[command(name = "cargo-cellbook")]
@@ -38,20 +77,206 @@ enum Commands {
     Init {
         /// Name of the project
         name: String,
+        /// Add the project to the members of the enclosing cargo workspace
+        #[arg(long)]
+        workspace: bool,
+    },
+    /// Start a project from one of the bundled example notebooks
+    Examples {
+        #[command(subcommand)]
+        command: ExamplesCommand,
     },
     /// Run the cellbook TUI with hot-reloading
-    Run,
+    Run {
+        /// Interface to use (defaults to the `ui` setting)
+        #[arg(long, value_enum)]
+        ui: Option<UiMode>,
+        #[command(flatten)]
+        repro: ReproArgs,
+    },
+    /// Serve the notebook as a web page with hot-reloading
+    Web {
+        /// Port to listen on (defaults to the `web_port` setting)
+        #[arg(long)]
+        port: Option<u16>,
+        /// Address to listen on (defaults to the `web_host` setting)
+        #[arg(long)]
+        host: Option<String>,
+        #[command(flatten)]
+        repro: ReproArgs,
+    },
+    /// Run init and the given cells once (every cell if none are given) and exit
+    Exec {
+        /// Cells to run after init, by name or number
+        cells: Vec<String>,
+        /// Print each cell's status, duration, output and error as JSON
+        #[arg(long)]
+        json: bool,
+        #[command(flatten)]
+        repro: ReproArgs,
+    },
+    /// Drive the notebook from an editor with JSON-RPC over stdin and stdout
+    Rpc {
+        #[command(flatten)]
+        repro: ReproArgs,
+    },
+    /// Run code cells from Jupyter front ends against the notebook's store
+    Kernel {
+        /// Connection file passed by Jupyter
+        #[arg(long, required_unless_present = "install")]
+        connection_file: Option<PathBuf>,
+        /// Register the kernel with Jupyter instead of starting it
+        #[arg(long)]
+        install: bool,
+        #[command(flatten)]
+        repro: ReproArgs,
+    },
+    /// Run every cell once and export a report
+    Export {
+        /// Report format
+        #[arg(long, value_enum, default_value = "html")]
+        format: export::ExportFormat,
+        /// Output file (defaults to cellbook-report.<ext>)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        #[command(flatten)]
+        repro: ReproArgs,
+    },
+    /// Type-check the notebook and print diagnostics grouped by cell
+    Check,
+    /// Check cellbook.rs for common notebook mistakes without building it
+    Lint,
+    /// Draw the graph of store keys passed between cells
+    Graph {
+        /// Print Graphviz DOT instead of text
+        #[arg(long)]
+        dot: bool,
+    },
+    /// Show the recorded provenance of cell runs
+    Runs {
+        #[command(subcommand)]
+        command: RunsCommand,
+    },
+    /// Inspect the context store's recorded history
+    Store {
+        #[command(subcommand)]
+        command: StoreCommand,
+    },
+    /// Remove reload copies, temporary images and store exports left behind
+    Clean {
+        /// Also run `cargo clean`
+        #[arg(long)]
+        cargo: bool,
+    },
+    /// Print a shell completion script
+    Completions {
+        #[arg(value_enum)]
+        shell: completions::Shell,
+    },
+    /// Run one cell in a worker process connected to the runner
+    #[command(hide = true)]
+    Worker {
+        /// Runner port on localhost
+        #[arg(long)]
+        port: u16,
+        /// Cell to run
+        #[arg(long)]
+        cell: String,
+        /// Run the cell in the sandbox directory, as with `sandbox_cells`
+        #[arg(long)]
+        sandbox: bool,
+        /// Notebook library
+        lib: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum ExamplesCommand {
+    /// List the bundled examples
+    List,
+    /// Create a project from an example
+    New {
+        /// Example to start from
+        example: String,
+        /// Name of the project (defaults to the example's name)
+        name: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum RunsCommand {
+    /// List recorded sessions with their commit and outcome
+    List {
+        /// Print the sessions as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show each cell execution of a session
+    Show {
+        /// Session id from `runs list` (defaults to the latest)
+        id: Option<String>,
+        /// Print the executions as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum StoreCommand {
+    /// List the keys the last session left stored, from the recorded history
+    List {
+        /// Print the keys as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show when keys were stored and removed, and by which cell
+    History {
+        /// Key to show, `*` matching any characters (defaults to every key)
+        key: Option<String>,
+        /// Only show changes made by this cell
+        #[arg(long)]
+        cell: Option<String>,
+        /// Print the changes as JSON
+        #[arg(long)]
+        json: bool,
+    },
 }
 
-#[tokio::main]
-async fn main() {
+/// Fixed seed and timestamp, to repeat an earlier run exactly.
+#[derive(Args)]
+struct ReproArgs {
+    /// Seed for `ctx.run_info()` (random if not given)
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Run timestamp for `ctx.run_info()`, in seconds since the Unix epoch (now if not given)
+    #[arg(long)]
+    timestamp: Option<u64>,
+}
+
+impl ReproArgs {
+    fn run_info(&self) -> run_info::RunInfo {
+        run_info::RunInfo::new(self.seed, self.timestamp)
+    }
+}
+
+fn main() {
     let cli = Cli::parse();
 
-    let result = match cli.command {
-        CargoSubcommand::Cellbook(args) => match args.command {
-            Commands::Init { name } => init_project(&name),
-            Commands::Run => run_project().await,
-        },
+    // anyhow and std read this from the environment, which is only safe to change
+    // while the process is single-threaded, so the notebook's `#[init]` config can't set it.
+    if tui::config::load_files().general.backtrace {
+        // SAFETY: The runtime and its threads don't exist yet.
+        unsafe { std::env::set_var("RUST_LIB_BACKTRACE", "1") };
+    }
+
+    let result = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => {
+            let result = runtime.block_on(dispatch(cli));
+            // The plain runner's stdin reader may still wait for a line after it quit.
+            runtime.shutdown_background();
+            result
+        }
+        Err(e) => Err(e.into()),
     };
 
     if let Err(e) = result {
@@ -60,10 +285,117 @@ async fn main() {
     }
 }
 
-async fn run_project() -> Result<()> {
-    // Load merged app config once (defaults <- global <- local) and reuse it.
+async fn dispatch(cli: Cli) -> Result<()> {
+    match cli.command {
+        CargoSubcommand::Cellbook(args) => match args.command {
+            Commands::Init { name, workspace } => init_project(&name, workspace),
+            Commands::Examples { command } => match command {
+                ExamplesCommand::List => {
+                    examples::list();
+                    Ok(())
+                }
+                ExamplesCommand::New { example, name } => examples::create(&example, name.as_deref()),
+            },
+            Commands::Run { ui, repro } => run_project(Interface::Ui(ui), repro.run_info()).await,
+            Commands::Web { port, host, repro } => {
+                run_project(Interface::Web { host, port }, repro.run_info()).await
+            }
+            Commands::Exec { cells, json, repro } => {
+                // Before anything is printed, like the RPC protocol.
+                let json = match json {
+                    true => Some(util::claim_stdout().map_err(|e| {
+                        std::io::Error::new(e.kind(), format!("can't take stdout for --json: {}", e))
+                    })?),
+                    false => None,
+                };
+                run_project(Interface::Exec { cells, json }, repro.run_info()).await
+            }
+            // Before anything is printed, so build progress can't corrupt the protocol.
+            Commands::Rpc { repro } => match rpc::claim_stdout() {
+                Ok(protocol) => run_project(Interface::Rpc(protocol), repro.run_info()).await,
+                Err(e) => Err(e),
+            },
+            Commands::Kernel {
+                connection_file,
+                install,
+                repro,
+            } => run_kernel(install, connection_file, repro).await,
+            Commands::Export {
+                format,
+                output,
+                repro,
+            } => export::export(format, output, repro.run_info()).await,
+            Commands::Check => check::check().await,
+            Commands::Lint => lint::lint(),
+            Commands::Graph { dot } => graph::print(dot).await,
+            Commands::Runs { command } => match command {
+                RunsCommand::List { json } => runs::list(json),
+                RunsCommand::Show { id, json } => runs::show(id.as_deref(), json),
+            },
+            Commands::Store { command } => match command {
+                StoreCommand::List { json } => history::list(json),
+                StoreCommand::History { key, cell, json } => {
+                    history::show(key.as_deref(), cell.as_deref(), json)
+                }
+            },
+            Commands::Clean { cargo } => clean::clean(cargo),
+            Commands::Completions { shell } => {
+                print!("{}", completions::script(shell, Cli::command()));
+                Ok(())
+            }
+            Commands::Worker {
+                port,
+                cell,
+                sandbox,
+                lib,
+            } => isolation::worker(&lib, &cell, port, sandbox).await,
+        },
+    }
+}
+
+/// Where `run_project` runs the notebook.
+enum Interface {
+    /// `run --ui`, or the `ui` setting when not given.
+    Ui(Option<UiMode>),
+    /// `web`, with the `web_host` and `web_port` settings unless given.
+    Web { host: Option<String>, port: Option<u16> },
+    /// `exec`, with the cells to run and the end of stdout for `--json`.
+    Exec {
+        cells: Vec<String>,
+        json: Option<fs::File>,
+    },
+    /// `rpc`, with the protocol's end of stdout.
+    Rpc(fs::File),
+    /// `kernel`, with the connection file Jupyter wrote.
+    #[cfg(feature = "jupyter")]
+    Kernel(PathBuf),
+}
+
+#[cfg(feature = "jupyter")]
+async fn run_kernel(install: bool, connection_file: Option<PathBuf>, repro: ReproArgs) -> Result<()> {
+    match connection_file {
+        Some(connection_file) if !install => {
+            run_project(Interface::Kernel(connection_file), repro.run_info()).await
+        }
+        _ => kernel::install(),
+    }
+}
+
+#[cfg(not(feature = "jupyter"))]
+async fn run_kernel(_install: bool, _connection_file: Option<PathBuf>, _repro: ReproArgs) -> Result<()> {
+    Err(errors::Error::Kernel(
+        "cargo-cellbook was built without the `jupyter` feature, \
+         reinstall it with `cargo install cargo-cellbook --features jupyter`"
+            .to_string(),
+    ))
+}
+
+async fn run_project(interface: Interface, run_info: run_info::RunInfo) -> Result<()> {
     tui::config::ensure_config_exists();
-    let app_config = tui::config::load();
+    loader::set_project_root()?;
+    run_info.share();
+    runs::start(&run_info);
+    history::start();
 
     // Find the dylib path
     let lib_path = loader::find_dylib_path()?;
@@ -74,6 +406,39 @@ async fn run_project() -> Result<()> {
     // Load the library
     let mut lib = loader::LoadedLibrary::load(&lib_path)?;
 
+    // Load merged app config once (defaults <- global <- notebook <- local) and reuse it.
+    let app_config = tui::config::load(lib.config())?;
+    // Before anything runs the notebook's code, including the startup check of init.
+    app_config.general.apply_runtime_settings();
+    // The web frontend, editors and Jupyter run cells like the plain prompt,
+    // without the TUI's startup check.
+    let ui = match interface {
+        Interface::Ui(ui) => ui.unwrap_or(app_config.general.ui),
+        Interface::Exec { .. } => UiMode::Headless,
+        _ => UiMode::Plain,
+    };
+    if ui != UiMode::Tui {
+        println!("Run {}", run_info.describe());
+    }
+    if ui == UiMode::Headless {
+        return match interface {
+            Interface::Exec { cells, json } => {
+                runner::exec(&mut lib, &app_config.general, &cells, json).await
+            }
+            _ => runner::headless(&mut lib, &app_config.general).await,
+        };
+    }
+
+    let init_run = if ui == UiMode::Tui && app_config.general.check_init {
+        match health::check_init(&mut lib).await? {
+            health::HealthCheck::Passed(run) => Some(run),
+            health::HealthCheck::Continue => None,
+            health::HealthCheck::Quit => return Ok(()),
+        }
+    } else {
+        None
+    };
+
     // Set up event channel
     let (event_tx, event_rx) = mpsc::channel(32);
 
@@ -81,10 +446,25 @@ async fn run_project() -> Result<()> {
     let tui_event_tx = event_tx.clone();
     let watcher_handle = watcher::start_watcher(event_tx, &app_config.general).await?;
 
-    // Run the TUI
-    tui::run(&mut lib, tui_event_tx, event_rx, app_config).await?;
+    let general = &app_config.general;
+    match interface {
+        Interface::Web { host, port } => {
+            let host = host.unwrap_or_else(|| general.web_host.clone());
+            let port = port.unwrap_or(general.web_port);
+            web::serve(&mut lib, event_rx, general, &host, port).await?
+        }
+        // Run headless above.
+        Interface::Exec { .. } => {}
+        Interface::Rpc(protocol) => rpc::serve(&mut lib, event_rx, general, protocol).await?,
+        #[cfg(feature = "jupyter")]
+        Interface::Kernel(connection_file) => {
+            kernel::serve(&mut lib, event_rx, general, &connection_file).await?
+        }
+        Interface::Ui(_) if ui == UiMode::Plain => runner::plain(&mut lib, event_rx, general).await?,
+        Interface::Ui(_) => tui::run(&mut lib, tui_event_tx, event_rx, app_config, init_run).await?,
+    }
 
-    // Stop the watcher when TUI exits
+    // Stop the watcher when the runner exits
     if let Some(handle) = watcher_handle {
         handle.stop();
     }
@@ -92,6 +472,14 @@ async fn run_project() -> Result<()> {
     Ok(())
 }
 
+/// Project settings written by `init --workspace`.
+const CELLBOOK_TOML: &str = "\
+# Settings for this notebook, layered over the global config
+# (~/.config/cellbook/config.toml). See the README for every key.
+
+[general]
+";
+
 fn is_valid_package_name(name: &str) -> bool {
     if name.is_empty() {
         return false;
@@ -104,7 +492,8 @@ fn is_valid_package_name(name: &str) -> bool {
     chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
 }
 
-fn init_project(name: &str) -> Result<()> {
+/// Check that `name` can be a package name and that no directory is in its way.
+fn check_new_project(name: &str) -> Result<()> {
     if !is_valid_package_name(name) {
         return Err(errors::Error::Io(std::io::Error::new(
             std::io::ErrorKind::InvalidInput,
@@ -117,14 +506,44 @@ fn init_project(name: &str) -> Result<()> {
         )));
     }
 
-    let project_path = Path::new(name);
-
-    if project_path.exists() {
+    if Path::new(name).exists() {
         return Err(errors::Error::Io(std::io::Error::new(
             std::io::ErrorKind::AlreadyExists,
             format!("Directory '{}' already exists", name),
         )));
     }
+    Ok(())
+}
+
+fn init_project(name: &str, workspace: bool) -> Result<()> {
+    check_new_project(name)?;
+    let project_path = Path::new(name);
+
+    let current = std::env::current_dir()?;
+    let dir = current.join(name);
+    let root = workspace::find_root(&dir);
+    if workspace && root.is_none() {
+        return Err(errors::Error::Workspace(format!(
+            "No cargo workspace contains {}",
+            current.display()
+        )));
+    }
+    // Cargo refuses to build a package inside a workspace that doesn't list it.
+    let member = root.as_ref().map(|root| {
+        let member = dir.strip_prefix(root).unwrap_or(&dir);
+        member.to_string_lossy().replace('\\', "/")
+    });
+    if let (Some(root), Some(member), false) = (&root, &member, workspace) {
+        let manifest = fs::read_to_string(root.join("Cargo.toml"))?;
+        if !workspace::is_member(&manifest, member)? {
+            return Err(errors::Error::Workspace(format!(
+                "{} is inside the cargo workspace at {} but not one of its members. \
+                 Pass --workspace to add it to the members, or list it in the workspace's `exclude`",
+                member,
+                root.display()
+            )));
+        }
+    }
 
     // Create project directory
     fs::create_dir_all(project_path)?;
@@ -164,6 +583,22 @@ async fn hello() -> Result<()> {
 "#;
     fs::write(project_path.join("cellbook.rs"), cellbook_rs)?;
 
+    match (root, member) {
+        (Some(root), Some(member)) if workspace => {
+            let manifest_path = root.join("Cargo.toml");
+            let manifest = fs::read_to_string(&manifest_path)?;
+            match workspace::add_member(&manifest, &member)? {
+                Some(updated) => {
+                    fs::write(&manifest_path, updated)?;
+                    println!("Added {} to the members of {}", member, manifest_path.display());
+                }
+                None => println!("{} is already covered by the workspace members", member),
+            }
+            fs::write(project_path.join("Cellbook.toml"), CELLBOOK_TOML)?;
+        }
+        _ => {}
+    }
+
     println!("Created cellbook project: {}", name);
 
     Ok(())
@@ -0,0 +1,57 @@
+//! Desktop notifications when long-running cells finish.
+//!
+//! Only sent when cargo-cellbook is built with the `desktop-notifications`
+//! feature and `notify_after_secs` is set.
+
+use std::time::Duration;
+
+/// Whether notifications can be sent by this build.
+pub const SUPPORTED: bool = cfg!(feature = "desktop-notifications");
+
+/// Notify that a cell finished, if it ran for at least `threshold`.
+pub fn cell_finished(name: &str, duration: Duration, error: Option<&str>, threshold: Option<Duration>) {
+    if threshold.is_none_or(|threshold| duration < threshold) {
+        return;
+    }
+    let (summary, body) = message(name, duration, error);
+    send(summary, body);
+}
+
+fn message(name: &str, duration: Duration, error: Option<&str>) -> (String, String) {
+    let took = format!("{:.1?}", duration);
+    match error {
+        None => (format!("{} finished", name), format!("Took {}", took)),
+        Some(error) => (format!("{} failed", name), format!("After {}: {}", took, error)),
+    }
+}
+
+#[cfg(feature = "desktop-notifications")]
+fn send(summary: String, body: String) {
+    // Showing a notification blocks on the notification daemon.
+    std::thread::spawn(move || {
+        let _ = notify_rust::Notification::new()
+            .appname("cellbook")
+            .summary(&summary)
+            .body(&body)
+            .show();
+    });
+}
+
+#[cfg(not(feature = "desktop-notifications"))]
+fn send(_summary: String, _body: String) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message() {
+        let (summary, body) = message("train", Duration::from_secs(300), None);
+        assert_eq!(summary, "train finished");
+        assert_eq!(body, "Took 300.0s");
+
+        let (summary, body) = message("train", Duration::from_millis(1500), Some("out of memory"));
+        assert_eq!(summary, "train failed");
+        assert_eq!(body, "After 1.5s: out of memory");
+    }
+}
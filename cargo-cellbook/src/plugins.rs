@@ -0,0 +1,202 @@
+//! Host plugins loaded from the dylibs listed in the `plugins` config setting.
+//!
+//! Plugins export `__cellbook_plugin_hooks` through `cellbook::export_plugin!`,
+//! along with the ABI handshake checked by [`loader::check_abi`].
+//! Hooks run on the TUI event loop. Panics are caught on the plugin side by
+//! `export_plugin!`, since they can't unwind across the plugin's own runtime.
+
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use libloading::Library;
+use parking_lot::Mutex;
+
+use crate::errors::{Error, Result};
+use crate::ffi::FfiSlice;
+use crate::loader;
+use crate::store::{self, Snapshot, StoreDiff};
+
+/// Mirrors `cellbook::plugin::FfiCellEvent`; field order and types must match.
+#[repr(C)]
+struct CellEvent {
+    name: FfiSlice,
+    duration_secs: u64,
+    duration_nanos: u32,
+    stdout: FfiSlice,
+    /// [`FfiSlice::NONE`] if the cell succeeded.
+    error: FfiSlice,
+}
+
+/// Mirrors `cellbook::plugin::FfiKeys`.
+#[repr(C)]
+struct Keys {
+    ptr: *const FfiSlice,
+    len: usize,
+}
+
+impl Keys {
+    fn new(keys: &[FfiSlice]) -> Self {
+        Self {
+            ptr: keys.as_ptr(),
+            len: keys.len(),
+        }
+    }
+}
+
+/// Mirrors `cellbook::plugin::FfiStoreChange`.
+#[repr(C)]
+struct StoreChange {
+    added: Keys,
+    changed: Keys,
+    removed: Keys,
+}
+
+/// Mirrors `cellbook::plugin::PluginHooks`; field order and types must match.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct PluginHooks {
+    on_cell_complete: unsafe extern "C" fn(*const CellEvent),
+    on_reload: extern "C" fn(),
+    on_store_change: unsafe extern "C" fn(*const StoreChange),
+}
+
+type GetHooksFn = unsafe extern "C" fn() -> PluginHooks;
+
+/// Hooks of the loaded plugins, in load order.
+static PLUGINS: LazyLock<Mutex<Vec<PluginHooks>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+
+/// Libraries of the loaded plugins, kept open for the hooks in [`PLUGINS`].
+static LIBRARIES: LazyLock<Mutex<Vec<Library>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+
+/// Store contents at the last change notification.
+static LAST_SNAPSHOT: LazyLock<Mutex<Option<Snapshot>>> = LazyLock::new(|| Mutex::new(None));
+
+/// Load every plugin in `paths`. Returns an error message per plugin that failed to load.
+pub fn load(paths: &[PathBuf]) -> Vec<String> {
+    let mut errors = Vec::new();
+    for path in paths {
+        match load_plugin(path) {
+            Ok((hooks, library)) => {
+                LIBRARIES.lock().push(library);
+                PLUGINS.lock().push(hooks);
+            }
+            Err(e) => errors.push(e.to_string()),
+        }
+    }
+    errors
+}
+
+fn load_plugin(path: &Path) -> Result<(PluginHooks, Library)> {
+    // SAFETY: Plugins are configured by the user and trusted like the notebook itself.
+    let library = unsafe { Library::new(path) }
+        .map_err(|e| Error::LibLoad(format!("Failed to load plugin {}: {}", path.display(), e)))?;
+    unsafe { loader::check_abi(&library, &format!("Plugin {}", path.display())) }?;
+    let hooks = unsafe {
+        let get_hooks = library
+            .get::<GetHooksFn>(b"__cellbook_plugin_hooks")
+            .map_err(|e| Error::LibLoad(format!("Invalid plugin {}: {}", path.display(), e)))?;
+        get_hooks()
+    };
+    Ok((hooks, library))
+}
+
+/// Call `hook` for every plugin. The list is copied first so that hooks run
+/// unlocked, and may take their time or call back into the host.
+fn each(hook: impl Fn(&PluginHooks)) {
+    let plugins = PLUGINS.lock().clone();
+    for hooks in &plugins {
+        hook(hooks);
+    }
+}
+
+pub fn cell_completed(name: &str, duration: Duration, stdout: &str, error: Option<&str>) {
+    let event = CellEvent {
+        name: name.into(),
+        duration_secs: duration.as_secs(),
+        duration_nanos: duration.subsec_nanos(),
+        stdout: stdout.into(),
+        error: error.into(),
+    };
+    each(|hooks| unsafe { (hooks.on_cell_complete)(&event) });
+}
+
+pub fn reloaded() {
+    each(|hooks| (hooks.on_reload)());
+}
+
+/// Notify plugins if the store changed since the previous call.
+pub fn observe_store() {
+    if PLUGINS.lock().is_empty() {
+        return;
+    }
+
+    let current = store::snapshot();
+    let previous = LAST_SNAPSHOT.lock().replace(current.clone()).unwrap_or_default();
+    let diff = StoreDiff::between(&previous, &current);
+    if diff.is_empty() {
+        return;
+    }
+
+    let added: Vec<FfiSlice> = diff.added.iter().map(|(k, _)| k.as_str().into()).collect();
+    let changed: Vec<FfiSlice> = diff.changed.iter().map(|(k, ..)| k.as_str().into()).collect();
+    let removed: Vec<FfiSlice> = diff.removed.iter().map(|(k, _)| k.as_str().into()).collect();
+    let change = StoreChange {
+        added: Keys::new(&added),
+        changed: Keys::new(&changed),
+        removed: Keys::new(&removed),
+    };
+    each(|hooks| unsafe { (hooks.on_store_change)(&change) });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    static COMPLETED: AtomicUsize = AtomicUsize::new(0);
+    static ADDED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    unsafe extern "C" fn on_cell_complete(event: *const CellEvent) {
+        let event = unsafe { &*event };
+        if unsafe { event.name.to_str() } == "plugin_test" {
+            // Hooks run with the plugin list unlocked.
+            assert!(!PLUGINS.lock().is_empty());
+            COMPLETED.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    extern "C" fn on_reload() {}
+
+    unsafe extern "C" fn on_store_change(change: *const StoreChange) {
+        let added = unsafe { &(*change).added };
+        let added = unsafe { std::slice::from_raw_parts(added.ptr, added.len) };
+        ADDED
+            .lock()
+            .extend(added.iter().map(|key| unsafe { key.to_str() }.into_owned()));
+    }
+
+    #[test]
+    fn test_hooks_see_cells_and_store_changes() {
+        PLUGINS.lock().push(PluginHooks {
+            on_cell_complete,
+            on_reload,
+            on_store_change,
+        });
+
+        cell_completed("plugin_test", Duration::from_millis(1), "", None);
+        assert_eq!(COMPLETED.load(Ordering::SeqCst), 1);
+
+        store::store_value("plugin_test_key", vec![1], "test").unwrap();
+        observe_store();
+        assert!(ADDED.lock().contains(&"plugin_test_key".to_string()));
+    }
+
+    #[test]
+    fn test_load_reports_missing_plugins() {
+        let errors = load(&[PathBuf::from("/nonexistent/libplugin.so")]);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("/nonexistent/libplugin.so"));
+    }
+}
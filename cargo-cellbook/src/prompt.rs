@@ -0,0 +1,145 @@
+//! Lines of input requested by running cells with `CellContext::prompt`.
+//!
+//! The interface decides how prompts are answered, see [`set_input`]. Cells
+//! can't read the runner's stdin themselves: the TUI owns the terminal and the
+//! plain runner reads commands from it.
+
+use std::collections::VecDeque;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use futures::future::BoxFuture;
+use parking_lot::Mutex;
+use tokio::sync::oneshot;
+
+/// Where answers to prompts come from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Input {
+    /// Nothing answers prompts, so they fail right away, as in exports.
+    #[default]
+    None,
+    /// Prompts wait in a queue until [`answer`]ed, as the TUI does from a popup.
+    Queue,
+    /// Prompts are printed and wait for the next line the plain runner reads,
+    /// which passes its lines to [`answer`] first.
+    Lines,
+    /// Prompts are printed and read from stdin directly, for the headless runner.
+    Stdin,
+}
+
+static INPUT: Mutex<Input> = Mutex::new(Input::None);
+
+/// A prompt waiting for its answer.
+struct Pending {
+    id: u64,
+    message: String,
+    reply: oneshot::Sender<Option<String>>,
+}
+
+static PENDING: Mutex<VecDeque<Pending>> = Mutex::new(VecDeque::new());
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Set how prompts are answered.
+pub fn set_input(input: Input) {
+    *INPUT.lock() = input;
+}
+
+/// Ask for a line of input, without the line ending.
+pub fn request(message: &str) -> BoxFuture<'static, std::result::Result<String, String>> {
+    let input = *INPUT.lock();
+    if matches!(input, Input::Lines | Input::Stdin) {
+        // Stdout is captured while the cell runs, so the message goes to stderr.
+        eprint!("{} ", message);
+        let _ = std::io::stderr().flush();
+    }
+    match input {
+        Input::None => Box::pin(async {
+            Err("no input is available here, prompts only work in `cargo cellbook run`".to_string())
+        }),
+        Input::Stdin => Box::pin(async {
+            let line = tokio::task::spawn_blocking(|| {
+                let mut line = String::new();
+                match std::io::stdin().read_line(&mut line) {
+                    Ok(0) | Err(_) => None,
+                    Ok(_) => Some(line),
+                }
+            })
+            .await
+            .ok()
+            .flatten();
+            line.map(|line| trim_line_ending(&line).to_string())
+                .ok_or_else(|| "end of input".to_string())
+        }),
+        Input::Queue | Input::Lines => {
+            let (reply, answer) = oneshot::channel();
+            PENDING.lock().push_back(Pending {
+                id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+                message: message.to_string(),
+                reply,
+            });
+            Box::pin(async move {
+                answer
+                    .await
+                    .ok()
+                    .flatten()
+                    .ok_or_else(|| "prompt cancelled".to_string())
+            })
+        }
+    }
+}
+
+/// Id and message of the oldest prompt still waiting for an answer.
+/// Prompts of cells that were aborted are dropped.
+pub fn pending() -> Option<(u64, String)> {
+    let mut pending = PENDING.lock();
+    while pending.front().is_some_and(|p| p.reply.is_closed()) {
+        pending.pop_front();
+    }
+    pending.front().map(|p| (p.id, p.message.clone()))
+}
+
+/// Answer the oldest waiting prompt with `line`, or cancel it with `None`.
+/// Returns the line if no prompt was waiting for it.
+pub fn answer(line: Option<String>) -> Option<String> {
+    let mut pending = PENDING.lock();
+    while let Some(prompt) = pending.pop_front() {
+        let line = line.as_deref().map(|l| trim_line_ending(l).to_string());
+        if prompt.reply.send(line).is_ok() {
+            return None;
+        }
+    }
+    line
+}
+
+fn trim_line_ending(line: &str) -> &str {
+    line.strip_suffix('\n')
+        .map(|l| l.strip_suffix('\r').unwrap_or(l))
+        .unwrap_or(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_queued_prompts_are_answered_in_order() {
+        set_input(Input::Queue);
+        let first = request("Delete 1M rows? y/n");
+        let second = request("Name?");
+        let aborted = request("Aborted?");
+        drop(aborted);
+
+        let (_, message) = pending().unwrap();
+        assert_eq!(message, "Delete 1M rows? y/n");
+        assert_eq!(answer(Some("y\n".to_string())), None);
+        assert_eq!(answer(None), None);
+        assert_eq!(first.await.unwrap(), "y");
+        assert!(second.await.is_err());
+
+        // The aborted prompt doesn't take the line.
+        assert_eq!(answer(Some("a".to_string())), Some("a".to_string()));
+        assert!(pending().is_none());
+        set_input(Input::None);
+    }
+}
@@ -0,0 +1,158 @@
+//! Shared resources for non-serializable values.
+//!
+//! Resources are values created by cells, such as clients or connections, which the
+//! runner keeps as opaque handles. Cloning and dropping a handle calls into the loaded
+//! library, so they must be cleared before it is reloaded.
+//!
+//! Values deserialized by `ctx.load_shared` are kept here too, together with the
+//! store version they were read from.
+
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use parking_lot::Mutex;
+
+use crate::ffi::FfiHandle;
+use crate::store;
+
+pub type Resource = FfiHandle;
+
+struct StoredResource {
+    value: Resource,
+    type_name: String,
+}
+
+static RESOURCES: LazyLock<Mutex<HashMap<String, StoredResource>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+struct SharedValue {
+    /// Store version of the key the value was deserialized from.
+    version: u64,
+    value: Resource,
+    type_name: String,
+}
+
+/// Values deserialized by `ctx.load_shared`, by store key.
+static SHARED: LazyLock<Mutex<HashMap<String, SharedValue>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+pub fn get(key: &str) -> Option<(Resource, String)> {
+    RESOURCES
+        .lock()
+        .get(key)
+        .map(|r| (r.value.clone(), r.type_name.clone()))
+}
+
+pub fn put(key: &str, value: Resource, type_name: &str) {
+    RESOURCES.lock().insert(
+        key.to_string(),
+        StoredResource {
+            value,
+            type_name: type_name.to_string(),
+        },
+    );
+}
+
+pub fn remove(key: &str) -> Option<(Resource, String)> {
+    RESOURCES.lock().remove(key).map(|r| (r.value, r.type_name))
+}
+
+/// List all resource keys and their type names.
+pub fn list() -> Vec<(String, String)> {
+    RESOURCES
+        .lock()
+        .iter()
+        .map(|(k, r)| (k.clone(), r.type_name.clone()))
+        .collect()
+}
+
+/// The value an earlier `load_shared` deserialized from `key`, if the key
+/// hasn't been stored again since.
+pub fn get_shared(key: &str) -> Option<(Resource, String)> {
+    let version = store::load_version(key)?;
+    SHARED
+        .lock()
+        .get(key)
+        .filter(|shared| shared.version == version)
+        .map(|shared| (shared.value.clone(), shared.type_name.clone()))
+}
+
+/// Keep a value deserialized from `key` for later shared loads, and drop the
+/// values of keys that were stored again or removed since.
+pub fn put_shared(key: &str, value: Resource, type_name: &str) {
+    let versions = store::versions();
+    let stale: Vec<_> = {
+        let mut shared = SHARED.lock();
+        let stale = shared
+            .extract_if(|k, s| versions.get(k.as_str()) != Some(&s.version))
+            .collect();
+        if let Some(&version) = versions.get(key) {
+            shared.insert(
+                key.to_string(),
+                SharedValue {
+                    version,
+                    value,
+                    type_name: type_name.to_string(),
+                },
+            );
+        }
+        stale
+    };
+    // Dropped without holding the lock, like cleared resources.
+    drop(stale);
+}
+
+/// Whether there are no resources or shared values, whose handles may point into
+/// any library loaded since they were last cleared.
+#[cfg(feature = "jupyter")]
+pub fn is_empty() -> bool {
+    RESOURCES.lock().is_empty() && SHARED.lock().is_empty()
+}
+
+/// Drop all resources and shared values.
+/// Returns the number of resources dropped.
+pub fn clear() -> usize {
+    // Move the values out so they are dropped without holding the lock.
+    let resources = std::mem::take(&mut *RESOURCES.lock());
+    let shared = std::mem::take(&mut *SHARED.lock());
+    drop(shared);
+    resources.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn test_put_get_remove() {
+        let value = Arc::new(3u32);
+        put("test_resource", FfiHandle::new(value.clone()), "u32");
+        let (handle, type_name) = get("test_resource").unwrap();
+        assert_eq!(type_name, "u32");
+        assert_eq!(Arc::strong_count(&value), 3);
+        drop(handle);
+        assert!(list().contains(&("test_resource".to_string(), "u32".to_string())));
+
+        assert!(remove("test_resource").is_some());
+        assert!(get("test_resource").is_none());
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn test_shared_value_is_dropped_when_key_is_stored_again() {
+        let key = "test_shared_value";
+        let first = Arc::new(1u32);
+        store::store_value(key, vec![1], "u32").unwrap();
+        put_shared(key, FfiHandle::new(first.clone()), "u32");
+        let (_, type_name) = get_shared(key).unwrap();
+        assert_eq!(type_name, "u32");
+
+        store::store_value(key, vec![2], "u32").unwrap();
+        assert!(get_shared(key).is_none());
+        store::remove_value(key);
+        put_shared(key, FfiHandle::new(Arc::new(2u32)), "u32");
+        assert!(get_shared(key).is_none());
+        assert_eq!(Arc::strong_count(&first), 1);
+    }
+}
@@ -0,0 +1,99 @@
+//! Retrying failed cell runs, as set by `#[cell(retries = 3, backoff_ms = 1000)]`.
+
+use std::future::Future;
+use std::time::Duration;
+
+use crate::failure::CellFailure;
+
+/// Longest wait before a retry, however many retries a cell sets.
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Wait before retry number `retry` (from 1): `backoff_ms`, doubled for each retry after the first.
+fn backoff(backoff_ms: u64, retry: u32) -> Duration {
+    let factor = 1u64.checked_shl(retry.saturating_sub(1)).unwrap_or(u64::MAX);
+    Duration::from_millis(backoff_ms.saturating_mul(factor)).min(MAX_BACKOFF)
+}
+
+/// Run `attempt` until it succeeds or `retries` retries have failed, returning the
+/// stdout of every attempt and the last result. `on_retry` is called with the
+/// number of each retry and the error that caused it, before the wait.
+pub async fn run<F, Fut>(
+    retries: u32,
+    backoff_ms: u64,
+    mut attempt: F,
+    mut on_retry: impl FnMut(u32, &CellFailure),
+) -> (String, Result<(), CellFailure>)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = (String, Result<(), CellFailure>)>,
+{
+    let mut stdout = String::new();
+    let mut retry = 0;
+    loop {
+        let (output, result) = attempt().await;
+        stdout.push_str(&output);
+        match result {
+            Err(e) if retry < retries => {
+                retry += 1;
+                on_retry(retry, &e);
+                tokio::time::sleep(backoff(backoff_ms, retry)).await;
+            }
+            result => return (stdout, result),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_limit() {
+        assert_eq!(backoff(1000, 1), Duration::from_secs(1));
+        assert_eq!(backoff(1000, 3), Duration::from_secs(4));
+        assert_eq!(backoff(0, 5), Duration::ZERO);
+        assert_eq!(backoff(1000, 100), MAX_BACKOFF);
+    }
+
+    #[tokio::test]
+    async fn test_retries_until_success_or_limit() {
+        let mut attempts = 0;
+        let mut retried = Vec::new();
+        let (stdout, result) = run(
+            3,
+            0,
+            || {
+                attempts += 1;
+                let result = if attempts < 3 {
+                    Err(CellFailure::from_message("timed out"))
+                } else {
+                    Ok(())
+                };
+                let output = format!("attempt {}\n", attempts);
+                async move { (output, result) }
+            },
+            |retry, e| retried.push((retry, e.message.clone())),
+        )
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(stdout, "attempt 1\nattempt 2\nattempt 3\n");
+        assert_eq!(
+            retried,
+            [(1, "timed out".to_string()), (2, "timed out".to_string())]
+        );
+
+        let mut attempts = 0;
+        let (_, result) = run(
+            2,
+            0,
+            || {
+                attempts += 1;
+                async { (String::new(), Err(CellFailure::from_message("down"))) }
+            },
+            |_, _| {},
+        )
+        .await;
+        assert_eq!(result.unwrap_err().message, "down");
+        assert_eq!(attempts, 3);
+    }
+}
@@ -0,0 +1,665 @@
+//! `cargo cellbook rpc`: JSON-RPC over stdio for editor plugins.
+//!
+//! Messages are framed as in the Language Server Protocol, a `Content-Length`
+//! header and a blank line before each JSON-RPC 2.0 message, so editors can drive
+//! the runner with the client they use for language servers and show cell output
+//! inline.
+//!
+//! Requests:
+//!
+//! - `initialize`: the runner's name and version and the project.
+//! - `cells/list`: cells in order, init first, with the status of their last run.
+//! - `cells/run` `{ "name": "load" }`: run a cell, answered when it finishes with
+//!   its status, duration, output and error. Runs wait for each other in the order
+//!   they were requested.
+//! - `cells/output` `{ "name": "load" }`: the last run of a cell, as `cells/run` answers.
+//! - `store/list`: store keys with their type, size and the cell run that stored them.
+//! - `store/get` `{ "key": "prices" }`: a key as `store/list` describes it, with its
+//!   `value` decoded if it was stored as JSON. Other formats can't be decoded without
+//!   their Rust type, so their `value` is `null`.
+//! - `prompt/answer` `{ "text": "y" }`: answer the prompt a cell waits on, `null` cancels it.
+//! - `shutdown`: stop the runner. End of input stops it too.
+//!
+//! Notifications from the runner: `cell/started`, `cell/output` with `{ name, text }`
+//! as a cell writes it, `cell/retrying`, `cell/finished`, `prompt` when a cell waits
+//! for `prompt/answer`, `build/started`, `build/failed` and `notebook/reloaded`.
+//!
+//! Stdout carries the messages, so everything else the runner and cells print to
+//! stdout outside a run goes to stderr.
+
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{BufRead, Read, Write};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio::sync::mpsc;
+use tokio::task::{JoinError, JoinHandle};
+
+use crate::errors::{Error, Result};
+use crate::failure::CellFailure;
+use crate::loader::LoadedLibrary;
+use crate::runner::{self, Finished, OUTPUT_INTERVAL, Runs, Start};
+use crate::tui::TuiEvent;
+use crate::tui::config::{GeneralConfig, RunOnStart, StartMode};
+use crate::{artifacts, prompt, store, util};
+
+/// Largest message body read. Larger ones are skipped, so a bad header can't
+/// make the runner allocate without bound.
+const MAX_MESSAGE_BYTES: usize = 16 * 1024 * 1024;
+
+/// Largest value `store/get` decodes.
+const MAX_VALUE_BYTES: usize = 16 * 1024 * 1024;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// No cell has the requested name.
+const CELL_NOT_FOUND: i64 = -32001;
+/// A reload aborted the run, or the runner stopped before it.
+const RUN_ABORTED: i64 = -32002;
+/// The store has no value under the requested key.
+const KEY_NOT_FOUND: i64 = -32003;
+
+/// Take stdout for the protocol, so nothing else printed there ends up between messages.
+pub fn claim_stdout() -> Result<File> {
+    util::claim_stdout().map_err(|e| Error::Rpc(format!("can't take stdout for the protocol: {}", e)))
+}
+
+/// Read one message body, or `None` at the end of input.
+fn read_message(input: &mut impl BufRead) -> std::io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let Some(length) = length else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "message without a Content-Length header",
+        ));
+    };
+    if length > MAX_MESSAGE_BYTES {
+        // Skipped so the next message is read from its header.
+        std::io::copy(&mut (&mut *input).take(length as u64), &mut std::io::sink())?;
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "message of {} is larger than the limit of {}",
+                store::format_bytes(length as u64),
+                store::format_bytes(MAX_MESSAGE_BYTES as u64)
+            ),
+        ));
+    }
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> std::io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+/// A request, or a notification without an id.
+#[derive(Deserialize)]
+struct Incoming {
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct CellParams {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct KeyParams {
+    key: String,
+}
+
+#[derive(Deserialize)]
+struct AnswerParams {
+    text: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// The protocol's end of stdout, shared with running cells for their output.
+#[derive(Clone)]
+struct Output(Arc<Mutex<File>>);
+
+impl Output {
+    fn send(&self, message: Value) {
+        // A closed stdout means the editor is gone, which the end of input also shows.
+        let _ = write_message(&mut *self.0.lock(), &message);
+    }
+
+    fn notify(&self, method: &str, params: Value) {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    /// Answer the request `id`. Notifications, without an id, aren't answered.
+    fn respond(&self, id: Option<Value>, result: std::result::Result<Value, RpcError>) {
+        let Some(id) = id else {
+            return;
+        };
+        self.send(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+        });
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    #[default]
+    Idle,
+    Queued,
+    Running,
+    Ok,
+    Failed,
+    /// A service cell running in the background.
+    Service,
+}
+
+/// The last run of a cell, as `cells/run` and `cells/output` answer.
+#[derive(Clone, Debug, Default, Serialize)]
+struct LastRun {
+    name: String,
+    status: Status,
+    runs: u32,
+    duration_ms: Option<u64>,
+    output: String,
+    /// The rendered error of a failed run.
+    error: Option<String>,
+}
+
+struct Running {
+    /// Request to answer when the run finishes, if a request started it.
+    request: Option<Value>,
+    idx: usize,
+    handle: JoinHandle<Finished>,
+}
+
+struct Session<'a> {
+    lib: &'a mut LoadedLibrary,
+    config: GeneralConfig,
+    out: Output,
+    last_runs: HashMap<String, LastRun>,
+    runs: Runs,
+    /// Cells waiting for the running one, with the request to answer when they finish.
+    queue: VecDeque<(Option<Value>, String)>,
+    running: Option<Running>,
+    /// Id of the last prompt sent to the editor.
+    prompted: Option<u64>,
+    shutdown: bool,
+}
+
+/// Answer requests on stdin until `shutdown` or the end of input, writing to
+/// `protocol` from [`claim_stdout`]. Reloads on the watcher's events like the TUI.
+pub async fn serve(
+    lib: &mut LoadedLibrary,
+    mut event_rx: mpsc::Receiver<TuiEvent>,
+    config: &GeneralConfig,
+    protocol: File,
+) -> Result<()> {
+    prompt::set_input(prompt::Input::Queue);
+
+    let (message_tx, mut message_rx) = mpsc::channel(32);
+    std::thread::spawn(move || {
+        let mut stdin = std::io::stdin().lock();
+        loop {
+            let message = match read_message(&mut stdin) {
+                Ok(Some(body)) => Ok(body),
+                Ok(None) => break,
+                Err(e) if e.kind() == std::io::ErrorKind::InvalidData => Err(e.to_string()),
+                Err(_) => break,
+            };
+            if message_tx.blocking_send(message).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut session = Session {
+        lib,
+        config: config.clone(),
+        out: Output(Arc::new(Mutex::new(protocol))),
+        last_runs: HashMap::new(),
+        runs: Runs::default(),
+        queue: VecDeque::new(),
+        running: None,
+        prompted: None,
+        shutdown: false,
+    };
+    session.queue_start_cells();
+
+    let mut ticker = tokio::time::interval(OUTPUT_INTERVAL);
+    while !session.shutdown {
+        session.start_next();
+        tokio::select! {
+            message = message_rx.recv() => match message {
+                Some(Ok(body)) => session.handle(&body),
+                Some(Err(e)) => session.out.send(json!({
+                    "jsonrpc": "2.0",
+                    "id": null,
+                    "error": RpcError::new(INVALID_REQUEST, e),
+                })),
+                None => break,
+            },
+            joined = runner::join(session.running.as_mut().map(|r| &mut r.handle)) => session.finish(joined),
+            Some(event) = event_rx.recv() => match event {
+                TuiEvent::BuildStarted => session.out.notify("build/started", json!({})),
+                TuiEvent::BuildCompleted(Some(message)) => {
+                    session.out.notify("build/failed", json!({ "message": message }))
+                }
+                TuiEvent::Reloaded => session.reload().await,
+                _ => {}
+            },
+            _ = ticker.tick(), if session.running.is_some() => session.announce_prompt(),
+        }
+    }
+
+    session.abort("the runner stopped").await;
+    for (request, _) in std::mem::take(&mut session.queue) {
+        session
+            .out
+            .respond(request, Err(RpcError::new(RUN_ABORTED, "the runner stopped")));
+    }
+    session.release().await;
+    artifacts::cleanup();
+    store::remove_disk_files();
+    Ok(())
+}
+
+impl Session<'_> {
+    fn handle(&mut self, body: &str) {
+        let message = serde_json::from_str::<Value>(body)
+            .map_err(|e| RpcError::new(PARSE_ERROR, e.to_string()))
+            .and_then(|value| {
+                serde_json::from_value::<Incoming>(value)
+                    .map_err(|e| RpcError::new(INVALID_REQUEST, e.to_string()))
+            });
+        let message = match message {
+            Ok(message) => message,
+            Err(error) => {
+                self.out
+                    .send(json!({ "jsonrpc": "2.0", "id": null, "error": error }));
+                return;
+            }
+        };
+        let id = message.id;
+        let result = match message.method.as_str() {
+            "initialize" => Ok(json!({
+                "name": "cargo-cellbook",
+                "version": env!("CARGO_PKG_VERSION"),
+                "project": std::env::current_dir()
+                    .ok()
+                    .and_then(|dir| dir.file_name().map(|name| name.to_string_lossy().into_owned())),
+            })),
+            "cells/list" => Ok(self.list_cells()),
+            "cells/run" => match params::<CellParams>(message.params) {
+                Ok(params) => {
+                    if !self.lib.visible_cells().iter().any(|c| c.name == params.name) {
+                        Err(unknown_cell(&params.name))
+                    } else {
+                        self.last_runs
+                            .entry(params.name.clone())
+                            .or_insert_with(|| LastRun {
+                                name: params.name.clone(),
+                                ..LastRun::default()
+                            })
+                            .status = Status::Queued;
+                        // Answered when the run finishes.
+                        self.queue.push_back((id, params.name));
+                        return;
+                    }
+                }
+                Err(e) => Err(e),
+            },
+            "cells/output" => params::<CellParams>(message.params).and_then(|params| {
+                if !self.lib.visible_cells().iter().any(|c| c.name == params.name) {
+                    return Err(unknown_cell(&params.name));
+                }
+                let last = self
+                    .last_runs
+                    .get(&params.name)
+                    .cloned()
+                    .unwrap_or_else(|| LastRun {
+                        name: params.name,
+                        ..LastRun::default()
+                    });
+                Ok(json!(last))
+            }),
+            "store/list" => Ok(list_store()),
+            "store/get" => params::<KeyParams>(message.params).and_then(|params| get_value(&params.key)),
+            "prompt/answer" => params::<AnswerParams>(message.params).and_then(|params| {
+                if prompt::pending().is_none() {
+                    return Err(RpcError::new(
+                        INVALID_REQUEST,
+                        "no prompt is waiting for an answer",
+                    ));
+                }
+                prompt::answer(params.text);
+                Ok(Value::Null)
+            }),
+            "shutdown" | "exit" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            method => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("unknown method '{}'", method),
+            )),
+        };
+        self.out.respond(id, result);
+    }
+
+    fn list_cells(&self) -> Value {
+        let cells: Vec<Value> = self
+            .lib
+            .visible_cells()
+            .iter()
+            .enumerate()
+            .map(|(index, cell)| {
+                let last = self.last_runs.get(&cell.name);
+                json!({
+                    "index": index,
+                    "name": cell.name,
+                    "title": cell.title,
+                    "doc": cell.doc,
+                    "line": cell.line,
+                    "hidden": cell.hidden,
+                    "service": cell.service,
+                    "status": last.map(|l| l.status).unwrap_or_default(),
+                    "runs": last.map(|l| l.runs).unwrap_or(0),
+                    "duration_ms": last.and_then(|l| l.duration_ms),
+                })
+            })
+            .collect();
+        json!(cells)
+    }
+
+    /// Queue the cells to run at startup and after each reload, per `run_on_start`.
+    fn queue_start_cells(&mut self) {
+        let names: Vec<String> = match &self.config.run_on_start {
+            RunOnStart::Mode(StartMode::Init) => vec![self.lib.init().name.clone()],
+            RunOnStart::Mode(StartMode::None) => Vec::new(),
+            RunOnStart::Cells(names) => names.clone(),
+        };
+        for name in names {
+            if self.lib.visible_cells().iter().any(|c| c.name == name) {
+                self.queue.push_back((None, name));
+            }
+        }
+    }
+
+    /// Start queued cells until one runs.
+    fn start_next(&mut self) {
+        while self.running.is_none() {
+            let Some((request, name)) = self.queue.pop_front() else {
+                return;
+            };
+            // A reload may have removed the cell since it was queued.
+            let Some(idx) = self.lib.visible_cells().iter().position(|c| c.name == name) else {
+                self.out.respond(request, Err(unknown_cell(&name)));
+                continue;
+            };
+            let (out, retrying) = (self.out.clone(), name.clone());
+            let retries = self.lib.visible_cells()[idx].retries;
+            let on_retry = move |retry, e: &CellFailure| {
+                out.notify(
+                    "cell/retrying",
+                    json!({ "name": retrying, "retry": retry, "retries": retries, "error": e.message }),
+                );
+            };
+            let last = match self.runs.start(self.lib, &self.config, idx, on_retry) {
+                Start::Run { run, future } => {
+                    let last = self.last_run(&name);
+                    last.runs = run;
+                    last.status = Status::Running;
+                    last.output.clear();
+                    last.error = None;
+                    self.out
+                        .notify("cell/started", json!({ "name": name, "run": run }));
+                    let (out, cell) = (self.out.clone(), name.clone());
+                    let handle = runner::spawn(&name, future, move |text| {
+                        out.notify("cell/output", json!({ "name": cell, "text": text }))
+                    });
+                    self.running = Some(Running { request, idx, handle });
+                    return;
+                }
+                Start::Service => {
+                    let last = self.last_run(&name);
+                    last.status = Status::Service;
+                    json!(last)
+                }
+                Start::Refused(reason) => {
+                    let last = self.last_run(&name);
+                    last.status = Status::Failed;
+                    last.error = Some(format!("Not run, {}\n", reason));
+                    json!(last)
+                }
+            };
+            self.out.respond(request, Ok(last));
+        }
+    }
+
+    fn finish(&mut self, joined: std::result::Result<Finished, JoinError>) {
+        let Some(running) = self.running.take() else {
+            return;
+        };
+        let cell = self.lib.visible_cells()[running.idx].clone();
+        let (stdout, result, duration) = runner::finished(joined);
+        self.runs
+            .finish(&cell, running.idx, duration, &result, &self.config);
+
+        let last = self.last_run(&cell.name);
+        last.status = if result.is_ok() {
+            Status::Ok
+        } else {
+            Status::Failed
+        };
+        last.duration_ms = Some(duration.as_millis() as u64);
+        last.output = stdout;
+        last.error = result.as_ref().err().map(|e| e.render(&cell.name, cell.line));
+        let last = json!(last);
+        self.out.notify("cell/finished", last.clone());
+        self.out.respond(running.request, Ok(last));
+    }
+
+    /// Tell the editor about a prompt a running cell waits on.
+    fn announce_prompt(&mut self) {
+        let Some((id, message)) = prompt::pending() else {
+            return;
+        };
+        if self.prompted != Some(id) {
+            self.prompted = Some(id);
+            self.out.notify("prompt", json!({ "message": message }));
+        }
+    }
+
+    /// Abort the running cell, answering its request with `reason`.
+    async fn abort(&mut self, reason: &str) {
+        let Some(running) = self.running.take() else {
+            return;
+        };
+        self.runs.abort(running.handle).await;
+        let name = self.lib.visible_cells()[running.idx].name.clone();
+        let last = self.last_run(&name);
+        last.status = Status::Failed;
+        last.error = Some(format!("Aborted: {}\n", reason));
+        self.out
+            .respond(running.request, Err(RpcError::new(RUN_ABORTED, reason)));
+    }
+
+    /// Drop state referencing the current library and load the new build, then run
+    /// the startup cells. Queued cells still run, by name, on the new build.
+    async fn reload(&mut self) {
+        self.abort("a reload aborted the run").await;
+        self.release().await;
+        match self.lib.reload() {
+            Ok(()) => {
+                let cells = self.list_cells();
+                self.out.notify("notebook/reloaded", json!({ "cells": cells }));
+                if let Err(e) = self.config.reload(self.lib.config()) {
+                    self.out
+                        .notify("build/failed", json!({ "message": e.to_string() }));
+                }
+                self.queue_start_cells();
+            }
+            Err(e) => self
+                .out
+                .notify("build/failed", json!({ "message": e.to_string() })),
+        }
+    }
+
+    /// Run teardown, then drop background tasks and resources.
+    /// Reports go to stderr, stdout carries the protocol.
+    async fn release(&mut self) {
+        self.lib.release().await.report(&mut std::io::stderr());
+    }
+
+    fn last_run(&mut self, name: &str) -> &mut LastRun {
+        self.last_runs.entry(name.to_string()).or_insert_with(|| LastRun {
+            name: name.to_string(),
+            ..LastRun::default()
+        })
+    }
+}
+
+fn params<T: serde::de::DeserializeOwned>(params: Value) -> std::result::Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))
+}
+
+fn unknown_cell(name: &str) -> RpcError {
+    RpcError::new(CELL_NOT_FOUND, format!("no cell named '{}'", name))
+}
+
+/// A key as `store/list` describes it, with its value decoded if it was stored as JSON.
+fn get_value(key: &str) -> std::result::Result<Value, RpcError> {
+    let Some((bytes, type_name, len)) = store::peek_value(key, MAX_VALUE_BYTES) else {
+        return Err(RpcError::new(KEY_NOT_FOUND, format!("no key named '{}'", key)));
+    };
+    let value = match store::is_json(&type_name) && len <= MAX_VALUE_BYTES as u64 {
+        true => serde_json::from_slice::<Value>(&bytes).ok(),
+        false => None,
+    };
+    let provenance = store::provenance(key);
+    Ok(json!({
+        "key": key,
+        "type": type_name,
+        "bytes": len,
+        "stored_by": provenance.as_ref().map(|p| &p.cell),
+        "run": provenance.as_ref().and_then(|p| p.run),
+        "value": value,
+    }))
+}
+
+fn list_store() -> Value {
+    store::expire();
+    let sizes = store::sizes();
+    let mut keys = store::list();
+    keys.sort();
+    let keys: Vec<Value> = keys
+        .into_iter()
+        .map(|(key, type_name)| {
+            let provenance = store::provenance(&key);
+            json!({
+                "key": key,
+                "type": type_name,
+                "bytes": sizes.get(&key).copied().unwrap_or(0),
+                "stored_by": provenance.as_ref().map(|p| &p.cell),
+                "run": provenance.as_ref().and_then(|p| p.run),
+            })
+        })
+        .collect();
+    json!(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages_round_trip_with_content_length() {
+        let mut framed = Vec::new();
+        let message =
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "cells/run", "params": { "name": "ünïcode" } });
+        write_message(&mut framed, &message).unwrap();
+        framed.extend(
+            b"Content-Type: application/vscode-jsonrpc; charset=utf-8\r\ncontent-length: 2\r\n\r\n{}",
+        );
+
+        let mut input = std::io::Cursor::new(framed);
+        let body = read_message(&mut input).unwrap().unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&body).unwrap(), message);
+        assert_eq!(read_message(&mut input).unwrap().unwrap(), "{}");
+        assert_eq!(read_message(&mut input).unwrap(), None);
+
+        let mut missing = std::io::Cursor::new(b"\r\n{}".to_vec());
+        assert!(read_message(&mut missing).is_err());
+
+        let mut oversized = format!("Content-Length: {}\r\n\r\n", MAX_MESSAGE_BYTES + 1).into_bytes();
+        oversized.resize(oversized.len() + MAX_MESSAGE_BYTES + 1, b' ');
+        oversized.extend(b"Content-Length: 2\r\n\r\n{}");
+        let mut input = std::io::Cursor::new(oversized);
+        assert!(read_message(&mut input).is_err());
+        assert_eq!(read_message(&mut input).unwrap().unwrap(), "{}");
+    }
+
+    #[test]
+    fn test_store_get_decodes_json_values() {
+        store::store_value(
+            "rpc_json",
+            br#"{"rows":[1,2]}"#.to_vec(),
+            "serde_json::Value@json",
+        )
+        .unwrap();
+        store::store_value("rpc_postcard", vec![1, 2, 3], "Vec<u8>").unwrap();
+
+        let value = get_value("rpc_json").unwrap();
+        assert_eq!(value["value"], json!({ "rows": [1, 2] }));
+        assert_eq!(value["bytes"], 14);
+        assert_eq!(get_value("rpc_postcard").unwrap()["value"], Value::Null);
+        assert_eq!(get_value("rpc_missing").unwrap_err().code, KEY_NOT_FOUND);
+
+        store::remove_value("rpc_json");
+        store::remove_value("rpc_postcard");
+    }
+}
@@ -0,0 +1,121 @@
+//! Seed and timestamp of a run, passed to notebook code for `ctx.run_info()`.
+
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::loader;
+
+/// Seed and start time shared by every cell of a run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RunInfo {
+    pub seed: u64,
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+impl RunInfo {
+    /// The given seed and timestamp, or a random seed and the current time.
+    pub fn new(seed: Option<u64>, timestamp: Option<u64>) -> Self {
+        Self {
+            seed: seed.unwrap_or_else(|| RandomState::new().hash_one(SystemTime::now())),
+            timestamp: timestamp.unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0)
+            }),
+        }
+    }
+
+    /// Pass the seed and timestamp to notebook code.
+    /// Must be called before the library is loaded and any cell runs.
+    pub fn share(&self) {
+        loader::set_notebook_var("CELLBOOK_SEED", self.seed.to_string());
+        loader::set_notebook_var("CELLBOOK_RUN_TIMESTAMP", self.timestamp.to_string());
+    }
+
+    /// Seed and time, and the flags that repeat the run.
+    pub fn describe(&self) -> String {
+        format!(
+            "seed {}, run at {} (--seed {} --timestamp {})",
+            self.seed,
+            format_utc(self.timestamp),
+            self.seed,
+            self.timestamp
+        )
+    }
+}
+
+/// `YYYY-MM-DD HH:MM:SS UTC` for seconds since the Unix epoch.
+pub fn format_utc(timestamp: u64) -> String {
+    let (year, month, day) = civil_date(timestamp);
+    let secs = timestamp % 86_400;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        secs / 3_600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// `YYYY-MM-DDTHH:MM:SS.ffffffZ` for a time, as ISO 8601 wants it.
+#[cfg(feature = "jupyter")]
+pub fn format_iso8601(time: std::time::SystemTime) -> String {
+    let since_epoch = time.duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+    let timestamp = since_epoch.as_secs();
+    let (year, month, day) = civil_date(timestamp);
+    let secs = timestamp % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+        year,
+        month,
+        day,
+        secs / 3_600,
+        secs / 60 % 60,
+        secs % 60,
+        since_epoch.subsec_micros()
+    )
+}
+
+/// Year, month and day of seconds since the Unix epoch.
+fn civil_date(timestamp: u64) -> (i64, i64, i64) {
+    let days = (timestamp / 86_400) as i64;
+
+    // Civil date from days since 1970-01-01, in 400-year eras starting in March.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_utc() {
+        assert_eq!(format_utc(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_utc(951_782_400), "2000-02-29 00:00:00 UTC");
+        assert_eq!(format_utc(1_700_000_000), "2023-11-14 22:13:20 UTC");
+    }
+
+    #[test]
+    fn test_new_keeps_given_values() {
+        let info = RunInfo::new(Some(42), Some(1_700_000_000));
+        assert_eq!(info.seed, 42);
+        assert_eq!(
+            info.describe(),
+            "seed 42, run at 2023-11-14 22:13:20 UTC (--seed 42 --timestamp 1700000000)"
+        );
+    }
+}
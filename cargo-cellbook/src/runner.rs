@@ -0,0 +1,855 @@
+//! Line-based runners for terminals where the TUI doesn't fit.
+//!
+//! `plain` reads commands from stdin and prints cell output as it is written,
+//! which works on dumb terminals, in tmux copy mode and through pipes.
+//! `headless` runs every cell once and exits, failing if any cell failed, and
+//! `exec` does so for chosen cells, optionally reporting them as JSON.
+//!
+//! [`Runs`] starts and records cell runs for these and for the web, RPC and
+//! Jupyter runners, so every interface but the TUI runs cells the same way.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{IsTerminal, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+use parking_lot::Mutex;
+use serde::Serialize;
+use tokio::sync::{Notify, mpsc};
+use tokio::task::{JoinError, JoinHandle};
+
+use crate::capture::capture_stdout_live;
+use crate::errors::{Error, Result};
+use crate::failure::CellFailure;
+use crate::line_editor::{LineEditor, ReadlineError};
+use crate::loader::{CellInfo, LoadedLibrary};
+use crate::store::{self, Provenance};
+use crate::tui::TuiEvent;
+use crate::tui::config::{GeneralConfig, RunOnStart, StartMode};
+use crate::{artifacts, isolation, notification, prompt, retry, runs, tasks, watcher};
+
+/// How often output of a spawned run is passed on, and prompts are looked for.
+pub const OUTPUT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Output, result and duration of a run spawned with [`spawn`].
+pub type Finished = (String, std::result::Result<(), CellFailure>, Duration);
+
+/// How a cell asked to run starts, from [`Runs::start`].
+pub enum Start {
+    /// Run number `run` of the cell. The future doesn't borrow the library, so it
+    /// can be spawned; its end goes to [`Runs::finish`].
+    Run {
+        run: u32,
+        future: BoxFuture<'static, std::result::Result<(), CellFailure>>,
+    },
+    /// A service cell, now running in the background until reload or exit.
+    Service,
+    /// The cell didn't start, for this reason.
+    Refused(String),
+}
+
+/// Cell runs of a session, for the runners other than the TUI: the checks before a
+/// run, its future with retries and isolation, and what is recorded after it.
+#[derive(Default)]
+pub struct Runs {
+    /// Runs per cell name, for store provenance.
+    counts: HashMap<String, u32>,
+    /// Whether the last init run succeeded.
+    init_succeeded: bool,
+}
+
+impl Runs {
+    /// Start the visible cell at `idx` (0 is init). Cells wait for init under
+    /// `init_required`, service cells start in the background, and others are retried
+    /// per `#[cell(retries)]`, with `on_retry` called before each retry. Output of
+    /// isolated cells is printed, into the caller's capture if there is one.
+    pub fn start(
+        &mut self,
+        lib: &LoadedLibrary,
+        config: &GeneralConfig,
+        idx: usize,
+        on_retry: impl FnMut(u32, &CellFailure) + Send + 'static,
+    ) -> Start {
+        let cell = &lib.visible_cells()[idx];
+        if idx > 0 && config.init_required && !self.init_succeeded {
+            return Start::Refused(format!("blocked until {} succeeds", lib.init().name));
+        }
+        let isolated = cell.isolated || config.isolate_cells;
+        if cell.service {
+            return match tasks::start_service(lib, cell, isolated) {
+                Ok(_) => Start::Service,
+                Err(e) => Start::Refused(format!("failed to start: {}", e)),
+            };
+        }
+
+        let future = if idx == 0 {
+            let future = lib.init_future();
+            Box::pin(async move { future.await.map_err(|e| CellFailure::from_error(&*e)) })
+        } else {
+            attempts(lib, cell, isolated, on_retry)
+        };
+        if idx == 0 && config.clear_on_run {
+            store::clear();
+        }
+        let run = self.counts.entry(cell.name.clone()).or_insert(0);
+        *run += 1;
+        artifacts::set_current_cell(Some(&cell.name));
+        store::set_producer(Some(Provenance::new(&cell.name, Some(*run))));
+        Start::Run { run: *run, future }
+    }
+
+    /// Record the end of a run of the visible cell at `idx` from [`Runs::start`].
+    pub fn finish(
+        &mut self,
+        cell: &CellInfo,
+        idx: usize,
+        duration: Duration,
+        result: &std::result::Result<(), CellFailure>,
+        config: &GeneralConfig,
+    ) {
+        let error = result.as_ref().err().map(|e| e.message.as_str());
+        runs::record(&cell.name, duration, error, &store::take_access(&cell.name));
+        notification::cell_finished(&cell.name, duration, error, config.notify_after());
+        store::set_producer(None);
+        artifacts::set_current_cell(None);
+        if idx == 0 {
+            self.init_succeeded = result.is_ok();
+        }
+    }
+
+    /// Abort a run spawned with [`spawn`] and wait until it has stopped.
+    pub async fn abort(&mut self, handle: JoinHandle<Finished>) {
+        handle.abort();
+        let _ = handle.await;
+        store::set_producer(None);
+        artifacts::set_current_cell(None);
+    }
+}
+
+/// The attempts of a cell run until one succeeds or its retries are used up.
+fn attempts(
+    lib: &LoadedLibrary,
+    cell: &CellInfo,
+    isolated: bool,
+    on_retry: impl FnMut(u32, &CellFailure) + Send + 'static,
+) -> BoxFuture<'static, std::result::Result<(), CellFailure>> {
+    let (retries, backoff_ms) = (cell.retries, cell.backoff_ms);
+    if isolated {
+        let (lib_path, name) = (lib.lib_path().to_path_buf(), cell.name.clone());
+        let attempt = move || {
+            let run = isolation::run(lib_path.clone(), name.clone());
+            async move {
+                let (stdout, result) = run.await;
+                print!("{}", stdout);
+                (String::new(), result)
+            }
+        };
+        return Box::pin(async move { retry::run(retries, backoff_ms, attempt, on_retry).await.1 });
+    }
+    match lib.cell_attempts(cell) {
+        Ok(attempts) => {
+            let attempt = move || {
+                let future = attempts();
+                async move {
+                    let result = match future {
+                        Ok(future) => future.await.map_err(|e| CellFailure::from_error(&*e)),
+                        Err(e) => Err(CellFailure::from_message(e.to_string())),
+                    };
+                    (String::new(), result)
+                }
+            };
+            Box::pin(async move { retry::run(retries, backoff_ms, attempt, on_retry).await.1 })
+        }
+        Err(e) => {
+            let failure = CellFailure::from_message(e.to_string());
+            Box::pin(async move { Err(failure) })
+        }
+    }
+}
+
+/// Run a future from [`Runs::start`] on a task, passing what it prints to
+/// `on_output` every [`OUTPUT_INTERVAL`] and returning all of it at the end.
+pub fn spawn(
+    name: &str,
+    run: BoxFuture<'static, std::result::Result<(), CellFailure>>,
+    mut on_output: impl FnMut(&str) + Send + 'static,
+) -> JoinHandle<Finished> {
+    let name = name.to_string();
+    tokio::spawn(async move {
+        let start = Instant::now();
+        let on_output = |text: &str| {
+            if !text.is_empty() {
+                on_output(text);
+            }
+        };
+        let (stdout, result) =
+            capture_stdout_live(|| store::tracked(&name, run), OUTPUT_INTERVAL, on_output).await;
+        (stdout, result, start.elapsed())
+    })
+}
+
+/// Run a future from [`Runs::start`] on a task like [`spawn`], leaving what it
+/// prints on stdout, for the plain runner which prints it as it is written.
+pub fn spawn_uncaptured(
+    name: &str,
+    run: BoxFuture<'static, std::result::Result<(), CellFailure>>,
+) -> JoinHandle<Finished> {
+    let name = name.to_string();
+    tokio::spawn(async move {
+        let start = Instant::now();
+        let result = store::tracked(&name, run).await;
+        (String::new(), result, start.elapsed())
+    })
+}
+
+/// Wait for a spawned run, or forever if there is none.
+pub async fn join(handle: Option<&mut JoinHandle<Finished>>) -> std::result::Result<Finished, JoinError> {
+    match handle {
+        Some(handle) => handle.await,
+        None => std::future::pending().await,
+    }
+}
+
+/// What a joined run finished with. A run whose task was aborted or panicked failed.
+pub fn finished(joined: std::result::Result<Finished, JoinError>) -> Finished {
+    joined.unwrap_or_else(|e| {
+        let message = if e.is_cancelled() {
+            "interrupted".to_string()
+        } else {
+            e.to_string()
+        };
+        (
+            String::new(),
+            Err(CellFailure::from_message(message)),
+            Duration::ZERO,
+        )
+    })
+}
+
+const HELP: &str = "\
+Commands:
+  <number> or <name>  Run a cell (0 is init)
+  a                   Run init and every cell in order
+  w <number|name>     Watch a cell, re-running it after every reload (again to stop)
+  w                   List watched cells
+  l                   List cells
+  s                   List store keys
+  r                   Rebuild and reload the notebook
+  h                   Show this help
+  q                   Quit
+
+In a terminal, Up and Down go through earlier lines, Tab completes commands
+and cell names, and Ctrl+C clears the line. Ctrl+C while a cell runs interrupts it.";
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    /// Run the visible cell at this index.
+    Run(usize),
+    RunAll,
+    /// Toggle watching the visible cell at this index, or list watched cells.
+    Watch(Option<usize>),
+    List,
+    Store,
+    Reload,
+    Help,
+    Quit,
+}
+
+/// Parse a command line. Returns `None` for an empty line.
+fn parse_command(line: &str, cells: &[CellInfo]) -> Option<std::result::Result<Command, String>> {
+    let line = line.trim();
+    let command = match line {
+        "" => return None,
+        "a" | "all" => Command::RunAll,
+        "w" | "watch" => Command::Watch(None),
+        "l" | "list" => Command::List,
+        "s" | "store" => Command::Store,
+        "r" | "reload" => Command::Reload,
+        "h" | "help" | "?" => Command::Help,
+        "q" | "quit" | "exit" => Command::Quit,
+        _ => {
+            if let Some(arg) = line.strip_prefix("w ").or_else(|| line.strip_prefix("watch ")) {
+                return Some(match find_cell(arg.trim(), cells) {
+                    Some(0) => Err("The init cell can't be watched".to_string()),
+                    Some(idx) => Ok(Command::Watch(Some(idx))),
+                    None => Err(format!("Unknown cell '{}'", arg.trim())),
+                });
+            }
+            match find_cell(line, cells) {
+                Some(idx) => Command::Run(idx),
+                None => return Some(Err(format!("Unknown cell or command '{}', try 'h'", line))),
+            }
+        }
+    };
+    Some(Ok(command))
+}
+
+/// Words the line editor completes: the commands and the names of `cells`.
+fn completion_words(cells: &[CellInfo]) -> Vec<String> {
+    let commands = ["all", "watch", "list", "store", "reload", "help", "quit"];
+    commands
+        .into_iter()
+        .map(String::from)
+        .chain(cells.iter().map(|c| c.name.clone()))
+        .collect()
+}
+
+const COMMAND_PROMPT: &str = "cellbook> ";
+
+/// Prompt for the next line the editor reads: a waiting cell's message, or the
+/// command prompt once the runner is ready. `None` after the runner has stopped.
+fn next_prompt(ready: &std::sync::mpsc::Receiver<()>) -> Option<String> {
+    loop {
+        if let Some((_, message)) = prompt::pending() {
+            return Some(format!("{} ", message));
+        }
+        match ready.recv_timeout(Duration::from_millis(50)) {
+            Ok(()) => return Some(COMMAND_PROMPT.to_string()),
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {}
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => return None,
+        }
+    }
+}
+
+/// Index of a cell given by number or name.
+fn find_cell(arg: &str, cells: &[CellInfo]) -> Option<usize> {
+    match arg.parse::<usize>() {
+        Ok(idx) => Some(idx).filter(|&idx| idx < cells.len()),
+        Err(_) => cells.iter().position(|c| c.name == arg),
+    }
+}
+
+/// A cell run by `exec`, as `--json` prints it.
+#[derive(Debug, Serialize)]
+struct Executed {
+    name: String,
+    /// `ok`, `failed`, `service`, or `skipped` after init failed.
+    status: &'static str,
+    duration_ms: Option<u64>,
+    output: String,
+    error: Option<String>,
+}
+
+struct Runner<'a> {
+    lib: &'a mut LoadedLibrary,
+    config: GeneralConfig,
+    runs: Runs,
+    /// Names of cells re-run after every reload.
+    watched: HashSet<String>,
+    /// Notified on Ctrl+C at a cell's prompt, which the line editor reads instead of the signal.
+    interrupt: Arc<Notify>,
+}
+
+/// How a run from [`Runner::run_cell`] ended.
+#[derive(Debug, PartialEq)]
+enum Ran {
+    Succeeded,
+    Failed,
+    /// Aborted with Ctrl+C.
+    Interrupted,
+}
+
+/// Wait for Ctrl+C, from the terminal or the line editor.
+async fn interrupted(interrupt: &Notify) {
+    tokio::select! {
+        Ok(()) = tokio::signal::ctrl_c() => {}
+        () = interrupt.notified() => {}
+    }
+}
+
+impl Runner<'_> {
+    /// Run the visible cell at `idx` (0 is init), printing its output as it is written,
+    /// until it ends or Ctrl+C interrupts it. Service cells start in the background.
+    async fn run_cell(&mut self, idx: usize) -> Ran {
+        let cell = self.lib.visible_cells()[idx].clone();
+        let (name, retries) = (cell.name.clone(), cell.retries);
+        let on_retry = move |retry, e: &CellFailure| {
+            println!("[{}] retry {}/{} after: {}", name, retry, retries, e.message);
+        };
+        let run = match self.runs.start(self.lib, &self.config, idx, on_retry) {
+            Start::Run { future, .. } => future,
+            Start::Service => {
+                println!("[{}] running as a service until reload or exit", cell.name);
+                return Ran::Succeeded;
+            }
+            Start::Refused(reason) => {
+                println!("[{}] {}", cell.name, reason);
+                return Ran::Failed;
+            }
+        };
+        if !(cell.isolated || self.config.isolate_cells) && self.lib.is_stale() {
+            println!(
+                "[{}] running from a stale library, `reload` loads the latest build",
+                cell.name
+            );
+        }
+
+        println!("[{}]", cell.name);
+        let mut handle = spawn_uncaptured(&cell.name, run);
+        let interrupt = self.interrupt.clone();
+        let (_, result, duration) = tokio::select! {
+            joined = &mut handle => finished(joined),
+            () = interrupted(&interrupt) => {
+                self.runs.abort(handle).await;
+                // After the ^C the terminal echoed.
+                println!();
+                println!("[{}] interrupted", cell.name);
+                return Ran::Interrupted;
+            }
+        };
+        self.runs.finish(&cell, idx, duration, &result, &self.config);
+
+        let _ = std::io::stdout().flush();
+        match &result {
+            Ok(()) => println!("[{}] done in {:.2?}", cell.name, duration),
+            Err(e) => {
+                println!("[{}] failed after {:.2?}", cell.name, duration);
+                print!("{}", e.render(&cell.name, cell.line));
+            }
+        }
+        match result {
+            Ok(()) => Ran::Succeeded,
+            Err(_) => Ran::Failed,
+        }
+    }
+
+    /// Run the visible cell at `idx` like [`Runner::run_cell`], capturing its output
+    /// instead of printing it.
+    async fn exec_cell(&mut self, idx: usize) -> Executed {
+        let cell = self.lib.visible_cells()[idx].clone();
+        let mut executed = Executed {
+            name: cell.name.clone(),
+            status: "ok",
+            duration_ms: None,
+            output: String::new(),
+            error: None,
+        };
+        let (name, retries) = (cell.name.clone(), cell.retries);
+        let on_retry = move |retry, e: &CellFailure| {
+            println!("[{}] retry {}/{} after: {}", name, retry, retries, e.message);
+        };
+        let run = match self.runs.start(self.lib, &self.config, idx, on_retry) {
+            Start::Run { future, .. } => future,
+            Start::Service => {
+                executed.status = "service";
+                return executed;
+            }
+            Start::Refused(reason) => {
+                executed.status = "failed";
+                executed.error = Some(reason);
+                return executed;
+            }
+        };
+
+        let (output, result, duration) = finished(spawn(&cell.name, run, |_| {}).await);
+        self.runs.finish(&cell, idx, duration, &result, &self.config);
+        if let Err(e) = &result {
+            executed.status = "failed";
+            executed.error = Some(e.render(&cell.name, cell.line));
+        }
+        executed.duration_ms = Some(duration.as_millis() as u64);
+        executed.output = output;
+        executed
+    }
+
+    /// Run cells in order, stopping if init fails or a run is interrupted.
+    /// Returns the number of failed cells, counting an interrupted one.
+    async fn run_cells(&mut self, indices: &[usize]) -> usize {
+        let mut failed = 0;
+        for &idx in indices {
+            match self.run_cell(idx).await {
+                Ran::Succeeded => {}
+                Ran::Failed => {
+                    failed += 1;
+                    // Cells only make sense on top of a successful init.
+                    if idx == 0 {
+                        println!("Init failed, skipping the remaining cells");
+                        break;
+                    }
+                }
+                Ran::Interrupted => {
+                    failed += 1;
+                    break;
+                }
+            }
+        }
+        failed
+    }
+
+    /// Cells to run at startup and after each reload, per `run_on_start`.
+    fn start_cells(&self) -> Vec<usize> {
+        let cells = self.lib.visible_cells();
+        match &self.config.run_on_start {
+            RunOnStart::Mode(StartMode::Init) => vec![0],
+            RunOnStart::Mode(StartMode::None) => Vec::new(),
+            RunOnStart::Cells(names) => names
+                .iter()
+                .filter_map(|name| cells.iter().position(|c| &c.name == name))
+                .collect(),
+        }
+    }
+
+    /// Watched cells in list order.
+    fn watched_cells(&self) -> Vec<usize> {
+        self.lib
+            .visible_cells()
+            .iter()
+            .enumerate()
+            .filter(|(_, cell)| self.watched.contains(&cell.name))
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    /// Start or stop watching the visible cell at `idx`.
+    fn toggle_watch(&mut self, idx: usize) {
+        let name = self.lib.visible_cells()[idx].name.clone();
+        if self.watched.remove(&name) {
+            println!("Stopped watching {}", name);
+        } else {
+            println!("Watching {}, it re-runs after every reload", name);
+            self.watched.insert(name);
+        }
+    }
+
+    /// List cells by number. Hidden cells are left out but can still be run.
+    fn list_cells(&self) {
+        for (idx, cell) in self.lib.visible_cells().iter().enumerate() {
+            if cell.hidden {
+                continue;
+            }
+            let suffix = match (idx, &cell.title) {
+                (0, _) => " (init)".to_string(),
+                (_, Some(_)) => format!(" ({})", cell.name),
+                _ => String::new(),
+            };
+            println!("{:>3}  {}{}", idx, cell.display_name(), suffix);
+        }
+    }
+
+    fn list_store(&self) {
+        store::expire();
+        let mut keys = store::list();
+        if keys.is_empty() {
+            println!("Store is empty");
+            return;
+        }
+        keys.sort();
+        let sizes = store::sizes();
+        let expiries = store::expiries();
+        for (key, type_name) in keys {
+            let size = sizes.get(&key).copied().unwrap_or(0);
+            let stale = match expiries.get(&key) {
+                Some(expires) if *expires <= Instant::now() => " stale",
+                _ => "",
+            };
+            println!("{}: {} ({}){}", key, type_name, store::format_bytes(size), stale);
+        }
+    }
+
+    /// Drop state referencing the current library and load the new build.
+    async fn reload(&mut self) -> bool {
+        self.release().await;
+        match self.lib.reload() {
+            Ok(()) => {
+                println!("Reloaded {} cells", self.lib.cells().len());
+                if let Err(e) = self.config.reload(self.lib.config()) {
+                    println!("Error: {}", e);
+                }
+                true
+            }
+            Err(e) => {
+                println!("Error: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Reload and run the startup cells again followed by watched cells, as the TUI does.
+    async fn reload_and_start(&mut self) {
+        if self.reload().await {
+            let mut cells = self.start_cells();
+            for idx in self.watched_cells() {
+                if !cells.contains(&idx) {
+                    cells.push(idx);
+                }
+            }
+            self.run_cells(&cells).await;
+        }
+    }
+
+    /// Run teardown, then drop background tasks and resources.
+    async fn release(&mut self) {
+        self.lib.release().await.report(&mut std::io::stdout());
+    }
+}
+
+/// Run every cell once, printing output as it is written.
+/// Prompts from cells read their answer from stdin.
+pub async fn headless(lib: &mut LoadedLibrary, config: &GeneralConfig) -> Result<()> {
+    exec(lib, config, &[], None).await
+}
+
+/// Run init and the cells in `names`, by name or number, once, or every cell if
+/// `names` is empty, like [`headless`]. With `json`, cell output is captured and
+/// the runs are written there as a JSON array instead.
+pub async fn exec(
+    lib: &mut LoadedLibrary,
+    config: &GeneralConfig,
+    names: &[String],
+    json: Option<File>,
+) -> Result<()> {
+    let mut indices = vec![0];
+    for name in names {
+        let idx = find_cell(name, lib.visible_cells()).ok_or_else(|| Error::CellNotFound(name.clone()))?;
+        if !indices.contains(&idx) {
+            indices.push(idx);
+        }
+    }
+    if names.is_empty() {
+        indices = (0..lib.visible_cells().len()).collect();
+    }
+
+    prompt::set_input(prompt::Input::Stdin);
+    let mut runner = Runner {
+        lib,
+        config: config.clone(),
+        runs: Runs::default(),
+        watched: HashSet::new(),
+        interrupt: Arc::default(),
+    };
+
+    let failed = match json {
+        None => runner.run_cells(&indices).await,
+        Some(mut out) => {
+            let mut executed: Vec<Executed> = Vec::new();
+            for &idx in &indices {
+                // Cells only make sense on top of a successful init.
+                if executed.first().is_some_and(|init| init.status == "failed") {
+                    executed.push(Executed {
+                        name: runner.lib.visible_cells()[idx].name.clone(),
+                        status: "skipped",
+                        duration_ms: None,
+                        output: String::new(),
+                        error: None,
+                    });
+                    continue;
+                }
+                executed.push(runner.exec_cell(idx).await);
+            }
+            serde_json::to_writer_pretty(&mut out, &executed).map_err(std::io::Error::from)?;
+            writeln!(out)?;
+            executed.iter().filter(|e| e.status == "failed").count()
+        }
+    };
+    runner.release().await;
+    artifacts::cleanup();
+    store::remove_disk_files();
+
+    if failed > 0 {
+        return Err(Error::CellsFailed(failed));
+    }
+    Ok(())
+}
+
+/// Prompt for commands on stdin until `q` or end of input.
+/// Reloads on the watcher's events like the TUI. While a cell waits for input,
+/// the next line answers it instead.
+pub async fn plain(
+    lib: &mut LoadedLibrary,
+    mut event_rx: mpsc::Receiver<TuiEvent>,
+    config: &GeneralConfig,
+) -> Result<()> {
+    prompt::set_input(prompt::Input::Lines);
+    let mut runner = Runner {
+        lib,
+        config: config.clone(),
+        runs: Runs::default(),
+        watched: HashSet::new(),
+        interrupt: Arc::default(),
+    };
+
+    let (line_tx, mut line_rx) = mpsc::channel(8);
+    let words = Arc::new(Mutex::new(completion_words(runner.lib.visible_cells())));
+    // In a terminal, lines are edited and the prompt is drawn by the editor
+    // once the runner is ready for the next command.
+    let editor = if std::io::stdin().is_terminal() {
+        LineEditor::new(words.clone())
+            .map_err(|e| println!("Line editing is off: {}", e))
+            .ok()
+    } else {
+        None
+    };
+    let redraw = editor.as_ref().map(LineEditor::redraw);
+    let ready = if let Some(mut editor) = editor {
+        let (ready_tx, ready_rx) = std::sync::mpsc::channel();
+        let interrupt = runner.interrupt.clone();
+        tokio::task::spawn_blocking(move || {
+            while let Some(prompt) = next_prompt(&ready_rx) {
+                if prompt != COMMAND_PROMPT {
+                    // Draw the cell's prompt over the message it printed.
+                    eprint!("\r");
+                }
+                // Ctrl+C clears the command line, and interrupts a cell waiting for an answer.
+                let line = loop {
+                    match editor.read_line(&prompt) {
+                        Err(ReadlineError::Interrupted) if prompt == COMMAND_PROMPT => {}
+                        line => break line,
+                    }
+                };
+                let line = match line {
+                    Ok(line) => line,
+                    Err(ReadlineError::Interrupted) => {
+                        let asked = prompt::pending().map(|(id, _)| id);
+                        interrupt.notify_one();
+                        // Aborting the cell drops its prompt, which isn't asked again.
+                        while asked.is_some() && prompt::pending().map(|(id, _)| id) == asked {
+                            std::thread::sleep(Duration::from_millis(50));
+                        }
+                        continue;
+                    }
+                    Err(_) => break,
+                };
+                // The runner was ready again after output printed while the line was edited.
+                ready_rx.try_iter().count();
+                let Some(line) = prompt::answer(Some(line)) else {
+                    continue;
+                };
+                if line_tx.blocking_send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Some(ready_tx)
+    } else {
+        tokio::task::spawn_blocking(move || {
+            for line in std::io::stdin().lines() {
+                let Ok(line) = line else { break };
+                // A cell waiting for input takes the line instead of the command prompt.
+                let Some(line) = prompt::answer(Some(line)) else {
+                    continue;
+                };
+                if line_tx.blocking_send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        None
+    };
+
+    runner.list_cells();
+    println!("Type 'h' for help");
+    let start = runner.start_cells();
+    runner.run_cells(&start).await;
+
+    loop {
+        match &ready {
+            Some(ready) => {
+                *words.lock() = completion_words(runner.lib.visible_cells());
+                let _ = ready.send(());
+            }
+            None => {
+                print!("{}", COMMAND_PROMPT);
+                let _ = std::io::stdout().flush();
+            }
+        }
+
+        let line = tokio::select! {
+            line = line_rx.recv() => match line {
+                Some(line) => line,
+                // End of input.
+                None => break,
+            },
+            // While the line editor reads keys, Ctrl+C is one of them.
+            Ok(()) = tokio::signal::ctrl_c() => {
+                println!();
+                break;
+            }
+            Some(event) = event_rx.recv() => {
+                if matches!(
+                    event,
+                    TuiEvent::SourceChanged | TuiEvent::SourceUnchanged | TuiEvent::BuildCompleted(None)
+                ) {
+                    continue;
+                }
+                println!();
+                match event {
+                    TuiEvent::BuildStarted => println!("Building..."),
+                    TuiEvent::BuildCompleted(Some(e)) => println!("{}", e),
+                    TuiEvent::Reloaded => runner.reload_and_start().await,
+                    _ => {}
+                }
+                if let Some(redraw) = &redraw {
+                    redraw.redraw();
+                }
+                continue;
+            }
+        };
+
+        let command = match parse_command(&line, runner.lib.visible_cells()) {
+            None => continue,
+            Some(Ok(command)) => command,
+            Some(Err(e)) => {
+                println!("{}", e);
+                continue;
+            }
+        };
+        match command {
+            Command::Run(idx) => {
+                runner.run_cell(idx).await;
+            }
+            Command::RunAll => {
+                let all: Vec<usize> = (0..runner.lib.visible_cells().len()).collect();
+                runner.run_cells(&all).await;
+            }
+            Command::Watch(Some(idx)) => runner.toggle_watch(idx),
+            Command::Watch(None) => {
+                let watched = runner.watched_cells();
+                if watched.is_empty() {
+                    println!("No watched cells");
+                }
+                for idx in watched {
+                    println!("{:>3}  {}", idx, runner.lib.visible_cells()[idx].display_name());
+                }
+            }
+            Command::List => runner.list_cells(),
+            Command::Store => runner.list_store(),
+            Command::Reload => match watcher::rebuild().await {
+                Ok(()) => runner.reload_and_start().await,
+                Err(e) => println!("{}", e),
+            },
+            Command::Help => println!("{}", HELP),
+            Command::Quit => break,
+        }
+    }
+
+    runner.release().await;
+    artifacts::cleanup();
+    store::remove_disk_files();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        let cells = vec![CellInfo::new("nb", "setup", 1), CellInfo::new("nb", "load", 5)];
+        assert_eq!(parse_command("  ", &cells), None);
+        assert_eq!(parse_command("1", &cells), Some(Ok(Command::Run(1))));
+        assert_eq!(parse_command("load", &cells), Some(Ok(Command::Run(1))));
+        assert_eq!(parse_command("a", &cells), Some(Ok(Command::RunAll)));
+        assert_eq!(parse_command("q", &cells), Some(Ok(Command::Quit)));
+        assert!(matches!(parse_command("2", &cells), Some(Err(_))));
+        assert!(matches!(parse_command("missing", &cells), Some(Err(_))));
+        assert_eq!(parse_command("w", &cells), Some(Ok(Command::Watch(None))));
+        assert_eq!(parse_command("w load", &cells), Some(Ok(Command::Watch(Some(1)))));
+        assert_eq!(
+            parse_command("watch 1", &cells),
+            Some(Ok(Command::Watch(Some(1))))
+        );
+        assert!(matches!(parse_command("w 0", &cells), Some(Err(_))));
+    }
+}
@@ -0,0 +1,531 @@
+//! Provenance of cell runs, kept as JSON lines under `.cellbook/runs/`.
+//!
+//! Each `cargo cellbook run` or `export` session writes one file with a line
+//! per cell execution, recording the commit and uncommitted files the notebook
+//! was built from, so a result can be traced back to the code that produced it.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::LazyLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{Error, Result};
+use crate::run_info::{self, RunInfo};
+use crate::store::Access;
+use crate::tui::config;
+
+const RUNS_DIR: &str = ".cellbook/runs";
+
+/// Code version and settings a notebook build came from.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Source {
+    /// Commit checked out in the notebook's git repository, if it has one.
+    pub commit: Option<String>,
+    /// Files with uncommitted changes, as listed by `git status`.
+    pub dirty: Vec<String>,
+    /// Cargo features from `[build]`.
+    pub features: Vec<String>,
+    pub no_default_features: bool,
+}
+
+impl Source {
+    /// Read the current commit and build settings.
+    fn current() -> Self {
+        let build = config::load_build();
+        let dirty = git(&["status", "--porcelain"])
+            .map(|status| {
+                status
+                    .lines()
+                    .filter_map(|line| line.get(3..))
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self {
+            commit: git(&["rev-parse", "HEAD"]).map(|out| out.trim().to_string()),
+            dirty,
+            features: build.features,
+            no_default_features: build.no_default_features,
+        }
+    }
+}
+
+/// One cell execution.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Record {
+    pub cell: String,
+    /// Seconds since the Unix epoch when the cell finished.
+    pub finished: u64,
+    pub duration_us: u64,
+    /// Error message if the cell failed.
+    pub error: Option<String>,
+    /// Store keys the cell wrote or removed.
+    pub writes: Vec<String>,
+    pub seed: u64,
+    pub run_timestamp: u64,
+    /// How long the build the cell ran from took, see [`Timings`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_us: Option<u64>,
+    /// How long loading the library the cell ran from took.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub load_us: Option<u64>,
+    /// Set for a `#[cell(service)]`, which gets a record when it starts and when it ends.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service: Option<ServiceEvent>,
+    #[serde(flatten)]
+    pub source: Source,
+}
+
+/// What a record of a service cell marks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ServiceEvent {
+    Started,
+    /// The service returned, failed or was stopped. The duration is how long it ran.
+    Ended,
+}
+
+/// How long the last notebook build and library load took, to tell compile
+/// times apart from cell execution.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Timings {
+    pub build: Option<Duration>,
+    pub load: Option<Duration>,
+}
+
+static TIMINGS: LazyLock<Mutex<Timings>> = LazyLock::new(|| Mutex::new(Timings::default()));
+
+/// Note how long a successful build of the notebook took.
+pub fn built(duration: Duration) {
+    TIMINGS.lock().build = Some(duration);
+}
+
+/// Note how long loading the notebook library took.
+pub fn loaded(duration: Duration) {
+    TIMINGS.lock().load = Some(duration);
+}
+
+pub fn timings() -> Timings {
+    *TIMINGS.lock()
+}
+
+struct Log {
+    path: PathBuf,
+    run_info: RunInfo,
+    source: Source,
+}
+
+static LOG: LazyLock<Mutex<Option<Log>>> = LazyLock::new(|| Mutex::new(None));
+
+/// Start recording cell runs of this session.
+pub fn start(run_info: &RunInfo) {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    *LOG.lock() = Some(Log {
+        path: Path::new(RUNS_DIR).join(format!("{}-{}.jsonl", secs, std::process::id())),
+        run_info: *run_info,
+        source: Source::current(),
+    });
+}
+
+/// Seed and start time of the session being recorded.
+pub fn run_info() -> Option<RunInfo> {
+    LOG.lock().as_ref().map(|log| log.run_info)
+}
+
+/// Read the commit and build settings again, after the notebook was rebuilt.
+pub fn refresh() {
+    if let Some(log) = LOG.lock().as_mut() {
+        log.source = Source::current();
+    }
+}
+
+/// Append a cell execution to the session's log, if recording.
+pub fn record(cell: &str, duration: Duration, error: Option<&str>, access: &Access) {
+    append_record(
+        cell,
+        duration,
+        error,
+        access.writes.iter().cloned().collect(),
+        None,
+    );
+}
+
+/// Append the start or end of a service cell to the session's log, if recording.
+pub fn record_service(cell: &str, event: ServiceEvent, duration: Duration, error: Option<&str>) {
+    append_record(cell, duration, error, Vec::new(), Some(event));
+}
+
+fn append_record(
+    cell: &str,
+    duration: Duration,
+    error: Option<&str>,
+    writes: Vec<String>,
+    service: Option<ServiceEvent>,
+) {
+    let timings = timings();
+    let mut guard = LOG.lock();
+    let Some(log) = guard.as_mut() else {
+        return;
+    };
+    let record = Record {
+        cell: cell.to_string(),
+        finished: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        duration_us: duration.as_micros() as u64,
+        error: error.map(String::from),
+        writes,
+        seed: log.run_info.seed,
+        run_timestamp: log.run_info.timestamp,
+        build_us: timings.build.map(|d| d.as_micros() as u64),
+        load_us: timings.load.map(|d| d.as_micros() as u64),
+        service,
+        source: log.source.clone(),
+    };
+    // Provenance is best effort and never fails a run.
+    let _ = append(&log.path, &record);
+}
+
+fn append(path: &Path, record: &Record) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut line = serde_json::to_string(record).map_err(|e| Error::Runs(e.to_string()))?;
+    line.push('\n');
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// Output of a git command in the current directory, if it succeeded.
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Session ids, oldest first.
+fn sessions() -> Result<Vec<String>> {
+    let mut ids: Vec<(u64, String)> = match fs::read_dir(RUNS_DIR) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let id = name.strip_suffix(".jsonl")?.to_string();
+                let secs = id.split('-').next()?.parse().ok()?;
+                Some((secs, id))
+            })
+            .collect(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e.into()),
+    };
+    ids.sort();
+    Ok(ids.into_iter().map(|(_, id)| id).collect())
+}
+
+/// Records of a session. Lines that don't parse are skipped.
+fn read(id: &str) -> Result<Vec<Record>> {
+    let path = Path::new(RUNS_DIR).join(format!("{}.jsonl", id));
+    let content = fs::read_to_string(&path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => {
+            Error::Runs(format!("no run '{}', see `cargo cellbook runs list`", id))
+        }
+        _ => e.into(),
+    })?;
+    Ok(parse(&content))
+}
+
+fn parse(content: &str) -> Vec<Record> {
+    content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// Short commit with a `+dirty` suffix, or `-` outside a git repository.
+fn describe_source(source: &Source) -> String {
+    let Some(commit) = &source.commit else {
+        return "-".to_string();
+    };
+    let short = &commit[..commit.len().min(10)];
+    if source.dirty.is_empty() {
+        short.to_string()
+    } else {
+        format!("{}+dirty", short)
+    }
+}
+
+/// A session as printed by `runs list --json`.
+#[derive(Debug, Serialize)]
+struct SessionSummary<'a> {
+    id: &'a str,
+    /// Seconds since the Unix epoch when the session started.
+    started: Option<u64>,
+    commit: Option<&'a str>,
+    dirty: &'a [String],
+    runs: usize,
+    failed: usize,
+}
+
+fn summarize(sessions: &[(String, Vec<Record>)]) -> Vec<SessionSummary<'_>> {
+    sessions
+        .iter()
+        .filter_map(|(id, records)| {
+            let first = records.first()?;
+            Some(SessionSummary {
+                id,
+                started: id.split('-').next().and_then(|secs| secs.parse().ok()),
+                commit: first.source.commit.as_deref(),
+                dirty: &first.source.dirty,
+                runs: records.len(),
+                failed: records.iter().filter(|r| r.error.is_some()).count(),
+            })
+        })
+        .collect()
+}
+
+/// One line per session: id, start time, commit and outcome.
+fn format_list(sessions: &[(String, Vec<Record>)]) -> String {
+    let mut out = String::new();
+    for (id, records) in sessions {
+        let Some(first) = records.first() else {
+            continue;
+        };
+        let failed = records.iter().filter(|r| r.error.is_some()).count();
+        let started = id
+            .split('-')
+            .next()
+            .and_then(|secs| secs.parse().ok())
+            .map(run_info::format_utc)
+            .unwrap_or_default();
+        out.push_str(&format!(
+            "{}  {}  {}  {} runs, {} failed\n",
+            id,
+            started,
+            describe_source(&first.source),
+            records.len(),
+            failed
+        ));
+    }
+    out
+}
+
+/// Settings of a session and a line per cell execution. The commit is repeated
+/// whenever a rebuild changed it.
+fn format_show(id: &str, records: &[Record]) -> String {
+    let mut out = format!("Run {}\n", id);
+    if let Some(first) = records.first() {
+        out.push_str(&format!(
+            "{}\n",
+            RunInfo {
+                seed: first.seed,
+                timestamp: first.run_timestamp,
+            }
+            .describe()
+        ));
+        if !first.source.features.is_empty() || first.source.no_default_features {
+            out.push_str(&format!(
+                "Features: {}{}\n",
+                first.source.features.join(", "),
+                if first.source.no_default_features {
+                    " (no default features)"
+                } else {
+                    ""
+                }
+            ));
+        }
+    }
+
+    let mut source: Option<&Source> = None;
+    let mut timings = None;
+    for record in records {
+        if source != Some(&record.source) {
+            out.push_str(&format!("\nCommit {}\n", describe_source(&record.source)));
+            for file in &record.source.dirty {
+                out.push_str(&format!("  modified {}\n", file));
+            }
+            source = Some(&record.source);
+        }
+        // Printed again whenever the notebook was rebuilt or reloaded.
+        if timings != Some((record.build_us, record.load_us)) {
+            if let Some(line) = describe_timings(record.build_us, record.load_us) {
+                out.push_str(&format!("  {}\n", line));
+            }
+            timings = Some((record.build_us, record.load_us));
+        }
+        let status = match (&record.error, record.service) {
+            (Some(_), _) => "✗",
+            (None, Some(ServiceEvent::Started)) => "▶",
+            (None, Some(ServiceEvent::Ended)) => "■",
+            (None, None) => "✓",
+        };
+        out.push_str(&format!("  {} {}", status, record.cell));
+        match record.service {
+            Some(ServiceEvent::Started) => out.push_str(" started"),
+            Some(ServiceEvent::Ended) => out.push_str(&format!(
+                " ended after {:.2?}",
+                Duration::from_micros(record.duration_us)
+            )),
+            None => out.push_str(&format!(" {:.2?}", Duration::from_micros(record.duration_us))),
+        }
+        if !record.writes.is_empty() {
+            out.push_str(&format!("  wrote {}", record.writes.join(", ")));
+        }
+        out.push('\n');
+        if let Some(error) = &record.error {
+            out.push_str(&format!("      {}\n", error.lines().next().unwrap_or_default()));
+        }
+    }
+    out
+}
+
+/// `built in 12.31s, loaded in 40.12ms`, or `None` if neither was recorded.
+fn describe_timings(build_us: Option<u64>, load_us: Option<u64>) -> Option<String> {
+    let parts: Vec<String> = [("built", build_us), ("loaded", load_us)]
+        .into_iter()
+        .filter_map(|(what, us)| Some(format!("{} in {:.2?}", what, Duration::from_micros(us?))))
+        .collect();
+    (!parts.is_empty()).then(|| parts.join(", "))
+}
+
+/// Print the recorded sessions, oldest first, as a JSON array if `json` is set.
+pub fn list(json: bool) -> Result<()> {
+    let sessions = sessions()?
+        .into_iter()
+        .map(|id| {
+            let records = read(&id)?;
+            Ok((id, records))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if json {
+        println!("{}", to_json(&summarize(&sessions))?);
+        return Ok(());
+    }
+    if sessions.is_empty() {
+        println!("No runs recorded in {}", RUNS_DIR);
+        return Ok(());
+    }
+    print!("{}", format_list(&sessions));
+    Ok(())
+}
+
+/// Print the cell executions of a session, the latest if `id` is `None`,
+/// as a JSON array of records if `json` is set.
+pub fn show(id: Option<&str>, json: bool) -> Result<()> {
+    let id = match id {
+        Some(id) => id.to_string(),
+        None => sessions()?
+            .pop()
+            .ok_or_else(|| Error::Runs(format!("no runs recorded in {}", RUNS_DIR)))?,
+    };
+    let records = read(&id)?;
+    if json {
+        println!("{}", to_json(&records)?);
+    } else {
+        print!("{}", format_show(&id, &records));
+    }
+    Ok(())
+}
+
+fn to_json(value: &impl Serialize) -> Result<String> {
+    serde_json::to_string_pretty(value).map_err(|e| Error::Runs(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(cell: &str, dirty: &[&str], error: Option<&str>) -> Record {
+        Record {
+            cell: cell.to_string(),
+            finished: 1_700_000_010,
+            duration_us: 1_500_000,
+            error: error.map(String::from),
+            writes: vec!["prices".to_string()],
+            seed: 42,
+            run_timestamp: 1_700_000_000,
+            build_us: Some(12_300_000),
+            load_us: Some(40_000),
+            service: None,
+            source: Source {
+                commit: Some("0123456789abcdef".to_string()),
+                dirty: dirty.iter().map(|f| f.to_string()).collect(),
+                features: vec!["plots".to_string()],
+                no_default_features: false,
+            },
+        }
+    }
+
+    #[test]
+    fn test_records_round_trip_as_json_lines() {
+        let records = vec![
+            record("load", &[], None),
+            record("plot", &["cellbook.rs"], Some("boom")),
+        ];
+        let content: String = records
+            .iter()
+            .map(|r| serde_json::to_string(r).unwrap() + "\n")
+            .collect();
+        assert!(content.starts_with("{\"cell\":\"load\""));
+        assert!(content.contains("\"commit\":\"0123456789abcdef\""));
+        assert_eq!(parse(&(content + "not json\n")), records);
+    }
+
+    #[test]
+    fn test_records_without_timings_still_parse() {
+        let mut old = record("load", &[], None);
+        old.build_us = None;
+        old.load_us = None;
+        let line = serde_json::to_string(&old).unwrap();
+        assert!(!line.contains("build_us"));
+        assert_eq!(parse(&line), vec![old]);
+    }
+
+    #[test]
+    fn test_show_marks_commit_changes() {
+        let records = vec![
+            record("load", &[], None),
+            record("plot", &["cellbook.rs"], Some("boom\nmore")),
+        ];
+        let out = format_show("1700000000-1", &records);
+        assert!(out.starts_with("Run 1700000000-1\nseed 42, run at 2023-11-14 22:13:20 UTC"));
+        assert!(out.contains("Features: plots\n"));
+        assert!(out.contains(
+            "\nCommit 0123456789\n  built in 12.30s, loaded in 40.00ms\n  ✓ load 1.50s  wrote prices\n"
+        ));
+        assert!(out.contains(
+            "\nCommit 0123456789+dirty\n  modified cellbook.rs\n  ✗ plot 1.50s  wrote prices\n      boom\n"
+        ));
+
+        let mut started = record("server", &[], None);
+        started.service = Some(ServiceEvent::Started);
+        let mut ended = record("server", &[], Some("port in use"));
+        ended.service = Some(ServiceEvent::Ended);
+        let services = format_show("1700000000-1", &[started.clone(), ended.clone()]);
+        assert!(services.contains(
+            "  ▶ server started  wrote prices
+  ✗ server ended after 1.50s"
+        ));
+        assert_eq!(parse(&serde_json::to_string(&started).unwrap()), vec![started]);
+
+        let list = format_list(&[("1700000000-1".to_string(), records)]);
+        assert_eq!(
+            list,
+            "1700000000-1  2023-11-14 22:13:20 UTC  0123456789  2 runs, 1 failed\n"
+        );
+    }
+}
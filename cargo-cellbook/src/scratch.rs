@@ -0,0 +1,233 @@
+//! Scratch cells: a snippet typed in the runner, compiled as a throwaway
+//! crate against the notebook's dependencies and run with the live store.
+//!
+//! The scratch crate lives in `<target>/cellbook-scratch` and shares the
+//! notebook's target directory, so dependencies are only compiled once.
+
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use tokio::process::Command;
+
+use crate::errors::{Error, Result};
+use crate::tui::config;
+use crate::{fast_build, loader};
+
+/// Name of the cell generated around the snippet.
+pub const CELL_NAME: &str = "scratch";
+
+const PACKAGE_NAME: &str = "cellbook-scratch";
+
+/// Write the scratch crate for `snippet`, build it and return the library path.
+///
+/// `lib_path` is the notebook library; the scratch library is built next to it.
+pub async fn build(snippet: &str, lib_path: &Path) -> Result<PathBuf> {
+    let profile_dir = lib_path
+        .parent()
+        .ok_or_else(|| Error::LibLoad("Notebook library has no parent directory".to_string()))?;
+    let target_dir = profile_dir.parent().unwrap_or(profile_dir);
+    let crate_dir = target_dir.join(PACKAGE_NAME);
+    std::fs::create_dir_all(&crate_dir)?;
+
+    let notebook_dir = std::env::current_dir()?;
+    std::fs::write(crate_dir.join("Cargo.toml"), manifest(&notebook_dir)?)?;
+    std::fs::write(crate_dir.join("scratch.rs"), source(snippet))?;
+    // Reuse the notebook's resolved versions so nothing new has to be fetched.
+    if let Some(lockfile) = find_lockfile(&notebook_dir) {
+        let _ = std::fs::copy(lockfile, crate_dir.join("Cargo.lock"));
+    }
+
+    // The notebook's profile, so its dependencies are reused.
+    let build = config::load_build();
+    let profile_args = if build.fast_build {
+        fast_build::cargo_args(&build)
+    } else {
+        Vec::new()
+    };
+    let output = Command::new("cargo")
+        .args(["build", "--lib", "--manifest-path"])
+        .arg(crate_dir.join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(target_dir)
+        .args(profile_args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Error::Build(stderr.to_string()));
+    }
+
+    Ok(profile_dir.join(loader::dylib_file_name(PACKAGE_NAME)))
+}
+
+/// Source of the scratch crate. The snippet is the body of a block whose
+/// value is printed with `Debug`.
+fn source(snippet: &str) -> String {
+    format!(
+        r#"#![allow(unused)]
+
+use cellbook::*;
+
+#[init]
+async fn scratch_init() -> std::result::Result<(), Box<dyn std::error::Error + Send + Sync>> {{
+    Ok(())
+}}
+
+#[cell]
+async fn {CELL_NAME}() -> std::result::Result<(), Box<dyn std::error::Error + Send + Sync>> {{
+    let value = {{
+{snippet}
+    }};
+    println!("{{:#?}}", value);
+    Ok(())
+}}
+"#
+    )
+}
+
+/// Manifest of the scratch crate, with the notebook's dependencies.
+fn manifest(notebook_dir: &Path) -> Result<String> {
+    let contents = std::fs::read_to_string(notebook_dir.join("Cargo.toml"))?;
+    let notebook: toml::Table =
+        toml::from_str(&contents).map_err(|e| Error::LibLoad(format!("Invalid Cargo.toml: {}", e)))?;
+    let workspace = find_workspace(notebook_dir);
+
+    let mut dependencies = toml::Table::new();
+    if let Some(deps) = notebook.get("dependencies").and_then(|d| d.as_table()) {
+        for (name, dep) in deps {
+            dependencies.insert(
+                name.clone(),
+                resolve_dependency(name, dep, notebook_dir, &workspace),
+            );
+        }
+    }
+
+    let mut package = toml::Table::new();
+    package.insert("name".into(), PACKAGE_NAME.into());
+    package.insert("version".into(), "0.0.0".into());
+    package.insert("edition".into(), "2024".into());
+    package.insert("publish".into(), false.into());
+
+    let mut lib = toml::Table::new();
+    lib.insert("crate-type".into(), toml::Value::Array(vec!["cdylib".into()]));
+    lib.insert("path".into(), "scratch.rs".into());
+
+    let mut manifest = toml::Table::new();
+    manifest.insert("package".into(), package.into());
+    manifest.insert("lib".into(), lib.into());
+    manifest.insert("dependencies".into(), dependencies.into());
+    // Keep the scratch crate out of any enclosing workspace.
+    manifest.insert("workspace".into(), toml::Table::new().into());
+
+    toml::to_string(&manifest).map_err(|e| Error::LibLoad(e.to_string()))
+}
+
+/// Make a dependency usable from the scratch crate: inherit workspace
+/// dependencies and make `path` entries absolute.
+fn resolve_dependency(
+    name: &str,
+    dep: &toml::Value,
+    notebook_dir: &Path,
+    workspace: &Option<(PathBuf, toml::Table)>,
+) -> toml::Value {
+    let Some(table) = dep.as_table() else {
+        return dep.clone();
+    };
+
+    let inherited = table.get("workspace").and_then(|w| w.as_bool()) == Some(true);
+    if inherited && let Some((root, manifest)) = workspace {
+        let base = manifest
+            .get("workspace")
+            .and_then(|w| w.get("dependencies"))
+            .and_then(|d| d.get(name));
+        if let Some(base) = base {
+            let mut resolved = match resolve_dependency(name, base, root, &None) {
+                toml::Value::Table(t) => t,
+                version => toml::Table::from_iter([("version".to_string(), version)]),
+            };
+            for (key, value) in table {
+                if key != "workspace" {
+                    resolved.insert(key.clone(), value.clone());
+                }
+            }
+            return resolved.into();
+        }
+    }
+
+    let mut resolved = table.clone();
+    if let Some(path) = table.get("path").and_then(|p| p.as_str()) {
+        let absolute = notebook_dir.join(path);
+        resolved.insert("path".into(), absolute.to_string_lossy().into_owned().into());
+    }
+    resolved.into()
+}
+
+/// Nearest enclosing workspace manifest, if any.
+fn find_workspace(notebook_dir: &Path) -> Option<(PathBuf, toml::Table)> {
+    notebook_dir.ancestors().find_map(|dir| {
+        let contents = std::fs::read_to_string(dir.join("Cargo.toml")).ok()?;
+        let manifest: toml::Table = toml::from_str(&contents).ok()?;
+        manifest
+            .contains_key("workspace")
+            .then(|| (dir.to_path_buf(), manifest))
+    })
+}
+
+fn find_lockfile(notebook_dir: &Path) -> Option<PathBuf> {
+    notebook_dir
+        .ancestors()
+        .map(|dir| dir.join("Cargo.lock"))
+        .find(|path| path.exists())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_dependency_paths_and_workspace() {
+        let notebook = Path::new("/work/examples/demo");
+        let workspace: toml::Table = toml::from_str(
+            r#"
+[workspace.dependencies]
+serde = "1"
+cellbook = { path = "cellbook" }
+"#,
+        )
+        .unwrap();
+        let workspace = Some((PathBuf::from("/work"), workspace));
+
+        let dep = |s: &str| toml::from_str::<toml::Table>(s).unwrap()["dep"].clone();
+
+        let local = resolve_dependency("x", &dep(r#"dep = { path = "../../x" }"#), notebook, &workspace);
+        assert_eq!(local["path"].as_str(), Some("/work/examples/demo/../../x"));
+
+        let inherited = resolve_dependency(
+            "serde",
+            &dep(r#"dep = { workspace = true, features = ["derive"] }"#),
+            notebook,
+            &workspace,
+        );
+        assert_eq!(inherited["version"].as_str(), Some("1"));
+        assert!(inherited.get("workspace").is_none());
+        assert!(inherited.get("features").is_some());
+
+        let inherited_path = resolve_dependency(
+            "cellbook",
+            &dep("dep = { workspace = true }"),
+            notebook,
+            &workspace,
+        );
+        assert_eq!(inherited_path["path"].as_str(), Some("/work/cellbook"));
+    }
+
+    #[test]
+    fn test_source_wraps_snippet_in_cell() {
+        let source = source("let x = 1;\nx + 1");
+        assert!(source.contains("async fn scratch()"));
+        assert!(source.contains("let value = {\nlet x = 1;\nx + 1\n    };"));
+    }
+}
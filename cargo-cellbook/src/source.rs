@@ -0,0 +1,375 @@
+//! Lightweight inspection of notebook source code.
+//!
+//! Provides a small Rust lexer that is good enough for highlighting and
+//! for locating the extent of a cell function by brace matching.
+
+use std::ops::RangeInclusive;
+
+/// Kind of a lexed source token.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenKind {
+    Whitespace,
+    Comment,
+    String,
+    Char,
+    Lifetime,
+    Number,
+    Keyword,
+    Ident,
+    Macro,
+    Punct,
+}
+
+/// A lexed source token borrowing from the input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub kind: TokenKind,
+    pub text: &'a str,
+}
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "false",
+    "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return",
+    "self", "Self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use", "where", "while",
+];
+
+/// Split Rust source into tokens. Concatenating all token texts yields the input.
+pub fn tokenize(src: &str) -> Vec<Token<'_>> {
+    let bytes = src.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let start = i;
+        let c = bytes[i];
+        let kind = if c.is_ascii_whitespace() {
+            while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+                i += 1;
+            }
+            TokenKind::Whitespace
+        } else if src[i..].starts_with("//") {
+            i = src[i..].find('\n').map_or(bytes.len(), |n| i + n);
+            TokenKind::Comment
+        } else if src[i..].starts_with("/*") {
+            i = src[i + 2..].find("*/").map_or(bytes.len(), |n| i + 2 + n + 2);
+            TokenKind::Comment
+        } else if let Some(end) = raw_string_end(src, i) {
+            i = end;
+            TokenKind::String
+        } else if c == b'"' || (c == b'b' && bytes.get(i + 1) == Some(&b'"')) {
+            i = quoted_end(bytes, if c == b'b' { i + 1 } else { i }, b'"');
+            TokenKind::String
+        } else if c == b'\'' {
+            if let Some(end) = char_literal_end(bytes, i) {
+                i = end;
+                TokenKind::Char
+            } else {
+                i += 1;
+                while i < bytes.len() && is_ident_byte(bytes[i]) {
+                    i += 1;
+                }
+                TokenKind::Lifetime
+            }
+        } else if c.is_ascii_digit() {
+            while i < bytes.len()
+                && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_' || bytes[i] == b'.')
+            {
+                // Stop before range operators such as `0..10`.
+                if bytes[i] == b'.' && bytes.get(i + 1) == Some(&b'.') {
+                    break;
+                }
+                i += 1;
+            }
+            TokenKind::Number
+        } else if is_ident_byte(c) {
+            while i < bytes.len() && is_ident_byte(bytes[i]) {
+                i += 1;
+            }
+            let word = &src[start..i];
+            if bytes.get(i) == Some(&b'!') && bytes.get(i + 1) != Some(&b'=') {
+                i += 1;
+                TokenKind::Macro
+            } else if KEYWORDS.contains(&word) {
+                TokenKind::Keyword
+            } else {
+                TokenKind::Ident
+            }
+        } else {
+            i += src[i..].chars().next().map_or(1, char::len_utf8);
+            TokenKind::Punct
+        };
+
+        tokens.push(Token {
+            kind,
+            text: &src[start..i],
+        });
+    }
+
+    tokens
+}
+
+fn is_ident_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b >= 0x80
+}
+
+/// End offset of a quoted literal starting at `start`, honoring backslash escapes.
+fn quoted_end(bytes: &[u8], start: usize, quote: u8) -> usize {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b if b == quote => return i + 1,
+            _ => i += 1,
+        }
+    }
+    bytes.len()
+}
+
+/// End offset of a char literal, or `None` if the quote starts a lifetime.
+fn char_literal_end(bytes: &[u8], start: usize) -> Option<usize> {
+    match bytes.get(start + 1)? {
+        b'\\' => Some(quoted_end(bytes, start, b'\'')),
+        _ => {
+            // A char literal closes after a single (possibly multi-byte) character.
+            let rest = std::str::from_utf8(&bytes[start + 1..]).ok()?;
+            let len = rest.chars().next()?.len_utf8();
+            (bytes.get(start + 1 + len) == Some(&b'\'')).then_some(start + len + 2)
+        }
+    }
+}
+
+/// End offset of a raw string literal (`r"..."`, `r#"..."#`, `br"..."`) starting at `start`.
+fn raw_string_end(src: &str, start: usize) -> Option<usize> {
+    let rest = &src[start..];
+    let rest = rest.strip_prefix('b').unwrap_or(rest);
+    let after_r = rest.strip_prefix('r')?;
+    let hashes = after_r.len() - after_r.trim_start_matches('#').len();
+    if !after_r[hashes..].starts_with('"') {
+        return None;
+    }
+
+    let prefix_len = src[start..].len() - after_r.len() + hashes + 1;
+    let body_start = start + prefix_len;
+    let terminator = format!("\"{}", "#".repeat(hashes));
+    Some(
+        src[body_start..]
+            .find(&terminator)
+            .map_or(src.len(), |n| body_start + n + terminator.len()),
+    )
+}
+
+/// Extract the source of the item whose name appears on the 1-based `line`,
+/// including preceding attributes and doc comments.
+pub fn extract_item(source: &str, line: u32) -> Option<&str> {
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let item_line = (line as usize).checked_sub(1)?;
+    let item_offset = *line_starts.get(item_line)?;
+
+    let mut first_line = item_line;
+    while first_line > 0 {
+        let prev = source[line_starts[first_line - 1]..]
+            .lines()
+            .next()
+            .unwrap_or("")
+            .trim_start();
+        if prev.starts_with("#[") || prev.starts_with("///") {
+            first_line -= 1;
+        } else {
+            break;
+        }
+    }
+
+    let mut depth = 0usize;
+    let mut offset = item_offset;
+    for token in tokenize(&source[item_offset..]) {
+        offset += token.text.len();
+        if token.kind != TokenKind::Punct {
+            continue;
+        }
+        match token.text {
+            "{" => depth += 1,
+            "}" => {
+                depth = depth.checked_sub(1)?;
+                if depth == 0 {
+                    return Some(&source[line_starts[first_line]..offset]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// A function marked `#[cell]`, `#[init]` or `#[teardown]` and the 1-based lines it spans,
+/// including its attributes and doc comments.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CellSpan {
+    pub name: String,
+    pub lines: RangeInclusive<u32>,
+}
+
+/// Find cell functions by their attributes, without building the notebook.
+pub fn cell_spans(source: &str) -> Vec<CellSpan> {
+    let lines: Vec<&str> = source.lines().collect();
+    let is_cell_attr = |line: &str| {
+        ["#[cell", "#[init", "#[teardown"].iter().any(|attr| {
+            line.strip_prefix(attr)
+                .is_some_and(|rest| rest.starts_with(']') || rest.starts_with('('))
+        })
+    };
+
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        if !is_cell_attr(lines[i].trim_start()) {
+            i += 1;
+            continue;
+        }
+        // The function follows the remaining attributes and doc comments.
+        let Some(fn_index) = (i..lines.len()).find(|&j| {
+            let line = lines[j].trim_start();
+            !(line.starts_with("#[") || line.starts_with("///") || line.is_empty())
+        }) else {
+            break;
+        };
+        let name = fn_name(lines[fn_index]);
+        let item = extract_item(source, fn_index as u32 + 1);
+        if let (Some(name), Some(item)) = (name, item) {
+            let attr_lines = item
+                .lines()
+                .map(str::trim_start)
+                .take_while(|line| line.starts_with("#[") || line.starts_with("///"))
+                .count() as u32;
+            let start = fn_index as u32 + 1 - attr_lines;
+            spans.push(CellSpan {
+                name,
+                lines: start..=start + item.lines().count() as u32 - 1,
+            });
+        }
+        i = fn_index + 1;
+    }
+    spans
+}
+
+/// Name of the function declared on `line`, such as `pub async fn name()`.
+fn fn_name(line: &str) -> Option<String> {
+    let tokens: Vec<Token> = tokenize(line)
+        .into_iter()
+        .filter(|t| t.kind != TokenKind::Whitespace)
+        .collect();
+    let fn_pos = tokens.iter().position(|t| t.text == "fn")?;
+    let name = tokens.get(fn_pos + 1)?;
+    (name.kind == TokenKind::Ident).then(|| name.text.to_string())
+}
+
+/// Text of the `///` doc comment at the start of an item from [`extract_item`].
+pub fn doc_comment(item: &str) -> Option<String> {
+    let lines: Vec<&str> = item
+        .lines()
+        .map(str::trim_start)
+        .take_while(|line| line.starts_with("///") || line.starts_with("#["))
+        .filter_map(|line| line.strip_prefix("///"))
+        .map(|line| line.strip_prefix(' ').unwrap_or(line))
+        .collect();
+    (!lines.is_empty()).then(|| lines.join("\n").trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize_round_trips() {
+        let src = r##"let s = r#"{"#; let c = '{'; fn f<'a>(x: &'a str) {} // }"##;
+        let joined: String = tokenize(src).iter().map(|t| t.text).collect();
+        assert_eq!(joined, src);
+    }
+
+    #[test]
+    fn test_tokenize_kinds() {
+        let kinds: Vec<_> = tokenize("println!(\"x\"); 'a'")
+            .into_iter()
+            .filter(|t| t.kind != TokenKind::Whitespace)
+            .map(|t| t.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Macro,
+                TokenKind::Punct,
+                TokenKind::String,
+                TokenKind::Punct,
+                TokenKind::Punct,
+                TokenKind::Char,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cell_spans_cover_attributes_and_body() {
+        let src = r#"use x;
+
+#[init]
+async fn setup() -> Result<()> {
+    Ok(())
+}
+
+fn helper() {}
+
+/// Docs.
+#[cell(name = "Load")]
+#[allow(unused)]
+pub async fn load() -> Result<()> {
+    if true { helper(); }
+    Ok(())
+}
+"#;
+        assert_eq!(
+            cell_spans(src),
+            vec![
+                CellSpan {
+                    name: "setup".to_string(),
+                    lines: 3..=6,
+                },
+                CellSpan {
+                    name: "load".to_string(),
+                    lines: 10..=16,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extract_item_includes_attributes_and_ignores_braces_in_literals() {
+        let src = r#"use x;
+
+/// Docs.
+#[cell]
+async fn first() -> Result<()> {
+    let s = "}";
+    if true { println!("{}", s); }
+    Ok(())
+}
+
+#[cell]
+async fn second() -> Result<()> {
+    Ok(())
+}
+"#;
+        let item = extract_item(src, 5).unwrap();
+        assert!(item.starts_with("/// Docs.\n#[cell]\nasync fn first()"));
+        assert!(item.ends_with("Ok(())\n}"));
+        assert!(!item.contains("second"));
+        assert_eq!(doc_comment(item).as_deref(), Some("Docs."));
+        assert_eq!(doc_comment(extract_item(src, 10).unwrap()), None);
+    }
+
+    #[test]
+    fn test_extract_item_out_of_range() {
+        assert_eq!(extract_item("fn a() {}", 5), None);
+        assert_eq!(extract_item("fn a() {}", 0), None);
+    }
+}
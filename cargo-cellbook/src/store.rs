@@ -1,38 +1,757 @@
 //! Context store for sharing data between cells.
 //!
 //! Values are stored as serialized bytes to survive hot-reloads.
+//! Large values can be compressed transparently, see [`set_compression`].
+//! Values stored with a lifetime expire, see [`set_expiry`].
+//! Changes of keys are recorded in the history, see [`history`].
+//! Large streamed values are kept on disk, see [`store_stream`], and so are large
+//! values with the mmap backend, see [`set_backend`].
 
-use std::collections::HashMap;
-use std::sync::LazyLock;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs::File;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{BufReader, BufWriter, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant, SystemTime};
 
+use memmap2::Mmap;
 use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
 
+use crate::errors::{Error, Result};
+use crate::history::{self, Change};
+use crate::loader;
+
+/// Where the bytes of a stored value are kept.
+#[derive(Clone)]
+enum Data {
+    Memory(Vec<u8>),
+    /// A streamed value above the spill threshold, or a large value with the mmap
+    /// backend. Copies of the store, such as the fork baseline, share the file.
+    Disk(Arc<DiskFile>),
+}
+
+/// A value written to a file and mapped into memory, deleted once no stored value,
+/// open reader or loaded mapping refers to it.
+struct DiskFile {
+    path: PathBuf,
+    len: u64,
+    /// Hash of the contents, computed while writing, for snapshots.
+    hash: u64,
+    /// `None` for empty files, which can't be mapped.
+    map: Option<Mmap>,
+}
+
+impl DiskFile {
+    /// Write `head` and then the rest of `reader` to a new file in `dir`.
+    fn write(dir: &Path, head: &[u8], reader: &mut dyn Read) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(FILE_COUNTER.fetch_add(1, Ordering::Relaxed).to_string());
+        // Wrapped first so a failed write doesn't leave the file behind.
+        let mut disk = Self {
+            path,
+            len: 0,
+            hash: 0,
+            map: None,
+        };
+        // Readable as well, for the map.
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&disk.path)?;
+        let mut file = BufWriter::new(file);
+        let mut hasher = DefaultHasher::new();
+        let mut chunk = head.to_vec();
+        while !chunk.is_empty() {
+            hasher.write(&chunk);
+            file.write_all(&chunk)?;
+            disk.len += chunk.len() as u64;
+            chunk.clear();
+            (&mut *reader).take(FILE_CHUNK_BYTES).read_to_end(&mut chunk)?;
+        }
+        let file = file.into_inner().map_err(|e| e.into_error())?;
+        disk.hash = hasher.finish();
+        if disk.len > 0 {
+            // SAFETY: The file is private to this process and not written again.
+            disk.map = Some(unsafe { Mmap::map(&file)? });
+        }
+        Ok(disk)
+    }
+}
+
+impl DiskFile {
+    fn bytes(&self) -> &[u8] {
+        self.map.as_deref().unwrap_or_default()
+    }
+}
+
+impl AsRef<[u8]> for DiskFile {
+    fn as_ref(&self) -> &[u8] {
+        self.bytes()
+    }
+}
+
+impl Drop for DiskFile {
+    fn drop(&mut self) {
+        // Unmapped first, since mapped files can't be deleted on Windows.
+        self.map.take();
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Reads a disk file, keeping it from being deleted until dropped.
+struct DiskReader {
+    file: BufReader<File>,
+    _disk: Arc<DiskFile>,
+}
+
+impl Read for DiskReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+#[derive(Clone)]
 struct StoredValue {
-    bytes: Vec<u8>,
+    /// Serialized value, encoded with `codec`.
+    data: Data,
     type_name: String,
+    codec: Codec,
+    /// Tick of the last store or load, for LRU eviction.
+    last_used: u64,
+    /// Tick when the value was stored, identifying it for shared loads.
+    version: u64,
+    /// Cell run that stored the value, if it was stored during one.
+    provenance: Option<Provenance>,
+    /// When the value stops being fresh, if it was stored with a lifetime.
+    expires: Option<Instant>,
+}
+
+/// The cell run that produced a stored value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    pub cell: String,
+    /// Run number of the cell, if runs of it are counted.
+    pub run: Option<u32>,
+    pub started: SystemTime,
+}
+
+impl Provenance {
+    /// Provenance for a run of `cell` starting now.
+    pub fn new(cell: &str, run: Option<u32>) -> Self {
+        Self {
+            cell: cell.to_string(),
+            run,
+            started: SystemTime::now(),
+        }
+    }
+}
+
+/// What to do when a store would exceed `max_store_bytes`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuotaPolicy {
+    /// Evict least recently used, unpinned keys until the value fits.
+    #[default]
+    Evict,
+    /// Reject the store with an error.
+    Error,
+}
+
+/// Where large stored values are kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreBackend {
+    /// Every value in memory.
+    #[default]
+    Memory,
+    /// Large values in files under `.cellbook/store/`, mapped into memory so loads
+    /// read the file's pages instead of a copy of the bytes.
+    Mmap,
+}
+
+/// What happens to values whose lifetime has passed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExpiryPolicy {
+    /// Remove expired values. Pinned keys are kept and marked stale instead.
+    #[default]
+    Remove,
+    /// Keep expired values and mark them stale in the store pane.
+    Mark,
+}
+
+/// Compression applied to stored values.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    #[default]
+    None,
+    Lz4,
+    Zstd,
+}
+
+impl Codec {
+    fn encode(self, bytes: &[u8]) -> Vec<u8> {
+        match self {
+            Codec::None => bytes.to_vec(),
+            Codec::Lz4 => lz4_flex::compress_prepend_size(bytes),
+            Codec::Zstd => zstd::encode_all(bytes, 0).expect("in-memory zstd encoding does not fail"),
+        }
+    }
+
+    fn decode(self, bytes: &[u8]) -> Vec<u8> {
+        match self {
+            Codec::None => bytes.to_vec(),
+            Codec::Lz4 => lz4_flex::decompress_size_prepended(bytes).expect("stored lz4 value is valid"),
+            Codec::Zstd => zstd::decode_all(bytes).expect("stored zstd value is valid"),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Compression {
+    codec: Codec,
+    /// Values smaller than this are stored uncompressed.
+    threshold: usize,
+}
+
+impl StoredValue {
+    /// Encode a value, compressing it if it is large enough and compression helps.
+    fn new(bytes: Vec<u8>, type_name: &str, compression: Compression) -> Self {
+        let (bytes, codec) = if compression.codec != Codec::None && bytes.len() >= compression.threshold {
+            let encoded = compression.codec.encode(&bytes);
+            if encoded.len() < bytes.len() {
+                (encoded, compression.codec)
+            } else {
+                (bytes, Codec::None)
+            }
+        } else {
+            (bytes, Codec::None)
+        };
+        Self::with_data(Data::Memory(bytes), type_name, codec)
+    }
+
+    fn with_data(data: Data, type_name: &str, codec: Codec) -> Self {
+        Self {
+            data,
+            type_name: type_name.to_string(),
+            codec,
+            last_used: tick(),
+            version: tick(),
+            provenance: producer(),
+            expires: None,
+        }
+    }
+
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    /// Size of the value as stored, after compression.
+    fn len(&self) -> u64 {
+        match &self.data {
+            Data::Memory(bytes) => bytes.len() as u64,
+            Data::Disk(disk) => disk.len,
+        }
+    }
+
+    /// Bytes the value takes in memory, which is what the quota limits.
+    fn resident_len(&self) -> u64 {
+        match &self.data {
+            Data::Memory(bytes) => bytes.len() as u64,
+            Data::Disk(_) => 0,
+        }
+    }
+
+    /// The serialized value as written by the cell.
+    fn decoded(&self) -> Vec<u8> {
+        match &self.data {
+            Data::Memory(bytes) => self.codec.decode(bytes),
+            Data::Disk(disk) => disk.bytes().to_vec(),
+        }
+    }
+
+    /// The serialized value, shared with the store for values on disk.
+    fn shared(&self) -> SharedBytes {
+        match &self.data {
+            Data::Memory(_) => Arc::new(self.decoded()),
+            Data::Disk(disk) => disk.clone(),
+        }
+    }
+
+    /// A reader over the serialized value, which doesn't load values on disk into memory.
+    fn reader(&self) -> std::io::Result<Box<dyn Read + Send>> {
+        match &self.data {
+            Data::Memory(_) => Ok(Box::new(Cursor::new(self.decoded()))),
+            Data::Disk(disk) => Ok(Box::new(DiskReader {
+                file: BufReader::new(File::open(&disk.path)?),
+                _disk: disk.clone(),
+            })),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Quota {
+    max_bytes: u64,
+    policy: QuotaPolicy,
+}
+
+/// A stored value written by [`export`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedValue {
+    pub key: String,
+    pub type_name: String,
+    pub bytes: Vec<u8>,
 }
 
 static STORE: LazyLock<Mutex<HashMap<String, StoredValue>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
 
-pub fn store_value(key: &str, bytes: Vec<u8>, type_name: &str) {
+/// Copy of the store taken by [`fork`]. While it exists, the live store is the fork.
+static BASELINE: LazyLock<Mutex<Option<HashMap<String, StoredValue>>>> = LazyLock::new(|| Mutex::new(None));
+
+/// Values removed by the last [`clear`] or [`clear_keys`], kept for [`undo_clear`]
+/// until the store next changes.
+static CLEARED: LazyLock<Mutex<Option<HashMap<String, StoredValue>>>> = LazyLock::new(|| Mutex::new(None));
+
+/// Keys that survive [`clear`].
+static PINNED: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+static QUOTA: Mutex<Option<Quota>> = Mutex::new(None);
+
+static EXPIRY_POLICY: Mutex<ExpiryPolicy> = Mutex::new(ExpiryPolicy::Remove);
+
+static COMPRESSION: Mutex<Compression> = Mutex::new(Compression {
+    codec: Codec::None,
+    threshold: 0,
+});
+
+/// Keys evicted to make room since the last [`take_evicted`].
+static EVICTED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Cell run that newly stored values are attributed to.
+static PRODUCER: Mutex<Option<Provenance>> = Mutex::new(None);
+
+tokio::task_local! {
+    /// Cell whose run the current task is polling.
+    static CELL: String;
+    /// Cell run the current task stores values for, see [`produced_by`].
+    static RUN: Provenance;
+}
+
+/// Store access of each cell's last [`tracked`] run.
+static ACCESS: LazyLock<Mutex<HashMap<String, Access>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Structure of versioned types by tagged type name (`Stats#v2`), registered by `storev!`.
+static SCHEMAS: LazyLock<Mutex<HashMap<String, String>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+static TICK: AtomicU64 = AtomicU64::new(0);
+
+/// Streamed values above this many bytes are written to disk, see [`store_stream`].
+static SPILL_THRESHOLD: AtomicU64 = AtomicU64::new(256 * 1024 * 1024);
+
+/// Values of at least this many bytes are written to disk and mapped, with the mmap backend.
+static MMAP_THRESHOLD: Mutex<Option<u64>> = Mutex::new(None);
+
+static FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Spill files of all runners, each in a directory named by its process id.
+pub const SPILL_DIR: &str = ".cellbook/spill";
+
+/// Files of the mmap backend, laid out like [`SPILL_DIR`].
+pub const MMAP_DIR: &str = ".cellbook/store";
+
+/// Bytes read from a streamed value at a time while writing it to disk.
+const FILE_CHUNK_BYTES: u64 = 1024 * 1024;
+
+/// Directory of this process under `base`, in the project root even while a cell runs
+/// elsewhere. Outside a runner, such as in tests, it is in the temporary directory.
+fn process_dir(base: &str) -> PathBuf {
+    loader::notebook_var("CELLBOOK_PROJECT_ROOT")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+        .join(base)
+        .join(std::process::id().to_string())
+}
+
+fn tick() -> u64 {
+    TICK.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Set the cell run that newly stored values are attributed to.
+pub fn set_producer(producer: Option<Provenance>) {
+    *PRODUCER.lock() = producer;
+}
+
+/// Run `future` with the values it stores attributed to `provenance`,
+/// taking precedence over [`set_producer`] for cells running at the same time.
+pub async fn produced_by<F: Future>(provenance: Provenance, future: F) -> F::Output {
+    RUN.scope(provenance, future).await
+}
+
+fn producer() -> Option<Provenance> {
+    RUN.try_with(Clone::clone)
+        .ok()
+        .or_else(|| PRODUCER.lock().clone())
+}
+
+/// Cell whose tracked run the current task belongs to.
+pub fn current_cell() -> Option<String> {
+    CELL.try_with(Clone::clone).ok()
+}
+
+/// Keys a cell run loaded and stored.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Access {
+    /// Loaded keys, including missing ones.
+    pub reads: BTreeSet<String>,
+    /// Stored and removed keys.
+    pub writes: BTreeSet<String>,
+}
+
+/// Run `future` as a run of `cell`, recording the keys it loads and stores.
+///
+/// Tasks it spawns aren't part of the run.
+pub async fn tracked<F: Future>(cell: &str, future: F) -> F::Output {
+    ACCESS.lock().remove(cell);
+    CELL.scope(cell.to_string(), future).await
+}
+
+/// Run `f` on a blocking thread, still recording store access for the current cell.
+pub async fn run_blocking<R: Send + 'static>(
+    f: impl FnOnce() -> R + Send + 'static,
+) -> std::result::Result<R, tokio::task::JoinError> {
+    let cell = CELL.try_with(Clone::clone).ok();
+    let run = RUN.try_with(Clone::clone).ok();
+    tokio::task::spawn_blocking(move || {
+        let f = move || match run {
+            Some(run) => RUN.sync_scope(run, f),
+            None => f(),
+        };
+        match cell {
+            Some(cell) => CELL.sync_scope(cell, f),
+            None => f(),
+        }
+    })
+    .await
+}
+
+/// Keys loaded and stored by the last tracked run of `cell`.
+pub fn take_access(cell: &str) -> Access {
+    ACCESS.lock().remove(cell).unwrap_or_default()
+}
+
+fn record_access(key: &str, write: bool) {
+    let _ = CELL.try_with(|cell| {
+        let mut access = ACCESS.lock();
+        let access = access.entry(cell.clone()).or_default();
+        if write {
+            access.writes.insert(key.to_string());
+        } else {
+            access.reads.insert(key.to_string());
+        }
+    });
+}
+
+/// Compress values of at least `threshold` serialized bytes with `codec`.
+/// Values already in the store keep their codec.
+pub fn set_compression(codec: Codec, threshold: usize) {
+    *COMPRESSION.lock() = Compression { codec, threshold };
+}
+
+/// Limit the total size of stored values. `None` removes the limit.
+pub fn set_quota(max_bytes: Option<u64>, policy: QuotaPolicy) {
+    *QUOTA.lock() = max_bytes.map(|max_bytes| Quota { max_bytes, policy });
+}
+
+/// Keep streamed values larger than `bytes` on disk instead of in memory.
+pub fn set_spill_threshold(bytes: u64) {
+    SPILL_THRESHOLD.store(bytes, Ordering::Relaxed);
+}
+
+/// Keep values of at least `threshold` serialized bytes in mapped files with
+/// [`StoreBackend::Mmap`]. Values already in the store stay where they are.
+pub fn set_backend(backend: StoreBackend, threshold: u64) {
+    *MMAP_THRESHOLD.lock() = (backend == StoreBackend::Mmap).then_some(threshold);
+}
+
+/// Delete this process's spill and mmap files, for when the runner exits with values still stored.
+pub fn remove_disk_files() {
+    for base in [SPILL_DIR, MMAP_DIR] {
+        let dir = process_dir(base);
+        let _ = std::fs::remove_dir_all(&dir);
+        // Left while other runners still use it.
+        if let Some(parent) = dir.parent() {
+            let _ = std::fs::remove_dir(parent);
+        }
+    }
+}
+
+pub fn set_expiry_policy(policy: ExpiryPolicy) {
+    *EXPIRY_POLICY.lock() = policy;
+}
+
+/// Give the value under `key` a lifetime of `ttl` from now.
+/// Storing the key again without a lifetime clears it.
+pub fn set_expiry(key: &str, ttl: Duration) {
+    if let Some(value) = STORE.lock().get_mut(key) {
+        value.expires = Some(Instant::now() + ttl);
+    }
+}
+
+/// Apply the expiry policy to values whose lifetime has passed.
+/// Returns the removed keys.
+pub fn expire() -> Vec<String> {
+    let policy = *EXPIRY_POLICY.lock();
+    let pinned = pinned();
+    let expired = expire_from(&mut STORE.lock(), &pinned, policy, Instant::now());
+    for key in &expired {
+        history::record(key, Change::Expire, None, None);
+    }
+    expired
+}
+
+fn expire_from(
+    store: &mut HashMap<String, StoredValue>,
+    pinned: &HashSet<String>,
+    policy: ExpiryPolicy,
+    now: Instant,
+) -> Vec<String> {
+    if policy == ExpiryPolicy::Mark {
+        return Vec::new();
+    }
+    let mut expired: Vec<String> = store
+        .iter()
+        .filter(|(k, v)| v.is_expired(now) && !pinned.contains(k.as_str()))
+        .map(|(k, _)| k.clone())
+        .collect();
+    expired.sort();
+    for k in &expired {
+        store.remove(k);
+    }
+    expired
+}
+
+/// Expiry time of each value stored with a lifetime.
+pub fn expiries() -> HashMap<String, Instant> {
+    let store = STORE.lock();
+    store
+        .iter()
+        .filter_map(|(k, v)| v.expires.map(|expires| (k.clone(), expires)))
+        .collect()
+}
+
+pub fn store_value(key: &str, bytes: Vec<u8>, type_name: &str) -> std::result::Result<(), String> {
+    // Copied out so the lock isn't held while the value is written to disk.
+    let mmap_threshold = *MMAP_THRESHOLD.lock();
+    let value = match mmap_threshold {
+        Some(threshold) if bytes.len() as u64 >= threshold => {
+            let disk = DiskFile::write(&process_dir(MMAP_DIR), &bytes, &mut std::io::empty())
+                .map_err(|e| format!("can't write to disk: {}", e))?;
+            StoredValue::with_data(Data::Disk(Arc::new(disk)), type_name, Codec::None)
+        }
+        _ => StoredValue::new(bytes, type_name, *COMPRESSION.lock()),
+    };
+    insert(key, value)
+}
+
+/// Store the bytes read from `reader` without collecting them in memory first if they
+/// exceed the spill threshold: the rest is streamed to a file, which spilled values are
+/// then read from. Returns the number of bytes stored.
+pub fn store_stream(key: &str, reader: &mut dyn Read, type_name: &str) -> std::result::Result<u64, String> {
+    let threshold = SPILL_THRESHOLD.load(Ordering::Relaxed);
+    let mut head = Vec::new();
+    (&mut *reader)
+        .take(threshold.saturating_add(1))
+        .read_to_end(&mut head)
+        .map_err(|e| e.to_string())?;
+
+    let value = if head.len() as u64 <= threshold {
+        StoredValue::new(head, type_name, *COMPRESSION.lock())
+    } else {
+        let disk = DiskFile::write(&process_dir(SPILL_DIR), &head, reader)
+            .map_err(|e| format!("can't spill to disk: {}", e))?;
+        StoredValue::with_data(Data::Disk(Arc::new(disk)), type_name, Codec::None)
+    };
+    let len = value.len();
+    insert(key, value)?;
+    Ok(len)
+}
+
+fn insert(key: &str, value: StoredValue) -> std::result::Result<(), String> {
+    record_access(key, true);
+    let quota = *QUOTA.lock();
+    CLEARED.lock().take();
+    // Copied before locking the store: `PINNED` is never taken while `STORE` is held.
+    let pinned = quota.map(|_| pinned()).unwrap_or_default();
     let mut store = STORE.lock();
-    store.insert(
-        key.to_string(),
-        StoredValue {
-            bytes,
-            type_name: type_name.to_string(),
-        },
-    );
+    let evicted = match quota {
+        Some(quota) => make_room(&mut store, &pinned, key, value.resident_len(), quota)?,
+        None => Vec::new(),
+    };
+    let (provenance, type_name, len) = (value.provenance.clone(), value.type_name.clone(), value.len());
+    store.insert(key.to_string(), value);
+    drop(store);
+
+    for evicted in &evicted {
+        history::record(evicted, Change::Evict, provenance.as_ref(), None);
+    }
+    history::record(key, Change::Store, provenance.as_ref(), Some((&type_name, len)));
+    EVICTED.lock().extend(evicted);
+    Ok(())
+}
+
+/// Check that a value of `len` bytes fits under `key`, evicting other keys if the
+/// policy allows. Returns the evicted keys.
+fn make_room(
+    store: &mut HashMap<String, StoredValue>,
+    pinned: &HashSet<String>,
+    key: &str,
+    len: u64,
+    quota: Quota,
+) -> std::result::Result<Vec<String>, String> {
+    let others = |store: &HashMap<String, StoredValue>| -> u64 {
+        store
+            .iter()
+            .filter(|(k, _)| k.as_str() != key)
+            .map(|(_, v)| v.resident_len())
+            .sum()
+    };
+    let exceeded = |total: u64| {
+        format!(
+            "store quota exceeded: {} needed, limit is {}",
+            format_bytes(total),
+            format_bytes(quota.max_bytes)
+        )
+    };
+
+    let mut total = others(store) + len;
+    if total <= quota.max_bytes {
+        return Ok(Vec::new());
+    }
+    if quota.policy == QuotaPolicy::Error {
+        return Err(exceeded(total));
+    }
+
+    let mut candidates: Vec<(u64, String, u64)> = store
+        .iter()
+        .filter(|(k, _)| k.as_str() != key && !pinned.contains(k.as_str()))
+        .map(|(k, v)| (v.last_used, k.clone(), v.resident_len()))
+        .collect();
+    candidates.sort();
+
+    let mut evicted = Vec::new();
+    for (_, candidate, size) in candidates {
+        if total <= quota.max_bytes {
+            break;
+        }
+        total -= size;
+        evicted.push(candidate);
+    }
+    if total > quota.max_bytes {
+        return Err(exceeded(total));
+    }
+    for k in &evicted {
+        store.remove(k);
+    }
+    Ok(evicted)
+}
+
+/// Keys evicted by the quota since the previous call.
+pub fn take_evicted() -> Vec<String> {
+    std::mem::take(&mut *EVICTED.lock())
 }
 
 pub fn load_value(key: &str) -> Option<(Vec<u8>, String)> {
+    // Expired values are gone for cells even before the next sweep.
+    expire();
+    record_access(key, false);
+    let mut store = STORE.lock();
+    store.get_mut(key).map(|v| {
+        v.last_used = tick();
+        (v.decoded(), v.type_name.clone())
+    })
+}
+
+/// The value under `key` without copying it out of the store if it is on disk,
+/// for loads that only read the bytes.
+pub fn load_bytes(key: &str) -> Option<(SharedBytes, String)> {
+    expire();
+    record_access(key, false);
+    let mut store = STORE.lock();
+    store.get_mut(key).map(|v| {
+        v.last_used = tick();
+        (v.shared(), v.type_name.clone())
+    })
+}
+
+/// Reader over the value under `key`, for values stored with [`store_stream`].
+pub fn load_stream(key: &str) -> Option<(Box<dyn Read + Send>, String)> {
+    expire();
+    record_access(key, false);
+    let mut store = STORE.lock();
+    let value = store.get_mut(key)?;
+    value.last_used = tick();
+    let reader = value.reader().ok()?;
+    Some((reader, value.type_name.clone()))
+}
+
+/// The first `limit` bytes of the value under `key` with its type name and full length,
+/// without counting as a load. Compressed values are decompressed after releasing the store.
+pub fn peek_value(key: &str, limit: usize) -> Option<(Vec<u8>, String, u64)> {
+    let prefix = |bytes: &[u8]| bytes[..bytes.len().min(limit)].to_vec();
+    let (compressed, codec, type_name) = {
+        let store = STORE.lock();
+        let value = store.get(key)?;
+        let type_name = value.type_name.clone();
+        match &value.data {
+            Data::Memory(bytes) if value.codec != Codec::None => (bytes.clone(), value.codec, type_name),
+            Data::Memory(bytes) => return Some((prefix(bytes), type_name, bytes.len() as u64)),
+            Data::Disk(disk) => return Some((prefix(disk.bytes()), type_name, disk.len)),
+        }
+    };
+    let mut bytes = codec.decode(&compressed);
+    let len = bytes.len() as u64;
+    bytes.truncate(limit);
+    Some((bytes, type_name, len))
+}
+
+/// Whether a value was stored with `codec = json`, which cellbook records after its type name.
+pub fn is_json(type_name: &str) -> bool {
+    type_name
+        .rsplit_once('@')
+        .is_some_and(|(_, codec)| codec == "json")
+}
+
+/// Version of the value under `key`, which changes whenever the key is stored.
+/// Counts as a load of the key.
+pub fn load_version(key: &str) -> Option<u64> {
+    expire();
+    record_access(key, false);
+    let mut store = STORE.lock();
+    store.get_mut(key).map(|v| {
+        v.last_used = tick();
+        v.version
+    })
+}
+
+/// Version of each stored value.
+pub fn versions() -> HashMap<String, u64> {
     let store = STORE.lock();
-    store.get(key).map(|v| (v.bytes.clone(), v.type_name.clone()))
+    store.iter().map(|(k, v)| (k.clone(), v.version)).collect()
 }
 
 pub fn remove_value(key: &str) -> Option<(Vec<u8>, String)> {
-    let mut store = STORE.lock();
-    store.remove(key).map(|v| (v.bytes, v.type_name))
+    record_access(key, true);
+    CLEARED.lock().take();
+    let removed = STORE.lock().remove(key)?;
+    history::record(key, Change::Remove, producer().as_ref(), None);
+    Some((removed.decoded(), removed.type_name))
 }
 
 pub fn list() -> Vec<(String, String)> {
@@ -43,32 +762,324 @@ pub fn list() -> Vec<(String, String)> {
         .collect()
 }
 
-pub fn clear() {
+/// The cell run that stored the value under `key`.
+pub fn provenance(key: &str) -> Option<Provenance> {
+    STORE.lock().get(key).and_then(|v| v.provenance.clone())
+}
+
+/// Remember the structure of a versioned type for the store inspector.
+/// A later registration of the same type and version replaces it.
+pub fn register_schema(type_name: &str, schema: &str) {
+    SCHEMAS.lock().insert(type_name.to_string(), schema.to_string());
+}
+
+/// Registered structure of a stored type, if its cells used `storev!` with a derived schema.
+pub fn schema(type_name: &str) -> Option<String> {
+    SCHEMAS.lock().get(type_name).cloned()
+}
+
+/// Size of each stored value in bytes, after compression.
+pub fn sizes() -> HashMap<String, u64> {
+    let store = STORE.lock();
+    store.iter().map(|(k, v)| (k.clone(), v.len())).collect()
+}
+
+/// Human-readable byte count, e.g. `1.5 MB`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Remove every value except pinned ones. Returns the number removed.
+/// The removed values can be restored with [`undo_clear`].
+pub fn clear() -> usize {
+    let pinned = pinned();
+    let mut store = STORE.lock();
+    let removed: HashMap<_, _> = store.extract_if(|k, _| !pinned.contains(k)).collect();
+    let keys = keep_cleared(removed);
+    drop(store);
+    record_cleared(&keys)
+}
+
+/// Remove the given keys, pinned or not. Returns the number removed.
+/// The removed values can be restored with [`undo_clear`].
+pub fn clear_keys(keys: &[String]) -> usize {
+    let mut store = STORE.lock();
+    let removed: HashMap<_, _> = keys.iter().filter_map(|key| store.remove_entry(key)).collect();
+    let keys = keep_cleared(removed);
+    drop(store);
+    record_cleared(&keys)
+}
+
+/// Keep removed values for [`undo_clear`], while the store is still locked so
+/// nothing stored meanwhile is overwritten by an undo. Returns their keys, sorted.
+fn keep_cleared(removed: HashMap<String, StoredValue>) -> Vec<String> {
+    let mut keys: Vec<String> = removed.keys().cloned().collect();
+    keys.sort();
+    *CLEARED.lock() = (!removed.is_empty()).then_some(removed);
+    keys
+}
+
+/// Record cleared keys in the history, after unlocking the store since that
+/// writes to disk. Returns the number of keys.
+fn record_cleared(keys: &[String]) -> usize {
+    let producer = producer();
+    for key in keys {
+        history::record(key, Change::Remove, producer.as_ref(), None);
+    }
+    keys.len()
+}
+
+/// Put back the values removed by the last clear, if nothing was stored or
+/// removed since. Returns the number restored.
+pub fn undo_clear() -> usize {
+    let Some(cleared) = CLEARED.lock().take() else {
+        return 0;
+    };
+    let count = cleared.len();
+    let producer = producer();
+    for (key, value) in &cleared {
+        history::record(
+            key,
+            Change::Store,
+            producer.as_ref(),
+            Some((&value.type_name, value.len())),
+        );
+    }
+    STORE.lock().extend(cleared);
+    count
+}
+
+/// Move a value to a new key, keeping its pin. Fails if the new key is taken.
+pub fn rename(key: &str, new_key: &str) -> std::result::Result<(), String> {
     let mut store = STORE.lock();
-    store.clear();
+    if store.contains_key(new_key) {
+        return Err(format!("'{}' already exists", new_key));
+    }
+    let value = store.remove(key).ok_or_else(|| format!("'{}' not found", key))?;
+    let (type_name, len) = (value.type_name.clone(), value.len());
+    store.insert(new_key.to_string(), value);
+    drop(store);
+    let mut pinned = PINNED.lock();
+    if pinned.remove(key) {
+        pinned.insert(new_key.to_string());
+    }
+    drop(pinned);
+    CLEARED.lock().take();
+
+    let producer = producer();
+    history::record(key, Change::Remove, producer.as_ref(), None);
+    history::record(new_key, Change::Store, producer.as_ref(), Some((&type_name, len)));
+    Ok(())
+}
+
+/// Toggle whether a key is pinned.
+/// Returns `true` if the key is now pinned.
+pub fn toggle_pin(key: &str) -> bool {
+    let mut pinned = PINNED.lock();
+    if pinned.remove(key) {
+        false
+    } else {
+        pinned.insert(key.to_string());
+        true
+    }
+}
+
+pub fn pinned() -> HashSet<String> {
+    PINNED.lock().clone()
 }
 
-pub type StoreFn = fn(&str, Vec<u8>, &str);
-pub type LoadFn = fn(&str) -> Option<(Vec<u8>, String)>;
-pub type RemoveFn = fn(&str) -> Option<(Vec<u8>, String)>;
-pub type ListFn = fn() -> Vec<(String, String)>;
+/// Write the given keys to a postcard-encoded file of [`ExportedValue`]s.
+/// Missing keys are skipped. Returns the number of values written.
+pub fn export(keys: &[String], path: &Path) -> Result<usize> {
+    let values: Vec<ExportedValue> = {
+        let store = STORE.lock();
+        keys.iter()
+            .filter_map(|key| {
+                store.get(key).map(|v| ExportedValue {
+                    key: key.clone(),
+                    type_name: v.type_name.clone(),
+                    bytes: v.decoded(),
+                })
+            })
+            .collect()
+    };
 
-pub fn get_store_fn() -> StoreFn {
-    store_value
+    let bytes = postcard::to_stdvec(&values).map_err(|e| Error::Store(e.to_string()))?;
+    std::fs::write(path, bytes)?;
+    Ok(values.len())
 }
 
-pub fn get_load_fn() -> LoadFn {
-    load_value
+/// Fork the session: keep a copy of the current store as the baseline so
+/// cells can run against identical inputs and be compared afterwards.
+/// Returns `false` if a fork already exists.
+pub fn fork() -> bool {
+    CLEARED.lock().take();
+    let mut baseline = BASELINE.lock();
+    if baseline.is_some() {
+        return false;
+    }
+    *baseline = Some(STORE.lock().clone());
+    true
 }
 
-pub fn get_remove_fn() -> RemoveFn {
-    remove_value
+pub fn is_forked() -> bool {
+    BASELINE.lock().is_some()
 }
 
-pub fn get_list_fn() -> ListFn {
-    list
+/// Changes made in the fork relative to the baseline.
+pub fn fork_diff() -> Option<StoreDiff> {
+    let baseline = snapshot_of(BASELINE.lock().as_ref()?);
+    Some(StoreDiff::between(&baseline, &snapshot()))
 }
 
+/// Drop the fork and restore the baseline store.
+/// Returns `false` if there was no fork.
+pub fn discard_fork() -> bool {
+    let Some(baseline) = BASELINE.lock().take() else {
+        return false;
+    };
+    CLEARED.lock().take();
+    // Recorded once the store is unlocked since that writes to disk.
+    let changes = restore_baseline(&mut STORE.lock(), baseline);
+
+    let producer = producer();
+    for (key, restored) in &changes {
+        match restored {
+            Some((type_name, len)) => {
+                history::record(key, Change::Store, producer.as_ref(), Some((type_name, *len)))
+            }
+            None => history::record(key, Change::Remove, producer.as_ref(), None),
+        }
+    }
+    true
+}
+
+/// Replace `store` with `baseline`. Returns the keys this removes, and those it
+/// restores with their type and size, sorted.
+fn restore_baseline(
+    store: &mut HashMap<String, StoredValue>,
+    baseline: HashMap<String, StoredValue>,
+) -> Vec<(String, Option<(String, u64)>)> {
+    let mut keys: Vec<&String> = store.keys().chain(baseline.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    let mut changes = Vec::new();
+    for key in keys {
+        match (store.get(key), baseline.get(key)) {
+            (Some(_), None) => changes.push((key.clone(), None)),
+            (current, Some(restored)) if current.is_none_or(|v| v.version != restored.version) => {
+                changes.push((key.clone(), Some((restored.type_name.clone(), restored.len()))));
+            }
+            _ => {}
+        }
+    }
+    *store = baseline;
+    changes
+}
+
+/// Keep the fork's values and drop the baseline.
+/// Returns `false` if there was no fork.
+pub fn promote_fork() -> bool {
+    BASELINE.lock().take().is_some()
+}
+
+/// Type name and value hash of a key at a point in time.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SnapshotEntry {
+    pub type_name: String,
+    pub hash: u64,
+}
+
+pub type Snapshot = HashMap<String, SnapshotEntry>;
+
+/// Capture the current keys, types and value hashes without copying values.
+pub fn snapshot() -> Snapshot {
+    snapshot_of(&STORE.lock())
+}
+
+fn snapshot_of(store: &HashMap<String, StoredValue>) -> Snapshot {
+    store
+        .iter()
+        .map(|(k, v)| {
+            let hash = match &v.data {
+                Data::Memory(bytes) => {
+                    let mut hasher = DefaultHasher::new();
+                    bytes.hash(&mut hasher);
+                    hasher.finish()
+                }
+                Data::Disk(disk) => disk.hash,
+            };
+            let entry = SnapshotEntry {
+                type_name: v.type_name.clone(),
+                hash,
+            };
+            (k.clone(), entry)
+        })
+        .collect()
+}
+
+/// Keys added, changed or removed between two snapshots, each sorted by key.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StoreDiff {
+    /// Added keys with their type.
+    pub added: Vec<(String, String)>,
+    /// Changed keys with their previous and current type.
+    pub changed: Vec<(String, String, String)>,
+    /// Removed keys with their previous type.
+    pub removed: Vec<(String, String)>,
+}
+
+impl StoreDiff {
+    pub fn between(before: &Snapshot, after: &Snapshot) -> Self {
+        let mut diff = StoreDiff::default();
+        for (key, entry) in after {
+            match before.get(key) {
+                None => diff.added.push((key.clone(), entry.type_name.clone())),
+                Some(old) if old != entry => {
+                    diff.changed
+                        .push((key.clone(), old.type_name.clone(), entry.type_name.clone()));
+                }
+                Some(_) => {}
+            }
+        }
+        for (key, entry) in before {
+            if !after.contains_key(key) {
+                diff.removed.push((key.clone(), entry.type_name.clone()));
+            }
+        }
+        diff.added.sort();
+        diff.changed.sort();
+        diff.removed.sort();
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+
+    /// Every added, changed or removed key.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.added
+            .iter()
+            .map(|(key, _)| key)
+            .chain(self.changed.iter().map(|(key, _, _)| key))
+            .chain(self.removed.iter().map(|(key, _)| key))
+            .map(String::as_str)
+    }
+}
+
+pub type SharedBytes = Arc<dyn AsRef<[u8]> + Send + Sync>;
+
 #[cfg(test)]
 mod tests {
     use std::sync::atomic::{AtomicU64, Ordering};
@@ -85,7 +1096,7 @@ mod tests {
     #[test]
     fn test_store_and_load() {
         let key = unique_key("bytes");
-        store_value(&key, vec![1, 2, 3, 4], "test");
+        store_value(&key, vec![1, 2, 3, 4], "test").unwrap();
         let loaded = load_value(&key).unwrap();
         assert_eq!(loaded.0, vec![1, 2, 3, 4]);
         assert_eq!(loaded.1, "test");
@@ -94,15 +1105,398 @@ mod tests {
     #[test]
     fn test_remove() {
         let key = unique_key("remove");
-        store_value(&key, vec![5, 6], "test");
+        store_value(&key, vec![5, 6], "test").unwrap();
         let removed = remove_value(&key).unwrap();
         assert_eq!(removed.0, vec![5, 6]);
         assert!(load_value(&key).is_none());
     }
 
+    #[test]
+    fn test_provenance_records_producer() {
+        let key = unique_key("provenance");
+        set_producer(Some(Provenance::new("load", Some(3))));
+        store_value(&key, vec![1], "test").unwrap();
+        set_producer(None);
+
+        let provenance = provenance(&key).unwrap();
+        assert_eq!(provenance.cell, "load");
+        assert_eq!(provenance.run, Some(3));
+    }
+
+    #[test]
+    fn test_rename_moves_value_and_pin() {
+        let key = unique_key("rename");
+        let new_key = unique_key("renamed");
+        store_value(&key, vec![1], "test").unwrap();
+        toggle_pin(&key);
+
+        rename(&key, &new_key).unwrap();
+        assert!(load_value(&key).is_none());
+        assert_eq!(load_value(&new_key).unwrap().0, vec![1]);
+        assert!(pinned().contains(&new_key));
+        toggle_pin(&new_key);
+
+        store_value(&key, vec![2], "test").unwrap();
+        assert!(rename(&key, &new_key).is_err());
+    }
+
+    #[test]
+    fn test_toggle_pin() {
+        let key = unique_key("pin");
+        assert!(toggle_pin(&key));
+        assert!(pinned().contains(&key));
+        assert!(!toggle_pin(&key));
+        assert!(!pinned().contains(&key));
+    }
+
+    #[test]
+    fn test_export_skips_missing_keys() {
+        let key = unique_key("export");
+        store_value(&key, vec![7, 8], "test").unwrap();
+        let path = std::env::temp_dir().join(format!("cellbook_{}.postcard", key));
+
+        let written = export(&[key.clone(), unique_key("missing")], &path).unwrap();
+        let exported: Vec<ExportedValue> = postcard::from_bytes(&std::fs::read(&path).unwrap()).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(written, 1);
+        assert_eq!(exported[0].key, key);
+        assert_eq!(exported[0].type_name, "test");
+        assert_eq!(exported[0].bytes, vec![7, 8]);
+    }
+
+    #[tokio::test]
+    async fn test_tracks_access_of_run() {
+        let cell = unique_key("cell");
+        let (read, missing, written) = (unique_key("read"), unique_key("missing"), unique_key("written"));
+        store_value(&read, vec![1], "u8").unwrap();
+
+        tracked(&cell, async {
+            load_value(&read);
+            load_value(&missing);
+            store_value(&written, vec![2], "u8").unwrap();
+        })
+        .await;
+        load_value(&written);
+
+        let access = take_access(&cell);
+        assert_eq!(access.reads, BTreeSet::from([read, missing]));
+        assert_eq!(access.writes, BTreeSet::from([written]));
+        assert_eq!(take_access(&cell), Access::default());
+    }
+
     #[test]
     fn test_not_found() {
         let key = unique_key("nonexistent");
         assert!(load_value(&key).is_none());
     }
+
+    #[test]
+    fn test_diff_between_snapshots() {
+        let entry = |type_name: &str, hash| SnapshotEntry {
+            type_name: type_name.to_string(),
+            hash,
+        };
+        let before = Snapshot::from([
+            ("kept".to_string(), entry("i32", 1)),
+            ("changed".to_string(), entry("i32", 2)),
+            ("retyped".to_string(), entry("i32", 3)),
+            ("removed".to_string(), entry("String", 4)),
+        ]);
+        let after = Snapshot::from([
+            ("kept".to_string(), entry("i32", 1)),
+            ("changed".to_string(), entry("i32", 5)),
+            ("retyped".to_string(), entry("i64", 3)),
+            ("added".to_string(), entry("f64", 6)),
+        ]);
+
+        let diff = StoreDiff::between(&before, &after);
+        assert_eq!(diff.added, vec![("added".to_string(), "f64".to_string())]);
+        assert_eq!(
+            diff.changed,
+            vec![
+                ("changed".to_string(), "i32".to_string(), "i32".to_string()),
+                ("retyped".to_string(), "i32".to_string(), "i64".to_string()),
+            ]
+        );
+        assert_eq!(diff.removed, vec![("removed".to_string(), "String".to_string())]);
+        assert!(StoreDiff::between(&after, &after).is_empty());
+    }
+
+    #[test]
+    fn test_snapshot_tracks_value_changes() {
+        let key = unique_key("snapshot");
+        store_value(&key, vec![1], "test").unwrap();
+        let before = snapshot();
+        store_value(&key, vec![2], "test").unwrap();
+        let after = snapshot();
+
+        assert_ne!(before[&key].hash, after[&key].hash);
+        assert_eq!(after[&key].type_name, "test");
+    }
+
+    fn value(bytes: usize, last_used: u64) -> StoredValue {
+        StoredValue {
+            data: Data::Memory(vec![0; bytes]),
+            type_name: "test".to_string(),
+            codec: Codec::None,
+            last_used,
+            version: last_used,
+            provenance: None,
+            expires: None,
+        }
+    }
+
+    #[test]
+    fn test_expiry_removes_unpinned_values() {
+        let now = Instant::now();
+        let (expired, fresh, pinned) = (unique_key("expired"), unique_key("fresh"), unique_key("pinned"));
+        let mut store = HashMap::from([
+            (expired.clone(), value(1, 0)),
+            (fresh.clone(), value(1, 0)),
+            (pinned.clone(), value(1, 0)),
+            (unique_key("forever"), value(1, 0)),
+        ]);
+        store.get_mut(&expired).unwrap().expires = Some(now);
+        store.get_mut(&fresh).unwrap().expires = Some(now + Duration::from_secs(60));
+        store.get_mut(&pinned).unwrap().expires = Some(now);
+        let pinned_keys = HashSet::from([pinned.clone()]);
+
+        assert!(expire_from(&mut store, &pinned_keys, ExpiryPolicy::Mark, now).is_empty());
+        assert_eq!(store.len(), 4);
+
+        let removed = expire_from(&mut store, &pinned_keys, ExpiryPolicy::Remove, now);
+        assert_eq!(removed, vec![expired]);
+        assert!(store.contains_key(&fresh) && store.contains_key(&pinned));
+    }
+
+    #[test]
+    fn test_store_again_clears_expiry() {
+        let key = unique_key("ttl");
+        store_value(&key, vec![1], "test").unwrap();
+        set_expiry(&key, Duration::from_secs(60));
+        assert!(expiries().contains_key(&key));
+
+        store_value(&key, vec![2], "test").unwrap();
+        assert!(!expiries().contains_key(&key));
+    }
+
+    #[test]
+    fn test_quota_evicts_least_recently_used() {
+        let (old, recent, pinned) = (unique_key("old"), unique_key("recent"), unique_key("pinned"));
+        let pinned_keys = HashSet::from([pinned.clone()]);
+        let mut store = HashMap::from([
+            (pinned.clone(), value(10, 0)),
+            (old.clone(), value(10, 1)),
+            (recent.clone(), value(10, 2)),
+        ]);
+        let quota = Quota {
+            max_bytes: 35,
+            policy: QuotaPolicy::Evict,
+        };
+
+        let evicted = make_room(&mut store, &pinned_keys, "new", 10, quota).unwrap();
+        assert_eq!(evicted, vec![old.clone()]);
+        assert!(store.contains_key(&pinned) && store.contains_key(&recent));
+
+        // Replacing a key only counts its new size.
+        assert!(
+            make_room(&mut store, &pinned_keys, &recent, 15, quota)
+                .unwrap()
+                .is_empty()
+        );
+        // Pinned keys are never evicted.
+        assert!(make_room(&mut store, &pinned_keys, "huge", 30, quota).is_err());
+    }
+
+    #[test]
+    fn test_discarded_fork_restores_the_baseline() {
+        let (kept, changed, removed) = (unique_key("kept"), unique_key("changed"), unique_key("removed"));
+        let mut store = HashMap::from([
+            (kept.clone(), value(1, 0)),
+            (changed.clone(), value(1, 1)),
+            (removed.clone(), value(2, 2)),
+        ]);
+        let baseline = store.clone();
+        let added = unique_key("added");
+        store.insert(changed.clone(), value(3, 3));
+        store.insert(added.clone(), value(1, 4));
+        store.remove(&removed);
+
+        let diff = StoreDiff::between(&snapshot_of(&baseline), &snapshot_of(&store));
+        assert_eq!(diff.added, vec![(added.clone(), "test".to_string())]);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.removed, vec![(removed.clone(), "test".to_string())]);
+
+        let mut changes = restore_baseline(&mut store, baseline.clone());
+        changes.sort();
+        let mut expected = vec![
+            (added, None),
+            (changed, Some(("test".to_string(), 1))),
+            (removed, Some(("test".to_string(), 2))),
+        ];
+        expected.sort();
+        assert_eq!(changes, expected);
+        assert_eq!(snapshot_of(&store), snapshot_of(&baseline));
+        assert!(restore_baseline(&mut store, baseline).is_empty());
+    }
+
+    #[test]
+    fn test_fork_keeps_pinned_keys_and_discard_restores_them() {
+        let (pinned, old) = (unique_key("pinned"), unique_key("old"));
+        let pinned_keys = HashSet::from([pinned.clone()]);
+        let mut store = HashMap::from([(pinned.clone(), value(10, 0)), (old.clone(), value(10, 1))]);
+        let baseline = store.clone();
+        let quota = Quota {
+            max_bytes: 25,
+            policy: QuotaPolicy::Evict,
+        };
+
+        // In the fork, the quota still evicts around pinned keys.
+        assert_eq!(
+            make_room(&mut store, &pinned_keys, "new", 10, quota).unwrap(),
+            vec![old.clone()]
+        );
+        // A pinned key changed in the fork goes back to its baseline value.
+        store.insert(pinned.clone(), value(5, 2));
+
+        restore_baseline(&mut store, baseline);
+        assert_eq!(store[&pinned].len(), 10);
+        assert!(store.contains_key(&old));
+    }
+
+    #[test]
+    fn test_no_fork_to_discard_or_promote() {
+        // The other tests never fork the shared store, which would restore over their values.
+        assert!(!discard_fork());
+        assert!(!promote_fork());
+        assert!(fork_diff().is_none());
+    }
+
+    #[test]
+    fn test_quota_error_policy_rejects() {
+        let key = unique_key("kept");
+        let mut store = HashMap::from([(key.clone(), value(10, 0))]);
+        let quota = Quota {
+            max_bytes: 15,
+            policy: QuotaPolicy::Error,
+        };
+
+        let err = make_room(&mut store, &HashSet::new(), "new", 10, quota).unwrap_err();
+        assert_eq!(err, "store quota exceeded: 20 B needed, limit is 15 B");
+        assert!(store.contains_key(&key));
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KB");
+        assert_eq!(format_bytes(3 * 1024 * 1024 * 1024), "3.0 GB");
+    }
+
+    #[test]
+    fn test_large_streams_spill_to_disk() {
+        /// Puts the spill threshold back for the other tests, also if this one fails.
+        struct Threshold(u64);
+        impl Drop for Threshold {
+            fn drop(&mut self) {
+                set_spill_threshold(self.0);
+            }
+        }
+        let _threshold = Threshold(SPILL_THRESHOLD.load(Ordering::Relaxed));
+        set_spill_threshold(16);
+        let (small, large) = (unique_key("small"), unique_key("large"));
+        let bytes: Vec<u8> = (0..100).collect();
+        assert_eq!(store_stream(&small, &mut &bytes[..8], "stream"), Ok(8));
+        assert_eq!(store_stream(&large, &mut &bytes[..], "stream"), Ok(100));
+        {
+            let store = STORE.lock();
+            assert!(matches!(store[&small].data, Data::Memory(_)));
+            assert!(matches!(store[&large].data, Data::Disk(_)));
+        }
+
+        let (mut reader, type_name) = load_stream(&large).unwrap();
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+        assert_eq!((read, type_name.as_str()), (bytes.clone(), "stream"));
+        let (peeked, _, len) = peek_value(&large, 10).unwrap();
+        assert_eq!((peeked.as_slice(), len), (&bytes[..10], 100));
+        assert_eq!(sizes()[&large], 100);
+        remove_value(&small);
+        remove_value(&large);
+    }
+
+    #[test]
+    fn test_spill_file_outlives_value_while_read() {
+        let dir = process_dir(SPILL_DIR);
+        let spill = Arc::new(DiskFile::write(&dir, b"head", &mut &b" and tail"[..]).unwrap());
+        let path = spill.path.clone();
+        let value = StoredValue::with_data(Data::Disk(spill), "stream", Codec::None);
+        let mut reader = value.reader().unwrap();
+        drop(value);
+
+        let mut read = String::new();
+        reader.read_to_string(&mut read).unwrap();
+        assert_eq!(read, "head and tail");
+        assert!(path.exists());
+        drop(reader);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_mapped_loads_share_the_file() {
+        let key = unique_key("mapped");
+        let bytes: Vec<u8> = (0..=255).collect();
+        let disk = DiskFile::write(&process_dir(MMAP_DIR), &bytes, &mut std::io::empty()).unwrap();
+        let path = disk.path.clone();
+        insert(
+            &key,
+            StoredValue::with_data(Data::Disk(Arc::new(disk)), "Vec<u8>", Codec::None),
+        )
+        .unwrap();
+        assert_eq!(load_value(&key).unwrap().0, bytes);
+
+        let (shared, type_name) = load_bytes(&key).unwrap();
+        assert_eq!(((*shared).as_ref(), type_name.as_str()), (&bytes[..], "Vec<u8>"));
+        remove_value(&key);
+        assert!(path.exists());
+        drop(shared);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_compression_round_trips() {
+        let bytes: Vec<u8> = (0..4096u32).flat_map(|i| (i % 7).to_le_bytes()).collect();
+        for codec in [Codec::Lz4, Codec::Zstd] {
+            let compression = Compression {
+                codec,
+                threshold: 1024,
+            };
+            let value = StoredValue::new(bytes.clone(), "Vec<u32>", compression);
+            assert_eq!(value.codec, codec);
+            assert!(value.len() < bytes.len() as u64);
+            assert_eq!(value.decoded(), bytes);
+
+            let small = StoredValue::new(vec![1, 2, 3], "Vec<u8>", compression);
+            assert_eq!(small.codec, Codec::None);
+            assert_eq!(small.decoded(), vec![1, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn test_peek_cuts_values_in_memory_short() {
+        let bytes: Vec<u8> = (0..4096u32).flat_map(|i| (i % 7).to_le_bytes()).collect();
+        let (plain, compressed) = (unique_key("plain"), unique_key("compressed"));
+        for (key, codec) in [(&plain, Codec::None), (&compressed, Codec::Zstd)] {
+            let compression = Compression { codec, threshold: 1024 };
+            insert(key, StoredValue::new(bytes.clone(), "Vec<u32>", compression)).unwrap();
+        }
+        assert_eq!(STORE.lock()[&compressed].codec, Codec::Zstd);
+
+        for key in [&plain, &compressed] {
+            let (peeked, type_name, len) = peek_value(key, 10).unwrap();
+            assert_eq!((peeked.as_slice(), type_name.as_str(), len), (&bytes[..10], "Vec<u32>", 16384));
+            remove_value(key);
+        }
+    }
 }
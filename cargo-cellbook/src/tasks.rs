@@ -0,0 +1,248 @@
+//! Background tasks spawned by cells.
+//!
+//! Tasks run on the host runtime but execute code from the loaded library,
+//! so they must be aborted before the library is reloaded.
+
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+use parking_lot::Mutex;
+use tokio::task::JoinHandle;
+
+use crate::errors::Result;
+use crate::isolation;
+use crate::loader::{CellInfo, LoadedLibrary};
+use crate::runs::{self, ServiceEvent};
+
+/// Execution state of a background task.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TaskState {
+    Running,
+    Finished,
+    Failed(String),
+    Cancelled,
+}
+
+/// Snapshot of a background task for display.
+#[derive(Clone, Debug)]
+pub struct TaskInfo {
+    pub id: u64,
+    pub name: String,
+    pub elapsed: Duration,
+    pub state: TaskState,
+}
+
+struct Task {
+    id: u64,
+    name: String,
+    started: Instant,
+    finished: Option<Instant>,
+    state: TaskState,
+    handle: Option<JoinHandle<()>>,
+}
+
+static TASKS: LazyLock<Mutex<Vec<Task>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Spawn a background task on the current runtime and start tracking it.
+pub fn spawn(name: &str, future: BoxFuture<'static, std::result::Result<(), String>>) {
+    track(name, future);
+}
+
+/// Start a `#[cell(service)]` as a background task, in a worker process if `isolate`.
+/// Returns the task id.
+///
+/// Unlike other cells a service's output isn't captured, since it never finishes:
+/// what it prints goes to the runner's stdout, as for other background tasks.
+/// Its start and end are recorded in the session's run log.
+pub fn start_service(lib: &LoadedLibrary, cell: &CellInfo, isolate: bool) -> Result<u64> {
+    let future: BoxFuture<'static, std::result::Result<(), String>> = if isolate {
+        let (lib_path, name) = (lib.lib_path().to_path_buf(), cell.name.clone());
+        Box::pin(async move {
+            let (stdout, result) = isolation::run(lib_path, name).await;
+            print!("{}", stdout);
+            result.map_err(|e| e.message)
+        })
+    } else {
+        let future = lib.cell_future(cell)?;
+        Box::pin(async move { future.await.map_err(|e| e.to_string()) })
+    };
+    runs::record_service(&cell.name, ServiceEvent::Started, Duration::ZERO, None);
+    let run = ServiceRun {
+        name: cell.name.clone(),
+        started: Instant::now(),
+        error: None,
+    };
+    let future = Box::pin(async move {
+        // Moved whole, so it's dropped with the task.
+        let mut run = run;
+        let result = future.await;
+        run.error = result.as_ref().err().cloned();
+        result
+    });
+    Ok(track(&cell.name, future))
+}
+
+/// Records the end of a service when dropped, which is also how a stopped
+/// service's task ends.
+struct ServiceRun {
+    name: String,
+    started: Instant,
+    error: Option<String>,
+}
+
+impl Drop for ServiceRun {
+    fn drop(&mut self) {
+        let duration = self.started.elapsed();
+        runs::record_service(&self.name, ServiceEvent::Ended, duration, self.error.as_deref());
+    }
+}
+
+fn track(name: &str, future: BoxFuture<'static, std::result::Result<(), String>>) -> u64 {
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    let mut task = Task {
+        id,
+        name: name.to_string(),
+        started: Instant::now(),
+        finished: None,
+        state: TaskState::Running,
+        handle: None,
+    };
+
+    match tokio::runtime::Handle::try_current() {
+        Ok(runtime) => {
+            // Hold the lock while spawning so the task is tracked before it can finish.
+            let mut tasks = TASKS.lock();
+            task.handle = Some(runtime.spawn(async move {
+                let result = future.await;
+                finish(id, result);
+            }));
+            tasks.push(task);
+        }
+        Err(e) => {
+            task.finished = Some(task.started);
+            task.state = TaskState::Failed(format!("no async runtime: {}", e));
+            TASKS.lock().push(task);
+        }
+    }
+    id
+}
+
+fn finish(id: u64, result: std::result::Result<(), String>) {
+    let mut tasks = TASKS.lock();
+    if let Some(task) = tasks.iter_mut().find(|t| t.id == id) {
+        task.finished = Some(Instant::now());
+        task.handle = None;
+        task.state = match result {
+            Ok(()) => TaskState::Finished,
+            Err(e) => TaskState::Failed(e),
+        };
+    }
+}
+
+/// List tracked tasks in spawn order.
+pub fn list() -> Vec<TaskInfo> {
+    let now = Instant::now();
+    TASKS
+        .lock()
+        .iter()
+        .map(|t| TaskInfo {
+            id: t.id,
+            name: t.name.clone(),
+            elapsed: t.finished.unwrap_or(now).duration_since(t.started),
+            state: t.state.clone(),
+        })
+        .collect()
+}
+
+/// Whether any task is still running, and may run code from the library it was spawned from.
+#[cfg(feature = "jupyter")]
+pub fn any_running() -> bool {
+    TASKS.lock().iter().any(|t| t.state == TaskState::Running)
+}
+
+/// Cancel a running task, or dismiss it if it has already stopped.
+pub fn cancel(id: u64) {
+    let mut tasks = TASKS.lock();
+    let Some(pos) = tasks.iter().position(|t| t.id == id) else {
+        return;
+    };
+
+    let task = &mut tasks[pos];
+    match task.handle.take() {
+        Some(handle) => {
+            handle.abort();
+            task.finished = Some(Instant::now());
+            task.state = TaskState::Cancelled;
+        }
+        None => {
+            tasks.remove(pos);
+        }
+    }
+}
+
+/// Abort a task if it is still running and forget it.
+pub fn remove(id: u64) {
+    let mut tasks = TASKS.lock();
+    if let Some(pos) = tasks.iter().position(|t| t.id == id)
+        && let Some(handle) = tasks.remove(pos).handle
+    {
+        handle.abort();
+    }
+}
+
+/// Abort all tasks and wait for them to be dropped, then forget them.
+///
+/// Must be awaited before unloading the library the tasks were spawned from.
+pub async fn abort_all() {
+    let handles: Vec<JoinHandle<()>> = {
+        let mut tasks = TASKS.lock();
+        let handles = tasks.iter_mut().filter_map(|t| t.handle.take()).collect();
+        tasks.clear();
+        handles
+    };
+
+    for handle in handles {
+        handle.abort();
+        let _ = handle.await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(id: u64) -> Option<TaskInfo> {
+        list().into_iter().find(|t| t.id == id)
+    }
+
+    #[tokio::test]
+    async fn test_task_failure_is_recorded() {
+        let id = track("failing", Box::pin(async { Err("boom".to_string()) }));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(find(id).unwrap().state, TaskState::Failed("boom".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_then_dismiss() {
+        let id = track("pending", Box::pin(futures::future::pending()));
+        assert_eq!(find(id).unwrap().state, TaskState::Running);
+
+        cancel(id);
+        assert_eq!(find(id).unwrap().state, TaskState::Cancelled);
+
+        cancel(id);
+        assert!(find(id).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_remove_aborts_and_forgets() {
+        let id = track("service", Box::pin(futures::future::pending()));
+        remove(id);
+        assert!(find(id).is_none());
+        remove(id);
+    }
+}
@@ -0,0 +1,128 @@
+//! Escape sequences for the terminal the user sees.
+//!
+//! Inside tmux or GNU screen, sequences the multiplexer doesn't handle itself,
+//! such as OSC 52 clipboard writes, are swallowed unless they are wrapped in a
+//! passthrough sequence that hands them on to the outer terminal.
+//!
+//! The TUI also sets the window title here, so the build status shows in the
+//! taskbar or tab list. Multiplexers keep a title per pane and pass it on
+//! themselves, so title sequences are written unwrapped.
+
+use std::io::Write;
+
+use parking_lot::Mutex;
+
+/// Terminal multiplexer the runner is running in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Multiplexer {
+    None,
+    Tmux,
+    Screen,
+}
+
+/// Screen cuts passthrough sequences after 768 bytes, so longer ones are sent in pieces.
+const SCREEN_CHUNK_BYTES: usize = 768;
+
+impl Multiplexer {
+    /// Detect the multiplexer from the environment it sets for its panes.
+    pub fn detect() -> Self {
+        let var = |name| std::env::var(name).unwrap_or_default();
+        // tmux sets TERM to `screen` too unless configured otherwise.
+        if !var("TMUX").is_empty() {
+            Multiplexer::Tmux
+        } else if !var("STY").is_empty() || var("TERM").starts_with("screen") {
+            Multiplexer::Screen
+        } else {
+            Multiplexer::None
+        }
+    }
+
+    /// Wrap `sequence` so it reaches the outer terminal.
+    pub fn wrap(self, sequence: &str) -> String {
+        match self {
+            Multiplexer::None => sequence.to_string(),
+            // Escapes inside the passthrough are doubled.
+            Multiplexer::Tmux => format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b")),
+            Multiplexer::Screen => {
+                let mut wrapped = String::new();
+                let mut rest = sequence;
+                while !rest.is_empty() {
+                    // Pieces end on a character boundary, so none is split in two.
+                    let mut end = rest.len().min(SCREEN_CHUNK_BYTES);
+                    while !rest.is_char_boundary(end) {
+                        end -= 1;
+                    }
+                    let (chunk, tail) = rest.split_at(end);
+                    wrapped.push_str(&format!("\x1bP{}\x1b\\", chunk));
+                    rest = tail;
+                }
+                wrapped
+            }
+        }
+    }
+}
+
+/// Title last set with [`set_title`], forgotten when the saved title is restored.
+static TITLE: Mutex<Option<String>> = Mutex::new(None);
+
+/// Set the window title, unless it is already set to `title`. The first title
+/// saves the previous one on the terminal's title stack, for [`restore_title`].
+pub fn set_title(title: &str) {
+    let mut last = TITLE.lock();
+    if last.as_deref() == Some(title) {
+        return;
+    }
+    if last.is_none() && write_raw("\x1b[22;0t").is_err() {
+        return;
+    }
+    // Control characters would end the sequence early.
+    let title: String = title.chars().filter(|c| !c.is_control()).collect();
+    if write_raw(&format!("\x1b]2;{}\x07", title)).is_ok() {
+        *last = Some(title);
+    }
+}
+
+/// Put back the title from before [`set_title`], if a title was set.
+pub fn restore_title() {
+    if TITLE.lock().take().is_some() {
+        let _ = write_raw("\x1b[23;0t");
+    }
+}
+
+/// Write `sequence` to the terminal, wrapped for the multiplexer the runner is in.
+/// Written to stderr, which the TUI draws on and cell output capture leaves alone.
+pub fn write_sequence(sequence: &str) -> std::io::Result<()> {
+    write_raw(&Multiplexer::detect().wrap(sequence))
+}
+
+/// Write `sequence` to the terminal as is, for sequences the multiplexer handles.
+fn write_raw(sequence: &str) -> std::io::Result<()> {
+    let mut stderr = std::io::stderr();
+    stderr.write_all(sequence.as_bytes())?;
+    stderr.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wraps_sequences_for_multiplexers() {
+        let osc = "\x1b]52;c;aGk=\x07";
+        assert_eq!(Multiplexer::None.wrap(osc), osc);
+        assert_eq!(
+            Multiplexer::Tmux.wrap(osc),
+            "\x1bPtmux;\x1b\x1b]52;c;aGk=\x07\x1b\\"
+        );
+        assert_eq!(Multiplexer::Screen.wrap(osc), "\x1bP\x1b]52;c;aGk=\x07\x1b\\");
+
+        let long = "a".repeat(SCREEN_CHUNK_BYTES + 1);
+        assert_eq!(Multiplexer::Screen.wrap(&long).matches("\x1bP").count(), 2);
+
+        // A three byte character across the chunk boundary goes whole into the next piece.
+        let text = format!("{}€", "a".repeat(SCREEN_CHUNK_BYTES - 1));
+        let wrapped = Multiplexer::Screen.wrap(&text);
+        assert!(!wrapped.contains('\u{fffd}'));
+        assert!(wrapped.ends_with("\x1bP€\x1b\\"));
+    }
+}
@@ -1,16 +1,27 @@
 //! App and runtime configuration.
 
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
+use clap::ValueEnum;
 use ratatui::crossterm::event::{KeyCode, KeyModifiers};
+use ratatui::style::{Color, Style};
 use serde::{Deserialize, Serialize};
 
+use crate::clipboard::ClipboardMode;
+use crate::errors::{Error, Result};
+use crate::loader;
+use crate::store::{self, Codec, ExpiryPolicy, QuotaPolicy, StoreBackend};
+
 /// App configuration.
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(default)]
 pub struct AppConfig {
     pub general: GeneralConfig,
+    pub build: BuildConfig,
     pub keybindings: Keybindings,
+    pub theme: ThemeConfig,
 }
 
 /// General settings.
@@ -19,8 +30,65 @@ pub struct AppConfig {
 pub struct GeneralConfig {
     pub auto_reload: bool,
     pub debounce_ms: u32,
+    /// How long a reload waits for running cells to stop before it goes
+    /// ahead and keeps the old library loaded.
+    pub reload_timeout_secs: u64,
     pub image_viewer: Option<String>,
     pub show_timings: bool,
+    pub run_on_start: RunOnStart,
+    /// Clear the context store before the init cell runs.
+    pub clear_on_run: bool,
+    /// Ask before clearing the store from the TUI.
+    pub confirm_clear: bool,
+    /// Host plugin libraries to load at startup.
+    pub plugins: Vec<PathBuf>,
+    /// Run the init cell before the TUI starts and stop on failure.
+    pub check_init: bool,
+    /// Refuse to run other cells until the init cell has succeeded.
+    pub init_required: bool,
+    /// Run every cell in a worker process, as if marked `#[cell(isolated)]`.
+    pub isolate_cells: bool,
+    /// Poll every cell on a blocking thread, as if marked `#[cell(blocking)]`.
+    pub blocking_cells: bool,
+    /// Give every cell without a `cwd` `.cellbook/scratch/` as its working directory, as if marked `#[cell(sandbox)]`.
+    pub sandbox_cells: bool,
+    /// Capture backtraces for errors returned by cells, as if `RUST_LIB_BACKTRACE=1` was set.
+    /// Read from the global and local config only, at startup.
+    pub backtrace: bool,
+    /// Limit on the total serialized size of the store.
+    pub max_store_bytes: Option<u64>,
+    /// Send a desktop notification when a cell that ran at least this long finishes.
+    pub notify_after_secs: Option<u64>,
+    /// What happens when a store would exceed `max_store_bytes`.
+    pub store_quota: QuotaPolicy,
+    /// What happens to values stored with a lifetime once it has passed.
+    pub store_expiry: ExpiryPolicy,
+    /// Codec for stored values of at least `compression_threshold` bytes.
+    pub compression: Codec,
+    pub compression_threshold: usize,
+    /// Streamed values larger than this are kept on disk instead of in memory.
+    pub spill_threshold_bytes: u64,
+    /// Where values of at least `mmap_threshold_bytes` are kept.
+    pub store_backend: StoreBackend,
+    pub mmap_threshold_bytes: u64,
+    /// How the TUI copies to the clipboard.
+    pub clipboard: ClipboardMode,
+    /// Show the project and build status in the terminal's window title.
+    pub window_title: bool,
+    /// Percentage of the TUI's height taken by the cells, the store gets the rest.
+    pub cells_pane_percent: u16,
+    /// Percentage of the cells pane's width taken by the output of the selected
+    /// cell. `0` hides the output pane.
+    pub output_pane_percent: u16,
+    /// Show the store pane below the cells.
+    pub show_store: bool,
+    /// Start in zen mode, with neither the store pane nor the status bar.
+    pub zen_mode: bool,
+    /// Address `cargo cellbook web` listens on, without `--host` and `--port`.
+    pub web_host: String,
+    pub web_port: u16,
+    /// Interface used by `cargo cellbook run` without `--ui`.
+    pub ui: UiMode,
 }
 
 impl Default for GeneralConfig {
@@ -28,8 +96,213 @@ impl Default for GeneralConfig {
         Self {
             auto_reload: true,
             debounce_ms: 500,
+            reload_timeout_secs: 10,
             image_viewer: None,
             show_timings: false,
+            run_on_start: RunOnStart::default(),
+            clear_on_run: false,
+            confirm_clear: true,
+            plugins: Vec::new(),
+            check_init: false,
+            init_required: false,
+            isolate_cells: false,
+            blocking_cells: false,
+            sandbox_cells: false,
+            backtrace: false,
+            max_store_bytes: None,
+            notify_after_secs: None,
+            store_quota: QuotaPolicy::default(),
+            store_expiry: ExpiryPolicy::default(),
+            compression: Codec::None,
+            compression_threshold: 64 * 1024,
+            spill_threshold_bytes: 256 * 1024 * 1024,
+            store_backend: StoreBackend::default(),
+            mmap_threshold_bytes: 1024 * 1024,
+            clipboard: ClipboardMode::default(),
+            window_title: true,
+            cells_pane_percent: 70,
+            output_pane_percent: 40,
+            show_store: true,
+            zen_mode: false,
+            web_host: "127.0.0.1".to_string(),
+            web_port: 8080,
+            ui: UiMode::Tui,
+        }
+    }
+}
+
+impl GeneralConfig {
+    /// Apply the settings that configure the store and cells rather than the interface.
+    pub fn apply_runtime_settings(&self) {
+        if let Some(viewer) = self.image_viewer.as_ref() {
+            loader::set_notebook_var("CELLBOOK_IMAGE_VIEWER", viewer.as_str());
+        }
+        store::set_quota(self.max_store_bytes, self.store_quota);
+        store::set_compression(self.compression, self.compression_threshold);
+        store::set_spill_threshold(self.spill_threshold_bytes);
+        store::set_backend(self.store_backend, self.mmap_threshold_bytes);
+        store::set_expiry_policy(self.store_expiry);
+        loader::set_blocking_cells(self.blocking_cells);
+        loader::set_sandbox_cells(self.sandbox_cells);
+    }
+
+    /// Load the settings again for a reloaded notebook, whose `#[init(...)]`
+    /// settings may have changed, and apply them. Invalid settings keep the current ones.
+    pub fn reload(&mut self, notebook: &[(String, String)]) -> Result<()> {
+        *self = load(notebook)?.general;
+        self.apply_runtime_settings();
+        Ok(())
+    }
+
+    pub fn reload_timeout(&self) -> Duration {
+        Duration::from_secs(self.reload_timeout_secs)
+    }
+
+    /// Minimum run time of cells that send a desktop notification when they finish.
+    pub fn notify_after(&self) -> Option<Duration> {
+        self.notify_after_secs.map(Duration::from_secs)
+    }
+}
+
+/// Options for the `cargo build` of the notebook.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BuildConfig {
+    /// Cargo features to enable.
+    pub features: Vec<String>,
+    pub no_default_features: bool,
+    /// Environment variables set for the build, such as `RUSTFLAGS`.
+    pub env: BTreeMap<String, String>,
+    /// Directory to build into, passed as `--target-dir`. Without it the
+    /// directory `cargo metadata` reports is used.
+    pub target_dir: Option<PathBuf>,
+    /// Build with a profile tuned for rebuild latency, see [`crate::fast_build`].
+    pub fast_build: bool,
+}
+
+impl BuildConfig {
+    /// Arguments added to `cargo build`.
+    pub fn cargo_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if !self.features.is_empty() {
+            args.push("--features".to_string());
+            args.push(self.features.join(","));
+        }
+        if self.no_default_features {
+            args.push("--no-default-features".to_string());
+        }
+        if let Some(target_dir) = &self.target_dir {
+            args.push("--target-dir".to_string());
+            args.push(target_dir.display().to_string());
+        }
+        args
+    }
+}
+
+/// Interface for running a notebook.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum UiMode {
+    /// Interactive terminal UI
+    #[default]
+    Tui,
+    /// Line-based prompt reading commands from stdin
+    Plain,
+    /// Run every cell once, print the output and exit
+    Headless,
+}
+
+/// Cells to run at startup and after every reload.
+///
+/// Either `"init"`, `"none"` or a list of cell names run in order.
+/// The init cell is included in a list by its function name.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RunOnStart {
+    Mode(StartMode),
+    Cells(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StartMode {
+    Init,
+    None,
+}
+
+impl Default for RunOnStart {
+    fn default() -> Self {
+        RunOnStart::Mode(StartMode::Init)
+    }
+}
+
+/// Theme settings for cell status indicators.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+    /// Render indicators without color so states are told apart by glyph alone.
+    pub monochrome: bool,
+    pub pending: StatusStyle,
+    pub running: StatusStyle,
+    pub success: StatusStyle,
+    pub error: StatusStyle,
+    /// Cells that can't run because the init cell failed, with `init_required`.
+    pub blocked: StatusStyle,
+    /// Running `#[cell(service)]` cells.
+    pub service: StatusStyle,
+}
+
+/// Glyph and color for a cell status indicator.
+///
+/// Colors accept names (`"red"`, `"darkgray"`), indices (`"42"`) or hex (`"#ff0000"`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusStyle {
+    pub glyph: String,
+    pub color: String,
+}
+
+impl StatusStyle {
+    fn new(glyph: &str, color: &str) -> Self {
+        Self {
+            glyph: glyph.into(),
+            color: color.into(),
+        }
+    }
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        Self {
+            monochrome: false,
+            pending: StatusStyle::new("[none]", "darkgray"),
+            running: StatusStyle::new("[running]", "yellow"),
+            success: StatusStyle::new("[success]", "green"),
+            error: StatusStyle::new("[error]", "red"),
+            blocked: StatusStyle::new("[blocked]", "magenta"),
+            service: StatusStyle::new("[service]", "cyan"),
+        }
+    }
+}
+
+impl ThemeConfig {
+    /// Style for an indicator. Monochrome mode and unknown colors fall back to the terminal default.
+    pub fn style(&self, status: &StatusStyle) -> Style {
+        if self.monochrome {
+            return Style::default();
+        }
+        status
+            .color
+            .parse::<Color>()
+            .map(|c| Style::default().fg(c))
+            .unwrap_or_default()
+    }
+
+    /// Style for a fixed UI color, dropped in monochrome mode.
+    pub fn color(&self, color: Color) -> Style {
+        if self.monochrome {
+            Style::default()
+        } else {
+            Style::default().fg(color)
         }
     }
 }
@@ -40,42 +313,193 @@ impl Default for GeneralConfig {
 pub struct Keybindings {
     pub quit: KeyBinding,
     pub clear_context: KeyBinding,
+    pub undo_clear: KeyBinding,
+    pub fork_session: KeyBinding,
+    pub promote_fork: KeyBinding,
+    pub discard_fork: KeyBinding,
     pub view_output: KeyBinding,
     pub view_error: KeyBinding,
     pub view_build_error: KeyBinding,
+    pub view_diff: KeyBinding,
+    pub view_access: KeyBinding,
+    pub view_graph: KeyBinding,
+    /// Show the selected cell's doc comment and attributes.
+    pub view_details: KeyBinding,
+    /// Copy the selected cell's output, or the selected store value in the Store pane.
+    pub copy_output: KeyBinding,
     pub reload: KeyBinding,
     pub edit: KeyBinding,
+    pub scratch: KeyBinding,
+    pub docs: KeyBinding,
     pub run_cell: KeyBinding,
+    /// The nth key runs cell n.
+    pub run_cell_by_index: KeyBinding,
+    pub run_last: KeyBinding,
+    /// Run the selected cell without waiting for the running one.
+    pub run_concurrent: KeyBinding,
+    pub toggle_watch: KeyBinding,
+    pub toggle_hidden: KeyBinding,
+    /// Stop the selected `#[cell(service)]`. Running it again restarts it.
+    pub stop_service: KeyBinding,
     pub navigate_down: KeyBinding,
     pub navigate_up: KeyBinding,
+    pub jump_first: KeyBinding,
+    pub jump_last: KeyBinding,
+    pub toggle_focus: KeyBinding,
+    /// Show or hide the store pane.
+    pub toggle_store: KeyBinding,
+    /// Hide the store pane and the status bar, or bring them back.
+    pub zen_mode: KeyBinding,
+    /// Focus the previous pane.
+    pub focus_previous: KeyBinding,
+    /// Grow the focused pane.
+    pub grow_pane: KeyBinding,
+    /// Shrink the focused pane.
+    pub shrink_pane: KeyBinding,
+    pub mark_key: KeyBinding,
+    pub delete_keys: KeyBinding,
+    pub rename_key: KeyBinding,
+    pub export_keys: KeyBinding,
+    pub pin_keys: KeyBinding,
+    pub view_value: KeyBinding,
+    pub view_history: KeyBinding,
+    /// Remove the selected cell from the run queue.
+    pub dequeue: KeyBinding,
+    /// Move the selected queued cell one place earlier.
+    pub move_queued_up: KeyBinding,
+    /// Move the selected queued cell one place later.
+    pub move_queued_down: KeyBinding,
+    pub cancel_task: KeyBinding,
+    pub open_artifact: KeyBinding,
+    pub copy_path: KeyBinding,
+    pub delete_artifact: KeyBinding,
+    /// Re-open the latest image produced by the selected cell.
+    pub open_image: KeyBinding,
 }
 
 #[derive(Debug, Clone, Default, Deserialize)]
 struct PartialAppConfig {
     general: Option<PartialGeneralConfig>,
+    build: Option<PartialBuildConfig>,
     keybindings: Option<PartialKeybindings>,
+    theme: Option<PartialThemeConfig>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct PartialBuildConfig {
+    features: Option<Vec<String>>,
+    no_default_features: Option<bool>,
+    env: Option<BTreeMap<String, String>>,
+    target_dir: Option<PathBuf>,
+    fast_build: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct PartialThemeConfig {
+    monochrome: Option<bool>,
+    pending: Option<PartialStatusStyle>,
+    running: Option<PartialStatusStyle>,
+    success: Option<PartialStatusStyle>,
+    error: Option<PartialStatusStyle>,
+    blocked: Option<PartialStatusStyle>,
+    service: Option<PartialStatusStyle>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct PartialStatusStyle {
+    glyph: Option<String>,
+    color: Option<String>,
 }
 
 #[derive(Debug, Clone, Default, Deserialize)]
 struct PartialGeneralConfig {
     auto_reload: Option<bool>,
     debounce_ms: Option<u32>,
+    reload_timeout_secs: Option<u64>,
     image_viewer: Option<String>,
     show_timings: Option<bool>,
+    run_on_start: Option<RunOnStart>,
+    clear_on_run: Option<bool>,
+    confirm_clear: Option<bool>,
+    plugins: Option<Vec<PathBuf>>,
+    check_init: Option<bool>,
+    init_required: Option<bool>,
+    isolate_cells: Option<bool>,
+    blocking_cells: Option<bool>,
+    sandbox_cells: Option<bool>,
+    backtrace: Option<bool>,
+    max_store_bytes: Option<u64>,
+    notify_after_secs: Option<u64>,
+    store_quota: Option<QuotaPolicy>,
+    store_expiry: Option<ExpiryPolicy>,
+    compression: Option<Codec>,
+    compression_threshold: Option<usize>,
+    spill_threshold_bytes: Option<u64>,
+    store_backend: Option<StoreBackend>,
+    mmap_threshold_bytes: Option<u64>,
+    clipboard: Option<ClipboardMode>,
+    window_title: Option<bool>,
+    cells_pane_percent: Option<u16>,
+    output_pane_percent: Option<u16>,
+    show_store: Option<bool>,
+    zen_mode: Option<bool>,
+    web_host: Option<String>,
+    web_port: Option<u16>,
+    ui: Option<UiMode>,
 }
 
 #[derive(Debug, Clone, Default, Deserialize)]
 struct PartialKeybindings {
     quit: Option<KeyBinding>,
     clear_context: Option<KeyBinding>,
+    undo_clear: Option<KeyBinding>,
+    fork_session: Option<KeyBinding>,
+    promote_fork: Option<KeyBinding>,
+    discard_fork: Option<KeyBinding>,
     view_output: Option<KeyBinding>,
     view_error: Option<KeyBinding>,
     view_build_error: Option<KeyBinding>,
+    view_diff: Option<KeyBinding>,
+    view_access: Option<KeyBinding>,
+    view_graph: Option<KeyBinding>,
+    view_details: Option<KeyBinding>,
+    copy_output: Option<KeyBinding>,
     reload: Option<KeyBinding>,
     edit: Option<KeyBinding>,
+    scratch: Option<KeyBinding>,
+    docs: Option<KeyBinding>,
     run_cell: Option<KeyBinding>,
+    run_cell_by_index: Option<KeyBinding>,
+    run_last: Option<KeyBinding>,
+    run_concurrent: Option<KeyBinding>,
+    toggle_watch: Option<KeyBinding>,
+    toggle_hidden: Option<KeyBinding>,
+    stop_service: Option<KeyBinding>,
     navigate_down: Option<KeyBinding>,
     navigate_up: Option<KeyBinding>,
+    jump_first: Option<KeyBinding>,
+    jump_last: Option<KeyBinding>,
+    toggle_focus: Option<KeyBinding>,
+    toggle_store: Option<KeyBinding>,
+    zen_mode: Option<KeyBinding>,
+    focus_previous: Option<KeyBinding>,
+    grow_pane: Option<KeyBinding>,
+    shrink_pane: Option<KeyBinding>,
+    mark_key: Option<KeyBinding>,
+    delete_keys: Option<KeyBinding>,
+    rename_key: Option<KeyBinding>,
+    export_keys: Option<KeyBinding>,
+    pin_keys: Option<KeyBinding>,
+    view_value: Option<KeyBinding>,
+    view_history: Option<KeyBinding>,
+    dequeue: Option<KeyBinding>,
+    move_queued_up: Option<KeyBinding>,
+    move_queued_down: Option<KeyBinding>,
+    cancel_task: Option<KeyBinding>,
+    open_artifact: Option<KeyBinding>,
+    copy_path: Option<KeyBinding>,
+    delete_artifact: Option<KeyBinding>,
+    open_image: Option<KeyBinding>,
 }
 
 impl Default for Keybindings {
@@ -83,14 +507,55 @@ impl Default for Keybindings {
         Self {
             quit: KeyBinding::Single("q".into()),
             clear_context: KeyBinding::Single("x".into()),
+            undo_clear: KeyBinding::Single("u".into()),
+            fork_session: KeyBinding::Single("F".into()),
+            promote_fork: KeyBinding::Single("P".into()),
+            discard_fork: KeyBinding::Single("X".into()),
             view_output: KeyBinding::Single("o".into()),
             view_error: KeyBinding::Single("e".into()),
             view_build_error: KeyBinding::Single("f".into()),
+            view_diff: KeyBinding::Single("D".into()),
+            view_access: KeyBinding::Single("i".into()),
+            view_graph: KeyBinding::Single("v".into()),
+            view_details: KeyBinding::Single("a".into()),
+            copy_output: KeyBinding::Single("Y".into()),
             reload: KeyBinding::Single("r".into()),
             edit: KeyBinding::Single("E".into()),
+            scratch: KeyBinding::Single(":".into()),
+            docs: KeyBinding::Single("K".into()),
             run_cell: KeyBinding::Single("Enter".into()),
+            run_cell_by_index: KeyBinding::Multiple((1..=9).map(|n| format!("Alt+{}", n)).collect()),
+            run_last: KeyBinding::Single(".".into()),
+            // Many terminals report Shift+Enter as Enter.
+            run_concurrent: KeyBinding::Multiple(vec!["Shift+Enter".into(), "&".into()]),
+            toggle_watch: KeyBinding::Single("w".into()),
+            toggle_hidden: KeyBinding::Single("H".into()),
+            stop_service: KeyBinding::Single("S".into()),
             navigate_down: KeyBinding::Multiple(vec!["Down".into(), "j".into()]),
             navigate_up: KeyBinding::Multiple(vec!["Up".into(), "k".into()]),
+            jump_first: KeyBinding::Single("g".into()),
+            jump_last: KeyBinding::Single("G".into()),
+            toggle_focus: KeyBinding::Single("Tab".into()),
+            toggle_store: KeyBinding::Single("Z".into()),
+            zen_mode: KeyBinding::Single("z".into()),
+            focus_previous: KeyBinding::Single("Shift+Tab".into()),
+            grow_pane: KeyBinding::Multiple(vec!["+".into(), "=".into()]),
+            shrink_pane: KeyBinding::Single("-".into()),
+            mark_key: KeyBinding::Single("Space".into()),
+            delete_keys: KeyBinding::Single("d".into()),
+            rename_key: KeyBinding::Single("R".into()),
+            export_keys: KeyBinding::Single("s".into()),
+            pin_keys: KeyBinding::Single("p".into()),
+            view_value: KeyBinding::Single("Enter".into()),
+            view_history: KeyBinding::Single("h".into()),
+            dequeue: KeyBinding::Single("d".into()),
+            move_queued_up: KeyBinding::Single("[".into()),
+            move_queued_down: KeyBinding::Single("]".into()),
+            cancel_task: KeyBinding::Single("c".into()),
+            open_artifact: KeyBinding::Single("Enter".into()),
+            copy_path: KeyBinding::Single("y".into()),
+            delete_artifact: KeyBinding::Single("d".into()),
+            open_image: KeyBinding::Single("I".into()),
         }
     }
 }
@@ -114,6 +579,22 @@ impl KeyBinding {
             KeyBinding::Multiple(keys) => keys.iter().any(|s| key_matches(s, code, modifiers)),
         }
     }
+
+    /// Position of the matching key, for bindings where each key picks an item.
+    pub fn position(&self, code: KeyCode, modifiers: KeyModifiers) -> Option<usize> {
+        match self {
+            KeyBinding::Single(s) => key_matches(s, code, modifiers).then_some(0),
+            KeyBinding::Multiple(keys) => keys.iter().position(|s| key_matches(s, code, modifiers)),
+        }
+    }
+
+    /// Keys as shown in the status bar, e.g. `Enter` or `Down/j`.
+    pub fn label(&self) -> String {
+        match self {
+            KeyBinding::Single(s) => s.clone(),
+            KeyBinding::Multiple(keys) => keys.join("/"),
+        }
+    }
 }
 
 fn key_matches(binding: &str, code: KeyCode, modifiers: KeyModifiers) -> bool {
@@ -138,7 +619,10 @@ fn parse_key(s: &str) -> Option<(KeyCode, KeyModifiers)> {
     let code = match key_part {
         "Enter" => KeyCode::Enter,
         "Esc" | "Escape" => KeyCode::Esc,
+        // Terminals report Shift+Tab as a key of its own.
+        "Tab" if modifiers == KeyModifiers::SHIFT => KeyCode::BackTab,
         "Tab" => KeyCode::Tab,
+        "BackTab" => KeyCode::BackTab,
         "Backspace" => KeyCode::Backspace,
         "Delete" => KeyCode::Delete,
         "Insert" => KeyCode::Insert,
@@ -195,12 +679,99 @@ fn merge(base: &mut AppConfig, patch: PartialAppConfig) {
         if let Some(debounce_ms) = general.debounce_ms {
             base.general.debounce_ms = debounce_ms;
         }
+        if let Some(reload_timeout_secs) = general.reload_timeout_secs {
+            base.general.reload_timeout_secs = reload_timeout_secs;
+        }
         if let Some(image_viewer) = general.image_viewer {
             base.general.image_viewer = Some(image_viewer);
         }
         if let Some(show_timings) = general.show_timings {
             base.general.show_timings = show_timings;
         }
+        if let Some(run_on_start) = general.run_on_start {
+            base.general.run_on_start = run_on_start;
+        }
+        if let Some(clear_on_run) = general.clear_on_run {
+            base.general.clear_on_run = clear_on_run;
+        }
+        if let Some(confirm_clear) = general.confirm_clear {
+            base.general.confirm_clear = confirm_clear;
+        }
+        if let Some(plugins) = general.plugins {
+            base.general.plugins = plugins;
+        }
+        if let Some(check_init) = general.check_init {
+            base.general.check_init = check_init;
+        }
+        if let Some(init_required) = general.init_required {
+            base.general.init_required = init_required;
+        }
+        if let Some(isolate_cells) = general.isolate_cells {
+            base.general.isolate_cells = isolate_cells;
+        }
+        if let Some(blocking_cells) = general.blocking_cells {
+            base.general.blocking_cells = blocking_cells;
+        }
+        if let Some(sandbox_cells) = general.sandbox_cells {
+            base.general.sandbox_cells = sandbox_cells;
+        }
+        if let Some(backtrace) = general.backtrace {
+            base.general.backtrace = backtrace;
+        }
+        if let Some(max_store_bytes) = general.max_store_bytes {
+            base.general.max_store_bytes = Some(max_store_bytes);
+        }
+        if let Some(notify_after_secs) = general.notify_after_secs {
+            base.general.notify_after_secs = Some(notify_after_secs);
+        }
+        if let Some(store_quota) = general.store_quota {
+            base.general.store_quota = store_quota;
+        }
+        if let Some(store_expiry) = general.store_expiry {
+            base.general.store_expiry = store_expiry;
+        }
+        if let Some(compression) = general.compression {
+            base.general.compression = compression;
+        }
+        if let Some(compression_threshold) = general.compression_threshold {
+            base.general.compression_threshold = compression_threshold;
+        }
+        if let Some(spill_threshold_bytes) = general.spill_threshold_bytes {
+            base.general.spill_threshold_bytes = spill_threshold_bytes;
+        }
+        if let Some(store_backend) = general.store_backend {
+            base.general.store_backend = store_backend;
+        }
+        if let Some(mmap_threshold_bytes) = general.mmap_threshold_bytes {
+            base.general.mmap_threshold_bytes = mmap_threshold_bytes;
+        }
+        if let Some(clipboard) = general.clipboard {
+            base.general.clipboard = clipboard;
+        }
+        if let Some(window_title) = general.window_title {
+            base.general.window_title = window_title;
+        }
+        if let Some(cells_pane_percent) = general.cells_pane_percent {
+            base.general.cells_pane_percent = cells_pane_percent;
+        }
+        if let Some(output_pane_percent) = general.output_pane_percent {
+            base.general.output_pane_percent = output_pane_percent;
+        }
+        if let Some(show_store) = general.show_store {
+            base.general.show_store = show_store;
+        }
+        if let Some(zen_mode) = general.zen_mode {
+            base.general.zen_mode = zen_mode;
+        }
+        if let Some(web_host) = general.web_host {
+            base.general.web_host = web_host;
+        }
+        if let Some(web_port) = general.web_port {
+            base.general.web_port = web_port;
+        }
+        if let Some(ui) = general.ui {
+            base.general.ui = ui;
+        }
     }
 
     if let Some(keybindings) = patch.keybindings {
@@ -210,6 +781,18 @@ fn merge(base: &mut AppConfig, patch: PartialAppConfig) {
         if let Some(v) = keybindings.clear_context {
             base.keybindings.clear_context = v;
         }
+        if let Some(v) = keybindings.undo_clear {
+            base.keybindings.undo_clear = v;
+        }
+        if let Some(v) = keybindings.fork_session {
+            base.keybindings.fork_session = v;
+        }
+        if let Some(v) = keybindings.promote_fork {
+            base.keybindings.promote_fork = v;
+        }
+        if let Some(v) = keybindings.discard_fork {
+            base.keybindings.discard_fork = v;
+        }
         if let Some(v) = keybindings.view_output {
             base.keybindings.view_output = v;
         }
@@ -219,21 +802,172 @@ fn merge(base: &mut AppConfig, patch: PartialAppConfig) {
         if let Some(v) = keybindings.view_build_error {
             base.keybindings.view_build_error = v;
         }
+        if let Some(v) = keybindings.view_diff {
+            base.keybindings.view_diff = v;
+        }
+        if let Some(v) = keybindings.view_access {
+            base.keybindings.view_access = v;
+        }
+        if let Some(v) = keybindings.view_graph {
+            base.keybindings.view_graph = v;
+        }
+        if let Some(v) = keybindings.view_details {
+            base.keybindings.view_details = v;
+        }
+        if let Some(v) = keybindings.copy_output {
+            base.keybindings.copy_output = v;
+        }
         if let Some(v) = keybindings.reload {
             base.keybindings.reload = v;
         }
         if let Some(v) = keybindings.edit {
             base.keybindings.edit = v;
         }
+        if let Some(v) = keybindings.scratch {
+            base.keybindings.scratch = v;
+        }
+        if let Some(v) = keybindings.docs {
+            base.keybindings.docs = v;
+        }
         if let Some(v) = keybindings.run_cell {
             base.keybindings.run_cell = v;
         }
+        if let Some(v) = keybindings.run_cell_by_index {
+            base.keybindings.run_cell_by_index = v;
+        }
+        if let Some(v) = keybindings.run_last {
+            base.keybindings.run_last = v;
+        }
+        if let Some(v) = keybindings.run_concurrent {
+            base.keybindings.run_concurrent = v;
+        }
+        if let Some(v) = keybindings.toggle_watch {
+            base.keybindings.toggle_watch = v;
+        }
+        if let Some(v) = keybindings.toggle_hidden {
+            base.keybindings.toggle_hidden = v;
+        }
+        if let Some(v) = keybindings.stop_service {
+            base.keybindings.stop_service = v;
+        }
         if let Some(v) = keybindings.navigate_down {
             base.keybindings.navigate_down = v;
         }
         if let Some(v) = keybindings.navigate_up {
             base.keybindings.navigate_up = v;
         }
+        if let Some(v) = keybindings.jump_first {
+            base.keybindings.jump_first = v;
+        }
+        if let Some(v) = keybindings.jump_last {
+            base.keybindings.jump_last = v;
+        }
+        if let Some(v) = keybindings.toggle_focus {
+            base.keybindings.toggle_focus = v;
+        }
+        if let Some(v) = keybindings.toggle_store {
+            base.keybindings.toggle_store = v;
+        }
+        if let Some(v) = keybindings.zen_mode {
+            base.keybindings.zen_mode = v;
+        }
+        if let Some(v) = keybindings.focus_previous {
+            base.keybindings.focus_previous = v;
+        }
+        if let Some(v) = keybindings.grow_pane {
+            base.keybindings.grow_pane = v;
+        }
+        if let Some(v) = keybindings.shrink_pane {
+            base.keybindings.shrink_pane = v;
+        }
+        if let Some(v) = keybindings.mark_key {
+            base.keybindings.mark_key = v;
+        }
+        if let Some(v) = keybindings.delete_keys {
+            base.keybindings.delete_keys = v;
+        }
+        if let Some(v) = keybindings.rename_key {
+            base.keybindings.rename_key = v;
+        }
+        if let Some(v) = keybindings.export_keys {
+            base.keybindings.export_keys = v;
+        }
+        if let Some(v) = keybindings.pin_keys {
+            base.keybindings.pin_keys = v;
+        }
+        if let Some(v) = keybindings.view_value {
+            base.keybindings.view_value = v;
+        }
+        if let Some(v) = keybindings.view_history {
+            base.keybindings.view_history = v;
+        }
+        if let Some(v) = keybindings.dequeue {
+            base.keybindings.dequeue = v;
+        }
+        if let Some(v) = keybindings.move_queued_up {
+            base.keybindings.move_queued_up = v;
+        }
+        if let Some(v) = keybindings.move_queued_down {
+            base.keybindings.move_queued_down = v;
+        }
+        if let Some(v) = keybindings.cancel_task {
+            base.keybindings.cancel_task = v;
+        }
+        if let Some(v) = keybindings.open_artifact {
+            base.keybindings.open_artifact = v;
+        }
+        if let Some(v) = keybindings.copy_path {
+            base.keybindings.copy_path = v;
+        }
+        if let Some(v) = keybindings.delete_artifact {
+            base.keybindings.delete_artifact = v;
+        }
+        if let Some(v) = keybindings.open_image {
+            base.keybindings.open_image = v;
+        }
+    }
+
+    if let Some(build) = patch.build {
+        if let Some(features) = build.features {
+            base.build.features = features;
+        }
+        if let Some(no_default_features) = build.no_default_features {
+            base.build.no_default_features = no_default_features;
+        }
+        // Variables are merged so a local config can override a single one.
+        if let Some(env) = build.env {
+            base.build.env.extend(env);
+        }
+        if let Some(target_dir) = build.target_dir {
+            base.build.target_dir = Some(target_dir);
+        }
+        if let Some(fast_build) = build.fast_build {
+            base.build.fast_build = fast_build;
+        }
+    }
+
+    if let Some(theme) = patch.theme {
+        if let Some(monochrome) = theme.monochrome {
+            base.theme.monochrome = monochrome;
+        }
+        merge_status_style(&mut base.theme.pending, theme.pending);
+        merge_status_style(&mut base.theme.running, theme.running);
+        merge_status_style(&mut base.theme.success, theme.success);
+        merge_status_style(&mut base.theme.error, theme.error);
+        merge_status_style(&mut base.theme.blocked, theme.blocked);
+        merge_status_style(&mut base.theme.service, theme.service);
+    }
+}
+
+fn merge_status_style(base: &mut StatusStyle, patch: Option<PartialStatusStyle>) {
+    let Some(patch) = patch else {
+        return;
+    };
+    if let Some(glyph) = patch.glyph {
+        base.glyph = glyph;
+    }
+    if let Some(color) = patch.color {
+        base.color = color;
     }
 }
 
@@ -253,14 +987,49 @@ fn merge_file(config: &mut AppConfig, path: Option<PathBuf>) {
     merge(config, partial);
 }
 
-/// Load app configuration from defaults, global, then local.
-pub fn load() -> AppConfig {
+/// Parse settings declared by the notebook with `#[init(...)]`.
+fn parse_notebook_config(settings: &[(String, String)]) -> Result<Option<PartialAppConfig>> {
+    if settings.is_empty() {
+        return Ok(None);
+    }
+    let mut contents = String::from("[general]\n");
+    for (key, value) in settings {
+        contents.push_str(&format!("{} = {}\n", key, value));
+    }
+    toml::from_str(&contents)
+        .map(Some)
+        .map_err(|e| Error::NotebookConfig(e.message().to_string()))
+}
+
+/// Load app configuration from defaults, global, the notebook's own settings, then local.
+pub fn load(notebook: &[(String, String)]) -> Result<AppConfig> {
+    Ok(layered(parse_notebook_config(notebook)?))
+}
+
+/// App configuration from defaults and the global and local config files,
+/// without the notebook's own settings.
+pub fn load_files() -> AppConfig {
+    layered(None)
+}
+
+fn layered(notebook: Option<PartialAppConfig>) -> AppConfig {
     let mut config = AppConfig::default();
     merge_file(&mut config, global_config_path());
+    if let Some(partial) = notebook {
+        merge(&mut config, partial);
+    }
     merge_file(&mut config, local_config_path());
     config
 }
 
+/// Build options from the global and local config files.
+///
+/// The notebook's own settings can't affect its build, so they aren't needed.
+/// Read on every build so edits to `Cellbook.toml` apply to the next one.
+pub fn load_build() -> BuildConfig {
+    load_files().build
+}
+
 /// Ensure the config file exists with default values.
 /// Creates the config directory and file if they don't exist.
 pub fn ensure_config_exists() {
@@ -282,6 +1051,69 @@ pub fn ensure_config_exists() {
     }
 }
 
+/// Write `key = value` to the `[general]` section of the global config file,
+/// keeping the rest of the file as it is written.
+pub fn save_general_setting(key: &str, value: &str) -> std::io::Result<()> {
+    let path =
+        global_config_path().ok_or_else(|| std::io::Error::other("no config directory on this platform"))?;
+    save_setting(&path, key, value)
+}
+
+/// Write `key = value` to the `[general]` section of the project's
+/// `Cellbook.toml`, creating the file if there is none.
+pub fn save_project_setting(key: &str, value: &str) -> std::io::Result<()> {
+    let path = local_config_path().ok_or_else(|| std::io::Error::other("no current directory"))?;
+    save_setting(&path, key, value)
+}
+
+fn save_setting(path: &Path, key: &str, value: &str) -> std::io::Result<()> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, set_general_key(&contents, key, value))
+}
+
+/// Replace `key` in the `[general]` section of `contents`, or add it at the
+/// top of the section, adding the section if there is none.
+fn set_general_key(contents: &str, key: &str, value: &str) -> String {
+    let setting = format!("{} = {}", key, value);
+    let mut lines: Vec<String> = contents.lines().map(String::from).collect();
+    let mut header = None;
+    let mut section = None;
+    for (i, line) in lines.iter().enumerate() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            section = Some(line == "[general]");
+            if line == "[general]" {
+                header = Some(i);
+            }
+        } else if section == Some(true)
+            && line
+                .strip_prefix(key)
+                .is_some_and(|rest| rest.trim_start().starts_with('='))
+        {
+            lines[i] = setting;
+            return lines.join("\n") + "\n";
+        }
+    }
+    match header {
+        Some(i) => lines.insert(i + 1, setting),
+        None => {
+            if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+                lines.push(String::new());
+            }
+            lines.push("[general]".to_string());
+            lines.push(setting);
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -460,6 +1292,87 @@ show_timings = true
         assert!(config.general.show_timings);
     }
 
+    #[test]
+    fn test_merge_build_config() {
+        let mut config = AppConfig::default();
+        let global: PartialAppConfig = toml::from_str(
+            r#"
+[build]
+features = ["gpu"]
+env = { RUSTFLAGS = "-C target-cpu=native", CC = "clang" }
+"#,
+        )
+        .unwrap();
+        let local: PartialAppConfig = toml::from_str(
+            r#"
+[build]
+no_default_features = true
+env = { CC = "gcc" }
+target_dir = "out"
+"#,
+        )
+        .unwrap();
+        merge(&mut config, global);
+        merge(&mut config, local);
+
+        assert_eq!(
+            config.build.cargo_args(),
+            [
+                "--features",
+                "gpu",
+                "--no-default-features",
+                "--target-dir",
+                "out"
+            ]
+        );
+        assert_eq!(config.build.env["RUSTFLAGS"], "-C target-cpu=native");
+        assert_eq!(config.build.env["CC"], "gcc");
+        assert!(BuildConfig::default().cargo_args().is_empty());
+    }
+
+    #[test]
+    fn test_parse_notebook_config() {
+        let settings = vec![
+            ("show_timings".to_string(), "true".to_string()),
+            ("image_viewer".to_string(), "\"feh\"".to_string()),
+            ("run_on_start".to_string(), "[\"setup\", \"load\"]".to_string()),
+        ];
+        let mut config = AppConfig::default();
+        merge(&mut config, parse_notebook_config(&settings).unwrap().unwrap());
+
+        assert!(config.general.show_timings);
+        assert_eq!(config.general.image_viewer.as_deref(), Some("feh"));
+        assert_eq!(
+            config.general.run_on_start,
+            RunOnStart::Cells(vec!["setup".into(), "load".into()])
+        );
+        assert!(parse_notebook_config(&[]).unwrap().is_none());
+
+        let invalid = [("show_timings".to_string(), "\"yes\"".to_string())];
+        let error = parse_notebook_config(&invalid).unwrap_err().to_string();
+        assert!(error.starts_with("Invalid #[init] settings: "), "{}", error);
+    }
+
+    #[test]
+    fn test_parse_run_on_start() {
+        let parse = |value: &str| {
+            toml::from_str::<PartialAppConfig>(&format!("[general]\nrun_on_start = {}", value))
+                .unwrap()
+                .general
+                .unwrap()
+                .run_on_start
+                .unwrap()
+        };
+
+        assert_eq!(parse(r#""init""#), RunOnStart::Mode(StartMode::Init));
+        assert_eq!(parse(r#""none""#), RunOnStart::Mode(StartMode::None));
+        assert_eq!(
+            parse(r#"["setup", "load_data"]"#),
+            RunOnStart::Cells(vec!["setup".into(), "load_data".into()])
+        );
+        assert!(toml::from_str::<PartialAppConfig>("[general]\nrun_on_start = \"all\"").is_err());
+    }
+
     #[test]
     fn test_merge_keybindings_is_field_level() {
         let mut config = AppConfig::default();
@@ -487,4 +1400,67 @@ quit = "Q"
                 .matches(KeyCode::Char('r'), KeyModifiers::NONE)
         );
     }
+
+    #[test]
+    fn test_merge_theme_is_field_level() {
+        let mut config = AppConfig::default();
+        merge(
+            &mut config,
+            toml::from_str::<PartialAppConfig>(
+                r#"
+[theme.error]
+glyph = "✗"
+"#,
+            )
+            .unwrap(),
+        );
+
+        assert_eq!(config.theme.error.glyph, "✗");
+        assert_eq!(config.theme.error.color, "red");
+        assert_eq!(config.theme.success.glyph, "[success]");
+    }
+
+    #[test]
+    fn test_theme_style() {
+        let mut theme = ThemeConfig::default();
+        assert_eq!(theme.style(&theme.pending), Style::default().fg(Color::DarkGray));
+        assert_eq!(
+            theme.style(&StatusStyle::new("x", "#ff0000")),
+            Style::default().fg(Color::Rgb(255, 0, 0))
+        );
+        assert_eq!(theme.style(&StatusStyle::new("x", "nope")), Style::default());
+
+        theme.monochrome = true;
+        assert_eq!(theme.style(&theme.error), Style::default());
+        assert_eq!(theme.color(Color::Blue), Style::default());
+    }
+
+    #[test]
+    fn test_set_general_key_keeps_the_rest_of_the_file() {
+        let contents = "# cellbook\n[general]\nauto_reload = true\ncells_pane_percent = 70\n\n[keybindings]\nquit = \"q\"\n";
+        assert_eq!(
+            set_general_key(contents, "cells_pane_percent", "75"),
+            "# cellbook\n[general]\nauto_reload = true\ncells_pane_percent = 75\n\n[keybindings]\nquit = \"q\"\n"
+        );
+        assert_eq!(
+            set_general_key(contents, "output_pane_percent", "30"),
+            "# cellbook\n[general]\noutput_pane_percent = 30\nauto_reload = true\ncells_pane_percent = 70\n\n[keybindings]\nquit = \"q\"\n"
+        );
+        assert_eq!(
+            set_general_key("[keybindings]\nquit = \"q\"\n", "output_pane_percent", "30"),
+            "[keybindings]\nquit = \"q\"\n\n[general]\noutput_pane_percent = 30\n"
+        );
+        assert_eq!(
+            set_general_key("", "cells_pane_percent", "60"),
+            "[general]\ncells_pane_percent = 60\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_shift_tab() {
+        assert_eq!(
+            parse_key("Shift+Tab"),
+            Some((KeyCode::BackTab, KeyModifiers::SHIFT))
+        );
+    }
 }
@@ -1,26 +1,57 @@
 //! Event handling for the TUI.
 
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread::JoinHandle;
 use std::time::Duration;
 
-use ratatui::crossterm::event::{self, Event as CrosstermEvent, KeyEvent, KeyEventKind};
+use ratatui::crossterm::event::{
+    self,
+    Event as CrosstermEvent,
+    KeyCode,
+    KeyEvent,
+    KeyEventKind,
+    KeyModifiers,
+};
 use tokio::sync::mpsc;
 
 use super::config::AppConfig;
-use super::state::App;
+use super::state::{App, Focus};
+use crate::failure::CellFailure;
+use crate::loader::CellId;
+use crate::store::StoreDiff;
 
 /// Events sent from the watcher or spawned tasks to the TUI.
 pub enum TuiEvent {
+    /// A source file was written, before the debounced rebuild starts.
+    SourceChanged,
+    /// The debounced change turned out not to modify any source file.
+    SourceUnchanged,
     Reloaded,
     BuildStarted,
     BuildCompleted(Option<String>),
     CellCompleted {
-        idx: usize,
+        id: CellId,
         name: String,
         stdout: String,
         duration: Duration,
+        result: std::result::Result<(), CellFailure>,
+        diff: StoreDiff,
+        /// Started with `run_concurrent`, so it didn't hold `executing`.
+        concurrent: bool,
+    },
+    /// A running cell failed and is about to be retried for the `retry`th time.
+    CellRetrying {
+        id: CellId,
+        retry: u32,
+        retries: u32,
+    },
+    /// A scratch snippet finished building, with the library path or build output.
+    ScratchBuilt(std::result::Result<PathBuf, String>),
+    ScratchCompleted {
+        snippet: String,
+        stdout: String,
         result: std::result::Result<(), String>,
     },
 }
@@ -122,29 +153,207 @@ impl EventHandler {
 pub enum Action {
     None,
     Quit,
-    RunCell(usize),
+    RunCell(CellId),
+    /// Run a cell beside the one currently running.
+    RunConcurrent(CellId),
     ViewOutput,
     ViewError,
     ViewBuildError,
+    ViewDiff,
+    ViewAccess,
+    ViewGraph,
+    /// Show the selected cell's doc comment and attributes.
+    ViewDetails,
+    /// Copy the selected cell's output to the clipboard.
+    CopyOutput,
+    /// Copy the selected store value to the clipboard as JSON.
+    CopyValue,
     ClearContext,
+    UndoClear,
+    ForkSession,
+    PromoteFork,
+    DiscardFork,
     Reload,
     Edit,
+    DeleteKeys,
+    /// Rename a store key: current name, new name.
+    RenameKey(String, String),
+    ExportKeys,
+    PinKeys,
+    ViewValue,
+    /// Show the recorded changes of the selected store key.
+    ViewHistory,
+    CancelTask,
+    OpenArtifact,
+    CopyArtifactPath,
+    DeleteArtifact,
+    /// Re-open the latest image produced by the selected cell.
+    OpenCellImage,
+    /// Stop the selected service cell.
+    StopService,
+    RunScratch(String),
+    /// Answer the waiting prompt, or cancel it with `None`.
+    AnswerPrompt(Option<String>),
+    /// Offer the symbols of the selected cell for documentation lookup.
+    LookupDocs,
+    OpenDocs(String),
+    /// Grow the focused pane, or shrink it with `false`.
+    ResizePane(bool),
+    /// Remember a toggled part of the layout in the project's `Cellbook.toml`.
+    SaveLayout(&'static str, bool),
 }
 
+/// Lines scrolled per page in the builtin pager.
+const PAGE_SCROLL_LINES: u16 = 20;
+
 /// Process a key event and return the action.
 pub fn handle_key(key: KeyEvent, app: &mut App, config: &AppConfig) -> Action {
     if key.kind != KeyEventKind::Press {
         return Action::None;
     }
 
+    app.notice = None;
+    // A count or `g` only applies to the next key.
+    let count = app.count.take();
+    let pending_jump = std::mem::take(&mut app.pending_jump);
+
     let kb = &config.keybindings;
 
+    // A cell is waiting on the prompt, so it takes keys before anything else.
+    if let Some(prompt) = app.prompt.as_mut() {
+        match key.code {
+            KeyCode::Esc => return Action::AnswerPrompt(None),
+            KeyCode::Enter => return Action::AnswerPrompt(Some(std::mem::take(&mut prompt.input))),
+            KeyCode::Backspace => {
+                prompt.input.pop();
+            }
+            KeyCode::Char(c)
+                if !key
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                prompt.input.push(c)
+            }
+            _ => {}
+        }
+        return Action::None;
+    }
+
+    if let Some(pager) = app.pager.as_mut() {
+        if kb.quit.matches(key.code, key.modifiers) || key.code == KeyCode::Esc {
+            app.close_pager();
+        } else if kb.navigate_down.matches(key.code, key.modifiers) {
+            pager.scroll_down(1);
+        } else if kb.navigate_up.matches(key.code, key.modifiers) {
+            pager.scroll_up(1);
+        } else if key.code == KeyCode::PageDown || key.code == KeyCode::Char(' ') {
+            pager.scroll_down(PAGE_SCROLL_LINES);
+        } else if key.code == KeyCode::PageUp {
+            pager.scroll_up(PAGE_SCROLL_LINES);
+        }
+        return Action::None;
+    }
+
+    if let Some(input) = app.scratch_input.as_mut() {
+        match key.code {
+            KeyCode::Esc => app.scratch_input = None,
+            KeyCode::Enter => {
+                if let Some(snippet) = app.submit_scratch() {
+                    return Action::RunScratch(snippet);
+                }
+            }
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Char(c)
+                if !key
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                input.push(c)
+            }
+            _ => {}
+        }
+        return Action::None;
+    }
+
+    if let Some(rename) = app.rename_input.as_mut() {
+        match key.code {
+            KeyCode::Esc => app.rename_input = None,
+            KeyCode::Enter => {
+                if let Some((key, name)) = app.submit_rename() {
+                    return Action::RenameKey(key, name);
+                }
+            }
+            KeyCode::Backspace => {
+                rename.input.pop();
+            }
+            KeyCode::Char(c)
+                if !key
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                rename.input.push(c)
+            }
+            _ => {}
+        }
+        return Action::None;
+    }
+
+    if let Some(lookup) = app.doc_lookup.as_mut() {
+        match key.code {
+            KeyCode::Esc => app.doc_lookup = None,
+            KeyCode::Enter => {
+                if let Some(symbol) = app.submit_doc_lookup() {
+                    return Action::OpenDocs(symbol);
+                }
+            }
+            KeyCode::Tab => lookup.cycle(),
+            KeyCode::Backspace => {
+                lookup.input.pop();
+            }
+            KeyCode::Char(c)
+                if !key
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                lookup.input.push(c)
+            }
+            _ => {}
+        }
+        return Action::None;
+    }
+
+    if app.confirming_clear {
+        app.confirming_clear = false;
+        return match key.code {
+            KeyCode::Char('y' | 'Y') | KeyCode::Enter => Action::ClearContext,
+            _ => Action::None,
+        };
+    }
+
     if kb.quit.matches(key.code, key.modifiers) {
         return Action::Quit;
     }
     if kb.clear_context.matches(key.code, key.modifiers) {
+        if config.general.confirm_clear {
+            app.confirming_clear = true;
+            return Action::None;
+        }
         return Action::ClearContext;
     }
+    if kb.undo_clear.matches(key.code, key.modifiers) {
+        return Action::UndoClear;
+    }
+    if kb.fork_session.matches(key.code, key.modifiers) {
+        return Action::ForkSession;
+    }
+    if kb.promote_fork.matches(key.code, key.modifiers) {
+        return Action::PromoteFork;
+    }
+    if kb.discard_fork.matches(key.code, key.modifiers) {
+        return Action::DiscardFork;
+    }
     if kb.view_output.matches(key.code, key.modifiers) {
         return Action::ViewOutput;
     }
@@ -154,25 +363,294 @@ pub fn handle_key(key: KeyEvent, app: &mut App, config: &AppConfig) -> Action {
     if kb.view_build_error.matches(key.code, key.modifiers) {
         return Action::ViewBuildError;
     }
+    if kb.view_diff.matches(key.code, key.modifiers) {
+        return Action::ViewDiff;
+    }
+    if kb.view_access.matches(key.code, key.modifiers) {
+        return Action::ViewAccess;
+    }
+    if kb.view_graph.matches(key.code, key.modifiers) {
+        return Action::ViewGraph;
+    }
+    if kb.view_details.matches(key.code, key.modifiers) {
+        return Action::ViewDetails;
+    }
+    if kb.open_image.matches(key.code, key.modifiers) {
+        return Action::OpenCellImage;
+    }
     if kb.reload.matches(key.code, key.modifiers) {
         return Action::Reload;
     }
     if kb.edit.matches(key.code, key.modifiers) {
         return Action::Edit;
     }
+    if kb.scratch.matches(key.code, key.modifiers) {
+        app.open_scratch();
+        return Action::None;
+    }
+    if kb.docs.matches(key.code, key.modifiers) {
+        return Action::LookupDocs;
+    }
+    if kb.run_last.matches(key.code, key.modifiers) {
+        return match app.last_run_cell {
+            Some(id) if app.index_of(id).is_some() => Action::RunCell(id),
+            Some(_) => {
+                app.set_notice("The last cell run no longer exists");
+                Action::None
+            }
+            None => {
+                app.set_notice("No cell has run yet");
+                Action::None
+            }
+        };
+    }
+    if kb.toggle_focus.matches(key.code, key.modifiers) {
+        app.toggle_focus();
+        return Action::None;
+    }
+    if kb.toggle_store.matches(key.code, key.modifiers) {
+        app.toggle_store();
+        return Action::SaveLayout("show_store", app.show_store);
+    }
+    if kb.zen_mode.matches(key.code, key.modifiers) {
+        app.toggle_zen();
+        return Action::SaveLayout("zen_mode", app.zen);
+    }
+    if kb.focus_previous.matches(key.code, key.modifiers) {
+        app.focus_previous();
+        return Action::None;
+    }
+    if kb.grow_pane.matches(key.code, key.modifiers) {
+        return Action::ResizePane(true);
+    }
+    if kb.shrink_pane.matches(key.code, key.modifiers) {
+        return Action::ResizePane(false);
+    }
+    match app.focus {
+        Focus::Output => return handle_output_key(key, app, config),
+        Focus::Store => return handle_store_key(key, app, config),
+        Focus::Queue => return handle_queue_key(key, app, config),
+        Focus::Tasks => return handle_tasks_key(key, app, config),
+        Focus::Artifacts => return handle_artifacts_key(key, app, config),
+        Focus::Diagnostics => return handle_diagnostics_key(key, app, config),
+        Focus::Cells => {}
+    }
+    if let KeyCode::Char(c @ '0'..='9') = key.code
+        && key.modifiers == KeyModifiers::NONE
+    {
+        let digit = c as usize - '0' as usize;
+        app.count = Some(count.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+        return Action::None;
+    }
     if kb.navigate_down.matches(key.code, key.modifiers) {
-        app.select_next();
+        app.select_next_by(count.unwrap_or(1));
         return Action::None;
     }
     if kb.navigate_up.matches(key.code, key.modifiers) {
-        app.select_previous();
+        app.select_previous_by(count.unwrap_or(1));
         return Action::None;
     }
-    if kb.run_cell.matches(key.code, key.modifiers)
+    if kb.jump_first.matches(key.code, key.modifiers) {
+        if !pending_jump {
+            app.count = count;
+            app.pending_jump = true;
+        } else if let Some(idx) = count {
+            app.select_cell(idx);
+        } else {
+            app.select_first();
+        }
+        return Action::None;
+    }
+    if kb.jump_last.matches(key.code, key.modifiers) {
+        match count {
+            Some(idx) => {
+                app.select_cell(idx);
+            }
+            None => app.select_last(),
+        }
+        return Action::None;
+    }
+    if let Some(n) = kb.run_cell_by_index.position(key.code, key.modifiers) {
+        return run_cell_at(app, n + 1);
+    }
+    if kb.toggle_watch.matches(key.code, key.modifiers) {
+        app.toggle_watch();
+        return Action::None;
+    }
+    if kb.toggle_hidden.matches(key.code, key.modifiers) {
+        app.toggle_hidden();
+        return Action::None;
+    }
+    if kb.stop_service.matches(key.code, key.modifiers) {
+        return Action::StopService;
+    }
+    if kb.copy_output.matches(key.code, key.modifiers) {
+        return Action::CopyOutput;
+    }
+    if kb.run_concurrent.matches(key.code, key.modifiers)
         && let Some(idx) = app.selected_cell_index()
         && idx > 0
     {
-        return Action::RunCell(idx);
+        return Action::RunConcurrent(app.cells[idx].id);
+    }
+    if kb.run_cell.matches(key.code, key.modifiers) {
+        if let Some(idx) = count {
+            return run_cell_at(app, idx);
+        }
+        if let Some(idx) = app.selected_cell_index()
+            && idx > 0
+        {
+            return Action::RunCell(app.cells[idx].id);
+        }
+    }
+
+    Action::None
+}
+
+/// Select and run the cell numbered `idx` in the list.
+/// The init cell only runs on reload, so `0` only selects it.
+fn run_cell_at(app: &mut App, idx: usize) -> Action {
+    if idx >= app.cells.len() {
+        app.set_notice(format!("No cell [{}]", idx));
+        return Action::None;
+    }
+    app.select_cell(idx);
+    if idx == 0 {
+        return Action::None;
+    }
+    Action::RunCell(app.cells[idx].id)
+}
+
+/// Process a key event while the Output pane has focus.
+fn handle_output_key(key: KeyEvent, app: &mut App, config: &AppConfig) -> Action {
+    let kb = &config.keybindings;
+
+    if kb.navigate_down.matches(key.code, key.modifiers) {
+        app.scroll_output(1);
+    } else if kb.navigate_up.matches(key.code, key.modifiers) {
+        app.scroll_output(-1);
+    } else if key.code == KeyCode::PageDown || key.code == KeyCode::Char(' ') {
+        app.scroll_output(PAGE_SCROLL_LINES as i16);
+    } else if key.code == KeyCode::PageUp {
+        app.scroll_output(-(PAGE_SCROLL_LINES as i16));
+    } else if kb.jump_first.matches(key.code, key.modifiers) {
+        app.scroll_output(i16::MIN);
+    } else if kb.jump_last.matches(key.code, key.modifiers) {
+        app.scroll_output(i16::MAX);
+    } else if kb.copy_output.matches(key.code, key.modifiers) {
+        return Action::CopyOutput;
+    } else if key.code == KeyCode::Esc {
+        app.focus = Focus::Cells;
+    }
+
+    Action::None
+}
+
+/// Process a key event while the Store pane has focus.
+fn handle_store_key(key: KeyEvent, app: &mut App, config: &AppConfig) -> Action {
+    let kb = &config.keybindings;
+
+    if kb.navigate_down.matches(key.code, key.modifiers) {
+        app.select_next_key();
+    } else if kb.navigate_up.matches(key.code, key.modifiers) {
+        app.select_previous_key();
+    } else if kb.mark_key.matches(key.code, key.modifiers) {
+        app.toggle_mark();
+    } else if kb.delete_keys.matches(key.code, key.modifiers) {
+        return Action::DeleteKeys;
+    } else if kb.rename_key.matches(key.code, key.modifiers) {
+        app.open_rename();
+    } else if kb.export_keys.matches(key.code, key.modifiers) {
+        return Action::ExportKeys;
+    } else if kb.pin_keys.matches(key.code, key.modifiers) {
+        return Action::PinKeys;
+    } else if kb.view_value.matches(key.code, key.modifiers) {
+        return Action::ViewValue;
+    } else if kb.view_history.matches(key.code, key.modifiers) {
+        return Action::ViewHistory;
+    } else if kb.copy_output.matches(key.code, key.modifiers) {
+        return Action::CopyValue;
+    } else if key.code == KeyCode::Esc {
+        app.focus = Focus::Cells;
+    }
+
+    Action::None
+}
+
+/// Process a key event while the Queue pane has focus.
+fn handle_queue_key(key: KeyEvent, app: &mut App, config: &AppConfig) -> Action {
+    let kb = &config.keybindings;
+
+    if kb.navigate_down.matches(key.code, key.modifiers) {
+        app.select_next_queued();
+    } else if kb.navigate_up.matches(key.code, key.modifiers) {
+        app.select_previous_queued();
+    } else if kb.move_queued_up.matches(key.code, key.modifiers) {
+        app.move_queued(false);
+    } else if kb.move_queued_down.matches(key.code, key.modifiers) {
+        app.move_queued(true);
+    } else if kb.dequeue.matches(key.code, key.modifiers) {
+        if let Some(idx) = app.dequeue_selected().and_then(|id| app.index_of(id)) {
+            let name = app.cells[idx].name.clone();
+            app.set_notice(format!("Removed {} from the queue", name));
+        }
+    } else if key.code == KeyCode::Esc {
+        app.focus = Focus::Cells;
+    }
+
+    Action::None
+}
+
+/// Process a key event while the Tasks pane has focus.
+fn handle_tasks_key(key: KeyEvent, app: &mut App, config: &AppConfig) -> Action {
+    let kb = &config.keybindings;
+
+    if kb.navigate_down.matches(key.code, key.modifiers) {
+        app.select_next_task();
+    } else if kb.navigate_up.matches(key.code, key.modifiers) {
+        app.select_previous_task();
+    } else if kb.cancel_task.matches(key.code, key.modifiers) {
+        return Action::CancelTask;
+    } else if key.code == KeyCode::Esc {
+        app.focus = Focus::Cells;
+    }
+
+    Action::None
+}
+
+/// Process a key event while the Artifacts pane has focus.
+fn handle_artifacts_key(key: KeyEvent, app: &mut App, config: &AppConfig) -> Action {
+    let kb = &config.keybindings;
+
+    if kb.navigate_down.matches(key.code, key.modifiers) {
+        app.select_next_artifact();
+    } else if kb.navigate_up.matches(key.code, key.modifiers) {
+        app.select_previous_artifact();
+    } else if kb.open_artifact.matches(key.code, key.modifiers) {
+        return Action::OpenArtifact;
+    } else if kb.copy_path.matches(key.code, key.modifiers) {
+        return Action::CopyArtifactPath;
+    } else if kb.delete_artifact.matches(key.code, key.modifiers) {
+        return Action::DeleteArtifact;
+    } else if key.code == KeyCode::Esc {
+        app.focus = Focus::Cells;
+    }
+
+    Action::None
+}
+
+/// Process a key event while the Diagnostics pane has focus.
+fn handle_diagnostics_key(key: KeyEvent, app: &mut App, config: &AppConfig) -> Action {
+    let kb = &config.keybindings;
+
+    if kb.navigate_down.matches(key.code, key.modifiers) {
+        app.select_next_finding();
+    } else if kb.navigate_up.matches(key.code, key.modifiers) {
+        app.select_previous_finding();
+    } else if kb.edit.matches(key.code, key.modifiers) {
+        return Action::Edit;
+    } else if key.code == KeyCode::Esc {
+        app.focus = Focus::Cells;
     }
 
     Action::None
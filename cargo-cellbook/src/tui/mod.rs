@@ -5,13 +5,18 @@ pub(crate) mod events;
 mod state;
 mod ui;
 
-use std::io::{Read, Write};
-use std::process::Command;
-use std::time::{Duration, Instant};
+use std::collections::{BTreeSet, HashMap};
+use std::ffi::OsStr;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Once;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 pub use events::TuiEvent;
 use events::{Action, AppEvent, EventHandler, handle_key};
-use gag::BufferRedirect;
+use futures::future::BoxFuture;
 use ratatui::Terminal;
 use ratatui::backend::CrosstermBackend;
 use ratatui::crossterm::cursor::MoveTo;
@@ -25,39 +30,110 @@ use ratatui::crossterm::terminal::{
     enable_raw_mode,
 };
 use ratatui::crossterm::{ExecutableCommand, execute};
-use state::{App, BuildStatus, CellOutput, CellStatus};
+use state::{App, BuildStatus, CellOutput, CellStatus, Focus, ReloadStep};
 use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
 
-use crate::errors::Result;
-use crate::loader::LoadedLibrary;
-use crate::{store, watcher};
+use crate::capture::capture_stdout;
+use crate::errors::{Error, Result};
+use crate::failure::CellFailure;
+use crate::graph::{Graph, Node};
+use crate::health::InitRun;
+use crate::loader::{CellId, CellInfo, LoadedLibrary, Released};
+use crate::store::{Access, Provenance, StoreDiff};
+use crate::{
+    artifacts,
+    clipboard,
+    docs,
+    graph,
+    hexdump,
+    history,
+    isolation,
+    lint,
+    loader,
+    notification,
+    plugins,
+    prompt,
+    resources,
+    retry,
+    runs,
+    scratch,
+    source,
+    store,
+    tasks,
+    terminal,
+    watcher,
+};
 
 type AppTerminal = Terminal<CrosstermBackend<std::io::Stderr>>;
 
+/// Whether the terminal is in raw mode on the alternate screen.
+static TERMINAL_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Restores the terminal when the TUI returns, also early with an error or by unwinding.
+struct TerminalGuard;
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
 /// Run the TUI.
 pub async fn run(
     lib: &mut LoadedLibrary,
     event_tx: mpsc::Sender<TuiEvent>,
     event_rx: mpsc::Receiver<TuiEvent>,
     app_config: config::AppConfig,
+    init_run: Option<InitRun>,
 ) -> Result<()> {
     let mut terminal = init_terminal()?;
+    let _terminal_guard = TerminalGuard;
 
-    // Set image viewer env var for cells to use.
-    if let Some(viewer) = app_config.general.image_viewer.as_ref() {
-        // SAFETY: Called once at startup before cells run.
-        unsafe { std::env::set_var("CELLBOOK_IMAGE_VIEWER", viewer) };
+    let mut app = App::new(lib.visible_cells().to_vec(), app_config.general.show_timings);
+    app.theme = app_config.theme.clone();
+    app.keybindings = app_config.keybindings.clone();
+    app.cells_percent = app_config.general.cells_pane_percent;
+    app.output_percent = app_config.general.output_pane_percent;
+    app.show_store = app_config.general.show_store;
+    app.zen = app_config.general.zen_mode;
+    apply_settings(&mut app, &app_config.general);
+    app.refresh_lint(lint::lint_notebook());
+    prompt::set_input(prompt::Input::Queue);
+    let mut startup_notices = plugins::load(&app_config.general.plugins);
+    if app.notify_after.is_some() && !notification::SUPPORTED {
+        startup_notices.push("notify_after_secs needs the desktop-notifications feature".to_string());
     }
-
-    let mut app = App::new(visible_cells(lib), app_config.general.show_timings);
-    app.refresh_context(store::list());
-    let mut cell_task: Option<JoinHandle<()>> = spawn_cell(lib, &mut app, 0, &event_tx);
+    // An init that already passed the startup check is not run again.
+    let skip_init = init_run.is_some();
+    if let Some(run) = init_run {
+        record_init_run(&mut app, run);
+    }
+    refresh_store(&mut app);
+    let mut cell_tasks = CellTasks {
+        exclusive: start_cells(lib, &mut app, &event_tx, skip_init),
+        ..CellTasks::default()
+    };
+    if !startup_notices.is_empty() {
+        app.set_notice(startup_notices.join("; "));
+    }
+    // Scratch libraries stay loaded until exit, since tasks or resources they
+    // created may still reference their code.
+    let mut scratch_libs: Vec<LoadedLibrary> = Vec::new();
 
     let mut events = EventHandler::new(event_rx, Duration::from_millis(100));
+    let project = std::env::current_dir()?
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let window_title = app_config.general.window_title;
 
     loop {
+        app.refresh_queue();
         terminal.draw(|frame| ui::render(frame, &mut app))?;
+        if window_title {
+            terminal::set_title(&ui::window_title(&project, &app.build_status));
+        }
 
         if let Some(event) = events.next().await {
             match event {
@@ -65,59 +141,345 @@ pub async fn run(
                     let action = handle_key(key, &mut app, &app_config);
                     match action {
                         Action::Quit => break,
-                        Action::RunCell(idx) => {
-                            if !app.executing {
-                                cell_task = spawn_cell(lib, &mut app, idx, &event_tx);
+                        Action::RunCell(id) => {
+                            if let Some(idx) = app.index_of(id) {
+                                if app.cells[idx].service {
+                                    start_service(lib, &mut app, idx);
+                                } else if !app.executing {
+                                    cell_tasks.exclusive = spawn_cell(lib, &mut app, idx, &event_tx, false);
+                                } else {
+                                    let name = app.cells[idx].name.clone();
+                                    match app.enqueue(id) {
+                                        Some(place) => {
+                                            let key = app.keybindings.run_concurrent.label();
+                                            app.set_notice(format!(
+                                                "Queued {} ({} in line), [{}] runs it beside the running cell",
+                                                name, place, key
+                                            ));
+                                        }
+                                        None => app.set_notice(format!("{} is already queued", name)),
+                                    }
+                                }
+                            }
+                        }
+                        Action::RunConcurrent(id) => {
+                            if let Some(idx) = app.index_of(id) {
+                                if app.cells[idx].service {
+                                    start_service(lib, &mut app, idx);
+                                } else if app.cell_statuses[idx] == CellStatus::Running {
+                                    app.set_notice(format!("{} is already running", app.cells[idx].name));
+                                } else if let Some(handle) = spawn_cell(lib, &mut app, idx, &event_tx, true) {
+                                    cell_tasks.concurrent.insert(id, handle);
+                                }
+                            }
+                        }
+                        Action::StopService => {
+                            if let Some(cell) = app.selected_cell_index().map(|i| app.cells[i].clone()) {
+                                match app.services.get(&cell.id) {
+                                    Some(&task) => {
+                                        tasks::cancel(task);
+                                        app.refresh_tasks(tasks::list());
+                                        app.set_notice(format!("Stopped {}", cell.name));
+                                    }
+                                    None => app.set_notice(format!("{} is not a running service", cell.name)),
+                                }
                             }
                         }
                         Action::ViewOutput => {
                             if let Some(name) = app.selected_cell_name()
                                 && let Some(output) = app.get_output(name)
                             {
-                                events.stop();
-                                view_output_in_pager(&output.stdout);
-                                terminal = init_terminal()?;
-                                events.resume();
+                                let title = format!("Output: {}", name);
+                                let content = output.stdout.clone();
+                                page(&mut terminal, &mut events, &mut app, title, content)?;
                             }
                         }
                         Action::ViewError => {
                             if let Some(idx) = app.selected_cell_index()
                                 && let Some(error) = app.get_error(idx)
                             {
-                                events.stop();
-                                view_output_in_pager(error);
-                                terminal = init_terminal()?;
-                                events.resume();
+                                let cell = &app.cells[idx];
+                                let title = format!("Error: {}", cell.name);
+                                let content = error.render(&cell.name, cell.line);
+                                page(&mut terminal, &mut events, &mut app, title, content)?;
                             }
                         }
                         Action::ViewBuildError => {
                             if let BuildStatus::BuildError(error) = &app.build_status {
-                                events.stop();
-                                view_output_in_pager(error);
-                                terminal = init_terminal()?;
-                                events.resume();
+                                let content = error.clone();
+                                page(&mut terminal, &mut events, &mut app, "Build error", content)?;
                             }
                         }
+                        Action::ViewDiff => {
+                            if let Some(name) = app.selected_cell_name()
+                                && let Some(diff) = app.store_diff(name)
+                            {
+                                let title = format!("Store changes: {}", name);
+                                let content = format_diff(name, diff);
+                                page(&mut terminal, &mut events, &mut app, title, content)?;
+                            }
+                        }
+                        Action::ViewAccess => {
+                            if let Some(name) = app.selected_cell_name()
+                                && let Some(access) = app.cell_access(name)
+                            {
+                                let title = format!("Store keys: {}", name);
+                                let content = format_access(name, access);
+                                page(&mut terminal, &mut events, &mut app, title, content)?;
+                            }
+                        }
+                        Action::ViewGraph => {
+                            let content = data_flow(&app).to_text();
+                            page(&mut terminal, &mut events, &mut app, "Data flow", content)?;
+                        }
                         Action::ClearContext => {
-                            store::clear();
-                            app.refresh_context(store::list());
+                            let cleared = store::clear();
+                            refresh_store(&mut app);
+                            if cleared > 0 {
+                                let undo = app.keybindings.undo_clear.label();
+                                app.set_notice(format!("Cleared {} key(s), [{}] Undo", cleared, undo));
+                            }
+                        }
+                        Action::UndoClear => {
+                            let restored = store::undo_clear();
+                            refresh_store(&mut app);
+                            if restored > 0 {
+                                app.set_notice(format!("Restored {} key(s)", restored));
+                            } else {
+                                app.set_notice("Nothing to undo");
+                            }
+                        }
+                        Action::ForkSession => {
+                            if store::fork() {
+                                refresh_store(&mut app);
+                                app.set_notice("Forked store, [F] Compare  [P] Promote  [X] Discard");
+                            } else if let Some(diff) = store::fork_diff() {
+                                app.open_pager("Fork comparison", format_fork_diff(&diff));
+                            }
+                        }
+                        Action::PromoteFork => {
+                            if store::promote_fork() {
+                                refresh_store(&mut app);
+                                app.set_notice("Promoted fork, its values are now the store");
+                            }
+                        }
+                        Action::DiscardFork => {
+                            if app.executing {
+                                app.set_notice("Wait for the running cell before discarding the fork");
+                            } else if store::discard_fork() {
+                                refresh_store(&mut app);
+                                app.set_notice("Discarded fork, store restored");
+                            }
+                        }
+                        Action::DeleteKeys => {
+                            let keys = app.target_keys();
+                            let deleted = store::clear_keys(&keys);
+                            for key in &keys {
+                                if app.pinned_keys.contains(key) {
+                                    store::toggle_pin(key);
+                                }
+                            }
+                            app.marked_keys.clear();
+                            refresh_store(&mut app);
+                            if deleted > 0 {
+                                let undo = app.keybindings.undo_clear.label();
+                                app.set_notice(format!("Deleted {} key(s), [{}] Undo", deleted, undo));
+                            }
+                        }
+                        Action::RenameKey(key, name) => match store::rename(&key, &name) {
+                            Ok(()) => {
+                                refresh_store(&mut app);
+                                app.select_key(&name);
+                                app.set_notice(format!("Renamed {} to {}", key, name));
+                            }
+                            Err(e) => app.set_notice(e),
+                        },
+                        Action::ExportKeys => {
+                            let keys = app.target_keys();
+                            if !keys.is_empty() {
+                                let path = export_path();
+                                match store::export(&keys, &path) {
+                                    Ok(n) => {
+                                        app.marked_keys.clear();
+                                        app.set_notice(format!(
+                                            "Exported {} key(s) to {}",
+                                            n,
+                                            path.display()
+                                        ));
+                                    }
+                                    Err(e) => app.set_notice(format!("Export failed: {}", e)),
+                                }
+                            }
+                        }
+                        Action::CancelTask => {
+                            if let Some(id) = app.selected_task_id() {
+                                tasks::cancel(id);
+                                app.refresh_tasks(tasks::list());
+                            }
+                        }
+                        Action::OpenArtifact => {
+                            if let Some(artifact) = app.selected_artifact()
+                                && let Err(e) = open_artifact(&artifact.path)
+                            {
+                                app.set_notice(e.to_string());
+                            }
+                        }
+                        Action::AnswerPrompt(line) => {
+                            prompt::answer(line);
+                            app.sync_prompt(prompt::pending());
+                        }
+                        Action::OpenCellImage => {
+                            if let Some(name) = app.selected_cell_name() {
+                                let result = match app.cell_images(name).last() {
+                                    Some(image) => open_artifact(&image.path).map_err(|e| e.to_string()),
+                                    None => Err(format!("{} has not produced any images", name)),
+                                };
+                                if let Err(e) = result {
+                                    app.set_notice(e);
+                                }
+                            }
+                        }
+                        Action::CopyArtifactPath => {
+                            if let Some(artifact) = app.selected_artifact() {
+                                let path = artifact.path.display().to_string();
+                                match clipboard::copy(&path, app.clipboard) {
+                                    Ok(()) => app.set_notice(format!("Copied {}", path)),
+                                    Err(e) => app.set_notice(e.to_string()),
+                                }
+                            }
+                        }
+                        Action::DeleteArtifact => {
+                            if let Some(path) = app.selected_artifact().map(|a| a.path.clone()) {
+                                match artifacts::delete(&path) {
+                                    Ok(()) => app.set_notice(format!("Deleted {}", path.display())),
+                                    Err(e) => app.set_notice(format!("Delete failed: {}", e)),
+                                }
+                                app.refresh_artifacts(artifacts::list());
+                            }
+                        }
+                        Action::ViewValue => {
+                            if let Some(key) = app.selected_key()
+                                && let Some((bytes, type_name, len)) =
+                                    store::peek_value(key, hexdump::MAX_DUMP_BYTES)
+                            {
+                                let title = format!("Value: {}", key);
+                                let origin = store::provenance(key).map(|p| ui::format_provenance(&p));
+                                let schema = store::schema(&type_name);
+                                let content = hexdump::describe(
+                                    key,
+                                    &type_name,
+                                    origin.as_deref(),
+                                    schema.as_deref(),
+                                    &bytes,
+                                    len,
+                                );
+                                page(&mut terminal, &mut events, &mut app, title, content)?;
+                            }
+                        }
+                        Action::CopyOutput => {
+                            if let Some(name) = app.selected_cell_name() {
+                                let result = match app.get_output(name) {
+                                    Some(output) => clipboard::copy(&output.stdout, app.clipboard),
+                                    None => Err(Error::Clipboard(format!("{} has no output yet", name))),
+                                };
+                                match result {
+                                    Ok(()) => app.set_notice(format!("Copied the output of {}", name)),
+                                    Err(e) => app.set_notice(e.to_string()),
+                                }
+                            }
+                        }
+                        Action::ResizePane(grow) => {
+                            if let Some((setting, percent)) = app.resize_focused(grow) {
+                                // Kept for the next session, in the global config.
+                                if let Err(e) = config::save_general_setting(setting, &percent.to_string()) {
+                                    app.set_notice(format!("Can't save {}: {}", setting, e));
+                                }
+                            }
+                        }
+                        Action::SaveLayout(setting, value) => {
+                            if let Err(e) = config::save_project_setting(setting, &value.to_string()) {
+                                app.set_notice(format!("Can't save {}: {}", setting, e));
+                            }
+                        }
+                        Action::CopyValue => {
+                            if let Some(key) = app.selected_key() {
+                                let result =
+                                    value_json(key).and_then(|json| clipboard::copy(&json, app.clipboard));
+                                match result {
+                                    Ok(()) => app.set_notice(format!("Copied {} as JSON", key)),
+                                    Err(e) => app.set_notice(e.to_string()),
+                                }
+                            }
+                        }
+                        Action::ViewDetails => {
+                            if let Some(idx) = app.selected_cell_index() {
+                                let cell = &app.cells[idx];
+                                let title = format!("Cell: {}", cell.display_name());
+                                let content = format_details(cell);
+                                app.open_pager(title, content);
+                            }
+                        }
+                        Action::ViewHistory => {
+                            if let Some(key) = app.selected_key() {
+                                let content = history::describe_key(key);
+                                app.open_pager(format!("History: {}", key), content);
+                            }
+                        }
+                        Action::PinKeys => {
+                            let keys = app.target_keys();
+                            for key in &keys {
+                                store::toggle_pin(key);
+                            }
+                            app.marked_keys.clear();
+                            refresh_store(&mut app);
                         }
                         Action::Reload => {
-                            cell_task = trigger_reload(&mut app, lib, &event_tx, cell_task.take()).await;
+                            if matches!(app.build_status, BuildStatus::Waiting(_)) {
+                                force_reload(&mut app, lib, &event_tx, &mut cell_tasks);
+                            } else {
+                                trigger_reload(&mut app, lib, &event_tx, &mut cell_tasks).await;
+                            }
                         }
                         Action::Edit => {
-                            let line = app.selected_cell_index().and_then(|i| {
-                                if i == 0 {
-                                    Some(lib.init_line())
-                                } else {
-                                    lib.cells().get(i - 1).map(|c| c.line)
-                                }
-                            });
+                            let line = if app.focus == Focus::Diagnostics {
+                                app.selected_finding().map(|finding| finding.line)
+                            } else {
+                                app.selected_cell_index()
+                                    .and_then(|i| app.cells.get(i).map(|c| c.line))
+                            };
                             events.stop();
-                            edit_cellbook(line);
+                            let result = edit_cellbook(line);
                             terminal = init_terminal()?;
                             events.resume();
+                            if let Err(e) = result {
+                                app.set_notice(e.to_string());
+                            }
+                        }
+                        Action::RunScratch(snippet) => {
+                            app.set_notice("Compiling scratch cell...");
+                            let lib_path = lib.lib_path().to_path_buf();
+                            let tx = event_tx.clone();
+                            tokio::spawn(async move {
+                                let result = scratch::build(&snippet, &lib_path)
+                                    .await
+                                    .map_err(|e| e.to_string());
+                                let _ = tx.send(TuiEvent::ScratchBuilt(result)).await;
+                            });
                         }
+                        Action::LookupDocs => {
+                            let source = std::fs::read_to_string("cellbook.rs").unwrap_or_default();
+                            let symbols = app
+                                .selected_cell_index()
+                                .and_then(|i| app.cells.get(i))
+                                .and_then(|cell| source::extract_item(&source, cell.line))
+                                .map(docs::symbols)
+                                .unwrap_or_default();
+                            app.open_doc_lookup(symbols);
+                        }
+                        Action::OpenDocs(symbol) => match open_docs(&symbol, lib.lib_path()) {
+                            Ok(url) => app.set_notice(format!("Opened {}", url)),
+                            Err(e) => app.set_notice(e.to_string()),
+                        },
                         Action::None => {}
                     }
                 }
@@ -126,208 +488,801 @@ pub async fn run(
                     // Terminal handles resize automatically.
                 }
 
+                AppEvent::Tui(TuiEvent::SourceChanged) => {
+                    app.mark_source_changed();
+                }
+
+                AppEvent::Tui(TuiEvent::SourceUnchanged) => {
+                    app.clear_source_changed();
+                }
+
+                // A waiting reload loads whatever build is newest when it goes ahead.
+                AppEvent::Tui(TuiEvent::BuildStarted)
+                    if matches!(app.build_status, BuildStatus::Waiting(_)) => {}
+                AppEvent::Tui(TuiEvent::BuildCompleted(_))
+                    if matches!(app.build_status, BuildStatus::Waiting(_)) =>
+                {
+                    app.refresh_lint(lint::lint_notebook());
+                }
+
                 AppEvent::Tui(TuiEvent::BuildStarted) => {
                     app.build_status = BuildStatus::Building;
                 }
 
                 AppEvent::Tui(TuiEvent::BuildCompleted(None)) => {
                     app.build_status = BuildStatus::Idle;
+                    app.refresh_lint(lint::lint_notebook());
                 }
 
                 AppEvent::Tui(TuiEvent::BuildCompleted(Some(err))) => {
+                    app.clear_source_changed();
                     app.build_status = BuildStatus::BuildError(err);
+                    app.refresh_lint(lint::lint_notebook());
                 }
 
                 AppEvent::Tui(TuiEvent::Reloaded) => {
-                    // Abort any running cell task before reloading the library.
-                    // The spawned future holds code from the current dylib, so it
-                    // must be dropped before the library is unmapped.
-                    if let Some(handle) = cell_task.take() {
-                        handle.abort();
-                        let _ = handle.await;
-                    }
-                    app.executing = false;
-                    app.build_status = BuildStatus::Reloading;
-                    match lib.reload() {
-                        Ok(()) => {
-                            app.refresh_cells(visible_cells(lib));
-                            cell_task = spawn_cell(lib, &mut app, 0, &event_tx);
-                            app.build_status = BuildStatus::Idle;
-                        }
-                        Err(e) => {
-                            app.build_status = BuildStatus::BuildError(e.to_string());
-                        }
-                    }
+                    app.clear_source_changed();
+                    begin_reload(&mut app, lib, &event_tx, &mut cell_tasks).await;
                 }
 
                 AppEvent::Tui(TuiEvent::CellCompleted {
-                    idx,
+                    id,
                     name,
                     stdout,
                     duration,
                     result,
+                    diff,
+                    concurrent,
                 }) => {
                     app.increment_count(&name);
-                    match result {
-                        Ok(()) => {
-                            app.cell_statuses[idx] = CellStatus::Success;
-                        }
-                        Err(e) => {
-                            app.cell_statuses[idx] = CellStatus::Error(e);
-                        }
+                    app.cell_retries.remove(&id);
+                    let idx = app.index_of(id);
+                    // Queued cells only make sense on top of a successful init.
+                    if idx == Some(0) && result.is_err() {
+                        app.pending_runs.clear();
+                    }
+                    let error = result.as_ref().err().map(|e| e.message.as_str());
+                    plugins::cell_completed(&name, duration, &stdout, error);
+                    notification::cell_finished(&name, duration, error, app.notify_after);
+                    let access = store::take_access(&name);
+                    runs::record(&name, duration, error, &access);
+                    // The cell may have been removed by a reload while it ran.
+                    if let Some(idx) = idx {
+                        app.cell_statuses[idx] = match result {
+                            Ok(()) => CellStatus::Success,
+                            Err(e) => CellStatus::Error(e),
+                        };
                     }
+                    app.record_run(&name, duration);
+                    app.record_store_access(&name, access, &diff);
+                    app.store_diffs.insert(name.clone(), diff);
                     app.store_output(&name, CellOutput { stdout, duration });
-                    app.refresh_context(store::list());
+                    refresh_store(&mut app);
+                    app.refresh_artifacts(artifacts::list());
+                    // A concurrent run finishing leaves the exclusive run and the queue alone.
+                    if concurrent {
+                        cell_tasks.concurrent.remove(&id);
+                    } else {
+                        artifacts::set_current_cell(None);
+                        store::set_producer(None);
+                        app.executing = false;
+                        cell_tasks.exclusive = run_next_pending(lib, &mut app, &event_tx);
+                    }
+                }
+
+                AppEvent::Tui(TuiEvent::CellRetrying { id, retry, retries }) => {
+                    app.cell_retries.insert(id, (retry, retries));
+                }
+
+                AppEvent::Tui(TuiEvent::ScratchBuilt(Err(e))) => {
+                    app.open_pager("Scratch build error", e);
+                }
+
+                AppEvent::Tui(TuiEvent::ScratchBuilt(Ok(path))) => {
+                    if app.executing {
+                        app.set_notice("A cell is running, run the scratch cell again when it finishes");
+                    } else {
+                        match spawn_scratch(&path, &mut app, &event_tx) {
+                            Ok((scratch_lib, handle)) => {
+                                scratch_libs.push(scratch_lib);
+                                cell_tasks.exclusive = Some(handle);
+                            }
+                            Err(e) => app.set_notice(e.to_string()),
+                        }
+                    }
+                }
+
+                AppEvent::Tui(TuiEvent::ScratchCompleted {
+                    snippet,
+                    stdout,
+                    result,
+                }) => {
+                    artifacts::set_current_cell(None);
+                    store::set_producer(None);
+                    let content = match result {
+                        Ok(()) => stdout,
+                        Err(e) => format!("{}Error: {}\n", stdout, e),
+                    };
+                    app.open_pager(format!("Scratch: {}", snippet), content);
+                    refresh_store(&mut app);
+                    app.refresh_artifacts(artifacts::list());
                     app.executing = false;
-                    cell_task = None;
+                    cell_tasks.exclusive = run_next_pending(lib, &mut app, &event_tx);
                 }
 
-                AppEvent::Tick => {}
+                AppEvent::Tick => {
+                    let expired = store::expire();
+                    if !expired.is_empty() {
+                        refresh_store(&mut app);
+                        app.set_notice(format!("Expired: {}", expired.join(", ")));
+                    }
+                    app.refresh_tasks(tasks::list());
+                    app.refresh_artifacts(artifacts::list());
+                    app.sync_prompt(prompt::pending());
+                    app.stale_library = lib.is_stale();
+                    poll_waiting_reload(&mut app, lib, &event_tx, &mut cell_tasks);
+                }
 
                 _ => {}
             }
         }
     }
 
-    // Abort any running cell task before exiting.
-    if let Some(handle) = cell_task.take() {
-        handle.abort();
-        let _ = handle.await;
-    }
+    // Abort any running cell and background tasks before exiting.
+    let released = release_library_state(lib, std::mem::take(&mut cell_tasks)).await;
+    drop(scratch_libs);
+    artifacts::cleanup();
+    store::remove_disk_files();
 
     restore_terminal();
 
+    if !released.teardown_stdout.is_empty() {
+        print!("{}", released.teardown_stdout);
+    }
+    if let Some(e) = released.teardown_error {
+        eprintln!("Error: {}", e);
+    }
+
     Ok(())
 }
 
 fn init_terminal() -> Result<AppTerminal> {
+    install_panic_hook();
     enable_raw_mode()?;
+    TERMINAL_ACTIVE.store(true, Ordering::SeqCst);
     execute!(std::io::stderr(), EnterAlternateScreen)?;
     let backend = CrosstermBackend::new(std::io::stderr());
     Ok(Terminal::new(backend)?)
 }
 
 fn restore_terminal() {
+    if !TERMINAL_ACTIVE.swap(false, Ordering::SeqCst) {
+        return;
+    }
     let _ = disable_raw_mode();
     let _ = execute!(std::io::stderr(), LeaveAlternateScreen);
+    terminal::restore_title();
+}
+
+/// Restore the terminal before a panic of the TUI itself is printed, so the message
+/// isn't lost with the alternate screen and the shell isn't left in raw mode.
+///
+/// Cells run on other threads and their panics fail the cell, so they keep the
+/// previous hook.
+fn install_panic_hook() {
+    static INSTALLED: Once = Once::new();
+    INSTALLED.call_once(|| {
+        let tui_thread = std::thread::current().id();
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if std::thread::current().id() != tui_thread || !TERMINAL_ACTIVE.load(Ordering::SeqCst) {
+                previous(info);
+                return;
+            }
+            restore_terminal();
+            eprintln!(
+                "\ncargo-cellbook {}\n\nstack backtrace:\n{}",
+                info,
+                std::backtrace::Backtrace::force_capture()
+            );
+        }));
+    });
+}
+
+/// Handles of the cell runs in flight, aborted before the library is unloaded.
+#[derive(Default)]
+struct CellTasks {
+    /// The run that holds `executing`: init, a queued or selected cell, or a scratch cell.
+    exclusive: Option<JoinHandle<()>>,
+    /// Cells started with `run_concurrent` beside it.
+    concurrent: HashMap<CellId, JoinHandle<()>>,
+}
+
+/// Drop everything that still references code from the loaded library:
+/// the running cells, background tasks and shared resources.
+///
+/// The notebook's `#[teardown]` function runs after the cells are aborted but
+/// while tasks and resources are still alive, so it can flush or close them.
+async fn release_library_state(lib: &LoadedLibrary, cell_tasks: CellTasks) -> Released {
+    for handle in cell_tasks
+        .exclusive
+        .into_iter()
+        .chain(cell_tasks.concurrent.into_values())
+    {
+        handle.abort();
+        let _ = handle.await;
+    }
+    store::set_producer(None);
+    lib.release().await
+}
+
+/// Tell the user about a failed teardown or resources invalidated by a reload.
+fn notify_released(app: &mut App, released: Released) {
+    if let Some(e) = released.teardown_error {
+        app.set_notice(format!("Teardown: {}", e));
+    } else if released.dropped > 0 {
+        app.set_notice(format!("Reload dropped {} resource(s)", released.dropped));
+    }
 }
 
 /// Trigger a manual rebuild and reload.
-/// Aborts any running cell task before reloading the library to prevent UB.
 async fn trigger_reload(
     app: &mut App,
     lib: &mut LoadedLibrary,
     event_tx: &mpsc::Sender<TuiEvent>,
-    cell_task: Option<JoinHandle<()>>,
-) -> Option<JoinHandle<()>> {
+    cell_tasks: &mut CellTasks,
+) {
     app.build_status = BuildStatus::Building;
+    let built = watcher::rebuild().await;
+    app.clear_source_changed();
+    app.refresh_lint(lint::lint_notebook());
+
+    match built {
+        Ok(()) => begin_reload(app, lib, event_tx, cell_tasks).await,
+        Err(e) => {
+            app.build_status = BuildStatus::BuildError(e.to_string());
+        }
+    }
+}
+
+/// Release what references the library, then reload it, or wait with
+/// [`BuildStatus::Waiting`] while cells still run its code.
+///
+/// Aborting a task only stops it at its next await, and a blocking cell keeps
+/// running on its thread, so the old library may only be unmapped once
+/// [`LoadedLibrary::in_use`] drops to zero.
+async fn begin_reload(
+    app: &mut App,
+    lib: &mut LoadedLibrary,
+    event_tx: &mpsc::Sender<TuiEvent>,
+    cell_tasks: &mut CellTasks,
+) {
+    // The reload already waiting loads the newest build once it goes ahead.
+    if matches!(app.build_status, BuildStatus::Waiting(_)) {
+        return;
+    }
+    let released = release_library_state(lib, std::mem::take(cell_tasks)).await;
+    notify_released(app, released);
+    app.executing = false;
+    if lib.in_use() == 0 {
+        finish_reload(app, lib, event_tx, cell_tasks);
+    } else {
+        app.build_status = BuildStatus::Waiting(Instant::now());
+    }
+}
 
-    match watcher::rebuild().await {
+/// Reload a waiting library once its cells stopped or the timeout passed.
+fn poll_waiting_reload(
+    app: &mut App,
+    lib: &mut LoadedLibrary,
+    event_tx: &mpsc::Sender<TuiEvent>,
+    cell_tasks: &mut CellTasks,
+) {
+    match app.waiting_reload(lib.in_use()) {
+        Some(ReloadStep::Reload) => finish_reload(app, lib, event_tx, cell_tasks),
+        Some(ReloadStep::Force) => force_reload(app, lib, event_tx, cell_tasks),
+        Some(ReloadStep::Wait) | None => {}
+    }
+}
+
+/// Reload without waiting any longer. The old library stays loaded until
+/// exit, since the cells still running need its code.
+fn force_reload(
+    app: &mut App,
+    lib: &mut LoadedLibrary,
+    event_tx: &mpsc::Sender<TuiEvent>,
+    cell_tasks: &mut CellTasks,
+) {
+    let running = lib.in_use();
+    finish_reload(app, lib, event_tx, cell_tasks);
+    app.set_notice(format!(
+        "Reloaded while {} cell run(s) still used the old library, which stays loaded",
+        running
+    ));
+}
+
+/// Settings of how cells run, applied again after a reload since the
+/// notebook's `#[init(...)]` may change them. The layout keeps what the user
+/// made of it.
+fn apply_settings(app: &mut App, general: &config::GeneralConfig) {
+    app.show_timings = general.show_timings;
+    app.run_on_start = general.run_on_start.clone();
+    app.clear_on_run = general.clear_on_run;
+    app.isolate_cells = general.isolate_cells;
+    app.clipboard = general.clipboard;
+    app.init_required = general.init_required;
+    app.max_store_bytes = general.max_store_bytes;
+    app.notify_after = general.notify_after();
+    app.reload_timeout = general.reload_timeout();
+}
+
+fn finish_reload(
+    app: &mut App,
+    lib: &mut LoadedLibrary,
+    event_tx: &mpsc::Sender<TuiEvent>,
+    cell_tasks: &mut CellTasks,
+) {
+    app.build_status = BuildStatus::Reloading;
+    match lib.reload() {
         Ok(()) => {
-            if let Some(handle) = cell_task {
-                handle.abort();
-                let _ = handle.await;
-            }
-            app.executing = false;
-            app.build_status = BuildStatus::Reloading;
-            match lib.reload() {
-                Ok(()) => {
-                    app.refresh_cells(visible_cells(lib));
-                    let handle = spawn_cell(lib, app, 0, event_tx);
-                    app.build_status = BuildStatus::Idle;
-                    handle
-                }
-                Err(e) => {
-                    app.build_status = BuildStatus::BuildError(e.to_string());
-                    None
+            app.refresh_cells(lib.visible_cells().to_vec());
+            match config::load(lib.config()) {
+                Ok(config) => {
+                    config.general.apply_runtime_settings();
+                    apply_settings(app, &config.general);
                 }
+                Err(e) => app.set_notice(e.to_string()),
             }
+            plugins::reloaded();
+            cell_tasks.exclusive = start_cells(lib, app, event_tx, false);
+            app.build_status = BuildStatus::Idle;
         }
         Err(e) => {
             app.build_status = BuildStatus::BuildError(e.to_string());
-            cell_task
         }
     }
 }
 
 /// Spawn a cell as a background task, sending the result via `event_tx`.
 /// Returns the `JoinHandle` so it can be aborted before a library reload.
+///
+/// A `concurrent` run doesn't hold `executing`, so it runs beside the exclusive
+/// run instead of waiting for it, and its completion doesn't start queued cells.
 fn spawn_cell(
     lib: &LoadedLibrary,
     app: &mut App,
     idx: usize,
     event_tx: &mpsc::Sender<TuiEvent>,
+    concurrent: bool,
 ) -> Option<JoinHandle<()>> {
     if idx >= app.cells.len() {
         return None;
     }
+    if matches!(app.build_status, BuildStatus::Waiting(_)) {
+        app.pending_runs.clear();
+        app.set_notice("Waiting for running cells to stop before reloading");
+        return None;
+    }
+    if idx > 0 && app.blocked_by_init() {
+        app.pending_runs.clear();
+        app.set_notice(format!(
+            "{} must succeed before other cells run",
+            app.cells[0].name
+        ));
+        return None;
+    }
 
-    let cell_name = app.cells[idx].clone();
-    app.executing = true;
+    let cell = app.cells[idx].clone();
+    // Isolated cells load the library from disk, so only in-process runs use the old code.
+    app.stale_library = lib.is_stale();
+    if app.stale_library
+        && matches!(app.build_status, BuildStatus::Idle)
+        && !(cell.isolated || app.isolate_cells)
+    {
+        app.set_notice(format!(
+            "{} runs from a stale library, [{}] loads the latest build",
+            cell.display_name(),
+            app.keybindings.reload.label()
+        ));
+    }
     app.cell_statuses[idx] = CellStatus::Running;
+    app.stale_cells.remove(&cell.id);
+    app.cell_retries.remove(&cell.id);
+    if idx > 0 {
+        app.last_run_cell = Some(cell.id);
+    }
+
+    let (id, retries) = (cell.id, cell.retries);
+    let retry_tx = event_tx.clone();
+    let on_retry = move |retry, _: &CellFailure| {
+        let _ = retry_tx.try_send(TuiEvent::CellRetrying { id, retry, retries });
+    };
 
-    let future = if idx == 0 {
-        lib.init_future()
+    // Cells run in a worker process get their output from the worker instead of
+    // capturing the runner's stdout.
+    let run: BoxFuture<'static, (String, std::result::Result<(), CellFailure>)> = if idx == 0 {
+        let future = lib.init_future();
+        Box::pin(capture_stdout(|| async {
+            future.await.map_err(|e| CellFailure::from_error(&*e))
+        }))
+    } else if cell.isolated || app.isolate_cells {
+        let lib_path = lib.lib_path().to_path_buf();
+        let name = cell.name.clone();
+        let attempt = move || isolation::run(lib_path.clone(), name.clone());
+        Box::pin(retry::run(retries, cell.backoff_ms, attempt, on_retry))
     } else {
-        match lib.cell_future(&cell_name) {
-            Ok(f) => f,
+        match lib.cell_attempts(&cell) {
+            Ok(attempts) => {
+                let attempt = move || {
+                    let future = attempts();
+                    capture_stdout(|| async {
+                        match future {
+                            Ok(future) => future.await.map_err(|e| CellFailure::from_error(&*e)),
+                            Err(e) => Err(CellFailure::from_message(e.to_string())),
+                        }
+                    })
+                };
+                Box::pin(retry::run(retries, cell.backoff_ms, attempt, on_retry))
+            }
             Err(e) => {
-                app.cell_statuses[idx] = CellStatus::Error(e.to_string());
-                app.executing = false;
+                app.cell_statuses[idx] = CellStatus::Error(CellFailure::from_message(e.to_string()));
                 return None;
             }
         }
     };
 
+    if idx == 0 && app.clear_on_run {
+        store::clear();
+    }
+
+    let run_number = app.get_count(&cell.name) + 1;
+    let provenance = Provenance::new(&cell.name, Some(run_number));
+    // Tasks the exclusive run spawns are attributed to it too. Concurrent runs only
+    // attribute their own stores and artifacts, which are tracked per task.
+    if !concurrent {
+        app.executing = true;
+        artifacts::set_current_cell(Some(&cell.name));
+        store::set_producer(Some(provenance.clone()));
+    }
     let tx = event_tx.clone();
-    let name = cell_name.clone();
+    let CellInfo { name, .. } = cell;
+    let before = store::snapshot();
     let handle = tokio::spawn(async move {
         let start = Instant::now();
-        let (stdout, result) = capture_stdout(|| async { future.await.map_err(|e| e.to_string()) }).await;
+        let (stdout, result) = store::produced_by(provenance, store::tracked(&name, run)).await;
         let duration = start.elapsed();
+        let diff = StoreDiff::between(&before, &store::snapshot());
 
         let _ = tx
             .send(TuiEvent::CellCompleted {
-                idx,
+                id,
                 name,
                 stdout,
                 duration,
                 result,
+                diff,
+                concurrent,
             })
             .await;
     });
     Some(handle)
 }
 
-fn visible_cells(lib: &LoadedLibrary) -> Vec<String> {
-    let mut cells = Vec::with_capacity(lib.cells().len() + 1);
-    cells.push(lib.init_name().to_string());
-    cells.extend(lib.cells().iter().map(|c| c.name.clone()));
-    cells
+/// Load a built scratch library and run its cell with the live store.
+fn spawn_scratch(
+    path: &Path,
+    app: &mut App,
+    event_tx: &mpsc::Sender<TuiEvent>,
+) -> Result<(LoadedLibrary, JoinHandle<()>)> {
+    let scratch_lib = LoadedLibrary::load_copy(path)?;
+    let cell = scratch_lib
+        .cells()
+        .iter()
+        .find(|c| c.name == scratch::CELL_NAME)
+        .ok_or_else(|| Error::CellNotFound(scratch::CELL_NAME.to_string()))?;
+    let future = scratch_lib.cell_future(cell)?;
+
+    app.executing = true;
+    artifacts::set_current_cell(Some(scratch::CELL_NAME));
+    store::set_producer(Some(Provenance::new(scratch::CELL_NAME, None)));
+    let snippet = app.last_scratch.clone();
+    let tx = event_tx.clone();
+    let handle = tokio::spawn(async move {
+        let (stdout, result) = capture_stdout(|| async { future.await.map_err(|e| e.to_string()) }).await;
+        let _ = tx
+            .send(TuiEvent::ScratchCompleted {
+                snippet,
+                stdout,
+                result,
+            })
+            .await;
+    });
+    Ok((scratch_lib, handle))
+}
+
+/// Queue the startup cells and watched cells after a load, and run the first one.
+/// `skip_init` leaves out an init cell that already ran before the TUI started.
+fn start_cells(
+    lib: &LoadedLibrary,
+    app: &mut App,
+    event_tx: &mpsc::Sender<TuiEvent>,
+    skip_init: bool,
+) -> Option<JoinHandle<()>> {
+    let unknown = app.queue_startup();
+    if skip_init && let Some(init) = app.cells.first() {
+        let init = init.id;
+        app.pending_runs.retain(|&id| id != init);
+    }
+    if !unknown.is_empty() {
+        app.set_notice(format!("run_on_start: unknown cell(s) {}", unknown.join(", ")));
+    }
+    run_next_pending(lib, app, event_tx)
 }
 
-/// Capture stdout during execution of an async closure.
-async fn capture_stdout<F, Fut, T>(f: F) -> (String, T)
-where
-    F: FnOnce() -> Fut,
-    Fut: std::future::Future<Output = T>,
-{
-    let mut buf = match BufferRedirect::stdout() {
-        Ok(buf) => buf,
-        Err(_) => return (String::new(), f().await),
+/// Show an init run from the startup health check as a completed run.
+fn record_init_run(app: &mut App, run: InitRun) {
+    let Some(name) = app.cells.first().map(|c| c.name.clone()) else {
+        return;
     };
+    app.increment_count(&name);
+    app.cell_statuses[0] = CellStatus::Success;
+    app.record_run(&name, run.duration);
+    app.record_store_access(&name, run.access, &run.diff);
+    app.store_diffs.insert(name.clone(), run.diff);
+    app.store_output(
+        &name,
+        CellOutput {
+            stdout: run.stdout,
+            duration: run.duration,
+        },
+    );
+}
 
-    let result = f().await;
-    let _ = std::io::stdout().flush();
+/// Spawn the next queued cell, skipping cells that no longer exist.
+fn run_next_pending(
+    lib: &LoadedLibrary,
+    app: &mut App,
+    event_tx: &mpsc::Sender<TuiEvent>,
+) -> Option<JoinHandle<()>> {
+    while let Some(id) = app.pending_runs.pop_front() {
+        match app.index_of(id) {
+            // Services run beside other cells, so the queue moves on.
+            Some(idx) if app.cells[idx].service => start_service(lib, app, idx),
+            Some(idx) => return spawn_cell(lib, app, idx, event_tx, false),
+            None => {}
+        }
+    }
+    None
+}
 
-    let mut output = String::new();
-    let _ = buf.read_to_string(&mut output);
+/// Start a service cell as a background task, restarting it if it is already running.
+///
+/// Services don't set `executing`, so other cells keep running while they serve.
+/// Their status follows the task, see [`App::refresh_tasks`].
+fn start_service(lib: &LoadedLibrary, app: &mut App, idx: usize) {
+    if app.blocked_by_init() {
+        app.set_notice(format!(
+            "{} must succeed before other cells run",
+            app.cells[0].name
+        ));
+        return;
+    }
+    let cell = app.cells[idx].clone();
+    let restarting = app.services.remove(&cell.id);
+    if let Some(task) = restarting {
+        tasks::remove(task);
+    }
+
+    match tasks::start_service(lib, &cell, cell.isolated || app.isolate_cells) {
+        Ok(task) => {
+            app.services.insert(cell.id, task);
+            app.cell_statuses[idx] = CellStatus::Running;
+            app.stale_cells.remove(&cell.id);
+            app.last_run_cell = Some(cell.id);
+            app.increment_count(&cell.name);
+            if restarting.is_some() {
+                app.set_notice(format!("Restarted {}", cell.name));
+            }
+        }
+        Err(e) => {
+            app.cell_statuses[idx] = CellStatus::Error(CellFailure::from_message(e.to_string()));
+        }
+    }
+    app.refresh_tasks(tasks::list());
+}
 
-    (output, result)
+fn refresh_store(app: &mut App) {
+    app.forked = store::is_forked();
+    app.refresh_context(store::list(), store::pinned());
+    app.key_sizes = store::sizes();
+    app.key_expiries = store::expiries();
+    let evicted = store::take_evicted();
+    if !evicted.is_empty() {
+        app.set_notice(format!("Store quota reached, evicted: {}", evicted.join(", ")));
+    }
+    plugins::observe_store();
+    app.refresh_resources(resources::list());
+}
+
+/// Path for a new store export file in the current directory.
+fn export_path() -> PathBuf {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    PathBuf::from(format!("cellbook-export-{}.postcard", secs))
+}
+
+/// Describe a cell's store changes for the pager.
+fn format_diff(cell_name: &str, diff: &StoreDiff) -> String {
+    if diff.is_empty() {
+        return format!("The last run of {} did not change the store.\n", cell_name);
+    }
+
+    let mut lines = vec![
+        format!("Store changes from the last run of {}:", cell_name),
+        String::new(),
+    ];
+    lines.extend(diff_lines(diff));
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+/// Graph of the keys cells passed in their last run, or declare in their source
+/// if they haven't run.
+fn data_flow(app: &App) -> Graph {
+    let source = std::fs::read_to_string("cellbook.rs").unwrap_or_default();
+    let nodes = app
+        .cells
+        .iter()
+        .map(|cell| Node {
+            cell: cell.name.clone(),
+            access: app.cell_access(&cell.name).cloned().unwrap_or_else(|| {
+                source::extract_item(&source, cell.line)
+                    .map(graph::declared_access)
+                    .unwrap_or_default()
+            }),
+        })
+        .collect();
+    Graph::new(nodes)
+}
+
+/// List the keys a cell loaded and stored in its last run.
+fn format_access(cell_name: &str, access: &Access) -> String {
+    let keys = |keys: &BTreeSet<String>| {
+        if keys.is_empty() {
+            vec!["  (none)".to_string()]
+        } else {
+            keys.iter().map(|key| format!("  {}", key)).collect()
+        }
+    };
+    let mut lines = vec![
+        format!("Store keys used by the last run of {}:", cell_name),
+        String::new(),
+    ];
+    lines.push("Inputs (loaded):".to_string());
+    lines.extend(keys(&access.reads));
+    lines.push(String::new());
+    lines.push("Outputs (stored or removed):".to_string());
+    lines.extend(keys(&access.writes));
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+/// Largest store value copied as JSON.
+const MAX_COPY_BYTES: usize = 1024 * 1024;
+
+/// The value under `key` as JSON, if it was stored with `codec = json`. The
+/// runner can't decode other values without their types.
+fn value_json(key: &str) -> Result<String> {
+    let Some((bytes, type_name, len)) = store::peek_value(key, MAX_COPY_BYTES) else {
+        return Err(Error::Store(format!("'{}' is not in the store", key)));
+    };
+    if !store::is_json(&type_name) {
+        return Err(Error::Clipboard(format!(
+            "{} can't be shown as JSON, only values stored with codec = json can",
+            key
+        )));
+    }
+    if len > MAX_COPY_BYTES as u64 {
+        return Err(Error::Clipboard(format!(
+            "{} is {}, too large to copy",
+            key,
+            store::format_bytes(len)
+        )));
+    }
+    let value: serde_json::Value =
+        serde_json::from_slice(&bytes).map_err(|e| Error::Clipboard(e.to_string()))?;
+    serde_json::to_string_pretty(&value).map_err(|e| Error::Clipboard(e.to_string()))
+}
+
+/// Describe a cell from its signature, attributes and doc comment.
+fn format_details(cell: &CellInfo) -> String {
+    let mut lines = vec![format!("fn {} (line {})", cell.name, cell.line)];
+    if let Some(title) = &cell.title {
+        lines.push(format!("name: {}", title));
+    }
+    if let Some(description) = &cell.description {
+        lines.push(format!("desc: {}", description));
+    }
+    let attributes: Vec<String> = [
+        (cell.isolated, "isolated".to_string()),
+        (cell.hidden, "hidden".to_string()),
+        (cell.blocking, "blocking".to_string()),
+        (cell.sandbox, "sandbox".to_string()),
+        (cell.service, "service".to_string()),
+        (
+            cell.cwd.is_some(),
+            format!("cwd = {:?}", cell.cwd.as_deref().unwrap_or_default()),
+        ),
+        (cell.retries > 0, format!("retries = {}", cell.retries)),
+        (cell.backoff_ms > 0, format!("backoff_ms = {}", cell.backoff_ms)),
+    ]
+    .into_iter()
+    .filter_map(|(set, attribute)| set.then_some(attribute))
+    .collect();
+    if !attributes.is_empty() {
+        lines.push(format!("#[cell({})]", attributes.join(", ")));
+    }
+    lines.push(String::new());
+    lines.push(cell.doc.clone().unwrap_or_else(|| "No doc comment.".to_string()));
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+/// Describe how the forked store differs from its baseline.
+fn format_fork_diff(diff: &StoreDiff) -> String {
+    if diff.is_empty() {
+        return "The fork matches the baseline store.\n".to_string();
+    }
+
+    let mut lines = vec![
+        "Fork changes relative to the baseline store:".to_string(),
+        String::new(),
+    ];
+    lines.extend(diff_lines(diff));
+    lines.push(String::new());
+    lines.push("[P] keeps the fork, [X] restores the baseline.".to_string());
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+fn diff_lines(diff: &StoreDiff) -> impl Iterator<Item = String> + '_ {
+    let added = diff.added.iter().map(|(k, ty)| format!("+ {}: {}", k, ty));
+    let changed = diff.changed.iter().map(|(k, old, new)| {
+        if old == new {
+            format!("~ {}: {}", k, new)
+        } else {
+            format!("~ {}: {} -> {}", k, old, new)
+        }
+    });
+    let removed = diff.removed.iter().map(|(k, ty)| format!("- {}: {}", k, ty));
+    added.chain(changed).chain(removed)
+}
+
+/// Show content in the external pager, with the TUI suspended while it runs,
+/// or in the builtin pager if the external one failed to start.
+fn page(
+    terminal: &mut AppTerminal,
+    events: &mut EventHandler,
+    app: &mut App,
+    title: impl Into<String>,
+    content: String,
+) -> Result<()> {
+    events.stop();
+    let result = view_output_in_pager(&content);
+    *terminal = init_terminal()?;
+    events.resume();
+    if let Err(e) = result {
+        app.set_notice(format!("{}, falling back to builtin", e));
+        app.open_pager(title, content);
+    }
+    Ok(())
 }
 
 /// View output in an external pager.
-fn view_output_in_pager(output: &str) {
+/// Returns an error if the pager could not be started.
+fn view_output_in_pager(output: &str) -> Result<()> {
     restore_terminal();
 
     // Clear screen to minimize flash of terminal history.
@@ -336,30 +1291,110 @@ fn view_output_in_pager(output: &str) {
         .and_then(|s| s.execute(MoveTo(0, 0)));
 
     let pager = std::env::var("PAGER").unwrap_or_else(|_| "less".to_string());
-    let mut child = match Command::new(&pager)
-        .arg("-R") // Enable raw control chars for less.
-        .stdin(std::process::Stdio::piped())
+    let mut command = Command::new(&pager);
+    if is_less(&pager) {
+        command.arg("-R"); // Pass colors through instead of escaping them.
+    }
+    let mut child = command
+        .stdin(Stdio::piped())
         .spawn()
-    {
-        Ok(child) => child,
-        Err(_) => {
-            // Fallback: just print the output.
-            print!("{}", output);
-            let _ = std::io::stdout().flush();
-            return;
-        }
-    };
+        .map_err(|source| Error::Launch {
+            var: "PAGER",
+            program: pager.clone(),
+            source,
+        })?;
 
     if let Some(mut stdin) = child.stdin.take() {
+        // The pager may exit before reading everything (e.g. `q` in less).
         let _ = stdin.write_all(output.as_bytes());
     }
 
-    let _ = child.wait();
+    child.wait()?;
+    Ok(())
+}
+
+/// Whether the pager is `less`, which escapes colors unless given `-R`.
+/// Other pagers may reject the flag.
+fn is_less(pager: &str) -> bool {
+    Path::new(pager).file_stem().is_some_and(|name| name == "less")
+}
+
+/// Open an artifact in the image viewer cells use.
+fn open_artifact(path: &Path) -> Result<()> {
+    let viewer = loader::notebook_var("CELLBOOK_IMAGE_VIEWER");
+    open_with("CELLBOOK_IMAGE_VIEWER", viewer, path)
+}
+
+/// Open the documentation for `symbol` and return its URL.
+fn open_docs(symbol: &str, lib_path: &Path) -> Result<String> {
+    // Docs are built by `cargo doc` next to the profile directory of the library.
+    let doc_dir = lib_path
+        .parent()
+        .and_then(Path::parent)
+        .map(|target| target.join("doc"))
+        .unwrap_or_default();
+    let notebook_crate = loader::lib_name()?;
+    let url = docs::url(symbol, &doc_dir, &notebook_crate);
+
+    open_with("BROWSER", std::env::var("BROWSER").ok(), &url)?;
+    Ok(url)
+}
+
+/// Platform-specific default viewers, tried in order, matching `cellbook::image`.
+fn default_viewers() -> &'static [&'static str] {
+    if cfg!(target_os = "macos") {
+        &["open"]
+    } else if cfg!(target_os = "windows") {
+        &["start", "explorer"]
+    } else {
+        &["xdg-open"]
+    }
+}
+
+/// Open `target` with the `viewer` set by `var`, falling back to the platform
+/// defaults if it is unset or can't be started, like `cellbook::image`.
+fn open_with(var: &'static str, viewer: Option<String>, target: impl AsRef<OsStr>) -> Result<()> {
+    let mut viewers: Vec<String> = viewer.into_iter().collect();
+    for fallback in default_viewers() {
+        if !viewers.iter().any(|viewer| viewer == fallback) {
+            viewers.push(fallback.to_string());
+        }
+    }
+    let mut failure = None;
+    for viewer in viewers {
+        let spawned = viewer_command(&viewer, &target)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        match spawned {
+            Ok(_) => return Ok(()),
+            Err(source) => failure = Some((viewer, source)),
+        }
+    }
+    let (program, source) = failure.expect("there is a default viewer on every platform");
+    Err(Error::Launch { var, program, source })
+}
+
+/// Command opening `target` with `viewer`, matching `cellbook::image`.
+/// On Windows `start` is a `cmd` builtin rather than a program, so it runs through `cmd /C`.
+fn viewer_command(viewer: &str, target: impl AsRef<OsStr>) -> Command {
+    if cfg!(target_os = "windows") && viewer.eq_ignore_ascii_case("start") {
+        let mut command = Command::new("cmd");
+        // `start` takes the first quoted argument as the window title.
+        command.args(["/C", "start", ""]).arg(target);
+        command
+    } else {
+        let mut command = Command::new(viewer);
+        command.arg(target);
+        command
+    }
 }
 
 /// Open cellbook.rs in the user's editor.
 /// If a line number is provided, attempts to open at that line.
-fn edit_cellbook(line: Option<u32>) {
+/// Returns an error if the editor could not be started.
+pub(crate) fn edit_cellbook(line: Option<u32>) -> Result<()> {
     restore_terminal();
 
     // Clear screen to minimize flash of terminal history.
@@ -376,5 +1411,10 @@ fn edit_cellbook(line: Option<u32>) {
     }
 
     cmd.arg("cellbook.rs");
-    let _ = cmd.status();
+    cmd.status().map_err(|source| Error::Launch {
+        var: "EDITOR",
+        program: editor,
+        source,
+    })?;
+    Ok(())
 }
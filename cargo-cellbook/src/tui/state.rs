@@ -2,11 +2,20 @@
 
 #![allow(unused)]
 
-use std::collections::HashMap;
-use std::time::Duration;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
 
 use ratatui::widgets::ListState;
 
+use super::config::{Keybindings, RunOnStart, StartMode, ThemeConfig};
+use crate::artifacts::Artifact;
+use crate::clipboard::ClipboardMode;
+use crate::failure::CellFailure;
+use crate::lint::Finding;
+use crate::loader::{CellId, CellInfo};
+use crate::store::{Access, StoreDiff};
+use crate::tasks::{TaskInfo, TaskState};
+
 /// Execution status for a cell.
 #[derive(Clone, Debug, Default, PartialEq)]
 pub enum CellStatus {
@@ -14,7 +23,7 @@ pub enum CellStatus {
     Pending,
     Running,
     Success,
-    Error(String),
+    Error(CellFailure),
 }
 
 /// Build and reload status.
@@ -22,11 +31,48 @@ pub enum CellStatus {
 pub enum BuildStatus {
     #[default]
     Idle,
+    /// A source file changed and a rebuild is about to start.
+    Changed,
     Building,
+    /// A reload waits since this instant for cells still running code from the
+    /// old library to stop.
+    Waiting(Instant),
     Reloading,
     BuildError(String),
 }
 
+/// What a reload in [`BuildStatus::Waiting`] does next.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReloadStep {
+    /// Cells still run the old library's code and the timeout hasn't passed.
+    Wait,
+    /// No cell runs the old library's code any more.
+    Reload,
+    /// The timeout passed, so reload while keeping the old library loaded.
+    Force,
+}
+
+/// Percentage points `+` and `-` resize a pane by.
+const PANE_STEP_PERCENT: u16 = 5;
+
+/// Limits of `cells_pane_percent` and `output_pane_percent` when resizing, so
+/// neither side of a split disappears.
+const CELLS_PERCENT_RANGE: (u16, u16) = (20, 90);
+const OUTPUT_PERCENT_RANGE: (u16, u16) = (20, 80);
+
+/// Pane receiving navigation keys.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Focus {
+    #[default]
+    Cells,
+    Output,
+    Store,
+    Queue,
+    Tasks,
+    Artifacts,
+    Diagnostics,
+}
+
 /// Captured output from a cell execution.
 #[derive(Clone, Debug, Default)]
 pub struct CellOutput {
@@ -34,10 +80,84 @@ pub struct CellOutput {
     pub duration: Duration,
 }
 
+/// Timing of a cell's most recent run.
+#[derive(Clone, Copy, Debug)]
+pub struct LastRun {
+    pub finished: Instant,
+    pub duration: Duration,
+}
+
+/// Answer being typed for a cell's prompt.
+#[derive(Clone, Debug)]
+pub struct PromptInput {
+    /// Id of the prompt, see [`prompt::pending`](crate::prompt::pending).
+    pub id: u64,
+    pub message: String,
+    pub input: String,
+}
+
+/// New name being typed for a store key.
+#[derive(Clone, Debug)]
+pub struct RenameInput {
+    pub key: String,
+    pub input: String,
+}
+
+/// Symbol being looked up in the documentation, with the candidates
+/// found in the selected cell.
+#[derive(Clone, Debug, Default)]
+pub struct DocLookup {
+    pub input: String,
+    pub symbols: Vec<String>,
+    next: usize,
+}
+
+impl DocLookup {
+    /// Replace the input with the next candidate symbol.
+    pub fn cycle(&mut self) {
+        if self.symbols.is_empty() {
+            return;
+        }
+        self.input = self.symbols[self.next % self.symbols.len()].clone();
+        self.next = (self.next + 1) % self.symbols.len();
+    }
+}
+
+/// Builtin fallback pager used when the external pager cannot be started.
+#[derive(Clone, Debug, Default)]
+pub struct Pager {
+    pub title: String,
+    pub content: String,
+    pub scroll: u16,
+}
+
+impl Pager {
+    pub fn new(title: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            content: content.into(),
+            scroll: 0,
+        }
+    }
+
+    pub fn line_count(&self) -> u16 {
+        self.content.lines().count().min(u16::MAX as usize) as u16
+    }
+
+    pub fn scroll_down(&mut self, amount: u16) {
+        let max = self.line_count().saturating_sub(1);
+        self.scroll = self.scroll.saturating_add(amount).min(max);
+    }
+
+    pub fn scroll_up(&mut self, amount: u16) {
+        self.scroll = self.scroll.saturating_sub(amount);
+    }
+}
+
 /// Main application state.
 pub struct App {
-    /// Cell names.
-    pub cells: Vec<String>,
+    /// Visible cells, init first.
+    pub cells: Vec<CellInfo>,
 
     /// Execution status for each cell.
     pub cell_statuses: Vec<CellStatus>,
@@ -45,26 +165,181 @@ pub struct App {
     /// Execution count for each cell.
     pub cell_counts: HashMap<String, u32>,
 
+    /// Cells whose status and output may be out of date: they come from a build
+    /// before the last reload, or a key they loaded has changed since they ran.
+    pub stale_cells: HashSet<CellId>,
+
+    /// Retry number and limit of running cells that failed and are being retried.
+    pub cell_retries: HashMap<CellId, (u32, u32)>,
+
+    /// Keys each cell loaded and stored in its last run.
+    pub cell_access: HashMap<String, Access>,
+
+    /// A source file changed since the last build, so every result may be stale.
+    pub source_changed: bool,
+
     /// List selection state.
     pub list_state: ListState,
 
+    /// Cells re-run automatically after every successful reload.
+    pub watched_cells: HashSet<CellId>,
+    /// Whether `#[cell(hidden)]` cells are listed.
+    pub show_hidden: bool,
+    /// Count typed before a command, as in `5G`.
+    pub count: Option<usize>,
+    /// First `g` of `gg` was pressed.
+    pub pending_jump: bool,
+
+    /// Cells that run at least this long send a desktop notification when they finish.
+    pub notify_after: Option<Duration>,
+
+    /// How long a reload waits for running cells before keeping the old library loaded.
+    pub reload_timeout: Duration,
+
+    /// Cell started most recently, re-run by `run_last`.
+    pub last_run_cell: Option<CellId>,
+
+    /// Cells queued to run once the current execution finishes.
+    pub pending_runs: VecDeque<CellId>,
+
+    /// Queue pane selection state.
+    pub queue_state: ListState,
+
+    /// Background task of each started service cell, until it stops.
+    pub services: HashMap<CellId, u64>,
+
     /// Current build status.
     pub build_status: BuildStatus,
 
+    /// A newer build of the notebook is on disk than the library that is loaded.
+    pub stale_library: bool,
+
     /// Captured output for each cell.
     pub cell_outputs: HashMap<String, CellOutput>,
 
-    /// Context store items.
+    /// When each cell last finished and how long that run took.
+    pub last_runs: HashMap<String, LastRun>,
+
+    /// Store changes made by the last run of each cell.
+    pub store_diffs: HashMap<String, StoreDiff>,
+
+    /// Context store items, sorted by key.
     pub context_items: Vec<(String, String)>,
 
+    /// Store pane selection state.
+    pub store_state: ListState,
+
+    /// Store keys marked for batch operations.
+    pub marked_keys: HashSet<String>,
+
+    /// Store keys that survive clearing the store.
+    pub pinned_keys: HashSet<String>,
+
+    /// Serialized size of each store value in bytes.
+    pub key_sizes: HashMap<String, u64>,
+
+    /// Expiry time of store values stored with a lifetime.
+    pub key_expiries: HashMap<String, Instant>,
+
+    /// Store size limit from `max_store_bytes`, shown next to the total.
+    pub max_store_bytes: Option<u64>,
+
+    /// Resource registry items, sorted by key.
+    pub resource_items: Vec<(String, String)>,
+
+    /// Background tasks spawned by cells.
+    pub tasks: Vec<TaskInfo>,
+
+    /// Tasks pane selection state.
+    pub task_state: ListState,
+
+    /// Files produced by cells.
+    pub artifacts: Vec<Artifact>,
+
+    /// Artifacts pane selection state.
+    pub artifact_state: ListState,
+
+    /// Lint findings in `cellbook.rs` from the latest build.
+    pub lint_findings: Vec<Finding>,
+
+    /// Diagnostics pane selection state.
+    pub lint_state: ListState,
+
+    /// Pane receiving navigation keys.
+    pub focus: Focus,
+
+    /// Percentage of the height taken by the cells, from `cells_pane_percent`.
+    pub cells_percent: u16,
+
+    /// Percentage of the cells pane's width taken by the output pane, `0` when hidden.
+    pub output_percent: u16,
+
+    /// Lines scrolled in the output pane, and the cell they were scrolled for.
+    pub output_scroll: (Option<CellId>, u16),
+
+    /// Whether the store pane is shown, outside zen mode.
+    pub show_store: bool,
+
+    /// Zen mode: the cells and output take the whole screen, without the store
+    /// pane or status bar.
+    pub zen: bool,
+
     /// Whether a cell is currently executing.
     pub executing: bool,
 
+    /// Whether the store is forked from a baseline copy.
+    pub forked: bool,
+
     pub show_timings: bool,
+
+    /// Status indicator glyphs and colors.
+    pub theme: ThemeConfig,
+
+    /// Active keybindings, shown in the status bar help.
+    pub keybindings: Keybindings,
+
+    /// Cells queued at startup and after every reload.
+    pub run_on_start: RunOnStart,
+
+    /// Clear the store before the init cell runs.
+    pub clear_on_run: bool,
+
+    /// Run every cell in a worker process.
+    pub isolate_cells: bool,
+
+    /// How text is copied to the clipboard.
+    pub clipboard: ClipboardMode,
+
+    /// Block other cells until the init cell succeeds.
+    pub init_required: bool,
+
+    /// Transient message shown in the status bar until the next key press.
+    pub notice: Option<String>,
+
+    /// Builtin pager overlay, if open.
+    pub pager: Option<Pager>,
+
+    /// Scratch snippet being typed, if the input line is open.
+    pub scratch_input: Option<String>,
+
+    /// Last submitted scratch snippet, used to prefill the input line.
+    pub last_scratch: String,
+
+    /// Documentation lookup input line, if open.
+    pub doc_lookup: Option<DocLookup>,
+
+    /// Rename input line for the selected store key, if open.
+    pub rename_input: Option<RenameInput>,
+
+    /// Asking whether to clear the store.
+    pub confirming_clear: bool,
+
+    /// Answer to the oldest prompt of a running cell, if one is waiting.
+    pub prompt: Option<PromptInput>,
 }
 
 impl App {
-    pub fn new(cells: Vec<String>, show_timings: bool) -> Self {
+    pub fn new(cells: Vec<CellInfo>, show_timings: bool) -> Self {
         let cell_count = cells.len();
         let mut list_state = ListState::default();
         if cell_count > 0 {
@@ -76,11 +351,63 @@ impl App {
             cell_statuses: vec![CellStatus::Pending; cell_count],
             cell_counts: HashMap::new(),
             list_state,
+            watched_cells: HashSet::new(),
+            stale_cells: HashSet::new(),
+            cell_retries: HashMap::new(),
+            cell_access: HashMap::new(),
+            source_changed: false,
+            show_hidden: false,
+            count: None,
+            pending_jump: false,
+            pending_runs: VecDeque::new(),
+            queue_state: ListState::default(),
             build_status: BuildStatus::Idle,
+            stale_library: false,
             cell_outputs: HashMap::new(),
+            last_runs: HashMap::new(),
+            last_run_cell: None,
+            services: HashMap::new(),
+            notify_after: None,
+            reload_timeout: Duration::from_secs(10),
+            store_diffs: HashMap::new(),
             context_items: Vec::new(),
+            store_state: ListState::default(),
+            marked_keys: HashSet::new(),
+            pinned_keys: HashSet::new(),
+            key_sizes: HashMap::new(),
+            key_expiries: HashMap::new(),
+            max_store_bytes: None,
+            resource_items: Vec::new(),
+            tasks: Vec::new(),
+            task_state: ListState::default(),
+            artifacts: Vec::new(),
+            artifact_state: ListState::default(),
+            lint_findings: Vec::new(),
+            lint_state: ListState::default(),
+            focus: Focus::Cells,
+            cells_percent: 70,
+            output_percent: 40,
+            output_scroll: (None, 0),
+            show_store: true,
+            zen: false,
             executing: false,
+            forked: false,
             show_timings,
+            theme: ThemeConfig::default(),
+            keybindings: Keybindings::default(),
+            run_on_start: RunOnStart::default(),
+            clear_on_run: false,
+            isolate_cells: false,
+            clipboard: ClipboardMode::default(),
+            init_required: false,
+            notice: None,
+            pager: None,
+            scratch_input: None,
+            last_scratch: String::new(),
+            doc_lookup: None,
+            rename_input: None,
+            confirming_clear: false,
+            prompt: None,
         }
     }
 
@@ -93,42 +420,292 @@ impl App {
         *count += 1;
     }
 
-    pub fn selected_cell_index(&self) -> Option<usize> {
-        self.list_state.selected()
+    /// Indices of the cells shown in the list, leaving out hidden cells unless `show_hidden` is set.
+    pub fn visible_rows(&self) -> Vec<usize> {
+        (0..self.cells.len())
+            .filter(|&i| self.show_hidden || !self.cells[i].hidden)
+            .collect()
     }
 
-    pub fn selected_cell_name(&self) -> Option<&str> {
+    pub fn hidden_count(&self) -> usize {
+        self.cells.iter().filter(|c| c.hidden).count()
+    }
+
+    /// Index into `cells` of the selected list row.
+    pub fn selected_cell_index(&self) -> Option<usize> {
         self.list_state
             .selected()
-            .and_then(|i| self.cells.get(i).map(|s| s.as_str()))
+            .and_then(|row| self.visible_rows().get(row).copied())
+    }
+
+    pub fn selected_cell_name(&self) -> Option<&str> {
+        self.selected_cell_index()
+            .and_then(|i| self.cells.get(i).map(|c| c.name.as_str()))
+    }
+
+    pub fn selected_cell_id(&self) -> Option<CellId> {
+        self.selected_cell_index()
+            .and_then(|i| self.cells.get(i).map(|c| c.id))
+    }
+
+    /// Select the row of the cell at `idx`. Returns `false` if the cell isn't listed.
+    pub fn select_cell(&mut self, idx: usize) -> bool {
+        match self.visible_rows().iter().position(|&i| i == idx) {
+            Some(row) => {
+                self.list_state.select(Some(row));
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn select_first(&mut self) {
+        if !self.visible_rows().is_empty() {
+            self.list_state.select(Some(0));
+        }
+    }
+
+    pub fn select_last(&mut self) {
+        let rows = self.visible_rows().len();
+        if rows > 0 {
+            self.list_state.select(Some(rows - 1));
+        }
+    }
+
+    /// Keys typed so far of a count or `gg`, shown in the status bar.
+    pub fn pending_keys(&self) -> String {
+        let mut keys = self.count.map(|n| n.to_string()).unwrap_or_default();
+        if self.pending_jump {
+            keys.push('g');
+        }
+        keys
+    }
+
+    /// Show or hide `#[cell(hidden)]` cells, keeping the selected cell selected if it stays visible.
+    pub fn toggle_hidden(&mut self) {
+        let selected = self.selected_cell_index();
+        self.show_hidden = !self.show_hidden;
+        let rows = self.visible_rows();
+        let row = selected
+            .and_then(|i| rows.iter().position(|&r| r == i))
+            .or_else(|| (!rows.is_empty()).then_some(0));
+        self.list_state.select(row);
+    }
+
+    /// Current list position of a cell.
+    pub fn index_of(&self, id: CellId) -> Option<usize> {
+        self.cells.iter().position(|c| c.id == id)
     }
 
     pub fn select_next(&mut self) {
-        if self.cells.is_empty() {
+        self.select_next_by(1);
+    }
+
+    pub fn select_previous(&mut self) {
+        self.select_previous_by(1);
+    }
+
+    /// Move the selection `steps` rows down, wrapping around the list.
+    pub fn select_next_by(&mut self, steps: usize) {
+        let rows = self.visible_rows().len();
+        if rows == 0 {
             return;
         }
         let i = match self.list_state.selected() {
-            Some(i) => (i + 1) % self.cells.len(),
+            Some(i) => (i + steps % rows) % rows,
             None => 0,
         };
         self.list_state.select(Some(i));
     }
 
-    pub fn select_previous(&mut self) {
-        if self.cells.is_empty() {
+    /// Move the selection `steps` rows up, wrapping around the list.
+    pub fn select_previous_by(&mut self, steps: usize) {
+        let rows = self.visible_rows().len();
+        if rows == 0 {
             return;
         }
         let i = match self.list_state.selected() {
-            Some(i) => {
-                if i == 0 {
-                    self.cells.len() - 1
-                } else {
-                    i - 1
+            Some(i) => (i + rows - steps % rows) % rows,
+            None => 0,
+        };
+        self.list_state.select(Some(i));
+    }
+
+    /// Toggle auto-rerun for the selected cell. The init cell always runs on reload.
+    pub fn toggle_watch(&mut self) {
+        let Some(idx) = self.selected_cell_index().filter(|&i| i > 0) else {
+            return;
+        };
+        let id = self.cells[idx].id;
+        if !self.watched_cells.remove(&id) {
+            self.watched_cells.insert(id);
+        }
+    }
+
+    pub fn is_watched(&self, id: CellId) -> bool {
+        self.watched_cells.contains(&id)
+    }
+
+    /// Next step of a waiting reload while `in_use` cell runs still use the old
+    /// library, or `None` if no reload waits.
+    pub fn waiting_reload(&self, in_use: usize) -> Option<ReloadStep> {
+        let BuildStatus::Waiting(since) = self.build_status else {
+            return None;
+        };
+        Some(if in_use == 0 {
+            ReloadStep::Reload
+        } else if since.elapsed() >= self.reload_timeout {
+            ReloadStep::Force
+        } else {
+            ReloadStep::Wait
+        })
+    }
+
+    /// Whether cells other than init can't run, because `init_required` is set
+    /// and init hasn't succeeded. An init that is still running doesn't block,
+    /// so cells queued behind it run once it succeeds.
+    pub fn blocked_by_init(&self) -> bool {
+        self.init_required
+            && !matches!(
+                self.cell_statuses.first(),
+                Some(CellStatus::Success | CellStatus::Running) | None
+            )
+    }
+
+    /// Whether the init cell failed and blocks the other cells.
+    pub fn init_failed_blocking(&self) -> bool {
+        self.blocked_by_init() && matches!(self.cell_statuses.first(), Some(CellStatus::Error(_)))
+    }
+
+    pub fn is_stale(&self, id: CellId) -> bool {
+        self.source_changed || self.stale_cells.contains(&id)
+    }
+
+    /// A source file changed: every result may be stale until the rebuild finishes.
+    pub fn mark_source_changed(&mut self) {
+        self.source_changed = true;
+        if matches!(self.build_status, BuildStatus::Idle | BuildStatus::BuildError(_)) {
+            self.build_status = BuildStatus::Changed;
+        }
+    }
+
+    /// The change or the rebuild it triggered is over.
+    pub fn clear_source_changed(&mut self) {
+        self.source_changed = false;
+        if matches!(self.build_status, BuildStatus::Changed) {
+            self.build_status = BuildStatus::Idle;
+        }
+    }
+
+    /// Queue watched cells in list order.
+    pub fn queue_watched(&mut self) {
+        let watched: Vec<CellId> = self
+            .cells
+            .iter()
+            .skip(1)
+            .map(|c| c.id)
+            .filter(|id| self.watched_cells.contains(id) && !self.pending_runs.contains(id))
+            .collect();
+        self.pending_runs.extend(watched);
+    }
+
+    /// Queue the `run_on_start` cells followed by watched cells.
+    /// Returns the configured names that don't match any cell.
+    pub fn queue_startup(&mut self) -> Vec<String> {
+        let mut unknown = Vec::new();
+        match &self.run_on_start {
+            RunOnStart::Mode(StartMode::Init) => {
+                if let Some(init) = self.cells.first() {
+                    self.pending_runs.push_back(init.id);
+                }
+            }
+            RunOnStart::Mode(StartMode::None) => {}
+            RunOnStart::Cells(names) => {
+                for name in names {
+                    match self.cells.iter().find(|c| &c.name == name) {
+                        Some(cell) => self.pending_runs.push_back(cell.id),
+                        None => unknown.push(name.clone()),
+                    }
                 }
             }
+        }
+        self.queue_watched();
+        unknown
+    }
+
+    /// Queue a cell behind the running one. Returns its place in the queue,
+    /// or `None` if it is already queued.
+    pub fn enqueue(&mut self, id: CellId) -> Option<usize> {
+        if self.pending_runs.contains(&id) {
+            return None;
+        }
+        self.pending_runs.push_back(id);
+        Some(self.pending_runs.len())
+    }
+
+    /// Place of a cell in the queue, counting from 1.
+    pub fn queue_position(&self, id: CellId) -> Option<usize> {
+        self.pending_runs
+            .iter()
+            .position(|&queued| queued == id)
+            .map(|i| i + 1)
+    }
+
+    /// Keep the queue selection in range as cells leave the queue, and leave
+    /// the Queue pane once it is empty.
+    pub fn refresh_queue(&mut self) {
+        let count = self.pending_runs.len();
+        match self.queue_state.selected() {
+            _ if count == 0 => self.queue_state.select(None),
+            Some(i) if i >= count => self.queue_state.select(Some(count - 1)),
+            None => self.queue_state.select(Some(0)),
+            Some(_) => {}
+        }
+        if count == 0 && self.focus == Focus::Queue {
+            self.focus = Focus::Cells;
+        }
+    }
+
+    pub fn select_next_queued(&mut self) {
+        if self.pending_runs.is_empty() {
+            return;
+        }
+        let i = match self.queue_state.selected() {
+            Some(i) => (i + 1) % self.pending_runs.len(),
             None => 0,
         };
-        self.list_state.select(Some(i));
+        self.queue_state.select(Some(i));
+    }
+
+    pub fn select_previous_queued(&mut self) {
+        if self.pending_runs.is_empty() {
+            return;
+        }
+        let i = match self.queue_state.selected() {
+            Some(0) | None => self.pending_runs.len() - 1,
+            Some(i) => i - 1,
+        };
+        self.queue_state.select(Some(i));
+    }
+
+    /// Remove the selected cell from the queue, returning its id.
+    pub fn dequeue_selected(&mut self) -> Option<CellId> {
+        let id = self.pending_runs.remove(self.queue_state.selected()?)?;
+        self.refresh_queue();
+        Some(id)
+    }
+
+    /// Move the selected queued cell one place earlier, or later with `later`.
+    /// The selection follows it.
+    pub fn move_queued(&mut self, later: bool) {
+        let Some(i) = self.queue_state.selected() else {
+            return;
+        };
+        let target = if later { i + 1 } else { i.wrapping_sub(1) };
+        if target < self.pending_runs.len() {
+            self.pending_runs.swap(i, target);
+            self.queue_state.select(Some(target));
+        }
     }
 
     pub fn store_output(&mut self, cell_name: &str, output: CellOutput) {
@@ -143,49 +720,654 @@ impl App {
         self.cell_outputs.get(cell_name)
     }
 
+    pub fn record_run(&mut self, cell_name: &str, duration: Duration) {
+        let run = LastRun {
+            finished: Instant::now(),
+            duration,
+        };
+        self.last_runs.insert(cell_name.to_string(), run);
+    }
+
+    pub fn last_run(&self, cell_name: &str) -> Option<&LastRun> {
+        self.last_runs.get(cell_name)
+    }
+
+    /// Duration of the cell execution that finished most recently.
+    pub fn latest_run_duration(&self) -> Option<Duration> {
+        self.last_runs
+            .values()
+            .max_by_key(|run| run.finished)
+            .map(|run| run.duration)
+    }
+
+    pub fn store_diff(&self, cell_name: &str) -> Option<&StoreDiff> {
+        self.store_diffs.get(cell_name)
+    }
+
     pub fn has_output(&self, cell_name: &str) -> bool {
         self.cell_outputs.contains_key(cell_name)
     }
 
-    pub fn get_error(&self, idx: usize) -> Option<&str> {
+    pub fn get_error(&self, idx: usize) -> Option<&CellFailure> {
         match self.cell_statuses.get(idx) {
-            Some(CellStatus::Error(msg)) => Some(msg.as_str()),
+            Some(CellStatus::Error(failure)) => Some(failure),
             _ => None,
         }
     }
 
-    pub fn refresh_cells(&mut self, cells: Vec<String>) {
-        let cell_count = cells.len();
+    /// Replace the cells after a reload.
+    ///
+    /// Statuses, outputs and counts of cells that still exist carry over and
+    /// are marked stale until the cell runs again. Those of removed cells are dropped.
+    pub fn refresh_cells(&mut self, cells: Vec<CellInfo>) {
+        let selected = self.selected_cell_id();
+        let selected_row = self.list_state.selected();
+        let offset = self.list_state.offset();
+        let previous: HashMap<CellId, CellStatus> = self
+            .cells
+            .iter()
+            .map(|c| c.id)
+            .zip(std::mem::take(&mut self.cell_statuses))
+            .collect();
+        self.cell_statuses = cells
+            .iter()
+            .map(|c| match previous.get(&c.id) {
+                // Running cells were aborted by the reload.
+                Some(CellStatus::Running) | None => CellStatus::Pending,
+                Some(status) => status.clone(),
+            })
+            .collect();
+
+        let names: HashSet<&str> = cells.iter().map(|c| c.name.as_str()).collect();
+        self.cell_counts.retain(|name, _| names.contains(name.as_str()));
+        self.cell_outputs.retain(|name, _| names.contains(name.as_str()));
+        self.last_runs.retain(|name, _| names.contains(name.as_str()));
+        self.store_diffs.retain(|name, _| names.contains(name.as_str()));
+        self.cell_access.retain(|name, _| names.contains(name.as_str()));
+        self.stale_cells = cells
+            .iter()
+            .zip(&self.cell_statuses)
+            .filter(|(c, status)| **status != CellStatus::Pending || self.cell_outputs.contains_key(&c.name))
+            .map(|(c, _)| c.id)
+            .collect();
+
         self.cells = cells;
-        self.cell_statuses = vec![CellStatus::Pending; cell_count];
-        self.cell_counts.clear();
+        self.pending_runs.clear();
+        // The reload aborted every background task, services included.
+        self.services.clear();
 
-        // Preserve selection if valid.
+        // Follow the selected cell to its new row, shifting the scroll offset
+        // by as much as the row moved so it stays at the same height on screen.
+        if let (Some(id), Some(old_row)) = (selected, selected_row)
+            && let Some(idx) = self.index_of(id)
+            && self.select_cell(idx)
+        {
+            let row = self.list_state.selected().unwrap_or(old_row);
+            *self.list_state.offset_mut() = (offset + row).saturating_sub(old_row).min(row);
+            return;
+        }
+
+        // Otherwise keep the row if it's still valid.
+        let rows = self.visible_rows().len();
+        *self.list_state.offset_mut() = offset.min(rows.saturating_sub(1));
         if let Some(i) = self.list_state.selected() {
-            if i >= cell_count && cell_count > 0 {
-                self.list_state.select(Some(cell_count - 1));
-            } else if cell_count == 0 {
+            if i >= rows && rows > 0 {
+                self.list_state.select(Some(rows - 1));
+            } else if rows == 0 {
                 self.list_state.select(None);
             }
-        } else if cell_count > 0 {
+        } else if rows > 0 {
             self.list_state.select(Some(0));
         }
     }
 
-    pub fn refresh_context(&mut self, items: Vec<(String, String)>) {
+    /// Record the keys a cell run loaded and stored, and mark cells that loaded
+    /// a key the run changed as stale.
+    pub fn record_store_access(&mut self, name: &str, access: Access, diff: &StoreDiff) {
+        let changed: HashSet<&str> = diff.keys().collect();
+        for cell in &self.cells {
+            let reads_changed = self
+                .cell_access
+                .get(&cell.name)
+                .is_some_and(|access| access.reads.iter().any(|key| changed.contains(key.as_str())));
+            if cell.name != name && reads_changed {
+                self.stale_cells.insert(cell.id);
+            }
+        }
+        self.cell_access.insert(name.to_string(), access);
+    }
+
+    pub fn cell_access(&self, cell_name: &str) -> Option<&Access> {
+        self.cell_access.get(cell_name)
+    }
+
+    pub fn set_notice(&mut self, notice: impl Into<String>) {
+        self.notice = Some(notice.into());
+    }
+
+    pub fn open_pager(&mut self, title: impl Into<String>, content: impl Into<String>) {
+        self.pager = Some(Pager::new(title, content));
+    }
+
+    pub fn close_pager(&mut self) {
+        self.pager = None;
+    }
+
+    /// Open the scratch input line, prefilled with the last snippet.
+    pub fn open_scratch(&mut self) {
+        self.scratch_input = Some(self.last_scratch.clone());
+    }
+
+    /// Close the input line and return the snippet to run, if any.
+    pub fn submit_scratch(&mut self) -> Option<String> {
+        let snippet = self.scratch_input.take()?;
+        if snippet.trim().is_empty() {
+            return None;
+        }
+        self.last_scratch = snippet.clone();
+        Some(snippet)
+    }
+
+    /// Open the documentation lookup line, prefilled with the first symbol.
+    pub fn open_doc_lookup(&mut self, symbols: Vec<String>) {
+        let mut lookup = DocLookup {
+            input: String::new(),
+            symbols,
+            next: 0,
+        };
+        lookup.cycle();
+        self.doc_lookup = Some(lookup);
+    }
+
+    /// Close the lookup line and return the symbol to look up, if any.
+    pub fn submit_doc_lookup(&mut self) -> Option<String> {
+        let symbol = self.doc_lookup.take()?.input;
+        let symbol = symbol.trim();
+        (!symbol.is_empty()).then(|| symbol.to_string())
+    }
+
+    /// Show the oldest waiting prompt, keeping what was typed while it is the same one.
+    pub fn sync_prompt(&mut self, pending: Option<(u64, String)>) {
+        match pending {
+            Some((id, _)) if self.prompt.as_ref().is_some_and(|p| p.id == id) => {}
+            Some((id, message)) => {
+                self.prompt = Some(PromptInput {
+                    id,
+                    message,
+                    input: String::new(),
+                })
+            }
+            None => self.prompt = None,
+        }
+    }
+
+    /// Open the rename line for the selected store key, prefilled with its name.
+    pub fn open_rename(&mut self) {
+        if let Some(key) = self.selected_key() {
+            self.rename_input = Some(RenameInput {
+                key: key.to_string(),
+                input: key.to_string(),
+            });
+        }
+    }
+
+    /// Close the rename line and return the key and its new name, if changed.
+    pub fn submit_rename(&mut self) -> Option<(String, String)> {
+        let rename = self.rename_input.take()?;
+        let name = rename.input.trim();
+        (!name.is_empty() && name != rename.key).then(|| (rename.key, name.to_string()))
+    }
+
+    /// Select a store key by name.
+    pub fn select_key(&mut self, key: &str) {
+        if let Some(i) = self.context_items.iter().position(|(k, _)| k == key) {
+            self.store_state.select(Some(i));
+        }
+    }
+
+    pub fn refresh_context(&mut self, mut items: Vec<(String, String)>, pinned_keys: HashSet<String>) {
+        items.sort_by(|a, b| a.0.cmp(&b.0));
+        self.marked_keys.retain(|key| items.iter().any(|(k, _)| k == key));
         self.context_items = items;
+        self.pinned_keys = pinned_keys;
+
+        let count = self.context_items.len();
+        match self.store_state.selected() {
+            _ if count == 0 => self.store_state.select(None),
+            Some(i) if i >= count => self.store_state.select(Some(count - 1)),
+            None => self.store_state.select(Some(0)),
+            Some(_) => {}
+        }
+    }
+
+    pub fn refresh_resources(&mut self, mut items: Vec<(String, String)>) {
+        items.sort_by(|a, b| a.0.cmp(&b.0));
+        self.resource_items = items;
+    }
+
+    /// Focus the next pane, skipping panes that are hidden or empty.
+    pub fn toggle_focus(&mut self) {
+        self.cycle_focus(true);
+    }
+
+    /// Focus the previous pane, skipping panes that are hidden or empty.
+    pub fn focus_previous(&mut self) {
+        self.cycle_focus(false);
+    }
+
+    fn cycle_focus(&mut self, forward: bool) {
+        let panes: Vec<Focus> = [
+            (Focus::Cells, true),
+            (Focus::Output, self.output_percent > 0),
+            (Focus::Store, self.store_shown()),
+            (Focus::Queue, !self.pending_runs.is_empty()),
+            (Focus::Tasks, !self.tasks.is_empty()),
+            (Focus::Artifacts, !self.artifacts.is_empty()),
+            (Focus::Diagnostics, !self.lint_findings.is_empty()),
+        ]
+        .into_iter()
+        .filter_map(|(pane, shown)| shown.then_some(pane))
+        .collect();
+        let current = panes.iter().position(|&pane| pane == self.focus).unwrap_or(0);
+        let next = if forward {
+            (current + 1) % panes.len()
+        } else {
+            (current + panes.len() - 1) % panes.len()
+        };
+        self.focus = panes[next];
+    }
+
+    pub fn store_shown(&self) -> bool {
+        self.show_store && !self.zen
+    }
+
+    /// Whether the status bar is drawn. In zen mode it only comes back while
+    /// an input line or a notice needs it.
+    pub fn status_bar_shown(&self) -> bool {
+        !self.zen
+            || self.notice.is_some()
+            || self.scratch_input.is_some()
+            || self.rename_input.is_some()
+            || self.doc_lookup.is_some()
+    }
+
+    /// Show or hide the store pane, leaving it if it had focus.
+    pub fn toggle_store(&mut self) {
+        self.show_store = !self.show_store;
+        self.leave_hidden_store();
+    }
+
+    /// Enter or leave zen mode.
+    pub fn toggle_zen(&mut self) {
+        self.zen = !self.zen;
+        self.leave_hidden_store();
+    }
+
+    fn leave_hidden_store(&mut self) {
+        if self.focus == Focus::Store && !self.store_shown() {
+            self.focus = Focus::Cells;
+        }
+    }
+
+    /// Grow or shrink the focused pane by `PANE_STEP_PERCENT`. Returns the
+    /// setting that changed with its new value, or `None` at the limit.
+    pub fn resize_focused(&mut self, grow: bool) -> Option<(&'static str, u16)> {
+        let step = |percent: u16, grow: bool, (min, max): (u16, u16)| {
+            let resized = if grow {
+                percent.saturating_add(PANE_STEP_PERCENT)
+            } else {
+                percent.saturating_sub(PANE_STEP_PERCENT)
+            };
+            let resized = resized.clamp(min, max);
+            (resized != percent).then_some(resized)
+        };
+        match self.focus {
+            Focus::Output => {
+                self.output_percent = step(self.output_percent, grow, OUTPUT_PERCENT_RANGE)?;
+                Some(("output_pane_percent", self.output_percent))
+            }
+            // Growing the store shrinks the cells.
+            focus => {
+                let grow = grow != (focus == Focus::Store);
+                self.cells_percent = step(self.cells_percent, grow, CELLS_PERCENT_RANGE)?;
+                Some(("cells_pane_percent", self.cells_percent))
+            }
+        }
+    }
+
+    /// Lines the output pane is scrolled by, back at the top when another cell is selected.
+    pub fn output_scroll(&mut self) -> u16 {
+        let selected = self.selected_cell_index().map(|i| self.cells[i].id);
+        if self.output_scroll.0 != selected {
+            self.output_scroll = (selected, 0);
+        }
+        self.output_scroll.1
+    }
+
+    pub fn scroll_output(&mut self, lines: i16) {
+        let scroll = self.output_scroll();
+        self.output_scroll.1 = scroll.saturating_add_signed(lines);
+    }
+
+    pub fn refresh_tasks(&mut self, tasks: Vec<TaskInfo>) {
+        self.tasks = tasks;
+
+        let count = self.tasks.len();
+        match self.task_state.selected() {
+            _ if count == 0 => self.task_state.select(None),
+            Some(i) if i >= count => self.task_state.select(Some(count - 1)),
+            None => self.task_state.select(Some(0)),
+            Some(_) => {}
+        }
+        if count == 0 && self.focus == Focus::Tasks {
+            self.focus = Focus::Cells;
+        }
+        self.sync_services();
+    }
+
+    /// Show each service cell in the state of its task, forgetting services that stopped.
+    /// A cancelled or dismissed service goes back to pending.
+    fn sync_services(&mut self) {
+        let states: Vec<(CellId, Option<TaskState>)> = self
+            .services
+            .iter()
+            .map(|(&cell, &task)| {
+                let state = self.tasks.iter().find(|t| t.id == task).map(|t| t.state.clone());
+                (cell, state)
+            })
+            .collect();
+        for (cell, state) in states {
+            if state != Some(TaskState::Running) {
+                self.services.remove(&cell);
+            }
+            let Some(idx) = self.index_of(cell) else {
+                continue;
+            };
+            self.cell_statuses[idx] = match state {
+                Some(TaskState::Running) => CellStatus::Running,
+                Some(TaskState::Finished) => CellStatus::Success,
+                Some(TaskState::Failed(e)) => CellStatus::Error(CellFailure::from_message(e)),
+                Some(TaskState::Cancelled) | None => CellStatus::Pending,
+            };
+        }
+    }
+
+    /// Whether the cell is a service that is currently running.
+    pub fn is_running_service(&self, id: CellId) -> bool {
+        self.services.contains_key(&id)
+    }
+
+    pub fn selected_task_id(&self) -> Option<u64> {
+        self.task_state
+            .selected()
+            .and_then(|i| self.tasks.get(i).map(|t| t.id))
+    }
+
+    pub fn select_next_task(&mut self) {
+        if self.tasks.is_empty() {
+            return;
+        }
+        let i = match self.task_state.selected() {
+            Some(i) => (i + 1) % self.tasks.len(),
+            None => 0,
+        };
+        self.task_state.select(Some(i));
+    }
+
+    pub fn select_previous_task(&mut self) {
+        if self.tasks.is_empty() {
+            return;
+        }
+        let i = match self.task_state.selected() {
+            Some(0) | None => self.tasks.len() - 1,
+            Some(i) => i - 1,
+        };
+        self.task_state.select(Some(i));
+    }
+
+    pub fn refresh_artifacts(&mut self, artifacts: Vec<Artifact>) {
+        self.artifacts = artifacts;
+
+        let count = self.artifacts.len();
+        match self.artifact_state.selected() {
+            _ if count == 0 => self.artifact_state.select(None),
+            Some(i) if i >= count => self.artifact_state.select(Some(count - 1)),
+            None => self.artifact_state.select(Some(0)),
+            Some(_) => {}
+        }
+        if count == 0 && self.focus == Focus::Artifacts {
+            self.focus = Focus::Cells;
+        }
+    }
+
+    /// Images produced by a cell, oldest first.
+    pub fn cell_images<'a>(&'a self, cell_name: &'a str) -> impl Iterator<Item = &'a Artifact> + 'a {
+        self.artifacts
+            .iter()
+            .filter(move |a| a.cell.as_deref() == Some(cell_name) && a.mime.starts_with("image/"))
+    }
+
+    pub fn selected_artifact(&self) -> Option<&Artifact> {
+        self.artifact_state.selected().and_then(|i| self.artifacts.get(i))
+    }
+
+    pub fn select_next_artifact(&mut self) {
+        if self.artifacts.is_empty() {
+            return;
+        }
+        let i = match self.artifact_state.selected() {
+            Some(i) => (i + 1) % self.artifacts.len(),
+            None => 0,
+        };
+        self.artifact_state.select(Some(i));
+    }
+
+    pub fn select_previous_artifact(&mut self) {
+        if self.artifacts.is_empty() {
+            return;
+        }
+        let i = match self.artifact_state.selected() {
+            Some(0) | None => self.artifacts.len() - 1,
+            Some(i) => i - 1,
+        };
+        self.artifact_state.select(Some(i));
+    }
+
+    pub fn refresh_lint(&mut self, findings: Vec<Finding>) {
+        self.lint_findings = findings;
+
+        let count = self.lint_findings.len();
+        match self.lint_state.selected() {
+            _ if count == 0 => self.lint_state.select(None),
+            Some(i) if i >= count => self.lint_state.select(Some(count - 1)),
+            None => self.lint_state.select(Some(0)),
+            Some(_) => {}
+        }
+        if count == 0 && self.focus == Focus::Diagnostics {
+            self.focus = Focus::Cells;
+        }
+    }
+
+    pub fn selected_finding(&self) -> Option<&Finding> {
+        self.lint_state.selected().and_then(|i| self.lint_findings.get(i))
+    }
+
+    pub fn select_next_finding(&mut self) {
+        if self.lint_findings.is_empty() {
+            return;
+        }
+        let i = match self.lint_state.selected() {
+            Some(i) => (i + 1) % self.lint_findings.len(),
+            None => 0,
+        };
+        self.lint_state.select(Some(i));
+    }
+
+    pub fn select_previous_finding(&mut self) {
+        if self.lint_findings.is_empty() {
+            return;
+        }
+        let i = match self.lint_state.selected() {
+            Some(0) | None => self.lint_findings.len() - 1,
+            Some(i) => i - 1,
+        };
+        self.lint_state.select(Some(i));
+    }
+
+    pub fn selected_key(&self) -> Option<&str> {
+        self.store_state
+            .selected()
+            .and_then(|i| self.context_items.get(i).map(|(k, _)| k.as_str()))
+    }
+
+    pub fn select_next_key(&mut self) {
+        if self.context_items.is_empty() {
+            return;
+        }
+        let i = match self.store_state.selected() {
+            Some(i) => (i + 1) % self.context_items.len(),
+            None => 0,
+        };
+        self.store_state.select(Some(i));
+    }
+
+    pub fn select_previous_key(&mut self) {
+        if self.context_items.is_empty() {
+            return;
+        }
+        let i = match self.store_state.selected() {
+            Some(0) | None => self.context_items.len() - 1,
+            Some(i) => i - 1,
+        };
+        self.store_state.select(Some(i));
+    }
+
+    /// Toggle the batch mark on the selected key.
+    pub fn toggle_mark(&mut self) {
+        let Some(key) = self.selected_key().map(str::to_string) else {
+            return;
+        };
+        if !self.marked_keys.remove(&key) {
+            self.marked_keys.insert(key);
+        }
+    }
+
+    /// Keys targeted by a batch operation: marked keys, or the selected key if none are marked.
+    pub fn target_keys(&self) -> Vec<String> {
+        if self.marked_keys.is_empty() {
+            return self
+                .selected_key()
+                .map(|k| vec![k.to_string()])
+                .unwrap_or_default();
+        }
+        let mut keys: Vec<String> = self.marked_keys.iter().cloned().collect();
+        keys.sort();
+        keys
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashSet;
+    use std::path::PathBuf;
     use std::time::Duration;
 
-    use super::{App, CellOutput};
+    use super::{App, BuildStatus, CellOutput, CellStatus, Focus, Pager, ReloadStep, RunOnStart, StartMode};
+    use crate::artifacts::Artifact;
+    use crate::failure::CellFailure;
+    use crate::lint::Finding;
+    use crate::loader::CellInfo;
+    use crate::store::{Access, StoreDiff};
+    use crate::tasks::{TaskInfo, TaskState};
+
+    fn cells(names: &[&str]) -> Vec<CellInfo> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| CellInfo::new("test", *name, i as u32 + 1))
+            .collect()
+    }
+
+    fn items(keys: &[&str]) -> Vec<(String, String)> {
+        keys.iter().map(|k| (k.to_string(), "i32".to_string())).collect()
+    }
+
+    #[test]
+    fn counted_moves_wrap_without_stepping_each_row() {
+        let mut app = App::new(cells(&["init", "load", "plot"]), false);
+        app.select_next_by(4);
+        assert_eq!(app.selected_cell_name(), Some("load"));
+        app.select_previous_by(2);
+        assert_eq!(app.selected_cell_name(), Some("plot"));
+        // A count typed as a long run of digits saturates, and is a whole number
+        // of turns here, so the selection stays put.
+        app.select_next_by(usize::MAX);
+        assert_eq!(app.selected_cell_name(), Some("plot"));
+        app.select_previous_by(usize::MAX - 1);
+        assert_eq!(app.selected_cell_name(), Some("init"));
+    }
+
+    #[test]
+    fn hidden_cells_are_skipped_until_revealed() {
+        let mut list = cells(&["init", "helper", "plot"]);
+        list[1].hidden = true;
+        let mut app = App::new(list, false);
+        assert_eq!(app.visible_rows(), vec![0, 2]);
+
+        app.select_next();
+        assert_eq!(app.selected_cell_name(), Some("plot"));
+        app.select_next();
+        assert_eq!(app.selected_cell_name(), Some("init"));
+
+        app.select_previous();
+        app.toggle_hidden();
+        assert_eq!(app.visible_rows(), vec![0, 1, 2]);
+        assert_eq!(app.selected_cell_name(), Some("plot"));
+
+        app.select_previous();
+        assert_eq!(app.selected_cell_name(), Some("helper"));
+        app.toggle_hidden();
+        assert_eq!(app.selected_cell_name(), Some("init"));
+    }
+
+    #[test]
+    fn waiting_reload_goes_ahead_when_unused_and_forces_after_the_timeout() {
+        let mut app = App::new(cells(&["init"]), false);
+        assert_eq!(app.waiting_reload(1), None);
+
+        app.reload_timeout = Duration::from_secs(10);
+        app.build_status = BuildStatus::Waiting(std::time::Instant::now());
+        assert_eq!(app.waiting_reload(1), Some(ReloadStep::Wait));
+        assert_eq!(app.waiting_reload(0), Some(ReloadStep::Reload));
+
+        app.reload_timeout = Duration::ZERO;
+        assert_eq!(app.waiting_reload(1), Some(ReloadStep::Force));
+        assert_eq!(app.waiting_reload(0), Some(ReloadStep::Reload));
+    }
+
+    #[test]
+    fn select_cell_by_number_skips_unlisted_cells() {
+        let mut list = cells(&["init", "helper", "plot", "report"]);
+        list[1].hidden = true;
+        let mut app = App::new(list, false);
+
+        assert!(app.select_cell(3));
+        assert_eq!(app.selected_cell_name(), Some("report"));
+        assert!(!app.select_cell(1));
+        assert_eq!(app.selected_cell_name(), Some("report"));
+        app.select_first();
+        assert_eq!(app.selected_cell_name(), Some("init"));
+        app.select_last();
+        assert_eq!(app.selected_cell_name(), Some("report"));
+
+        app.count = Some(12);
+        app.pending_jump = true;
+        assert_eq!(app.pending_keys(), "12g");
+    }
 
     #[test]
     fn empty_output_is_not_marked_as_output() {
-        let mut app = App::new(vec!["init".to_string()], false);
+        let mut app = App::new(cells(&["init"]), false);
         app.store_output(
             "init",
             CellOutput {
@@ -198,7 +1380,7 @@ mod tests {
 
     #[test]
     fn non_empty_output_is_marked_as_output() {
-        let mut app = App::new(vec!["init".to_string()], false);
+        let mut app = App::new(cells(&["init"]), false);
         app.store_output(
             "init",
             CellOutput {
@@ -208,4 +1390,370 @@ mod tests {
         );
         assert!(app.has_output("init"));
     }
+
+    #[test]
+    fn pager_scroll_is_clamped_to_content() {
+        let mut pager = Pager::new("Output", "a\nb\nc");
+        pager.scroll_down(10);
+        assert_eq!(pager.scroll, 2);
+        pager.scroll_up(1);
+        assert_eq!(pager.scroll, 1);
+        pager.scroll_up(10);
+        assert_eq!(pager.scroll, 0);
+    }
+
+    #[test]
+    fn target_keys_prefers_marked_keys() {
+        let mut app = App::new(cells(&["init"]), false);
+        app.refresh_context(items(&["b", "a", "c"]), HashSet::new());
+        assert_eq!(app.target_keys(), vec!["a"]);
+
+        app.select_next_key();
+        app.toggle_mark();
+        app.select_next_key();
+        app.toggle_mark();
+        assert_eq!(app.target_keys(), vec!["b", "c"]);
+    }
+
+    #[test]
+    fn refresh_context_drops_marks_for_removed_keys() {
+        let mut app = App::new(cells(&["init"]), false);
+        app.refresh_context(items(&["a", "b"]), HashSet::new());
+        app.toggle_mark();
+        app.select_next_key();
+        app.toggle_mark();
+
+        app.refresh_context(items(&["a"]), HashSet::new());
+        assert_eq!(app.target_keys(), vec!["a"]);
+        assert_eq!(app.store_state.selected(), Some(0));
+    }
+
+    #[test]
+    fn queue_startup_follows_run_on_start() {
+        let mut app = App::new(cells(&["init", "a", "b"]), false);
+        assert!(app.queue_startup().is_empty());
+        assert_eq!(app.pending_runs, [app.cells[0].id]);
+
+        app.pending_runs.clear();
+        app.run_on_start = RunOnStart::Mode(StartMode::None);
+        assert!(app.queue_startup().is_empty());
+        assert!(app.pending_runs.is_empty());
+
+        app.run_on_start = RunOnStart::Cells(vec!["b".into(), "missing".into(), "a".into()]);
+        app.select_next();
+        app.toggle_watch();
+        assert_eq!(app.queue_startup(), ["missing"]);
+        assert_eq!(app.pending_runs, [app.cells[2].id, app.cells[1].id]);
+    }
+
+    #[test]
+    fn queue_watched_follows_list_order_and_skips_init() {
+        let mut app = App::new(cells(&["init", "a", "b", "c"]), false);
+        app.select_next();
+        app.select_next();
+        app.select_next();
+        app.toggle_watch();
+        app.select_previous();
+        app.select_previous();
+        app.toggle_watch();
+        app.select_previous();
+        app.toggle_watch();
+
+        app.queue_watched();
+        assert_eq!(app.pending_runs, [app.cells[1].id, app.cells[3].id]);
+    }
+
+    #[test]
+    fn cell_ids_follow_cells_when_reload_inserts_above() {
+        let mut app = App::new(cells(&["init", "a", "b"]), false);
+        app.select_next();
+        app.select_next();
+        app.toggle_watch();
+        let b = app.selected_cell_id().unwrap();
+
+        app.refresh_cells(cells(&["init", "new", "a", "b"]));
+        assert_eq!(app.index_of(b), Some(3));
+        app.queue_watched();
+        assert_eq!(app.pending_runs, [b]);
+    }
+
+    fn reading(key: &str) -> Access {
+        Access {
+            reads: [key.to_string()].into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn init_required_blocks_until_init_succeeds() {
+        let mut app = App::new(cells(&["init", "a"]), false);
+        assert!(!app.blocked_by_init());
+
+        app.init_required = true;
+        assert!(app.blocked_by_init());
+        assert!(!app.init_failed_blocking());
+
+        app.cell_statuses[0] = CellStatus::Running;
+        assert!(!app.blocked_by_init());
+        app.cell_statuses[0] = CellStatus::Error(CellFailure::from_message("boom"));
+        assert!(app.init_failed_blocking());
+        app.cell_statuses[0] = CellStatus::Success;
+        assert!(!app.blocked_by_init());
+    }
+
+    #[test]
+    fn changed_keys_mark_cells_that_loaded_them_stale() {
+        let mut app = App::new(cells(&["init", "load", "plot", "other"]), false);
+        let (plot, other) = (app.cells[2].id, app.cells[3].id);
+        app.record_store_access("plot", reading("prices"), &StoreDiff::default());
+        app.record_store_access("other", reading("config"), &StoreDiff::default());
+
+        let diff = StoreDiff {
+            changed: vec![(
+                "prices".to_string(),
+                "Vec<f64>".to_string(),
+                "Vec<f64>".to_string(),
+            )],
+            ..Default::default()
+        };
+        app.record_store_access("load", Access::default(), &diff);
+        assert!(app.is_stale(plot));
+        assert!(!app.is_stale(other));
+    }
+
+    #[test]
+    fn source_change_marks_all_cells_stale_until_settled() {
+        let mut app = App::new(cells(&["init", "a"]), false);
+        let a = app.cells[1].id;
+        assert!(!app.is_stale(a));
+
+        app.mark_source_changed();
+        assert!(app.is_stale(a));
+        assert!(matches!(app.build_status, BuildStatus::Changed));
+
+        app.clear_source_changed();
+        assert!(!app.is_stale(a));
+        assert!(matches!(app.build_status, BuildStatus::Idle));
+
+        // A build already underway keeps its status.
+        app.build_status = BuildStatus::Building;
+        app.mark_source_changed();
+        assert!(matches!(app.build_status, BuildStatus::Building));
+    }
+
+    #[test]
+    fn reload_keeps_selected_cell_and_scroll_position() {
+        let mut app = App::new(cells(&["init", "a", "b", "c", "d"]), false);
+        app.select_cell(3);
+        *app.list_state.offset_mut() = 2;
+
+        app.refresh_cells(cells(&["init", "new", "a", "b", "c", "d"]));
+        assert_eq!(app.selected_cell_index(), Some(4));
+        assert_eq!(app.list_state.offset(), 3);
+
+        app.refresh_cells(cells(&["c", "d"]));
+        assert_eq!(app.selected_cell_index(), Some(0));
+        assert_eq!(app.list_state.offset(), 0);
+
+        // A removed cell leaves the selection on the same row.
+        app.select_cell(1);
+        app.refresh_cells(cells(&["c", "e"]));
+        assert_eq!(app.list_state.selected(), Some(1));
+    }
+
+    #[test]
+    fn reload_keeps_results_of_remaining_cells_as_stale() {
+        let mut app = App::new(cells(&["init", "a", "b"]), false);
+        app.cell_statuses[1] = CellStatus::Success;
+        app.cell_statuses[2] = CellStatus::Running;
+        app.increment_count("a");
+        app.increment_count("b");
+        app.store_output(
+            "b",
+            CellOutput {
+                stdout: "done\n".to_string(),
+                duration: Duration::from_millis(1),
+            },
+        );
+        let a = app.cells[1].id;
+
+        app.refresh_cells(cells(&["init", "new", "a"]));
+        assert_eq!(app.cell_statuses[2], CellStatus::Success);
+        assert_eq!(app.cell_statuses[1], CellStatus::Pending);
+        assert_eq!(app.get_count("a"), 1);
+        assert_eq!(app.get_count("b"), 0);
+        assert!(!app.has_output("b"));
+        assert!(app.is_stale(a));
+        assert!(!app.is_stale(app.cells[1].id));
+    }
+
+    #[test]
+    fn toggle_focus_skips_empty_panes() {
+        let mut app = App::new(cells(&["init"]), false);
+        app.toggle_focus();
+        assert_eq!(app.focus, Focus::Output);
+        app.toggle_focus();
+        assert_eq!(app.focus, Focus::Store);
+        app.toggle_focus();
+        assert_eq!(app.focus, Focus::Cells);
+        app.focus_previous();
+        assert_eq!(app.focus, Focus::Store);
+
+        app.focus = Focus::Cells;
+        app.output_percent = 0;
+
+        app.refresh_artifacts(vec![Artifact {
+            path: "/tmp/plot.svg".into(),
+            mime: "image/svg+xml".to_string(),
+            cell: Some("plot".to_string()),
+            temporary: true,
+        }]);
+        app.toggle_focus();
+        app.toggle_focus();
+        assert_eq!(app.focus, Focus::Artifacts);
+
+        app.refresh_artifacts(Vec::new());
+        assert_eq!(app.focus, Focus::Cells);
+    }
+
+    #[test]
+    fn diagnostics_pane_is_focusable_while_there_are_findings() {
+        let mut app = App::new(cells(&["init"]), false);
+        app.output_percent = 0;
+        app.show_store = false;
+        let finding = |line: u32| Finding {
+            line,
+            cell: None,
+            message: "no #[init] function".to_string(),
+        };
+
+        app.refresh_lint(vec![finding(1), finding(7)]);
+        app.toggle_focus();
+        assert_eq!(app.focus, Focus::Diagnostics);
+        app.select_previous_finding();
+        assert_eq!(app.selected_finding().map(|f| f.line), Some(7));
+
+        app.refresh_lint(vec![finding(1)]);
+        assert_eq!(app.selected_finding().map(|f| f.line), Some(1));
+        app.refresh_lint(Vec::new());
+        assert_eq!(app.focus, Focus::Cells);
+        assert!(app.selected_finding().is_none());
+    }
+
+    #[test]
+    fn hiding_the_store_moves_focus_off_it() {
+        let mut app = App::new(cells(&["init"]), false);
+        app.output_percent = 0;
+        app.focus = Focus::Store;
+        app.toggle_zen();
+        assert_eq!(app.focus, Focus::Cells);
+        assert!(!app.status_bar_shown());
+        app.toggle_focus();
+        assert_eq!(app.focus, Focus::Cells);
+
+        app.toggle_zen();
+        app.toggle_store();
+        assert!(!app.store_shown());
+        assert!(app.status_bar_shown());
+        app.toggle_store();
+        app.toggle_focus();
+        assert_eq!(app.focus, Focus::Store);
+    }
+
+    #[test]
+    fn resize_focused_grows_the_focused_side() {
+        let mut app = App::new(cells(&["init"]), false);
+        assert_eq!(app.resize_focused(true), Some(("cells_pane_percent", 75)));
+        app.focus = Focus::Store;
+        assert_eq!(app.resize_focused(true), Some(("cells_pane_percent", 70)));
+        app.focus = Focus::Output;
+        assert_eq!(app.resize_focused(false), Some(("output_pane_percent", 35)));
+
+        app.output_percent = 80;
+        assert_eq!(app.resize_focused(true), None);
+        assert_eq!(app.output_percent, 80);
+    }
+
+    #[test]
+    fn queue_reorders_and_removes_cells() {
+        let mut app = App::new(cells(&["init", "a", "b", "c"]), false);
+        let [a, b, c] = [1, 2, 3].map(|i| app.cells[i].id);
+        assert_eq!(app.enqueue(a), Some(1));
+        assert_eq!(app.enqueue(b), Some(2));
+        assert_eq!(app.enqueue(a), None);
+        assert_eq!(app.enqueue(c), Some(3));
+
+        app.refresh_queue();
+        app.focus = Focus::Queue;
+        app.select_previous_queued();
+        app.move_queued(false);
+        assert_eq!(app.pending_runs, [a, c, b]);
+        assert_eq!(app.queue_position(c), Some(2));
+        app.move_queued(false);
+        app.move_queued(false);
+        assert_eq!(app.pending_runs, [c, a, b]);
+
+        app.select_next_queued();
+        assert_eq!(app.dequeue_selected(), Some(a));
+        assert_eq!(app.pending_runs, [c, b]);
+
+        app.pending_runs.clear();
+        app.refresh_queue();
+        assert_eq!(app.focus, Focus::Cells);
+        assert_eq!(app.dequeue_selected(), None);
+    }
+
+    #[test]
+    fn cell_images_lists_images_of_that_cell_only() {
+        let artifact = |path: &str, mime: &str, cell: &str| Artifact {
+            path: path.into(),
+            mime: mime.to_string(),
+            cell: Some(cell.to_string()),
+            temporary: true,
+        };
+        let mut app = App::new(cells(&["init", "plot", "report"]), false);
+        app.refresh_artifacts(vec![
+            artifact("/tmp/a.png", "image/png", "plot"),
+            artifact("/tmp/report.csv", "text/csv", "plot"),
+            artifact("/tmp/b.svg", "image/svg+xml", "report"),
+            artifact("/tmp/c.svg", "image/svg+xml", "plot"),
+        ]);
+
+        let paths: Vec<_> = app.cell_images("plot").map(|a| a.path.clone()).collect();
+        assert_eq!(
+            paths,
+            vec![PathBuf::from("/tmp/a.png"), PathBuf::from("/tmp/c.svg")]
+        );
+        assert_eq!(app.cell_images("init").count(), 0);
+    }
+
+    #[test]
+    fn service_cells_follow_their_task() {
+        let task = |id: u64, state: TaskState| TaskInfo {
+            id,
+            name: "serve".to_string(),
+            elapsed: Duration::ZERO,
+            state,
+        };
+        let mut app = App::new(cells(&["init", "serve", "load"]), false);
+        let serve = app.cells[1].id;
+        app.services.insert(serve, 7);
+        app.cell_statuses[1] = CellStatus::Running;
+
+        app.refresh_tasks(vec![task(7, TaskState::Running)]);
+        assert!(app.is_running_service(serve));
+        assert_eq!(app.cell_statuses[1], CellStatus::Running);
+
+        app.refresh_tasks(vec![task(7, TaskState::Failed("port in use".to_string()))]);
+        assert!(!app.is_running_service(serve));
+        assert!(matches!(&app.cell_statuses[1], CellStatus::Error(e) if e.message == "port in use"));
+
+        // A stopped service stays as it was once its task is dismissed.
+        app.refresh_tasks(Vec::new());
+        assert!(matches!(app.cell_statuses[1], CellStatus::Error(_)));
+
+        app.services.insert(serve, 8);
+        app.refresh_tasks(vec![task(8, TaskState::Cancelled)]);
+        assert_eq!(app.cell_statuses[1], CellStatus::Pending);
+    }
 }
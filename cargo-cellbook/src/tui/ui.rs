@@ -1,91 +1,359 @@
 //! TUI rendering.
 
+use std::time::{Duration, Instant};
+
 use ratatui::Frame;
 use ratatui::layout::{Alignment, Constraint, Direction, Layout, Rect};
-use ratatui::style::{Color, Style};
+use ratatui::style::{Color, Modifier, Style};
 use ratatui::text::{Line, Span};
-use ratatui::widgets::{Block, Borders, Clear, List, ListItem, Paragraph, Wrap};
+use ratatui::widgets::{Block, Borders, Clear, List, ListItem, Paragraph};
 
-use super::state::{App, BuildStatus, CellStatus};
+use super::config::{KeyBinding, Keybindings, ThemeConfig};
+use super::state::{App, BuildStatus, CellStatus, Focus};
+use crate::runs;
+use crate::store::{self, Provenance};
+use crate::tasks::TaskState;
 
 /// Render the entire UI.
 pub fn render(frame: &mut Frame, app: &mut App) {
     // Clear previous frame content so stale characters don't persist when indicator widths change.
     frame.render_widget(Clear, frame.area());
 
+    // Hiding the store or, in zen mode, the status bar gives the cells their rows.
+    let (cells, context) = if app.store_shown() {
+        (
+            Constraint::Percentage(app.cells_percent),
+            Constraint::Percentage(100 - app.cells_percent.min(100)),
+        )
+    } else {
+        (Constraint::Fill(1), Constraint::Length(0))
+    };
+    let status_bar = Constraint::Length(app.status_bar_shown() as u16);
     let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([cells, context, status_bar])
+        .split(frame.area());
+
+    // The selected cell's output is shown beside the cells unless turned off.
+    let (cells_area, output_area) = if app.output_percent == 0 {
+        (chunks[0], None)
+    } else {
+        let split = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Percentage(100 - app.output_percent.min(100)),
+                Constraint::Percentage(app.output_percent),
+            ])
+            .split(chunks[0]);
+        (split[0], Some(split[1]))
+    };
+
+    // The queue, tasks, artifacts and diagnostics take a slice of the cells pane only while any exist.
+    let pane_height = |len: usize| if len == 0 { 0 } else { len.min(5) as u16 + 1 };
+    let split = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Ratio(5, 7), // Cells
-            Constraint::Ratio(2, 7), // Context
-            Constraint::Length(1),   // Status bar
+            Constraint::Fill(1),
+            Constraint::Length(pane_height(app.pending_runs.len())),
+            Constraint::Length(pane_height(app.tasks.len())),
+            Constraint::Length(pane_height(app.artifacts.len())),
+            Constraint::Length(pane_height(app.lint_findings.len())),
         ])
-        .split(frame.area());
+        .split(cells_area);
 
-    render_cells(frame, app, chunks[0]);
-    render_context(frame, app, chunks[1]);
-    render_status_bar(frame, app, chunks[2]);
+    render_cells(frame, app, split[0]);
+    if !app.pending_runs.is_empty() {
+        render_queue(frame, app, split[1]);
+    }
+    if !app.tasks.is_empty() {
+        render_tasks(frame, app, split[2]);
+    }
+    if !app.artifacts.is_empty() {
+        render_artifacts(frame, app, split[3]);
+    }
+    if !app.lint_findings.is_empty() {
+        render_diagnostics(frame, app, split[4]);
+    }
+    if let Some(area) = output_area {
+        render_output(frame, app, area);
+    }
+    // Resources are shown beside the store to keep them visibly separate from stored values.
+    if app.store_shown() && app.resource_items.is_empty() {
+        render_context(frame, app, chunks[1]);
+    } else if app.store_shown() {
+        let split = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Ratio(2, 3), Constraint::Ratio(1, 3)])
+            .split(chunks[1]);
+        render_context(frame, app, split[0]);
+        render_resources(frame, app, split[1]);
+    }
+    if app.status_bar_shown() {
+        render_status_bar(frame, app, chunks[2]);
+    }
+
+    if app.pager.is_some() {
+        let area = Rect {
+            height: chunks[0].height + chunks[1].height,
+            ..chunks[0]
+        };
+        render_pager(frame, app, area);
+    }
+    if app.confirming_clear {
+        render_confirm_clear(frame, app, chunks[0]);
+    }
+    if app.prompt.is_some() {
+        render_prompt(frame, app, chunks[0]);
+    }
+}
+
+/// Popup for a cell's prompt with the answer being typed, centered over `area`.
+fn render_prompt(frame: &mut Frame, app: &App, area: Rect) {
+    let Some(prompt) = &app.prompt else {
+        return;
+    };
+    let mut lines: Vec<Line> = prompt.message.lines().map(Line::raw).collect();
+    lines.push(Line::raw(""));
+    lines.push(Line::from(vec![
+        Span::styled("> ", Style::default().fg(Color::Cyan)),
+        Span::raw(prompt.input.as_str()),
+        Span::styled("_", Style::default().add_modifier(Modifier::SLOW_BLINK)),
+    ]));
+    lines.push(Line::raw(""));
+    lines.push(Line::styled(
+        "[Enter] Answer  [Esc] Cancel",
+        Style::default().fg(Color::DarkGray),
+    ));
+
+    let width = 60.min(area.width);
+    let height = (lines.len() as u16 + 2).min(area.height);
+    let popup = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+    let paragraph = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Cyan))
+            .title("Input "),
+    );
+    frame.render_widget(Clear, popup);
+    frame.render_widget(paragraph, popup);
+}
+
+/// Popup asking whether to clear the store, centered over `area`.
+fn render_confirm_clear(frame: &mut Frame, app: &App, area: Rect) {
+    let count = app
+        .context_items
+        .iter()
+        .filter(|(key, _)| !app.pinned_keys.contains(key))
+        .count();
+    let mut lines = vec![Line::from(format!("Clear {} key(s) from the store?", count))];
+    if !app.pinned_keys.is_empty() {
+        lines.push(Line::styled(
+            "Pinned keys are kept.",
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+    lines.push(Line::raw(""));
+    lines.push(Line::styled(
+        "[y] Clear  [n] Cancel",
+        Style::default().fg(Color::DarkGray),
+    ));
+
+    let width = 40.min(area.width);
+    let height = (lines.len() as u16 + 2).min(area.height);
+    let popup = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+    let paragraph = Paragraph::new(lines).alignment(Alignment::Center).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Yellow))
+            .title("Clear store "),
+    );
+    frame.render_widget(Clear, popup);
+    frame.render_widget(paragraph, popup);
+}
+
+fn render_pager(frame: &mut Frame, app: &App, area: Rect) {
+    let Some(pager) = &app.pager else {
+        return;
+    };
+
+    let paragraph = Paragraph::new(pager.content.as_str())
+        .block(
+            Block::default()
+                .borders(Borders::TOP)
+                .border_style(Style::default().fg(Color::White))
+                .title(format!("{} ", pager.title)),
+        )
+        .scroll((pager.scroll, 0));
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(paragraph, area);
 }
 
 fn render_cells(frame: &mut Frame, app: &mut App, area: Rect) {
+    // The selected cell's function name and description, when it has a display name or description,
+    // followed by the file names of the images it produced.
+    let about = app
+        .selected_cell_index()
+        .and_then(|i| app.cells.get(i))
+        .and_then(|cell| {
+            let mut parts = Vec::new();
+            if cell.title.is_some() || cell.description.is_some() {
+                parts.push(format!("fn {}", cell.name));
+            }
+            if let Some(description) = &cell.description {
+                parts.push(description.clone());
+            }
+            let images = image_names(app, &cell.name);
+            if !images.is_empty() {
+                parts.push(format!(
+                    "images: {} [{}] open latest",
+                    images,
+                    app.keybindings.open_image.label()
+                ));
+            }
+            (!parts.is_empty()).then(|| parts.join(" · "))
+        });
+    let area = match about {
+        Some(about) => {
+            let split = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Fill(1), Constraint::Length(1)])
+                .split(area);
+            let line = Paragraph::new(Span::styled(about, Style::default().fg(Color::DarkGray)));
+            frame.render_widget(line, split[1]);
+            split[0]
+        }
+        None => area,
+    };
+
+    // Banner explaining why the other cells won't run.
+    let area = if app.init_failed_blocking() {
+        let split = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(1), Constraint::Fill(1)])
+            .split(area);
+        let init = app.cells.first().map_or("init", |c| c.name.as_str());
+        let banner = format!(
+            "{} failed, other cells are blocked until it succeeds ([{}] shows the error)",
+            init,
+            app.keybindings.view_error.label()
+        );
+        let line = Paragraph::new(Span::styled(
+            banner,
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        ));
+        frame.render_widget(line, split[0]);
+        split[1]
+    } else {
+        area
+    };
+
     let inner_width = area.width as usize;
+    let blocked = app.init_failed_blocking();
 
     let items: Vec<ListItem> = app
-        .cells
-        .iter()
-        .enumerate()
-        .map(|(i, name)| {
+        .visible_rows()
+        .into_iter()
+        .map(|i| {
+            let cell = &app.cells[i];
+            let name = cell.name.as_str();
             let cell_num = format!("[{}] ", i);
 
             // Count indicator.
             let count = app.get_count(name);
-            let count_span = if i == 0 {
-                Span::styled(format!("[{}]", count), Style::default().fg(Color::Cyan))
+            let count_style = if i == 0 {
+                app.theme.color(Color::Cyan)
             } else if count == 0 {
-                Span::styled(format!("[{}]", count), Style::default().fg(Color::DarkGray))
+                app.theme.color(Color::DarkGray)
             } else {
-                Span::styled(format!("[{}]", count), Style::default().fg(Color::Yellow))
+                app.theme.color(Color::Yellow)
             };
+            let count_span = Span::styled(format!("[{}]", count), count_style);
 
             // Output indicator.
-            let output_span = if app.has_output(name) {
-                Span::styled("[output]", Style::default().fg(Color::Blue))
+            let stale = app.is_stale(cell.id);
+            let output_span = if app.has_output(name) && !stale {
+                Span::styled("[output]", app.theme.color(Color::Blue))
+            } else if app.has_output(name) {
+                Span::styled("[output]", app.theme.color(Color::DarkGray))
             } else {
-                Span::styled("[none]", Style::default().fg(Color::DarkGray))
+                Span::styled("[none]", app.theme.color(Color::DarkGray))
             };
 
-            // Status indicator.
-            let status_span = match &app.cell_statuses[i] {
-                CellStatus::Pending => Span::styled("[none]", Style::default().fg(Color::DarkGray)),
-                CellStatus::Running => Span::styled("[running]", Style::default().fg(Color::Yellow)),
-                CellStatus::Success => Span::styled("[success]", Style::default().fg(Color::Green)),
-                CellStatus::Error(_) => Span::styled("[error]", Style::default().fg(Color::Red)),
+            // Status indicator, dimmed while it is from before the last reload.
+            let mut status_span = if i > 0 && blocked {
+                Span::styled(
+                    app.theme.blocked.glyph.clone(),
+                    app.theme.style(&app.theme.blocked),
+                )
+            } else if app.is_running_service(cell.id) {
+                Span::styled(
+                    app.theme.service.glyph.clone(),
+                    app.theme.style(&app.theme.service),
+                )
+            } else {
+                status_span(&app.cell_statuses[i], &app.theme)
             };
+            if stale {
+                status_span.style = app.theme.color(Color::DarkGray);
+            }
 
-            // Calculate right side width.
-            let count_text = format!("[{}]", count);
-            let output_text = if app.has_output(name) {
-                "[output]"
+            // Last run timing, redrawn every tick so the age stays current, the
+            // retry of a running cell that failed, or the place of a queued cell.
+            let retry = app
+                .cell_retries
+                .get(&cell.id)
+                .filter(|_| app.cell_statuses[i] == CellStatus::Running);
+            let queued = app.queue_position(cell.id);
+            let timing = if let Some((retry, retries)) = retry {
+                Some(format!("retry {}/{} ", retry, retries))
+            } else if let Some(place) = queued {
+                Some(format!("queued {} ", place))
             } else {
-                "[none]"
+                app.last_run(name).map(|run| {
+                    let age = format_age(run.finished.elapsed());
+                    if app.show_timings {
+                        format!("{} {} ", format_duration(run.duration), age)
+                    } else {
+                        format!("{} ", age)
+                    }
+                })
             };
-            let status_text = match &app.cell_statuses[i] {
-                CellStatus::Pending => "[none]",
-                CellStatus::Running => "[running]",
-                CellStatus::Success => "[success]",
-                CellStatus::Error(_) => "[error]",
+            let timing_style = if retry.is_some() || queued.is_some() {
+                app.theme.color(Color::Yellow)
+            } else {
+                app.theme.color(Color::DarkGray)
             };
-            let right_len = count_text.len() + 1 + output_text.len() + 1 + status_text.len();
+            let timing_span = Span::styled(timing.unwrap_or_default(), timing_style);
+
+            // Calculate right side width.
+            let right_len =
+                timing_span.width() + count_span.width() + 1 + output_span.width() + 1 + status_span.width();
             let left_len = cell_num.len();
 
+            let watch_marker = if app.is_watched(cell.id) { "⟳ " } else { "" };
+            let left_len = left_len + watch_marker.chars().count();
+
             let name_max_len = inner_width.saturating_sub(right_len + left_len + 1);
-            let display_name: String = name.chars().take(name_max_len).collect();
-            let padding = inner_width.saturating_sub(left_len + display_name.len() + right_len);
+            let display_name: String = cell.display_name().chars().take(name_max_len).collect();
+            let padding = inner_width.saturating_sub(left_len + display_name.chars().count() + right_len);
 
             let line = Line::from(vec![
                 Span::styled(cell_num, Style::default().fg(Color::DarkGray)),
+                Span::styled(watch_marker, Style::default().fg(Color::Cyan)),
                 Span::raw(display_name),
                 Span::raw(" ".repeat(padding)),
+                timing_span,
                 count_span,
                 Span::raw(" "),
                 output_span,
@@ -93,15 +361,36 @@ fn render_cells(frame: &mut Frame, app: &mut App, area: Rect) {
                 status_span,
             ]);
 
-            ListItem::new(line)
+            // First line of the doc comment, dimmed and aligned with the name.
+            let summary = cell
+                .doc
+                .as_deref()
+                .and_then(|doc| doc.lines().next())
+                .map(|first| {
+                    let summary: String = first.chars().take(inner_width.saturating_sub(left_len)).collect();
+                    Line::from(vec![
+                        Span::raw(" ".repeat(left_len)),
+                        Span::styled(summary, app.theme.color(Color::DarkGray)),
+                    ])
+                });
+
+            match summary {
+                Some(summary) => ListItem::new(vec![line, summary]),
+                None => ListItem::new(line),
+            }
         })
         .collect();
 
+    let border_color = if app.focus == Focus::Cells {
+        Color::Cyan
+    } else {
+        Color::White
+    };
     let list = List::new(items)
         .block(
             Block::default()
                 .borders(Borders::TOP)
-                .border_style(Style::default().fg(Color::White))
+                .border_style(Style::default().fg(border_color))
                 .title("Cells "),
         )
         .highlight_style(Style::default().bg(Color::Rgb(35, 37, 42)));
@@ -109,76 +398,495 @@ fn render_cells(frame: &mut Frame, app: &mut App, area: Rect) {
     frame.render_stateful_widget(list, area, &mut app.list_state);
 }
 
-fn render_context(frame: &mut Frame, app: &App, area: Rect) {
-    let items: Vec<Span> = if app.context_items.is_empty() {
-        vec![]
+/// File names of the latest images a cell produced, newest last, e.g. `+2 hist.png, plot.svg`.
+fn image_names(app: &App, cell_name: &str) -> String {
+    const SHOWN: usize = 3;
+    let names: Vec<String> = app
+        .cell_images(cell_name)
+        .map(|image| {
+            image.path.file_name().map_or_else(
+                || image.path.display().to_string(),
+                |n| n.to_string_lossy().into_owned(),
+            )
+        })
+        .collect();
+    let hidden = names.len().saturating_sub(SHOWN);
+    let shown = names[hidden..].join(", ");
+    match hidden {
+        0 => shown,
+        n => format!("+{} {}", n, shown),
+    }
+}
+
+/// Compact duration such as `850µs`, `12ms`, `1.4s` or `2m05s`.
+fn format_duration(duration: Duration) -> String {
+    let micros = duration.as_micros();
+    if micros < 1_000 {
+        format!("{}µs", micros)
+    } else if micros < 1_000_000 {
+        format!("{}ms", micros / 1_000)
+    } else if duration.as_secs() < 60 {
+        format!("{:.1}s", duration.as_secs_f64())
     } else {
-        app.context_items
-            .iter()
-            .flat_map(|(key, type_name)| {
-                vec![
-                    Span::styled(key, Style::default().fg(Color::Cyan)),
-                    Span::raw(": "),
-                    Span::styled(type_name, Style::default().fg(Color::Yellow)),
-                    Span::raw("  "),
-                ]
-            })
-            .collect()
+        format!("{}m{:02}s", duration.as_secs() / 60, duration.as_secs() % 60)
+    }
+}
+
+/// ` [build 12.3s · load 40ms · run 1.2s]` for whichever steps have happened.
+fn format_timings(timings: runs::Timings, run: Option<Duration>) -> String {
+    let parts: Vec<String> = [("build", timings.build), ("load", timings.load), ("run", run)]
+        .into_iter()
+        .filter_map(|(step, duration)| Some(format!("{} {}", step, format_duration(duration?))))
+        .collect();
+    if parts.is_empty() {
+        return String::new();
+    }
+    format!(" [{}]", parts.join(" · "))
+}
+
+/// Relative age such as `just now`, `42s ago`, `2m ago` or `3h ago`.
+fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..5 => "just now".to_string(),
+        5..60 => format!("{}s ago", secs),
+        60..3600 => format!("{}m ago", secs / 60),
+        3600..86400 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+/// Describe the cell run that stored a value, e.g. `load, run 3, 2m ago`.
+pub(super) fn format_provenance(provenance: &Provenance) -> String {
+    let mut out = provenance.cell.clone();
+    if let Some(run) = provenance.run {
+        out.push_str(&format!(", run {}", run));
+    }
+    if let Ok(age) = provenance.started.elapsed() {
+        out.push_str(&format!(", {}", format_age(age)));
+    }
+    out
+}
+
+/// Window title showing the project and build status, e.g. `cellbook: stocks — Building`.
+pub(super) fn window_title(project: &str, status: &BuildStatus) -> String {
+    let status = match status {
+        BuildStatus::Idle => "Ready",
+        BuildStatus::Changed | BuildStatus::Building | BuildStatus::Waiting(_) | BuildStatus::Reloading => {
+            "Building"
+        }
+        BuildStatus::BuildError(_) => "Error",
+    };
+    format!("cellbook: {} — {}", project, status)
+}
+
+/// Status indicator for a cell. Errors are bold so they stand out without color.
+fn status_span(status: &CellStatus, theme: &ThemeConfig) -> Span<'static> {
+    let indicator = match status {
+        CellStatus::Pending => &theme.pending,
+        CellStatus::Running => &theme.running,
+        CellStatus::Success => &theme.success,
+        CellStatus::Error(_) => &theme.error,
+    };
+    let mut style = theme.style(indicator);
+    if matches!(status, CellStatus::Error(_)) {
+        style = style.add_modifier(Modifier::BOLD);
+    }
+    Span::styled(indicator.glyph.clone(), style)
+}
+
+/// Captured output of the selected cell, scrolled with the pane focused.
+fn render_output(frame: &mut Frame, app: &mut App, area: Rect) {
+    let name = app.selected_cell_name().map(String::from);
+    let output = name.as_deref().and_then(|name| app.get_output(name));
+    let text = output.map(|output| output.stdout.as_str()).unwrap_or_default();
+    let stale = app
+        .selected_cell_index()
+        .is_some_and(|i| app.is_stale(app.cells[i].id));
+    let lines = text.lines().count() as u16;
+    let title = match &name {
+        Some(name) if output.is_none() => format!("Output: {} (none) ", name),
+        Some(name) => format!("Output: {} ", name),
+        None => "Output ".to_string(),
+    };
+    let style = if stale {
+        Style::default().fg(Color::DarkGray)
+    } else {
+        Style::default()
+    };
+    let paragraph = Paragraph::new(text.to_string()).style(style);
+
+    // Keep the last lines in view when scrolled past the end.
+    let visible = area.height.saturating_sub(1);
+    let scroll = app.output_scroll().min(lines.saturating_sub(visible));
+    app.output_scroll.1 = scroll;
+
+    let border_color = if app.focus == Focus::Output {
+        Color::Cyan
+    } else {
+        Color::White
+    };
+    let paragraph = paragraph.scroll((scroll, 0)).block(
+        Block::default()
+            .borders(Borders::TOP | Borders::LEFT)
+            .border_style(Style::default().fg(border_color))
+            .title(title),
+    );
+    frame.render_widget(paragraph, area);
+}
+
+fn render_queue(frame: &mut Frame, app: &mut App, area: Rect) {
+    let items: Vec<ListItem> = app
+        .pending_runs
+        .iter()
+        .enumerate()
+        .map(|(place, &id)| {
+            let name = match app.index_of(id) {
+                Some(idx) => app.cells[idx].display_name().to_string(),
+                None => "removed".to_string(),
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("{}. ", place + 1), Style::default().fg(Color::DarkGray)),
+                Span::raw(name),
+            ]))
+        })
+        .collect();
+
+    let focused = app.focus == Focus::Queue;
+    let border_color = if focused { Color::Cyan } else { Color::White };
+    let highlight = if focused {
+        Style::default().bg(Color::Rgb(35, 37, 42))
+    } else {
+        Style::default()
     };
 
-    let context = Paragraph::new(Line::from(items))
+    let list = List::new(items)
         .block(
             Block::default()
                 .borders(Borders::TOP)
-                .border_style(Style::default().fg(Color::White))
+                .border_style(Style::default().fg(border_color))
+                .title("Queue "),
+        )
+        .highlight_style(highlight);
+
+    frame.render_stateful_widget(list, area, &mut app.queue_state);
+}
+
+fn render_tasks(frame: &mut Frame, app: &mut App, area: Rect) {
+    let items: Vec<ListItem> = app
+        .tasks
+        .iter()
+        .map(|task| {
+            let state = match &task.state {
+                TaskState::Running => Span::styled("[running]", Style::default().fg(Color::Yellow)),
+                TaskState::Finished => Span::styled("[finished]", Style::default().fg(Color::Green)),
+                TaskState::Failed(_) => Span::styled("[failed]", Style::default().fg(Color::Red)),
+                TaskState::Cancelled => Span::styled("[cancelled]", Style::default().fg(Color::DarkGray)),
+            };
+            let mut spans = vec![
+                Span::raw(task.name.as_str()),
+                Span::styled(
+                    format!(" {:.1}s ", task.elapsed.as_secs_f64()),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                state,
+            ];
+            if let TaskState::Failed(e) = &task.state {
+                spans.push(Span::styled(format!(" {}", e), Style::default().fg(Color::Red)));
+            }
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+
+    let focused = app.focus == Focus::Tasks;
+    let border_color = if focused { Color::Cyan } else { Color::White };
+    let highlight = if focused {
+        Style::default().bg(Color::Rgb(35, 37, 42))
+    } else {
+        Style::default()
+    };
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::TOP)
+                .border_style(Style::default().fg(border_color))
+                .title("Tasks "),
+        )
+        .highlight_style(highlight);
+
+    frame.render_stateful_widget(list, area, &mut app.task_state);
+}
+
+fn render_artifacts(frame: &mut Frame, app: &mut App, area: Rect) {
+    let items: Vec<ListItem> = app
+        .artifacts
+        .iter()
+        .map(|artifact| {
+            let cell = artifact.cell.as_deref().unwrap_or("background");
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("{} ", cell), Style::default().fg(Color::Cyan)),
+                Span::raw(artifact.path.display().to_string()),
+                Span::styled(
+                    format!(" {}", artifact.mime),
+                    Style::default().fg(Color::DarkGray),
+                ),
+            ]))
+        })
+        .collect();
+
+    let focused = app.focus == Focus::Artifacts;
+    let border_color = if focused { Color::Cyan } else { Color::White };
+    let highlight = if focused {
+        Style::default().bg(Color::Rgb(35, 37, 42))
+    } else {
+        Style::default()
+    };
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::TOP)
+                .border_style(Style::default().fg(border_color))
+                .title("Artifacts "),
+        )
+        .highlight_style(highlight);
+
+    frame.render_stateful_widget(list, area, &mut app.artifact_state);
+}
+
+fn render_diagnostics(frame: &mut Frame, app: &mut App, area: Rect) {
+    let items: Vec<ListItem> = app
+        .lint_findings
+        .iter()
+        .map(|finding| {
+            let mut spans = vec![Span::styled(
+                format!("{:>4} ", finding.line),
+                Style::default().fg(Color::Yellow),
+            )];
+            if let Some(cell) = &finding.cell {
+                spans.push(Span::styled(
+                    format!("{} ", cell),
+                    Style::default().fg(Color::Cyan),
+                ));
+            }
+            spans.push(Span::raw(finding.message.as_str()));
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+
+    let focused = app.focus == Focus::Diagnostics;
+    let border_color = if focused { Color::Cyan } else { Color::White };
+    let highlight = if focused {
+        Style::default().bg(Color::Rgb(35, 37, 42))
+    } else {
+        Style::default()
+    };
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::TOP)
+                .border_style(Style::default().fg(border_color))
+                .title("Diagnostics "),
+        )
+        .highlight_style(highlight);
+
+    frame.render_stateful_widget(list, area, &mut app.lint_state);
+}
+
+fn render_context(frame: &mut Frame, app: &mut App, area: Rect) {
+    let items: Vec<ListItem> = app
+        .context_items
+        .iter()
+        .map(|(key, type_name)| {
+            let mark = if app.marked_keys.contains(key) { "* " } else { "  " };
+            let mut spans = vec![
+                Span::styled(mark, Style::default().fg(Color::Magenta)),
+                Span::styled(key.as_str(), Style::default().fg(Color::Cyan)),
+                Span::raw(": "),
+                Span::styled(type_name.as_str(), Style::default().fg(Color::Yellow)),
+            ];
+            if let Some(&size) = app.key_sizes.get(key) {
+                spans.push(Span::styled(
+                    format!(" {}", store::format_bytes(size)),
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            if let Some(expires) = app.key_expiries.get(key) {
+                let left = expires.saturating_duration_since(Instant::now());
+                if left.is_zero() {
+                    spans.push(Span::styled(" [stale]", Style::default().fg(Color::Red)));
+                } else {
+                    let left = Duration::from_secs(left.as_secs() + 1);
+                    spans.push(Span::styled(
+                        format!(" [ttl {}]", format_duration(left)),
+                        Style::default().fg(Color::DarkGray),
+                    ));
+                }
+            }
+            if app.pinned_keys.contains(key) {
+                spans.push(Span::styled(" [pinned]", Style::default().fg(Color::Magenta)));
+            }
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+
+    let focused = app.focus == Focus::Store;
+    let border_color = if focused { Color::Cyan } else { Color::White };
+    let highlight = if focused {
+        Style::default().bg(Color::Rgb(35, 37, 42))
+    } else {
+        Style::default()
+    };
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::TOP)
+                .border_style(Style::default().fg(border_color))
                 .title("Store "),
         )
-        .wrap(Wrap { trim: true });
+        .highlight_style(highlight);
 
-    frame.render_widget(context, area);
+    frame.render_stateful_widget(list, area, &mut app.store_state);
 }
 
-fn render_status_bar(frame: &mut Frame, app: &App, area: Rect) {
-    let help = vec![
-        Span::styled("[Enter]", Style::default().fg(Color::Cyan)),
-        Span::raw(" Run  "),
-        Span::styled("[o]", Style::default().fg(Color::Cyan)),
-        Span::raw(" Output  "),
-        Span::styled("[e]", Style::default().fg(Color::Cyan)),
-        Span::raw(" Error  "),
-        Span::styled("[E]", Style::default().fg(Color::Cyan)),
-        Span::raw(" Edit  "),
-        Span::styled("[x]", Style::default().fg(Color::Cyan)),
-        Span::raw(" Clear  "),
-        Span::styled("[r]", Style::default().fg(Color::Cyan)),
-        Span::raw(" Reload  "),
-        Span::styled("[q]", Style::default().fg(Color::Cyan)),
-        Span::raw(" Quit  "),
-    ];
+fn render_resources(frame: &mut Frame, app: &App, area: Rect) {
+    let items: Vec<ListItem> = app
+        .resource_items
+        .iter()
+        .map(|(key, type_name)| {
+            ListItem::new(Line::from(vec![
+                Span::styled(key.as_str(), Style::default().fg(Color::Cyan)),
+                Span::raw(": "),
+                Span::styled(type_name.as_str(), Style::default().fg(Color::Magenta)),
+            ]))
+        })
+        .collect();
 
-    let help_width: usize = help.iter().map(|s| s.width()).sum();
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::TOP)
+            .border_style(Style::default().fg(Color::White))
+            .title("Resources "),
+    );
+
+    frame.render_widget(list, area);
+}
+
+fn render_status_bar(frame: &mut Frame, app: &App, area: Rect) {
+    if let Some(input) = &app.scratch_input {
+        render_input(frame, "scratch> ", input, "  [Enter] Run  [Esc] Cancel", area);
+        return;
+    }
+    if let Some(rename) = &app.rename_input {
+        let prompt = format!("rename {}> ", rename.key);
+        render_input(
+            frame,
+            &prompt,
+            &rename.input,
+            "  [Enter] Rename  [Esc] Cancel",
+            area,
+        );
+        return;
+    }
+    if let Some(lookup) = &app.doc_lookup {
+        let hint = "  [Tab] Next symbol  [Enter] Open docs  [Esc] Cancel";
+        render_input(frame, "docs> ", &lookup.input, hint, area);
+        return;
+    }
 
     let status = match &app.build_status {
+        BuildStatus::Idle if app.stale_library => Span::styled(
+            format!("[{}] Stale library loaded", app.keybindings.reload.label()),
+            Style::default().fg(Color::Yellow),
+        ),
         BuildStatus::Idle => Span::styled("Ready", Style::default().fg(Color::Green)),
+        BuildStatus::Changed => {
+            Span::styled("Source changed, rebuilding…", Style::default().fg(Color::Yellow))
+        }
         BuildStatus::Building => Span::styled("Building", Style::default().fg(Color::Yellow)),
+        BuildStatus::Waiting(since) => Span::styled(
+            format!(
+                "Waiting for running cell… {}s [{}] Force reload",
+                app.reload_timeout.saturating_sub(since.elapsed()).as_secs(),
+                app.keybindings.reload.label()
+            ),
+            Style::default().fg(Color::Yellow),
+        ),
         BuildStatus::Reloading => Span::styled("Reloading", Style::default().fg(Color::Cyan)),
-        BuildStatus::BuildError(_) => Span::styled("[f] Failed", Style::default().fg(Color::Red)),
+        BuildStatus::BuildError(_) => Span::styled(
+            format!("[{}] Failed", app.keybindings.view_build_error.label()),
+            Style::default().fg(Color::Red),
+        ),
     };
 
-    let cell_count = Span::styled(
-        format!(" [{} cells]", app.cells.len()),
+    // Notices replace the help keys until the next key press.
+    let help = if let Some(notice) = &app.notice {
+        vec![Span::styled(
+            format!("{}  ", notice),
+            Style::default().fg(Color::Yellow),
+        )]
+    } else {
+        // The build status stays visible, since it can warn of a stale library.
+        help_spans(
+            app.focus,
+            &app.keybindings,
+            (area.width as usize).saturating_sub(status.width()),
+        )
+    };
+
+    let help_width: usize = help.iter().map(|s| s.width()).sum();
+
+    let cell_count = match app.hidden_count() {
+        0 => format!(" [{} cells]", app.cells.len()),
+        hidden => format!(" [{} cells, {} hidden]", app.cells.len(), hidden),
+    };
+    let cell_count = Span::styled(cell_count, Style::default().fg(Color::DarkGray));
+    let store_bytes: u64 = app.key_sizes.values().sum();
+    let store_size = match app.max_store_bytes {
+        Some(max) => format!(
+            " [{} / {} store]",
+            store::format_bytes(store_bytes),
+            store::format_bytes(max)
+        ),
+        None => format!(" [{} store]", store::format_bytes(store_bytes)),
+    };
+    let store_size = Span::styled(store_size, Style::default().fg(Color::DarkGray));
+    let timings = Span::styled(
+        format_timings(runs::timings(), app.latest_run_duration()),
+        Style::default().fg(Color::DarkGray),
+    );
+    // The seed to pass to `--seed` to repeat the session's random choices.
+    let seed = Span::styled(
+        runs::run_info().map_or(String::new(), |info| format!(" [seed {}]", info.seed)),
         Style::default().fg(Color::DarkGray),
     );
+    let fork = if app.forked {
+        Span::styled(" [fork]", Style::default().fg(Color::Magenta))
+    } else {
+        Span::raw("")
+    };
 
     let bar_style = Style::default().bg(Color::Rgb(35, 37, 42));
 
-    // Left side: help keys.
+    // Left side: help keys or notice.
     let left = Paragraph::new(Line::from(help)).style(bar_style);
 
     // Right side: status and cell count.
-    let right = Paragraph::new(Line::from(vec![status, cell_count]))
-        .alignment(Alignment::Right)
-        .style(bar_style);
+    let pending = Span::styled(
+        match app.pending_keys().as_str() {
+            "" => String::new(),
+            keys => format!("{}  ", keys),
+        },
+        Style::default().fg(Color::Yellow),
+    );
+    let right = Paragraph::new(Line::from(vec![
+        pending, status, timings, seed, fork, store_size, cell_count,
+    ]))
+    .alignment(Alignment::Right)
+    .style(bar_style);
 
     // Prioritize commands over status when space is limited.
     let chunks = Layout::default()
@@ -189,3 +897,230 @@ fn render_status_bar(frame: &mut Frame, app: &App, area: Rect) {
     frame.render_widget(left, chunks[0]);
     frame.render_widget(right, chunks[1]);
 }
+
+/// Input line shown in place of the status bar.
+fn render_input(frame: &mut Frame, prompt: &str, input: &str, hint: &str, area: Rect) {
+    let line = Line::from(vec![
+        Span::styled(prompt, Style::default().fg(Color::Cyan)),
+        Span::raw(input),
+        Span::styled(hint, Style::default().fg(Color::DarkGray)),
+    ]);
+    let paragraph = Paragraph::new(line).style(Style::default().bg(Color::Rgb(35, 37, 42)));
+    frame.render_widget(paragraph, area);
+
+    let cursor = (prompt.len() + input.chars().count()) as u16;
+    frame.set_cursor_position((area.x + cursor.min(area.width.saturating_sub(1)), area.y));
+}
+
+/// Help entries for the focused pane, most important first.
+fn help_entries(focus: Focus, kb: &Keybindings) -> Vec<(String, &'static str)> {
+    let entries: Vec<(&KeyBinding, &'static str)> = match focus {
+        Focus::Artifacts => vec![
+            (&kb.open_artifact, "Open"),
+            (&kb.copy_path, "Copy path"),
+            (&kb.delete_artifact, "Delete"),
+            (&kb.toggle_focus, "Next pane"),
+            (&kb.quit, "Quit"),
+        ],
+        Focus::Output => vec![
+            (&kb.navigate_down, "Scroll"),
+            (&kb.copy_output, "Copy"),
+            (&kb.grow_pane, "Grow"),
+            (&kb.shrink_pane, "Shrink"),
+            (&kb.toggle_focus, "Next pane"),
+            (&kb.quit, "Quit"),
+        ],
+        Focus::Queue => vec![
+            (&kb.move_queued_up, "Earlier"),
+            (&kb.move_queued_down, "Later"),
+            (&kb.dequeue, "Remove"),
+            (&kb.toggle_focus, "Next pane"),
+            (&kb.quit, "Quit"),
+        ],
+        Focus::Diagnostics => vec![
+            (&kb.edit, "Edit"),
+            (&kb.toggle_focus, "Next pane"),
+            (&kb.quit, "Quit"),
+        ],
+        Focus::Tasks => vec![
+            (&kb.cancel_task, "Cancel/dismiss"),
+            (&kb.toggle_focus, "Next pane"),
+            (&kb.quit, "Quit"),
+        ],
+        Focus::Store => vec![
+            (&kb.view_value, "Bytes"),
+            (&kb.view_history, "History"),
+            (&kb.copy_output, "Copy JSON"),
+            (&kb.mark_key, "Mark"),
+            (&kb.delete_keys, "Delete"),
+            (&kb.rename_key, "Rename"),
+            (&kb.export_keys, "Export"),
+            (&kb.pin_keys, "Pin"),
+            (&kb.toggle_focus, "Next pane"),
+            (&kb.quit, "Quit"),
+        ],
+        Focus::Cells => vec![
+            (&kb.run_cell, "Run"),
+            (&kb.run_last, "Rerun"),
+            (&kb.toggle_watch, "Watch"),
+            (&kb.view_output, "Output"),
+            (&kb.view_error, "Error"),
+            (&kb.view_diff, "Diff"),
+            (&kb.view_access, "Keys"),
+            (&kb.view_graph, "Graph"),
+            (&kb.view_details, "Details"),
+            (&kb.copy_output, "Copy output"),
+            (&kb.open_image, "Image"),
+            (&kb.edit, "Edit"),
+            (&kb.scratch, "Scratch"),
+            (&kb.docs, "Docs"),
+            (&kb.stop_service, "Stop service"),
+            (&kb.clear_context, "Clear"),
+            (&kb.undo_clear, "Undo"),
+            (&kb.reload, "Reload"),
+            (&kb.toggle_store, "Store"),
+            (&kb.zen_mode, "Zen"),
+            (&kb.toggle_focus, "Next pane"),
+            (&kb.quit, "Quit"),
+        ],
+    };
+    entries
+        .into_iter()
+        .map(|(binding, action)| (binding.label(), action))
+        .collect()
+}
+
+/// Help spans for the focused pane that fit in `width` columns.
+///
+/// Entries that don't fit are dropped whole, except quit, which is kept
+/// in place of the last entries that would fit.
+fn help_spans(focus: Focus, kb: &Keybindings, width: usize) -> Vec<Span<'static>> {
+    let mut entries = help_entries(focus, kb);
+    let entry_width = |(key, action): &(String, &str)| key.chars().count() + action.chars().count() + 5;
+
+    let total: usize = entries.iter().map(entry_width).sum();
+    if total > width {
+        let quit = entries.pop();
+        let quit_width = quit.as_ref().map(entry_width).unwrap_or(0);
+        let mut used = quit_width;
+        let fitting = entries
+            .iter()
+            .take_while(|entry| {
+                used += entry_width(entry);
+                used <= width
+            })
+            .count();
+        entries.truncate(fitting);
+        entries.extend(quit.filter(|_| quit_width <= width));
+    }
+
+    entries
+        .into_iter()
+        .flat_map(|(key, action)| {
+            [
+                Span::styled(format!("[{}]", key), Style::default().fg(Color::Cyan)),
+                Span::raw(format!(" {}  ", action)),
+            ]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{format_age, format_duration, format_provenance, format_timings, help_spans, window_title};
+    use crate::store::Provenance;
+    use crate::tui::config::{KeyBinding, Keybindings};
+    use crate::tui::state::{BuildStatus, Focus};
+
+    fn help_text(kb: &Keybindings, width: usize) -> String {
+        help_spans(Focus::Tasks, kb, width)
+            .iter()
+            .map(|span| span.content.as_ref())
+            .collect()
+    }
+
+    #[test]
+    fn test_help_uses_configured_keys() {
+        let kb = Keybindings {
+            cancel_task: KeyBinding::Multiple(vec!["c".into(), "Delete".into()]),
+            ..Keybindings::default()
+        };
+        assert_eq!(
+            help_text(&kb, 200),
+            "[c/Delete] Cancel/dismiss  [Tab] Next pane  [q] Quit  "
+        );
+    }
+
+    #[test]
+    fn test_window_title_shows_build_status() {
+        assert_eq!(
+            window_title("stocks", &BuildStatus::Idle),
+            "cellbook: stocks — Ready"
+        );
+        assert_eq!(
+            window_title("stocks", &BuildStatus::Changed),
+            "cellbook: stocks — Building"
+        );
+        assert_eq!(
+            window_title("stocks", &BuildStatus::BuildError("error[E0308]".into())),
+            "cellbook: stocks — Error"
+        );
+    }
+
+    #[test]
+    fn test_help_drops_entries_that_dont_fit() {
+        let kb = Keybindings::default();
+        assert_eq!(help_text(&kb, 30), "[c] Cancel/dismiss  [q] Quit  ");
+        assert_eq!(help_text(&kb, 10), "[q] Quit  ");
+        assert_eq!(help_text(&kb, 5), "");
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_micros(850)), "850µs");
+        assert_eq!(format_duration(Duration::from_millis(12)), "12ms");
+        assert_eq!(format_duration(Duration::from_millis(1400)), "1.4s");
+        assert_eq!(format_duration(Duration::from_secs(125)), "2m05s");
+    }
+
+    #[test]
+    fn test_format_timings() {
+        let timings = crate::runs::Timings {
+            build: Some(Duration::from_millis(12_300)),
+            load: Some(Duration::from_millis(40)),
+        };
+        assert_eq!(
+            format_timings(timings, Some(Duration::from_millis(1200))),
+            " [build 12.3s · load 40ms · run 1.2s]"
+        );
+        let loaded = crate::runs::Timings {
+            build: None,
+            ..timings
+        };
+        assert_eq!(format_timings(loaded, None), " [load 40ms]");
+        assert_eq!(format_timings(Default::default(), None), "");
+    }
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(Duration::from_secs(2)), "just now");
+        assert_eq!(format_age(Duration::from_secs(42)), "42s ago");
+        assert_eq!(format_age(Duration::from_secs(150)), "2m ago");
+        assert_eq!(format_age(Duration::from_secs(3 * 3600)), "3h ago");
+        assert_eq!(format_age(Duration::from_secs(2 * 86400)), "2d ago");
+    }
+
+    #[test]
+    fn test_format_provenance() {
+        assert_eq!(
+            format_provenance(&Provenance::new("load", Some(3))),
+            "load, run 3, just now"
+        );
+        assert_eq!(
+            format_provenance(&Provenance::new("scratch", None)),
+            "scratch, just now"
+        );
+    }
+}
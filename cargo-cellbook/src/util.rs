@@ -0,0 +1,80 @@
+//! Small helpers shared by modules that have nothing else in common.
+
+use std::fs::File;
+
+use crate::errors::Result;
+
+/// 32 random hex characters from the operating system's random source, for
+/// secrets such as the web runner's session token.
+pub fn random_token() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::fill(&mut bytes).map_err(|e| std::io::Error::other(e.to_string()))?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Standard base64 with padding, for images in reports and Jupyter and for
+/// clipboard writes through the terminal.
+pub fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Take stdout for machine-readable output, pointing the process's stdout at
+/// stderr so nothing else printed there ends up in it. Called before anything
+/// is printed.
+#[cfg(unix)]
+pub fn claim_stdout() -> std::io::Result<File> {
+    use std::io::Write;
+    use std::os::fd::AsFd;
+
+    std::io::stdout().flush()?;
+    let claimed = std::io::stdout().as_fd().try_clone_to_owned()?;
+    // SAFETY: Only replaces file descriptor 1 with a copy of 2, before any cell runs.
+    if unsafe { libc::dup2(2, 1) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(File::from(claimed))
+}
+
+#[cfg(not(unix))]
+pub fn claim_stdout() -> std::io::Result<File> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "only supported on Unix",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn test_random_tokens_are_hex_and_differ() {
+        let token = random_token().unwrap();
+        assert_eq!(token.len(), 32);
+        assert!(token.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(token, random_token().unwrap());
+    }
+}
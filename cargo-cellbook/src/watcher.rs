@@ -3,11 +3,12 @@
 use std::collections::HashMap;
 use std::io::BufRead;
 use std::path::{Path, PathBuf};
+use std::pin::Pin;
 use std::process::Stdio;
 use std::sync::{Arc, Mutex};
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 
-use notify::{RecommendedWatcher, RecursiveMode};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 use notify_debouncer_mini::{DebouncedEventKind, Debouncer, new_debouncer};
 #[cfg(windows)]
 use ratatui::crossterm::QueueableCommand;
@@ -15,13 +16,13 @@ use ratatui::crossterm::cursor::{MoveToColumn, MoveUp};
 use ratatui::crossterm::execute;
 use ratatui::crossterm::style::Print;
 use ratatui::crossterm::terminal::{Clear, ClearType};
-use serde::Deserialize;
 use tokio::process::Command;
 use tokio::sync::{mpsc, oneshot};
 
 use crate::errors::{Error, Result};
 use crate::tui::TuiEvent;
-use crate::tui::config::GeneralConfig;
+use crate::tui::config::{self, BuildConfig, GeneralConfig};
+use crate::{fast_build, runs, workspace};
 
 type NotifyDebouncer = Debouncer<RecommendedWatcher>;
 
@@ -46,47 +47,36 @@ fn get_mtime(path: &Path) -> Option<SystemTime> {
     std::fs::metadata(path).ok()?.modified().ok()
 }
 
-#[derive(Debug, Deserialize)]
-struct CargoMetadata {
-    workspace_root: PathBuf,
-}
-
-fn workspace_root_from_metadata() -> Option<PathBuf> {
-    let output = std::process::Command::new("cargo")
-        .args(["metadata", "--format-version", "1", "--no-deps"])
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .output()
-        .ok()?;
-
-    if !output.status.success() {
-        return None;
-    }
-
-    let metadata = serde_json::from_slice::<CargoMetadata>(&output.stdout).ok()?;
-    Some(metadata.workspace_root)
-}
-
-fn has_lockfile() -> bool {
+fn has_lockfile(build: &BuildConfig) -> bool {
     if Path::new("Cargo.lock").exists() {
         return true;
     }
 
-    workspace_root_from_metadata()
-        .map(|root| root.join("Cargo.lock").exists())
+    workspace::metadata(&build.env)
+        .map(|metadata| metadata.workspace_root.join("Cargo.lock").exists())
         .unwrap_or(false)
 }
 
-fn cargo_build_args() -> Vec<&'static str> {
-    let mut args = vec!["build", "--lib"];
-    if has_lockfile() {
-        args.push("--locked");
+/// Arguments for `cargo <command>` on the notebook library, shared by builds and `cargo cellbook check`.
+pub fn cargo_args(command: &str, build: &BuildConfig) -> Vec<String> {
+    let mut args = vec![command.to_string(), "--lib".to_string()];
+    if has_lockfile(build) {
+        args.push("--locked".to_string());
+    }
+    args.extend(build.cargo_args());
+    if build.fast_build {
+        args.extend(fast_build::cargo_args(build));
     }
     args
 }
 
-fn cargo_build_display_cmd() -> String {
-    format!("cargo {}", cargo_build_args().join(" "))
+fn cargo_build_display_cmd(build: &BuildConfig) -> String {
+    let env: String = build
+        .env
+        .iter()
+        .map(|(name, value)| format!("{}={:?} ", name, value))
+        .collect();
+    format!("{}cargo {}", env, cargo_args("build", build).join(" "))
 }
 
 /// Check if any paths have changed since last recorded.
@@ -110,9 +100,37 @@ fn has_actual_changes(paths: &[PathBuf], mtimes: &mut HashMap<PathBuf, SystemTim
     changed
 }
 
+/// Whether a raw filesystem event writes, creates or removes a source file.
+fn is_source_change(event: &notify::Event) -> bool {
+    !matches!(event.kind, EventKind::Access(_) | EventKind::Other)
+        && event
+            .paths
+            .iter()
+            .any(|path| path.extension().is_some_and(|ext| ext == "rs"))
+}
+
+/// Watch `cellbook.rs` and `src/`, whichever exist.
+fn watch_sources(watcher: &mut dyn Watcher) -> Result<()> {
+    let cellbook_rs = Path::new("cellbook.rs");
+    let src_path = Path::new("src");
+
+    if cellbook_rs.exists() {
+        watcher
+            .watch(cellbook_rs, RecursiveMode::NonRecursive)
+            .map_err(|e| Error::Watch(e.to_string()))?;
+    }
+    if src_path.exists() {
+        watcher
+            .watch(src_path, RecursiveMode::Recursive)
+            .map_err(|e| Error::Watch(e.to_string()))?;
+    }
+    Ok(())
+}
+
 pub struct WatcherHandle {
     shutdown_tx: oneshot::Sender<()>,
     _debouncer: NotifyDebouncer,
+    _change_watcher: RecommendedWatcher,
 }
 
 impl WatcherHandle {
@@ -137,21 +155,18 @@ pub async fn start_watcher(
     let debounce_duration = Duration::from_millis(config.debounce_ms as u64);
     let mut debouncer = new_debouncer(debounce_duration, tx).map_err(|e| Error::Watch(e.to_string()))?;
 
-    let cellbook_rs = Path::new("cellbook.rs");
-    let src_path = Path::new("src");
+    watch_sources(debouncer.watcher())?;
 
-    if cellbook_rs.exists() {
-        debouncer
-            .watcher()
-            .watch(cellbook_rs, RecursiveMode::NonRecursive)
-            .map_err(|e| Error::Watch(e.to_string()))?;
-    }
-    if src_path.exists() {
-        debouncer
-            .watcher()
-            .watch(src_path, RecursiveMode::Recursive)
-            .map_err(|e| Error::Watch(e.to_string()))?;
-    }
+    // Undebounced, so the TUI can show the change while the debounce runs.
+    let change_tx = event_tx.clone();
+    let mut change_watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if event.is_ok_and(|event| is_source_change(&event)) {
+            let _ = change_tx.try_send(TuiEvent::SourceChanged);
+        }
+    })
+    .map_err(|e| Error::Watch(e.to_string()))?;
+    watch_sources(&mut change_watcher)?;
+    let cellbook_rs = Path::new("cellbook.rs");
 
     let (shutdown_tx, mut shutdown_rx) = oneshot::channel();
 
@@ -174,6 +189,8 @@ pub async fn start_watcher(
     }
 
     tokio::spawn(async move {
+        // Dropping the in-flight build kills its `cargo build`.
+        let mut build: Option<Pin<Box<dyn Future<Output = Result<()>> + Send>>> = None;
         loop {
             tokio::select! {
                 biased;
@@ -182,6 +199,21 @@ pub async fn start_watcher(
                     break;
                 }
 
+                result = async { build.as_mut().unwrap().await }, if build.is_some() => {
+                    build = None;
+                    match result {
+                        Ok(()) => {
+                            let _ = event_tx.send(TuiEvent::BuildCompleted(None)).await;
+                            let _ = event_tx.send(TuiEvent::Reloaded).await;
+                        }
+                        Err(e) => {
+                            let _ = event_tx
+                                .send(TuiEvent::BuildCompleted(Some(e.to_string())))
+                                .await;
+                        }
+                    }
+                }
+
                 event = file_event_rx.recv() => {
                     match event {
                         Some(Ok(events)) => {
@@ -194,20 +226,16 @@ pub async fn start_watcher(
                                 .filter_map(|e| e.path.canonicalize().ok())
                                 .collect();
 
-                            if !rs_paths.is_empty() && has_actual_changes(&rs_paths, &mut mtimes) {
+                            if rs_paths.is_empty() || !has_actual_changes(&rs_paths, &mut mtimes) {
+                                let _ = event_tx.send(TuiEvent::SourceUnchanged).await;
+                                continue;
+                            }
+
+                            // A build of older source is cancelled for one of the latest.
+                            if build.is_none() {
                                 let _ = event_tx.send(TuiEvent::BuildStarted).await;
-                                match rebuild().await {
-                                    Ok(()) => {
-                                        let _ = event_tx.send(TuiEvent::BuildCompleted(None)).await;
-                                        let _ = event_tx.send(TuiEvent::Reloaded).await;
-                                    }
-                                    Err(e) => {
-                                        let _ = event_tx
-                                            .send(TuiEvent::BuildCompleted(Some(e.to_string())))
-                                            .await;
-                                    }
-                                }
                             }
+                            build = Some(Box::pin(rebuild()));
                         }
                         Some(Err(e)) => {
                             eprintln!("Watch error: {:?}", e);
@@ -224,15 +252,20 @@ pub async fn start_watcher(
     Ok(Some(WatcherHandle {
         shutdown_tx,
         _debouncer: debouncer,
+        _change_watcher: change_watcher,
     }))
 }
 
+/// Build the notebook library. Dropping the future kills `cargo build`.
 pub async fn rebuild() -> Result<()> {
-    let args = cargo_build_args();
+    let build = config::load_build();
+    let started = Instant::now();
     let output = Command::new("cargo")
-        .args(&args)
+        .args(cargo_args("build", &build))
+        .envs(&build.env)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
+        .kill_on_drop(true)
         .output()
         .await?;
 
@@ -240,13 +273,15 @@ pub async fn rebuild() -> Result<()> {
         let stderr = String::from_utf8_lossy(&output.stderr);
         return Err(Error::Build(stderr.to_string()));
     }
+    runs::built(started.elapsed());
 
     Ok(())
 }
 
 pub async fn initial_build() -> Result<()> {
     let spinner_chars = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
-    let build_cmd = cargo_build_display_cmd();
+    let build = config::load_build();
+    let build_cmd = cargo_build_display_cmd(&build);
     let latest_output = Arc::new(Mutex::new(String::new()));
 
     // Reserve two terminal lines that we redraw in-place:
@@ -291,10 +326,11 @@ pub async fn initial_build() -> Result<()> {
     });
 
     let output_for_reader = Arc::clone(&latest_output);
+    let started = Instant::now();
     let build_result = tokio::task::spawn_blocking(move || -> Result<()> {
-        let args = cargo_build_args();
         let mut child = std::process::Command::new("cargo")
-            .args(&args)
+            .args(cargo_args("build", &build))
+            .envs(&build.env)
             .stdout(Stdio::null())
             .stderr(Stdio::piped())
             .spawn()?;
@@ -316,6 +352,7 @@ pub async fn initial_build() -> Result<()> {
         if !status.success() {
             return Err(Error::Build(stderr_log));
         }
+        runs::built(started.elapsed());
 
         Ok(())
     })
@@ -0,0 +1,798 @@
+//! `cargo cellbook web`: the notebook in a browser.
+//!
+//! A small HTTP server with an embedded page that lists cells with run buttons,
+//! streams their output as it is written, shows the store and displays the images
+//! cells open. Cells run one at a time on the runner, in the order they were asked
+//! for, and edits to the notebook rebuild and reload it as in the TUI.
+//!
+//! The page follows changes through server-sent events on `/api/events`. Each event
+//! is a JSON object whose `type` says what changed; the page fetches the store and
+//! artifact lists again when told they changed.
+//!
+//! The page is only served to requests for the server's own address, so a DNS
+//! rebinding page can't load it, unless the server listens on all addresses and
+//! can't know the names it is reached by. It sets the session token as a cookie. Every
+//! `/api` request needs that cookie and is refused from pages of other origins,
+//! so other sites open in the browser can't run cells or read the store.
+
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use axum::Router;
+use axum::extract::{Path, Request, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{Html, IntoResponse, Json, Response};
+use axum::routing::{get, post};
+use futures::Stream;
+use parking_lot::Mutex;
+use serde::Serialize;
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::{JoinError, JoinHandle};
+
+use crate::errors::{Error, Result};
+use crate::failure::CellFailure;
+use crate::loader::{CellInfo, LoadedLibrary};
+use crate::runner::{self, Finished, Runs, Start};
+use crate::tui::TuiEvent;
+use crate::tui::config::{GeneralConfig, RunOnStart, StartMode};
+use crate::{artifacts, prompt, store, util};
+
+const INDEX_HTML: &str = include_str!("index.html");
+
+/// Cookie carrying the session token, set when the page is served.
+const TOKEN_COOKIE: &str = "cellbook_token";
+
+/// Updates kept for pages that fall behind, after which they reload the state.
+const UPDATE_BUFFER: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Idle,
+    Queued,
+    Running,
+    Ok,
+    Failed,
+    /// A service cell running in the background.
+    Service,
+}
+
+/// A cell as the page shows it.
+#[derive(Clone, Debug, Serialize)]
+struct CellView {
+    name: String,
+    /// Display name from `#[cell(name = "...")]`.
+    title: Option<String>,
+    doc: Option<String>,
+    line: u32,
+    hidden: bool,
+    status: Status,
+    runs: u32,
+    duration_ms: Option<u64>,
+    output: String,
+    /// The rendered error of a failed run.
+    error: Option<String>,
+    /// Retry number and total while a failed run is retried.
+    retry: Option<(u32, u32)>,
+}
+
+impl CellView {
+    fn new(cell: &CellInfo) -> Self {
+        Self {
+            name: cell.name.clone(),
+            title: cell.title.clone(),
+            doc: cell.doc.clone(),
+            line: cell.line,
+            hidden: cell.hidden,
+            status: Status::Idle,
+            runs: 0,
+            duration_ms: None,
+            output: String::new(),
+            error: None,
+            retry: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "state", rename_all = "lowercase")]
+enum BuildView {
+    Ready,
+    Building,
+    Error { message: String },
+}
+
+#[derive(Serialize)]
+struct View {
+    project: String,
+    build: BuildView,
+    cells: Vec<CellView>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Update<'a> {
+    /// A cell's status or result changed.
+    Cell {
+        cell: &'a CellView,
+    },
+    /// Output a running cell wrote since the last update.
+    Output {
+        name: &'a str,
+        text: &'a str,
+    },
+    Build {
+        build: &'a BuildView,
+    },
+    /// The cell list changed after a reload, the page fetches the state again.
+    Cells,
+    Store,
+    Artifacts,
+}
+
+enum Command {
+    Run(String),
+    RunAll,
+}
+
+/// State shared between the runner and the request handlers.
+struct Shared {
+    view: Mutex<View>,
+    updates: broadcast::Sender<String>,
+    commands: mpsc::Sender<Command>,
+    /// Secret of this session, given to the page as a cookie for its requests.
+    token: String,
+    hosts: Hosts,
+}
+
+/// `Host` headers of requests to this server.
+enum Hosts {
+    /// The address it listens on and `localhost` with its port.
+    Known(Vec<String>),
+    /// Any host with the port, for a server listening on all addresses, which
+    /// is reached by names and addresses it can't list.
+    AnyOnPort(u16),
+}
+
+impl Hosts {
+    fn new(host: &str, port: u16) -> Self {
+        match host.parse::<IpAddr>() {
+            Ok(ip) if ip.is_unspecified() => Hosts::AnyOnPort(port),
+            _ => Hosts::Known(vec![authority(host, port), authority("localhost", port)]),
+        }
+    }
+
+    fn contains(&self, host: &str) -> bool {
+        match self {
+            Hosts::Known(hosts) => hosts.iter().any(|known| known.eq_ignore_ascii_case(host)),
+            Hosts::AnyOnPort(port) => host
+                .rsplit_once(':')
+                .is_some_and(|(_, p)| p.parse() == Ok(*port)),
+        }
+    }
+}
+
+impl Shared {
+    fn send(&self, update: Update) {
+        if let Ok(json) = serde_json::to_string(&update) {
+            // No receivers just means no page is open.
+            let _ = self.updates.send(json);
+        }
+    }
+
+    /// Change the cell called `name` and send it to the pages.
+    fn update_cell(&self, name: &str, f: impl FnOnce(&mut CellView)) {
+        let mut view = self.view.lock();
+        if let Some(cell) = view.cells.iter_mut().find(|c| c.name == name) {
+            f(cell);
+            self.send(Update::Cell { cell });
+        }
+    }
+
+    /// Add output written by the running cell `name`, sending only the new text.
+    fn append_output(&self, name: &str, text: &str) {
+        let mut view = self.view.lock();
+        if let Some(cell) = view.cells.iter_mut().find(|c| c.name == name) {
+            cell.output.push_str(text);
+            self.send(Update::Output { name, text });
+        }
+    }
+
+    fn set_build(&self, build: BuildView) {
+        let mut view = self.view.lock();
+        view.build = build;
+        self.send(Update::Build { build: &view.build });
+    }
+
+    /// Whether a request is for this server's address. Pages of other hosts
+    /// resolving to it, as with DNS rebinding, send their own host name.
+    fn known_host(&self, headers: &HeaderMap) -> bool {
+        let host = headers.get(header::HOST).and_then(|v| v.to_str().ok());
+        host.is_some_and(|host| self.hosts.contains(host))
+    }
+
+    /// Whether a request may use the API: it is for this server's address,
+    /// carries the session cookie, and comes from the page itself if the browser
+    /// says which page sent it.
+    fn authorized(&self, headers: &HeaderMap) -> bool {
+        let token = headers
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|cookies| cookies.split(';'))
+            .find_map(|cookie| cookie.trim().strip_prefix(TOKEN_COOKIE)?.strip_prefix('='));
+        let origin = headers.get(header::ORIGIN).and_then(|v| v.to_str().ok());
+        let host = headers.get(header::HOST).and_then(|v| v.to_str().ok());
+        let same_origin = match (origin, host) {
+            (None, _) => true,
+            (Some(origin), Some(host)) => origin.strip_prefix("http://") == Some(host),
+            (Some(_), None) => false,
+        };
+        self.known_host(headers) && token == Some(self.token.as_str()) && same_origin
+    }
+
+    /// Replace the cell list after a reload, keeping the results of cells that are still there.
+    fn set_cells(&self, cells: &[CellInfo]) {
+        let mut view = self.view.lock();
+        let mut old: HashMap<String, CellView> = view.cells.drain(..).map(|c| (c.name.clone(), c)).collect();
+        view.cells = cells
+            .iter()
+            .map(|cell| {
+                let mut new = CellView::new(cell);
+                if let Some(old) = old.remove(&cell.name) {
+                    new.runs = old.runs;
+                    new.duration_ms = old.duration_ms;
+                    new.output = old.output;
+                    new.error = old.error;
+                    new.status = match old.status {
+                        Status::Ok | Status::Failed => old.status,
+                        _ => Status::Idle,
+                    };
+                }
+                new
+            })
+            .collect();
+        self.send(Update::Cells);
+    }
+}
+
+/// Serve the notebook on `host:port` until interrupted with Ctrl+C.
+/// Reloads on the watcher's events like the TUI. Prompts from cells read their
+/// answer from the terminal the server runs in.
+pub async fn serve(
+    lib: &mut LoadedLibrary,
+    mut event_rx: mpsc::Receiver<TuiEvent>,
+    config: &GeneralConfig,
+    host: &str,
+    port: u16,
+) -> Result<()> {
+    prompt::set_input(prompt::Input::Stdin);
+
+    let project = std::env::current_dir()?
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let (command_tx, mut command_rx) = mpsc::channel(32);
+    let (updates, _) = broadcast::channel(UPDATE_BUFFER);
+    let token = util::random_token()?;
+    let shared = Arc::new(Shared {
+        view: Mutex::new(View {
+            project,
+            build: BuildView::Ready,
+            cells: lib.visible_cells().iter().map(CellView::new).collect(),
+        }),
+        updates,
+        commands: command_tx,
+        token,
+        hosts: Hosts::new(host, port),
+    });
+
+    let listener = TcpListener::bind((host, port))
+        .await
+        .map_err(|e| Error::Web(format!("can't listen on {}:{}: {}", host, port, e)))?;
+    let server = tokio::spawn(axum::serve(listener, router(shared.clone())).into_future());
+    println!(
+        "Serving the notebook on http://{}:{}, press Ctrl+C to stop",
+        host, port
+    );
+
+    let mut runner = Runner {
+        lib,
+        config: config.clone(),
+        shared,
+        runs: Runs::default(),
+        queue: VecDeque::new(),
+        running: None,
+    };
+    runner.queue_start_cells();
+
+    // Cells run on their own task, so requests and reloads are handled while one runs.
+    loop {
+        runner.start_next();
+        tokio::select! {
+            Some(command) = command_rx.recv() => match command {
+                Command::Run(name) => runner.queue(name),
+                Command::RunAll => {
+                    let names: Vec<String> = runner.lib.visible_cells().iter().map(|c| c.name.clone()).collect();
+                    names.into_iter().for_each(|name| runner.queue(name));
+                }
+            },
+            joined = runner::join(runner.running.as_mut().map(|r| &mut r.handle)) => runner.finish(joined),
+            Some(event) = event_rx.recv() => match event {
+                TuiEvent::BuildStarted => runner.shared.set_build(BuildView::Building),
+                TuiEvent::BuildCompleted(Some(message)) => {
+                    runner.shared.set_build(BuildView::Error { message })
+                }
+                TuiEvent::BuildCompleted(None) => runner.shared.set_build(BuildView::Ready),
+                TuiEvent::Reloaded => runner.reload_and_start().await,
+                _ => {}
+            },
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+
+    server.abort();
+    runner.abort().await;
+    runner.release().await;
+    artifacts::cleanup();
+    store::remove_disk_files();
+    Ok(())
+}
+
+/// `host:port` as browsers send it in the `Host` header, with IPv6 addresses in brackets.
+fn authority(host: &str, port: u16) -> String {
+    if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+fn router(shared: Arc<Shared>) -> Router {
+    let api = Router::new()
+        .route("/api/state", get(state))
+        .route("/api/events", get(events))
+        .route("/api/cells/{name}/run", post(run_cell))
+        .route("/api/run-all", post(run_all))
+        .route("/api/store", get(store_keys))
+        .route("/api/artifacts", get(artifact_list))
+        .route("/api/artifacts/{index}", get(artifact_file))
+        .route_layer(middleware::from_fn_with_state(shared.clone(), require_token));
+    Router::new()
+        .route("/", get(index))
+        .merge(api)
+        .with_state(shared)
+}
+
+/// Refuse API requests that aren't [`authorized`](Shared::authorized).
+async fn require_token(State(shared): State<Arc<Shared>>, request: Request, next: Next) -> Response {
+    if !shared.authorized(request.headers()) {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+    next.run(request).await
+}
+
+/// The page, with the session cookie, for requests to this server's own address.
+async fn index(State(shared): State<Arc<Shared>>, headers: HeaderMap) -> Response {
+    if !shared.known_host(&headers) {
+        return StatusCode::MISDIRECTED_REQUEST.into_response();
+    }
+    let cookie = format!(
+        "{}={}; HttpOnly; SameSite=Strict; Path=/",
+        TOKEN_COOKIE, shared.token
+    );
+    ([(header::SET_COOKIE, cookie)], Html(INDEX_HTML)).into_response()
+}
+
+async fn state(State(shared): State<Arc<Shared>>) -> Response {
+    Json(&*shared.view.lock()).into_response()
+}
+
+async fn events(
+    State(shared): State<Arc<Shared>>,
+) -> Sse<impl Stream<Item = std::result::Result<Event, Infallible>>> {
+    let stream = futures::stream::unfold(shared.updates.subscribe(), |mut updates| async move {
+        let data = match updates.recv().await {
+            Ok(json) => json,
+            // The page missed updates, so it starts over from the full state.
+            Err(broadcast::error::RecvError::Lagged(_)) => {
+                serde_json::to_string(&Update::Cells).unwrap_or_default()
+            }
+            Err(broadcast::error::RecvError::Closed) => return None,
+        };
+        Some((Ok(Event::default().data(data)), updates))
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+async fn run_cell(State(shared): State<Arc<Shared>>, Path(name): Path<String>) -> StatusCode {
+    if !shared.view.lock().cells.iter().any(|c| c.name == name) {
+        return StatusCode::NOT_FOUND;
+    }
+    match shared.commands.send(Command::Run(name)).await {
+        Ok(()) => StatusCode::ACCEPTED,
+        Err(_) => StatusCode::SERVICE_UNAVAILABLE,
+    }
+}
+
+async fn run_all(State(shared): State<Arc<Shared>>) -> StatusCode {
+    match shared.commands.send(Command::RunAll).await {
+        Ok(()) => StatusCode::ACCEPTED,
+        Err(_) => StatusCode::SERVICE_UNAVAILABLE,
+    }
+}
+
+#[derive(Serialize)]
+struct KeyView {
+    key: String,
+    type_name: String,
+    size: String,
+    /// Cell run that stored the value, as `load (run 3)`.
+    stored_by: Option<String>,
+}
+
+async fn store_keys() -> Json<Vec<KeyView>> {
+    store::expire();
+    let sizes = store::sizes();
+    let mut keys: Vec<KeyView> = store::list()
+        .into_iter()
+        .map(|(key, type_name)| KeyView {
+            size: store::format_bytes(sizes.get(&key).copied().unwrap_or(0)),
+            stored_by: store::provenance(&key).map(|p| match p.run {
+                Some(run) => format!("{} (run {})", p.cell, run),
+                None => p.cell,
+            }),
+            key,
+            type_name,
+        })
+        .collect();
+    keys.sort_by(|a, b| a.key.cmp(&b.key));
+    Json(keys)
+}
+
+#[derive(Serialize)]
+struct ArtifactView {
+    /// Index for `/api/artifacts/{index}`.
+    index: usize,
+    name: String,
+    mime: String,
+    cell: Option<String>,
+}
+
+async fn artifact_list() -> Json<Vec<ArtifactView>> {
+    let artifacts = artifacts::list()
+        .into_iter()
+        .enumerate()
+        .map(|(index, artifact)| ArtifactView {
+            index,
+            name: artifact
+                .path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            mime: artifact.mime,
+            cell: artifact.cell,
+        })
+        .collect();
+    Json(artifacts)
+}
+
+/// Raster images, which browsers show without running anything in them.
+fn is_raster_image(mime: &str) -> bool {
+    matches!(
+        mime,
+        "image/png" | "image/jpeg" | "image/gif" | "image/webp" | "image/bmp" | "image/avif"
+    )
+}
+
+/// Contents of a registered artifact. Only files cells registered are served.
+///
+/// Artifacts are served on the page's origin, so they are sandboxed, and types
+/// other than raster images, such as SVG or HTML that could run script, are
+/// downloaded instead of shown.
+async fn artifact_file(Path(index): Path<usize>) -> Response {
+    let Some(artifact) = artifacts::list().into_iter().nth(index) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let Ok(bytes) = std::fs::read(&artifact.path) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let disposition = if is_raster_image(&artifact.mime) {
+        "inline".to_string()
+    } else {
+        let name = artifact
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().replace(['"', '\\', '\r', '\n'], "_"))
+            .unwrap_or_default();
+        format!("attachment; filename=\"{}\"", name)
+    };
+    let headers = [
+        (header::CONTENT_TYPE, artifact.mime),
+        (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+        (header::CONTENT_SECURITY_POLICY, "sandbox".to_string()),
+        (header::CONTENT_DISPOSITION, disposition),
+    ];
+    (headers, bytes).into_response()
+}
+
+/// A cell run in progress.
+struct Running {
+    /// Index of the cell among the visible cells.
+    idx: usize,
+    handle: JoinHandle<Finished>,
+}
+
+struct Runner<'a> {
+    lib: &'a mut LoadedLibrary,
+    config: GeneralConfig,
+    shared: Arc<Shared>,
+    runs: Runs,
+    /// Names of the cells waiting for the running one, in the order they were asked for.
+    queue: VecDeque<String>,
+    running: Option<Running>,
+}
+
+impl Runner<'_> {
+    /// Queue the cell `name` behind the running one.
+    fn queue(&mut self, name: String) {
+        self.shared.update_cell(&name, |cell| {
+            if cell.status != Status::Running {
+                cell.status = Status::Queued;
+            }
+        });
+        self.queue.push_back(name);
+    }
+
+    /// Queue the cells to run at startup and after each reload, per `run_on_start`.
+    fn queue_start_cells(&mut self) {
+        let cells = self.lib.visible_cells();
+        let names: Vec<String> = match &self.config.run_on_start {
+            RunOnStart::Mode(StartMode::Init) => vec![cells[0].name.clone()],
+            RunOnStart::Mode(StartMode::None) => Vec::new(),
+            RunOnStart::Cells(names) => names
+                .iter()
+                .filter(|name| cells.iter().any(|c| &c.name == *name))
+                .cloned()
+                .collect(),
+        };
+        names.into_iter().for_each(|name| self.queue(name));
+    }
+
+    /// Start queued cells until one runs, sending its output to the pages as it is written.
+    fn start_next(&mut self) {
+        while self.running.is_none() {
+            let Some(name) = self.queue.pop_front() else {
+                return;
+            };
+            // A reload may have removed the cell since it was queued.
+            let Some(idx) = self.lib.visible_cells().iter().position(|c| c.name == name) else {
+                continue;
+            };
+            let cell = &self.lib.visible_cells()[idx];
+            let (shared, retries) = (self.shared.clone(), cell.retries);
+            let on_retry = move |retry, e: &CellFailure| {
+                println!("[{}] retry {}/{} after: {}", name, retry, retries, e.message);
+                shared.update_cell(&name, |view| view.retry = Some((retry, retries)));
+            };
+            let name = cell.name.clone();
+            match self.runs.start(self.lib, &self.config, idx, on_retry) {
+                Start::Run { run, future } => {
+                    self.shared.update_cell(&name, |view| {
+                        view.runs = run;
+                        view.status = Status::Running;
+                        view.output.clear();
+                        view.error = None;
+                        view.retry = None;
+                    });
+                    let (shared, cell) = (self.shared.clone(), name.clone());
+                    let handle = runner::spawn(&name, future, move |text| shared.append_output(&cell, text));
+                    self.running = Some(Running { idx, handle });
+                }
+                Start::Service => self
+                    .shared
+                    .update_cell(&name, |view| view.status = Status::Service),
+                Start::Refused(reason) => self.shared.update_cell(&name, |view| {
+                    view.status = Status::Failed;
+                    view.error = Some(format!("Not run, {}\n", reason));
+                }),
+            }
+        }
+    }
+
+    /// Record the end of the running cell and show its result.
+    fn finish(&mut self, joined: std::result::Result<Finished, JoinError>) {
+        let Some(running) = self.running.take() else {
+            return;
+        };
+        let cell = self.lib.visible_cells()[running.idx].clone();
+        let (_, result, duration) = runner::finished(joined);
+        self.runs
+            .finish(&cell, running.idx, duration, &result, &self.config);
+        self.shared.update_cell(&cell.name, |view| {
+            view.status = if result.is_ok() {
+                Status::Ok
+            } else {
+                Status::Failed
+            };
+            view.duration_ms = Some(duration.as_millis() as u64);
+            view.error = result.as_ref().err().map(|e| e.render(&cell.name, cell.line));
+            view.retry = None;
+        });
+        self.shared.send(Update::Store);
+        self.shared.send(Update::Artifacts);
+        // Cells only make sense on top of a successful init.
+        if running.idx == 0 && result.is_err() {
+            for name in std::mem::take(&mut self.queue) {
+                self.shared.update_cell(&name, |view| {
+                    if view.status == Status::Queued {
+                        view.status = Status::Idle;
+                    }
+                });
+            }
+        }
+    }
+
+    /// Abort the running cell, since it runs code from the library a reload unloads.
+    async fn abort(&mut self) {
+        let Some(running) = self.running.take() else {
+            return;
+        };
+        self.runs.abort(running.handle).await;
+        let name = self.lib.visible_cells()[running.idx].name.clone();
+        self.shared.update_cell(&name, |view| {
+            view.status = Status::Failed;
+            view.error = Some("Aborted by a reload\n".to_string());
+            view.retry = None;
+        });
+    }
+
+    /// Drop state referencing the current library, load the new build and queue the
+    /// startup cells. Cells still queued run, by name, on the new build.
+    async fn reload_and_start(&mut self) {
+        self.abort().await;
+        self.release().await;
+        match self.lib.reload() {
+            Ok(()) => {
+                self.shared.set_cells(self.lib.visible_cells());
+                match self.config.reload(self.lib.config()) {
+                    Ok(()) => self.shared.set_build(BuildView::Ready),
+                    Err(e) => self.shared.set_build(BuildView::Error {
+                        message: e.to_string(),
+                    }),
+                }
+                self.queue_start_cells();
+            }
+            Err(e) => self.shared.set_build(BuildView::Error {
+                message: e.to_string(),
+            }),
+        }
+    }
+
+    /// Run teardown, then drop background tasks and resources.
+    async fn release(&mut self) {
+        self.lib.release().await.report(&mut std::io::stdout());
+        self.shared.send(Update::Store);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_updates_are_tagged_json() {
+        let mut cell = CellView::new(&CellInfo::new("nb", "load", 5));
+        cell.status = Status::Running;
+        let json: serde_json::Value =
+            serde_json::from_str(&serde_json::to_string(&Update::Cell { cell: &cell }).unwrap()).unwrap();
+        assert_eq!(json["type"], "cell");
+        assert_eq!(json["cell"]["name"], "load");
+        assert_eq!(json["cell"]["status"], "running");
+
+        let json = serde_json::to_string(&Update::Build {
+            build: &BuildView::Error {
+                message: "oops".to_string(),
+            },
+        })
+        .unwrap();
+        assert_eq!(
+            json,
+            r#"{"type":"build","build":{"state":"error","message":"oops"}}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&Update::Store).unwrap(),
+            r#"{"type":"store"}"#
+        );
+    }
+
+    fn shared(host: &str) -> Shared {
+        let (commands, _) = mpsc::channel(1);
+        Shared {
+            view: Mutex::new(View {
+                project: String::new(),
+                build: BuildView::Ready,
+                cells: Vec::new(),
+            }),
+            updates: broadcast::channel(1).0,
+            commands,
+            token: "secret".to_string(),
+            hosts: Hosts::new(host, 8080),
+        }
+    }
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_api_needs_the_host_the_cookie_and_the_same_origin() {
+        let shared = shared("127.0.0.1");
+        let host = ("host", "127.0.0.1:8080");
+        let cookie = ("cookie", "theme=dark; cellbook_token=secret");
+        assert!(shared.authorized(&headers(&[host, cookie])));
+        assert!(shared.authorized(&headers(&[("host", "localhost:8080"), cookie])));
+        assert!(shared.authorized(&headers(&[
+            host,
+            cookie,
+            ("origin", "http://127.0.0.1:8080")
+        ])));
+        assert!(!shared.authorized(&headers(&[host])));
+        assert!(!shared.authorized(&headers(&[host, ("cookie", "cellbook_token=guess")])));
+        assert!(!shared.authorized(&headers(&[
+            host,
+            cookie,
+            ("origin", "https://example.com")
+        ])));
+
+        // A rebound host name reaches the server, but with its own Host header.
+        let rebound = ("host", "attacker.example:8080");
+        assert!(!shared.known_host(&headers(&[rebound])));
+        assert!(!shared.authorized(&headers(&[
+            rebound,
+            cookie,
+            ("origin", "http://attacker.example:8080")
+        ])));
+        assert!(!shared.known_host(&headers(&[])));
+        assert_eq!(authority("::1", 8080), "[::1]:8080");
+    }
+
+    #[test]
+    fn test_any_host_on_the_port_reaches_a_server_on_all_addresses() {
+        let cookie = ("cookie", "cellbook_token=secret");
+        for shared in [shared("0.0.0.0"), shared("::")] {
+            let host = ("host", "notebook.lan:8080");
+            assert!(shared.authorized(&headers(&[host, cookie])));
+            assert!(shared.authorized(&headers(&[
+                ("host", "[fe80::1]:8080"),
+                cookie,
+                ("origin", "http://[fe80::1]:8080")
+            ])));
+            assert!(!shared.known_host(&headers(&[("host", "notebook.lan:9090")])));
+            assert!(!shared.known_host(&headers(&[("host", "notebook.lan")])));
+            assert!(!shared.authorized(&headers(&[host])));
+            assert!(!shared.authorized(&headers(&[
+                host,
+                cookie,
+                ("origin", "http://attacker.example:8080")
+            ])));
+        }
+        assert!(!shared("192.168.1.5").known_host(&headers(&[("host", "notebook.lan:8080")])));
+    }
+
+    #[test]
+    fn test_only_raster_images_are_shown_inline() {
+        assert!(is_raster_image("image/png"));
+        assert!(!is_raster_image("image/svg+xml"));
+        assert!(!is_raster_image("text/html"));
+    }
+}
@@ -0,0 +1,271 @@
+//! Cargo workspace detection and membership.
+//!
+//! Notebooks inside a workspace build into the workspace's shared target
+//! directory, and `init --workspace` adds new notebooks to its members.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use serde::Deserialize;
+use toml_edit::{Array, DocumentMut, TomlError, Value};
+
+use crate::errors::{Error, Result};
+
+/// What `cargo metadata` reports for the workspace of the current directory.
+#[derive(Debug, Deserialize)]
+pub struct Metadata {
+    pub workspace_root: PathBuf,
+    /// Where cargo builds to, following `CARGO_TARGET_DIR` and `build.target-dir`
+    /// in `.cargo/config.toml`.
+    pub target_directory: PathBuf,
+    /// Workspace members, or the current package outside a workspace.
+    pub packages: Vec<Package>,
+}
+
+/// A package with its name resolved, also when inherited from the workspace.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Package {
+    pub name: String,
+    pub manifest_path: PathBuf,
+    pub targets: Vec<Target>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Target {
+    pub name: String,
+    pub kind: Vec<String>,
+}
+
+impl Metadata {
+    /// The package whose manifest is `manifest_path`.
+    pub fn package(&self, manifest_path: &Path) -> Option<&Package> {
+        let manifest_path = manifest_path.canonicalize().ok()?;
+        self.packages.iter().find(|package| {
+            package
+                .manifest_path
+                .canonicalize()
+                .is_ok_and(|path| path == manifest_path)
+        })
+    }
+}
+
+impl Package {
+    /// Crate name of the library target, which `[lib] name` can set apart from the package name.
+    pub fn lib_name(&self) -> Option<&str> {
+        self.targets
+            .iter()
+            .find(|target| {
+                target
+                    .kind
+                    .iter()
+                    .any(|kind| matches!(kind.as_str(), "lib" | "cdylib" | "dylib" | "rlib"))
+            })
+            .map(|target| target.name.as_str())
+    }
+}
+
+/// Run `cargo metadata` with the build's environment variables.
+pub fn metadata(env: &BTreeMap<String, String>) -> Result<Metadata> {
+    let output = Command::new("cargo")
+        .args(["metadata", "--format-version", "1", "--no-deps"])
+        .envs(env)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Error::Metadata(stderr.trim().to_string()));
+    }
+
+    serde_json::from_slice(&output.stdout).map_err(|e| Error::Metadata(e.to_string()))
+}
+
+/// Root of the workspace containing `dir`, if any.
+///
+/// A directory whose `Cargo.toml` has a `[workspace]` table is a root. A root
+/// that excludes `dir` doesn't count, since `dir` then builds on its own.
+pub fn find_root(dir: &Path) -> Option<PathBuf> {
+    for root in dir.ancestors() {
+        let Ok(content) = std::fs::read_to_string(root.join("Cargo.toml")) else {
+            continue;
+        };
+        let Ok(manifest) = toml::from_str::<toml::Table>(&content) else {
+            continue;
+        };
+        let Some(workspace) = manifest.get("workspace").and_then(|w| w.as_table()) else {
+            continue;
+        };
+        let relative = dir.strip_prefix(root).unwrap_or(dir);
+        let excluded = strings(workspace, "exclude")
+            .iter()
+            .any(|path| relative.starts_with(path));
+        if !excluded {
+            return Some(root.to_path_buf());
+        }
+    }
+    None
+}
+
+/// Whether the `[workspace] members` of a manifest cover `member`, a path
+/// relative to the workspace root.
+pub fn is_member(manifest: &str, member: &str) -> Result<bool> {
+    let parsed: toml::Table = toml::from_str(manifest).map_err(|e| Error::Workspace(e.to_string()))?;
+    let workspace = parsed
+        .get("workspace")
+        .and_then(|w| w.as_table())
+        .ok_or_else(|| Error::Workspace("no [workspace] table in Cargo.toml".to_string()))?;
+    Ok(strings(workspace, "members")
+        .iter()
+        .any(|pattern| matches_member(pattern, member)))
+}
+
+/// Add `member` to the `[workspace] members` of a manifest, keeping its formatting.
+///
+/// Returns `None` if a member path or glob already covers it.
+pub fn add_member(manifest: &str, member: &str) -> Result<Option<String>> {
+    if is_member(manifest, member)? {
+        return Ok(None);
+    }
+
+    let mut document: DocumentMut = manifest
+        .parse()
+        .map_err(|e: TomlError| Error::Workspace(e.to_string()))?;
+    let workspace = document
+        .get_mut("workspace")
+        .and_then(|w| w.as_table_like_mut())
+        .ok_or_else(|| Error::Workspace("no [workspace] table in Cargo.toml".to_string()))?;
+    let Some(members) = workspace.get_mut("members") else {
+        workspace.insert("members", toml_edit::value(Array::from_iter([member])));
+        return Ok(Some(document.to_string()));
+    };
+    let members = members
+        .as_array_mut()
+        .ok_or_else(|| Error::Workspace("[workspace] members must be an array".to_string()))?;
+
+    // One member per line: add a line with the same indentation, after any
+    // comment that ends the last line.
+    let line_prefix = members
+        .iter()
+        .last()
+        .and_then(|last| last.decor().prefix()?.as_str())
+        .filter(|prefix| prefix.contains('\n'))
+        .map(String::from);
+    let Some(line_prefix) = line_prefix else {
+        members.push(member);
+        return Ok(Some(document.to_string()));
+    };
+    let mut tail = String::new();
+    if !members.trailing_comma()
+        && let Some(last) = members.iter_mut().last()
+    {
+        tail += last.decor().suffix().and_then(|s| s.as_str()).unwrap_or_default();
+        last.decor_mut().set_suffix("");
+    }
+    tail += members.trailing().as_str().unwrap_or_default();
+    let (comment, closing) = tail.split_at(tail.rfind('\n').unwrap_or(tail.len()));
+    let mut value = Value::from(member);
+    value
+        .decor_mut()
+        .set_prefix(format!("{}{}", comment, line_prefix));
+    let closing = closing.to_string();
+    members.push_formatted(value);
+    members.set_trailing_comma(true);
+    members.set_trailing(closing);
+    Ok(Some(document.to_string()))
+}
+
+/// Whether a `members` entry covers `member`. Supports a trailing `*` component.
+fn matches_member(pattern: &str, member: &str) -> bool {
+    let pattern = pattern.trim_end_matches('/');
+    match pattern.strip_suffix('*') {
+        Some(prefix) => member
+            .strip_prefix(prefix)
+            .is_some_and(|rest| !rest.is_empty() && !rest.contains('/')),
+        None => pattern == member,
+    }
+}
+
+fn strings(table: &toml::Table, key: &str) -> Vec<String> {
+    table
+        .get(key)
+        .and_then(|v| v.as_array())
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|v| v.as_str().map(String::from))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lib_name_follows_lib_target() {
+        let json = r#"{
+            "workspace_root": "/ws",
+            "target_directory": "/ws/target",
+            "packages": [
+                {"name": "tool", "manifest_path": "/ws/tool/Cargo.toml", "targets": [{"name": "tool", "kind": ["bin"]}]},
+                {"name": "my-notebook", "manifest_path": "/ws/nb/Cargo.toml", "targets": [
+                    {"name": "notebook_core", "kind": ["cdylib", "rlib"]},
+                    {"name": "helper", "kind": ["bin"]}
+                ]}
+            ]
+        }"#;
+        let metadata: Metadata = serde_json::from_str(json).unwrap();
+        assert_eq!(metadata.packages[1].lib_name(), Some("notebook_core"));
+        assert_eq!(metadata.packages[0].lib_name(), None);
+    }
+
+    #[test]
+    fn test_add_member_inline() {
+        let manifest = "[workspace]\nmembers = [\"core\"]\nresolver = \"2\"\n";
+        assert_eq!(
+            add_member(manifest, "notebooks/prices").unwrap().unwrap(),
+            "[workspace]\nmembers = [\"core\", \"notebooks/prices\"]\nresolver = \"2\"\n"
+        );
+        assert_eq!(
+            add_member("[workspace]\nmembers = []\n", "nb").unwrap().unwrap(),
+            "[workspace]\nmembers = [\"nb\"]\n"
+        );
+    }
+
+    #[test]
+    fn test_add_member_multiline_and_missing() {
+        let manifest = "[workspace]\nmembers = [\n    \"core\",\n    \"cli\"\n]\n";
+        assert_eq!(
+            add_member(manifest, "nb").unwrap().unwrap(),
+            "[workspace]\nmembers = [\n    \"core\",\n    \"cli\",\n    \"nb\",\n]\n"
+        );
+        assert_eq!(
+            add_member("[workspace]\nresolver = \"2\"\n", "nb")
+                .unwrap()
+                .unwrap(),
+            "[workspace]\nresolver = \"2\"\nmembers = [\"nb\"]\n"
+        );
+    }
+
+    #[test]
+    fn test_add_member_keeps_comments_and_other_tables() {
+        let manifest = "[workspace]\n# Crates [and notebooks]\nmembers = [\n    \"core\", # the ] library\n]\n\n[workspace.dependencies]\nmembers = \"1\"\n";
+        assert_eq!(
+            add_member(manifest, "nb").unwrap().unwrap(),
+            "[workspace]\n# Crates [and notebooks]\nmembers = [\n    \"core\", # the ] library\n    \"nb\",\n]\n\n[workspace.dependencies]\nmembers = \"1\"\n"
+        );
+        assert!(is_member("[workspace]\nmembers = [\"nb\"]\n", "nb").unwrap());
+        assert!(!is_member("[workspace]\n", "nb").unwrap());
+    }
+
+    #[test]
+    fn test_add_member_covered_by_glob() {
+        let manifest = "[workspace]\nmembers = [\"examples/*\"]\n";
+        assert_eq!(add_member(manifest, "examples/prices").unwrap(), None);
+        assert!(add_member(manifest, "examples/a/b").unwrap().is_some());
+        assert!(add_member("[package]\nname = \"x\"\n", "nb").is_err());
+    }
+}
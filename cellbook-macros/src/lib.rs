@@ -1,33 +1,258 @@
 use proc_macro::TokenStream;
-use quote::{format_ident, quote};
+use quote::{format_ident, quote, quote_spanned};
+use syn::punctuated::Punctuated;
 use syn::visit_mut::VisitMut;
-use syn::{DeriveInput, Expr, ExprLit, FnArg, ItemFn, Lit, Meta, MetaNameValue, parse_macro_input};
+use syn::{
+    Attribute,
+    Data,
+    DeriveInput,
+    Expr,
+    ExprLit,
+    Fields,
+    FnArg,
+    Ident,
+    Item,
+    ItemFn,
+    Lit,
+    Meta,
+    MetaNameValue,
+    Pat,
+    ReturnType,
+    Signature,
+    Token,
+    Type,
+    parse_macro_input,
+};
 
-/// Adds `ctx` prefix to context macro calls.
-struct CtxInjector;
+/// Adds the context argument to context macro calls, so `store!(data)` becomes
+/// `store!(ctx, data)`.
+struct CtxInjector(Ident);
+
+impl CtxInjector {
+    fn new() -> Self {
+        Self(format_ident!("ctx"))
+    }
+}
 
 impl VisitMut for CtxInjector {
+    // Functions and other items nested in the body can't see the context.
+    fn visit_item_mut(&mut self, _item: &mut Item) {}
+
     fn visit_macro_mut(&mut self, mac: &mut syn::Macro) {
         let path = &mac.path;
         let is_context_macro = path.is_ident("store")
+            || path.is_ident("store_as")
             || path.is_ident("storev")
             || path.is_ident("load")
             || path.is_ident("loadv")
             || path.is_ident("remove")
             || path.is_ident("consume")
-            || path.is_ident("consumev");
+            || path.is_ident("consumev")
+            || path.is_ident("store_df")
+            || path.is_ident("load_df");
 
         if is_context_macro {
+            let ctx = &self.0;
             let tokens = &mac.tokens;
-            mac.tokens = quote! { ctx, #tokens };
+            mac.tokens = quote! { #ctx, #tokens };
         }
     }
 }
 
+/// Name of a cell's declared context parameter, as in `async fn plot(ctx: &CellContext)`.
+fn context_param(sig: &Signature) -> Option<Ident> {
+    match sig.inputs.first()? {
+        FnArg::Typed(arg) if matches!(*arg.ty, Type::Reference(_)) => match &*arg.pat {
+            Pat::Ident(pat) => Some(pat.ident.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Check that a `#[cell]`, `#[init]` or `#[teardown]` function is `async` (cells
+/// may be plain functions), takes no parameters (cells may declare the context)
+/// and returns a `Result`.
+fn check_signature(sig: &Signature, attr: &str) -> syn::Result<()> {
+    if sig.asyncness.is_none() && attr != "cell" {
+        return Err(syn::Error::new_spanned(
+            sig.fn_token,
+            format!("#[{attr}] functions must be async: `async fn {}()`", sig.ident),
+        ));
+    }
+
+    let declared = usize::from(attr == "cell" && context_param(sig).is_some());
+    if let Some(arg) = sig.inputs.iter().nth(declared) {
+        let message = match attr {
+            "cell" => "#[cell] functions take no parameters other than `ctx: &CellContext`; \
+                       use `load!` to read values from the store"
+                .to_string(),
+            _ => format!("#[{attr}] functions take no parameters"),
+        };
+        return Err(syn::Error::new_spanned(arg, message));
+    }
+
+    let is_result = match &sig.output {
+        ReturnType::Type(_, ty) => match ty.as_ref() {
+            Type::Path(path) => path.path.segments.last().is_some_and(|s| s.ident == "Result"),
+            _ => false,
+        },
+        ReturnType::Default => false,
+    };
+    if !is_result {
+        let message = format!("#[{attr}] functions must return a `Result`, e.g. `cellbook::Result<()>`");
+        return Err(match &sig.output {
+            ReturnType::Type(_, ty) => syn::Error::new_spanned(ty, message),
+            ReturnType::Default => syn::Error::new_spanned(&sig.ident, message),
+        });
+    }
+
+    Ok(())
+}
+
+/// Options accepted by `#[cell(...)]`.
+const CELL_OPTIONS: &[&str] = &[
+    "isolated",
+    "hidden",
+    "blocking",
+    "no_inject",
+    "cwd",
+    "sandbox",
+    "service",
+    "name",
+    "desc",
+    "retries",
+    "backoff_ms",
+];
+
+/// Options given to `#[cell(...)]`.
+#[derive(Default)]
+struct CellOptions {
+    isolated: bool,
+    hidden: bool,
+    /// Poll the cell on a blocking thread.
+    blocking: bool,
+    /// Leave context macro calls as written.
+    no_inject: bool,
+    /// Working directory relative to the project root.
+    cwd: Option<String>,
+    /// Run in `.cellbook/scratch/`.
+    sandbox: bool,
+    /// Runs until stopped, beside other cells.
+    service: bool,
+    /// Display name shown instead of the function name.
+    title: Option<String>,
+    description: Option<String>,
+    /// Times a failed run is retried before the cell is marked failed.
+    retries: u32,
+    /// Wait before the first retry, doubled before each later one.
+    backoff_ms: u64,
+}
+
+/// Parse `#[cell(...)]` options.
+fn parse_cell_options(args: Punctuated<Meta, Token![,]>) -> syn::Result<CellOptions> {
+    let mut options = CellOptions::default();
+    for option in args {
+        let key = option
+            .path()
+            .get_ident()
+            .map(|i| i.to_string())
+            .unwrap_or_default();
+        match (key.as_str(), &option) {
+            ("isolated", Meta::Path(_)) => options.isolated = true,
+            ("hidden", Meta::Path(_)) => options.hidden = true,
+            ("blocking", Meta::Path(_)) => options.blocking = true,
+            ("no_inject", Meta::Path(_)) => options.no_inject = true,
+            ("sandbox", Meta::Path(_)) => options.sandbox = true,
+            ("service", Meta::Path(_)) => options.service = true,
+            ("name", Meta::NameValue(nv)) => options.title = Some(string_value(&nv.value)?),
+            ("desc", Meta::NameValue(nv)) => options.description = Some(string_value(&nv.value)?),
+            ("cwd", Meta::NameValue(nv)) => options.cwd = Some(string_value(&nv.value)?),
+            ("retries", Meta::NameValue(nv)) => options.retries = int_value(&nv.value)?,
+            ("backoff_ms", Meta::NameValue(nv)) => options.backoff_ms = int_value(&nv.value)?,
+            ("isolated" | "hidden" | "blocking" | "no_inject" | "sandbox" | "service", _) => {
+                return Err(syn::Error::new_spanned(&option, format!("expected `{}`", key)));
+            }
+            ("name" | "desc" | "cwd", _) => {
+                return Err(syn::Error::new_spanned(
+                    &option,
+                    format!("expected a string: `{} = \"...\"`", key),
+                ));
+            }
+            ("retries" | "backoff_ms", _) => {
+                return Err(syn::Error::new_spanned(
+                    &option,
+                    format!("expected an integer: `{} = 3`", key),
+                ));
+            }
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    option.path(),
+                    format!(
+                        "unknown cell option `{}`, expected one of: {}",
+                        key,
+                        CELL_OPTIONS.join(", ")
+                    ),
+                ));
+            }
+        }
+    }
+    if options.sandbox && options.cwd.is_some() {
+        return Err(syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "`cwd` and `sandbox` both set the working directory, use one of them",
+        ));
+    }
+    Ok(options)
+}
+
+fn string_value(value: &Expr) -> syn::Result<String> {
+    match value {
+        Expr::Lit(ExprLit { lit: Lit::Str(s), .. }) => Ok(s.value()),
+        _ => Err(syn::Error::new_spanned(value, "expected a string literal")),
+    }
+}
+
+fn int_value<N>(value: &Expr) -> syn::Result<N>
+where
+    N: std::str::FromStr,
+    N::Err: std::fmt::Display,
+{
+    match value {
+        Expr::Lit(ExprLit { lit: Lit::Int(i), .. }) => i.base10_parse(),
+        _ => Err(syn::Error::new_spanned(value, "expected an integer literal")),
+    }
+}
+
 /// Marks an async function as a cellbook cell.
 ///
+/// `#[cell(isolated)]` runs the cell in a separate worker process, so a crash
+/// in native code doesn't take down the runner.
+///
+/// `#[cell(hidden)]` leaves a helper cell out of the runner's cell list. It can
+/// still be run, for example from `run_on_start`.
+///
+/// `#[cell(name = "Load market data", desc = "Reads data/prices.csv")]` sets a
+/// display name and description shown by the runner instead of the function name.
+///
+/// `#[cell(cwd = "data")]` sets the cell's working directory relative to the
+/// project root, and `#[cell(sandbox)]` sets it to `.cellbook/scratch/`.
+/// `ctx.path(...)` resolves paths against it, and isolated cells run in it.
+///
+/// The cell's `///` doc comment is passed to the runner, which shows its first
+/// line under the cell name.
+///
+/// `#[cell(service)]` marks a cell that runs until stopped, such as a web
+/// server. The runner keeps it in the background so other cells can run beside it.
+///
+/// `#[cell(retries = 3, backoff_ms = 1000)]` retries a failed run up to three
+/// times before the cell is marked failed, waiting one second before the first
+/// retry and twice as long before each later one. Useful for cells that fetch
+/// from flaky network services.
+///
 /// The macro:
-/// - Adds a `ctx: CellContext` parameter
+/// - Adds a `ctx: &CellContext` parameter, unless the cell declares it
+/// - Passes the context to the store macros, so `store!(data)` becomes `store!(ctx, data)`
 /// - Generates a `#[no_mangle]` wrapper for FFI
 /// - Registers the cell with inventory
 ///
@@ -38,19 +263,100 @@ impl VisitMut for CtxInjector {
 ///     Ok(())
 /// }
 /// ```
+///
+/// A plain `fn` cell is run on a blocking thread by the runner, so CPU-bound work
+/// doesn't stall it:
+///
+/// ```ignore
+/// #[cell]
+/// fn fit() -> Result<()> {
+///     let points: Vec<(f64, f64)> = load!(points)?;
+///     store!(model = regression(&points))?;
+///     Ok(())
+/// }
+/// ```
+///
+/// `#[cell(blocking)]` does the same for an `async fn` that mixes awaits with
+/// long synchronous computations.
+///
+/// A cell that declares the context can name it and pass it on to helpers.
+/// Macro calls in functions nested in the cell body are never rewritten.
+///
+/// `#[cell(no_inject)]` leaves macro calls as written, for notebooks that
+/// define their own `store!` or `load!`. The context is then passed explicitly:
+///
+/// ```ignore
+/// #[cell(no_inject)]
+/// async fn my_cell(ctx: &CellContext) -> Result<()> {
+///     cellbook::store!(ctx, data)?;
+///     summarize(ctx)
+/// }
+/// ```
 #[proc_macro_attribute]
-pub fn cell(_attr: TokenStream, item: TokenStream) -> TokenStream {
+pub fn cell(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attr with Punctuated::<Meta, Token![,]>::parse_terminated);
+    let CellOptions {
+        isolated,
+        hidden,
+        blocking,
+        no_inject,
+        cwd,
+        sandbox,
+        service,
+        title,
+        description,
+        retries,
+        backoff_ms,
+    } = match parse_cell_options(args) {
+        Ok(options) => options,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let title = option_tokens(title);
+    let description = option_tokens(description);
+    let cwd = option_tokens(cwd);
+
     let mut input = parse_macro_input!(item as ItemFn);
+    if let Err(e) = check_signature(&input.sig, "cell") {
+        return e.to_compile_error().into();
+    }
 
     let fn_name = input.sig.ident.clone();
     let fn_name_str = fn_name.to_string();
     let wrapper_name = format_ident!("__cellbook_cell_{}", fn_name_str);
+    let doc = option_tokens(doc_comment(&input.attrs));
+    // A plain `fn` runs when the wrapper's future is first polled.
+    let is_async = input.sig.asyncness.is_some();
+    let blocking = blocking || !is_async;
+    let call = if !is_async {
+        quote! { #fn_name(&ctx) }
+    } else {
+        quote! { #fn_name(&ctx).await }
+    };
     let line = fn_name.span().start().line as u32;
 
-    CtxInjector.visit_item_fn_mut(&mut input);
+    // Cells are looked up by name, so two cells with the same name (e.g. in
+    // different modules) would be ambiguous. Exporting a macro per cell name
+    // turns that into a "defined multiple times" error pointing at both cells.
+    let unique_name = format_ident!("__cellbook_cell_name_must_be_unique__{}", fn_name_str);
+    let unique_check = quote_spanned! {fn_name.span()=>
+        #[doc(hidden)]
+        #[macro_export]
+        macro_rules! #unique_name {
+            () => {};
+        }
+    };
 
-    let ctx_param: FnArg = syn::parse_quote!(ctx: &::cellbook::CellContext);
-    input.sig.inputs.insert(0, ctx_param);
+    let ctx = match context_param(&input.sig) {
+        Some(ctx) => ctx,
+        None => {
+            let ctx_param: FnArg = syn::parse_quote!(ctx: &::cellbook::CellContext);
+            input.sig.inputs.insert(0, ctx_param);
+            format_ident!("ctx")
+        }
+    };
+    if !no_inject {
+        CtxInjector(ctx).visit_item_fn_mut(&mut input);
+    }
 
     let fn_vis = &input.vis;
     let fn_sig = &input.sig;
@@ -63,46 +369,166 @@ pub fn cell(_attr: TokenStream, item: TokenStream) -> TokenStream {
 
         #[doc(hidden)]
         #[unsafe(no_mangle)]
-        pub fn #wrapper_name(
-            store_fn: fn(&str, Vec<u8>, &str),
-            load_fn: fn(&str) -> Option<(Vec<u8>, String)>,
-            remove_fn: fn(&str) -> Option<(Vec<u8>, String)>,
-            list_fn: fn() -> Vec<(String, String)>,
-        ) -> ::cellbook::futures::future::BoxFuture<'static, ::std::result::Result<(), Box<dyn ::std::error::Error + Send + Sync>>> {
-            let ctx = ::cellbook::CellContext::new(store_fn, load_fn, remove_fn, list_fn);
-            Box::pin(async move {
-                #fn_name(&ctx)
-                    .await
-                    .map_err(|e| -> Box<dyn ::std::error::Error + Send + Sync> { e.into() })
-            })
+        pub extern "C" fn #wrapper_name(host: ::cellbook::context::HostFns) -> ::cellbook::ffi::FfiFuture {
+            let ctx = ::cellbook::CellContext::from_host(host);
+            ::cellbook::ffi::FfiFuture::cell(async move { #call })
         }
 
         ::cellbook::inventory::submit!(::cellbook::CellInfo {
             name: #fn_name_str,
             func: #wrapper_name,
             line: #line,
+            isolated: #isolated,
+            hidden: #hidden,
+            blocking: #blocking,
+            cwd: #cwd,
+            sandbox: #sandbox,
+            service: #service,
+            title: #title,
+            description: #description,
+            doc: #doc,
+            retries: #retries,
+            backoff_ms: #backoff_ms,
         });
+
+        #unique_check
     };
 
     TokenStream::from(expanded)
 }
 
+/// Text of the `///` doc comment on an item, without the leading space of each line.
+fn doc_comment(attrs: &[Attribute]) -> Option<String> {
+    let lines: Vec<String> = attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(MetaNameValue {
+                value: Expr::Lit(ExprLit { lit: Lit::Str(s), .. }),
+                ..
+            }) => Some(s.value()),
+            _ => None,
+        })
+        .map(|line| line.strip_prefix(' ').map(String::from).unwrap_or(line))
+        .collect();
+    let doc = lines.join("\n").trim().to_string();
+    (!doc.is_empty()).then_some(doc)
+}
+
+/// `Some("...")` or `None` as an `Option<&'static str>` expression.
+fn option_tokens(value: Option<String>) -> proc_macro2::TokenStream {
+    match value {
+        Some(value) => quote! { Some(#value) },
+        None => quote! { None },
+    }
+}
+
+/// Settings a notebook can declare in `#[init(...)]`, with the literal kind each accepts.
+const CONFIG_KEYS: &[(&str, &str)] = &[
+    ("auto_reload", "bool"),
+    ("debounce_ms", "int"),
+    ("image_viewer", "str"),
+    ("show_timings", "bool"),
+    ("clear_on_run", "bool"),
+    ("check_init", "bool"),
+    ("run_on_start", "str or [str]"),
+];
+
+/// Convert `#[init(key = value, ...)]` settings into `(key, TOML value)` pairs.
+fn parse_config(args: Punctuated<MetaNameValue, Token![,]>) -> syn::Result<Vec<(String, String)>> {
+    let mut config = Vec::new();
+    for MetaNameValue { path, value, .. } in args {
+        let key = path
+            .get_ident()
+            .map(|i| i.to_string())
+            .ok_or_else(|| syn::Error::new_spanned(&path, "expected a setting name"))?;
+        let Some(&(_, kind)) = CONFIG_KEYS.iter().find(|(k, _)| *k == key) else {
+            let known: Vec<_> = CONFIG_KEYS.iter().map(|(k, _)| *k).collect();
+            return Err(syn::Error::new_spanned(
+                &path,
+                format!("unknown setting `{}`, expected one of: {}", key, known.join(", ")),
+            ));
+        };
+
+        let toml = match (kind, &value) {
+            (
+                "bool",
+                Expr::Lit(ExprLit {
+                    lit: Lit::Bool(b), ..
+                }),
+            ) => b.value.to_string(),
+            ("int", Expr::Lit(ExprLit { lit: Lit::Int(i), .. })) => i.base10_parse::<u32>()?.to_string(),
+            ("str" | "str or [str]", Expr::Lit(ExprLit { lit: Lit::Str(s), .. })) => toml_string(&s.value()),
+            ("str or [str]", Expr::Array(array)) => {
+                let items = array
+                    .elems
+                    .iter()
+                    .map(|e| match e {
+                        Expr::Lit(ExprLit { lit: Lit::Str(s), .. }) => Ok(toml_string(&s.value())),
+                        _ => Err(syn::Error::new_spanned(e, "expected a string literal")),
+                    })
+                    .collect::<syn::Result<Vec<_>>>()?;
+                format!("[{}]", items.join(", "))
+            }
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &value,
+                    format!("`{}` expects a {} literal", key, kind),
+                ));
+            }
+        };
+        config.push((key, toml));
+    }
+    Ok(config)
+}
+
+/// Quote a string as a TOML basic string.
+fn toml_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04X}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
 /// Marks an async function as the required cellbook init entrypoint.
 ///
 /// The macro:
 /// - Keeps the function as-is (arbitrary function name)
-/// - Exports `__cellbook_get_cells`
-/// - Exports `__cellbook_get_init`
+/// - Exports `__cellbook_abi_version` and `__cellbook_version` for the runner's ABI check
+/// - Exports `__cellbook_manifest` with the cells, their options, the teardown
+///   function and the runner settings given as arguments, as JSON
+/// - Exports `__cellbook_cell_fn` to look up cell, init and teardown functions by name
 ///
 /// ```ignore
-/// #[init]
+/// #[init(show_timings = true, debounce_ms = 200)]
 /// async fn setup() -> Result<()> {
 ///     Ok(())
 /// }
 /// ```
 #[proc_macro_attribute]
-pub fn init(_attr: TokenStream, item: TokenStream) -> TokenStream {
+pub fn init(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attr with Punctuated::<MetaNameValue, Token![,]>::parse_terminated);
+    let config = match parse_config(args) {
+        Ok(config) => config,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let (config_keys, config_values): (Vec<_>, Vec<_>) = config.into_iter().unzip();
+
     let input = parse_macro_input!(item as ItemFn);
+    if let Err(e) = check_signature(&input.sig, "init") {
+        return e.to_compile_error().into();
+    }
+
     let fn_name = input.sig.ident.clone();
     let fn_name_str = fn_name.to_string();
     let wrapper_name = format_ident!("__cellbook_init_{}", fn_name_str);
@@ -119,38 +545,103 @@ pub fn init(_attr: TokenStream, item: TokenStream) -> TokenStream {
 
         #[doc(hidden)]
         #[unsafe(no_mangle)]
-        pub fn #wrapper_name() -> ::cellbook::futures::future::BoxFuture<'static, ::std::result::Result<(), Box<dyn ::std::error::Error + Send + Sync>>> {
-            Box::pin(async move {
-                #fn_name()
-                    .await
-                    .map_err(|e| -> Box<dyn ::std::error::Error + Send + Sync> { e.into() })
-            })
+        pub extern "C" fn #wrapper_name(host: ::cellbook::context::HostFns) -> ::cellbook::ffi::FfiFuture {
+            // Init takes no context, but creating one installs the host callbacks.
+            let _ = ::cellbook::CellContext::from_host(host);
+            ::cellbook::ffi::FfiFuture::cell(async move { #fn_name().await })
+        }
+
+        // Handshake read by the runner before any other symbol. Plain C values,
+        // so a runner built with another compiler or cellbook can read them.
+        #[unsafe(no_mangle)]
+        pub extern "C" fn __cellbook_abi_version() -> u32 {
+            ::cellbook::registry::ABI_VERSION
         }
 
         #[unsafe(no_mangle)]
-        pub extern "Rust" fn __cellbook_get_cells() -> Vec<(
-            String,
-            u32,
-            fn(
-                fn(&str, Vec<u8>, &str),
-                fn(&str) -> Option<(Vec<u8>, String)>,
-                fn(&str) -> Option<(Vec<u8>, String)>,
-                fn() -> Vec<(String, String)>,
-            ) -> ::cellbook::futures::future::BoxFuture<'static, ::std::result::Result<(), Box<dyn ::std::error::Error + Send + Sync>>>
-        )> {
-            ::cellbook::registry::cells()
-                .into_iter()
-                .map(|c| (c.name.to_string(), c.line, c.func))
-                .collect()
+        pub extern "C" fn __cellbook_version() -> *const ::std::ffi::c_char {
+            ::cellbook::registry::VERSION.as_ptr()
         }
 
+        // The manifest and function lookup only pass C strings and pointers,
+        // so the runner can read them whatever compiler built either side.
         #[unsafe(no_mangle)]
-        pub extern "Rust" fn __cellbook_get_init() -> (
-            String,
-            u32,
-            fn() -> ::cellbook::futures::future::BoxFuture<'static, ::std::result::Result<(), Box<dyn ::std::error::Error + Send + Sync>>>
-        ) {
-            (#fn_name_str.to_string(), #line, #wrapper_name)
+        pub extern "C" fn __cellbook_manifest() -> *const ::std::ffi::c_char {
+            ::cellbook::registry::manifest(#fn_name_str, #line, &[#((#config_keys, #config_values)),*])
+        }
+
+        #[unsafe(no_mangle)]
+        pub unsafe extern "C" fn __cellbook_cell_fn(name: *const ::std::ffi::c_char) -> *const ::std::ffi::c_void {
+            unsafe { ::cellbook::registry::cell_fn(name, #fn_name_str, #wrapper_name) }
+        }
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// Marks an async function to run when the runner exits and before each reload,
+/// so the notebook can flush files, close connections or persist state.
+///
+/// The macro:
+/// - Keeps the function as-is (arbitrary function name)
+/// - Registers it in the notebook's manifest
+///
+/// At most one `#[teardown]` function is allowed per notebook. Like cells,
+/// it can use the store macros and `ctx`.
+///
+/// ```ignore
+/// #[teardown]
+/// async fn cleanup() -> Result<()> {
+///     let db: Arc<Pool> = ctx.get_resource("db")?;
+///     db.close().await;
+///     Ok(())
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn teardown(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut input = parse_macro_input!(item as ItemFn);
+    if let Err(e) = check_signature(&input.sig, "teardown") {
+        return e.to_compile_error().into();
+    }
+
+    let fn_name = input.sig.ident.clone();
+    let fn_name_str = fn_name.to_string();
+    let wrapper_name = format_ident!("__cellbook_teardown_{}", fn_name_str);
+    let line = fn_name.span().start().line as u32;
+
+    // Like cells, teardown gets a context so it can reach the store and resources.
+    CtxInjector::new().visit_item_fn_mut(&mut input);
+
+    let ctx_param: FnArg = syn::parse_quote!(ctx: &::cellbook::CellContext);
+    input.sig.inputs.insert(0, ctx_param);
+
+    let fn_vis = &input.vis;
+    let fn_sig = &input.sig;
+    let fn_block = &input.block;
+    let fn_attrs = &input.attrs;
+
+    let expanded = quote! {
+        #(#fn_attrs)*
+        #fn_vis #fn_sig #fn_block
+
+        #[doc(hidden)]
+        #[unsafe(no_mangle)]
+        pub extern "C" fn #wrapper_name(host: ::cellbook::context::HostFns) -> ::cellbook::ffi::FfiFuture {
+            let ctx = ::cellbook::CellContext::from_host(host);
+            ::cellbook::ffi::FfiFuture::cell(async move { #fn_name(&ctx).await })
+        }
+
+        ::cellbook::inventory::submit!(::cellbook::registry::Teardown {
+            name: #fn_name_str,
+            func: #wrapper_name,
+            line: #line,
+        });
+
+        // A second `#[teardown]` exports the same macro, failing the build.
+        #[doc(hidden)]
+        #[macro_export]
+        macro_rules! __cellbook_teardown_must_be_unique {
+            () => {};
         }
     };
 
@@ -158,9 +649,12 @@ pub fn init(_attr: TokenStream, item: TokenStream) -> TokenStream {
 }
 
 /// Derive `cellbook::StoreSchema` with a version set by `#[store_schema(version = N)]`.
+///
+/// The type's fields, their types and doc comments become `StoreSchema::SCHEMA`.
 #[proc_macro_derive(StoreSchema, attributes(store_schema))]
 pub fn derive_store_schema(item: TokenStream) -> TokenStream {
     let input = parse_macro_input!(item as DeriveInput);
+    let schema = schema_outline(&input);
     let ident = input.ident;
     let generics = input.generics;
     let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
@@ -222,7 +716,117 @@ pub fn derive_store_schema(item: TokenStream) -> TokenStream {
     let expanded = quote! {
         impl #impl_generics ::cellbook::StoreSchema for #ident #ty_generics #where_clause {
             const VERSION: u32 = #version;
+            const SCHEMA: &'static str = #schema;
         }
     };
     TokenStream::from(expanded)
 }
+
+/// Outline of a type's definition, with doc comments and without attributes
+/// or bodies, e.g. `struct Stats {\n    mean: f64,\n}`.
+fn schema_outline(input: &DeriveInput) -> String {
+    let mut out = String::new();
+    push_docs(&mut out, &input.attrs, "");
+    let name = format!("{}{}", input.ident, tokens_string(&input.generics));
+    match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => {
+                out.push_str(&format!("struct {} {{\n", name));
+                for field in &fields.named {
+                    push_docs(&mut out, &field.attrs, "    ");
+                    let ident = field.ident.as_ref().expect("named field");
+                    out.push_str(&format!("    {}: {},\n", ident, tokens_string(&field.ty)));
+                }
+                out.push('}');
+            }
+            fields => out.push_str(&format!("struct {}{};", name, inline_fields(fields))),
+        },
+        Data::Enum(data) => {
+            out.push_str(&format!("enum {} {{\n", name));
+            for variant in &data.variants {
+                push_docs(&mut out, &variant.attrs, "    ");
+                out.push_str(&format!(
+                    "    {}{},\n",
+                    variant.ident,
+                    inline_fields(&variant.fields)
+                ));
+            }
+            out.push('}');
+        }
+        Data::Union(data) => {
+            out.push_str(&format!("union {} {{\n", name));
+            for field in &data.fields.named {
+                push_docs(&mut out, &field.attrs, "    ");
+                let ident = field.ident.as_ref().expect("named field");
+                out.push_str(&format!("    {}: {},\n", ident, tokens_string(&field.ty)));
+            }
+            out.push('}');
+        }
+    }
+    out
+}
+
+/// Fields on one line: ` { a: A, b: B }`, `(A, B)` or nothing.
+fn inline_fields(fields: &Fields) -> String {
+    match fields {
+        Fields::Named(fields) => {
+            let fields: Vec<String> = fields
+                .named
+                .iter()
+                .map(|f| {
+                    let ident = f.ident.as_ref().expect("named field");
+                    format!("{}: {}", ident, tokens_string(&f.ty))
+                })
+                .collect();
+            format!(" {{ {} }}", fields.join(", "))
+        }
+        Fields::Unnamed(fields) => {
+            let fields: Vec<String> = fields.unnamed.iter().map(|f| tokens_string(&f.ty)).collect();
+            format!("({})", fields.join(", "))
+        }
+        Fields::Unit => String::new(),
+    }
+}
+
+/// Append the `///` doc comments among `attrs`, indented by `indent`.
+fn push_docs(out: &mut String, attrs: &[Attribute], indent: &str) {
+    for attr in attrs {
+        if !attr.path().is_ident("doc") {
+            continue;
+        }
+        let Meta::NameValue(MetaNameValue {
+            value: Expr::Lit(ExprLit {
+                lit: Lit::Str(doc), ..
+            }),
+            ..
+        }) = &attr.meta
+        else {
+            continue;
+        };
+        let doc = doc.value();
+        let line = doc.strip_prefix(' ').unwrap_or(&doc);
+        out.push_str(&format!("{}/// {}\n", indent, line).replace("/// \n", "///\n"));
+    }
+}
+
+/// Tokens as source text, without the spaces `quote` puts around punctuation.
+fn tokens_string(tokens: &impl quote::ToTokens) -> String {
+    let mut text = tokens.to_token_stream().to_string();
+    for (from, to) in [
+        (" :: ", "::"),
+        (":: ", "::"),
+        (" <", "<"),
+        ("< ", "<"),
+        (" >", ">"),
+        (" ,", ","),
+        ("& ", "&"),
+        ("[ ", "["),
+        (" ]", "]"),
+        (" ;", ";"),
+        ("( ", "("),
+        (" )", ")"),
+    ] {
+        text = text.replace(from, to);
+    }
+    text
+}
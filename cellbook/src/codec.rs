@@ -0,0 +1,120 @@
+//! Serialization formats for stored values.
+//!
+//! Values are stored with postcard unless a cell picks another codec with
+//! [`store_as!`](crate::store_as) or [`CellContext::store_as`](crate::CellContext::store_as).
+//! The codec is recorded after the type name (`serde_json::Value@json`), so every
+//! load decodes the bytes with the codec they were written with.
+//!
+//! ```ignore
+//! store_as!(response, codec = json)?;
+//! let response: serde_json::Value = load!(response)?;
+//! ```
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+/// Format of a stored value's bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Codec {
+    /// Compact and fast, but not self-describing, so it can't handle types that
+    /// need one such as `#[serde(flatten)]`, `#[serde(untagged)]` or `serde_json::Value`.
+    #[default]
+    Postcard,
+    /// Self-describing and readable, at the cost of size. Map keys must be strings.
+    Json,
+    /// Compact and self-describing. Needs the `msgpack` feature.
+    MessagePack,
+}
+
+impl Codec {
+    pub fn name(self) -> &'static str {
+        match self {
+            Codec::Postcard => "postcard",
+            Codec::Json => "json",
+            Codec::MessagePack => "msgpack",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [Codec::Postcard, Codec::Json, Codec::MessagePack]
+            .into_iter()
+            .find(|codec| codec.name() == name)
+    }
+
+    /// Type name recorded for a value of `type_name` stored with this codec.
+    pub(crate) fn tag(self, type_name: &str) -> String {
+        match self {
+            Codec::Postcard => type_name.to_string(),
+            _ => format!("{}@{}", type_name, self.name()),
+        }
+    }
+
+    /// Split a recorded type name into the type and its codec.
+    /// Untagged names were stored with postcard.
+    pub(crate) fn split(tagged: &str) -> (&str, Codec) {
+        tagged
+            .rsplit_once('@')
+            .and_then(|(type_name, name)| Some((type_name, Codec::from_name(name)?)))
+            .unwrap_or((tagged, Codec::Postcard))
+    }
+
+    pub(crate) fn encode<T: Serialize + ?Sized>(self, value: &T) -> Result<Vec<u8>, String> {
+        match self {
+            Codec::Postcard => postcard::to_stdvec(value).map_err(|e| e.to_string()),
+            Codec::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            #[cfg(feature = "msgpack")]
+            Codec::MessagePack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+            #[cfg(not(feature = "msgpack"))]
+            Codec::MessagePack => Err(MSGPACK_DISABLED.to_string()),
+        }
+    }
+
+    pub(crate) fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T, String> {
+        match self {
+            Codec::Postcard => postcard::from_bytes(bytes).map_err(|e| e.to_string()),
+            Codec::Json => serde_json::from_slice(bytes).map_err(|e| e.to_string()),
+            #[cfg(feature = "msgpack")]
+            Codec::MessagePack => rmp_serde::from_slice(bytes).map_err(|e| e.to_string()),
+            #[cfg(not(feature = "msgpack"))]
+            Codec::MessagePack => Err(MSGPACK_DISABLED.to_string()),
+        }
+    }
+}
+
+#[cfg(not(feature = "msgpack"))]
+const MSGPACK_DISABLED: &str = "the msgpack codec needs the `msgpack` feature of cellbook";
+
+/// Codecs by the names `store_as!` accepts.
+#[doc(hidden)]
+#[allow(non_upper_case_globals)]
+pub mod by_name {
+    use super::Codec;
+
+    pub const postcard: Codec = Codec::Postcard;
+    pub const json: Codec = Codec::Json;
+    pub const msgpack: Codec = Codec::MessagePack;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tag_and_split() {
+        assert_eq!(Codec::Postcard.tag("u32"), "u32");
+        assert_eq!(Codec::Json.tag("u32#v2"), "u32#v2@json");
+        assert_eq!(Codec::split("u32#v2@json"), ("u32#v2", Codec::Json));
+        assert_eq!(Codec::split("u32"), ("u32", Codec::Postcard));
+        assert_eq!(Codec::split("u32@xml"), ("u32@xml", Codec::Postcard));
+    }
+
+    #[test]
+    fn test_json_handles_self_describing_types() {
+        let value = serde_json::json!({"rows": [1, 2], "ok": true});
+        let bytes = Codec::Postcard.encode(&value).unwrap();
+        assert!(Codec::Postcard.decode::<serde_json::Value>(&bytes).is_err());
+
+        let bytes = Codec::Json.encode(&value).unwrap();
+        assert_eq!(Codec::Json.decode::<serde_json::Value>(&bytes).unwrap(), value);
+    }
+}
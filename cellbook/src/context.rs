@@ -1,55 +1,258 @@
 //! Context handle for cells to access the host's store.
 //!
-//! Values are serialized with postcard, allowing them to survive hot-reloads.
-
-use std::any::type_name;
-
+//! Values are serialized with postcard (or another [`Codec`]), allowing them to survive hot-reloads.
+//! Background tasks and resources live in the host process and are dropped on reload.
+
+use std::any::{Any, type_name};
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, RwLock};
+use std::time::Duration;
+
+use parking_lot::Mutex;
 use serde::Serialize;
 use serde::de::DeserializeOwned;
 
 use crate::StoreSchema;
+use crate::codec::Codec;
 use crate::errors::{ContextError, Result};
+use crate::ffi::{FfiBuffer, FfiFuture, FfiHandle, FfiReader, FfiResource, FfiSlice, FfiStream, FfiString, FfiValue};
+use crate::run::RunInfo;
 
-pub type StoreFn = fn(&str, Vec<u8>, &str);
+pub type StoreFn = fn(&str, Vec<u8>, &str) -> std::result::Result<(), String>;
 pub type LoadFn = fn(&str) -> Option<(Vec<u8>, String)>;
 pub type RemoveFn = fn(&str) -> Option<(Vec<u8>, String)>;
 pub type ListFn = fn() -> Vec<(String, String)>;
+pub type Resource = Arc<dyn Any + Send + Sync>;
+/// Serialized value lent by the host, such as a memory-mapped file.
+pub type SharedBytes = Arc<dyn AsRef<[u8]> + Send + Sync>;
 
-/// Handle to the host's context store.
+/// Looks up a value the runner passes to notebook code, by the name of the
+/// environment variable that carries it when there is no runner.
+pub type VarFn = unsafe extern "C" fn(FfiSlice) -> FfiString;
+
+/// Records a file produced by the cell: its path, MIME type and whether it is temporary.
+pub type ArtifactFn = unsafe extern "C" fn(FfiSlice, FfiSlice, bool);
+
+/// Type name recorded for values stored with [`CellContext::store_stream`].
+pub const STREAM_TYPE_NAME: &str = "stream";
+
+/// Host callbacks passed to each cell across the dylib boundary.
 ///
-/// Passed to each cell to provide typed access to store/load operations.
-/// Types must implement `Serialize` for storing and `DeserializeOwned` for loading.
+/// Only C-ABI functions and [`ffi`](crate::ffi) values are used, so a runner built
+/// by another compiler can call them. Keys and type names are passed as UTF-8
+/// slices and errors come back as C strings. The host keeps a mirror of this
+/// struct, so field order and types must not change without updating
+/// `cargo-cellbook` as well.
+#[repr(C)]
 #[derive(Clone, Copy)]
-pub struct CellContext {
+pub struct HostFns {
+    /// Store `bytes` under a key with a type name, returning an error or none.
+    pub store_fn: unsafe extern "C" fn(FfiSlice, FfiSlice, FfiSlice) -> FfiString,
+    pub load_fn: unsafe extern "C" fn(FfiSlice) -> FfiValue,
+    /// Remove a key, returning whether it existed.
+    pub remove_fn: unsafe extern "C" fn(FfiSlice) -> bool,
+    /// Keys and type names as a JSON array of pairs.
+    pub list_fn: unsafe extern "C" fn() -> FfiString,
+    pub spawn_fn: unsafe extern "C" fn(FfiSlice, FfiFuture),
+    pub get_resource_fn: unsafe extern "C" fn(FfiSlice) -> FfiResource,
+    pub put_resource_fn: unsafe extern "C" fn(FfiSlice, FfiHandle, FfiSlice),
+    pub remove_resource_fn: unsafe extern "C" fn(FfiSlice) -> bool,
+    pub artifact_fn: ArtifactFn,
+    /// Expire a key after the given number of milliseconds.
+    pub expire_fn: unsafe extern "C" fn(FfiSlice, u64),
+    pub get_shared_fn: unsafe extern "C" fn(FfiSlice) -> FfiResource,
+    pub put_shared_fn: unsafe extern "C" fn(FfiSlice, FfiHandle, FfiSlice),
+    pub schema_fn: unsafe extern "C" fn(FfiSlice, FfiSlice),
+    /// Ask the user, resolving to the answer.
+    pub prompt_fn: unsafe extern "C" fn(FfiSlice) -> FfiFuture,
+    /// Streaming store and load, or `None` to go through `store_fn` and `load_fn`.
+    /// The store sets the number of bytes read and returns an error or none.
+    pub store_stream_fn: Option<unsafe extern "C" fn(FfiSlice, *mut FfiReader, FfiSlice, *mut u64) -> FfiString>,
+    pub load_stream_fn: Option<unsafe extern "C" fn(FfiSlice) -> FfiStream>,
+    /// Loads without copying values the host keeps in mapped files, or `None` to use `load_fn`.
+    pub load_bytes_fn: Option<unsafe extern "C" fn(FfiSlice) -> FfiValue>,
+    /// Absolute directory from `#[cell(cwd = "...")]` or `#[cell(sandbox)]`, or
+    /// [`FfiSlice::NONE`] for the project root. See [`CellContext::working_dir`].
+    pub working_dir: FfiSlice,
+    /// Runner values such as the project root, or `None` to read them from the environment.
+    pub var_fn: Option<VarFn>,
+}
+
+// Layout of ABI version 9, asserted the same way in `cargo-cellbook`.
+crate::ffi::assert_layout!(
+    HostFns,
+    20,
+    {
+        store_fn: 0,
+        load_fn: 1,
+        remove_fn: 2,
+        list_fn: 3,
+        spawn_fn: 4,
+        get_resource_fn: 5,
+        put_resource_fn: 6,
+        remove_resource_fn: 7,
+        artifact_fn: 8,
+        expire_fn: 9,
+        get_shared_fn: 10,
+        put_shared_fn: 11,
+        schema_fn: 12,
+        prompt_fn: 13,
+        store_stream_fn: 14,
+        load_stream_fn: 15,
+        load_bytes_fn: 16,
+        working_dir: 17,
+        var_fn: 19,
+    }
+);
+
+/// Host lookup of runner values, set when a cell context is created.
+static VAR_FN: RwLock<Option<VarFn>> = RwLock::new(None);
+
+/// A value passed by the runner, such as `CELLBOOK_PROJECT_ROOT`: asked from the
+/// host when running under one, else read from the environment variable of that name.
+pub(crate) fn runner_var(name: &str) -> Option<String> {
+    match VAR_FN.read().ok().and_then(|slot| *slot) {
+        Some(var_fn) => unsafe { var_fn(name.into()) }.into_string(),
+        None => std::env::var(name).ok(),
+    }
+}
+
+/// Store callbacks of a context made by [`CellContext::new`], used instead of the host's.
+#[derive(Clone, Copy)]
+struct LocalStore {
     store_fn: StoreFn,
     load_fn: LoadFn,
     remove_fn: RemoveFn,
     list_fn: ListFn,
 }
 
+/// Handle to the host's context store.
+///
+/// Passed to each cell to provide typed access to store/load operations.
+/// Types must implement `Serialize` for storing and `DeserializeOwned` for loading.
+#[derive(Clone, Copy)]
+pub struct CellContext {
+    host: HostFns,
+    local: Option<LocalStore>,
+}
+
 impl CellContext {
+    /// Create a context from store callbacks.
+    ///
+    /// Background tasks run on a dedicated thread and resources are kept in-process,
+    /// which is what tests without a host need.
     pub fn new(store_fn: StoreFn, load_fn: LoadFn, remove_fn: RemoveFn, list_fn: ListFn) -> Self {
-        Self {
+        let mut ctx = Self::from_host(HostFns {
+            store_fn: local_store,
+            load_fn: local_load,
+            remove_fn: local_remove,
+            list_fn: local_list,
+            spawn_fn: local_spawn,
+            get_resource_fn: local_get_resource,
+            put_resource_fn: local_put_resource,
+            remove_resource_fn: local_remove_resource,
+            artifact_fn: local_artifact,
+            expire_fn: local_expire,
+            get_shared_fn: local_get_shared,
+            put_shared_fn: local_put_shared,
+            schema_fn: local_schema,
+            prompt_fn: local_prompt,
+            store_stream_fn: None,
+            load_stream_fn: None,
+            load_bytes_fn: None,
+            working_dir: FfiSlice::NONE,
+            var_fn: None,
+        });
+        ctx.local = Some(LocalStore {
             store_fn,
             load_fn,
             remove_fn,
             list_fn,
+        });
+        ctx
+    }
+
+    /// Create a context from the full set of host callbacks.
+    ///
+    /// Also routes files produced by [`open_image`](crate::open_image) to the host's artifact list.
+    pub fn from_host(host: HostFns) -> Self {
+        crate::image::set_artifact_fn(host.artifact_fn);
+        if let Some(var_fn) = host.var_fn
+            && let Ok(mut slot) = VAR_FN.write()
+        {
+            *slot = Some(var_fn);
         }
+        Self { host, local: None }
+    }
+
+    /// Directory the cell works in: the one set with `#[cell(cwd = "...")]` or
+    /// `#[cell(sandbox)]`, else the project root.
+    ///
+    /// The process working directory is shared with the runner and other cells,
+    /// so it is only switched for isolated cells. Resolve paths with
+    /// [`path`](Self::path) instead of relying on it.
+    pub fn working_dir(&self) -> PathBuf {
+        if self.host.working_dir.is_none() {
+            return crate::project_root();
+        }
+        // SAFETY: The host hands out directories that live as long as the process.
+        PathBuf::from(unsafe { self.host.working_dir.to_str() }.into_owned())
+    }
+
+    /// Resolve `path` against the cell's [`working_dir`](Self::working_dir).
+    /// Absolute paths are returned unchanged.
+    pub fn path(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.working_dir().join(path)
     }
 
     /// Store a value with the given key.
     pub fn store<T: Serialize>(&self, key: &str, value: &T) -> Result<()> {
-        let bytes = postcard::to_stdvec(value).map_err(|e| ContextError::Serialization {
-            key: key.to_string(),
-            message: e.to_string(),
-        })?;
-        (self.store_fn)(key, bytes, type_name::<T>());
+        self.store_as(key, value, Codec::Postcard)
+    }
+
+    /// Store a value serialized with `codec` instead of postcard.
+    ///
+    /// The codec is recorded with the value, so the usual loads decode it.
+    pub fn store_as<T: Serialize>(&self, key: &str, value: &T, codec: Codec) -> Result<()> {
+        let bytes = codec
+            .encode(value)
+            .map_err(|message| ContextError::Serialization {
+                key: key.to_string(),
+                message,
+            })?;
+        self.store_raw(key, bytes, &codec.tag(type_name::<T>()))
+            .map_err(|message| ContextError::Rejected {
+                key: key.to_string(),
+                message,
+            })?;
+        Ok(())
+    }
+
+    /// Store a value that expires `ttl` from now.
+    ///
+    /// Once expired the runner removes the value, or marks it stale in the store
+    /// pane if `store_expiry = "mark"`. Storing the key again without a lifetime
+    /// keeps it indefinitely.
+    pub fn store_with_ttl<T: Serialize>(&self, key: &str, value: &T, ttl: Duration) -> Result<()> {
+        self.store(key, value)?;
+        let ttl_ms = u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX);
+        unsafe { (self.host.expire_fn)(key.into(), ttl_ms) };
         Ok(())
     }
 
     /// Store a versioned value with the given key.
+    ///
+    /// Also registers the type's [`StoreSchema::SCHEMA`] for the store inspector.
     pub fn store_versioned<T: Serialize + StoreSchema>(&self, key: &str, value: &T) -> Result<()> {
-        self.store_versioned_with(key, value, T::VERSION)
+        self.store_versioned_with(key, value, T::VERSION)?;
+        if !T::SCHEMA.is_empty() {
+            let type_name = format!("{}#v{}", type_name::<T>(), T::VERSION);
+            unsafe { (self.host.schema_fn)(type_name.as_str().into(), T::SCHEMA.into()) };
+        }
+        Ok(())
     }
 
     /// Store a value with an explicit schema version.
@@ -59,59 +262,83 @@ impl CellContext {
             message: e.to_string(),
         })?;
         let tagged_type_name = format!("{}#v{}", type_name::<T>(), version);
-        (self.store_fn)(key, bytes, &tagged_type_name);
+        self.store_raw(key, bytes, &tagged_type_name)
+            .map_err(|message| ContextError::Rejected {
+                key: key.to_string(),
+                message,
+            })?;
         Ok(())
     }
 
     /// Load a value by key.
     pub fn load<T: DeserializeOwned>(&self, key: &str) -> Result<T> {
-        let (bytes, stored_type_name) =
-            (self.load_fn)(key).ok_or_else(|| ContextError::NotFound(key.to_string()))?;
+        let (bytes, tagged_type_name) = self.load_bytes(key)?;
+        let (stored_type_name, codec) = Codec::split(&tagged_type_name);
         let requested_type_name = type_name::<T>();
         if stored_type_name != requested_type_name {
             return Err(ContextError::TypeMismatch {
                 key: key.to_string(),
                 expected: requested_type_name.to_string(),
-                found: stored_type_name,
+                found: stored_type_name.to_string(),
             }
             .into());
         }
 
-        postcard::from_bytes(&bytes).map_err(|e| {
-            ContextError::Deserialization {
-                key: key.to_string(),
-                message: e.to_string(),
-            }
-            .into()
-        })
+        Self::decode(key, (*bytes).as_ref(), codec)
+    }
+
+    /// Load a value by key, deserializing it only once.
+    ///
+    /// The host keeps the deserialized value and hands the same `Arc` to every
+    /// later `load_shared` of the key, until the key is stored again. Large values
+    /// read by several cells then take memory once instead of once per load.
+    /// Cached values are dropped when the notebook reloads.
+    pub fn load_shared<T: DeserializeOwned + Send + Sync + 'static>(&self, key: &str) -> Result<Arc<T>> {
+        let shared = unsafe { (self.host.get_shared_fn)(key.into()) };
+        if let Some((value, stored_type_name)) = unsafe { resource_of(shared) }
+            && stored_type_name == type_name::<T>()
+            && let Ok(value) = value.downcast::<T>()
+        {
+            return Ok(value);
+        }
+
+        let value = Arc::new(self.load::<T>(key)?);
+        unsafe {
+            (self.host.put_shared_fn)(
+                key.into(),
+                FfiHandle::new(value.clone()),
+                type_name::<T>().into(),
+            )
+        };
+        Ok(value)
     }
 
     /// Remove a value by key.
     /// Returns true if the key existed.
     pub fn remove(&self, key: &str) -> bool {
-        (self.remove_fn)(key).is_some()
+        match self.local {
+            Some(local) => (local.remove_fn)(key).is_some(),
+            None => unsafe { (self.host.remove_fn)(key.into()) },
+        }
     }
 
     /// Load and remove a value in one operation.
     pub fn consume<T: DeserializeOwned>(&self, key: &str) -> Result<T> {
-        let (bytes, stored_type_name) =
-            (self.load_fn)(key).ok_or_else(|| ContextError::NotFound(key.to_string()))?;
+        let (bytes, tagged_type_name) = self.load_bytes(key)?;
+        let (stored_type_name, codec) = Codec::split(&tagged_type_name);
         let requested_type_name = type_name::<T>();
         if stored_type_name != requested_type_name {
             return Err(ContextError::TypeMismatch {
                 key: key.to_string(),
                 expected: requested_type_name.to_string(),
-                found: stored_type_name,
+                found: stored_type_name.to_string(),
             }
             .into());
         }
 
-        let value = postcard::from_bytes(&bytes).map_err(|e| ContextError::Deserialization {
-            key: key.to_string(),
-            message: e.to_string(),
-        })?;
+        let value = Self::decode(key, (*bytes).as_ref(), codec)?;
 
-        let _ = (self.remove_fn)(key);
+        self.remove(key);
         Ok(value)
     }
 
@@ -122,17 +349,11 @@ impl CellContext {
 
     /// Load a value by key with an explicit expected schema version.
     pub fn load_versioned_with<T: DeserializeOwned>(&self, key: &str, version: u32) -> Result<T> {
-        let (bytes, stored_type_name) =
-            (self.load_fn)(key).ok_or_else(|| ContextError::NotFound(key.to_string()))?;
-        Self::validate_versioned_type(key, &stored_type_name, type_name::<T>(), version)?;
+        let (bytes, tagged_type_name) = self.load_bytes(key)?;
+        let (stored_type_name, codec) = Codec::split(&tagged_type_name);
+        Self::validate_versioned_type(key, stored_type_name, type_name::<T>(), version)?;
 
-        postcard::from_bytes(&bytes).map_err(|e| {
-            ContextError::Deserialization {
-                key: key.to_string(),
-                message: e.to_string(),
-            }
-            .into()
-        })
+        Self::decode(key, (*bytes).as_ref(), codec)
     }
 
     /// Load and remove a versioned value in one operation.
@@ -142,21 +363,224 @@ impl CellContext {
 
     /// Load and remove a value with an explicit expected schema version.
     pub fn consume_versioned_with<T: DeserializeOwned>(&self, key: &str, version: u32) -> Result<T> {
-        let (bytes, stored_type_name) =
-            (self.load_fn)(key).ok_or_else(|| ContextError::NotFound(key.to_string()))?;
-        Self::validate_versioned_type(key, &stored_type_name, type_name::<T>(), version)?;
+        let (bytes, tagged_type_name) = self.load_bytes(key)?;
+        let (stored_type_name, codec) = Codec::split(&tagged_type_name);
+        Self::validate_versioned_type(key, stored_type_name, type_name::<T>(), version)?;
 
-        let value = postcard::from_bytes(&bytes).map_err(|e| ContextError::Deserialization {
-            key: key.to_string(),
-            message: e.to_string(),
-        })?;
-        let _ = (self.remove_fn)(key);
+        let value = Self::decode(key, (*bytes).as_ref(), codec)?;
+        self.remove(key);
         Ok(value)
     }
 
+    /// Store the bytes read from `reader` as they are, returning how many were stored.
+    ///
+    /// Values too large to hold in memory are written to disk by the host as
+    /// they are read, so neither the cell nor the runner keeps a full copy.
+    /// Load them back with [`CellContext::load_stream`].
+    ///
+    /// ```ignore
+    /// let file = std::fs::File::open("data/events.parquet")?;
+    /// ctx.store_stream("events", file)?;
+    /// ```
+    pub fn store_stream(&self, key: &str, mut reader: impl Read) -> Result<u64> {
+        let rejected = |message| ContextError::Rejected {
+            key: key.to_string(),
+            message,
+        };
+        let stored = match self.host.store_stream_fn {
+            Some(store_stream_fn) => {
+                let mut reader: &mut dyn Read = &mut reader;
+                let mut reader = FfiReader::borrowed(&mut reader);
+                let mut len = 0;
+                let error =
+                    unsafe { store_stream_fn(key.into(), &mut reader, STREAM_TYPE_NAME.into(), &mut len) };
+                error.into_string().map_or(Ok(len), Err)
+            }
+            _ => {
+                let mut bytes = Vec::new();
+                reader.read_to_end(&mut bytes)?;
+                let len = bytes.len() as u64;
+                self.store_raw(key, bytes, STREAM_TYPE_NAME).map(|()| len)
+            }
+        };
+        Ok(stored.map_err(rejected)?)
+    }
+
+    /// Reader over a value stored with [`CellContext::store_stream`].
+    pub fn load_stream(&self, key: &str) -> Result<Box<dyn Read + Send>> {
+        let loaded = match self.host.load_stream_fn {
+            Some(load_stream_fn) => {
+                let stream = unsafe { load_stream_fn(key.into()) };
+                let reader = stream.reader;
+                stream
+                    .type_name
+                    .into_string()
+                    .map(|type_name| (Box::new(reader) as Box<dyn Read + Send>, type_name))
+            }
+            _ => self.load_raw(key).map(|(bytes, type_name)| {
+                let reader = Cursor::new(ReadBytes(bytes));
+                (Box::new(reader) as Box<dyn Read + Send>, type_name)
+            }),
+        };
+        let (reader, stored_type_name) = loaded.ok_or_else(|| ContextError::NotFound(key.to_string()))?;
+        if stored_type_name != STREAM_TYPE_NAME {
+            return Err(ContextError::TypeMismatch {
+                key: key.to_string(),
+                expected: STREAM_TYPE_NAME.to_string(),
+                found: Codec::split(&stored_type_name).0.to_string(),
+            }
+            .into());
+        }
+        Ok(reader)
+    }
+
     /// List all keys and their type names.
     pub fn list(&self) -> Vec<(String, String)> {
-        (self.list_fn)()
+        match self.local {
+            Some(local) => (local.list_fn)(),
+            None => unsafe { (self.host.list_fn)() }
+                .into_string()
+                .and_then(|json| serde_json::from_str(&json).ok())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Spawn a background task that outlives the cell.
+    ///
+    /// Tasks are listed in the runner's tasks pane, can be cancelled from there,
+    /// and are aborted before the notebook is reloaded.
+    pub fn spawn_background<F, E>(&self, name: &str, future: F)
+    where
+        F: Future<Output = std::result::Result<(), E>> + Send + 'static,
+        E: std::fmt::Display,
+    {
+        let future = FfiFuture::new(async move {
+            future
+                .await
+                .map(|()| String::new())
+                .map_err(|e| e.to_string())
+        });
+        unsafe { (self.host.spawn_fn)(name.into(), future) };
+    }
+
+    /// Get a shared resource by key, creating it with `init` if missing.
+    ///
+    /// If the key holds a value of a different type it is replaced.
+    /// See [`CellContext::put_resource`] for how resources differ from stored values.
+    pub fn resource<T, F>(&self, key: &str, init: F) -> Arc<T>
+    where
+        T: Send + Sync + 'static,
+        F: FnOnce() -> T,
+    {
+        if let Ok(value) = self.get_resource::<T>(key) {
+            return value;
+        }
+
+        self.put_resource(key, init())
+    }
+
+    /// Put a resource into the host's resource registry.
+    ///
+    /// Resources are kept as `Arc<dyn Any>` in the host process instead of being serialized,
+    /// so they can hold clients, connections and other non-serializable values.
+    /// They are separate from the context store and are dropped when the notebook reloads.
+    pub fn put_resource<T: Send + Sync + 'static>(&self, key: &str, value: T) -> Arc<T> {
+        let value = Arc::new(value);
+        unsafe {
+            (self.host.put_resource_fn)(
+                key.into(),
+                FfiHandle::new(value.clone()),
+                type_name::<T>().into(),
+            )
+        };
+        value
+    }
+
+    /// Get a resource by key.
+    pub fn get_resource<T: Send + Sync + 'static>(&self, key: &str) -> Result<Arc<T>> {
+        let resource = unsafe { (self.host.get_resource_fn)(key.into()) };
+        let (value, stored_type_name) = unsafe { resource_of(resource) }
+            .ok_or_else(|| ContextError::ResourceNotFound(key.to_string()))?;
+        value.downcast::<T>().map_err(|_| {
+            ContextError::ResourceTypeMismatch {
+                key: key.to_string(),
+                expected: type_name::<T>().to_string(),
+                found: stored_type_name,
+            }
+            .into()
+        })
+    }
+
+    /// Remove a resource by key.
+    /// Returns true if the key existed.
+    pub fn remove_resource(&self, key: &str) -> bool {
+        unsafe { (self.host.remove_resource_fn)(key.into()) }
+    }
+
+    /// Ask the user for a line of input and wait for it, without the line ending.
+    ///
+    /// The TUI shows the message in a popup, and the line runners print it and
+    /// read the answer from stdin. Fails where nothing can answer, such as in exports.
+    ///
+    /// ```ignore
+    /// let name = ctx.prompt("Dataset name?").await?;
+    /// ```
+    pub async fn prompt(&self, message: &str) -> Result<String> {
+        let answer = unsafe { (self.host.prompt_fn)(message.into()) };
+        answer.await.map_err(|e| ContextError::Prompt(e).into())
+    }
+
+    /// Ask a yes/no question, true if the answer is `y` or `yes` in any case.
+    ///
+    /// ```ignore
+    /// if !ctx.confirm("Delete 1M rows? y/n").await? {
+    ///     return Ok(());
+    /// }
+    /// ```
+    pub async fn confirm(&self, message: &str) -> Result<bool> {
+        let answer = self.prompt(message).await?;
+        Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+    }
+
+    /// Seed and start time of the current run, for reproducible results.
+    pub fn run_info(&self) -> RunInfo {
+        RunInfo::current()
+    }
+
+    fn store_raw(&self, key: &str, bytes: Vec<u8>, type_name: &str) -> std::result::Result<(), String> {
+        match self.local {
+            Some(local) => (local.store_fn)(key, bytes, type_name),
+            None => {
+                let error = unsafe { (self.host.store_fn)(key.into(), bytes[..].into(), type_name.into()) };
+                error.into_string().map_or(Ok(()), Err)
+            }
+        }
+    }
+
+    fn load_raw(&self, key: &str) -> Option<(SharedBytes, String)> {
+        match self.local {
+            Some(local) => (local.load_fn)(key).map(|(bytes, type_name)| (Arc::new(bytes) as SharedBytes, type_name)),
+            None => shared_value(unsafe { (self.host.load_fn)(key.into()) }),
+        }
+    }
+
+    /// Serialized value under `key`, borrowed from the host when it supports that.
+    fn load_bytes(&self, key: &str) -> Result<(SharedBytes, String)> {
+        let loaded = match self.host.load_bytes_fn {
+            Some(load_bytes_fn) => shared_value(unsafe { load_bytes_fn(key.into()) }),
+            _ => self.load_raw(key),
+        };
+        Ok(loaded.ok_or_else(|| ContextError::NotFound(key.to_string()))?)
+    }
+
+    fn decode<T: DeserializeOwned>(key: &str, bytes: &[u8], codec: Codec) -> Result<T> {
+        codec.decode(bytes).map_err(|message| {
+            ContextError::Deserialization {
+                key: key.to_string(),
+                message,
+            }
+            .into()
+        })
     }
 
     fn validate_versioned_type(
@@ -211,7 +635,108 @@ impl CellContext {
     }
 }
 
-// SAFETY: CellContext only contains function pointers which are Send + Sync.
+/// Value and type name of a resource handed back by the host.
+///
+/// # Safety
+///
+/// Resources are only put by this library, which the host drops them before reloading.
+unsafe fn resource_of(resource: FfiResource) -> Option<(Resource, String)> {
+    let type_name = resource.type_name.into_string()?;
+    Some((unsafe { resource.handle.into_resource() }?, type_name))
+}
+
+fn shared_value(value: FfiValue) -> Option<(SharedBytes, String)> {
+    value
+        .into_option()
+        .map(|(bytes, type_name)| (Arc::new(bytes) as SharedBytes, type_name))
+}
+
+/// Loaded bytes read as a stream.
+struct ReadBytes(SharedBytes);
+
+impl AsRef<[u8]> for ReadBytes {
+    fn as_ref(&self) -> &[u8] {
+        (*self.0).as_ref()
+    }
+}
+
+static LOCAL_RESOURCES: LazyLock<Mutex<HashMap<String, (Resource, String)>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// Contexts made by `CellContext::new` store through their `LocalStore` instead.
+unsafe extern "C" fn local_store(_key: FfiSlice, _bytes: FfiSlice, _type_name: FfiSlice) -> FfiString {
+    FfiString::new("no host store")
+}
+
+unsafe extern "C" fn local_load(_key: FfiSlice) -> FfiValue {
+    FfiValue {
+        bytes: FfiBuffer::new(Vec::new()),
+        type_name: FfiString::none(),
+    }
+}
+
+unsafe extern "C" fn local_remove(_key: FfiSlice) -> bool {
+    false
+}
+
+unsafe extern "C" fn local_list() -> FfiString {
+    FfiString::none()
+}
+
+unsafe extern "C" fn local_spawn(_name: FfiSlice, future: FfiFuture) {
+    std::thread::spawn(move || {
+        let _ = futures::executor::block_on(future);
+    });
+}
+
+fn local_resource(resource: Option<(Resource, String)>) -> FfiResource {
+    match resource {
+        Some((value, type_name)) => FfiResource {
+            handle: FfiHandle::new(value),
+            type_name: FfiString::new(type_name),
+        },
+        None => FfiResource {
+            handle: FfiHandle::null(),
+            type_name: FfiString::none(),
+        },
+    }
+}
+
+unsafe extern "C" fn local_get_resource(key: FfiSlice) -> FfiResource {
+    local_resource(LOCAL_RESOURCES.lock().get(&*unsafe { key.to_str() }).cloned())
+}
+
+unsafe extern "C" fn local_put_resource(key: FfiSlice, value: FfiHandle, type_name: FfiSlice) {
+    let Some(value) = (unsafe { value.into_resource() }) else {
+        return;
+    };
+    let (key, type_name) = unsafe { (key.to_str().into_owned(), type_name.to_str().into_owned()) };
+    LOCAL_RESOURCES.lock().insert(key, (value, type_name));
+}
+
+unsafe extern "C" fn local_remove_resource(key: FfiSlice) -> bool {
+    LOCAL_RESOURCES.lock().remove(&*unsafe { key.to_str() }).is_some()
+}
+
+unsafe extern "C" fn local_artifact(_path: FfiSlice, _mime: FfiSlice, _temporary: bool) {}
+
+unsafe extern "C" fn local_expire(_key: FfiSlice, _ttl_ms: u64) {}
+
+unsafe extern "C" fn local_prompt(_message: FfiSlice) -> FfiFuture {
+    FfiFuture::new(async { Err("no runner to answer prompts".to_string()) })
+}
+
+// Without a host there is no store version to check a cached value against.
+unsafe extern "C" fn local_get_shared(_key: FfiSlice) -> FfiResource {
+    local_resource(None)
+}
+
+unsafe extern "C" fn local_put_shared(_key: FfiSlice, _value: FfiHandle, _type_name: FfiSlice) {}
+
+unsafe extern "C" fn local_schema(_type_name: FfiSlice, _schema: FfiSlice) {}
+
+// SAFETY: CellContext only contains function pointers, which are Send + Sync, and
+// a working directory the host keeps for the rest of the process.
 unsafe impl Send for CellContext {}
 unsafe impl Sync for CellContext {}
 
@@ -231,10 +756,11 @@ mod tests {
     static STORE: LazyLock<Mutex<HashMap<String, StoredValue>>> =
         LazyLock::new(|| Mutex::new(HashMap::new()));
 
-    fn store(key: &str, bytes: Vec<u8>, type_name: &str) {
+    fn store(key: &str, bytes: Vec<u8>, type_name: &str) -> std::result::Result<(), String> {
         STORE
             .lock()
             .insert(key.to_string(), (bytes, type_name.to_string()));
+        Ok(())
     }
 
     fn load(key: &str) -> Option<(Vec<u8>, String)> {
@@ -290,6 +816,40 @@ mod tests {
         assert_eq!(still_present, value);
     }
 
+    #[test]
+    fn paths_resolve_against_the_working_dir() {
+        let ctx = CellContext::new(store, load, remove, list);
+        assert_eq!(ctx.working_dir(), crate::project_root());
+
+        let ctx = CellContext::from_host(HostFns {
+            working_dir: FfiSlice::from("/tmp/notebook/data"),
+            ..ctx.host
+        });
+        assert_eq!(ctx.path("prices.csv"), Path::new("/tmp/notebook/data/prices.csv"));
+        assert_eq!(ctx.path("/etc/hosts"), Path::new("/etc/hosts"));
+    }
+
+    #[test]
+    fn streams_fall_back_to_whole_values() {
+        let ctx = CellContext::new(store, load, remove, list);
+        let stored = ctx.store_stream("stream_data", &b"line 1\nline 2\n"[..]).unwrap();
+        assert_eq!(stored, 14);
+
+        let mut read = String::new();
+        ctx.load_stream("stream_data")
+            .unwrap()
+            .read_to_string(&mut read)
+            .unwrap();
+        assert_eq!(read, "line 1\nline 2\n");
+
+        ctx.store("stream_data", &7u32).unwrap();
+        let err = ctx
+            .load_stream("stream_data")
+            .map(|_| ())
+            .expect_err("load should fail");
+        assert!(matches!(err, Error::Context(ContextError::TypeMismatch { .. })));
+    }
+
     #[derive(Debug, Serialize, Deserialize, PartialEq)]
     struct VersionedData {
         value: u32,
@@ -318,7 +878,7 @@ mod tests {
         let value = VersionedData { value: 7 };
         let bytes = postcard::to_stdvec(&value).expect("serialization should succeed");
         let tagged_type_name = format!("{}#v99", std::any::type_name::<VersionedData>());
-        store("versioned_data", bytes, &tagged_type_name);
+        store("versioned_data", bytes, &tagged_type_name).unwrap();
 
         let err = ctx
             .load_versioned::<VersionedData>("versioned_data")
@@ -338,7 +898,7 @@ mod tests {
         let value = VersionedData { value: 9 };
         let bytes = postcard::to_stdvec(&value).expect("serialization should succeed");
         let tagged_type_name = format!("{}#v3", std::any::type_name::<VersionedData>());
-        store("versioned_data", bytes, &tagged_type_name);
+        store("versioned_data", bytes, &tagged_type_name).unwrap();
 
         let err = ctx
             .consume_versioned::<VersionedData>("versioned_data")
@@ -370,6 +930,53 @@ mod tests {
         assert_eq!(loaded, value);
     }
 
+    #[test]
+    fn resource_is_initialized_once() {
+        let ctx = CellContext::new(store, load, remove, list);
+        let first = ctx.resource("client", || vec![1u32, 2, 3]);
+        let second = ctx.resource("client", || -> Vec<u32> {
+            panic!("should reuse existing resource")
+        });
+        assert!(Arc::ptr_eq(&first, &second));
+    }
+
+    #[test]
+    fn resource_is_replaced_on_type_change() {
+        let ctx = CellContext::new(store, load, remove, list);
+        let _ = ctx.resource("counter", || 1u32);
+        let replaced = ctx.resource("counter", || "one".to_string());
+        assert_eq!(*replaced, "one");
+    }
+
+    #[test]
+    fn get_resource_rejects_type_mismatch() {
+        let ctx = CellContext::new(store, load, remove, list);
+        ctx.put_resource("conn", 5u64);
+
+        let err = ctx
+            .get_resource::<String>("conn")
+            .expect_err("get_resource should fail");
+        let Error::Context(ContextError::ResourceTypeMismatch { key, expected, found }) = err else {
+            panic!("expected resource type mismatch error");
+        };
+
+        assert_eq!(key, "conn");
+        assert_eq!(expected, std::any::type_name::<String>());
+        assert_eq!(found, std::any::type_name::<u64>());
+    }
+
+    #[test]
+    fn remove_resource_reports_existence() {
+        let ctx = CellContext::new(store, load, remove, list);
+        ctx.put_resource("temp_conn", 1u8);
+        assert!(ctx.remove_resource("temp_conn"));
+        assert!(!ctx.remove_resource("temp_conn"));
+        assert!(matches!(
+            ctx.get_resource::<u8>("temp_conn"),
+            Err(Error::Context(ContextError::ResourceNotFound(_)))
+        ));
+    }
+
     #[test]
     fn load_versioned_with_rejects_schema_mismatch() {
         let ctx = CellContext::new(store, load, remove, list);
@@ -0,0 +1,152 @@
+//! Polars data frame loaders and store helpers.
+//!
+//! Paths are resolved relative to the project root. Frames are kept in the
+//! store as Parquet, together with a hash of each file they were read from, so
+//! cells that load them can tell when the data on disk has changed.
+//!
+//! ```ignore
+//! let prices = data::read_csv("data/prices.csv")?;
+//! store_df!(prices, source = "data/prices.csv")?;
+//!
+//! let prices = load_df!(prices)?;
+//! if !data::stale_sources(ctx, "prices")?.is_empty() {
+//!     println!("prices.csv changed, re-run the load cell");
+//! }
+//! ```
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use polars::prelude::{CsvReadOptions, DataFrame, ParquetReader, ParquetWriter, SerReader};
+use serde::{Deserialize, Serialize};
+
+use crate::context::CellContext;
+use crate::errors::{ContextError, Error, Result};
+use crate::project::project_path;
+
+/// A file a stored frame was read from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Source {
+    pub path: PathBuf,
+    /// [`file_hash`] of the contents when the frame was stored.
+    pub hash: u64,
+}
+
+/// A data frame in the store.
+#[derive(Serialize, Deserialize)]
+struct StoredFrame {
+    parquet: Vec<u8>,
+    sources: Vec<Source>,
+}
+
+/// Read a CSV file with a header row.
+pub fn read_csv(path: impl AsRef<Path>) -> Result<DataFrame> {
+    let path = project_path(path);
+    CsvReadOptions::default()
+        .with_has_header(true)
+        .try_into_reader_with_file_path(Some(path.clone()))
+        .and_then(|reader| reader.finish())
+        .map_err(|e| read_error(&path, e))
+}
+
+/// Read a Parquet file.
+pub fn read_parquet(path: impl AsRef<Path>) -> Result<DataFrame> {
+    let path = project_path(path);
+    let file = File::open(&path).map_err(|e| read_error(&path, e))?;
+    ParquetReader::new(file)
+        .finish()
+        .map_err(|e| read_error(&path, e))
+}
+
+/// FNV-1a hash of a file's contents, stable across builds and runs.
+pub fn file_hash(path: impl AsRef<Path>) -> Result<u64> {
+    let bytes = std::fs::read(project_path(path))?;
+    Ok(bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    }))
+}
+
+/// Store a frame, recording the hash of each file it was read from.
+pub fn store_df(ctx: &CellContext, key: &str, df: &DataFrame, sources: &[&Path]) -> Result<()> {
+    let mut parquet = Vec::new();
+    ParquetWriter::new(&mut parquet)
+        .finish(&mut df.clone())
+        .map_err(|e| ContextError::Serialization {
+            key: key.to_string(),
+            message: e.to_string(),
+        })?;
+    let sources = sources
+        .iter()
+        .map(|path| {
+            Ok(Source {
+                path: path.to_path_buf(),
+                hash: file_hash(path)?,
+            })
+        })
+        .collect::<Result<_>>()?;
+    ctx.store(key, &StoredFrame { parquet, sources })
+}
+
+/// Load a frame stored with [`store_df`].
+pub fn load_df(ctx: &CellContext, key: &str) -> Result<DataFrame> {
+    let stored: StoredFrame = ctx.load(key)?;
+    ParquetReader::new(std::io::Cursor::new(stored.parquet))
+        .finish()
+        .map_err(|e| {
+            ContextError::Deserialization {
+                key: key.to_string(),
+                message: e.to_string(),
+            }
+            .into()
+        })
+}
+
+/// Source files of a stored frame.
+pub fn sources(ctx: &CellContext, key: &str) -> Result<Vec<Source>> {
+    let stored: StoredFrame = ctx.load(key)?;
+    Ok(stored.sources)
+}
+
+/// Source files of a stored frame that changed or disappeared since it was stored.
+pub fn stale_sources(ctx: &CellContext, key: &str) -> Result<Vec<PathBuf>> {
+    Ok(sources(ctx, key)?
+        .into_iter()
+        .filter(|source| file_hash(&source.path).ok() != Some(source.hash))
+        .map(|source| source.path)
+        .collect())
+}
+
+fn read_error(path: &Path, error: impl ToString) -> Error {
+    Error::ReadData {
+        path: path.to_path_buf(),
+        message: error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::TestContext;
+
+    #[test]
+    fn test_round_trip_and_staleness() {
+        let dir = std::env::temp_dir().join(format!("cellbook-data-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let csv = dir.join("prices.csv");
+        std::fs::write(&csv, "symbol,close\nAAPL,182.5\nMSFT,411.0\n").unwrap();
+
+        let ctx = TestContext::default();
+        let df = read_csv(&csv).unwrap();
+        assert_eq!(df.shape(), (2, 2));
+        store_df(&ctx, "prices", &df, &[csv.as_path()]).unwrap();
+
+        let loaded = load_df(&ctx, "prices").unwrap();
+        assert!(loaded.equals(&df));
+        assert!(stale_sources(&ctx, "prices").unwrap().is_empty());
+
+        std::fs::write(&csv, "symbol,close\nAAPL,190.0\n").unwrap();
+        assert_eq!(stale_sources(&ctx, "prices").unwrap(), vec![csv.clone()]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -6,6 +6,17 @@ pub enum Error {
     Context(#[from] ContextError),
     #[error(transparent)]
     Io(#[from] std::io::Error),
+    #[error("image viewer '{viewer}' could not be started: {source}")]
+    Viewer { viewer: String, source: std::io::Error },
+    #[error("failed to build table: {0}")]
+    Table(String),
+    #[error("failed to read {}: {message}", path.display())]
+    ReadData {
+        path: std::path::PathBuf,
+        message: String,
+    },
+    #[error("request to {url} failed: {message}")]
+    Http { url: String, message: String },
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -22,6 +33,18 @@ pub enum ContextError {
     SchemaVersionMismatch { key: String, expected: u32, found: u32 },
     #[error("failed to serialize '{key}': {message}")]
     Serialization { key: String, message: String },
+    #[error("store rejected '{key}': {message}")]
+    Rejected { key: String, message: String },
     #[error("failed to deserialize '{key}': {message}")]
     Deserialization { key: String, message: String },
+    #[error("prompt failed: {0}")]
+    Prompt(String),
+    #[error("resource '{0}' not found")]
+    ResourceNotFound(String),
+    #[error("resource type mismatch for '{key}': expected '{expected}', found '{found}'")]
+    ResourceTypeMismatch {
+        key: String,
+        expected: String,
+        found: String,
+    },
 }
@@ -0,0 +1,617 @@
+//! C-ABI values exchanged with the runner.
+//!
+//! Notebooks, plugins and `cargo-cellbook` may be built by different compilers,
+//! so only `#[repr(C)]` values and `extern "C"` functions cross between them:
+//! strings and bytes as pointer and length, owned values together with the
+//! function that frees them, and futures and readers as C function tables.
+//! Errors cross as C strings.
+//!
+//! The host keeps a mirror of these types, so their layout must not change
+//! without bumping [`ABI_VERSION`](crate::registry::ABI_VERSION).
+
+use std::any::Any;
+use std::borrow::Cow;
+use std::ffi::{CStr, CString, c_char, c_void};
+use std::future::Future;
+use std::io::Read;
+use std::mem::ManuallyDrop;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::pin::Pin;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+use futures::future::BoxFuture;
+use serde::Serialize;
+
+/// Bytes or UTF-8 text borrowed for the duration of a call.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct FfiSlice {
+    ptr: *const u8,
+    len: usize,
+}
+
+impl FfiSlice {
+    /// No value, for optional fields.
+    pub const NONE: Self = Self {
+        ptr: std::ptr::null(),
+        len: 0,
+    };
+
+    pub fn is_none(&self) -> bool {
+        self.ptr.is_null()
+    }
+
+    /// # Safety
+    ///
+    /// The borrowed bytes must still be alive for `'a`.
+    pub unsafe fn as_bytes<'a>(self) -> &'a [u8] {
+        if self.ptr.is_null() {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+
+    /// # Safety
+    ///
+    /// The borrowed bytes must still be alive for `'a`.
+    pub unsafe fn to_str<'a>(self) -> Cow<'a, str> {
+        String::from_utf8_lossy(unsafe { self.as_bytes() })
+    }
+}
+
+impl From<&[u8]> for FfiSlice {
+    fn from(bytes: &[u8]) -> Self {
+        Self {
+            ptr: bytes.as_ptr(),
+            len: bytes.len(),
+        }
+    }
+}
+
+impl From<&str> for FfiSlice {
+    fn from(s: &str) -> Self {
+        s.as_bytes().into()
+    }
+}
+
+/// A C string owned by the side that allocated it, which also frees it.
+/// A null pointer stands for no string.
+#[repr(C)]
+pub struct FfiString {
+    ptr: *mut c_char,
+    free: unsafe extern "C" fn(*mut c_char),
+}
+
+impl FfiString {
+    /// `s` as a C string, without any NUL bytes it contains.
+    pub fn new(s: impl Into<String>) -> Self {
+        let mut s = s.into();
+        s.retain(|c| c != '\0');
+        let ptr = CString::new(s).map_or(std::ptr::null_mut(), CString::into_raw);
+        Self { ptr, free: free_string }
+    }
+
+    pub fn none() -> Self {
+        Self {
+            ptr: std::ptr::null_mut(),
+            free: free_string,
+        }
+    }
+
+    pub fn is_none(&self) -> bool {
+        self.ptr.is_null()
+    }
+
+    /// Copy of the string, freeing the original.
+    pub fn into_string(self) -> Option<String> {
+        (!self.ptr.is_null()).then(|| unsafe { CStr::from_ptr(self.ptr) }.to_string_lossy().into_owned())
+    }
+}
+
+impl From<Option<String>> for FfiString {
+    fn from(s: Option<String>) -> Self {
+        s.map_or_else(Self::none, Self::new)
+    }
+}
+
+impl Drop for FfiString {
+    fn drop(&mut self) {
+        if !self.ptr.is_null() {
+            unsafe { (self.free)(self.ptr) }
+        }
+    }
+}
+
+unsafe extern "C" fn free_string(ptr: *mut c_char) {
+    drop(unsafe { CString::from_raw(ptr) });
+}
+
+/// Bytes owned by the side that produced them, such as a loaded value.
+/// Released by that side when dropped.
+#[repr(C)]
+pub struct FfiBuffer {
+    ptr: *const u8,
+    len: usize,
+    owner: *mut c_void,
+    release: unsafe extern "C" fn(*mut c_void),
+}
+
+impl FfiBuffer {
+    pub fn new<T: AsRef<[u8]> + Send + Sync + 'static>(bytes: T) -> Self {
+        let owner = Box::new(bytes);
+        let slice = (*owner).as_ref();
+        Self {
+            ptr: slice.as_ptr(),
+            len: slice.len(),
+            owner: Box::into_raw(owner).cast(),
+            release: release::<T>,
+        }
+    }
+}
+
+impl AsRef<[u8]> for FfiBuffer {
+    fn as_ref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl Drop for FfiBuffer {
+    fn drop(&mut self) {
+        unsafe { (self.release)(self.owner) }
+    }
+}
+
+unsafe extern "C" fn release<T>(owner: *mut c_void) {
+    drop(unsafe { Box::from_raw(owner.cast::<T>()) });
+}
+
+// SAFETY: Buffers are made from `Send + Sync` owners.
+unsafe impl Send for FfiBuffer {}
+unsafe impl Sync for FfiBuffer {}
+
+/// A stored value, or none if `type_name` is none.
+#[repr(C)]
+pub struct FfiValue {
+    pub bytes: FfiBuffer,
+    pub type_name: FfiString,
+}
+
+impl FfiValue {
+    pub fn into_option(self) -> Option<(FfiBuffer, String)> {
+        let type_name = self.type_name.into_string()?;
+        Some((self.bytes, type_name))
+    }
+}
+
+/// A value only notebook code can use, such as a resource, which the host
+/// keeps as an opaque pointer. A null pointer stands for no value.
+#[repr(C)]
+pub struct FfiHandle {
+    ptr: *const c_void,
+    clone: unsafe extern "C" fn(*const c_void) -> *const c_void,
+    drop: unsafe extern "C" fn(*const c_void),
+}
+
+type Resource = std::sync::Arc<dyn Any + Send + Sync>;
+
+impl FfiHandle {
+    pub fn new(value: Resource) -> Self {
+        Self {
+            ptr: Box::into_raw(Box::new(value)).cast(),
+            clone: clone_resource,
+            drop: drop_resource,
+        }
+    }
+
+    pub fn null() -> Self {
+        Self {
+            ptr: std::ptr::null(),
+            clone: clone_resource,
+            drop: drop_resource,
+        }
+    }
+
+    /// The value of a handle made by [`FfiHandle::new`].
+    ///
+    /// # Safety
+    ///
+    /// The handle must come from this library, or from another build of it
+    /// with the same `cellbook` version and compiler.
+    pub unsafe fn into_resource(self) -> Option<Resource> {
+        (!self.ptr.is_null()).then(|| unsafe { &*self.ptr.cast::<Resource>() }.clone())
+    }
+}
+
+impl Drop for FfiHandle {
+    fn drop(&mut self) {
+        if !self.ptr.is_null() {
+            unsafe { (self.drop)(self.ptr) }
+        }
+    }
+}
+
+unsafe extern "C" fn clone_resource(ptr: *const c_void) -> *const c_void {
+    let value = unsafe { &*ptr.cast::<Resource>() };
+    Box::into_raw(Box::new(value.clone())).cast()
+}
+
+unsafe extern "C" fn drop_resource(ptr: *const c_void) {
+    // A panicking `Drop` of a resource must not unwind into the host.
+    let _ = catch_unwind(AssertUnwindSafe(|| drop(unsafe { Box::from_raw(ptr.cast_mut().cast::<Resource>()) })));
+}
+
+/// A resource looked up by key, or none if `type_name` is none.
+#[repr(C)]
+pub struct FfiResource {
+    pub handle: FfiHandle,
+    pub type_name: FfiString,
+}
+
+/// A `Waker` passed to the other side while it polls a future.
+///
+/// `wake` doesn't consume the waker. Only clones are dropped, the waker
+/// passed to `poll` stays owned by the caller.
+#[repr(C)]
+pub struct FfiWaker {
+    data: *const c_void,
+    clone: unsafe extern "C" fn(*const c_void) -> *const c_void,
+    wake: unsafe extern "C" fn(*const c_void),
+    drop: unsafe extern "C" fn(*const c_void),
+}
+
+impl FfiWaker {
+    /// `waker` lent for one call.
+    fn borrowed(waker: &Waker) -> Self {
+        Self {
+            data: (waker as *const Waker).cast(),
+            clone: clone_waker,
+            wake: wake_waker,
+            drop: drop_waker,
+        }
+    }
+}
+
+unsafe extern "C" fn clone_waker(data: *const c_void) -> *const c_void {
+    let waker = unsafe { &*data.cast::<Waker>() };
+    Box::into_raw(Box::new(waker.clone())).cast()
+}
+
+unsafe extern "C" fn wake_waker(data: *const c_void) {
+    unsafe { &*data.cast::<Waker>() }.wake_by_ref();
+}
+
+unsafe extern "C" fn drop_waker(data: *const c_void) {
+    drop(unsafe { Box::from_raw(data.cast_mut().cast::<Waker>()) });
+}
+
+/// A std `Waker` over a waker from the other side, valid while `waker` is.
+unsafe fn foreign_waker(waker: &FfiWaker) -> ManuallyDrop<Waker> {
+    let raw = RawWaker::new((waker as *const FfiWaker).cast(), &BORROWED_WAKER);
+    ManuallyDrop::new(unsafe { Waker::from_raw(raw) })
+}
+
+static BORROWED_WAKER: RawWakerVTable =
+    RawWakerVTable::new(clone_foreign, wake_foreign, wake_foreign, drop_borrowed);
+static OWNED_WAKER: RawWakerVTable = RawWakerVTable::new(clone_foreign, wake_owned, wake_foreign, drop_owned);
+
+unsafe fn clone_foreign(ptr: *const ()) -> RawWaker {
+    let waker = unsafe { &*ptr.cast::<FfiWaker>() };
+    let owned = Box::new(FfiWaker {
+        data: unsafe { (waker.clone)(waker.data) },
+        clone: waker.clone,
+        wake: waker.wake,
+        drop: waker.drop,
+    });
+    RawWaker::new(Box::into_raw(owned).cast(), &OWNED_WAKER)
+}
+
+unsafe fn wake_foreign(ptr: *const ()) {
+    let waker = unsafe { &*ptr.cast::<FfiWaker>() };
+    unsafe { (waker.wake)(waker.data) }
+}
+
+unsafe fn wake_owned(ptr: *const ()) {
+    unsafe {
+        wake_foreign(ptr);
+        drop_owned(ptr);
+    }
+}
+
+unsafe fn drop_owned(ptr: *const ()) {
+    let waker = unsafe { Box::from_raw(ptr.cast_mut().cast::<FfiWaker>()) };
+    unsafe { (waker.drop)(waker.data) }
+}
+
+unsafe fn drop_borrowed(_ptr: *const ()) {}
+
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FfiStatus {
+    Pending,
+    Ready,
+    Failed,
+}
+
+/// Error of a cell as passed to the host, which only gets strings: its message,
+/// the messages of its sources and its `Debug` output, which holds any backtrace.
+#[derive(Serialize)]
+struct CellReport {
+    message: String,
+    causes: Vec<String>,
+    debug: String,
+}
+
+impl CellReport {
+    fn new(error: &(dyn std::error::Error + 'static)) -> Self {
+        let mut causes = Vec::new();
+        let mut source = error.source();
+        while let Some(cause) = source {
+            causes.push(cause.to_string());
+            source = cause.source();
+        }
+        Self {
+            message: error.to_string(),
+            causes,
+            debug: format!("{:?}", error),
+        }
+    }
+
+    fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_else(|_| self.message.clone())
+    }
+}
+
+/// Result of polling an [`FfiFuture`]: the output once ready, or the error message.
+#[repr(C)]
+pub struct FfiPoll {
+    pub status: FfiStatus,
+    pub output: FfiString,
+}
+
+/// A future resolving to a string or an error message, polled through C
+/// function pointers. Cells resolve to an empty string.
+///
+/// Panics while polling are caught on the side that owns the future and fail it.
+#[repr(C)]
+pub struct FfiFuture {
+    data: *mut c_void,
+    poll: unsafe extern "C" fn(*mut c_void, *const FfiWaker) -> FfiPoll,
+    drop: unsafe extern "C" fn(*mut c_void),
+}
+
+type BoxedFuture = BoxFuture<'static, std::result::Result<String, String>>;
+
+impl FfiFuture {
+    pub fn new<F>(future: F) -> Self
+    where
+        F: Future<Output = std::result::Result<String, String>> + Send + 'static,
+    {
+        let future: BoxedFuture = Box::pin(future);
+        Self {
+            data: Box::into_raw(Box::new(future)).cast(),
+            poll: poll_boxed,
+            drop: drop_boxed,
+        }
+    }
+
+    /// A cell, init or teardown run, failing with a [`CellReport`] of its error as JSON.
+    #[doc(hidden)]
+    pub fn cell<F, E>(future: F) -> Self
+    where
+        F: Future<Output = std::result::Result<(), E>> + Send + 'static,
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        Self::new(async move {
+            future
+                .await
+                .map(|()| String::new())
+                .map_err(|e| CellReport::new(&*e.into()).to_json())
+        })
+    }
+}
+
+impl Future for FfiFuture {
+    type Output = std::result::Result<String, String>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let waker = FfiWaker::borrowed(cx.waker());
+        let polled = unsafe { (self.poll)(self.data, &waker) };
+        match polled.status {
+            FfiStatus::Pending => Poll::Pending,
+            FfiStatus::Ready => Poll::Ready(Ok(polled.output.into_string().unwrap_or_default())),
+            FfiStatus::Failed => Poll::Ready(Err(polled.output.into_string().unwrap_or_default())),
+        }
+    }
+}
+
+impl Drop for FfiFuture {
+    fn drop(&mut self) {
+        unsafe { (self.drop)(self.data) }
+    }
+}
+
+// SAFETY: Futures are made from `Send` futures.
+unsafe impl Send for FfiFuture {}
+
+unsafe extern "C" fn poll_boxed(data: *mut c_void, waker: *const FfiWaker) -> FfiPoll {
+    let future = unsafe { &mut *data.cast::<BoxedFuture>() };
+    let waker = unsafe { foreign_waker(&*waker) };
+    let mut cx = Context::from_waker(&waker);
+    let (status, output) = match catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(&mut cx))) {
+        Ok(Poll::Pending) => (FfiStatus::Pending, FfiString::none()),
+        Ok(Poll::Ready(Ok(output))) => (FfiStatus::Ready, FfiString::new(output)),
+        Ok(Poll::Ready(Err(e))) => (FfiStatus::Failed, FfiString::new(e)),
+        Err(panic) => (FfiStatus::Failed, FfiString::new(panic_message(&*panic))),
+    };
+    FfiPoll { status, output }
+}
+
+unsafe extern "C" fn drop_boxed(data: *mut c_void) {
+    let future = unsafe { Box::from_raw(data.cast::<BoxedFuture>()) };
+    let _ = catch_unwind(AssertUnwindSafe(|| drop(future)));
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    let message = panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic");
+    format!("panicked: {}", message)
+}
+
+/// A reader passed to the other side. `read` fills up to `len` bytes at `buf`,
+/// sets how many it read and returns an error message or none.
+#[repr(C)]
+pub struct FfiReader {
+    data: *mut c_void,
+    read: unsafe extern "C" fn(*mut c_void, *mut u8, usize, *mut usize) -> FfiString,
+    drop: unsafe extern "C" fn(*mut c_void),
+}
+
+impl FfiReader {
+    /// `reader` lent for one call.
+    pub fn borrowed(reader: &mut &mut dyn Read) -> Self {
+        Self {
+            data: (reader as *mut &mut dyn Read).cast(),
+            read: read_from::<&mut dyn Read>,
+            drop: drop_nothing,
+        }
+    }
+}
+
+impl Read for FfiReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut read = 0;
+        let error = unsafe { (self.read)(self.data, buf.as_mut_ptr(), buf.len(), &mut read) };
+        match error.into_string() {
+            Some(message) => Err(std::io::Error::other(message)),
+            None => Ok(read),
+        }
+    }
+}
+
+impl Drop for FfiReader {
+    fn drop(&mut self) {
+        unsafe { (self.drop)(self.data) }
+    }
+}
+
+// SAFETY: The host only hands out `Send` readers.
+unsafe impl Send for FfiReader {}
+
+unsafe extern "C" fn read_from<R: Read>(data: *mut c_void, buf: *mut u8, len: usize, read: *mut usize) -> FfiString {
+    let reader = unsafe { &mut *data.cast::<R>() };
+    let buf = unsafe { std::slice::from_raw_parts_mut(buf, len) };
+    match catch_unwind(AssertUnwindSafe(|| reader.read(buf))) {
+        Ok(Ok(n)) => {
+            unsafe { *read = n };
+            FfiString::none()
+        }
+        Ok(Err(e)) => FfiString::new(e.to_string()),
+        Err(panic) => FfiString::new(panic_message(&*panic)),
+    }
+}
+
+unsafe extern "C" fn drop_nothing(_data: *mut c_void) {}
+
+/// A reader over a stored stream, or none if `type_name` is none.
+#[repr(C)]
+pub struct FfiStream {
+    pub reader: FfiReader,
+    pub type_name: FfiString,
+}
+
+/// Fails the build unless `$ty` has the given size and field offsets, counted in
+/// pointers, and pointer alignment.
+macro_rules! assert_layout {
+    ($ty:ty, $size:expr, { $($field:ident: $offset:expr),* $(,)? }) => {
+        const _: () = {
+            const P: usize = size_of::<usize>();
+            assert!(size_of::<$ty>() == $size * P);
+            assert!(align_of::<$ty>() == P);
+            $(assert!(std::mem::offset_of!($ty, $field) == $offset * P);)*
+        };
+    };
+}
+
+pub(crate) use assert_layout;
+
+// Layouts of ABI version 9, asserted the same way in `cargo-cellbook`. If either
+// copy of a type changes, its build fails until both copies, these layouts and
+// the ABI version are updated together.
+const _: () = assert!(crate::registry::ABI_VERSION == 9);
+assert_layout!(FfiSlice, 2, { ptr: 0, len: 1 });
+assert_layout!(FfiString, 2, { ptr: 0, free: 1 });
+assert_layout!(FfiBuffer, 4, { ptr: 0, len: 1, owner: 2, release: 3 });
+assert_layout!(FfiValue, 6, { bytes: 0, type_name: 4 });
+assert_layout!(FfiHandle, 3, { ptr: 0, clone: 1, drop: 2 });
+assert_layout!(FfiResource, 5, { handle: 0, type_name: 3 });
+assert_layout!(FfiWaker, 4, { data: 0, clone: 1, wake: 2, drop: 3 });
+const _: () = assert!(size_of::<FfiStatus>() == 1);
+assert_layout!(FfiPoll, 3, { status: 0, output: 1 });
+assert_layout!(FfiFuture, 3, { data: 0, poll: 1, drop: 2 });
+assert_layout!(FfiReader, 3, { data: 0, read: 1, drop: 2 });
+assert_layout!(FfiStream, 5, { reader: 0, type_name: 3 });
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::Wake;
+
+    use super::*;
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn futures_wake_and_resolve_across_the_boundary() {
+        let (tx, rx) = futures::channel::oneshot::channel::<String>();
+        let mut future = FfiFuture::new(async move { rx.await.map_err(|e| e.to_string()) });
+
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let mut cx = Context::from_waker(&waker);
+        assert!(Pin::new(&mut future).poll(&mut cx).is_pending());
+
+        tx.send("answer".to_string()).unwrap();
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+        assert_eq!(Pin::new(&mut future).poll(&mut cx), Poll::Ready(Ok("answer".to_string())));
+    }
+
+    #[test]
+    fn panics_and_errors_fail_the_future() {
+        let failed = FfiFuture::cell(async { Err::<(), _>("no data") });
+        let report = futures::executor::block_on(failed).unwrap_err();
+        let report: serde_json::Value = serde_json::from_str(&report).unwrap();
+        assert_eq!(report["message"], "no data");
+        assert_eq!(report["causes"], serde_json::json!([]));
+
+        fn fail() -> std::result::Result<String, String> {
+            panic!("bad cell")
+        }
+        let panicked = FfiFuture::new(async { fail() });
+        assert_eq!(
+            futures::executor::block_on(panicked),
+            Err("panicked: bad cell".to_string())
+        );
+    }
+
+    #[test]
+    fn strings_and_readers_round_trip() {
+        assert_eq!(FfiString::new("a\0b").into_string().as_deref(), Some("ab"));
+        assert_eq!(FfiString::none().into_string(), None);
+
+        let mut source: &[u8] = b"streamed bytes";
+        let mut source: &mut dyn Read = &mut source;
+        let mut read = String::new();
+        FfiReader::borrowed(&mut source).read_to_string(&mut read).unwrap();
+        assert_eq!(read, "streamed bytes");
+    }
+}
@@ -0,0 +1,298 @@
+//! HTTP fetch helpers that cache responses in the context store.
+//!
+//! Responses are stored under `http:<url>` with their `ETag` and
+//! `Last-Modified` headers. Later fetches send a conditional request and reuse
+//! the stored body when the server answers `304 Not Modified`, so re-running a
+//! data-loading cell doesn't download the same data again.
+//!
+//! ```ignore
+//! let prices: Vec<Price> = http::fetch_csv(ctx, "https://example.com/prices.csv").await?;
+//! let quote: Quote = http::get(url).ttl(Duration::from_secs(600)).json(ctx).await?;
+//! let fresh = http::get(url).no_cache().bytes(ctx).await?;
+//! ```
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::context::CellContext;
+use crate::errors::{Error, Result};
+
+/// Largest body downloaded unless raised with [`Fetch::max_bytes`].
+const DEFAULT_MAX_BYTES: u64 = 256 * 1024 * 1024;
+
+/// A response body kept in the store.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedResponse {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    /// Seconds since the Unix epoch when the server last confirmed the body.
+    fetched_at: u64,
+    body: Vec<u8>,
+}
+
+impl CachedResponse {
+    fn is_fresh(&self, ttl: Option<Duration>, now: u64) -> bool {
+        ttl.is_some_and(|ttl| now.saturating_sub(self.fetched_at) < ttl.as_secs())
+    }
+}
+
+/// Fetch a URL and parse the body as JSON.
+pub async fn fetch_json<T: DeserializeOwned>(ctx: &CellContext, url: &str) -> Result<T> {
+    get(url).json(ctx).await
+}
+
+/// Fetch a URL and return the raw body.
+pub async fn fetch_bytes(ctx: &CellContext, url: &str) -> Result<Vec<u8>> {
+    get(url).bytes(ctx).await
+}
+
+/// Fetch a URL and parse the body as CSV with a header row, one `T` per record.
+pub async fn fetch_csv<T: DeserializeOwned>(ctx: &CellContext, url: &str) -> Result<Vec<T>> {
+    get(url).csv(ctx).await
+}
+
+/// Start a GET request with cache options.
+pub fn get(url: &str) -> Fetch {
+    Fetch {
+        url: url.to_string(),
+        ttl: None,
+        bypass: false,
+        max_bytes: DEFAULT_MAX_BYTES,
+    }
+}
+
+/// A GET request, sent by [`bytes`](Self::bytes), [`json`](Self::json) or [`csv`](Self::csv).
+#[derive(Debug, Clone)]
+pub struct Fetch {
+    url: String,
+    ttl: Option<Duration>,
+    bypass: bool,
+    max_bytes: u64,
+}
+
+impl Fetch {
+    /// Reuse a cached body without asking the server for `ttl` after it was fetched.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Ignore the cached body and download it again. The new body is still cached.
+    pub fn no_cache(mut self) -> Self {
+        self.bypass = true;
+        self
+    }
+
+    /// Fail instead of downloading a body larger than `max_bytes`, 256 MB by default.
+    pub fn max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    pub async fn bytes(self, ctx: &CellContext) -> Result<Vec<u8>> {
+        let key = format!("http:{}", self.url);
+        let cached = if self.bypass {
+            None
+        } else {
+            ctx.load::<CachedResponse>(&key).ok()
+        };
+        let now = unix_now();
+        if let Some(cached) = &cached
+            && cached.is_fresh(self.ttl, now)
+        {
+            return Ok(cached.body.clone());
+        }
+
+        let (url, max_bytes) = (self.url.clone(), self.max_bytes);
+        let validators = cached.as_ref().map(|c| (c.etag.clone(), c.last_modified.clone()));
+        let (sender, receiver) = futures::channel::oneshot::channel();
+        // ureq blocks, so the request runs off the cell's runtime thread.
+        std::thread::spawn(move || {
+            let _ = sender.send(send(&url, validators, max_bytes));
+        });
+        let response = receiver
+            .await
+            .map_err(|_| self.error("request thread stopped"))??;
+
+        let entry = match (response, cached) {
+            (Response::NotModified, Some(mut cached)) => {
+                cached.fetched_at = now;
+                cached
+            }
+            (Response::NotModified, None) => return Err(self.error("304 Not Modified without a cached body")),
+            (Response::Ok(entry), _) => entry,
+        };
+        ctx.store(&key, &entry)?;
+        Ok(entry.body)
+    }
+
+    pub async fn json<T: DeserializeOwned>(self, ctx: &CellContext) -> Result<T> {
+        let url = self.url.clone();
+        let body = self.bytes(ctx).await?;
+        serde_json::from_slice(&body).map_err(|e| Error::Http {
+            url,
+            message: format!("invalid JSON: {}", e),
+        })
+    }
+
+    pub async fn csv<T: DeserializeOwned>(self, ctx: &CellContext) -> Result<Vec<T>> {
+        let url = self.url.clone();
+        let body = self.bytes(ctx).await?;
+        csv::Reader::from_reader(body.as_slice())
+            .deserialize()
+            .collect::<std::result::Result<Vec<T>, _>>()
+            .map_err(|e| Error::Http {
+                url,
+                message: format!("invalid CSV: {}", e),
+            })
+    }
+
+    fn error(&self, message: &str) -> Error {
+        Error::Http {
+            url: self.url.clone(),
+            message: message.to_string(),
+        }
+    }
+}
+
+enum Response {
+    NotModified,
+    Ok(CachedResponse),
+}
+
+/// Send the request, conditional on the cached `ETag` and `Last-Modified` if given.
+fn send(url: &str, validators: Option<(Option<String>, Option<String>)>, max_bytes: u64) -> Result<Response> {
+    let error = |message: String| Error::Http {
+        url: url.to_string(),
+        message,
+    };
+    let mut request = ureq::get(url);
+    if let Some((etag, last_modified)) = validators {
+        if let Some(etag) = etag {
+            request = request.header("If-None-Match", etag);
+        }
+        if let Some(last_modified) = last_modified {
+            request = request.header("If-Modified-Since", last_modified);
+        }
+    }
+
+    let mut response = request
+        .config()
+        .http_status_as_error(false)
+        .build()
+        .call()
+        .map_err(|e| error(e.to_string()))?;
+    let status = response.status();
+    if status.as_u16() == 304 {
+        return Ok(Response::NotModified);
+    }
+    if !status.is_success() {
+        return Err(error(format!("server returned {}", status)));
+    }
+
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let etag = header("etag");
+    let last_modified = header("last-modified");
+    let body = response
+        .body_mut()
+        .with_config()
+        .limit(max_bytes)
+        .read_to_vec()
+        .map_err(|e| match e {
+            ureq::Error::BodyExceedsLimit(limit) => error(format!(
+                "body is larger than {} bytes, raise the limit with `max_bytes`",
+                limit
+            )),
+            e => error(e.to_string()),
+        })?;
+    Ok(Response::Ok(CachedResponse {
+        etag,
+        last_modified,
+        fetched_at: unix_now(),
+        body,
+    }))
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    use super::*;
+    use crate::test::TestContext;
+
+    /// Serve one response per expected request, returning the requests received.
+    fn serve(responses: Vec<&'static str>) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/data.json", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buf = [0; 4096];
+                let n = stream.read(&mut buf).unwrap();
+                requests.push(String::from_utf8_lossy(&buf[..n]).to_lowercase());
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn test_revalidates_with_etag() {
+        let ok = "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: 7\r\nConnection: close\r\n\r\n[1,2,3]";
+        let not_modified = "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n";
+        let (url, server) = serve(vec![ok, not_modified]);
+        let ctx = TestContext::default();
+
+        let first: Vec<u32> = futures::executor::block_on(fetch_json(&ctx, &url)).unwrap();
+        let second: Vec<u32> = futures::executor::block_on(fetch_json(&ctx, &url)).unwrap();
+        assert_eq!(first, vec![1, 2, 3]);
+        assert_eq!(second, first);
+
+        let requests = server.join().unwrap();
+        assert!(!requests[0].contains("if-none-match"));
+        assert!(requests[1].contains("if-none-match: \"v1\""));
+    }
+
+    #[test]
+    fn test_body_over_max_bytes_fails() {
+        let ok = "HTTP/1.1 200 OK\r\nContent-Length: 7\r\nConnection: close\r\n\r\n[1,2,3]";
+        let (url, server) = serve(vec![ok]);
+        let ctx = TestContext::default();
+
+        let fetched = futures::executor::block_on(get(&url).max_bytes(4).bytes(&ctx));
+        let error = fetched.unwrap_err().to_string();
+        assert!(error.contains("body is larger than 4 bytes"), "{}", error);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_ttl_skips_request() {
+        let cached = CachedResponse {
+            etag: None,
+            last_modified: None,
+            fetched_at: 1_000,
+            body: Vec::new(),
+        };
+        assert!(cached.is_fresh(Some(Duration::from_secs(60)), 1_030));
+        assert!(!cached.is_fresh(Some(Duration::from_secs(60)), 1_060));
+        assert!(!cached.is_fresh(None, 1_000));
+    }
+}
@@ -1,63 +1,198 @@
 //! Image viewing utilities.
 
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::RwLock;
 
-use crate::errors::Result;
+use crate::context::ArtifactFn;
+use crate::errors::{Error, Result};
+
+/// Host callback recording produced files, set when a cell context is created.
+static ARTIFACT_FN: RwLock<Option<ArtifactFn>> = RwLock::new(None);
+
+pub(crate) fn set_artifact_fn(artifact_fn: ArtifactFn) {
+    if let Ok(mut slot) = ARTIFACT_FN.write() {
+        *slot = Some(artifact_fn);
+    }
+}
+
+/// Tell the host about a produced file so it can be listed, re-opened and cleaned up.
+fn register_artifact(path: &Path, temporary: bool) {
+    // Cells may run in another working directory than the runner.
+    let path = &std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+    if let Ok(slot) = ARTIFACT_FN.read()
+        && let Some(artifact_fn) = *slot
+    {
+        let path = path.to_string_lossy();
+        unsafe { artifact_fn(path.as_ref().into(), mime_type(extension).into(), temporary) };
+    }
+}
+
+/// MIME type for an image file extension.
+fn mime_type(extension: &str) -> &'static str {
+    match extension.to_ascii_lowercase().as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        _ => "application/octet-stream",
+    }
+}
 
 /// Open an image file in the configured viewer.
+/// When exporting a report, the image is copied into the export directory instead.
 pub fn open_image(path: impl AsRef<Path>) -> Result<()> {
     let path = path.as_ref();
+    if let Some(dir) = export_dir() {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("png");
+        let artifact = dir.join(artifact_name(extension));
+        std::fs::copy(path, &artifact)?;
+        println!("[image] {}", path.display());
+        return Ok(());
+    }
+
     println!("[image] {}", path.display());
+    register_artifact(path, false);
     let viewer = get_image_viewer();
     spawn_viewer(&viewer, path)
 }
 
 /// Open image data in the configured viewer.
 /// Writes the data to a temporary file with the given extension.
+/// When exporting a report, the file is written to the export directory and no viewer is spawned.
 pub fn open_image_bytes(data: &[u8], extension: &str) -> Result<()> {
-    let rand_id: u64 = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .map(|d| d.as_nanos() as u64)
-        .unwrap_or(0)
-        ^ (std::process::id() as u64);
-
-    let temp_path = std::env::temp_dir().join(format!("cellbook_{:x}.{}", rand_id, extension));
+    let export = export_dir();
+    let dir = export.clone().unwrap_or_else(std::env::temp_dir);
+    let temp_path = dir.join(artifact_name(extension));
 
     let mut file = std::fs::File::create(&temp_path)?;
     file.write_all(data)?;
     file.flush()?;
 
     println!("[image] {}", temp_path.display());
+    if export.is_some() {
+        return Ok(());
+    }
+    register_artifact(&temp_path, true);
     let viewer = get_image_viewer();
     spawn_viewer(&viewer, &temp_path)
 }
 
+/// Unique file name for an image with the given extension.
+fn artifact_name(extension: &str) -> String {
+    let rand_id: u64 = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+        ^ (std::process::id() as u64);
+
+    format!("cellbook_{:x}.{}", rand_id, extension)
+}
+
+/// Directory collecting images while `cargo cellbook export` runs the notebook.
+fn export_dir() -> Option<PathBuf> {
+    crate::context::runner_var("CELLBOOK_EXPORT_DIR").map(PathBuf::from)
+}
+
 /// Get the image viewer command.
-/// Checks CELLBOOK_IMAGE_VIEWER env var, then falls back to platform default.
+/// Checks the runner's CELLBOOK_IMAGE_VIEWER, then falls back to platform default.
 fn get_image_viewer() -> String {
-    std::env::var("CELLBOOK_IMAGE_VIEWER").unwrap_or_else(|_| default_viewer().to_string())
+    crate::context::runner_var("CELLBOOK_IMAGE_VIEWER").unwrap_or_else(|| default_viewers()[0].to_string())
 }
 
-/// Platform-specific default image viewer.
-fn default_viewer() -> &'static str {
+/// Platform-specific default image viewers, tried in order.
+fn default_viewers() -> &'static [&'static str] {
     if cfg!(target_os = "macos") {
-        "open"
+        &["open"]
     } else if cfg!(target_os = "windows") {
-        "start"
+        &["start", "explorer"]
     } else {
-        "xdg-open"
+        &["xdg-open"]
     }
 }
 
 /// Spawn the viewer process.
+/// Falls back to the platform defaults if a configured viewer cannot be started.
 fn spawn_viewer(viewer: &str, path: &Path) -> Result<()> {
-    Command::new(viewer)
-        .arg(path)
+    let mut err = match try_spawn(viewer, path) {
+        Ok(()) => return Ok(()),
+        Err(err) => err,
+    };
+    let mut failed = viewer;
+
+    for &fallback in default_viewers().iter().filter(|&&v| v != viewer) {
+        println!(
+            "[image] viewer '{}' could not be started ({}), falling back to '{}'",
+            failed, err, fallback
+        );
+        match try_spawn(fallback, path) {
+            Ok(()) => return Ok(()),
+            Err(e) => {
+                err = e;
+                failed = fallback;
+            }
+        }
+    }
+
+    Err(Error::Viewer {
+        viewer: failed.to_string(),
+        source: err,
+    })
+}
+
+fn try_spawn(viewer: &str, path: &Path) -> std::io::Result<()> {
+    viewer_command(viewer, path)
         .stdin(std::process::Stdio::null())
         .stdout(std::process::Stdio::null())
         .stderr(std::process::Stdio::null())
         .spawn()?;
     Ok(())
 }
+
+/// Command opening `path` with `viewer`.
+/// On Windows `start` is a `cmd` builtin rather than a program, so it runs through `cmd /C`.
+fn viewer_command(viewer: &str, path: &Path) -> Command {
+    if cfg!(target_os = "windows") && viewer.eq_ignore_ascii_case("start") {
+        let mut command = Command::new("cmd");
+        // `start` takes the first quoted argument as the window title.
+        command.args(["/C", "start", ""]).arg(path);
+        command
+    } else {
+        let mut command = Command::new(viewer);
+        command.arg(path);
+        command
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_viewer_command_passes_path() {
+        let command = viewer_command("feh", Path::new("plot.png"));
+        assert_eq!(command.get_program(), "feh");
+        assert_eq!(command.get_args().collect::<Vec<_>>(), ["plot.png"]);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_viewer_command_runs_start_through_cmd() {
+        let command = viewer_command("start", Path::new("plot.png"));
+        assert_eq!(command.get_program(), "cmd");
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            ["/C", "start", "", "plot.png"]
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_default_viewers_fall_back_to_explorer() {
+        assert_eq!(default_viewers(), ["start", "explorer"]);
+    }
+}
@@ -3,21 +3,34 @@
 //! Provides the user-facing API for cellbook projects.
 //! The runtime lives in `cargo-cellbook`.
 //!
-//! Values in the context store are serialized with postcard.
+//! Values in the context store are serialized with postcard, or with the codec given to `store_as!`.
 //! Stored types must implement `Serialize` and loaded types must implement `DeserializeOwned`.
 
+pub mod codec;
 pub mod context;
+#[cfg(feature = "data")]
+pub mod data;
 pub mod errors;
+pub mod ffi;
+#[cfg(feature = "http")]
+pub mod http;
 pub mod image;
 mod macros;
+pub mod plugin;
+pub mod project;
 pub mod registry;
+pub mod run;
+pub mod table;
 pub mod test;
 
-pub use cellbook_macros::{StoreSchema, cell, init};
+pub use cellbook_macros::{StoreSchema, cell, init, teardown};
+pub use codec::Codec;
 pub use context::CellContext;
 pub use errors::{ContextError, Error, Result};
 pub use image::{open_image, open_image_bytes};
+pub use project::{project_path, project_root};
 pub use registry::CellInfo;
+pub use run::RunInfo;
 pub use {futures, inventory, serde};
 
 /// Opt-in schema version metadata for versioned shared-store operations.
@@ -25,4 +38,10 @@ pub use {futures, inventory, serde};
 /// Use `#[derive(StoreSchema)]` with `#[store_schema(version = N)]` on your type.
 pub trait StoreSchema {
     const VERSION: u32;
+
+    /// Human-readable structure of the type: its fields, their types and doc comments.
+    ///
+    /// Registered with the host on each versioned store, so the store inspector
+    /// can show the shape of a value without deserializing it. The derive fills it in.
+    const SCHEMA: &'static str = "";
 }
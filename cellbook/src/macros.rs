@@ -15,6 +15,37 @@ macro_rules! store {
     ($ctx:expr, $name:ident = $value:expr) => {
         $ctx.store(stringify!($name), &$value)
     };
+    (ctx, $($rest:tt)*) => {
+        compile_error!("invalid arguments, expected `store!(name)` or `store!(name = value)`")
+    };
+    ($($rest:tt)*) => {
+        compile_error!("`store!` can only be used inside a `#[cell]` function")
+    };
+}
+
+/// Store a value in the context serialized with another codec than postcard.
+///
+/// The codec is `postcard`, `json` or `msgpack` (with the `msgpack` feature).
+/// Loads pick the codec up from the stored value, so `load!` reads it as usual.
+///
+/// ```ignore
+/// store_as!(response, codec = json);
+/// store_as!(my_key = some_value, codec = msgpack);
+/// ```
+#[macro_export]
+macro_rules! store_as {
+    ($ctx:expr, $var:ident, codec = $codec:ident) => {
+        $ctx.store_as(stringify!($var), &$var, $crate::codec::by_name::$codec)
+    };
+    ($ctx:expr, $name:ident = $value:expr, codec = $codec:ident) => {
+        $ctx.store_as(stringify!($name), &$value, $crate::codec::by_name::$codec)
+    };
+    (ctx, $($rest:tt)*) => {
+        compile_error!("invalid arguments, expected `store_as!(name, codec = json)` or `store_as!(name = value, codec = json)`")
+    };
+    ($($rest:tt)*) => {
+        compile_error!("`store_as!` can only be used inside a `#[cell]` function")
+    };
 }
 
 /// Store a value in the context with schema version metadata.
@@ -43,6 +74,12 @@ macro_rules! storev {
     ($ctx:expr, $name:ident = $value:expr) => {
         $ctx.store_versioned(stringify!($name), &$value)
     };
+    (ctx, $($rest:tt)*) => {
+        compile_error!("invalid arguments, expected `storev!(name)` or `storev!(name = value)`, optionally followed by `, version = N`")
+    };
+    ($($rest:tt)*) => {
+        compile_error!("`storev!` can only be used inside a `#[cell]` function")
+    };
 }
 
 /// Load a value from the context.
@@ -62,6 +99,12 @@ macro_rules! load {
     ($ctx:expr, $name:ident) => {
         $ctx.load(stringify!($name))
     };
+    (ctx, $($rest:tt)*) => {
+        compile_error!("invalid arguments, expected `load!(name)` or `load!(name as Type)`")
+    };
+    ($($rest:tt)*) => {
+        compile_error!("`load!` can only be used inside a `#[cell]` function")
+    };
 }
 
 /// Load a value from the context with schema version checking.
@@ -89,6 +132,12 @@ macro_rules! loadv {
     ($ctx:expr, $name:ident) => {
         $ctx.load_versioned(stringify!($name))
     };
+    (ctx, $($rest:tt)*) => {
+        compile_error!("invalid arguments, expected `loadv!(name)` or `loadv!(name as Type)`, optionally followed by `, version = N`")
+    };
+    ($($rest:tt)*) => {
+        compile_error!("`loadv!` can only be used inside a `#[cell]` function")
+    };
 }
 
 /// Remove a value from the context.
@@ -103,6 +152,12 @@ macro_rules! remove {
     ($ctx:expr, $name:ident) => {
         $ctx.remove(stringify!($name))
     };
+    (ctx, $($rest:tt)*) => {
+        compile_error!("invalid arguments, expected `remove!(name)`")
+    };
+    ($($rest:tt)*) => {
+        compile_error!("`remove!` can only be used inside a `#[cell]` function")
+    };
 }
 
 /// Load and remove a value in one operation.
@@ -122,6 +177,12 @@ macro_rules! consume {
     ($ctx:expr, $name:ident) => {
         $ctx.consume(stringify!($name))
     };
+    (ctx, $($rest:tt)*) => {
+        compile_error!("invalid arguments, expected `consume!(name)` or `consume!(name as Type)`")
+    };
+    ($($rest:tt)*) => {
+        compile_error!("`consume!` can only be used inside a `#[cell]` function")
+    };
 }
 
 /// Load and remove a value with schema version checking.
@@ -148,4 +209,133 @@ macro_rules! consumev {
     ($ctx:expr, $name:ident) => {
         $ctx.consume_versioned(stringify!($name))
     };
+    (ctx, $($rest:tt)*) => {
+        compile_error!("invalid arguments, expected `consumev!(name)` or `consumev!(name as Type)`, optionally followed by `, version = N`")
+    };
+    ($($rest:tt)*) => {
+        compile_error!("`consumev!` can only be used inside a `#[cell]` function")
+    };
+}
+
+/// Store a polars data frame in the context, optionally recording the files it
+/// was read from so loading cells can check [`stale_sources`](crate::data::stale_sources).
+///
+/// Requires the `data` feature.
+///
+/// ```ignore
+/// store_df!(prices);
+/// store_df!(prices = df, source = "data/prices.csv");
+/// ```
+#[macro_export]
+macro_rules! store_df {
+    ($ctx:expr, $var:ident $(, source = $source:expr)*) => {
+        $crate::data::store_df($ctx, stringify!($var), &$var, &[$(::std::path::Path::new(&$source)),*])
+    };
+    ($ctx:expr, $name:ident = $value:expr $(, source = $source:expr)*) => {
+        $crate::data::store_df($ctx, stringify!($name), &$value, &[$(::std::path::Path::new(&$source)),*])
+    };
+    (ctx, $($rest:tt)*) => {
+        compile_error!("invalid arguments, expected `store_df!(name)` or `store_df!(name = df)`, optionally followed by `, source = path`")
+    };
+    ($($rest:tt)*) => {
+        compile_error!("`store_df!` can only be used inside a `#[cell]` function")
+    };
+}
+
+/// Load a polars data frame stored with [`store_df!`].
+///
+/// Requires the `data` feature.
+///
+/// ```ignore
+/// let prices = load_df!(prices)?;
+/// ```
+#[macro_export]
+macro_rules! load_df {
+    ($ctx:expr, $name:ident) => {
+        $crate::data::load_df($ctx, stringify!($name))
+    };
+    (ctx, $($rest:tt)*) => {
+        compile_error!("invalid arguments, expected `load_df!(name)`")
+    };
+    ($($rest:tt)*) => {
+        compile_error!("`load_df!` can only be used inside a `#[cell]` function")
+    };
+}
+
+/// Resolve a path relative to the notebook project root.
+///
+/// Unlike `concat!(env!("CARGO_MANIFEST_DIR"), ...)`, the root is provided by the
+/// runner at runtime, so it is correct however the notebook library was built.
+///
+/// ```ignore
+/// let csv = std::fs::read_to_string(path!("data/stock_prices.csv"))?;
+/// ```
+#[macro_export]
+macro_rules! path {
+    ($path:expr) => {
+        $crate::project_path($path)
+    };
+}
+
+/// Print rows or a data frame as a table, with optional [`Table`](crate::table::Table) settings.
+///
+/// ```ignore
+/// table!(stats)?;
+/// table!(stats, max_rows = 10, max_width = 16, precision = 2)?;
+/// ```
+#[macro_export]
+macro_rules! table {
+    ($rows:expr $(, $option:ident = $value:expr)* $(,)?) => {
+        $crate::table::ToTable::to_table(&$rows).map(|table| table$(.$option($value))*.print())
+    };
+}
+
+/// Export a [`HostPlugin`](crate::plugin::HostPlugin) from a plugin `cdylib`.
+///
+/// The expression is evaluated once, when the runner first calls a hook.
+///
+/// ```ignore
+/// cellbook::export_plugin!(Notify::new());
+/// ```
+#[macro_export]
+macro_rules! export_plugin {
+    ($plugin:expr) => {
+        static __CELLBOOK_PLUGIN: $crate::plugin::Exported =
+            ::std::sync::LazyLock::new(|| ::std::boxed::Box::new($plugin));
+
+        // Handshake read by the runner before `__cellbook_plugin_hooks`, as for notebooks.
+        #[doc(hidden)]
+        #[unsafe(no_mangle)]
+        pub extern "C" fn __cellbook_abi_version() -> u32 {
+            $crate::registry::ABI_VERSION
+        }
+
+        #[doc(hidden)]
+        #[unsafe(no_mangle)]
+        pub extern "C" fn __cellbook_version() -> *const ::std::ffi::c_char {
+            $crate::registry::VERSION.as_ptr()
+        }
+
+        #[doc(hidden)]
+        #[unsafe(no_mangle)]
+        pub extern "C" fn __cellbook_plugin_hooks() -> $crate::plugin::PluginHooks {
+            unsafe extern "C" fn on_cell_complete(event: *const $crate::plugin::FfiCellEvent) {
+                unsafe { $crate::plugin::cell_completed(&__CELLBOOK_PLUGIN, event) }
+            }
+
+            extern "C" fn on_reload() {
+                $crate::plugin::reloaded(&__CELLBOOK_PLUGIN)
+            }
+
+            unsafe extern "C" fn on_store_change(change: *const $crate::plugin::FfiStoreChange) {
+                unsafe { $crate::plugin::store_changed(&__CELLBOOK_PLUGIN, change) }
+            }
+
+            $crate::plugin::PluginHooks {
+                on_cell_complete,
+                on_reload,
+                on_store_change,
+            }
+        }
+    };
 }
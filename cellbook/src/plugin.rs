@@ -0,0 +1,159 @@
+//! Host plugins for integrating the runner with external tools.
+//!
+//! A plugin is a `cdylib` crate implementing [`HostPlugin`] and exporting it
+//! with [`export_plugin!`](crate::export_plugin). The runner loads plugins
+//! listed in the `plugins` config setting and calls their hooks from its
+//! event loop, so slow work (uploads, network calls) should be moved to a
+//! thread by the plugin.
+//!
+//! ```ignore
+//! use cellbook::plugin::{CellEvent, HostPlugin};
+//!
+//! struct Notify;
+//!
+//! impl HostPlugin for Notify {
+//!     fn on_cell_complete(&self, event: &CellEvent) {
+//!         if let Some(error) = event.error {
+//!             eprintln!("{} failed: {}", event.name, error);
+//!         }
+//!     }
+//! }
+//!
+//! cellbook::export_plugin!(Notify);
+//! ```
+
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use crate::ffi::FfiSlice;
+
+/// A finished cell run.
+pub struct CellEvent<'a> {
+    pub name: &'a str,
+    pub duration: Duration,
+    pub stdout: &'a str,
+    /// Error message if the cell failed.
+    pub error: Option<&'a str>,
+}
+
+/// Store keys added, changed or removed since the previous notification.
+pub struct StoreChange<'a> {
+    pub added: &'a [String],
+    pub changed: &'a [String],
+    pub removed: &'a [String],
+}
+
+/// Hooks called by the runner. All methods default to doing nothing.
+pub trait HostPlugin: Send + Sync + 'static {
+    /// Called after every cell run, including init.
+    fn on_cell_complete(&self, _event: &CellEvent) {}
+
+    /// Called after the notebook library was rebuilt and reloaded.
+    fn on_reload(&self) {}
+
+    /// Called when the store contents changed.
+    fn on_store_change(&self, _change: &StoreChange) {}
+}
+
+/// [`CellEvent`] as passed by the host. `error` is [`FfiSlice::NONE`] if the cell succeeded.
+///
+/// The host keeps a mirror of this struct, so field order and types must not change
+/// without updating `cargo-cellbook` as well.
+#[doc(hidden)]
+#[repr(C)]
+pub struct FfiCellEvent {
+    pub name: FfiSlice,
+    pub duration_secs: u64,
+    pub duration_nanos: u32,
+    pub stdout: FfiSlice,
+    pub error: FfiSlice,
+}
+
+/// Keys of a [`StoreChange`] as passed by the host. Mirrored like [`FfiCellEvent`].
+#[doc(hidden)]
+#[repr(C)]
+pub struct FfiKeys {
+    pub ptr: *const FfiSlice,
+    pub len: usize,
+}
+
+/// [`StoreChange`] as passed by the host. Mirrored like [`FfiCellEvent`].
+#[doc(hidden)]
+#[repr(C)]
+pub struct FfiStoreChange {
+    pub added: FfiKeys,
+    pub changed: FfiKeys,
+    pub removed: FfiKeys,
+}
+
+pub type CellCompleteFn = unsafe extern "C" fn(*const FfiCellEvent);
+pub type ReloadFn = extern "C" fn();
+pub type StoreChangeFn = unsafe extern "C" fn(*const FfiStoreChange);
+
+/// Plugin hooks passed to the host across the dylib boundary.
+///
+/// Generated by [`export_plugin!`](crate::export_plugin) and mirrored by the host.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PluginHooks {
+    pub on_cell_complete: CellCompleteFn,
+    pub on_reload: ReloadFn,
+    pub on_store_change: StoreChangeFn,
+}
+
+/// The exported plugin, created on first use.
+#[doc(hidden)]
+pub type Exported = LazyLock<Box<dyn HostPlugin>>;
+
+// Panics can't unwind into the host, which has its own runtime, so they stop here.
+
+/// Call `on_cell_complete` of `plugin` with an event from the host.
+///
+/// # Safety
+///
+/// `event` must point to a valid event whose strings live for the call.
+#[doc(hidden)]
+pub unsafe fn cell_completed(plugin: &Exported, event: *const FfiCellEvent) {
+    let event = unsafe { &*event };
+    let (name, stdout) = unsafe { (event.name.to_str(), event.stdout.to_str()) };
+    let error = (!event.error.is_none()).then(|| unsafe { event.error.to_str() });
+    let event = CellEvent {
+        name: &name,
+        duration: Duration::new(event.duration_secs, event.duration_nanos),
+        stdout: &stdout,
+        error: error.as_deref(),
+    };
+    let _ = catch_unwind(AssertUnwindSafe(|| plugin.on_cell_complete(&event)));
+}
+
+#[doc(hidden)]
+pub fn reloaded(plugin: &Exported) {
+    let _ = catch_unwind(AssertUnwindSafe(|| plugin.on_reload()));
+}
+
+/// Call `on_store_change` of `plugin` with a change from the host.
+///
+/// # Safety
+///
+/// `change` must point to a valid change whose keys live for the call.
+#[doc(hidden)]
+pub unsafe fn store_changed(plugin: &Exported, change: *const FfiStoreChange) {
+    let change = unsafe { &*change };
+    let keys = |keys: &FfiKeys| -> Vec<String> {
+        if keys.ptr.is_null() {
+            return Vec::new();
+        }
+        unsafe { std::slice::from_raw_parts(keys.ptr, keys.len) }
+            .iter()
+            .map(|key| unsafe { key.to_str() }.into_owned())
+            .collect()
+    };
+    let (added, changed, removed) = (keys(&change.added), keys(&change.changed), keys(&change.removed));
+    let change = StoreChange {
+        added: &added,
+        changed: &changed,
+        removed: &removed,
+    };
+    let _ = catch_unwind(AssertUnwindSafe(|| plugin.on_store_change(&change)));
+}
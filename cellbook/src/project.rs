@@ -0,0 +1,19 @@
+//! Paths relative to the notebook project.
+
+use std::path::{Path, PathBuf};
+
+/// Root directory of the notebook project.
+///
+/// The runner passes it as `CELLBOOK_PROJECT_ROOT`. Without a runner (for
+/// example in tests) the current directory is used.
+pub fn project_root() -> PathBuf {
+    match crate::context::runner_var("CELLBOOK_PROJECT_ROOT") {
+        Some(root) => PathBuf::from(root),
+        None => std::env::current_dir().unwrap_or_default(),
+    }
+}
+
+/// Resolve `path` relative to the project root. Absolute paths are returned unchanged.
+pub fn project_path(path: impl AsRef<Path>) -> PathBuf {
+    project_root().join(path)
+}
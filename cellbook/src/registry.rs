@@ -1,28 +1,192 @@
 //! Cell registry using inventory for automatic collection.
 
-use futures::future::BoxFuture;
+use std::ffi::{CStr, CString, c_char, c_void};
+use std::sync::OnceLock;
 
-use crate::context::{ListFn, LoadFn, RemoveFn, StoreFn};
+use serde::Serialize;
 
-pub type CellFn = fn(
-    StoreFn,
-    LoadFn,
-    RemoveFn,
-    ListFn,
-)
-    -> BoxFuture<'static, std::result::Result<(), Box<dyn std::error::Error + Send + Sync>>>;
+use crate::context::HostFns;
+use crate::ffi::FfiFuture;
+
+/// Version of the interface between notebook or plugin libraries and the runner:
+/// the exported symbols, the manifest format, [`HostFns`], [`CellFn`], the
+/// [`ffi`](crate::ffi) types and the plugin hooks. Bumped on incompatible changes,
+/// so the runner refuses libraries it can't call safely.
+///
+/// Everything crossing the boundary is C-ABI, so libraries built by another
+/// compiler than the runner load as long as this matches.
+pub const ABI_VERSION: u32 = 9;
+
+/// Version of this crate, exported by notebooks and plugins to explain ABI mismatches.
+#[doc(hidden)]
+pub const VERSION: &CStr =
+    match CStr::from_bytes_with_nul(concat!(env!("CARGO_PKG_VERSION"), "\0").as_bytes()) {
+        Ok(version) => version,
+        Err(_) => panic!("crate version contains a NUL byte"),
+    };
+
+/// Cell, init or teardown function generated by the macros, called by the runner.
+pub type CellFn = extern "C" fn(HostFns) -> FfiFuture;
 
 pub struct CellInfo {
     pub name: &'static str,
     pub func: CellFn,
     pub line: u32,
+    /// Run in a separate worker process, set by `#[cell(isolated)]`.
+    pub isolated: bool,
+    /// Left out of the runner's cell list, set by `#[cell(hidden)]`.
+    pub hidden: bool,
+    /// Polled on a blocking thread by the runner, set for plain `fn` cells and
+    /// by `#[cell(blocking)]`.
+    pub blocking: bool,
+    /// Working directory relative to the project root, set by `#[cell(cwd = "...")]`.
+    pub cwd: Option<&'static str>,
+    /// Run in `.cellbook/scratch/`, set by `#[cell(sandbox)]`.
+    pub sandbox: bool,
+    /// Runs until stopped beside other cells, set by `#[cell(service)]`.
+    pub service: bool,
+    /// Display name, set by `#[cell(name = "...")]`.
+    pub title: Option<&'static str>,
+    /// Set by `#[cell(desc = "...")]`.
+    pub description: Option<&'static str>,
+    /// The function's `///` doc comment.
+    pub doc: Option<&'static str>,
+    /// Times a failed run is retried, set by `#[cell(retries = 3)]`.
+    pub retries: u32,
+    /// Wait before the first retry, doubled before each later one, set by
+    /// `#[cell(backoff_ms = 1000)]`.
+    pub backoff_ms: u64,
 }
 
 inventory::collect!(CellInfo);
 
+/// The `#[teardown]` function, registered like cells.
+pub struct Teardown {
+    pub name: &'static str,
+    pub func: CellFn,
+    pub line: u32,
+}
+
+inventory::collect!(Teardown);
+
 /// Returns all registered cells sorted by source line number.
 pub fn cells() -> Vec<&'static CellInfo> {
     let mut cells: Vec<_> = inventory::iter::<CellInfo>.into_iter().collect();
     cells.sort_by_key(|c| c.line);
     cells
 }
+
+/// Cells of a notebook as passed to the runner by `__cellbook_manifest`.
+///
+/// The manifest crosses the library boundary as a JSON C string, so reading it
+/// doesn't depend on how the notebook and runner compilers lay out Rust types.
+#[derive(Serialize)]
+struct Manifest<'a> {
+    init: ManifestCell,
+    teardown: Option<ManifestCell>,
+    cells: Vec<ManifestCell>,
+    /// Runner settings declared with `#[init(...)]` as `(key, TOML value)` pairs.
+    config: &'a [(&'a str, &'a str)],
+}
+
+#[derive(Serialize)]
+struct ManifestCell {
+    name: &'static str,
+    line: u32,
+    isolated: bool,
+    hidden: bool,
+    blocking: bool,
+    cwd: Option<&'static str>,
+    sandbox: bool,
+    service: bool,
+    title: Option<&'static str>,
+    description: Option<&'static str>,
+    doc: Option<&'static str>,
+    retries: u32,
+    backoff_ms: u64,
+}
+
+impl ManifestCell {
+    fn plain(name: &'static str, line: u32) -> Self {
+        Self {
+            name,
+            line,
+            isolated: false,
+            hidden: false,
+            blocking: false,
+            cwd: None,
+            sandbox: false,
+            service: false,
+            title: None,
+            description: None,
+            doc: None,
+            retries: 0,
+            backoff_ms: 0,
+        }
+    }
+}
+
+static MANIFEST: OnceLock<CString> = OnceLock::new();
+
+/// JSON manifest of the notebook's cells, exported by `#[init]` as `__cellbook_manifest`.
+#[doc(hidden)]
+pub fn manifest(init: &'static str, line: u32, config: &[(&str, &str)]) -> *const c_char {
+    MANIFEST
+        .get_or_init(|| {
+            let manifest = Manifest {
+                init: ManifestCell::plain(init, line),
+                teardown: inventory::iter::<Teardown>
+                    .into_iter()
+                    .next()
+                    .map(|t| ManifestCell::plain(t.name, t.line)),
+                cells: cells()
+                    .into_iter()
+                    .map(|c| ManifestCell {
+                        name: c.name,
+                        line: c.line,
+                        isolated: c.isolated,
+                        hidden: c.hidden,
+                        blocking: c.blocking,
+                        cwd: c.cwd,
+                        sandbox: c.sandbox,
+                        service: c.service,
+                        title: c.title,
+                        description: c.description,
+                        doc: c.doc,
+                        retries: c.retries,
+                        backoff_ms: c.backoff_ms,
+                    })
+                    .collect(),
+                config,
+            };
+            let json = serde_json::to_string(&manifest).expect("manifest is serializable");
+            CString::new(json).expect("JSON escapes NUL bytes")
+        })
+        .as_ptr()
+}
+
+/// Function of the cell, init or teardown function called `name`, or null if
+/// there is none. Exported by `#[init]` as `__cellbook_cell_fn`.
+///
+/// # Safety
+///
+/// `name` must point to a NUL-terminated string.
+#[doc(hidden)]
+pub unsafe fn cell_fn(name: *const c_char, init: &str, init_fn: CellFn) -> *const c_void {
+    let name = unsafe { CStr::from_ptr(name) }.to_string_lossy();
+    let func = if name == init {
+        Some(init_fn)
+    } else {
+        inventory::iter::<CellInfo>
+            .into_iter()
+            .find(|c| c.name == name)
+            .map(|c| c.func)
+            .or_else(|| {
+                inventory::iter::<Teardown>
+                    .into_iter()
+                    .find(|t| t.name == name)
+                    .map(|t| t.func)
+            })
+    };
+    func.map_or(std::ptr::null(), |func| func as *const c_void)
+}
@@ -0,0 +1,79 @@
+//! Seed and timestamp shared by every cell of a run.
+//!
+//! `cargo cellbook run --seed 42` fixes the seed, and `--timestamp` the run
+//! time, so a run that draws random numbers or stamps its results with the
+//! current time can be repeated exactly. Exported reports record both.
+//!
+//! ```ignore
+//! let info = ctx.run_info();
+//! let mut rng = StdRng::seed_from_u64(info.seed_for("simulate"));
+//! println!("simulated at {:?}", info.time());
+//! ```
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Seed and start time of the current run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunInfo {
+    /// Seed for random number generators.
+    pub seed: u64,
+    /// Seconds since the Unix epoch when the run started.
+    pub timestamp: u64,
+}
+
+impl RunInfo {
+    /// Seed and timestamp of the current run.
+    ///
+    /// The runner passes them as `CELLBOOK_SEED` and `CELLBOOK_RUN_TIMESTAMP`.
+    /// Without a runner (for example in tests) the seed is 0 and the timestamp
+    /// is the current time.
+    pub fn current() -> Self {
+        let var = |name| crate::context::runner_var(name).and_then(|v| v.parse().ok());
+        Self {
+            seed: var("CELLBOOK_SEED").unwrap_or(0),
+            timestamp: var("CELLBOOK_RUN_TIMESTAMP").unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0)
+            }),
+        }
+    }
+
+    /// Start of the run.
+    pub fn time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.timestamp)
+    }
+
+    /// Seed for a named random stream, derived from the run seed.
+    ///
+    /// Cells that seed their generators with different names get independent
+    /// streams, and a stream doesn't change when cells before it draw more numbers.
+    pub fn seed_for(&self, name: &str) -> u64 {
+        // FNV-1a over the run seed and the name, stable across builds and runs.
+        self.seed
+            .to_le_bytes()
+            .iter()
+            .chain(name.as_bytes())
+            .fold(0xcbf29ce484222325, |hash, &byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seed_for_depends_on_seed_and_name() {
+        let info = RunInfo {
+            seed: 42,
+            timestamp: 1_700_000_000,
+        };
+        assert_eq!(info.seed_for("a"), info.seed_for("a"));
+        assert_ne!(info.seed_for("a"), info.seed_for("b"));
+        assert_ne!(info.seed_for("a"), RunInfo { seed: 43, ..info }.seed_for("a"));
+        assert_eq!(info.time(), UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+    }
+}
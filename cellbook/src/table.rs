@@ -0,0 +1,427 @@
+//! Aligned tables for cell output.
+//!
+//! Rows of any `Serialize` type become a table with one column per field.
+//! With the `polars` feature, data frames can be printed the same way.
+//!
+//! ```ignore
+//! table::print(&stats)?;
+//! Table::from_rows(&stats)?.max_rows(10).precision(2).print();
+//! table!(stats, max_rows = 10, precision = 2)?;
+//! ```
+
+use std::fmt;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::errors::{Error, Result};
+
+/// Rows shown before the rest are summarized.
+const DEFAULT_MAX_ROWS: usize = 50;
+/// Widest a column gets before values are cut off.
+const DEFAULT_MAX_WIDTH: usize = 40;
+
+/// Values that can be shown as a [`Table`].
+pub trait ToTable {
+    fn to_table(&self) -> Result<Table>;
+}
+
+impl<T: Serialize> ToTable for [T] {
+    fn to_table(&self) -> Result<Table> {
+        Table::from_rows(self)
+    }
+}
+
+impl<T: Serialize> ToTable for Vec<T> {
+    fn to_table(&self) -> Result<Table> {
+        Table::from_rows(self)
+    }
+}
+
+/// The frame is kept as it is, and only the rows shown are turned into text.
+#[cfg(feature = "polars")]
+impl ToTable for polars::prelude::DataFrame {
+    fn to_table(&self) -> Result<Table> {
+        let headers = self
+            .get_column_names()
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        let floats = self
+            .get_columns()
+            .iter()
+            .map(|column| column.dtype().is_float())
+            .collect();
+        Ok(Table {
+            floats: Some(floats),
+            ..Table::with_rows(headers, Rows::Frame(self.clone()))
+        })
+    }
+}
+
+/// Print rows as a table with the default limits.
+pub fn print<R: ToTable + ?Sized>(rows: &R) -> Result<()> {
+    rows.to_table()?.print();
+    Ok(())
+}
+
+/// A table of text cells, rendered with box-drawing characters.
+///
+/// Numeric cells are right-aligned.
+#[derive(Debug, Clone)]
+pub struct Table {
+    headers: Vec<String>,
+    rows: Rows,
+    /// Which columns hold floats that `precision` applies to, or `None` to go
+    /// by what each cell's text looks like.
+    floats: Option<Vec<bool>>,
+    max_rows: usize,
+    max_width: usize,
+    precision: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+enum Rows {
+    Text(Vec<Vec<String>>),
+    #[cfg(feature = "polars")]
+    Frame(polars::prelude::DataFrame),
+}
+
+impl Rows {
+    fn len(&self) -> usize {
+        match self {
+            Rows::Text(rows) => rows.len(),
+            #[cfg(feature = "polars")]
+            Rows::Frame(frame) => frame.height(),
+        }
+    }
+
+    /// Text of the first `n` rows.
+    fn head(&self, n: usize) -> Vec<Vec<String>> {
+        match self {
+            Rows::Text(rows) => rows[..n.min(rows.len())].to_vec(),
+            #[cfg(feature = "polars")]
+            Rows::Frame(frame) => {
+                let head = frame.head(Some(n));
+                (0..head.height())
+                    .map(|i| {
+                        head.get_columns()
+                            .iter()
+                            .map(|column| {
+                                column
+                                    .get(i)
+                                    .map(|value| any_value_text(&value))
+                                    .unwrap_or_default()
+                            })
+                            .collect()
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+impl Table {
+    pub fn new(headers: Vec<String>, rows: Vec<Vec<String>>) -> Self {
+        Self::with_rows(headers, Rows::Text(rows))
+    }
+
+    fn with_rows(headers: Vec<String>, rows: Rows) -> Self {
+        Self {
+            headers,
+            rows,
+            floats: None,
+            max_rows: DEFAULT_MAX_ROWS,
+            max_width: DEFAULT_MAX_WIDTH,
+            precision: None,
+        }
+    }
+
+    /// One row per item, with a column per struct field or map key.
+    /// Tuples get numbered columns and other values a single `value` column.
+    pub fn from_rows<T: Serialize>(items: &[T]) -> Result<Self> {
+        let values = items
+            .iter()
+            .map(serde_json::to_value)
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| Error::Table(e.to_string()))?;
+
+        let mut headers: Vec<String> = Vec::new();
+        for value in &values {
+            let keys: Vec<String> = match value {
+                Value::Object(map) => map.keys().cloned().collect(),
+                Value::Array(items) => (0..items.len()).map(|i| i.to_string()).collect(),
+                _ => vec!["value".to_string()],
+            };
+            for key in keys {
+                if !headers.contains(&key) {
+                    headers.push(key);
+                }
+            }
+        }
+
+        let fields: Vec<Vec<Option<&Value>>> = values
+            .iter()
+            .map(|value| {
+                headers
+                    .iter()
+                    .map(|header| match value {
+                        Value::Object(map) => map.get(header),
+                        Value::Array(items) => header.parse::<usize>().ok().and_then(|i| items.get(i)),
+                        _ => Some(value),
+                    })
+                    .collect()
+            })
+            .collect();
+        let floats = (0..headers.len())
+            .map(|col| {
+                fields
+                    .iter()
+                    .any(|row| matches!(row[col], Some(Value::Number(n)) if n.is_f64()))
+            })
+            .collect();
+        let rows = fields
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|field| field.map(cell_text).unwrap_or_default())
+                    .collect()
+            })
+            .collect();
+        Ok(Self {
+            floats: Some(floats),
+            ..Self::new(headers, rows)
+        })
+    }
+
+    /// Show at most `max_rows` rows, followed by a count of the rest.
+    pub fn max_rows(mut self, max_rows: usize) -> Self {
+        self.max_rows = max_rows;
+        self
+    }
+
+    /// Cut off values wider than `max_width` characters.
+    pub fn max_width(mut self, max_width: usize) -> Self {
+        self.max_width = max_width.max(1);
+        self
+    }
+
+    /// Show decimal numbers with `precision` digits after the point, in the
+    /// float columns of rows and data frames.
+    pub fn precision(mut self, precision: usize) -> Self {
+        self.precision = Some(precision);
+        self
+    }
+
+    pub fn print(&self) {
+        print!("{}", self);
+    }
+
+    /// Text of the cell in column `col` after applying precision and the width limit.
+    fn format_cell(&self, col: usize, text: &str) -> String {
+        let float = match &self.floats {
+            Some(floats) => floats.get(col).copied().unwrap_or(false),
+            None => text.contains('.'),
+        };
+        let text = match self.precision {
+            Some(precision) if float => match text.parse::<f64>() {
+                Ok(number) => format!("{:.*}", precision, number),
+                Err(_) => text.to_string(),
+            },
+            _ => text.to_string(),
+        };
+        truncate(&text, self.max_width)
+    }
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let headers: Vec<String> = self.headers.iter().map(|h| truncate(h, self.max_width)).collect();
+        let rows: Vec<Vec<String>> = self
+            .rows
+            .head(self.max_rows)
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .enumerate()
+                    .map(|(col, cell)| self.format_cell(col, cell))
+                    .collect()
+            })
+            .collect();
+
+        let widths: Vec<usize> = (0..headers.len())
+            .map(|col| {
+                rows.iter()
+                    .filter_map(|row| row.get(col))
+                    .chain(std::iter::once(&headers[col]))
+                    .map(|cell| cell.chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        let border = |left: &str, mid: &str, right: &str| {
+            let lines: Vec<String> = widths.iter().map(|w| "─".repeat(w + 2)).collect();
+            format!("{}{}{}\n", left, lines.join(mid), right)
+        };
+        let line = |cells: &[String], header: bool| {
+            let cells: Vec<String> = widths
+                .iter()
+                .enumerate()
+                .map(|(col, &width)| {
+                    let cell = cells.get(col).map(String::as_str).unwrap_or("");
+                    if !header && is_number(cell) {
+                        format!(" {:>width$} ", cell)
+                    } else {
+                        format!(" {:<width$} ", cell)
+                    }
+                })
+                .collect();
+            format!("│{}│\n", cells.join("│"))
+        };
+
+        f.write_str(&border("┌", "┬", "┐"))?;
+        f.write_str(&line(&headers, true))?;
+        f.write_str(&border("├", "┼", "┤"))?;
+        for row in &rows {
+            f.write_str(&line(row, false))?;
+        }
+        f.write_str(&border("└", "┴", "┘"))?;
+        match self.rows.len() - rows.len() {
+            0 => {}
+            1 => writeln!(f, "… 1 more row")?,
+            more => writeln!(f, "… {} more rows", more)?,
+        }
+        Ok(())
+    }
+}
+
+/// Strings without the quotes polars adds when displaying them.
+#[cfg(feature = "polars")]
+fn any_value_text(value: &polars::prelude::AnyValue) -> String {
+    use polars::prelude::AnyValue;
+    match value {
+        AnyValue::Null => String::new(),
+        AnyValue::String(s) => s.to_string(),
+        AnyValue::StringOwned(s) => s.to_string(),
+        other => other.to_string(),
+    }
+}
+
+fn cell_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn is_number(text: &str) -> bool {
+    !text.is_empty() && text.parse::<f64>().is_ok()
+}
+
+/// Cut `text` to `width` characters, marking the cut with `…`.
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let mut cut: String = text.chars().take(width - 1).collect();
+    cut.push('…');
+    cut
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Stock {
+        symbol: &'static str,
+        close: f64,
+        volume: u64,
+    }
+
+    fn stocks() -> Vec<Stock> {
+        vec![
+            Stock {
+                symbol: "AAPL",
+                close: 182.3141,
+                volume: 1200,
+            },
+            Stock {
+                symbol: "MSFT",
+                close: 411.5,
+                volume: 980,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_renders_struct_rows() {
+        let table = Table::from_rows(&stocks()).unwrap().precision(2);
+        assert_eq!(
+            table.to_string(),
+            "\
+┌────────┬────────┬────────┐
+│ symbol │ close  │ volume │
+├────────┼────────┼────────┤
+│ AAPL   │ 182.31 │   1200 │
+│ MSFT   │ 411.50 │    980 │
+└────────┴────────┴────────┘
+"
+        );
+    }
+
+    #[test]
+    fn test_limits_rows_and_width() {
+        let table = Table::from_rows(&stocks()).unwrap().max_rows(1).max_width(4);
+        let text = table.to_string();
+        assert!(text.contains("│ sym… │ clo… │ vol… │"));
+        assert!(text.contains("│ AAPL │ 182… │ 1200 │"));
+        assert!(!text.contains("MSFT"));
+        assert!(text.ends_with("… 1 more row\n"));
+
+        let more = Table::from_rows(&[1, 2, 3]).unwrap().max_rows(1).to_string();
+        assert!(more.ends_with("… 2 more rows\n"));
+    }
+
+    #[test]
+    fn test_precision_only_applies_to_float_columns() {
+        #[derive(Serialize)]
+        struct Version {
+            name: &'static str,
+            score: f64,
+        }
+        let rows = [Version {
+            name: "1.25",
+            score: 0.123,
+        }];
+        let text = Table::from_rows(&rows).unwrap().precision(1).to_string();
+        assert!(text.contains("│ 1.25 │   0.1 │"), "{}", text);
+
+        let guessed = Table::new(vec!["x".into()], vec![vec!["1.25".into()]]).precision(1);
+        assert!(guessed.to_string().contains("│ 1.2 │"));
+    }
+
+    #[cfg(feature = "polars")]
+    #[test]
+    fn test_data_frame_shows_its_first_rows() {
+        use polars::prelude::*;
+
+        let frame =
+            df!("id" => [1, 2, 3], "tag" => ["1.25", "b", "c"], "close" => [1.25, 2.5, 3.75]).unwrap();
+        let text = frame.to_table().unwrap().max_rows(1).precision(1).to_string();
+        assert!(text.contains("│  1 │ 1.25 │   1.2 │"), "{}", text);
+        assert!(text.ends_with("… 2 more rows\n"));
+    }
+
+    #[test]
+    fn test_tuples_and_scalars() {
+        let pairs = Table::from_rows(&[("a", 1), ("b", 2)]).unwrap();
+        assert_eq!(pairs.headers, vec!["0", "1"]);
+        assert_eq!(pairs.rows.head(2)[1], vec!["b", "2"]);
+
+        let values = Table::from_rows(&[1.5, 2.0]).unwrap();
+        assert_eq!(values.headers, vec!["value"]);
+    }
+}
@@ -33,11 +33,12 @@ thread_local! {
     static CURRENT_PREFIX: RefCell<String> = const { RefCell::new(String::new()) };
 }
 
-fn store(key: &str, bytes: Vec<u8>, type_name: &str) {
+fn store(key: &str, bytes: Vec<u8>, type_name: &str) -> std::result::Result<(), String> {
     let prefixed_key = CURRENT_PREFIX.with(|p| format!("{}:{}", p.borrow(), key));
     TEST_STORE
         .lock()
         .insert(prefixed_key, (bytes, type_name.to_string()));
+    Ok(())
 }
 
 fn load(key: &str) -> Option<(Vec<u8>, String)> {
@@ -1,5 +1,5 @@
 use anyhow::Result;
-use cellbook::{cell, init, load, store};
+use cellbook::{cell, init, load, store, teardown};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,6 +19,12 @@ async fn setup() -> Result<()> {
     Ok(())
 }
 
+#[teardown]
+async fn shutdown() -> Result<()> {
+    println!("Leaving {} values in the store", ctx.list().len());
+    Ok(())
+}
+
 #[cell]
 async fn setup_data() -> Result<()> {
     let config = DemoConfig {
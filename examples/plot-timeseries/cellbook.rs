@@ -1,7 +1,7 @@
 //! Stock price analysis example.
 
 use anyhow::Result;
-use cellbook::{cell, init, load, open_image_bytes, store};
+use cellbook::{cell, data, init, load, open_image_bytes, path, store, store_df, table};
 use plotters::prelude::*;
 use polars::prelude::*;
 use serde::{Deserialize, Serialize};
@@ -41,25 +41,20 @@ struct DailyReturns {
     returns: Vec<f64>,
 }
 
-#[cell]
+#[cell(name = "Load market data", desc = "Reads data/stock_prices.csv")]
 async fn load_data() -> Result<()> {
-    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/data/stock_prices.csv");
+    let path = path!("data/stock_prices.csv");
 
-    let df = CsvReadOptions::default()
-        .with_has_header(true)
-        .try_into_reader_with_file_path(Some(path.into()))
-        .map_err(|e| std::io::Error::other(e.to_string()))?
-        .finish()
-        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    let df = data::read_csv(&path)?;
 
-    println!("Loaded {} rows from {}", df.height(), path);
+    println!("Loaded {} rows from {}", df.height(), path.display());
     println!("\nSchema:");
     for field in df.schema().iter_fields() {
         println!("  {}: {:?}", field.name(), field.dtype());
     }
 
     println!("\nFirst 5 rows:");
-    println!("{}", df.head(Some(5)));
+    table!(df, max_rows = 5)?;
 
     let symbols: Vec<String> = df
         .column("symbol")
@@ -122,6 +117,7 @@ async fn load_data() -> Result<()> {
 
     store!(symbols)?;
     store!(all_prices)?;
+    store_df!(market_data = df, source = path)?;
 
     Ok(())
 }
@@ -564,10 +560,6 @@ async fn plot_returns() -> Result<()> {
 async fn summary() -> Result<()> {
     let all_stats: Vec<StockStats> = load!(all_stats)?;
 
-    println!("+--------------------------------------------------------------------+");
-    println!("|                    STOCK ANALYSIS SUMMARY                          |");
-    println!("+--------------------------------------------------------------------+");
-
     let best = all_stats
         .iter()
         .max_by(|a, b| a.price_change_pct.partial_cmp(&b.price_change_pct).unwrap())
@@ -581,30 +573,28 @@ async fn summary() -> Result<()> {
         .min_by(|a, b| a.volatility.partial_cmp(&b.volatility).unwrap())
         .unwrap();
 
+    for path in data::stale_sources(ctx, "market_data")? {
+        println!(
+            "{} changed since it was loaded, re-run 'Load market data'\n",
+            path.display()
+        );
+    }
+
+    println!("STOCK ANALYSIS SUMMARY\n");
     println!(
-        "|  Best Performer:    {:5} ({:+.2}%)                              |",
+        "Best Performer:    {:5} ({:+.2}%)",
         best.symbol, best.price_change_pct
     );
     println!(
-        "|  Worst Performer:   {:5} ({:+.2}%)                              |",
+        "Worst Performer:   {:5} ({:+.2}%)",
         worst.symbol, worst.price_change_pct
     );
     println!(
-        "|  Lowest Volatility: {:5} ({:.2}%)                               |",
+        "Lowest Volatility: {:5} ({:.2}%)\n",
         lowest_vol.symbol, lowest_vol.volatility
     );
-    println!("+--------------------------------------------------------------------+");
-    println!("|  Symbol  |  Close Avg  |  Change  |  Volatility  |   Volume       |");
-    println!("+--------------------------------------------------------------------+");
-
-    for s in &all_stats {
-        println!(
-            "|  {:5}   |  ${:>8.2} | {:>+6.2}%  |    {:>5.2}%    | {:>13}  |",
-            s.symbol, s.mean_close, s.price_change_pct, s.volatility, s.total_volume
-        );
-    }
 
-    println!("+--------------------------------------------------------------------+");
+    table!(all_stats, precision = 2)?;
 
     Ok(())
 }
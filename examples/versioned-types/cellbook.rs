@@ -1,7 +1,8 @@
 //! Versioned store example for cellbook.
 //!
 //! Demonstrates:
-//! - derive-based schema versions via `StoreSchema`
+//! - derive-based schema versions via `StoreSchema`, whose fields and doc comments
+//!   show up in the store inspector
 //! - explicit version at call-site for foreign-like types
 //! - version mismatch handling
 //! - consume semantics (failed consume keeps data, successful consume removes it)
@@ -10,10 +11,12 @@ use anyhow::Result;
 use cellbook::{Error as CellbookError, StoreSchema, cell, consumev, init, loadv, storev};
 use serde::{Deserialize, Serialize};
 
+/// Switches read by the plotting cells.
 #[derive(Debug, Clone, Serialize, Deserialize, StoreSchema)]
 #[store_schema(version = 1)]
 struct FeatureFlags {
     enable_plots: bool,
+    /// Number of rows drawn from the data set.
     sample_size: usize,
 }
 